@@ -1,20 +1,23 @@
 use hpke::{
-    aead::{Aead as AeadTrait, AeadCtxR, AeadTag},
+    aead::{Aead as AeadTrait, AeadCtxR, AeadTag, ExportOnlyAead},
     kdf::Kdf as KdfTrait,
     kem::Kem as KemTrait,
     setup_receiver, setup_sender, OpModeR, OpModeS, PskBundle,
 };
 
-use criterion::{black_box, criterion_main, Criterion};
+use criterion::{black_box, criterion_main, BenchmarkGroup, Criterion, Throughput};
 use rand::{rngs::StdRng, RngCore, SeedableRng};
 use std::time::Instant;
 
 // Length of AAD for all seal/open benchmarks
 const AAD_LEN: usize = 64;
-// Length of plaintext and ciphertext for all seal/open benchmarks
-const MSG_LEN: usize = 64;
+// Lengths of plaintext and ciphertext for the seal/open benchmarks. These are a small packet, a
+// typical request body, and a large-ish upload chunk
+const MSG_LENS: &[usize] = &[64, 1024, 16384];
+// Length of the exporter output in the export benchmark
+const EXPORT_LEN: usize = 32;
 // Length of PSK. Since we're only testing the 128-bit security level, make it 128 bits
-const PSK_LEN: usize = 16;
+const PSK_LEN: usize = 32;
 
 // Generic function to bench the specified ciphersuite
 fn bench_ciphersuite<Aead, Kdf, Kem>(group_name: &str, c: &mut Criterion)
@@ -33,6 +36,27 @@ where
     // Make a recipient keypair to encrypt to
     let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
 
+    // Make a sender keypair for OpModeAuth and OpModeAuthPsk
+    let (sk_sender, pk_sender) = Kem::gen_keypair(&mut csprng);
+
+    // Bench the bare KEM operations, both unauthenticated and authenticated. These are what
+    // dominate setup time, so it's useful to see them separately from the key schedule.
+    group.bench_function("encap[auth=false]", |b| {
+        b.iter(|| Kem::encap(&pk_recip, None, &mut csprng).unwrap())
+    });
+    group.bench_function("encap[auth=true]", |b| {
+        b.iter(|| Kem::encap(&pk_recip, Some((&sk_sender, &pk_sender)), &mut csprng).unwrap())
+    });
+    let (_, encapped_key) = Kem::encap(&pk_recip, None, &mut csprng).unwrap();
+    group.bench_function("decap[auth=false]", |b| {
+        b.iter(|| Kem::decap(&sk_recip, None, &encapped_key).unwrap())
+    });
+    let (_, encapped_key) =
+        Kem::encap(&pk_recip, Some((&sk_sender, &pk_sender)), &mut csprng).unwrap();
+    group.bench_function("decap[auth=true]", |b| {
+        b.iter(|| Kem::decap(&sk_recip, Some(&pk_sender), &encapped_key).unwrap())
+    });
+
     // Make a PSK bundle for OpModePsk and OpModeAuthPsk
     let mut psk = [0u8; PSK_LEN];
     let mut psk_id = [0u8; 8];
@@ -43,22 +67,19 @@ where
         psk_id: &psk_id,
     };
 
-    // Make a sender keypair for OpModeAuth and OpModeAuthPsk
-    let (sk_sender, pk_sender) = Kem::gen_keypair(&mut csprng);
-
     // Construct all the opmodes we'll use in setup_sender and setup_receiver
     let opmodes = ["base", "auth", "psk", "authpsk"];
-    let opmodes_s = vec![
+    let opmodes_s = [
         OpModeS::Base,
         OpModeS::Auth((sk_sender.clone(), pk_sender.clone())),
         OpModeS::Psk(psk_bundle),
         OpModeS::AuthPsk((sk_sender, pk_sender.clone()), psk_bundle),
     ];
-    let opmodes_r = vec![
+    let opmodes_r = [
         OpModeR::Base,
+        OpModeR::Auth(pk_sender.clone()),
         OpModeR::Psk(psk_bundle),
-        OpModeR::Auth(pk_recip.clone()),
-        OpModeR::AuthPsk(pk_recip.clone(), psk_bundle),
+        OpModeR::AuthPsk(pk_sender, psk_bundle),
     ];
 
     // Bench setup_sender() for each opmode
@@ -79,14 +100,9 @@ where
     // Collect the encapsulated keys from each setup_sender under each opmode. We will pass these
     // to setup_receiver in a moment
     let encapped_keys = opmodes_s.iter().map(|opmode_s| {
-        setup_sender::<Aead, Kdf, Kem, _>(
-            &opmode_s,
-            &pk_recip,
-            b"bench setup receiver",
-            &mut csprng,
-        )
-        .unwrap()
-        .0
+        setup_sender::<Aead, Kdf, Kem, _>(opmode_s, &pk_recip, b"bench setup receiver", &mut csprng)
+            .unwrap()
+            .0
     });
 
     // Bench setup_receiver for each opmode
@@ -98,26 +114,64 @@ where
                     &opmode_r,
                     &sk_recip,
                     &encapped_key,
-                    b"bench setup sender",
+                    b"bench setup receiver",
                 )
                 .unwrap()
             })
         });
     }
 
+    // Make an encryption context so we can benchmark export()
+    let (_, encryption_ctx) =
+        setup_sender::<Aead, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"bench seal", &mut csprng)
+            .unwrap();
+
+    // Bench export() of an EXPORT_LEN-byte secret
+    let bench_name = format!("export[len={}]", EXPORT_LEN);
+    group.bench_function(bench_name, |b| {
+        let mut out = [0u8; EXPORT_LEN];
+        b.iter(|| encryption_ctx.export(b"bench export", &mut out).unwrap())
+    });
+
+    // The export-only AEAD panics on seal() and open(), so there's nothing more to bench
+    if Aead::AEAD_ID != ExportOnlyAead::AEAD_ID {
+        for &msg_len in MSG_LENS {
+            bench_seal_open::<Aead, Kdf, Kem>(&mut group, msg_len);
+        }
+    }
+
+    group.finish();
+}
+
+// Benches seal_in_place_detached() and open_in_place_detached() on msg_len-byte messages with
+// AAD_LEN-byte AADs
+fn bench_seal_open<Aead, Kdf, Kem>(
+    group: &mut BenchmarkGroup<criterion::measurement::WallTime>,
+    msg_len: usize,
+) where
+    Aead: AeadTrait,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let mut csprng = StdRng::from_entropy();
+    // Report seal/open speeds in bytes per second. This must come last in the group, since the
+    // setting applies to every benchmark after it
+    group.throughput(Throughput::Bytes(msg_len as u64));
+
     // Make the encryption context so we can benchmark seal()
+    let (_, pk_recip) = Kem::gen_keypair(&mut csprng);
     let (_, mut encryption_ctx) =
         setup_sender::<Aead, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"bench seal", &mut csprng)
             .unwrap();
 
-    // Bench seal_in_place_detached() on a MSG_LEN-byte plaintext and AAD_LEN-byte AAD
+    // Bench seal_in_place_detached() on a msg_len-byte plaintext and AAD_LEN-byte AAD
     let bench_name = format!(
         "seal_in_place_detached[msglen={},aadlen={}]",
-        MSG_LEN, AAD_LEN
+        msg_len, AAD_LEN
     );
     group.bench_function(bench_name, |b| {
         // Pick random inputs
-        let mut plaintext = [0u8; MSG_LEN];
+        let mut plaintext = vec![0u8; msg_len];
         let mut aad = [0u8; AAD_LEN];
         csprng.fill_bytes(&mut plaintext);
         csprng.fill_bytes(&mut aad);
@@ -129,29 +183,28 @@ where
         })
     });
 
-    // Bench open_in_place_detached() on MSG_LEN-bytes ciphertexts with AAD_LEN-byte AADs. This is
+    // Bench open_in_place_detached() on msg_len-byte ciphertexts with AAD_LEN-byte AADs. This is
     // more complicated than the other benchmarks because we need to first construct and store a
     // ton of ciphertexts that we can open in sequence.
     let bench_name = format!(
         "open_in_place_detached[msglen={},aadlen={}]",
-        MSG_LEN, AAD_LEN
+        msg_len, AAD_LEN
     );
     group.bench_function(bench_name, |b| {
         b.iter_custom(|iters| {
             // Make a decryption context and however many (ciphertexts, aad, tag) tuples the
             // bencher tells us we need
             let (mut decryption_ctx, ciphertext_aad_tags) =
-                make_decryption_ctx_with_ciphertexts::<Aead, Kdf, Kem>(iters as usize);
+                make_decryption_ctx_with_ciphertexts::<Aead, Kdf, Kem>(iters as usize, msg_len);
 
             // Start the timer, open every ciphertext in quick succession, then stop the timer
             let start = Instant::now();
             for (mut ciphertext, aad, tag) in ciphertext_aad_tags.into_iter() {
                 // black_box makes sure the compiler doesn't optimize away this computation
-                black_box(
-                    decryption_ctx
-                        .open_in_place_detached(&mut ciphertext, &aad, &tag)
-                        .unwrap(),
-                );
+                decryption_ctx
+                    .open_in_place_detached(&mut ciphertext, &aad, &tag)
+                    .unwrap();
+                black_box(&ciphertext);
             }
             start.elapsed()
         });
@@ -159,12 +212,13 @@ where
 }
 
 // A tuple of (ciphertext, aad, auth_tag) resulting from a call to seal()
-type CiphertextAadTag<A> = ([u8; MSG_LEN], [u8; AAD_LEN], AeadTag<A>);
+type CiphertextAadTag<A> = (Vec<u8>, [u8; AAD_LEN], AeadTag<A>);
 
-// Constructs a decryption context with num_ciphertexts many CiphertextAadTag tuples that are
-// decryptable in sequence
+// Constructs a decryption context with num_ciphertexts many CiphertextAadTag tuples of msg_len
+// bytes each that are decryptable in sequence
 fn make_decryption_ctx_with_ciphertexts<Aead, Kdf, Kem>(
     num_ciphertexts: usize,
+    msg_len: usize,
 ) -> (AeadCtxR<Aead, Kdf, Kem>, Vec<CiphertextAadTag<Aead>>)
 where
     Aead: AeadTrait,
@@ -183,7 +237,7 @@ where
     let mut ciphertext_aad_tags = Vec::with_capacity(num_ciphertexts);
     for _ in 0..num_ciphertexts {
         // Make the plaintext and AAD random
-        let mut plaintext = [0u8; MSG_LEN];
+        let mut plaintext = vec![0u8; msg_len];
         let mut aad = [0u8; AAD_LEN];
         csprng.fill_bytes(&mut plaintext);
         csprng.fill_bytes(&mut aad);
@@ -207,23 +261,92 @@ where
     (decryption_ctx, ciphertext_aad_tags)
 }
 
+// This macro takes in all the supported AEADs, KDFs, and KEMs, and benches every combination of
+// them. The group name of each ciphersuite is "KEM[kdf=KDF,aead=AEAD]".
+macro_rules! bench_all_ciphersuites {
+    // Step 1: Roll up the AEAD, KDF, and KEM types into tuples. We'll unroll them later
+    ($c:ident, ($( $aead_ty:ident ),*), ($( $kdf_ty:ident ),*), $kem_ty:ident) => {
+        bench_all_ciphersuites!(@tup1 $c, ($( $aead_ty ),*), ($( $kdf_ty ),*), $kem_ty)
+    };
+    // Step 2: Expand with respect to every AEAD
+    (@tup1 $c:ident, ($( $aead_ty:ident ),*), $kdf_tup:tt, $kem_ty:ident) => {
+        $(
+            bench_all_ciphersuites!(@tup2 $c, $aead_ty, $kdf_tup, $kem_ty);
+        )*
+    };
+    // Step 3: Expand with respect to every KDF, and run the bench
+    (@tup2 $c:ident, $aead_ty:ident, ($( $kdf_ty:ident ),*), $kem_ty:ident) => {
+        $(
+            bench_ciphersuite::<$aead_ty, $kdf_ty, $kem_ty>(
+                concat!(
+                    stringify!($kem_ty),
+                    "[kdf=",
+                    stringify!($kdf_ty),
+                    ",aead=",
+                    stringify!($aead_ty),
+                    "]"
+                ),
+                $c,
+            );
+        )*
+    };
+}
+
 pub fn benches() {
+    use hpke::{
+        aead::{AesGcm128, AesGcm256, ChaCha20Poly1305},
+        kdf::{HkdfSha256, HkdfSha384, HkdfSha512},
+    };
+
     let mut c = Criterion::default().configure_from_args();
+    let c = &mut c;
 
-    // NIST ciphersuite at the 128-bit security level is AES-GCM-128, HKDF-SHA256, and ECDH-P256
-    #[cfg(feature = "p256")]
-    bench_ciphersuite::<hpke::aead::AesGcm128, hpke::kdf::HkdfSha256, hpke::kem::DhP256HkdfSha256>(
-        "NIST[seclevel=128]",
-        &mut c,
-    );
+    // Every KEM gets benched with every KDF and AEAD. Use criterion's filter argument to narrow
+    // this down, e.g., `cargo bench -- DhP256HkdfSha256` or `cargo bench -- aead=AesGcm128`.
 
-    // Non-NIST ciphersuite at the 128-bit security level is ChaCha20Poly1305, HKDF-SHA256, and X25519
     #[cfg(feature = "x25519")]
-    bench_ciphersuite::<
-        hpke::aead::ChaCha20Poly1305,
-        hpke::kdf::HkdfSha256,
-        hpke::kem::X25519HkdfSha256,
-    >("Non-NIST[seclevel=128]", &mut c);
+    {
+        use hpke::kem::X25519HkdfSha256;
+        bench_all_ciphersuites!(
+            c,
+            (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+            (HkdfSha256, HkdfSha384, HkdfSha512),
+            X25519HkdfSha256
+        );
+    }
+
+    #[cfg(feature = "p256")]
+    {
+        use hpke::kem::DhP256HkdfSha256;
+        bench_all_ciphersuites!(
+            c,
+            (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+            (HkdfSha256, HkdfSha384, HkdfSha512),
+            DhP256HkdfSha256
+        );
+    }
+
+    #[cfg(feature = "p384")]
+    {
+        use hpke::kem::DhP384HkdfSha384;
+        bench_all_ciphersuites!(
+            c,
+            (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+            (HkdfSha256, HkdfSha384, HkdfSha512),
+            DhP384HkdfSha384
+        );
+    }
+
+    #[cfg(feature = "p521")]
+    {
+        use hpke::kem::DhP521HkdfSha512;
+        bench_all_ciphersuites!(
+            c,
+            (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+            (HkdfSha256, HkdfSha384, HkdfSha512),
+            DhP521HkdfSha512
+        );
+    }
 }
 
 criterion_main!(benches);
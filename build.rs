@@ -0,0 +1,14 @@
+// Declares the backend selection flags of the aes and polyval crates, which aes_gcm_backends()
+// reads, so that rustc doesn't warn about them as unexpected cfgs. The single-colon form of the
+// instruction is ignored by Cargo versions that predate it, which keeps the MSRV.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    for cfg in [
+        "aes_armv8",
+        "aes_force_soft",
+        "polyval_armv8",
+        "polyval_force_soft",
+    ] {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+    }
+}
@@ -18,6 +18,7 @@ use hpke::{
     kem::{
         DhP256HkdfSha256, DhP384HkdfSha384, DhP521HkdfSha512, Kem as KemTrait, X25519HkdfSha256,
     },
+    policy::Policy,
     setup_receiver, setup_sender, Deserializable, HpkeError, OpModeR, OpModeS, PskBundle,
     Serializable,
 };
@@ -57,6 +58,12 @@ enum AgileHpkeError {
     HpkeError(HpkeError),
 }
 
+// The suites we're willing to use. In a real server, this would come from configuration.
+const POLICY: Policy<'static> = Policy {
+    min_security_bits: 128,
+    ..Policy::PERMISSIVE
+};
+
 // This just wraps the HpkeError
 impl From<HpkeError> for AgileHpkeError {
     fn from(e: HpkeError) -> AgileHpkeError {
@@ -323,13 +330,13 @@ struct AgileOpModeR<'a> {
 }
 
 impl<'a> AgileOpModeR<'a> {
-    fn try_lift<Kem: KemTrait, Kdf: KdfTrait>(self) -> Result<OpModeR<'a, Kem>, AgileHpkeError> {
+    fn try_lift<Kem: KemTrait>(self) -> Result<OpModeR<'a, Kem>, AgileHpkeError> {
         let res = match self.op_mode_ty {
             AgileOpModeRTy::Base => OpModeR::Base,
-            AgileOpModeRTy::Psk(bundle) => OpModeR::Psk(bundle.try_lift::<Kdf>()?),
+            AgileOpModeRTy::Psk(bundle) => OpModeR::Psk(bundle.try_lift()?),
             AgileOpModeRTy::Auth(pk) => OpModeR::Auth(pk.try_lift::<Kem>()?),
             AgileOpModeRTy::AuthPsk(pk, bundle) => {
-                OpModeR::AuthPsk(pk.try_lift::<Kem>()?, bundle.try_lift::<Kdf>()?)
+                OpModeR::AuthPsk(pk.try_lift::<Kem>()?, bundle.try_lift()?)
             }
         };
 
@@ -338,27 +345,16 @@ impl<'a> AgileOpModeR<'a> {
 
     fn validate(&self) -> Result<(), AgileHpkeError> {
         match &self.op_mode_ty {
-            AgileOpModeRTy::Auth(pk) => {
-                if pk.kem_alg != self.kem_alg {
-                    return Err(AgileHpkeError::AlgMismatch(
-                        (self.kem_alg.name(), "AgileOpModeR::kem_alg"),
-                        (
-                            pk.kem_alg.name(),
-                            "AgileOpModeR::op_mode_ty::AgilePublicKey::kem_alg",
-                        ),
-                    ));
-                }
-            }
-            AgileOpModeRTy::AuthPsk(pk, _) => {
-                if pk.kem_alg != self.kem_alg {
-                    return Err(AgileHpkeError::AlgMismatch(
-                        (self.kem_alg.name(), "AgileOpModeR::kem_alg"),
-                        (
-                            pk.kem_alg.name(),
-                            "AgileOpModeR::op_mode_ty::AgilePublicKey::kem_alg",
-                        ),
-                    ));
-                }
+            AgileOpModeRTy::Auth(pk) | AgileOpModeRTy::AuthPsk(pk, _)
+                if pk.kem_alg != self.kem_alg =>
+            {
+                return Err(AgileHpkeError::AlgMismatch(
+                    (self.kem_alg.name(), "AgileOpModeR::kem_alg"),
+                    (
+                        pk.kem_alg.name(),
+                        "AgileOpModeR::op_mode_ty::AgilePublicKey::kem_alg",
+                    ),
+                ));
             }
             _ => (),
         }
@@ -382,13 +378,13 @@ struct AgileOpModeS<'a> {
 }
 
 impl<'a> AgileOpModeS<'a> {
-    fn try_lift<Kem: KemTrait, Kdf: KdfTrait>(self) -> Result<OpModeS<'a, Kem>, AgileHpkeError> {
+    fn try_lift<Kem: KemTrait>(self) -> Result<OpModeS<'a, Kem>, AgileHpkeError> {
         let res = match self.op_mode_ty {
             AgileOpModeSTy::Base => OpModeS::Base,
-            AgileOpModeSTy::Psk(bundle) => OpModeS::Psk(bundle.try_lift::<Kdf>()?),
+            AgileOpModeSTy::Psk(bundle) => OpModeS::Psk(bundle.try_lift()?),
             AgileOpModeSTy::Auth(keypair) => OpModeS::Auth(keypair.try_lift::<Kem>()?),
             AgileOpModeSTy::AuthPsk(keypair, bundle) => {
-                OpModeS::AuthPsk(keypair.try_lift::<Kem>()?, bundle.try_lift::<Kdf>()?)
+                OpModeS::AuthPsk(keypair.try_lift::<Kem>()?, bundle.try_lift()?)
             }
         };
 
@@ -440,7 +436,7 @@ enum AgileOpModeSTy<'a> {
 struct AgilePskBundle<'a>(PskBundle<'a>);
 
 impl<'a> AgilePskBundle<'a> {
-    fn try_lift<Kdf: KdfTrait>(self) -> Result<PskBundle<'a>, AgileHpkeError> {
+    fn try_lift(self) -> Result<PskBundle<'a>, AgileHpkeError> {
         Ok(self.0)
     }
 }
@@ -528,7 +524,7 @@ where
     R: CryptoRng + RngCore,
 {
     let kem_alg = mode.kem_alg;
-    let mode = mode.clone().try_lift::<Kem, Kdf>()?;
+    let mode = mode.clone().try_lift::<Kem>()?;
     let pk_recip = pk_recip.try_lift::<Kem>()?;
 
     let (encapped_key, aead_ctx) = setup_sender::<A, Kdf, Kem, _>(&mode, &pk_recip, info, csprng)?;
@@ -550,6 +546,7 @@ fn agile_setup_sender<R: CryptoRng + RngCore>(
     csprng: &mut R,
 ) -> Result<(AgileEncappedKey, Box<dyn AgileAeadCtxS>), AgileHpkeError> {
     // Do all the necessary validation
+    POLICY.check_ids(kem_alg.to_u16(), kdf_alg.to_u16(), aead_alg.to_u16())?;
     mode.validate()?;
     if mode.kem_alg != pk_recip.kem_alg {
         return Err(AgileHpkeError::AlgMismatch(
@@ -593,6 +590,7 @@ fn agile_setup_sender<R: CryptoRng + RngCore>(
 
 // The leg work of agile_setup_receiver. The Dummy type parameter is so that it can be used with
 // the hpke_dispatch! macro. The macro expects its callback function to have 4 type parameters
+#[allow(clippy::extra_unused_type_parameters)]
 fn do_setup_receiver<A, Kdf, Kem, Dummy>(
     mode: &AgileOpModeR,
     recip_keypair: &AgileKeypair,
@@ -604,7 +602,7 @@ where
     Kdf: 'static + KdfTrait,
     Kem: 'static + KemTrait,
 {
-    let mode = mode.clone().try_lift::<Kem, Kdf>()?;
+    let mode = mode.clone().try_lift::<Kem>()?;
     let (sk_recip, _) = recip_keypair.try_lift::<Kem>()?;
     let encapped_key = encapped_key.try_lift::<Kem>()?;
 
@@ -622,6 +620,7 @@ fn agile_setup_receiver(
     info: &[u8],
 ) -> Result<Box<dyn AgileAeadCtxR>, AgileHpkeError> {
     // Do all the necessary validation
+    POLICY.check_ids(kem_alg.to_u16(), kdf_alg.to_u16(), aead_alg.to_u16())?;
     recip_keypair.validate()?;
     mode.validate()?;
     if mode.kem_alg != recip_keypair.0.kem_alg {
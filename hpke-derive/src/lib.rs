@@ -0,0 +1,208 @@
+//! The `HpkeSealed` derive macro. Use it through the `hpke` crate's `derive` feature, as
+//! `hpke::sealed::HpkeSealed`, whose docs describe what it generates.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, Data, DeriveInput, Error, Fields, Ident, LitStr,
+    Meta, Path, Token,
+};
+
+/// Generates a companion struct with the `#[hpke(sealed)]` fields sealed, and methods to convert
+/// between the two. See `hpke::sealed::HpkeSealed`.
+#[proc_macro_derive(HpkeSealed, attributes(hpke))]
+pub fn derive_hpke_sealed(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The struct-level `#[hpke(...)]` options
+struct StructOpts {
+    /// The companion struct's name, from `name = "..."`
+    name: Option<Ident>,
+    /// The derives to put on the companion struct, from `derive(...)`
+    derives: Vec<Path>,
+}
+
+fn parse_struct_opts(input: &DeriveInput) -> Result<StructOpts, Error> {
+    let mut opts = StructOpts {
+        name: None,
+        derives: Vec::new(),
+    };
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("hpke")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let name: LitStr = meta.value()?.parse()?;
+                opts.name = Some(name.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("derive") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                let paths = Punctuated::<Path, Token![,]>::parse_terminated(&content)?;
+                opts.derives.extend(paths);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"` or `derive(...)`"))
+            }
+        })?;
+    }
+    Ok(opts)
+}
+
+/// Returns whether a field is marked `#[hpke(sealed)]`
+fn is_sealed(field: &syn::Field) -> Result<bool, Error> {
+    let mut sealed = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("hpke")) {
+        match &attr.meta {
+            Meta::List(_) => attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("sealed") {
+                    sealed = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `sealed`"))
+                }
+            })?,
+            _ => return Err(Error::new_spanned(attr, "expected `#[hpke(sealed)]`")),
+        }
+    }
+    Ok(sealed)
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "HpkeSealed can't be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    Span::call_site(),
+                    "HpkeSealed needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "HpkeSealed can only be derived for structs",
+            ))
+        }
+    };
+
+    let opts = parse_struct_opts(&input)?;
+    let vis = &input.vis;
+    let name = &input.ident;
+    let sealed_name = opts.name.unwrap_or_else(|| format_ident!("{}Sealed", name));
+    let derives = &opts.derives;
+    let derive_attr = if derives.is_empty() {
+        quote!()
+    } else {
+        quote!(#[derive(#(#derives),*)])
+    };
+    let struct_name = name.to_string();
+
+    let mut companion_fields = Vec::new();
+    let mut seal_fields = Vec::new();
+    let mut open_fields = Vec::new();
+    let mut sealed_count = 0usize;
+    for field in fields {
+        let field_vis = &field.vis;
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let ty = &field.ty;
+        let field_name = ident.to_string();
+        if is_sealed(field)? {
+            sealed_count += 1;
+            companion_fields.push(quote! {
+                #[doc = concat!("The sealed `", #field_name, "`")]
+                #field_vis #ident: ::hpke::sealed::Vec<u8>
+            });
+            seal_fields.push(quote! {
+                #ident: ::hpke::sealed::seal_field::<A, Kdf, Kem, R>(
+                    pk_recip,
+                    #struct_name,
+                    #field_name,
+                    &::hpke::sealed::SealedField::to_field_bytes(&self.#ident),
+                    aad,
+                    csprng,
+                )?
+            });
+            open_fields.push(quote! {
+                #ident: ::hpke::sealed::SealedField::from_field_bytes(
+                    ::hpke::sealed::open_field::<A, Kdf, Kem>(
+                        sk_recip,
+                        #struct_name,
+                        #field_name,
+                        &self.#ident,
+                        aad,
+                    )?,
+                )?
+            });
+        } else {
+            companion_fields.push(quote!(#field_vis #ident: #ty));
+            seal_fields.push(quote!(#ident: ::core::clone::Clone::clone(&self.#ident)));
+            open_fields.push(quote!(#ident: ::core::clone::Clone::clone(&self.#ident)));
+        }
+    }
+    if sealed_count == 0 {
+        return Err(Error::new(
+            Span::call_site(),
+            "HpkeSealed needs at least one `#[hpke(sealed)]` field",
+        ));
+    }
+
+    let companion_doc = format!("[`{}`] with its sealed fields sealed", name);
+    Ok(quote! {
+        #[doc = #companion_doc]
+        #derive_attr
+        #vis struct #sealed_name {
+            #(#companion_fields,)*
+        }
+
+        impl #name {
+            /// Seals the `#[hpke(sealed)]` fields to `pk_recip`, each under its own encapsulation,
+            /// and copies the rest. `aad` is bound into every sealed field, e.g., a row's primary
+            /// key, and must be the same on opening.
+            #vis fn seal<A, Kdf, Kem, R>(
+                &self,
+                pk_recip: &Kem::PublicKey,
+                aad: &[u8],
+                csprng: &mut R,
+            ) -> ::core::result::Result<#sealed_name, ::hpke::HpkeError>
+            where
+                A: ::hpke::aead::Aead,
+                Kdf: ::hpke::kdf::Kdf,
+                Kem: ::hpke::Kem,
+                R: ::hpke::rand_core::CryptoRng + ::hpke::rand_core::RngCore,
+            {
+                ::core::result::Result::Ok(#sealed_name {
+                    #(#seal_fields,)*
+                })
+            }
+        }
+
+        impl #sealed_name {
+            /// Opens the sealed fields with `sk_recip`, and copies the rest
+            #vis fn open<A, Kdf, Kem>(
+                &self,
+                sk_recip: &Kem::PrivateKey,
+                aad: &[u8],
+            ) -> ::core::result::Result<#name, ::hpke::HpkeError>
+            where
+                A: ::hpke::aead::Aead,
+                Kdf: ::hpke::kdf::Kdf,
+                Kem: ::hpke::Kem,
+            {
+                ::core::result::Result::Ok(#name {
+                    #(#open_fields,)*
+                })
+            }
+        }
+    })
+}
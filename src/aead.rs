@@ -1,18 +1,26 @@
 //! Traits and structs for authenticated encryption schemes
 
 use crate::{
-    kdf::{Kdf as KdfTrait, LabeledExpand, SimpleHkdf},
+    kdf::{DigestArray, Kdf as KdfTrait, LabeledExpand, SimpleHkdf},
     kem::Kem as KemTrait,
+    media::{self, MediaKeys, SrtpProfile, MEDIA_EXPORT_DOMAIN},
+    psk::{ExportedPsk, PSK_EXPORT_DOMAIN, PSK_LEN},
     setup::ExporterSecret,
-    util::{enforce_equal_len, enforce_outbuf_len, full_suite_id, FullSuiteId},
+    util::{enforce_equal_len, enforce_outbuf_len, exceeds, full_suite_id, FullSuiteId},
     Deserializable, HpkeError, Serializable,
 };
 
-use core::{default::Default, marker::PhantomData, u8};
+#[cfg(feature = "offload")]
+use crate::offload::{AesGcm, AsyncAesGcmAccelerator, Unsupported};
+
+use core::{default::Default, marker::PhantomData, ops::Deref};
 
 use aead::{AeadCore as BaseAeadCore, AeadInPlace as BaseAeadInPlace, KeyInit as BaseKeyInit};
 use byteorder::{BigEndian, ByteOrder};
-use generic_array::GenericArray;
+use digest::Digest;
+use generic_array::{typenum::Unsigned, GenericArray};
+use sha2::Sha256;
+use subtle::{Choice, ConstantTimeEq};
 use zeroize::Zeroize;
 
 /// Represents authenticated encryption functionality
@@ -23,6 +31,15 @@ pub trait Aead {
 
     /// The algorithm identifier for an AEAD implementation
     const AEAD_ID: u16;
+
+    /// The largest plaintext, in bytes, that can be sealed in a single message
+    const MAX_PLAINTEXT_LEN: u64;
+
+    /// The largest AAD, in bytes, that can be given to a single seal or open
+    const MAX_AAD_LEN: u64;
+
+    /// The size of an authentication tag, i.e., how many bytes sealing adds to a plaintext
+    const TAG_SIZE: usize = <<Self::AeadImpl as BaseAeadCore>::TagSize as Unsigned>::USIZE;
 }
 
 // A nonce is a bytestring you only use for encryption once
@@ -106,30 +123,94 @@ fn increment_seq(seq: &Seq) -> Option<Seq> {
 /// Derives a nonce from the base nonce and a "sequence number". The sequence number is treated as
 /// a big-endian integer with length equal to the nonce length.
 fn mix_nonce<A: Aead>(base_nonce: &AeadNonce<A>, seq: &Seq) -> AeadNonce<A> {
-    // Write `seq` in big-endian order into a byte buffer that's the size of a nonce
-    let mut seq_buf = AeadNonce::<A>::default();
-    // We just write to the last seq_size bytes. This is necessary because our AEAD nonces (>= 96
+    let mut nonce = AeadNonce::<A>::default();
+    xor_seq_into_nonce(&base_nonce.0, seq.0, &mut nonce.0);
+    nonce
+}
+
+/// The body of `mix_nonce`. This is not generic, so there's only one copy of it no matter how
+/// many AEADs are in use. `out` must be the same length as `base_nonce`.
+fn xor_seq_into_nonce(base_nonce: &[u8], seq: u64, out: &mut [u8]) {
+    // We just XOR into the last seq_size bytes. This is necessary because our AEAD nonces (>= 96
     // bits) are always bigger than the sequence buffer (64 bits). We write to the last 64 bits
     // because this is a big-endian number.
     let seq_size = core::mem::size_of::<Seq>();
-    let nonce_size = base_nonce.0.len();
-    BigEndian::write_u64(&mut seq_buf.0[nonce_size - seq_size..], seq.0);
+    let nonce_size = base_nonce.len();
+    let mut seq_buf = [0u8; 8];
+    BigEndian::write_u64(&mut seq_buf, seq);
+
+    out.copy_from_slice(base_nonce);
+    out[nonce_size - seq_size..]
+        .iter_mut()
+        .zip(seq_buf.iter())
+        .for_each(|(nonce_byte, seq_byte)| *nonce_byte ^= seq_byte);
+}
+
+/// A byte buffer with a fixed capacity of `N` bytes, stored inline. This is returned by
+/// `AeadCtxS::seal_to_array` and `AeadCtxR::open_to_array`, and derefs to the bytes written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrayBuf<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayBuf<N> {
+    /// Returns the bytes written to this buffer
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Wraps the first `len` bytes of `bytes`
+    pub(crate) fn new(bytes: [u8; N], len: usize) -> ArrayBuf<N> {
+        debug_assert!(len <= N);
+        ArrayBuf { bytes, len }
+    }
+}
+
+impl<const N: usize> Deref for ArrayBuf<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for ArrayBuf<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
 
-    // XOR the base nonce bytes with the sequence bytes
-    let new_nonce_iter = base_nonce
-        .0
-        .iter()
-        .zip(seq_buf.0.iter())
-        .map(|(nonce_byte, seq_byte)| nonce_byte ^ seq_byte);
+// Stable Rust can't write `[u8; N + A::TAG_SIZE]` in a signature, so `seal_fixed` and `open_fixed`
+// take the ciphertext length as a second parameter, and evaluating `OK` checks it at compile time
+struct FixedLens<A: Aead, const N: usize, const M: usize>(PhantomData<A>);
 
-    // This cannot fail, as the length of AeadNonce<A> is precisely the length of Seq
-    AeadNonce(GenericArray::from_exact_iter(new_nonce_iter).unwrap())
+impl<A: Aead, const N: usize, const M: usize> FixedLens<A, N, M> {
+    const OK: () = assert!(
+        M == N + A::TAG_SIZE,
+        "ciphertext length must be the plaintext length plus the AEAD's TAG_SIZE"
+    );
 }
 
 /// An authenticated encryption tag
 #[derive(Clone)]
 pub struct AeadTag<A: Aead>(GenericArray<u8, <A::AeadImpl as BaseAeadCore>::TagSize>);
 
+impl<A: Aead> ConstantTimeEq for AeadTag<A> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+// Comparing tags with == is constant-time too. Tags are checked when opening, so there should be
+// no need to compare them by hand. But if an application does, this makes it safe.
+impl<A: Aead> PartialEq for AeadTag<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+impl<A: Aead> Eq for AeadTag<A> {}
+
 impl<A: Aead> Default for AeadTag<A> {
     fn default() -> AeadTag<A> {
         AeadTag(GenericArray::<u8, <A::AeadImpl as BaseAeadCore>::TagSize>::default())
@@ -159,59 +240,444 @@ impl<A: Aead> Deserializable for AeadTag<A> {
     }
 }
 
+impl_hex_display_fromstr!(AeadTag<A>, A: Aead);
+impl_try_from_bytes!(AeadTag<A>, A: Aead);
+
+impl<A: Aead> AsRef<[u8]> for AeadTag<A> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// The raw secrets of an HPKE context: its AEAD key, base nonce, exporter secret, and the next
+/// sequence number. These are zeroized on drop. See `AeadCtxS::danger_into_raw_parts` for what can
+/// go wrong with them.
+#[cfg(feature = "hazmat")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hazmat")))]
+pub struct RawCtxParts<A: Aead, Kdf: KdfTrait> {
+    key: AeadKey<A>,
+    base_nonce: AeadNonce<A>,
+    exporter_secret: ExporterSecret<Kdf>,
+    seq: u64,
+}
+
+#[cfg(feature = "hazmat")]
+impl<A: Aead, Kdf: KdfTrait> RawCtxParts<A, Kdf> {
+    /// Returns the AEAD key. This is empty for the export-only AEAD.
+    pub fn key(&self) -> &[u8] {
+        &self.key.0
+    }
+
+    /// Returns the base nonce. The nonce of the message with sequence number `seq` is this XORed
+    /// with `seq`, big-endian and left-padded to the nonce size. This is empty for the export-only
+    /// AEAD.
+    pub fn base_nonce(&self) -> &[u8] {
+        &self.base_nonce.0
+    }
+
+    /// Returns the exporter secret
+    pub fn exporter_secret(&self) -> &[u8] {
+        &self.exporter_secret.0
+    }
+
+    /// Returns the sequence number of the next message, i.e., the number of messages the context
+    /// sealed or opened before it was taken apart
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+/// Calls `f` on the concatenation of the given AAD pieces. The AEAD implementations only take
+/// contiguous AAD, so this joins them, but skips the allocation when there's at most one piece.
+#[cfg(any(feature = "alloc", feature = "std"))]
+fn with_joined_aad<T>(aad: &[&[u8]], f: impl FnOnce(&[u8]) -> T) -> T {
+    match aad {
+        [] => f(&[]),
+        [piece] => f(piece),
+        pieces => f(&pieces.concat()),
+    }
+}
+
+/// The labels HPKE uses internally, in its key schedule, KEMs, context forks, responses, and
+/// sessions. These are refused
+/// as labels by `export_labeled`, so application labels can't be confused with them.
+pub const RESERVED_LABELS: &[&[u8]] = &[
+    b"psk_id_hash",
+    b"info_hash",
+    b"secret",
+    b"key",
+    b"base_nonce",
+    b"exp",
+    b"sec",
+    b"eae_prk",
+    b"shared_secret",
+    b"dkp_prk",
+    b"candidate",
+    b"sk",
+    b"fork_key",
+    b"fork_base_nonce",
+    b"fork_exp",
+    b"response_key",
+    b"response_base_nonce",
+    b"response_exp",
+    b"duplex_key",
+    b"duplex_base_nonce",
+    b"duplex_exp",
+    b"context_id",
+    b"hybrid_secret",
+    b"hybrid_key",
+    b"hybrid_base_nonce",
+    b"hybrid_exp",
+];
+
+/// The size of the random nonce that starts every response. See `AeadCtxR::respond`.
+pub const RESPONSE_NONCE_SIZE: usize = 32;
+
+/// The version of the suspended context format. See `AeadCtxS::suspend`.
+const SUSPENDED_CTX_VERSION: u8 = 1;
+
+/// The number of bytes of SHA-256 that a suspended context is checksummed with
+const SUSPENDED_CHECKSUM_SIZE: usize = 4;
+
+/// The maximum size of a suspended context, in bytes. This is reached by AES-256-GCM with
+/// HKDF-SHA512: a 7-byte header, an 8-byte sequence number, a 32-byte key, a 12-byte nonce, a
+/// 64-byte exporter secret, and a 4-byte checksum.
+pub const MAX_SUSPENDED_CTX_SIZE: usize = 127;
+
 /// The HPKE encryption context. This is what you use to `seal` plaintexts and `open` ciphertexts.
 pub(crate) struct AeadCtx<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
-    /// Records whether the nonce sequence counter has overflowed
+    /// Records whether the nonce sequence counter has overflowed. Once set, this is never unset,
+    /// and every seal and open fails.
     overflowed: bool,
-    /// The underlying AEAD instance. This also does decryption.
+    /// The underlying AEAD instance. This also does decryption. With `compact-ctx`, this isn't
+    /// kept, and is made from `key` for each seal and open instead, since AES-GCM's expanded key is
+    /// several times the size of the rest of the context.
+    #[cfg(not(feature = "compact-ctx"))]
     encryptor: A::AeadImpl,
+    /// The AEAD key
+    key: AeadKey<A>,
     /// The base nonce which we XOR with sequence numbers
     base_nonce: AeadNonce<A>,
-    /// The exporter secret, used in the `export()` method
+    /// The HKDF context keyed with the exporter secret, used in the `export()` method. This is
+    /// computed once so that each export is a single HKDF-Expand. Like the AEAD's key schedule, the
+    /// underlying HMAC state is not zeroized on drop. With `compact-ctx`, this is made from
+    /// `exporter_secret` on each use instead.
+    #[cfg(not(feature = "compact-ctx"))]
+    exporter_hkdf: SimpleHkdf<Kdf>,
+    /// The exporter secret
     exporter_secret: ExporterSecret<Kdf>,
     /// The running sequence number
     seq: Seq,
     /// This binds the `AeadCtx` to the KEM that made it. Used to generate `suite_id`.
     src_kem: PhantomData<Kem>,
-    /// The full ID of the ciphersuite that created this `AeadCtx`. Used for context binding.
-    suite_id: FullSuiteId,
 }
 
-// Necessary for test_setup_soundness
+// Necessary for test_setup_soundness. This must never be available outside of tests, since a
+// cloned sender reuses nonces. See `AeadCtxS::fork_for_broadcast` for the safe alternative.
 #[cfg(test)]
 impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> Clone for AeadCtx<A, Kdf, Kem> {
     fn clone(&self) -> AeadCtx<A, Kdf, Kem> {
         AeadCtx {
             overflowed: self.overflowed,
+            #[cfg(not(feature = "compact-ctx"))]
             encryptor: self.encryptor.clone(),
+            key: AeadKey(self.key.0.clone()),
             base_nonce: self.base_nonce.clone(),
+            #[cfg(not(feature = "compact-ctx"))]
+            exporter_hkdf: self.exporter_hkdf.clone(),
             exporter_secret: self.exporter_secret.clone(),
             seq: self.seq.clone(),
             src_kem: PhantomData,
-            suite_id: self.suite_id,
         }
     }
 }
 
 impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtx<A, Kdf, Kem> {
+    /// The full ID of the ciphersuite that created this `AeadCtx`. Used for context binding.
+    pub(crate) const SUITE_ID: FullSuiteId = full_suite_id::<A, Kdf, Kem>();
+
     /// Makes an AeadCtx from a raw key and nonce
     pub(crate) fn new(
         key: &AeadKey<A>,
         base_nonce: AeadNonce<A>,
         exporter_secret: ExporterSecret<Kdf>,
     ) -> AeadCtx<A, Kdf, Kem> {
-        let suite_id = full_suite_id::<A, Kdf, Kem>();
         AeadCtx {
             overflowed: false,
+            #[cfg(not(feature = "compact-ctx"))]
             encryptor: <A::AeadImpl as aead::KeyInit>::new(&key.0),
+            key: AeadKey(key.0.clone()),
             base_nonce,
+            #[cfg(not(feature = "compact-ctx"))]
+            exporter_hkdf: Self::make_exporter_hkdf(&exporter_secret),
             exporter_secret,
             seq: <Seq as Default>::default(),
             src_kem: PhantomData,
-            suite_id,
         }
     }
 
+    /// Makes the HKDF context keyed with the given exporter secret
+    fn make_exporter_hkdf(exporter_secret: &ExporterSecret<Kdf>) -> SimpleHkdf<Kdf> {
+        // Use our exporter secret as the PRK for HKDF-Expand. The only time this fails is when the
+        // length of the PRK is not the the underlying hash function's digest size. But that's
+        // guaranteed by the type system, so we can unwrap().
+        SimpleHkdf::<Kdf>::from_prk(exporter_secret.0.as_slice()).unwrap()
+    }
+
+    /// Returns the AEAD instance that seals and opens under this context's key
+    #[cfg(not(feature = "compact-ctx"))]
+    fn aead(&self) -> &A::AeadImpl {
+        &self.encryptor
+    }
+
+    /// Makes the AEAD instance that seals and opens under this context's key. Like the key
+    /// schedules of the AEAD crates themselves, this isn't zeroized on drop.
+    #[cfg(feature = "compact-ctx")]
+    fn aead(&self) -> A::AeadImpl {
+        <A::AeadImpl as aead::KeyInit>::new(&self.key.0)
+    }
+
+    /// Returns the HKDF context keyed with the exporter secret, for exports and derived contexts
+    #[cfg(not(feature = "compact-ctx"))]
+    fn exporter_hkdf(&self) -> &SimpleHkdf<Kdf> {
+        &self.exporter_hkdf
+    }
+
+    /// Makes the HKDF context keyed with the exporter secret, for exports and derived contexts
+    #[cfg(feature = "compact-ctx")]
+    fn exporter_hkdf(&self) -> SimpleHkdf<Kdf> {
+        Self::make_exporter_hkdf(&self.exporter_secret)
+    }
+
+    /// The length of this context when suspended. See `AeadCtxS::suspend`.
+    const SUSPENDED_SIZE: usize =
+        1 + 6 + 8 + Self::SUSPENDED_KEY_NONCE_SIZE + Kdf::DIGEST_SIZE + SUSPENDED_CHECKSUM_SIZE;
+
+    /// The export-only AEAD never uses its key or nonce, so suspending it skips them
+    const SUSPENDED_KEY_NONCE_SIZE: usize = if A::AEAD_ID == ExportOnlyAead::AEAD_ID {
+        0
+    } else {
+        <<A::AeadImpl as aead::KeySizeUser>::KeySize as Unsigned>::USIZE
+            + <<A::AeadImpl as BaseAeadCore>::NonceSize as Unsigned>::USIZE
+    };
+
+    /// Serializes this context's state. `is_receiver` is recorded so that a suspended sender can't
+    /// be resumed as a receiver, or vice versa.
+    fn suspend(&self, is_receiver: bool) -> ArrayBuf<MAX_SUSPENDED_CTX_SIZE> {
+        let mut bytes = [0u8; MAX_SUSPENDED_CTX_SIZE];
+        let mut len = 0;
+
+        let header =
+            (SUSPENDED_CTX_VERSION << 4) | ((self.overflowed as u8) << 1) | (is_receiver as u8);
+        let fields: [&[u8]; 6] = [
+            &[header],
+            &Self::SUITE_ID[4..],
+            &self.seq.0.to_be_bytes(),
+            if Self::SUSPENDED_KEY_NONCE_SIZE == 0 {
+                &[]
+            } else {
+                &self.key.0
+            },
+            if Self::SUSPENDED_KEY_NONCE_SIZE == 0 {
+                &[]
+            } else {
+                &self.base_nonce.0
+            },
+            &self.exporter_secret.0,
+        ];
+        for field in fields {
+            bytes[len..len + field.len()].copy_from_slice(field);
+            len += field.len();
+        }
+
+        // Append a truncated hash of everything so far, so corruption is detected on resume
+        let checksum = Sha256::digest(&bytes[..len]);
+        bytes[len..len + SUSPENDED_CHECKSUM_SIZE]
+            .copy_from_slice(&checksum[..SUSPENDED_CHECKSUM_SIZE]);
+        len += SUSPENDED_CHECKSUM_SIZE;
+
+        debug_assert_eq!(len, Self::SUSPENDED_SIZE);
+        ArrayBuf { bytes, len }
+    }
+
+    /// Takes the context apart into its secrets. See `AeadCtxS::danger_into_raw_parts`.
+    #[cfg(feature = "hazmat")]
+    fn into_raw_parts(self) -> Result<RawCtxParts<A, Kdf>, HpkeError> {
+        if self.overflowed {
+            return Err(HpkeError::MessageLimitReached);
+        }
+        Ok(RawCtxParts {
+            key: self.key,
+            base_nonce: self.base_nonce,
+            exporter_secret: self.exporter_secret,
+            seq: self.seq.0,
+        })
+    }
+
+    /// Deserializes a context that was suspended with the same role and ciphersuite
+    fn resume(suspended: &[u8], is_receiver: bool) -> Result<AeadCtx<A, Kdf, Kem>, HpkeError> {
+        enforce_equal_len(Self::SUSPENDED_SIZE, suspended.len())?;
+
+        // Check the checksum, then the header and suite
+        let (body, checksum) = suspended.split_at(suspended.len() - SUSPENDED_CHECKSUM_SIZE);
+        // The checksum is computed over secrets, so compare it in constant time
+        if !bool::from(Sha256::digest(body)[..SUSPENDED_CHECKSUM_SIZE].ct_eq(checksum)) {
+            return Err(HpkeError::ValidationError);
+        }
+        let (header, rest) = body.split_at(1);
+        let (suite, rest) = rest.split_at(6);
+        let (seq, rest) = rest.split_at(8);
+        let header = header[0];
+        if header >> 4 != SUSPENDED_CTX_VERSION
+            || header & 0b1101 != is_receiver as u8
+            || suite != &Self::SUITE_ID[4..]
+        {
+            return Err(HpkeError::ValidationError);
+        }
+
+        // Now the secrets. These are all the right size, since the total length was checked.
+        let mut key = AeadKey::<A>::default();
+        let mut base_nonce = AeadNonce::<A>::default();
+        let mut exporter_secret = <ExporterSecret<Kdf> as Default>::default();
+        let rest = if Self::SUSPENDED_KEY_NONCE_SIZE == 0 {
+            rest
+        } else {
+            let (key_bytes, rest) = rest.split_at(key.0.len());
+            let (nonce_bytes, rest) = rest.split_at(base_nonce.0.len());
+            key.0.copy_from_slice(key_bytes);
+            base_nonce.0.copy_from_slice(nonce_bytes);
+            rest
+        };
+        exporter_secret.0.copy_from_slice(rest);
+
+        let mut ctx = AeadCtx::new(&key, base_nonce, exporter_secret);
+        ctx.overflowed = header & 0b10 != 0;
+        ctx.seq = Seq(u64::from_be_bytes(seq.try_into().unwrap()));
+        Ok(ctx)
+    }
+
+    /// Checks a message and its AAD against the AEAD's length limits. If the message is too long,
+    /// returns `too_long`. The export-only AEAD is exempt, so that sealing and opening with it
+    /// still panic as documented.
+    fn check_msg_lens(
+        msg_len: usize,
+        aad_len: usize,
+        too_long: HpkeError,
+    ) -> Result<(), HpkeError> {
+        if A::AEAD_ID == ExportOnlyAead::AEAD_ID {
+            Ok(())
+        } else if exceeds(aad_len, A::MAX_AAD_LEN) {
+            Err(HpkeError::InputTooLong)
+        } else if exceeds(msg_len, A::MAX_PLAINTEXT_LEN) {
+            Err(too_long)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Derives a fresh context from this one's exporter secret, `fork_seq`, and `fork_id`. The
+    /// labels are distinct from the RFC's "sec", so no `export()` output ever equals a fork's
+    /// secrets.
+    fn fork(&self, fork_seq: u64, fork_id: &[u8]) -> AeadCtx<A, Kdf, Kem> {
+        self.derive_child(
+            [b"fork_key", b"fork_base_nonce", b"fork_exp"],
+            &[&fork_seq.to_be_bytes(), fork_id],
+        )
+    }
+
+    /// Derives a fork under the current sequence number, and uses that number up, so no two forks
+    /// of this context are ever derived from the same number. See `AeadCtxS::fork_for_broadcast`.
+    fn fork_next(&mut self, fork_id: &[u8]) -> Result<AeadCtx<A, Kdf, Kem>, HpkeError> {
+        if self.overflowed {
+            return Err(HpkeError::MessageLimitReached);
+        }
+        let fork = self.fork(self.seq.0, fork_id);
+        match increment_seq(&self.seq) {
+            Some(new_seq) => self.seq = new_seq,
+            None => self.overflowed = true,
+        }
+        Ok(fork)
+    }
+
+    /// Derives the context that seals or opens a response under the given response nonce. See
+    /// `AeadCtxR::respond`.
+    fn response_ctx(&self, response_nonce: &[u8]) -> AeadCtx<A, Kdf, Kem> {
+        self.derive_child(
+            [b"response_key", b"response_base_nonce", b"response_exp"],
+            &[response_nonce],
+        )
+    }
+
+    /// Derives the context that carries messages sent by the given end of a duplex session. See
+    /// `session::Session`.
+    #[cfg(feature = "session")]
+    fn duplex_ctx(&self, sent_by_initiator: bool) -> AeadCtx<A, Kdf, Kem> {
+        let direction: &[u8] = if sent_by_initiator {
+            b"initiator"
+        } else {
+            b"responder"
+        };
+        self.derive_child(
+            [b"duplex_key", b"duplex_base_nonce", b"duplex_exp"],
+            &[direction],
+        )
+    }
+
+    /// Derives a context whose key, base nonce, and exporter secret are expanded from this
+    /// context's exporter secret with the given labels, in that order, and the concatenation of
+    /// `id`
+    // The HKDF context is borrowed, unless compact-ctx makes it on the spot
+    #[allow(clippy::needless_borrow)]
+    fn derive_child<const N: usize>(
+        &self,
+        labels: [&[u8]; 3],
+        id: &[&[u8]; N],
+    ) -> AeadCtx<A, Kdf, Kem> {
+        Self::from_prk(&self.exporter_hkdf(), labels, id)
+    }
+
+    /// Makes a context whose key, base nonce, and exporter secret are expanded from `prk` with
+    /// the given labels, in that order, and the concatenation of `info`
+    pub(crate) fn from_prk<const N: usize>(
+        prk: &SimpleHkdf<Kdf>,
+        labels: [&[u8]; 3],
+        info: &[&[u8]; N],
+    ) -> AeadCtx<A, Kdf, Kem> {
+        let mut key = AeadKey::<A>::default();
+        let mut base_nonce = AeadNonce::<A>::default();
+        let mut exporter_secret = <ExporterSecret<Kdf> as Default>::default();
+
+        // None of these fail, since every output is far shorter than 255x the digest size
+        let outs = [
+            key.0.as_mut_slice(),
+            base_nonce.0.as_mut_slice(),
+            exporter_secret.0.as_mut_slice(),
+        ];
+        for (label, out) in labels.into_iter().zip(outs) {
+            prk.labeled_expand_multi(&Self::SUITE_ID, label, info, out)
+                .unwrap();
+        }
+
+        AeadCtx::new(&key, base_nonce, exporter_secret)
+    }
+
+    /// Returns the nonce that the next seal or open will use. This lets test vectors record and
+    /// check the nonce of every message.
+    #[allow(dead_code)]
+    pub(crate) fn current_nonce(&self) -> AeadNonce<A> {
+        mix_nonce::<A>(&self.base_nonce, &self.seq)
+    }
+
+    /// Returns the context ID. See `AeadCtxS::context_id`.
+    pub(crate) fn context_id(&self) -> DigestArray<Kdf> {
+        let mut id = DigestArray::<Kdf>::default();
+        // This doesn't fail, since the output is one digest long
+        self.exporter_hkdf()
+            .labeled_expand(&Self::SUITE_ID, b"context_id", b"", &mut id)
+            .unwrap();
+        id
+    }
+
     // RFC 9180 §5.3
     // def Context.Export(exporter_context, L):
     //   return LabeledExpand(self.exporter_secret, "sec",
@@ -225,21 +691,97 @@ impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtx<A, Kdf, Kem> {
     /// Returns `Ok(())` on success. If the buffer length is more than 255x the digest size (in
     /// bytes) of the underlying hash function, returns an `Err(HpkeError::KdfOutputTooLong)`. Just
     /// don't use to fill massive buffers and you'll be fine.
+    /// If the exporter context is longer than `Kdf::MAX_EXPORTER_CTX_LEN`, returns
+    /// `Err(HpkeError::InputTooLong)`.
     pub fn export(&self, exporter_ctx: &[u8], out_buf: &mut [u8]) -> Result<(), HpkeError> {
-        // Use our exporter secret as the PRK for an HKDF-Expand op. The only time this fails is
-        // when the length of the PRK is not the the underlying hash function's digest size. But
-        // that's guaranteed by the type system, so we can unwrap().
-        let hkdf_ctx = SimpleHkdf::<Kdf>::from_prk(self.exporter_secret.0.as_slice()).unwrap();
-
-        // This call either succeeds or returns hkdf::InvalidLength (iff the buffer length is more
-        // than 255x the digest size of the underlying hash function)
-        hkdf_ctx
-            .labeled_expand(&self.suite_id, b"sec", exporter_ctx, out_buf)
+        let res = if exceeds(exporter_ctx.len(), Kdf::MAX_EXPORTER_CTX_LEN) {
+            Err(HpkeError::InputTooLong)
+        } else {
+            // This call either succeeds or returns hkdf::InvalidLength (iff the buffer length is
+            // more than 255x the digest size of the underlying hash function)
+            self.exporter_hkdf()
+                .labeled_expand(&Self::SUITE_ID, b"sec", exporter_ctx, out_buf)
+                .map_err(|_| HpkeError::KdfOutputTooLong)
+        };
+
+        crate::trace::export::<A, Kdf, Kem>(
+            exporter_ctx.len(),
+            out_buf.len(),
+            res.as_ref().copied(),
+        );
+        res
+    }
+
+    /// Exports with the exporter context `I2OSP(len(domain), 2) || domain || I2OSP(len(label), 2)
+    /// || label || context`. See `AeadCtxS::export_labeled`.
+    pub fn export_labeled(
+        &self,
+        domain: &[u8],
+        label: &[u8],
+        context: &[u8],
+        out_buf: &mut [u8],
+    ) -> Result<(), HpkeError> {
+        let res = self.export_labeled_untraced(domain, label, context, out_buf);
+        let framed_len = (2 + domain.len() + 2 + label.len()).saturating_add(context.len());
+        crate::trace::export::<A, Kdf, Kem>(framed_len, out_buf.len(), res.as_ref().copied());
+        res
+    }
+
+    /// Exports a PSK for another protocol. See `AeadCtxS::export_psk`.
+    pub fn export_psk(&self, target: &[u8], context: &[u8]) -> Result<ExportedPsk, HpkeError> {
+        let mut psk = ExportedPsk([0u8; PSK_LEN]);
+        self.export_labeled(PSK_EXPORT_DOMAIN, target, context, &mut psk.0)?;
+        Ok(psk)
+    }
+
+    /// Exports media keys. See `AeadCtxS::export_media_keys`.
+    pub fn export_media_keys(
+        &self,
+        profile: SrtpProfile,
+        context: &[u8],
+        is_initiator: bool,
+    ) -> Result<MediaKeys, HpkeError> {
+        media::export_media_keys(profile, context, is_initiator, |label, context, out| {
+            self.export_labeled(MEDIA_EXPORT_DOMAIN, label, context, out)
+        })
+    }
+
+    /// The body of `export_labeled`
+    fn export_labeled_untraced(
+        &self,
+        domain: &[u8],
+        label: &[u8],
+        context: &[u8],
+        out_buf: &mut [u8],
+    ) -> Result<(), HpkeError> {
+        if RESERVED_LABELS.contains(&label) {
+            return Err(HpkeError::ReservedLabel);
+        }
+        let domain_len = u16::try_from(domain.len()).map_err(|_| HpkeError::ValidationError)?;
+        let label_len = u16::try_from(label.len()).map_err(|_| HpkeError::ValidationError)?;
+
+        let framed_len = (2 + domain.len() + 2 + label.len()).saturating_add(context.len());
+        if exceeds(framed_len, Kdf::MAX_EXPORTER_CTX_LEN) {
+            return Err(HpkeError::InputTooLong);
+        }
+
+        let exporter_ctx = [
+            &domain_len.to_be_bytes()[..],
+            domain,
+            &label_len.to_be_bytes()[..],
+            label,
+            context,
+        ];
+        self.exporter_hkdf()
+            .labeled_expand_multi(&Self::SUITE_ID, b"sec", &exporter_ctx, out_buf)
             .map_err(|_| HpkeError::KdfOutputTooLong)
     }
 }
 
 /// The HPKE receiver's context. This is what you use to `open` ciphertexts and `export` secrets.
+///
+/// Once a context has opened a message under its last sequence number, it refuses every further
+/// open with `HpkeError::MessageLimitReached`. Exporting still works.
 pub struct AeadCtxR<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(AeadCtx<A, Kdf, Kem>);
 
 // AeadCtx -> AeadCtxR via wrapping
@@ -258,6 +800,17 @@ impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> Clone for AeadCtxR<A, Kdf, Kem> {
 }
 
 impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtxR<A, Kdf, Kem> {
+    /// The `suite_id` of this context's ciphersuite, i.e., `"HPKE" || I2OSP(kem_id, 2) ||
+    /// I2OSP(kdf_id, 2) || I2OSP(aead_id, 2)`. This is computed at compile time, and is useful
+    /// for binding a ciphersuite into an envelope format.
+    pub const SUITE_ID: [u8; 10] = AeadCtx::<A, Kdf, Kem>::SUITE_ID;
+
+    /// Returns the nonce that the next message will use
+    #[allow(dead_code)]
+    pub(crate) fn current_nonce(&self) -> AeadNonce<A> {
+        self.0.current_nonce()
+    }
+
     // RFC 9180 §5.2
     // def ContextR.Open(aad, ct):
     //   pt = Open(self.key, self.ComputeNonce(self.seq), aad, ct)
@@ -273,23 +826,51 @@ impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtxR<A, Kdf, Kem> {
     /// ============
     /// Returns `Ok(())` on success. If this context has been used for so many encryptions that the
     /// sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If this happens,
-    /// `ciphertext` will be unmodified. If the tag fails to validate, returns
-    /// `Err(HpkeError::OpenError)`. If this happens, `ciphertext` is in an undefined state.
+    /// `ciphertext` will be unmodified. If `aad` is longer than `A::MAX_AAD_LEN`, returns
+    /// `Err(HpkeError::InputTooLong)`. If the tag fails to validate, or `ciphertext` is longer than
+    /// `A::MAX_PLAINTEXT_LEN`, returns `Err(HpkeError::OpenError)`. If this happens, `ciphertext`
+    /// is in an undefined state.
     pub fn open_in_place_detached(
         &mut self,
         ciphertext: &mut [u8],
         aad: &[u8],
         tag: &AeadTag<A>,
+    ) -> Result<(), HpkeError> {
+        let seq = self.0.seq.0;
+        let res = self.open_in_place_detached_untraced(ciphertext, aad, tag);
+        crate::trace::message::<A, Kdf, Kem>(
+            "open",
+            seq,
+            ciphertext.len(),
+            aad.len(),
+            res.as_ref().copied(),
+        );
+        res
+    }
+
+    /// The body of `open_in_place_detached`
+    fn open_in_place_detached_untraced(
+        &mut self,
+        ciphertext: &mut [u8],
+        aad: &[u8],
+        tag: &AeadTag<A>,
     ) -> Result<(), HpkeError> {
         if self.0.overflowed {
             // If the sequence counter overflowed, we've been used for too long. Shut down.
             Err(HpkeError::MessageLimitReached)
         } else {
+            // A ciphertext longer than any plaintext can't be valid, so don't bother the AEAD
+            AeadCtx::<A, Kdf, Kem>::check_msg_lens(
+                ciphertext.len(),
+                aad.len(),
+                HpkeError::OpenError,
+            )?;
+
             // Compute the nonce and do the encryption in place
             let nonce = mix_nonce::<A>(&self.0.base_nonce, &self.0.seq);
             let decrypt_res = self
                 .0
-                .encryptor
+                .aead()
                 .decrypt_in_place_detached(&nonce.0, aad, ciphertext, &tag.0);
 
             if decrypt_res.is_err() {
@@ -308,154 +889,1313 @@ impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtxR<A, Kdf, Kem> {
         }
     }
 
-    /// Opens the given ciphertext and returns a plaintext
+    /// Does what `open_in_place_detached` does, with the decryption done by `accel`, e.g., a
+    /// crypto peripheral driven by DMA, and awaited. If `accel` returns `Unsupported`, this
+    /// decrypts in software.
+    ///
+    /// Return Value
+    /// ============
+    /// The same as `open_in_place_detached`.
+    #[cfg(feature = "offload")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "offload")))]
+    pub async fn open_in_place_detached_offloaded<H: AsyncAesGcmAccelerator>(
+        &mut self,
+        accel: &mut H,
+        ciphertext: &mut [u8],
+        aad: &[u8],
+        tag: &AeadTag<A>,
+    ) -> Result<(), HpkeError>
+    where
+        A: AesGcm,
+    {
+        let seq = self.0.seq.0;
+        let res = self
+            .open_in_place_detached_offloaded_untraced(accel, ciphertext, aad, tag)
+            .await;
+        crate::trace::message::<A, Kdf, Kem>(
+            "open",
+            seq,
+            ciphertext.len(),
+            aad.len(),
+            res.as_ref().copied(),
+        );
+        res
+    }
+
+    /// The body of `open_in_place_detached_offloaded`
+    #[cfg(feature = "offload")]
+    async fn open_in_place_detached_offloaded_untraced<H: AsyncAesGcmAccelerator>(
+        &mut self,
+        accel: &mut H,
+        ciphertext: &mut [u8],
+        aad: &[u8],
+        tag: &AeadTag<A>,
+    ) -> Result<(), HpkeError>
+    where
+        A: AesGcm,
+    {
+        if self.0.overflowed {
+            return Err(HpkeError::MessageLimitReached);
+        }
+        AeadCtx::<A, Kdf, Kem>::check_msg_lens(ciphertext.len(), aad.len(), HpkeError::OpenError)?;
+
+        let nonce = mix_nonce::<A>(&self.0.base_nonce, &self.0.seq);
+        let nonce_arr = nonce
+            .0
+            .as_slice()
+            .try_into()
+            .expect("AES-GCM nonces are 12 bytes");
+        let tag_arr = tag
+            .0
+            .as_slice()
+            .try_into()
+            .expect("AES-GCM tags are 16 bytes");
+        let offloaded = accel
+            .decrypt_in_place_detached(&self.0.key.0, nonce_arr, aad, ciphertext, tag_arr)
+            .await;
+        let valid = match offloaded {
+            Ok(valid) => valid,
+            Err(Unsupported) => self
+                .0
+                .aead()
+                .decrypt_in_place_detached(&nonce.0, aad, ciphertext, &tag.0)
+                .is_ok(),
+        };
+        if !valid {
+            // Opening failed due to a bad tag
+            return Err(HpkeError::OpenError);
+        }
+
+        // Opening was a success. Try to increment the sequence counter. If it fails, this was
+        // our last decryption.
+        match increment_seq(&self.0.seq) {
+            Some(new_seq) => self.0.seq = new_seq,
+            None => self.0.overflowed = true,
+        }
+
+        Ok(())
+    }
+
+    /// Opens a ciphertext with the tag appended, as output by `seal()`, in place. On success, the
+    /// returned slice is the plaintext, which is the start of `ciphertext_and_tag`. This neither
+    /// allocates nor copies.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If this context has been used for so many encryptions
+    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If
+    /// `ciphertext_and_tag` is too short to contain a tag, or the tag fails to validate, returns
+    /// `Err(HpkeError::OpenError)`. If this happens, `ciphertext_and_tag` is in an undefined state.
+    pub fn open_in_place_combined<'a>(
+        &mut self,
+        ciphertext_and_tag: &'a mut [u8],
+        aad: &[u8],
+    ) -> Result<&'a mut [u8], HpkeError> {
+        // Split off the tag. If there's no room for one, the ciphertext is certainly not valid.
+        let msg_len = ciphertext_and_tag
+            .len()
+            .checked_sub(AeadTag::<A>::size())
+            .ok_or(HpkeError::OpenError)?;
+        let (ciphertext, tag_slice) = ciphertext_and_tag.split_at_mut(msg_len);
+        let tag = AeadTag::<A>::from_bytes(tag_slice)?;
+
+        self.open_in_place_detached(ciphertext, aad, &tag)?;
+        Ok(ciphertext)
+    }
+
+    /// Opens the given ciphertext and writes the plaintext into the start of `out`. This is like
+    /// `open()`, but doesn't allocate.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext_len)` on success. If `out` is shorter than the plaintext, i.e.,
+    /// `ciphertext.len()` minus the tag size, returns
+    /// `Err(HpkeError::IncorrectInputLength(plaintext_len, out.len()))`. If this context has been
+    /// used for so many encryptions that the sequence number overflowed, returns
+    /// `Err(HpkeError::MessageLimitReached)`. If the tag fails to validate, returns
+    /// `Err(HpkeError::OpenError)`. If this happens, the contents of `out` are undefined.
+    pub fn open_into(
+        &mut self,
+        out: &mut [u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<usize, HpkeError> {
+        // Make sure the auth'd ciphertext is long enough to contain a tag. If it isn't, it's
+        // certainly not valid.
+        let tag_len = AeadTag::<A>::size();
+        let msg_len = ciphertext
+            .len()
+            .checked_sub(tag_len)
+            .ok_or(HpkeError::OpenError)?;
+        if out.len() < msg_len {
+            return Err(HpkeError::IncorrectInputLength(msg_len, out.len()));
+        }
+
+        // Now deconstruct the auth'd ciphertext
+        let (ciphertext, tag_slice) = ciphertext.split_at(msg_len);
+        let buf = &mut out[..msg_len];
+        buf.copy_from_slice(ciphertext);
+        let tag = {
+            let mut t = <AeadTag<A> as Default>::default();
+            t.0.copy_from_slice(tag_slice);
+            t
+        };
+
+        // Decrypt in the output buffer
+        self.open_in_place_detached(buf, aad, &tag)?;
+        Ok(msg_len)
+    }
+
+    /// Opens a run of consecutive ciphertexts, each with its own AAD, and writes their plaintexts
+    /// back to back into `out`. This is equivalent to calling `open()` on each `(ciphertext, aad)`
+    /// pair, except that it makes one AEAD instance for the whole run, doesn't allocate, and is all
+    /// or nothing: if any ciphertext fails to open, this context is left exactly as it was before
+    /// the call, and the part of `out` that was written is zeroed. The plaintext of a ciphertext
+    /// is `A::TAG_SIZE` bytes shorter than it, so the caller can split `out` up afterwards.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(len)`, the total length of the plaintexts, on success. If this context doesn't
+    /// have `msgs.len()` sequence numbers left, returns `Err(HpkeError::MessageLimitReached)`. If
+    /// `out` is shorter than the plaintexts, returns
+    /// `Err(HpkeError::IncorrectInputLength(len, out.len()))`. If any ciphertext is too short or
+    /// too long, or its tag fails to validate, returns `Err(HpkeError::OpenError)`. Nothing is
+    /// opened unless all the lengths are valid.
+    pub fn open_many(
+        &mut self,
+        msgs: &[(&[u8], &[u8])],
+        out: &mut [u8],
+    ) -> Result<usize, HpkeError> {
+        // The last message uses sequence number seq + n - 1. Make sure that exists.
+        if let Some(last_offset) = (msgs.len() as u64).checked_sub(1) {
+            if self.0.overflowed || self.0.seq.0.checked_add(last_offset).is_none() {
+                return Err(HpkeError::MessageLimitReached);
+            }
+        }
+
+        // Check every length before opening anything
+        let tag_len = AeadTag::<A>::size();
+        let mut total_len = 0usize;
+        for (ciphertext, aad) in msgs {
+            AeadCtx::<A, Kdf, Kem>::check_msg_lens(
+                ciphertext.len(),
+                aad.len(),
+                HpkeError::OpenError,
+            )?;
+            let msg_len = ciphertext
+                .len()
+                .checked_sub(tag_len)
+                .ok_or(HpkeError::OpenError)?;
+            total_len = total_len.checked_add(msg_len).ok_or(HpkeError::OpenError)?;
+        }
+        if out.len() < total_len {
+            return Err(HpkeError::IncorrectInputLength(total_len, out.len()));
+        }
+
+        // Open each message into its place in `out`, with one AEAD instance. The sequence number
+        // is only written back once everything has opened.
+        let aead = self.0.aead();
+        let mut seq = self.0.seq.clone();
+        let mut overflowed = self.0.overflowed;
+        let mut written = 0;
+        for (ciphertext, aad) in msgs {
+            let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - tag_len);
+            let buf = &mut out[written..written + ciphertext.len()];
+            buf.copy_from_slice(ciphertext);
+
+            let nonce = mix_nonce::<A>(&self.0.base_nonce, &seq);
+            let res = aead
+                .decrypt_in_place_detached(&nonce.0, aad, buf, GenericArray::from_slice(tag))
+                .map_err(|_| HpkeError::OpenError);
+            crate::trace::message::<A, Kdf, Kem>(
+                "open",
+                seq.0,
+                buf.len(),
+                aad.len(),
+                res.as_ref().copied(),
+            );
+            written += ciphertext.len();
+            if let Err(e) = res {
+                out[..written].zeroize();
+                return Err(e);
+            }
+
+            // This only fails after the last message, which was checked to be the last one left
+            match increment_seq(&seq) {
+                Some(new_seq) => seq = new_seq,
+                None => overflowed = true,
+            }
+        }
+
+        self.0.seq = seq;
+        self.0.overflowed = overflowed;
+        Ok(total_len)
+    }
+
+    /// Opens the given ciphertext and returns the plaintext in a buffer of capacity `N`. This is
+    /// like `open()`, but doesn't allocate.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If `N` is less than the plaintext length, returns
+    /// `Err(HpkeError::IncorrectInputLength(plaintext_len, N))`. If this context has been used for
+    /// so many encryptions that the sequence number overflowed, returns
+    /// `Err(HpkeError::MessageLimitReached)`. If the tag fails to validate, returns
+    /// `Err(HpkeError::OpenError)`.
+    pub fn open_to_array<const N: usize>(
+        &mut self,
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<ArrayBuf<N>, HpkeError> {
+        let mut bytes = [0u8; N];
+        let len = self.open_into(&mut bytes, ciphertext, aad)?;
+        Ok(ArrayBuf { bytes, len })
+    }
+
+    /// Opens a ciphertext of exactly `M` bytes into a plaintext of exactly `N` bytes. This is the
+    /// inverse of `AeadCtxS::seal_fixed()`. `M` must be `N + A::TAG_SIZE`, and a call with any
+    /// other sizes fails to compile.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If this context has been used for so many encryptions
+    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If the
+    /// tag fails to validate, returns `Err(HpkeError::OpenError)`.
+    pub fn open_fixed<const M: usize, const N: usize>(
+        &mut self,
+        ciphertext: &[u8; M],
+        aad: &[u8],
+    ) -> Result<[u8; N], HpkeError> {
+        #[allow(clippy::let_unit_value)]
+        let () = FixedLens::<A, N, M>::OK;
+        let mut plaintext = [0u8; N];
+        self.open_into(&mut plaintext, ciphertext, aad)?;
+        Ok(plaintext)
+    }
+
+    /// Opens the given ciphertext and returns a plaintext. This is like `open()`, except the AAD
+    /// is given as a sequence of pieces, whose concatenation is the AAD the sender used.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If this context has been used for so many encryptions
+    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If the
+    /// tag fails to validate, returns `Err(HpkeError::OpenError)`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn open_vectored(
+        &mut self,
+        ciphertext: &[u8],
+        aad: &[&[u8]],
+    ) -> Result<crate::Vec<u8>, HpkeError> {
+        with_joined_aad(aad, |aad| self.open(ciphertext, aad))
+    }
+
+    /// Opens the given ciphertext and returns a plaintext
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(())` on success. If this context has been used for so many encryptions that the
+    /// sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If the tag fails
+    /// to validate, returns `Err(HpkeError::OpenError)`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn open(&mut self, ciphertext: &[u8], aad: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        // Make a buffer that can hold the plaintext. If the ciphertext is too short to hold a
+        // tag, open_into will catch it.
+        let msg_len = ciphertext.len().saturating_sub(AeadTag::<A>::size());
+        let mut buf = vec![0u8; msg_len];
+
+        // Decrypt and return the decrypted buffer
+        self.open_into(&mut buf, ciphertext, aad)?;
+        Ok(buf)
+    }
+
+    /// Fills a given buffer with secret bytes derived from this encryption context. This value
+    /// does not depend on sequence number, so it is constant for the lifetime of this context.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(())` on success. If the buffer length is more than about 255x the digest size
+    /// (in bytes) of the underlying hash function, returns an `Err(HpkeError::KdfOutputTooLong)`.
+    /// The exact number is given in the "Input Length Restrictions" section of the spec. Just
+    /// don't use to fill massive buffers and you'll be fine.
+    /// If the exporter context is longer than `Kdf::MAX_EXPORTER_CTX_LEN`, returns
+    /// `Err(HpkeError::InputTooLong)`.
+    pub fn export(&self, info: &[u8], out_buf: &mut [u8]) -> Result<(), HpkeError> {
+        // Pass to AeadCtx
+        self.0.export(info, out_buf)
+    }
+
+    /// Returns the context ID, which is the same as the sender's. See `AeadCtxS::context_id`.
+    pub fn context_id(&self) -> DigestArray<Kdf> {
+        self.0.context_id()
+    }
+
+    /// Does what `AeadCtxS::export_labeled` does
+    pub fn export_labeled(
+        &self,
+        domain: &[u8],
+        label: &[u8],
+        context: &[u8],
+        out_buf: &mut [u8],
+    ) -> Result<(), HpkeError> {
+        self.0.export_labeled(domain, label, context, out_buf)
+    }
+
+    /// Does what `AeadCtxS::export_psk` does
+    pub fn export_psk(&self, target: &[u8], context: &[u8]) -> Result<ExportedPsk, HpkeError> {
+        self.0.export_psk(target, context)
+    }
+
+    /// Does what `AeadCtxS::export_media_keys` does, as the responder. The returned `send` keys
+    /// are the sender's `recv` keys, and vice versa.
+    pub fn export_media_keys(
+        &self,
+        profile: SrtpProfile,
+        context: &[u8],
+    ) -> Result<MediaKeys, HpkeError> {
+        self.0.export_media_keys(profile, context, false)
+    }
+
+    /// Serializes this context into at most `MAX_SUSPENDED_CTX_SIZE` (127) bytes, so it can be
+    /// stored and later restored with `AeadCtxR::resume`. See `AeadCtxS::suspend` for the format
+    /// and caveats.
+    pub fn suspend(&self) -> ArrayBuf<MAX_SUSPENDED_CTX_SIZE> {
+        self.0.suspend(true)
+    }
+
+    /// Consumes this context and returns its raw secrets. See `AeadCtxS::danger_into_raw_parts`.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(parts)` on success. If the sequence number has overflowed, returns
+    /// `Err(HpkeError::MessageLimitReached)`.
+    #[cfg(feature = "hazmat")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hazmat")))]
+    pub fn danger_into_raw_parts(self) -> Result<RawCtxParts<A, Kdf>, HpkeError> {
+        self.0.into_raw_parts()
+    }
+
+    /// Restores a receiver context that was serialized with `AeadCtxR::suspend`
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ctx)` on success. If `suspended` is not the size of a suspended context of this
+    /// ciphersuite, returns `Err(HpkeError::IncorrectInputLength)`. If the checksum is wrong, or
+    /// `suspended` is of a different version, ciphersuite, or role, returns
+    /// `Err(HpkeError::ValidationError)`.
+    pub fn resume(suspended: &[u8]) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError> {
+        AeadCtx::resume(suspended, true).map(AeadCtxR)
+    }
+
+    /// Derives the receiving end of the fork that `AeadCtxS::fork_for_broadcast` made with the
+    /// same `fork_id`. Like the sender's, this uses up a sequence number, so it must be called at
+    /// the same point in the stream of messages as the sender's was. See that method for details.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(fork)` on success. If this context has been used for so many decryptions that
+    /// the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`.
+    pub fn fork_for_broadcast(
+        &mut self,
+        fork_id: &[u8],
+    ) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError> {
+        self.0.fork_next(fork_id).map(AeadCtxR)
+    }
+
+    /// Derives the fork that `fork_for_broadcast` would, without using up a sequence number
+    #[cfg(feature = "session")]
+    pub(crate) fn peek_fork(&self, fork_id: &[u8]) -> AeadCtxR<A, Kdf, Kem> {
+        AeadCtxR(self.0.fork(self.0.seq.0, fork_id))
+    }
+
+    /// Splits this context into the sending and receiving contexts of the responder's end of a
+    /// duplex session
+    #[cfg(feature = "session")]
+    pub(crate) fn into_duplex(self) -> (AeadCtxS<A, Kdf, Kem>, AeadCtxR<A, Kdf, Kem>) {
+        (
+            AeadCtxS(self.0.duplex_ctx(false), None),
+            AeadCtxR(self.0.duplex_ctx(true)),
+        )
+    }
+
+    /// Seals a response to the message this context was set up to receive, e.g., the reply to an
+    /// RPC request. The response is `response_nonce || ciphertext`, where `response_nonce` is
+    /// `RESPONSE_NONCE_SIZE` random bytes, and the ciphertext is sealed under a key and nonce
+    /// expanded from this context's exporter secret and `response_nonce`, with fixed labels. This
+    /// is the pattern of Oblivious HTTP. The sender opens it with `AeadCtxS::open_response`.
+    ///
+    /// Since every response gets a fresh nonce, and so a fresh key, a context can respond any
+    /// number of times. Responding doesn't touch this context's sequence number.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(response)` on success. If `csprng` failed, returns `Err(HpkeError::RngError)`.
+    /// Otherwise, the errors are those of `AeadCtxS::seal`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn respond<R: rand_core::CryptoRng + rand_core::RngCore>(
+        &self,
+        plaintext: &[u8],
+        aad: &[u8],
+        csprng: &mut R,
+    ) -> Result<crate::Vec<u8>, HpkeError> {
+        let mut response_nonce = [0u8; RESPONSE_NONCE_SIZE];
+        csprng
+            .try_fill_bytes(&mut response_nonce)
+            .map_err(|_| HpkeError::RngError)?;
+
+        let ciphertext =
+            AeadCtxS(self.0.response_ctx(&response_nonce), None).seal(plaintext, aad)?;
+        let mut response = response_nonce.to_vec();
+        response.extend_from_slice(&ciphertext);
+        Ok(response)
+    }
+
+    /// Does what `respond` does, in a caller-provided buffer. `buf` starts with the
+    /// `plaintext_len`-byte plaintext, and must have room for the response nonce and the tag as
+    /// well. On success, `buf` starts with the response, which `AeadCtxS::open_response` and
+    /// `AeadCtxS::open_response_in_place` open. Nothing is allocated.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(response_len)` on success. If `buf` is shorter than `RESPONSE_NONCE_SIZE`
+    /// plus `plaintext_len` plus the tag size, returns
+    /// `Err(HpkeError::IncorrectInputLength(needed, buf.len()))`, and leaves `buf` as it was.
+    /// Otherwise, the errors are those of `respond`.
+    pub fn respond_in_place<R: rand_core::CryptoRng + rand_core::RngCore>(
+        &self,
+        buf: &mut [u8],
+        plaintext_len: usize,
+        aad: &[u8],
+        csprng: &mut R,
+    ) -> Result<usize, HpkeError> {
+        let response_len = RESPONSE_NONCE_SIZE
+            .saturating_add(plaintext_len)
+            .saturating_add(AeadTag::<A>::size());
+        if buf.len() < response_len {
+            return Err(HpkeError::IncorrectInputLength(response_len, buf.len()));
+        }
+
+        let mut response_nonce = [0u8; RESPONSE_NONCE_SIZE];
+        csprng
+            .try_fill_bytes(&mut response_nonce)
+            .map_err(|_| HpkeError::RngError)?;
+
+        // Make room for the nonce in front of the plaintext, then seal the plaintext where it is
+        buf.copy_within(..plaintext_len, RESPONSE_NONCE_SIZE);
+        let (nonce_buf, rest) = buf.split_at_mut(RESPONSE_NONCE_SIZE);
+        nonce_buf.copy_from_slice(&response_nonce);
+        let (plaintext, rest) = rest.split_at_mut(plaintext_len);
+        let tag = AeadCtxS(self.0.response_ctx(&response_nonce), None)
+            .seal_in_place_detached(plaintext, aad)?;
+        tag.write_exact(&mut rest[..AeadTag::<A>::size()]);
+
+        Ok(response_len)
+    }
+}
+
+/// The HPKE senders's context. This is what you use to `seal` plaintexts and `export` secrets.
+///
+/// Once a context has sealed a message under its last sequence number, it refuses every further
+/// seal with `HpkeError::MessageLimitReached`, so no nonce is ever used twice. Exporting still
+/// works.
+///
+/// This is not `Clone`, since a copy would seal under the same nonces as the original. To send
+/// several independent streams from one session, use `AeadCtxS::fork_for_broadcast`.
+pub struct AeadCtxS<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    AeadCtx<A, Kdf, Kem>,
+    /// The encapsulated key this context was set up with, if it came from `setup_sender`
+    Option<Kem::EncappedKey>,
+);
+
+// AeadCtx -> AeadCtxS via wrapping
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> From<AeadCtx<A, Kdf, Kem>> for AeadCtxS<A, Kdf, Kem> {
+    fn from(ctx: AeadCtx<A, Kdf, Kem>) -> AeadCtxS<A, Kdf, Kem> {
+        AeadCtxS(ctx, None)
+    }
+}
+
+// Necessary for test_setup_soundness
+#[cfg(test)]
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> Clone for AeadCtxS<A, Kdf, Kem> {
+    fn clone(&self) -> AeadCtxS<A, Kdf, Kem> {
+        self.0.clone().into()
+    }
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtxS<A, Kdf, Kem> {
+    /// The `suite_id` of this context's ciphersuite, i.e., `"HPKE" || I2OSP(kem_id, 2) ||
+    /// I2OSP(kdf_id, 2) || I2OSP(aead_id, 2)`. This is computed at compile time, and is useful
+    /// for binding a ciphersuite into an envelope format.
+    pub const SUITE_ID: [u8; 10] = AeadCtx::<A, Kdf, Kem>::SUITE_ID;
+
+    /// Returns the nonce that the next message will use
+    #[allow(dead_code)]
+    pub(crate) fn current_nonce(&self) -> AeadNonce<A> {
+        self.0.current_nonce()
+    }
+
+    /// Returns the encapsulated key that `setup_sender` returned along with this context, so
+    /// the code that builds the wire message can get it from the context instead of having it
+    /// passed down separately. The recipient needs it to open anything this context seals.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Some(encapped_key)` if this context was made by `setup_sender`. Returns `None` for
+    /// contexts derived some other way, i.e., by `fork_for_broadcast`, `resume`, or a `Session`,
+    /// since the recipient doesn't use an encapsulated key to get those.
+    pub fn encapped_key(&self) -> Option<&Kem::EncappedKey> {
+        self.1.as_ref()
+    }
+
+    /// Records the encapsulated key that this context was set up with
+    pub(crate) fn with_encapped_key(mut self, encapped_key: Kem::EncappedKey) -> Self {
+        self.1 = Some(encapped_key);
+        self
+    }
+
+    // RFC 9180 §5.2
+    // def ContextS.Seal(aad, pt):
+    //   ct = Seal(self.key, self.ComputeNonce(self.seq), aad, pt)
+    //   self.IncrementSeq()
+    //   return ct
+
+    /// Does a "detached seal in place", meaning it overwrites `plaintext` with the resulting
+    /// ciphertext, and returns the resulting authentication tag
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(tag)` on success.  If this context has been used for so many encryptions that
+    /// the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If
+    /// `plaintext` is longer than `A::MAX_PLAINTEXT_LEN`, returns
+    /// `Err(HpkeError::PlaintextTooLong)`, and if `aad` is longer than `A::MAX_AAD_LEN`, returns
+    /// `Err(HpkeError::InputTooLong)`. In these cases, `plaintext` will be unmodified. If an error
+    /// happened during encryption, returns `Err(HpkeError::SealError)`. If this happens, the
+    /// contents of `plaintext` is undefined.
+    pub fn seal_in_place_detached(
+        &mut self,
+        plaintext: &mut [u8],
+        aad: &[u8],
+    ) -> Result<AeadTag<A>, HpkeError> {
+        let seq = self.0.seq.0;
+        let res = self.seal_in_place_detached_untraced(plaintext, aad);
+        crate::trace::message::<A, Kdf, Kem>(
+            "seal",
+            seq,
+            plaintext.len(),
+            aad.len(),
+            res.as_ref().map(|_| ()),
+        );
+        res
+    }
+
+    /// The body of `seal_in_place_detached`
+    fn seal_in_place_detached_untraced(
+        &mut self,
+        plaintext: &mut [u8],
+        aad: &[u8],
+    ) -> Result<AeadTag<A>, HpkeError> {
+        if self.0.overflowed {
+            // If the sequence counter overflowed, we've been used for far too long. Shut down.
+            Err(HpkeError::MessageLimitReached)
+        } else {
+            AeadCtx::<A, Kdf, Kem>::check_msg_lens(
+                plaintext.len(),
+                aad.len(),
+                HpkeError::PlaintextTooLong,
+            )?;
+
+            // Compute the nonce and do the encryption in place
+            let nonce = mix_nonce::<A>(&self.0.base_nonce, &self.0.seq);
+            let tag = self
+                .0
+                .aead()
+                .encrypt_in_place_detached(&nonce.0, aad, plaintext)
+                .map_err(|_| HpkeError::SealError)?;
+
+            // Try to increment the sequence counter. If it fails, this was our last encryption.
+            match increment_seq(&self.0.seq) {
+                Some(new_seq) => self.0.seq = new_seq,
+                None => self.0.overflowed = true,
+            }
+
+            // Return the tag
+            Ok(AeadTag(tag))
+        }
+    }
+
+    /// Does what `seal_in_place_detached` does, with the encryption done by `accel`, e.g., a
+    /// crypto peripheral driven by DMA, and awaited. If `accel` returns `Unsupported`, this
+    /// encrypts in software.
+    ///
+    /// Return Value
+    /// ============
+    /// The same as `seal_in_place_detached`.
+    #[cfg(feature = "offload")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "offload")))]
+    pub async fn seal_in_place_detached_offloaded<H: AsyncAesGcmAccelerator>(
+        &mut self,
+        accel: &mut H,
+        plaintext: &mut [u8],
+        aad: &[u8],
+    ) -> Result<AeadTag<A>, HpkeError>
+    where
+        A: AesGcm,
+    {
+        let seq = self.0.seq.0;
+        let res = self
+            .seal_in_place_detached_offloaded_untraced(accel, plaintext, aad)
+            .await;
+        crate::trace::message::<A, Kdf, Kem>(
+            "seal",
+            seq,
+            plaintext.len(),
+            aad.len(),
+            res.as_ref().map(|_| ()),
+        );
+        res
+    }
+
+    /// The body of `seal_in_place_detached_offloaded`
+    #[cfg(feature = "offload")]
+    async fn seal_in_place_detached_offloaded_untraced<H: AsyncAesGcmAccelerator>(
+        &mut self,
+        accel: &mut H,
+        plaintext: &mut [u8],
+        aad: &[u8],
+    ) -> Result<AeadTag<A>, HpkeError>
+    where
+        A: AesGcm,
+    {
+        if self.0.overflowed {
+            return Err(HpkeError::MessageLimitReached);
+        }
+        AeadCtx::<A, Kdf, Kem>::check_msg_lens(
+            plaintext.len(),
+            aad.len(),
+            HpkeError::PlaintextTooLong,
+        )?;
+
+        let nonce = mix_nonce::<A>(&self.0.base_nonce, &self.0.seq);
+        let nonce_arr = nonce
+            .0
+            .as_slice()
+            .try_into()
+            .expect("AES-GCM nonces are 12 bytes");
+        let offloaded = accel
+            .encrypt_in_place_detached(&self.0.key.0, nonce_arr, aad, plaintext)
+            .await;
+        let tag = match offloaded {
+            Ok(tag) => GenericArray::clone_from_slice(&tag),
+            Err(Unsupported) => self
+                .0
+                .aead()
+                .encrypt_in_place_detached(&nonce.0, aad, plaintext)
+                .map_err(|_| HpkeError::SealError)?,
+        };
+
+        // Try to increment the sequence counter. If it fails, this was our last encryption.
+        match increment_seq(&self.0.seq) {
+            Some(new_seq) => self.0.seq = new_seq,
+            None => self.0.overflowed = true,
+        }
+
+        Ok(AeadTag(tag))
+    }
+
+    /// Seals the given plaintext and writes the ciphertext, followed by the tag, into the start of
+    /// `out`. This is like `seal()`, but doesn't allocate.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ciphertext_len)` on success, where `ciphertext_len` is `plaintext.len()` plus
+    /// the tag size. If `out` is shorter than this, returns
+    /// `Err(HpkeError::IncorrectInputLength(ciphertext_len, out.len()))`. If this context has been
+    /// used for so many encryptions that the sequence number overflowed, returns
+    /// `Err(HpkeError::MessageLimitReached)`. If an error happened during encryption, returns
+    /// `Err(HpkeError::SealError)`. In either of the last two cases, the contents of `out` are
+    /// undefined.
+    pub fn seal_into(
+        &mut self,
+        out: &mut [u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<usize, HpkeError> {
+        let msg_len = plaintext.len();
+        let tag_len = AeadTag::<A>::size();
+        let ct_len = msg_len + tag_len;
+        if out.len() < ct_len {
+            return Err(HpkeError::IncorrectInputLength(ct_len, out.len()));
+        }
+
+        // Copy in the plaintext and seal it with a detached tag
+        out[..msg_len].copy_from_slice(plaintext);
+        let tag = self.seal_in_place_detached(&mut out[..msg_len], aad)?;
+        // Then put the tag right after. The start of the buffer is now the auth'd ciphertext
+        out[msg_len..ct_len].copy_from_slice(&tag.0);
+
+        Ok(ct_len)
+    }
+
+    /// Seals the given plaintext and returns the ciphertext in a buffer of capacity `N`. This is
+    /// like `seal()`, but doesn't allocate.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ciphertext)` on success. If `N` is less than the ciphertext length, i.e.,
+    /// `plaintext.len()` plus the tag size, returns
+    /// `Err(HpkeError::IncorrectInputLength(ciphertext_len, N))`. If this context has been used for
+    /// so many encryptions that the sequence number overflowed, returns
+    /// `Err(HpkeError::MessageLimitReached)`. If an error happened during encryption, returns
+    /// `Err(HpkeError::SealError)`.
+    pub fn seal_to_array<const N: usize>(
+        &mut self,
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<ArrayBuf<N>, HpkeError> {
+        let mut bytes = [0u8; N];
+        let len = self.seal_into(&mut bytes, plaintext, aad)?;
+        Ok(ArrayBuf { bytes, len })
+    }
+
+    /// Seals exactly `N` bytes of plaintext into exactly `M` bytes of ciphertext, for protocols
+    /// whose packets are all the same size. `M` must be `N + A::TAG_SIZE`, and a call with any
+    /// other sizes fails to compile, so buffer sizing can't go wrong at runtime. Since `M` can't be
+    /// computed in the signature on stable Rust, spell it out with the constant, e.g.,
+    /// `ctx.seal_fixed::<32, { 32 + ChaCha20Poly1305::TAG_SIZE }>(&pt, aad)`, or let it be
+    /// inferred from the type the result is assigned to.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ciphertext)` on success. If this context has been used for so many encryptions
+    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If an
+    /// error happened during encryption, returns `Err(HpkeError::SealError)`.
+    pub fn seal_fixed<const N: usize, const M: usize>(
+        &mut self,
+        plaintext: &[u8; N],
+        aad: &[u8],
+    ) -> Result<[u8; M], HpkeError> {
+        #[allow(clippy::let_unit_value)]
+        let () = FixedLens::<A, N, M>::OK;
+        let mut ciphertext = [0u8; M];
+        self.seal_into(&mut ciphertext, plaintext, aad)?;
+        Ok(ciphertext)
+    }
+
+    /// Seals each of the given plaintexts under the same AAD, in order, and returns their
+    /// ciphertexts. This is equivalent to calling `seal()` on each plaintext, except that it makes
+    /// one AEAD instance for all of them, and checks up front that every length is valid and that
+    /// there are enough sequence numbers left, so it either seals everything or nothing.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ciphertexts)` on success. If this context doesn't have `plaintexts.len()`
+    /// sequence numbers left, returns `Err(HpkeError::MessageLimitReached)`. If any plaintext is
+    /// longer than `A::MAX_PLAINTEXT_LEN`, returns `Err(HpkeError::PlaintextTooLong)`, and if the
+    /// AAD is longer than `A::MAX_AAD_LEN`, returns `Err(HpkeError::InputTooLong)`. In either case,
+    /// nothing is sealed. If an error happened during encryption, returns
+    /// `Err(HpkeError::SealError)`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn seal_all(
+        &mut self,
+        plaintexts: &[&[u8]],
+        aad: &[u8],
+    ) -> Result<crate::Vec<crate::Vec<u8>>, HpkeError> {
+        // The last message uses sequence number seq + n - 1. Make sure that exists.
+        if let Some(last_offset) = (plaintexts.len() as u64).checked_sub(1) {
+            if self.0.overflowed || self.0.seq.0.checked_add(last_offset).is_none() {
+                return Err(HpkeError::MessageLimitReached);
+            }
+        }
+
+        // Check every length before sealing anything
+        for plaintext in plaintexts {
+            AeadCtx::<A, Kdf, Kem>::check_msg_lens(
+                plaintext.len(),
+                aad.len(),
+                HpkeError::PlaintextTooLong,
+            )?;
+        }
+
+        // Seal each message with one AEAD instance. The sequence number is only written back once
+        // everything has been sealed.
+        let aead = self.0.aead();
+        let mut seq = self.0.seq.clone();
+        let mut overflowed = self.0.overflowed;
+        let mut ciphertexts = crate::Vec::with_capacity(plaintexts.len());
+        for plaintext in plaintexts {
+            let mut buf = crate::Vec::with_capacity(plaintext.len() + A::TAG_SIZE);
+            buf.extend_from_slice(plaintext);
+
+            let nonce = mix_nonce::<A>(&self.0.base_nonce, &seq);
+            let res = aead
+                .encrypt_in_place_detached(&nonce.0, aad, &mut buf)
+                .map_err(|_| HpkeError::SealError);
+            crate::trace::message::<A, Kdf, Kem>(
+                "seal",
+                seq.0,
+                plaintext.len(),
+                aad.len(),
+                res.as_ref().map(|_| ()),
+            );
+            buf.extend_from_slice(&res?);
+            ciphertexts.push(buf);
+
+            // This only fails after the last message, which was checked to be the last one left
+            match increment_seq(&seq) {
+                Some(new_seq) => seq = new_seq,
+                None => overflowed = true,
+            }
+        }
+
+        self.0.seq = seq;
+        self.0.overflowed = overflowed;
+        Ok(ciphertexts)
+    }
+
+    /// Seals the given plaintext and returns the ciphertext. This is like `seal()`, except the
+    /// plaintext and AAD are each given as a sequence of pieces, which are treated as if they were
+    /// concatenated. The plaintext pieces are copied straight into the ciphertext buffer.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ciphertext)` on success.  If this context has been used for so many encryptions
+    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If an
+    /// error happened during encryption, returns `Err(HpkeError::SealError)`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn seal_vectored(
+        &mut self,
+        plaintext: &[&[u8]],
+        aad: &[&[u8]],
+    ) -> Result<crate::Vec<u8>, HpkeError> {
+        // Gather the plaintext into a buffer that can also hold the tag
+        let msg_len = plaintext.iter().map(|piece| piece.len()).sum::<usize>();
+        let mut buf = crate::Vec::with_capacity(msg_len + AeadTag::<A>::size());
+        for piece in plaintext {
+            buf.extend_from_slice(piece);
+        }
+
+        // Seal in place and append the tag
+        let tag = with_joined_aad(aad, |aad| self.seal_in_place_detached(&mut buf, aad))?;
+        buf.extend_from_slice(&tag.0);
+
+        Ok(buf)
+    }
+
+    /// Seals the given plaintext and returns the ciphertext
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ciphertext)` on success.  If this context has been used for so many encryptions
+    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If an
+    /// error happened during encryption, returns `Err(HpkeError::SealError)`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn seal(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        // Make a buffer that can hold a ciphertext + tag, and seal into it
+        let mut buf = vec![0u8; plaintext.len() + AeadTag::<A>::size()];
+        self.seal_into(&mut buf, plaintext, aad)?;
+
+        Ok(buf)
+    }
+
+    /// Fills a given buffer with secret bytes derived from this encryption context. This value
+    /// does not depend on sequence number, so it is constant for the lifetime of this context.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(())` on success. If the buffer length is more than 255x the digest size (in
+    /// bytes) of the underlying hash function, returns an `Err(HpkeError::KdfOutputTooLong)`. Just
+    /// don't use to fill massive buffers and you'll be fine.
+    /// If the exporter context is longer than `Kdf::MAX_EXPORTER_CTX_LEN`, returns
+    /// `Err(HpkeError::InputTooLong)`.
+    pub fn export(&self, info: &[u8], out_buf: &mut [u8]) -> Result<(), HpkeError> {
+        // Pass to AeadCtx
+        self.0.export(info, out_buf)
+    }
+
+    /// Returns this context's ID: a digest-sized value that the sender and receiver compute
+    /// identically, and that differs between any two contexts. Bind it into a signature or
+    /// session ticket to tie that to this particular HPKE context.
+    ///
+    /// The ID is derived from the exporter secret under its own reserved label, so it reveals
+    /// nothing about the context's keys or its exports. It is not secret, and may be sent in the
+    /// clear. Unlike [`key_schedule_context`](crate::key_schedule_context), which only covers the
+    /// mode, PSK ID, and `info`, it also depends on the shared secret and PSK.
+    pub fn context_id(&self) -> DigestArray<Kdf> {
+        self.0.context_id()
+    }
+
+    /// Fills a given buffer with secret bytes derived from this encryption context, for the given
+    /// subsystem (`domain`) and purpose (`label`). This is `export` with the exporter context set
+    /// to `I2OSP(len(domain), 2) || domain || I2OSP(len(label), 2) || label || context`. Because
+    /// of the length prefixes, two different `(domain, label)` pairs never give the same exporter
+    /// context, so subsystems that each pick their own domain can't derive each other's keys,
+    /// whatever labels and contexts they use.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(())` on success. If `label` is one of [`RESERVED_LABELS`], returns
+    /// `Err(HpkeError::ReservedLabel)`. If `domain` or `label` is longer than 65535 bytes, returns
+    /// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of `export`.
+    pub fn export_labeled(
+        &self,
+        domain: &[u8],
+        label: &[u8],
+        context: &[u8],
+        out_buf: &mut [u8],
+    ) -> Result<(), HpkeError> {
+        self.0.export_labeled(domain, label, context, out_buf)
+    }
+
+    /// Exports a secret for the protocol named `target`, e.g., `b"noise"`, to use as its PSK.
+    /// This is `export_labeled(PSK_EXPORT_DOMAIN, target, context)`. See the [`psk`](crate::psk)
+    /// module.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(psk)` on success. The errors are those of `export_labeled`.
+    pub fn export_psk(&self, target: &[u8], context: &[u8]) -> Result<ExportedPsk, HpkeError> {
+        self.0.export_psk(target, context)
+    }
+
+    /// Exports the master keys and salts for both directions of a realtime media session, e.g.,
+    /// SRTP, sized for `profile`. This context's side is the initiator. `context` distinguishes
+    /// sessions keyed from the same HPKE context, e.g., a call ID. See the [`media`](crate::media)
+    /// module for the labels.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(keys)` on success. The errors are those of `export_labeled`.
+    pub fn export_media_keys(
+        &self,
+        profile: SrtpProfile,
+        context: &[u8],
+    ) -> Result<MediaKeys, HpkeError> {
+        self.0.export_media_keys(profile, context, true)
+    }
+
+    /// Serializes this context into at most `MAX_SUSPENDED_CTX_SIZE` (127) bytes, so it can be
+    /// stored, e.g., in flash while a device sleeps, and restored with `AeadCtxS::resume`.
+    ///
+    /// The output contains the context's secrets, so store it as carefully as a private key. Once a
+    /// context is resumed, any other copy of the same suspended state MUST NOT be used again, or
+    /// nonces will be reused.
+    ///
+    /// Format
+    /// ======
+    /// All integers are big-endian. The fields, in order, are:
+    ///
+    /// * 1 byte: the format version (currently 1) in the high 4 bits. The low bits are flags: bit
+    ///   0 is set for a receiver context, and bit 1 is set if the sequence number has overflowed.
+    /// * 6 bytes: the KEM, KDF, and AEAD IDs, 2 bytes each
+    /// * 8 bytes: the sequence number
+    /// * Nk bytes: the AEAD key. This is omitted for the export-only AEAD.
+    /// * Nn bytes: the base nonce. This is omitted for the export-only AEAD.
+    /// * Nh bytes: the exporter secret
+    /// * 4 bytes: the first 4 bytes of the SHA-256 hash of all the previous bytes. This detects
+    ///   corruption. It is not a MAC.
+    pub fn suspend(&self) -> ArrayBuf<MAX_SUSPENDED_CTX_SIZE> {
+        self.0.suspend(false)
+    }
+
+    /// Consumes this context and returns its raw AEAD key, base nonce, exporter secret, and next
+    /// sequence number. This is for handing an HPKE session to a stack that only does a raw AEAD,
+    /// e.g., hardware that can do AES-GCM but not HPKE.
+    ///
+    /// DANGER
+    /// ======
+    /// Once the secrets leave this crate, nothing enforces HPKE's rules about them, so the security
+    /// of the session is entirely up to whatever uses them. In particular:
+    ///
+    /// * Each nonce MUST be computed as RFC 9180 §5.2 says, from the base nonce and a sequence
+    ///   number that starts at `seq()` and goes up by one per message, and never wraps. Reusing a
+    ///   nonce under the same key breaks the AEAD, e.g., it reveals the XOR of two plaintexts and
+    ///   lets anyone forge AES-GCM tags.
+    /// * The secrets MUST NOT be used anywhere else, including in a second copy of this context.
+    ///   This is why the context is consumed.
+    /// * The secrets MUST be erased when the session ends. The returned value zeroizes its copy on
+    ///   drop, but not any copies made from it.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(parts)` on success. If the sequence number has overflowed, returns
+    /// `Err(HpkeError::MessageLimitReached)`, since the secrets can't be used for any more
+    /// messages.
+    #[cfg(feature = "hazmat")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hazmat")))]
+    pub fn danger_into_raw_parts(self) -> Result<RawCtxParts<A, Kdf>, HpkeError> {
+        self.0.into_raw_parts()
+    }
+
+    /// Restores a sender context that was serialized with `AeadCtxS::suspend`. See that method for
+    /// the format.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(ctx)` on success. If `suspended` is not the size of a suspended context of this
+    /// ciphersuite, returns `Err(HpkeError::IncorrectInputLength)`. If the checksum is wrong, or
+    /// `suspended` is of a different version, ciphersuite, or role, returns
+    /// `Err(HpkeError::ValidationError)`.
+    pub fn resume(suspended: &[u8]) -> Result<AeadCtxS<A, Kdf, Kem>, HpkeError> {
+        AeadCtx::resume(suspended, false).map(AeadCtxS::from)
+    }
+
+    /// Derives an independent sender context, for fanning one HPKE session out into several
+    /// message streams, e.g., one per channel or worker. The fork has its own key, base nonce, and
+    /// exporter secret, all derived from this context's exporter secret, its current sequence
+    /// number, and `fork_id`, and its sequence number starts at 0. Forking uses up that sequence
+    /// number, as sealing does, so no two forks of a context share a key, even if they have the
+    /// same `fork_id`. Sealing with a fork never uses up a nonce of this context or of another
+    /// fork. The receiver makes the matching context with `AeadCtxR::fork_for_broadcast` and the
+    /// same `fork_id`, at the same point in the stream.
+    ///
+    /// Contexts are deliberately not `Clone`, since two copies of a sender would seal under the
+    /// same nonces. This is the way to get more than one. A fork can itself be forked.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(fork)` on success. If this context has been used for so many encryptions that
+    /// the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`.
+    pub fn fork_for_broadcast(
+        &mut self,
+        fork_id: &[u8],
+    ) -> Result<AeadCtxS<A, Kdf, Kem>, HpkeError> {
+        self.0.fork_next(fork_id).map(|ctx| AeadCtxS(ctx, None))
+    }
+
+    /// Splits this context into the sending and receiving contexts of the initiator's end of a
+    /// duplex session
+    #[cfg(feature = "session")]
+    pub(crate) fn into_duplex(self) -> (AeadCtxS<A, Kdf, Kem>, AeadCtxR<A, Kdf, Kem>) {
+        (
+            AeadCtxS(self.0.duplex_ctx(true), None),
+            AeadCtxR(self.0.duplex_ctx(false)),
+        )
+    }
+
+    /// Opens a response made by `AeadCtxR::respond` on the receiving end of this context
     ///
     /// Return Value
     /// ============
-    /// Returns `Ok(())` on success. If this context has been used for so many encryptions that the
-    /// sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If the tag fails
-    /// to validate, returns `Err(HpkeError::OpenError)`.
+    /// Returns `Ok(plaintext)` on success. If `response` is too short to hold a response nonce
+    /// and a tag, or fails to open, returns `Err(HpkeError::OpenError)`.
     #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
     #[cfg(any(feature = "alloc", feature = "std"))]
-    pub fn open(&mut self, ciphertext: &[u8], aad: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
-        // Make sure the auth'd ciphertext is long enough to contain a tag. If it isn't, it's
-        // certainly not valid.
-        let tag_len = AeadTag::<A>::size();
-        let msg_len = ciphertext
-            .len()
-            .checked_sub(tag_len)
-            .ok_or(HpkeError::OpenError)?;
-
-        // Now deconstruct the auth'd ciphertext
-        let (ciphertext, tag_slice) = ciphertext.split_at(msg_len);
-        let mut buf = ciphertext.to_vec();
-        let tag = {
-            let mut t = <AeadTag<A> as Default>::default();
-            t.0.copy_from_slice(tag_slice);
-            t
-        };
-
-        // Decrypt and return the decrypted buffer
-        self.open_in_place_detached(&mut buf, aad, &tag)?;
-        Ok(buf)
+    pub fn open_response(&self, response: &[u8], aad: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        if response.len() < RESPONSE_NONCE_SIZE {
+            return Err(HpkeError::OpenError);
+        }
+        let (response_nonce, ciphertext) = response.split_at(RESPONSE_NONCE_SIZE);
+        AeadCtxR(self.0.response_ctx(response_nonce)).open(ciphertext, aad)
     }
 
-    /// Fills a given buffer with secret bytes derived from this encryption context. This value
-    /// does not depend on sequence number, so it is constant for the lifetime of this context.
+    /// Does what `open_response` does, in place. Nothing is allocated.
     ///
     /// Return Value
     /// ============
-    /// Returns `Ok(())` on success. If the buffer length is more than about 255x the digest size
-    /// (in bytes) of the underlying hash function, returns an `Err(HpkeError::KdfOutputTooLong)`.
-    /// The exact number is given in the "Input Length Restrictions" section of the spec. Just
-    /// don't use to fill massive buffers and you'll be fine.
-    pub fn export(&self, info: &[u8], out_buf: &mut [u8]) -> Result<(), HpkeError> {
-        // Pass to AeadCtx
-        self.0.export(info, out_buf)
+    /// Returns `Ok(plaintext)` on success, where `plaintext` is a subslice of `response`. The
+    /// errors are those of `open_response`. On error, the contents of `response` are undefined.
+    pub fn open_response_in_place<'a>(
+        &self,
+        response: &'a mut [u8],
+        aad: &[u8],
+    ) -> Result<&'a mut [u8], HpkeError> {
+        if response.len() < RESPONSE_NONCE_SIZE {
+            return Err(HpkeError::OpenError);
+        }
+        let (response_nonce, ciphertext_and_tag) = response.split_at_mut(RESPONSE_NONCE_SIZE);
+        AeadCtxR(self.0.response_ctx(response_nonce))
+            .open_in_place_combined(ciphertext_and_tag, aad)
     }
 }
 
-/// The HPKE senders's context. This is what you use to `seal` plaintexts and `export` secrets.
-pub struct AeadCtxS<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(AeadCtx<A, Kdf, Kem>);
-
-// AeadCtx -> AeadCtxS via wrapping
-impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> From<AeadCtx<A, Kdf, Kem>> for AeadCtxS<A, Kdf, Kem> {
-    fn from(ctx: AeadCtx<A, Kdf, Kem>) -> AeadCtxS<A, Kdf, Kem> {
-        AeadCtxS(ctx)
-    }
+/// A sender context that can seal from many threads at once. Each seal takes the next sequence
+/// number with a single atomic increment, and computes its nonce from that, so sealers never wait
+/// on each other. Make one from an `AeadCtxS` with `SyncSenderCtx::new`, and share it by reference,
+/// e.g., in an `Arc`.
+///
+/// Concurrent seals finish in no particular order, but the receiver's `AeadCtxR` opens messages in
+/// sequence order. So every seal returns the sequence number it used, and it is up to the
+/// application to get messages to the receiver in that order, e.g., by sending the number along
+/// and reordering on arrival.
+///
+/// This is only available on targets with 64-bit atomics.
+#[cfg(target_has_atomic = "64")]
+pub struct SyncSenderCtx<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    ctx: AeadCtx<A, Kdf, Kem>,
+    /// The next sequence number to hand out. `u64::MAX` means the context is used up. This means
+    /// the last sequence number, `u64::MAX`, is never used, unlike in `AeadCtxS`.
+    next_seq: core::sync::atomic::AtomicU64,
 }
 
-// Necessary for test_setup_soundness
-#[cfg(test)]
-impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> Clone for AeadCtxS<A, Kdf, Kem> {
-    fn clone(&self) -> AeadCtxS<A, Kdf, Kem> {
-        self.0.clone().into()
+#[cfg(target_has_atomic = "64")]
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> SyncSenderCtx<A, Kdf, Kem> {
+    /// Wraps the given sender context. Sealing continues from the context's current sequence
+    /// number.
+    pub fn new(ctx: AeadCtxS<A, Kdf, Kem>) -> SyncSenderCtx<A, Kdf, Kem> {
+        let next_seq = if ctx.0.overflowed {
+            u64::MAX
+        } else {
+            ctx.0.seq.0
+        };
+        SyncSenderCtx {
+            ctx: ctx.0,
+            next_seq: core::sync::atomic::AtomicU64::new(next_seq),
+        }
     }
-}
 
-impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> AeadCtxS<A, Kdf, Kem> {
-    // RFC 9180 §5.2
-    // def ContextS.Seal(aad, pt):
-    //   ct = Seal(self.key, self.ComputeNonce(self.seq), aad, pt)
-    //   self.IncrementSeq()
-    //   return ct
+    /// Takes the next sequence number, or returns `None` if they're all used up
+    fn alloc_seq(&self) -> Option<u64> {
+        use core::sync::atomic::Ordering;
 
-    /// Does a "detached seal in place", meaning it overwrites `plaintext` with the resulting
-    /// ciphertext, and returns the resulting authentication tag
+        // Only uniqueness matters here. Nothing else is synchronized through this counter.
+        self.next_seq
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |seq| {
+                seq.checked_add(1)
+            })
+            .ok()
+    }
+
+    /// Does a "detached seal in place", like `AeadCtxS::seal_in_place_detached`, but through a
+    /// shared reference
     ///
     /// Return Value
     /// ============
-    /// Returns `Ok(tag)` on success.  If this context has been used for so many encryptions that
-    /// the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If this
-    /// happens, `plaintext` will be unmodified. If an error happened during encryption, returns
-    /// `Err(HpkeError::SealError)`. If this happens, the contents of `plaintext` is undefined.
+    /// Returns `Ok((seq, tag))` on success, where `seq` is the sequence number this message was
+    /// sealed under. If every sequence number has been used, returns
+    /// `Err(HpkeError::MessageLimitReached)`, and `plaintext` is unmodified. The length errors are
+    /// those of `AeadCtxS::seal_in_place_detached`. If an error happened during encryption,
+    /// returns `Err(HpkeError::SealError)`. If this happens, the contents of `plaintext` is
+    /// undefined, and the sequence number is used up.
     pub fn seal_in_place_detached(
-        &mut self,
+        &self,
         plaintext: &mut [u8],
         aad: &[u8],
-    ) -> Result<AeadTag<A>, HpkeError> {
-        if self.0.overflowed {
-            // If the sequence counter overflowed, we've been used for far too long. Shut down.
-            Err(HpkeError::MessageLimitReached)
-        } else {
-            // Compute the nonce and do the encryption in place
-            let nonce = mix_nonce::<A>(&self.0.base_nonce, &self.0.seq);
-            let tag = self
-                .0
-                .encryptor
-                .encrypt_in_place_detached(&nonce.0, aad, plaintext)
-                .map_err(|_| HpkeError::SealError)?;
-
-            // Try to increment the sequence counter. If it fails, this was our last encryption.
-            match increment_seq(&self.0.seq) {
-                Some(new_seq) => self.0.seq = new_seq,
-                None => self.0.overflowed = true,
-            }
-
-            // Return the tag
-            Ok(AeadTag(tag))
-        }
+    ) -> Result<(u64, AeadTag<A>), HpkeError> {
+        // Check lengths first, so that a rejected message doesn't use up a sequence number
+        let res = AeadCtx::<A, Kdf, Kem>::check_msg_lens(
+            plaintext.len(),
+            aad.len(),
+            HpkeError::PlaintextTooLong,
+        );
+        // A message that's refused before it gets a sequence number is traced with the maximum one
+        let (seq, res) =
+            match res.and_then(|()| self.alloc_seq().ok_or(HpkeError::MessageLimitReached)) {
+                Ok(seq) => {
+                    let nonce = mix_nonce::<A>(&self.ctx.base_nonce, &Seq(seq));
+                    let res = self
+                        .ctx
+                        .aead()
+                        .encrypt_in_place_detached(&nonce.0, aad, plaintext)
+                        .map(|tag| (seq, AeadTag(tag)))
+                        .map_err(|_| HpkeError::SealError);
+                    (seq, res)
+                }
+                Err(err) => (u64::MAX, Err(err)),
+            };
+
+        crate::trace::message::<A, Kdf, Kem>(
+            "seal",
+            seq,
+            plaintext.len(),
+            aad.len(),
+            res.as_ref().map(|_| ()),
+        );
+        res
     }
 
-    /// Seals the given plaintext and returns the ciphertext
+    /// Seals the given plaintext and returns the ciphertext, like `AeadCtxS::seal`, but through a
+    /// shared reference
     ///
     /// Return Value
     /// ============
-    /// Returns `Ok(ciphertext)` on success.  If this context has been used for so many encryptions
-    /// that the sequence number overflowed, returns `Err(HpkeError::MessageLimitReached)`. If an
-    /// error happened during encryption, returns `Err(HpkeError::SealError)`.
+    /// Returns `Ok((seq, ciphertext))` on success, where `seq` is the sequence number this message
+    /// was sealed under. The errors are those of `SyncSenderCtx::seal_in_place_detached`.
     #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
     #[cfg(any(feature = "alloc", feature = "std"))]
-    pub fn seal(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
-        let msg_len = plaintext.len();
-        let tag_len = AeadTag::<A>::size();
-
-        // Make a buffer that can hold a ciphertext + tag. Copy in the plaintext
-        let mut buf = vec![0u8; msg_len + tag_len];
-        buf[..msg_len].copy_from_slice(plaintext);
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<(u64, crate::Vec<u8>), HpkeError> {
+        let mut buf = plaintext.to_vec();
+        let (seq, tag) = self.seal_in_place_detached(&mut buf, aad)?;
+        buf.extend_from_slice(&tag.0);
 
-        // Seal with a detached tag
-        let tag = self.seal_in_place_detached(&mut buf[..plaintext.len()], aad)?;
-        // Then append the tag to the end of the buffer. The buffer is now the auth'd ciphertext
-        buf[msg_len..msg_len + tag_len].copy_from_slice(&tag.0);
-
-        Ok(buf)
+        Ok((seq, buf))
     }
 
-    /// Fills a given buffer with secret bytes derived from this encryption context. This value
-    /// does not depend on sequence number, so it is constant for the lifetime of this context.
+    /// Fills a given buffer with secret bytes derived from this encryption context. This is the
+    /// same as `AeadCtxS::export`.
     ///
     /// Return Value
     /// ============
     /// Returns `Ok(())` on success. If the buffer length is more than 255x the digest size (in
-    /// bytes) of the underlying hash function, returns an `Err(HpkeError::KdfOutputTooLong)`. Just
-    /// don't use to fill massive buffers and you'll be fine.
+    /// bytes) of the underlying hash function, returns an `Err(HpkeError::KdfOutputTooLong)`.
     pub fn export(&self, info: &[u8], out_buf: &mut [u8]) -> Result<(), HpkeError> {
-        // Pass to AeadCtx
-        self.0.export(info, out_buf)
+        self.ctx.export(info, out_buf)
     }
 }
 
+/// Re-encrypts one message from one context to another, in place. `buf[..ciphertext_len]` is a
+/// ciphertext with its tag appended that `from` can open. On success, `buf[..new_len]` is the same
+/// plaintext sealed by `to`, with its tag appended. This is meant for key rotation over large
+/// archives: by going chunk by chunk through one reused buffer, memory use stays constant, and the
+/// plaintext never leaves `buf`.
+///
+/// `aad` is used both to open and to seal. `buf` must have room for the resealed message, i.e.,
+/// the plaintext plus `to`'s tag size. If both AEADs have the same tag size, `ciphertext_len`
+/// bytes is enough.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(new_len)` on success. If `buf` is too short to hold the ciphertext or the resealed
+/// message, returns `Err(HpkeError::IncorrectInputLength(needed, buf.len()))`. If `to` has reached
+/// its message limit, returns `Err(HpkeError::MessageLimitReached)`. If the plaintext is longer
+/// than `to`'s AEAD can seal, returns `Err(HpkeError::PlaintextTooLong)`, and if `aad` is, returns
+/// `Err(HpkeError::InputTooLong)`. None of these use up a sequence number of `from`. Otherwise, the errors are those of `AeadCtxR::open_in_place_combined`
+/// and `AeadCtxS::seal_in_place_detached`, and `buf` is in an undefined state.
+pub fn reencrypt_in_place<A1, Kdf1, Kem1, A2, Kdf2, Kem2>(
+    from: &mut AeadCtxR<A1, Kdf1, Kem1>,
+    to: &mut AeadCtxS<A2, Kdf2, Kem2>,
+    buf: &mut [u8],
+    ciphertext_len: usize,
+    aad: &[u8],
+) -> Result<usize, HpkeError>
+where
+    A1: Aead,
+    Kdf1: KdfTrait,
+    Kem1: KemTrait,
+    A2: Aead,
+    Kdf2: KdfTrait,
+    Kem2: KemTrait,
+{
+    // Check everything we can before opening, so that a failure here doesn't cost `from` a
+    // sequence number
+    if ciphertext_len > buf.len() {
+        return Err(HpkeError::IncorrectInputLength(ciphertext_len, buf.len()));
+    }
+    let msg_len = ciphertext_len
+        .checked_sub(A1::TAG_SIZE)
+        .ok_or(HpkeError::OpenError)?;
+    let new_len = msg_len + A2::TAG_SIZE;
+    if new_len > buf.len() {
+        return Err(HpkeError::IncorrectInputLength(new_len, buf.len()));
+    }
+    if to.0.overflowed {
+        return Err(HpkeError::MessageLimitReached);
+    }
+    AeadCtx::<A2, Kdf2, Kem2>::check_msg_lens(msg_len, aad.len(), HpkeError::PlaintextTooLong)?;
+
+    // Open, then seal the plaintext where it lies and put the new tag after it
+    from.open_in_place_combined(&mut buf[..ciphertext_len], aad)?;
+    let tag = to.seal_in_place_detached(&mut buf[..msg_len], aad)?;
+    buf[msg_len..new_len].copy_from_slice(&tag.0);
+
+    Ok(new_len)
+}
+
 // Export all the AEAD implementations
 mod aes_gcm;
 mod chacha20_poly1305;
@@ -465,11 +2205,9 @@ pub use crate::aead::{aes_gcm::*, chacha20_poly1305::*, export_only::*};
 
 #[cfg(test)]
 mod test {
-    use super::{AeadTag, AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead, Seq};
+    use super::{AeadTag, AesGcm128, ChaCha20Poly1305};
 
-    use crate::{
-        kdf::HkdfSha256, test_util::gen_ctx_simple_pair, Deserializable, HpkeError, Serializable,
-    };
+    use crate::{Deserializable, HpkeError, Serializable};
 
     /// Tests that AeadKey::from_bytes fails on inputs of incorrect length
     macro_rules! test_invalid_nonce {
@@ -674,13 +2412,147 @@ mod test {
         };
     }
 
+    /// Tests that `seal_into()` and `open_into()` agree with `seal()` and `open()`, and reject
+    /// output buffers that are too short
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    macro_rules! test_into_correctness {
+        ($test_name:ident, $aead_ty:ty, $kem_ty:ty) => {
+            #[test]
+            fn $test_name() {
+                type A = $aead_ty;
+                type Kdf = HkdfSha256;
+                type Kem = $kem_ty;
+
+                let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+                let tag_len = AeadTag::<A>::size();
+
+                let msg = b"Don't you know that you're toxic";
+                let aad = b"And I love what you do";
+
+                // Too short an output buffer is an error, and doesn't use up a nonce
+                let mut out = [0u8; 128];
+                assert_eq!(
+                    sender_ctx.seal_into(&mut out[..msg.len()], msg, aad),
+                    Err(HpkeError::IncorrectInputLength(
+                        msg.len() + tag_len,
+                        msg.len()
+                    ))
+                );
+
+                // Seal into a buffer that's bigger than necessary, and open with open()
+                let ct_len = sender_ctx.seal_into(&mut out, msg, aad).unwrap();
+                assert_eq!(ct_len, msg.len() + tag_len);
+                let decrypted = receiver_ctx.open(&out[..ct_len], aad).unwrap();
+                assert_eq!(&decrypted, msg);
+
+                // Seal with seal(), and open into an exactly sized buffer
+                let ciphertext = sender_ctx.seal(msg, aad).unwrap();
+                let mut pt = [0u8; 128];
+                assert_eq!(
+                    receiver_ctx.open_into(&mut pt[..msg.len() - 1], &ciphertext, aad),
+                    Err(HpkeError::IncorrectInputLength(msg.len(), msg.len() - 1))
+                );
+                let pt_len = receiver_ctx
+                    .open_into(&mut pt[..msg.len()], &ciphertext, aad)
+                    .unwrap();
+                assert_eq!(&pt[..pt_len], msg);
+
+                // A ciphertext too short to hold a tag is just invalid
+                assert_eq!(
+                    receiver_ctx.open_into(&mut pt, &ciphertext[..tag_len - 1], aad),
+                    Err(HpkeError::OpenError)
+                );
+
+                // So does opening in place
+                let mut ciphertext = sender_ctx.seal(msg, aad).unwrap();
+                let plaintext = receiver_ctx
+                    .open_in_place_combined(&mut ciphertext, aad)
+                    .unwrap();
+                assert_eq!(plaintext, msg);
+                assert_eq!(
+                    receiver_ctx.open_in_place_combined(&mut ciphertext[..tag_len - 1], aad),
+                    Err(HpkeError::OpenError)
+                );
+
+                // The array-backed variants work the same way
+                let ciphertext = sender_ctx.seal_to_array::<64>(msg, aad).unwrap();
+                assert_eq!(ciphertext.len(), msg.len() + tag_len);
+                let decrypted = receiver_ctx.open_to_array::<32>(&ciphertext, aad).unwrap();
+                assert_eq!(decrypted.as_slice(), msg);
+                assert_eq!(
+                    sender_ctx.seal_to_array::<32>(msg, aad),
+                    Err(HpkeError::IncorrectInputLength(msg.len() + tag_len, 32))
+                );
+
+                // So do the fixed-size ones, with the sizes checked at compile time
+                let ciphertext = sender_ctx
+                    .seal_fixed::<32, { 32 + A::TAG_SIZE }>(msg, aad)
+                    .unwrap();
+                let decrypted: [u8; 32] = receiver_ctx.open_fixed(&ciphertext, aad).unwrap();
+                assert_eq!(&decrypted, msg);
+                let mut ciphertext: [u8; 32 + A::TAG_SIZE] =
+                    sender_ctx.seal_fixed(msg, aad).unwrap();
+                ciphertext[0] ^= 1;
+                assert_eq!(
+                    receiver_ctx.open_fixed::<_, 32>(&ciphertext, aad),
+                    Err(HpkeError::OpenError)
+                );
+            }
+        };
+    }
+
     test_invalid_nonce!(test_invalid_nonce_aes128, AesGcm128);
     test_invalid_nonce!(test_invalid_nonce_aes256, AesGcm128);
     test_invalid_nonce!(test_invalid_nonce_chacha, ChaCha20Poly1305);
 
+    /// Tests that, with `compact-ctx`, for every suite, a context holds nothing but its key, base
+    /// nonce, exporter secret, and sequence number, and a sender its encapsulated key too, so that
+    /// 200 contexts fit in 64KiB
+    #[cfg(feature = "compact-ctx")]
+    #[test]
+    fn test_ctx_size() {
+        use super::{Aead, AeadCtxR, AeadCtxS, AeadKey, AeadNonce, AesGcm256, ExportOnlyAead};
+        use crate::{kdf::Kdf as KdfTrait, kem::Kem as KemTrait};
+        use core::mem::size_of;
+
+        fn check<A: Aead, Kdf: KdfTrait, Kem: KemTrait>() {
+            let secrets_size =
+                size_of::<AeadKey<A>>() + size_of::<AeadNonce<A>>() + Kdf::DIGEST_SIZE;
+            // The sequence number and overflow flag, with padding
+            let receiver_size = size_of::<AeadCtxR<A, Kdf, Kem>>();
+            assert!(receiver_size <= secrets_size + 16);
+            let sender_size = size_of::<AeadCtxS<A, Kdf, Kem>>();
+            assert!(sender_size <= receiver_size + size_of::<Option<Kem::EncappedKey>>() + 8);
+            assert!(sender_size <= 64 * 1024 / 200);
+        }
+
+        macro_rules! check_aeads {
+            ($kdf:ty, $kem:ty) => {
+                check::<AesGcm128, $kdf, $kem>();
+                check::<AesGcm256, $kdf, $kem>();
+                check::<ChaCha20Poly1305, $kdf, $kem>();
+                check::<ExportOnlyAead, $kdf, $kem>();
+            };
+        }
+
+        #[cfg(feature = "x25519")]
+        check_aeads!(crate::kdf::HkdfSha256, crate::kem::X25519HkdfSha256);
+        #[cfg(feature = "p256")]
+        check_aeads!(crate::kdf::HkdfSha256, crate::kem::DhP256HkdfSha256);
+        #[cfg(feature = "p384")]
+        check_aeads!(crate::kdf::HkdfSha384, crate::kem::DhP384HkdfSha384);
+        #[cfg(feature = "p521")]
+        check_aeads!(crate::kdf::HkdfSha512, crate::kem::DhP521HkdfSha512);
+    }
+
     #[cfg(all(feature = "x25519", any(feature = "alloc", feature = "std")))]
     mod x25519_tests {
         use super::*;
+        use crate::{
+            aead::{Aead, AesGcm256, ExportOnlyAead, Seq},
+            kdf::HkdfSha256,
+            test_util::gen_ctx_simple_pair,
+        };
 
         test_export_idempotence!(test_export_idempotence_x25519, crate::kem::X25519HkdfSha256);
         test_exportonly_panics!(
@@ -690,6 +2562,795 @@ mod test {
         );
         test_overflow!(test_overflow_x25519, crate::kem::X25519HkdfSha256);
 
+        /// Tests that `open_many()` opens a run of messages in order, and leaves the context
+        /// untouched if any of them fails
+        #[test]
+        fn test_open_many() {
+            type A = ChaCha20Poly1305;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+
+            let msgs: [(&[u8], &[u8]); 3] = [(b"one", b"a"), (b"two", b""), (b"", b"c")];
+            let ciphertexts: crate::Vec<crate::Vec<u8>> = msgs
+                .iter()
+                .map(|(pt, aad)| sender_ctx.seal(pt, aad).unwrap())
+                .collect();
+            let mut batch: crate::Vec<(&[u8], &[u8])> = ciphertexts
+                .iter()
+                .zip(msgs.iter())
+                .map(|(ct, (_, aad))| (ct.as_slice(), *aad))
+                .collect();
+
+            let mut out = [0u8; 16];
+
+            // An empty batch is fine and doesn't use up any nonces
+            assert_eq!(receiver_ctx.open_many(&[], &mut out), Ok(0));
+
+            // An output buffer that's too short is caught before anything is opened
+            assert_eq!(
+                receiver_ctx.open_many(&batch, &mut out[..5]),
+                Err(HpkeError::IncorrectInputLength(6, 5))
+            );
+
+            // Use the wrong AAD on the last message. Nothing should be opened, and what was
+            // written is cleared.
+            batch[2].1 = b"wrong";
+            assert_eq!(
+                receiver_ctx.open_many(&batch, &mut out),
+                Err(HpkeError::OpenError)
+            );
+            assert_eq!(out, [0u8; 16]);
+
+            // The context should not have moved, so the first message opens on its own
+            assert_eq!(receiver_ctx.open(&ciphertexts[0], b"a").unwrap(), b"one");
+
+            // Now open the rest as a batch
+            let len = receiver_ctx
+                .open_many(&[(&ciphertexts[1], b""), (&ciphertexts[2], b"c")], &mut out)
+                .unwrap();
+            assert_eq!(&out[..len], b"two");
+        }
+
+        /// Tests that `reencrypt_in_place()` moves a stream of chunks from one context to another
+        /// through a single buffer
+        #[test]
+        fn test_reencrypt_in_place() {
+            use crate::{aead::reencrypt_in_place, kdf::HkdfSha384};
+
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut old_sender, mut old_receiver) =
+                gen_ctx_simple_pair::<AesGcm128, HkdfSha256, Kem>();
+            let (mut new_sender, mut new_receiver) =
+                gen_ctx_simple_pair::<ChaCha20Poly1305, HkdfSha384, Kem>();
+
+            let chunks: [&[u8]; 3] = [b"chunk zero", b"chunk one, which is longer", b""];
+            let aad = b"archive 7";
+            let old_ciphertexts: crate::Vec<_> = chunks
+                .iter()
+                .map(|chunk| old_sender.seal(chunk, aad).unwrap())
+                .collect();
+
+            // A buffer that's too small is caught before anything is opened
+            let mut buf = [0u8; 64];
+            assert_eq!(
+                reencrypt_in_place(&mut old_receiver, &mut new_sender, &mut buf[..8], 26, aad),
+                Err(HpkeError::IncorrectInputLength(26, 8))
+            );
+
+            for (chunk, old_ciphertext) in chunks.iter().zip(old_ciphertexts.iter()) {
+                let len = old_ciphertext.len();
+                buf[..len].copy_from_slice(old_ciphertext);
+                let new_len =
+                    reencrypt_in_place(&mut old_receiver, &mut new_sender, &mut buf, len, aad)
+                        .unwrap();
+                assert_eq!(new_receiver.open(&buf[..new_len], aad).unwrap(), *chunk);
+            }
+
+            // A message that `from` can open, but that's too long for `to` to seal, is caught
+            // before it's opened, and so is an AAD that's too long
+            #[cfg(feature = "reduced-security")]
+            {
+                type A2 = crate::reduced_security::AesGcm128Tag64;
+
+                let (mut small_sender, _) = gen_ctx_simple_pair::<A2, HkdfSha256, Kem>();
+                let long_msg = vec![7u8; (1 << 14) + 1];
+                let long_aad = vec![0u8; (1 << 14) + 1];
+                let long_msg_ciphertext = old_sender.seal(&long_msg, aad).unwrap();
+                let long_aad_ciphertext = old_sender.seal(b"short", &long_aad).unwrap();
+
+                let mut buf = long_msg_ciphertext.clone();
+                let len = buf.len();
+                assert_eq!(
+                    reencrypt_in_place(&mut old_receiver, &mut small_sender, &mut buf, len, aad),
+                    Err(HpkeError::PlaintextTooLong)
+                );
+                let mut buf = long_aad_ciphertext.clone();
+                let len = buf.len();
+                assert_eq!(
+                    reencrypt_in_place(
+                        &mut old_receiver,
+                        &mut small_sender,
+                        &mut buf,
+                        len,
+                        &long_aad
+                    ),
+                    Err(HpkeError::InputTooLong)
+                );
+
+                // Neither failure used up a sequence number of `from`
+                assert_eq!(
+                    old_receiver.open(&long_msg_ciphertext, aad).unwrap(),
+                    long_msg
+                );
+                assert_eq!(
+                    old_receiver.open(&long_aad_ciphertext, &long_aad).unwrap(),
+                    b"short"
+                );
+            }
+        }
+
+        /// Tests that `seal_all()` agrees with `seal()`, and seals nothing if it would run out of
+        /// sequence numbers or any plaintext is too long
+        #[test]
+        fn test_seal_all() {
+            type A = ChaCha20Poly1305;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+
+            let msgs: [&[u8]; 3] = [b"first line", b"", b"third line"];
+            let aad = b"log batch";
+
+            // Sealing all at once is the same as sealing one by one
+            let mut one_by_one = sender_ctx.clone();
+            let ciphertexts = sender_ctx.seal_all(&msgs, aad).unwrap();
+            for (msg, ciphertext) in msgs.iter().zip(ciphertexts.iter()) {
+                assert_eq!(&one_by_one.seal(msg, aad).unwrap(), ciphertext);
+                assert_eq!(&receiver_ctx.open(ciphertext, aad).unwrap(), msg);
+            }
+
+            // With 2 sequence numbers left, sealing 3 messages fails without using any of them
+            sender_ctx.0.seq = Seq(u64::MAX - 1);
+            receiver_ctx.0.seq = Seq(u64::MAX - 1);
+            assert_eq!(
+                sender_ctx.seal_all(&msgs, aad),
+                Err(HpkeError::MessageLimitReached)
+            );
+            let ciphertexts = sender_ctx.seal_all(&msgs[..2], aad).unwrap();
+            let mut out = [0u8; 16];
+            let len = receiver_ctx
+                .open_many(&[(&ciphertexts[0], aad), (&ciphertexts[1], aad)], &mut out)
+                .unwrap();
+            assert_eq!(&out[..len], b"first line");
+
+            // Now the context is used up, but an empty batch is still fine
+            assert_eq!(
+                sender_ctx.seal_all(&msgs[..1], aad),
+                Err(HpkeError::MessageLimitReached)
+            );
+            assert!(sender_ctx.seal_all(&[], aad).unwrap().is_empty());
+
+            // A plaintext that's too long, after one that's fine, seals neither. This needs an AEAD
+            // with a small plaintext limit.
+            #[cfg(feature = "reduced-security")]
+            {
+                type A = crate::reduced_security::AesGcm128Tag64;
+
+                let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+                let too_long = vec![0u8; (1 << 14) + 1];
+                assert_eq!(
+                    sender_ctx.seal_all(&[b"fine", &too_long], aad),
+                    Err(HpkeError::PlaintextTooLong)
+                );
+                let ciphertext = sender_ctx.seal(b"fine", aad).unwrap();
+                assert_eq!(receiver_ctx.open(&ciphertext, aad).unwrap(), b"fine");
+            }
+        }
+
+        /// Tests that the vectored variants of seal and open agree with their contiguous versions
+        #[test]
+        fn test_vectored() {
+            type A = AesGcm128;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+
+            let msg = b"header|body|trailer";
+            let aad = b"version|type|length";
+            let msg_pieces: [&[u8]; 4] = [b"header|", b"", b"body|", b"trailer"];
+            let aad_pieces: [&[u8]; 3] = [b"version|", b"type|", b"length"];
+
+            // Vectored and contiguous sealing give the same ciphertext
+            let ciphertext = sender_ctx.clone().seal(msg, aad).unwrap();
+            assert_eq!(
+                sender_ctx.seal_vectored(&msg_pieces, &aad_pieces).unwrap(),
+                ciphertext
+            );
+            assert_eq!(
+                receiver_ctx
+                    .open_vectored(&ciphertext, &aad_pieces)
+                    .unwrap(),
+                msg
+            );
+
+            // Zero and one AAD pieces work too, and the pieces must match the AAD
+            let ciphertext = sender_ctx.seal_vectored(&[msg], &[]).unwrap();
+            assert_eq!(
+                receiver_ctx.clone().open_vectored(&ciphertext, &[b"x"]),
+                Err(HpkeError::OpenError)
+            );
+            assert_eq!(receiver_ctx.open(&ciphertext, b"").unwrap(), msg);
+            let ciphertext = sender_ctx.seal_vectored(&[msg], &[aad]).unwrap();
+            assert_eq!(
+                receiver_ctx.open_vectored(&ciphertext, &[aad]).unwrap(),
+                msg
+            );
+        }
+
+        /// A cheap stand-in for a real AEAD. It leaves the plaintext as is, and its tag is the
+        /// last 8 bytes of the nonce, so tags show which nonce each message used.
+        #[derive(Clone)]
+        struct MockAeadImpl;
+
+        impl aead::AeadCore for MockAeadImpl {
+            type NonceSize = generic_array::typenum::U12;
+            type TagSize = generic_array::typenum::U8;
+            type CiphertextOverhead = generic_array::typenum::U0;
+        }
+
+        impl aead::KeySizeUser for MockAeadImpl {
+            type KeySize = generic_array::typenum::U16;
+        }
+
+        impl aead::KeyInit for MockAeadImpl {
+            fn new(_: &aead::Key<Self>) -> Self {
+                MockAeadImpl
+            }
+        }
+
+        impl aead::AeadInPlace for MockAeadImpl {
+            fn encrypt_in_place_detached(
+                &self,
+                nonce: &aead::Nonce<Self>,
+                _: &[u8],
+                _: &mut [u8],
+            ) -> Result<aead::Tag<Self>, aead::Error> {
+                Ok(aead::Tag::<Self>::clone_from_slice(&nonce[4..]))
+            }
+
+            fn decrypt_in_place_detached(
+                &self,
+                nonce: &aead::Nonce<Self>,
+                _: &[u8],
+                _: &mut [u8],
+                tag: &aead::Tag<Self>,
+            ) -> Result<(), aead::Error> {
+                if tag.as_slice() == &nonce[4..] {
+                    Ok(())
+                } else {
+                    Err(aead::Error)
+                }
+            }
+        }
+
+        struct MockAead;
+
+        impl super::super::Aead for MockAead {
+            type AeadImpl = MockAeadImpl;
+            const AEAD_ID: u16 = 0xfffe;
+            const MAX_PLAINTEXT_LEN: u64 = u64::MAX;
+            const MAX_AAD_LEN: u64 = u64::MAX;
+        }
+
+        /// The mock AEAD, but with tiny length limits
+        struct SmallMockAead;
+
+        impl super::super::Aead for SmallMockAead {
+            type AeadImpl = MockAeadImpl;
+            const AEAD_ID: u16 = 0xfffe;
+            const MAX_PLAINTEXT_LEN: u64 = 16;
+            const MAX_AAD_LEN: u64 = 8;
+        }
+
+        /// Tests every plaintext and AAD length around the AEAD's limits, and checks that an
+        /// oversized input neither gets sealed or opened nor uses up a sequence number
+        #[test]
+        fn test_length_limits() {
+            use super::super::AeadCtxR;
+
+            type A = SmallMockAead;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            let buf = [0xaau8; 32];
+
+            // Opening a message and then a bad one of the given length checks that the bad one
+            // was refused with `err` and didn't use up a sequence number
+            let open_after = |receiver_ctx: &mut AeadCtxR<A, Kdf, Kem>, ct_len, aad_len, err| {
+                let mut ct = buf;
+                let tag = AeadTag::<A>::default();
+                assert_eq!(
+                    receiver_ctx.open_in_place_detached(&mut ct[..ct_len], &buf[..aad_len], &tag),
+                    Err(err)
+                );
+            };
+
+            for msg_len in 0..=18 {
+                for aad_len in 0..=10 {
+                    let mut msg = buf;
+                    let res =
+                        sender_ctx.seal_in_place_detached(&mut msg[..msg_len], &buf[..aad_len]);
+                    if aad_len > 8 {
+                        assert_eq!(res.err(), Some(HpkeError::InputTooLong));
+                        assert_eq!(msg, buf);
+                        open_after(&mut receiver_ctx, msg_len, aad_len, HpkeError::InputTooLong);
+                    } else if msg_len > 16 {
+                        assert_eq!(res.err(), Some(HpkeError::PlaintextTooLong));
+                        assert_eq!(msg, buf);
+                        open_after(&mut receiver_ctx, msg_len, aad_len, HpkeError::OpenError);
+                    } else {
+                        let tag = res.unwrap();
+                        receiver_ctx
+                            .open_in_place_detached(&mut msg[..msg_len], &buf[..aad_len], &tag)
+                            .unwrap();
+                    }
+                }
+            }
+
+            // Too-long exporter contexts are caught by the same kind of check, but HKDF-SHA256's
+            // limit is 2^61 bytes, so only the check itself is tested
+            assert!(!crate::util::exceeds(16, 16));
+            assert!(crate::util::exceeds(17, 16));
+            assert!(crate::util::exceeds(usize::MAX, u64::MAX >> 1));
+        }
+
+        /// Tests that a context that has used its last sequence number refuses every kind of seal
+        /// and open from then on, even after being suspended and resumed, and that no nonce was
+        /// used twice on the way there
+        #[test]
+        fn test_poisoned_after_overflow() {
+            use super::super::{reencrypt_in_place, AeadCtxR, AeadCtxS};
+
+            type A = MockAead;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            sender_ctx.0.seq = Seq(u64::MAX - 2);
+            receiver_ctx.0.seq = Seq(u64::MAX - 2);
+
+            // Use up the last three sequence numbers. The tags are the nonce suffixes.
+            let mut tags = crate::Vec::new();
+            for _ in 0..3 {
+                let mut msg = *b"msg";
+                let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+                receiver_ctx
+                    .open_in_place_detached(&mut msg, b"", &tag)
+                    .unwrap();
+                tags.push(tag);
+            }
+            assert!(tags[0] != tags[1] && tags[1] != tags[2] && tags[0] != tags[2]);
+
+            let limit = Some(HpkeError::MessageLimitReached);
+            let mut buf = [0u8; 32];
+
+            // Every way of sealing is refused
+            assert_eq!(
+                sender_ctx.seal_in_place_detached(&mut buf[..3], b"").err(),
+                limit
+            );
+            assert_eq!(sender_ctx.seal_into(&mut buf, b"msg", b"").err(), limit);
+            assert_eq!(sender_ctx.seal_to_array::<32>(b"msg", b"").err(), limit);
+            assert_eq!(sender_ctx.seal(b"msg", b"").err(), limit);
+            assert_eq!(sender_ctx.seal_all(&[b"msg"], b"").err(), limit);
+            assert_eq!(sender_ctx.seal_vectored(&[b"msg"], &[b""]).err(), limit);
+
+            // Every way of opening is refused, even with a tag that would otherwise validate
+            let ciphertext = [&b"msg"[..], &tags[2].0].concat();
+            assert_eq!(
+                receiver_ctx
+                    .open_in_place_detached(&mut buf[..3], b"", &tags[2])
+                    .err(),
+                limit
+            );
+            assert_eq!(
+                receiver_ctx
+                    .open_in_place_combined(&mut ciphertext.clone(), b"")
+                    .err(),
+                limit
+            );
+            assert_eq!(
+                receiver_ctx.open_into(&mut buf, &ciphertext, b"").err(),
+                limit
+            );
+            assert_eq!(
+                receiver_ctx.open_to_array::<32>(&ciphertext, b"").err(),
+                limit
+            );
+            assert_eq!(receiver_ctx.open(&ciphertext, b"").err(), limit);
+            assert_eq!(
+                receiver_ctx
+                    .open_many(&[(&ciphertext, b"")], &mut buf)
+                    .err(),
+                limit
+            );
+            assert_eq!(receiver_ctx.open_vectored(&ciphertext, &[b""]).err(), limit);
+
+            // Re-encrypting from or into an exhausted context is refused too
+            let (mut fresh_sender_ctx, mut fresh_receiver_ctx) =
+                gen_ctx_simple_pair::<A, Kdf, Kem>();
+            buf[..ciphertext.len()].copy_from_slice(&ciphertext);
+            assert_eq!(
+                reencrypt_in_place(
+                    &mut receiver_ctx,
+                    &mut fresh_sender_ctx,
+                    &mut buf,
+                    ciphertext.len(),
+                    b""
+                )
+                .err(),
+                limit
+            );
+            assert_eq!(
+                reencrypt_in_place(
+                    &mut fresh_receiver_ctx,
+                    &mut sender_ctx,
+                    &mut buf,
+                    ciphertext.len(),
+                    b""
+                )
+                .err(),
+                limit
+            );
+
+            // Suspending and resuming doesn't clear the poison
+            let mut resumed_sender_ctx =
+                AeadCtxS::<A, Kdf, Kem>::resume(&sender_ctx.suspend()).unwrap();
+            let mut resumed_receiver_ctx =
+                AeadCtxR::<A, Kdf, Kem>::resume(&receiver_ctx.suspend()).unwrap();
+            assert_eq!(resumed_sender_ctx.seal(b"msg", b"").err(), limit);
+            assert_eq!(resumed_receiver_ctx.open(&ciphertext, b"").err(), limit);
+
+            // Exporting still works
+            let mut exported_s = [0u8; 16];
+            let mut exported_r = [0u8; 16];
+            sender_ctx.export(b"ctx", &mut exported_s).unwrap();
+            receiver_ctx.export(b"ctx", &mut exported_r).unwrap();
+            assert_eq!(exported_s, exported_r);
+        }
+
+        /// Tests that suspended contexts resume where they left off, and that corrupted or
+        /// mismatched ones are rejected
+        #[test]
+        fn test_suspend_resume() {
+            use crate::{
+                aead::{AeadCtxR, AeadCtxS, MAX_SUSPENDED_CTX_SIZE},
+                kdf::HkdfSha512,
+            };
+
+            type A = AesGcm256;
+            type Kdf = HkdfSha512;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            let msg = b"sleeping";
+            let aad = b"";
+
+            // Use up a nonce before suspending
+            let ciphertext = sender_ctx.seal(msg, aad).unwrap();
+            receiver_ctx.open(&ciphertext, aad).unwrap();
+
+            // This is the largest suite, so it should take up the whole max size
+            let suspended_sender = sender_ctx.suspend();
+            let suspended_receiver = receiver_ctx.suspend();
+            assert_eq!(suspended_sender.len(), MAX_SUSPENDED_CTX_SIZE);
+
+            // The resumed contexts should pick up at the next sequence number, and export the same
+            let mut resumed_sender = AeadCtxS::<A, Kdf, Kem>::resume(&suspended_sender).unwrap();
+            let mut resumed_receiver =
+                AeadCtxR::<A, Kdf, Kem>::resume(&suspended_receiver).unwrap();
+            let ciphertext = resumed_sender.seal(msg, aad).unwrap();
+            assert_eq!(ciphertext, sender_ctx.seal(msg, aad).unwrap());
+            assert_eq!(resumed_receiver.open(&ciphertext, aad).unwrap(), msg);
+            let (mut out1, mut out2) = ([0u8; 32], [0u8; 32]);
+            sender_ctx.export(b"ctx", &mut out1).unwrap();
+            resumed_receiver.export(b"ctx", &mut out2).unwrap();
+            assert_eq!(out1, out2);
+
+            // Flipping any bit is detected
+            let mut bytes = suspended_sender.to_vec();
+            for i in 0..bytes.len() {
+                bytes[i] ^= 1;
+                assert_eq!(
+                    AeadCtxS::<A, Kdf, Kem>::resume(&bytes).err(),
+                    Some(HpkeError::ValidationError)
+                );
+                bytes[i] ^= 1;
+            }
+
+            // The wrong role, suite, or length are all rejected
+            assert_eq!(
+                AeadCtxR::<A, Kdf, Kem>::resume(&suspended_sender).err(),
+                Some(HpkeError::ValidationError)
+            );
+            assert_eq!(
+                AeadCtxS::<ChaCha20Poly1305, Kdf, Kem>::resume(&suspended_sender).err(),
+                Some(HpkeError::ValidationError)
+            );
+            assert_eq!(
+                AeadCtxS::<A, HkdfSha256, Kem>::resume(&suspended_sender).err(),
+                Some(HpkeError::IncorrectInputLength(95, MAX_SUSPENDED_CTX_SIZE))
+            );
+
+            // Export-only contexts leave out the key and nonce
+            let (sender_ctx, _) = gen_ctx_simple_pair::<ExportOnlyAead, Kdf, Kem>();
+            let suspended = sender_ctx.suspend();
+            assert_eq!(suspended.len(), 1 + 6 + 8 + 64 + 4);
+            let resumed = AeadCtxS::<ExportOnlyAead, Kdf, Kem>::resume(&suspended).unwrap();
+            sender_ctx.export(b"ctx", &mut out1).unwrap();
+            resumed.export(b"ctx", &mut out2).unwrap();
+            assert_eq!(out1, out2);
+        }
+
+        /// Tests that the raw parts of a context let a plain AEAD carry on where it left off
+        #[cfg(feature = "hazmat")]
+        #[test]
+        fn test_danger_into_raw_parts() {
+            use ::aead::{AeadInPlace as _, KeyInit, Nonce};
+
+            type A = AesGcm128;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            let (msg, aad) = (b"handed off", b"aad");
+            let ciphertext = sender_ctx.seal(msg, aad).unwrap();
+            receiver_ctx.open(&ciphertext, aad).unwrap();
+
+            let ciphertext = sender_ctx.seal(msg, aad).unwrap();
+            let parts = receiver_ctx.danger_into_raw_parts().unwrap();
+            assert_eq!(parts.seq(), 1);
+            assert_eq!(parts.key().len(), 16);
+            assert_eq!(parts.exporter_secret().len(), 32);
+
+            // nonce = base_nonce XOR I2OSP(seq, Nn)
+            let mut nonce = Nonce::<::aes_gcm::Aes128Gcm>::clone_from_slice(parts.base_nonce());
+            for (n, s) in nonce.iter_mut().rev().zip(parts.seq().to_le_bytes()) {
+                *n ^= s;
+            }
+            let raw = ::aes_gcm::Aes128Gcm::new_from_slice(parts.key()).unwrap();
+            let mut buf = msg.to_vec();
+            let tag = raw
+                .encrypt_in_place_detached(&nonce, aad, &mut buf)
+                .unwrap();
+            buf.extend_from_slice(&tag);
+            assert_eq!(buf, ciphertext);
+
+            // Both ends hold the same secrets, and the sender is one message ahead
+            let sender_parts = sender_ctx.danger_into_raw_parts().unwrap();
+            assert_eq!(sender_parts.seq(), 2);
+            assert_eq!(sender_parts.key(), parts.key());
+            assert_eq!(sender_parts.base_nonce(), parts.base_nonce());
+            assert_eq!(sender_parts.exporter_secret(), parts.exporter_secret());
+        }
+
+        /// Tests that forks interoperate with their matching receiver forks, and are independent
+        /// of their parent and of each other
+        #[test]
+        fn test_fork_for_broadcast() {
+            type A = ChaCha20Poly1305;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            let msg = b"fan out";
+            let aad = b"";
+
+            let mut fork_s1 = sender_ctx.fork_for_broadcast(b"channel 1").unwrap();
+            let mut fork_s2 = sender_ctx.fork_for_broadcast(b"channel 2").unwrap();
+            let mut fork_r1 = receiver_ctx.fork_for_broadcast(b"channel 1").unwrap();
+            let mut fork_r2 = receiver_ctx.fork_for_broadcast(b"channel 2").unwrap();
+
+            // Each fork starts at sequence number 0, under its own key and nonce
+            let ct1 = fork_s1.seal(msg, aad).unwrap();
+            let ct2 = fork_s2.seal(msg, aad).unwrap();
+            assert_ne!(ct1, ct2);
+            assert_eq!(fork_r2.clone().open(&ct1, aad), Err(HpkeError::OpenError));
+            assert_eq!(fork_r1.open(&ct1, aad).unwrap(), msg);
+            assert_eq!(fork_r2.open(&ct2, aad).unwrap(), msg);
+
+            // Each fork used up one of the parent's sequence numbers, and the parent's ciphertexts
+            // differ from the forks'
+            assert_eq!(sender_ctx.0.seq.0, 2);
+            let ct = sender_ctx.seal(msg, aad).unwrap();
+            assert_ne!(ct, ct1);
+            assert_eq!(receiver_ctx.open(&ct, aad).unwrap(), msg);
+
+            // Reusing a fork ID still gives a fork with its own key and nonces
+            let mut fork_s1_again = sender_ctx.fork_for_broadcast(b"channel 1").unwrap();
+            let mut fork_r1_again = receiver_ctx.fork_for_broadcast(b"channel 1").unwrap();
+            let ct1_again = fork_s1_again.seal(msg, aad).unwrap();
+            assert_ne!(ct1_again, ct1);
+            assert_eq!(fork_r1_again.open(&ct1_again, aad).unwrap(), msg);
+
+            // Forks export differently from their parent, and agree with their receivers
+            let (mut parent_out, mut out_s, mut out_r) = ([0u8; 32], [0u8; 32], [0u8; 32]);
+            sender_ctx.export(b"ctx", &mut parent_out).unwrap();
+            fork_s1.export(b"ctx", &mut out_s).unwrap();
+            fork_r1.export(b"ctx", &mut out_r).unwrap();
+            assert_ne!(parent_out, out_s);
+            assert_eq!(out_s, out_r);
+        }
+
+        /// Tests that responses open on the sender's end, are fresh every time, and leave the
+        /// request contexts' sequence numbers alone
+        #[test]
+        fn test_respond() {
+            use crate::aead::RESPONSE_NONCE_SIZE;
+            use rand::{rngs::StdRng, SeedableRng};
+
+            type A = ChaCha20Poly1305;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let mut csprng = StdRng::from_entropy();
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            let request = sender_ctx.seal(b"request", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&request, b"").unwrap(), b"request");
+
+            let response1 = receiver_ctx
+                .respond(b"response", b"aad", &mut csprng)
+                .unwrap();
+            let response2 = receiver_ctx
+                .respond(b"response", b"aad", &mut csprng)
+                .unwrap();
+            assert_eq!(response1.len(), RESPONSE_NONCE_SIZE + 8 + 16);
+            assert_ne!(response1, response2);
+            assert_eq!(
+                sender_ctx.open_response(&response1, b"aad").unwrap(),
+                b"response"
+            );
+            assert_eq!(
+                sender_ctx.open_response(&response2, b"aad").unwrap(),
+                b"response"
+            );
+            assert_eq!(
+                sender_ctx.open_response(&response1, b"other"),
+                Err(HpkeError::OpenError)
+            );
+            assert_eq!(
+                sender_ctx.open_response(&response1[..RESPONSE_NONCE_SIZE - 1], b"aad"),
+                Err(HpkeError::OpenError)
+            );
+
+            // The request contexts carry on where they were
+            let request = sender_ctx.seal(b"request 2", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&request, b"").unwrap(), b"request 2");
+        }
+
+        /// Tests that a `SyncSenderCtx` shared between threads uses every sequence number exactly
+        /// once, and that its messages open in sequence order
+        #[cfg(feature = "std")]
+        #[test]
+        fn test_sync_sender_ctx() {
+            use crate::aead::SyncSenderCtx;
+
+            type A = ChaCha20Poly1305;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            const THREADS: usize = 4;
+            const PER_THREAD: usize = 25;
+
+            let (mut sender_ctx, mut receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            // Start from a used context, to check that the sequence carries over
+            let first = sender_ctx.seal(b"first", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&first, b"").unwrap(), b"first");
+
+            let sync_ctx = SyncSenderCtx::new(sender_ctx);
+            let mut sealed: crate::Vec<(u64, crate::Vec<u8>)> = std::thread::scope(|s| {
+                let handles: crate::Vec<_> = (0..THREADS)
+                    .map(|_| {
+                        s.spawn(|| {
+                            (0..PER_THREAD)
+                                .map(|_| sync_ctx.seal(b"concurrent", b"").unwrap())
+                                .collect::<crate::Vec<_>>()
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|h| h.join().unwrap())
+                    .collect()
+            });
+
+            // Every sequence number after the first message was used exactly once, and the
+            // messages open in that order
+            sealed.sort_by_key(|(seq, _)| *seq);
+            for (i, (seq, ciphertext)) in sealed.iter().enumerate() {
+                assert_eq!(*seq, i as u64 + 1);
+                assert_eq!(receiver_ctx.open(ciphertext, b"").unwrap(), b"concurrent");
+            }
+
+            // A used-up context stays used up
+            let (mut sender_ctx, _) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            sender_ctx.0.seq = Seq(u64::MAX - 1);
+            let sync_ctx = SyncSenderCtx::new(sender_ctx);
+            assert_eq!(sync_ctx.seal(b"last", b"").unwrap().0, u64::MAX - 1);
+            assert_eq!(
+                sync_ctx.seal(b"too many", b"").err(),
+                Some(HpkeError::MessageLimitReached)
+            );
+        }
+
+        /// Tests that labeled exports are framed exports, that the framing separates domains and
+        /// labels, and that reserved labels are refused
+        #[test]
+        fn test_export_labeled() {
+            type A = ChaCha20Poly1305;
+            type Kdf = HkdfSha256;
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let (sender_ctx, receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+            let (mut out1, mut out2) = ([0u8; 32], [0u8; 32]);
+
+            // This is an export with the documented exporter context, and both sides agree
+            sender_ctx
+                .export_labeled(b"ab", b"c", b"ctx", &mut out1)
+                .unwrap();
+            sender_ctx
+                .export(b"\x00\x02ab\x00\x01cctx", &mut out2)
+                .unwrap();
+            assert_eq!(out1, out2);
+            receiver_ctx
+                .export_labeled(b"ab", b"c", b"ctx", &mut out2)
+                .unwrap();
+            assert_eq!(out1, out2);
+
+            // Moving bytes between the domain, label, and context changes the output
+            sender_ctx
+                .export_labeled(b"a", b"bc", b"ctx", &mut out2)
+                .unwrap();
+            assert_ne!(out1, out2);
+            sender_ctx
+                .export_labeled(b"ab", b"cc", b"tx", &mut out2)
+                .unwrap();
+            assert_ne!(out1, out2);
+
+            for label in crate::aead::RESERVED_LABELS {
+                assert_eq!(
+                    sender_ctx.export_labeled(b"app", label, b"", &mut out1),
+                    Err(HpkeError::ReservedLabel)
+                );
+            }
+        }
+
+        /// Tests that the compile-time suite IDs match the RFC's encoding
+        #[test]
+        fn test_suite_ids() {
+            use crate::{
+                aead::{AeadCtxR, AeadCtxS},
+                kem::X25519HkdfSha256,
+                Kem,
+            };
+
+            assert_eq!(&X25519HkdfSha256::SUITE_ID, b"KEM\x00\x20");
+            assert_eq!(
+                &AeadCtxS::<ChaCha20Poly1305, HkdfSha256, X25519HkdfSha256>::SUITE_ID,
+                b"HPKE\x00\x20\x00\x01\x00\x03"
+            );
+            assert_eq!(
+                &AeadCtxR::<ExportOnlyAead, HkdfSha256, X25519HkdfSha256>::SUITE_ID,
+                b"HPKE\x00\x20\x00\x01\xff\xff"
+            );
+        }
+
         test_ctx_correctness!(
             test_ctx_correctness_aes128_x25519,
             AesGcm128,
@@ -705,11 +3366,27 @@ mod test {
             ChaCha20Poly1305,
             crate::kem::X25519HkdfSha256
         );
+
+        test_into_correctness!(
+            test_into_correctness_aes128_x25519,
+            AesGcm128,
+            crate::kem::X25519HkdfSha256
+        );
+        test_into_correctness!(
+            test_into_correctness_chacha_x25519,
+            ChaCha20Poly1305,
+            crate::kem::X25519HkdfSha256
+        );
     }
 
     #[cfg(all(feature = "p256", any(feature = "alloc", feature = "std")))]
     mod p256_tests {
         use super::*;
+        use crate::{
+            aead::{Aead, AesGcm256, ExportOnlyAead, Seq},
+            kdf::HkdfSha256,
+            test_util::gen_ctx_simple_pair,
+        };
 
         test_export_idempotence!(test_export_idempotence_p256, crate::kem::DhP256HkdfSha256);
         test_exportonly_panics!(
@@ -734,11 +3411,27 @@ mod test {
             ChaCha20Poly1305,
             crate::kem::DhP256HkdfSha256
         );
+
+        test_into_correctness!(
+            test_into_correctness_aes128_p256,
+            AesGcm128,
+            crate::kem::DhP256HkdfSha256
+        );
+        test_into_correctness!(
+            test_into_correctness_chacha_p256,
+            ChaCha20Poly1305,
+            crate::kem::DhP256HkdfSha256
+        );
     }
 
     #[cfg(all(feature = "p384", any(feature = "alloc", feature = "std")))]
     mod p384_tests {
         use super::*;
+        use crate::{
+            aead::{Aead, AesGcm256, ExportOnlyAead, Seq},
+            kdf::HkdfSha256,
+            test_util::gen_ctx_simple_pair,
+        };
 
         test_export_idempotence!(test_export_idempotence_p384, crate::kem::DhP384HkdfSha384);
         test_exportonly_panics!(
@@ -763,6 +3456,62 @@ mod test {
             ChaCha20Poly1305,
             crate::kem::DhP384HkdfSha384
         );
+
+        test_into_correctness!(
+            test_into_correctness_aes128_p384,
+            AesGcm128,
+            crate::kem::DhP384HkdfSha384
+        );
+        test_into_correctness!(
+            test_into_correctness_chacha_p384,
+            ChaCha20Poly1305,
+            crate::kem::DhP384HkdfSha384
+        );
+    }
+
+    #[cfg(all(feature = "p521", any(feature = "alloc", feature = "std")))]
+    mod p521_tests {
+        use super::*;
+        use crate::{
+            aead::{Aead, AesGcm256, ExportOnlyAead, Seq},
+            kdf::HkdfSha256,
+            test_util::gen_ctx_simple_pair,
+        };
+
+        test_export_idempotence!(test_export_idempotence_p521, crate::kem::DhP521HkdfSha512);
+        test_exportonly_panics!(
+            test_exportonly_panics_p521_seal,
+            test_exportonly_panics_p521_open,
+            crate::kem::DhP521HkdfSha512
+        );
+        test_overflow!(test_overflow_p521, crate::kem::DhP521HkdfSha512);
+
+        test_ctx_correctness!(
+            test_ctx_correctness_aes128_p521,
+            AesGcm128,
+            crate::kem::DhP521HkdfSha512
+        );
+        test_ctx_correctness!(
+            test_ctx_correctness_aes256_p521,
+            AesGcm256,
+            crate::kem::DhP521HkdfSha512
+        );
+        test_ctx_correctness!(
+            test_ctx_correctness_chacha_p521,
+            ChaCha20Poly1305,
+            crate::kem::DhP521HkdfSha512
+        );
+
+        test_into_correctness!(
+            test_into_correctness_aes128_p521,
+            AesGcm128,
+            crate::kem::DhP521HkdfSha512
+        );
+        test_into_correctness!(
+            test_into_correctness_chacha_p521,
+            ChaCha20Poly1305,
+            crate::kem::DhP521HkdfSha512
+        );
     }
 
     /// Tests that Serialize::write_exact() panics when given a buffer of incorrect length
@@ -776,4 +3525,14 @@ mod test {
         let mut buf = [0u8; 17];
         tag.write_exact(&mut buf);
     }
+
+    /// Tests that the standard conversions agree with Serializable and Deserializable
+    #[test]
+    fn test_tag_conversions() {
+        let bytes: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let tag = AeadTag::<AesGcm128>::try_from(&bytes[..]).unwrap();
+        assert_eq!(tag.as_ref(), &bytes);
+        assert_eq!(tag.as_ref(), tag.to_bytes().as_slice());
+        assert!(AeadTag::<AesGcm128>::try_from(&bytes[..15]).is_err());
+    }
 }
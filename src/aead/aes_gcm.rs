@@ -8,6 +8,10 @@ impl Aead for AesGcm128 {
 
     // RFC 9180 §7.3: AES-128-GCM
     const AEAD_ID: u16 = 0x0001;
+
+    // This is the limit that the aes-gcm crate enforces
+    const MAX_PLAINTEXT_LEN: u64 = aes_gcm::P_MAX;
+    const MAX_AAD_LEN: u64 = aes_gcm::A_MAX;
 }
 
 /// The implementation of AES-256-GCM
@@ -18,4 +22,125 @@ impl Aead for AesGcm256 {
 
     // RFC 9180 §7.3: AES-256-GCM
     const AEAD_ID: u16 = 0x0002;
+
+    // This is the limit that the aes-gcm crate enforces
+    const MAX_PLAINTEXT_LEN: u64 = aes_gcm::P_MAX;
+    const MAX_AAD_LEN: u64 = aes_gcm::A_MAX;
+}
+
+/// How a primitive underlying AES-GCM is implemented on this machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AesGcmBackend {
+    /// CPU instructions, i.e., AES-NI and CLMUL on x86, or the ARMv8 Cryptography Extensions on
+    /// aarch64
+    Hardware,
+    /// A portable, constant-time software implementation. This is bitsliced for AES.
+    Software,
+}
+
+/// The backends that AES-GCM uses on this machine. See `aes_gcm_backends()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AesGcmBackends {
+    /// The backend of the AES block cipher
+    pub aes: AesGcmBackend,
+    /// The backend of the GHASH universal hash, i.e., carryless multiplication
+    pub ghash: AesGcmBackend,
+}
+
+// These mirror the detection logic in the aes and polyval crates. The CPU check is cached, so this
+// is cheap to call repeatedly.
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(aes_force_soft)))]
+cpufeatures::new!(aes_intrinsics, "aes");
+#[cfg(all(target_arch = "aarch64", aes_armv8, not(aes_force_soft)))]
+cpufeatures::new!(aes_intrinsics, "aes");
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    not(polyval_force_soft)
+))]
+cpufeatures::new!(clmul_intrinsics, "pclmulqdq");
+// On aarch64, the aes feature implies PMULL
+#[cfg(all(target_arch = "aarch64", polyval_armv8, not(polyval_force_soft)))]
+cpufeatures::new!(clmul_intrinsics, "aes");
+
+/// Returns which backends `AesGcm128` and `AesGcm256` use on this machine.
+///
+/// Hardware support is detected at runtime, and there is no runtime switch. To pin a backend, set
+/// `cfg` flags for the underlying `aes` and `polyval` crates when building, e.g.,
+/// `RUSTFLAGS="--cfg aes_force_soft --cfg polyval_force_soft"` to always use constant-time
+/// software. On aarch64, hardware support is only compiled in with
+/// `--cfg aes_armv8 --cfg polyval_armv8`. A service that requires hardware AES can check this once
+/// at startup.
+pub fn aes_gcm_backends() -> AesGcmBackends {
+    let aes = {
+        #[cfg(any(
+            all(any(target_arch = "x86", target_arch = "x86_64"), not(aes_force_soft)),
+            all(target_arch = "aarch64", aes_armv8, not(aes_force_soft))
+        ))]
+        let hw = aes_intrinsics::get();
+        #[cfg(not(any(
+            all(any(target_arch = "x86", target_arch = "x86_64"), not(aes_force_soft)),
+            all(target_arch = "aarch64", aes_armv8, not(aes_force_soft))
+        )))]
+        let hw = false;
+        hw
+    };
+    let ghash = {
+        #[cfg(any(
+            all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                not(polyval_force_soft)
+            ),
+            all(target_arch = "aarch64", polyval_armv8, not(polyval_force_soft))
+        ))]
+        let hw = clmul_intrinsics::get();
+        #[cfg(not(any(
+            all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                not(polyval_force_soft)
+            ),
+            all(target_arch = "aarch64", polyval_armv8, not(polyval_force_soft))
+        )))]
+        let hw = false;
+        hw
+    };
+
+    let backend = |hw| {
+        if hw {
+            AesGcmBackend::Hardware
+        } else {
+            AesGcmBackend::Software
+        }
+    };
+    AesGcmBackends {
+        aes: backend(aes),
+        ghash: backend(ghash),
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{aes_gcm_backends, AesGcmBackend};
+
+    /// Tests that the reported backends agree with what std detects
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_backends_match_cpu() {
+        let backends = aes_gcm_backends();
+
+        let expected_aes = if cfg!(aes_force_soft) || !std::is_x86_feature_detected!("aes") {
+            AesGcmBackend::Software
+        } else {
+            AesGcmBackend::Hardware
+        };
+        let expected_ghash =
+            if cfg!(polyval_force_soft) || !std::is_x86_feature_detected!("pclmulqdq") {
+                AesGcmBackend::Software
+            } else {
+                AesGcmBackend::Hardware
+            };
+        assert_eq!(backends.aes, expected_aes);
+        assert_eq!(backends.ghash, expected_ghash);
+    }
 }
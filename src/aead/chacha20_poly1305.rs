@@ -8,4 +8,10 @@ impl Aead for ChaCha20Poly1305 {
 
     // RFC 9180 §7.3: ChaCha20Poly1305
     const AEAD_ID: u16 = 0x0003;
+
+    // The chacha20poly1305 crate rejects messages of u32::MAX or more 64-byte blocks
+    const MAX_PLAINTEXT_LEN: u64 = (u32::MAX as u64) * 64 - 1;
+
+    // RFC 8439 §2.8 allows AAD of up to 2^64 - 1 bytes, and the crate doesn't limit it further
+    const MAX_AAD_LEN: u64 = u64::MAX;
 }
@@ -13,9 +13,10 @@ use generic_array::typenum;
 pub struct EmptyAeadImpl;
 
 impl BaseAeadCore for EmptyAeadImpl {
-    // The nonce size has to be bigger than the sequence size (currently u64), otherwise we get an
-    // underflow error on seal()/open() before we can even panic
-    type NonceSize = typenum::U128;
+    // The nonce size has to be at least the sequence size (currently u64), otherwise we get an
+    // underflow error on seal()/open() before we can even panic. Any more would be stored in every
+    // context for nothing.
+    type NonceSize = typenum::U8;
     type TagSize = typenum::U0;
     type CiphertextOverhead = typenum::U0;
 }
@@ -62,4 +63,8 @@ impl Aead for ExportOnlyAead {
 
     // RFC 9180 §7.3: Export-only
     const AEAD_ID: u16 = 0xFFFF;
+
+    // Nothing can be sealed
+    const MAX_PLAINTEXT_LEN: u64 = 0;
+    const MAX_AAD_LEN: u64 = 0;
 }
@@ -0,0 +1,280 @@
+//! Decapsulating with private keys that live in another process, in the style of `ssh-agent`.
+//!
+//! An agent is a separate, hardened process that holds the recipient's private keys and does
+//! nothing but decapsulate. The application sends it an encapsulated key, gets back the shared
+//! secret, and runs the rest of the key schedule itself with
+//! [`AgentClient::setup_receiver`]. The private keys never leave the agent, though each shared
+//! secret does, so the channel to the agent must be private, e.g., a Unix socket only the
+//! application can open.
+//!
+//! The agent side is [`serve_request`], which answers one request from a
+//! [`RecipientKeyRing`]. How requests get to it, e.g., a socket and an accept loop, is up to the
+//! agent.
+//!
+//! Protocol
+//! ========
+//! Every message is a frame: a 4-byte big-endian length, then that many bytes of body. A request
+//! body is
+//!
+//! ```text
+//! 0x01 || I2OSP(kem_id, 2) || key_id || I2OSP(len(pk_sender_id), 2) || pk_sender_id || enc
+//! ```
+//!
+//! where `pk_sender_id` is empty outside of the Auth modes. A response body is a status byte,
+//! followed by the shared secret if the status is [`STATUS_OK`].
+
+use crate::{
+    aead::{Aead, AeadCtxR},
+    kdf::Kdf as KdfTrait,
+    kem::{Kem as KemTrait, SharedSecret},
+    keyring::RecipientKeyRing,
+    setup::setup_receiver_from_shared_secret,
+    util::enforce_equal_len,
+    Deserializable, HpkeError, OpModeR, Serializable,
+};
+
+/// The size of the length prefix that starts every frame
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+/// The type of a decapsulation request
+const REQUEST_DECAP: u8 = 0x01;
+
+/// The response status of a successful decapsulation
+pub const STATUS_OK: u8 = 0x00;
+/// The response status when the agent has no key with the requested key ID
+pub const STATUS_UNKNOWN_KEY: u8 = 0x01;
+/// The response status when decapsulation failed
+pub const STATUS_DECAP_FAILED: u8 = 0x02;
+/// The response status when the request was malformed, or for a KEM the agent doesn't serve
+pub const STATUS_BAD_REQUEST: u8 = 0x03;
+
+/// A connection to an agent
+pub trait AgentTransport {
+    /// Sends a request frame to the agent, and returns its response frame
+    fn round_trip(&mut self, request: &[u8]) -> Result<crate::Vec<u8>, HpkeError>;
+}
+
+/// The application's end of the agent protocol
+pub struct AgentClient<T: AgentTransport> {
+    transport: T,
+}
+
+impl<T: AgentTransport> AgentClient<T> {
+    /// Makes a client that talks to the agent over `transport`
+    pub fn new(transport: T) -> AgentClient<T> {
+        AgentClient { transport }
+    }
+
+    /// Asks the agent to decapsulate `encapped_key` with its key with ID `key_id`. `pk_sender_id`
+    /// is the sender's identity key in the Auth modes, and `None` otherwise.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(shared_secret)` on success. If the agent has no key with ID `key_id`, returns
+    /// `Err(HpkeError::UnknownKeyId)`. If decapsulation failed, returns
+    /// `Err(HpkeError::DecapError)`. If the agent refused the request, or its response is
+    /// malformed, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+    /// the transport.
+    pub fn decap<Kem: KemTrait>(
+        &mut self,
+        key_id: u8,
+        pk_sender_id: Option<&Kem::PublicKey>,
+        encapped_key: &Kem::EncappedKey,
+    ) -> Result<SharedSecret<Kem>, HpkeError> {
+        let pk_sender_id = pk_sender_id.map(|pk| pk.to_bytes());
+        let pk_sender_id = pk_sender_id.as_deref().unwrap_or(&[]);
+        let mut body = [REQUEST_DECAP].to_vec();
+        body.extend_from_slice(&Kem::KEM_ID.to_be_bytes());
+        body.push(key_id);
+        // Public keys are far shorter than 2^16 bytes
+        body.extend_from_slice(&(pk_sender_id.len() as u16).to_be_bytes());
+        body.extend_from_slice(pk_sender_id);
+        body.extend_from_slice(&encapped_key.to_bytes());
+
+        let response = self.transport.round_trip(&encode_frame(&body))?;
+        let body = decode_frame(&response).map_err(|_| HpkeError::ValidationError)?;
+        match body.split_first() {
+            Some((&STATUS_OK, shared_secret)) => {
+                let mut ss = SharedSecret::<Kem>::default();
+                enforce_equal_len(ss.0.len(), shared_secret.len())
+                    .map_err(|_| HpkeError::ValidationError)?;
+                ss.0.copy_from_slice(shared_secret);
+                Ok(ss)
+            }
+            Some((&STATUS_UNKNOWN_KEY, [])) => Err(HpkeError::UnknownKeyId),
+            Some((&STATUS_DECAP_FAILED, [])) => Err(HpkeError::DecapError),
+            _ => Err(HpkeError::ValidationError),
+        }
+    }
+
+    /// Does a [`setup_receiver`](crate::setup_receiver), with the decapsulation done by the
+    /// agent's key with ID `key_id`
+    ///
+    /// Return Value
+    /// ============
+    /// On success, returns a decryption context. The errors are those of `AgentClient::decap` and
+    /// `setup_receiver`.
+    pub fn setup_receiver<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+        &mut self,
+        key_id: u8,
+        mode: &OpModeR<Kem>,
+        encapped_key: &Kem::EncappedKey,
+        info: &[u8],
+    ) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError> {
+        let shared_secret = self.decap::<Kem>(key_id, mode.get_pk_sender_id(), encapped_key)?;
+        setup_receiver_from_shared_secret(mode, shared_secret, info)
+    }
+}
+
+/// Answers one request frame with the keys in `keys`, and returns the response frame. Errors are
+/// reported to the client in the response.
+pub fn serve_request<Kem: KemTrait>(
+    keys: &RecipientKeyRing<Kem>,
+    request: &[u8],
+) -> crate::Vec<u8> {
+    let body = match handle_decap(keys, request) {
+        Ok(shared_secret) => {
+            let mut body = [STATUS_OK].to_vec();
+            body.extend_from_slice(&shared_secret.0);
+            body
+        }
+        Err(HpkeError::UnknownKeyId) => [STATUS_UNKNOWN_KEY].to_vec(),
+        Err(HpkeError::DecapError) => [STATUS_DECAP_FAILED].to_vec(),
+        Err(_) => [STATUS_BAD_REQUEST].to_vec(),
+    };
+    encode_frame(&body)
+}
+
+/// Parses a request frame and does the decapsulation it asks for
+fn handle_decap<Kem: KemTrait>(
+    keys: &RecipientKeyRing<Kem>,
+    request: &[u8],
+) -> Result<SharedSecret<Kem>, HpkeError> {
+    let body = decode_frame(request)?;
+    let (header, rest) = split(body, 6)?;
+    if header[0] != REQUEST_DECAP || header[1..3] != Kem::KEM_ID.to_be_bytes() {
+        return Err(HpkeError::ValidationError);
+    }
+    let key_id = header[3];
+    let (pk_sender_id, encapped_key) =
+        split(rest, u16::from_be_bytes([header[4], header[5]]).into())?;
+
+    let pk_sender_id = match pk_sender_id {
+        [] => None,
+        pk => Some(Kem::PublicKey::from_bytes(pk)?),
+    };
+    let encapped_key = Kem::EncappedKey::from_bytes(encapped_key)?;
+    let sk_recip = keys.get(key_id).ok_or(HpkeError::UnknownKeyId)?;
+    Kem::decap(sk_recip, pk_sender_id.as_ref(), &encapped_key)
+}
+
+/// Splits `bytes` at `mid`, or fails if it's too short
+fn split(bytes: &[u8], mid: usize) -> Result<(&[u8], &[u8]), HpkeError> {
+    if bytes.len() < mid {
+        return Err(HpkeError::ValidationError);
+    }
+    Ok(bytes.split_at(mid))
+}
+
+/// Returns the total length of the frame that starts with the given header, i.e.,
+/// `FRAME_HEADER_SIZE` plus the length it encodes. Use this to know how much to read from the
+/// transport.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(frame_len)` on success. If `header` is shorter than `FRAME_HEADER_SIZE`, returns
+/// `Err(HpkeError::IncorrectInputLength(FRAME_HEADER_SIZE, header.len()))`.
+pub fn frame_len(header: &[u8]) -> Result<usize, HpkeError> {
+    let header: [u8; FRAME_HEADER_SIZE] = header
+        .get(..FRAME_HEADER_SIZE)
+        .and_then(|h| h.try_into().ok())
+        .ok_or(HpkeError::IncorrectInputLength(
+            FRAME_HEADER_SIZE,
+            header.len(),
+        ))?;
+    Ok(FRAME_HEADER_SIZE + u32::from_be_bytes(header) as usize)
+}
+
+/// Prefixes `body` with its length
+fn encode_frame(body: &[u8]) -> crate::Vec<u8> {
+    // Bodies are a few hundred bytes at most
+    let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(body);
+    frame
+}
+
+/// Returns the body of a whole frame
+fn decode_frame(frame: &[u8]) -> Result<&[u8], HpkeError> {
+    enforce_equal_len(frame_len(frame)?, frame.len())?;
+    Ok(&frame[FRAME_HEADER_SIZE..])
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256, setup_sender, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Passes requests straight to `serve_request`
+    struct InProcess<'a>(&'a RecipientKeyRing<Kem>);
+
+    impl AgentTransport for InProcess<'_> {
+        fn round_trip(&mut self, request: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+            Ok(serve_request(self.0, request))
+        }
+    }
+
+    /// Tests that contexts set up through the agent open what the sender seals, in Base and Auth
+    /// mode, and that errors make it back to the client
+    #[test]
+    fn test_agent() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (sk_sender, pk_sender) = Kem::gen_keypair(&mut csprng);
+        let mut keys = RecipientKeyRing::new();
+        keys.insert(5, sk_recip);
+        let mut client = AgentClient::new(InProcess(&keys));
+
+        for (mode_s, mode_r) in [
+            (OpModeS::Base, OpModeR::Base),
+            (
+                OpModeS::Auth((sk_sender, pk_sender.clone())),
+                OpModeR::Auth(pk_sender),
+            ),
+        ] {
+            let (enc, mut sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(&mode_s, &pk_recip, b"info", &mut csprng).unwrap();
+            let mut receiver_ctx = client
+                .setup_receiver::<A, Kdf, Kem>(5, &mode_r, &enc, b"info")
+                .unwrap();
+            let ct = sender_ctx.seal(b"from afar", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&ct, b"").unwrap(), b"from afar");
+        }
+
+        let (_, enc) = Kem::encap(&pk_recip, None, &mut csprng).unwrap();
+        assert_eq!(
+            client.decap::<Kem>(6, None, &enc).err(),
+            Some(HpkeError::UnknownKeyId)
+        );
+
+        // Malformed requests are refused
+        let mut request = encode_frame(&[REQUEST_DECAP, 0x00, 0x20, 5, 0, 0]);
+        assert_eq!(
+            serve_request(&keys, &request),
+            encode_frame(&[STATUS_BAD_REQUEST])
+        );
+        request.push(0);
+        assert_eq!(
+            serve_request(&keys, &request),
+            encode_frame(&[STATUS_BAD_REQUEST])
+        );
+        assert_eq!(frame_len(&request).unwrap(), request.len() - 1);
+    }
+}
@@ -0,0 +1,371 @@
+//! ASCII armor for envelopes, so sealed messages survive email, ticket trackers, and copy-paste.
+//!
+//! [`armor_envelope`] wraps the output of a single-shot seal, with the suite and the recipient's
+//! key ID, in PEM-like text, and [`dearmor_envelope`] parses it back:
+//!
+//! ```text
+//! -----BEGIN HPKE MESSAGE-----
+//! CQAgAAEAAxERERERERERERERERERERERERERERERERERERERERERIiIiIiIiIiIi
+//! IiIiIiIiIiIiIiI=
+//! =pX53
+//! -----END HPKE MESSAGE-----
+//! ```
+//!
+//! Format
+//! ======
+//! The body is the padded base64 (RFC 4648 §4) of `concat(I2OSP(key_id, 1), I2OSP(kem_id, 2),
+//! I2OSP(kdf_id, 2), I2OSP(aead_id, 2), enc, ciphertext)`, in lines of 64 characters, with the
+//! last line possibly shorter. The line after it is `=` followed by the base64 of the 3-byte
+//! CRC-24 of the decoded body, as in OpenPGP armor (RFC 4880 §6.1). The checksum catches mangled
+//! text early, with a clear error. It is not a MAC; the AEAD still authenticates the ciphertext.
+//!
+//! [`seal_armored`] also has the AEAD authenticate the first 7 bytes of the body, the key ID and
+//! suite, by prepending them to the AAD. Anyone can recompute the checksum, but not the tag.
+//!
+//! Parsing
+//! =======
+//! Parsing is strict, so every envelope has exactly one armored form. Whitespace before the first
+//! line and after the last is ignored, and lines may end in `\n` or `\r\n`. Anything else, e.g.,
+//! headers, blank lines, lines of other lengths, or misplaced padding, is rejected.
+
+use crate::{
+    aead::Aead,
+    encoding::{base64_decode_group, base64_encode_group},
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    single_shot_open, single_shot_seal, Deserializable, HpkeError, OpModeR, OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::string::String;
+
+/// The first line of an armored envelope
+pub const BEGIN_LINE: &str = "-----BEGIN HPKE MESSAGE-----";
+/// The last line of an armored envelope
+pub const END_LINE: &str = "-----END HPKE MESSAGE-----";
+
+/// The number of base64 characters in every body line but the last
+const LINE_LEN: usize = 64;
+/// The length of the key ID and the three algorithm IDs at the start of the body
+const HEADER_SIZE: usize = 7;
+
+/// Does a `single_shot_seal` of `plaintext` to `pk_recip`, in the Base mode, and armors the result
+/// as an envelope to the key with ID `key_id`. If `bind_header` is true, the key ID and suite are
+/// prepended to `aad`. It should be true unless the recipient doesn't expect that.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(armored)` on success. The errors are those of `single_shot_seal`.
+pub fn seal_armored<A, Kdf, Kem, R>(
+    key_id: u8,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    bind_header: bool,
+    csprng: &mut R,
+) -> Result<String, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    let aad = body_aad::<A, Kdf, Kem>(key_id, aad, bind_header);
+    let (encapped_key, ciphertext) = single_shot_seal::<A, Kdf, Kem, R>(
+        &OpModeS::Base,
+        pk_recip,
+        info,
+        plaintext,
+        &aad,
+        csprng,
+    )?;
+    Ok(armor_envelope::<A, Kdf, Kem>(
+        key_id,
+        &encapped_key,
+        &ciphertext,
+    ))
+}
+
+/// Parses an envelope armored by [`seal_armored`], and opens it with `sk_recip`, in the Base mode.
+/// `bind_header` must be what it was when the envelope was sealed. To find which key to open it
+/// with, call [`dearmor_envelope`] first.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)` on success. The errors are those of [`dearmor_envelope`] and
+/// `single_shot_open`.
+pub fn open_armored<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    sk_recip: &Kem::PrivateKey,
+    info: &[u8],
+    aad: &[u8],
+    bind_header: bool,
+    s: &str,
+) -> Result<crate::Vec<u8>, HpkeError> {
+    let (key_id, encapped_key, ciphertext) = dearmor_envelope::<A, Kdf, Kem>(s)?;
+    let aad = body_aad::<A, Kdf, Kem>(key_id, aad, bind_header);
+    single_shot_open::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_recip,
+        &encapped_key,
+        info,
+        &ciphertext,
+        &aad,
+    )
+}
+
+/// Armors the output of a `single_shot_seal` with the given suite, to the key with ID `key_id`.
+/// This doesn't bind the header; see [`seal_armored`].
+pub fn armor_envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    key_id: u8,
+    encapped_key: &Kem::EncappedKey,
+    ciphertext: &[u8],
+) -> String {
+    let mut body =
+        crate::Vec::with_capacity(HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE + ciphertext.len());
+    body.extend_from_slice(&header::<A, Kdf, Kem>(key_id));
+    body.extend_from_slice(&encapped_key.to_bytes());
+    body.extend_from_slice(ciphertext);
+
+    let mut chars = crate::Vec::with_capacity(4 * body.chunks(3).len());
+    for group in body.chunks(3) {
+        chars.extend_from_slice(&base64_encode_group(group));
+    }
+    let crc = crc24(&body).to_be_bytes();
+
+    let mut out = String::new();
+    out.push_str(BEGIN_LINE);
+    out.push('\n');
+    for line in chars.chunks(LINE_LEN) {
+        // The alphabet and the padding are ASCII
+        out.push_str(core::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push('=');
+    out.push_str(core::str::from_utf8(&base64_encode_group(&crc[1..])).unwrap());
+    out.push('\n');
+    out.push_str(END_LINE);
+    out.push('\n');
+    out
+}
+
+/// Parses an envelope armored by [`armor_envelope`]
+///
+/// Return Value
+/// ============
+/// Returns `Ok((key_id, encapped_key, ciphertext))` on success. If `s` isn't exactly an armored
+/// envelope (see the module docs), the checksum doesn't match, or the envelope is for a different
+/// suite, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `Kem::EncappedKey::from_bytes`.
+pub fn dearmor_envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    s: &str,
+) -> Result<(u8, Kem::EncappedKey, crate::Vec<u8>), HpkeError> {
+    let mut body = dearmor(s)?;
+    if body.len() < HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE {
+        return Err(HpkeError::ValidationError);
+    }
+    let key_id = body[0];
+    if body[..HEADER_SIZE] != header::<A, Kdf, Kem>(key_id) {
+        return Err(HpkeError::ValidationError);
+    }
+    let encapped_key =
+        Kem::EncappedKey::from_bytes(&body[HEADER_SIZE..HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE])?;
+    let ciphertext = body.split_off(HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE);
+    Ok((key_id, encapped_key, ciphertext))
+}
+
+/// Returns the first `HEADER_SIZE` bytes of the body of an envelope to the key with ID `key_id`
+fn header<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(key_id: u8) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[0] = key_id;
+    header[1..3].copy_from_slice(&Kem::KEM_ID.to_be_bytes());
+    header[3..5].copy_from_slice(&Kdf::KDF_ID.to_be_bytes());
+    header[5..7].copy_from_slice(&A::AEAD_ID.to_be_bytes());
+    header
+}
+
+/// Returns the AAD that an envelope to the key with ID `key_id` is sealed with: `aad`, after the
+/// header if `bind_header` is true
+fn body_aad<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    key_id: u8,
+    aad: &[u8],
+    bind_header: bool,
+) -> crate::Vec<u8> {
+    let mut buf = crate::Vec::with_capacity(HEADER_SIZE + aad.len());
+    if bind_header {
+        buf.extend_from_slice(&header::<A, Kdf, Kem>(key_id));
+    }
+    buf.extend_from_slice(aad);
+    buf
+}
+
+/// Returns the body of the armored text `s`, after checking its framing and checksum
+fn dearmor(s: &str) -> Result<crate::Vec<u8>, HpkeError> {
+    let mut lines = s
+        .trim()
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line));
+    if lines.next() != Some(BEGIN_LINE) || lines.next_back() != Some(END_LINE) {
+        return Err(HpkeError::ValidationError);
+    }
+    let checksum = lines
+        .next_back()
+        .and_then(|line| line.strip_prefix('='))
+        .ok_or(HpkeError::ValidationError)?;
+
+    // Every body line is full but the last, which can't be empty
+    let lines: crate::Vec<&str> = lines.collect();
+    let (last, full) = lines.split_last().ok_or(HpkeError::ValidationError)?;
+    if full.iter().any(|line| line.len() != LINE_LEN) || last.is_empty() || last.len() > LINE_LEN {
+        return Err(HpkeError::ValidationError);
+    }
+    let chars: crate::Vec<u8> = lines.iter().flat_map(|line| line.bytes()).collect();
+    if !chars.chunks_exact(4).remainder().is_empty() {
+        return Err(HpkeError::ValidationError);
+    }
+
+    // Only the last group can be padded. Its padding says how many bytes it holds.
+    let (quads, last_quad) = chars.split_at(chars.len() - 4);
+    let last_group_len = 3 - last_quad.iter().filter(|c| **c == b'=').count();
+    if last_group_len == 0 {
+        return Err(HpkeError::ValidationError);
+    }
+    let mut body = vec![0u8; 3 * (quads.len() / 4) + last_group_len];
+    let (groups, last_group) = body.split_at_mut(3 * (quads.len() / 4));
+    for (quad, group) in quads.chunks(4).zip(groups.chunks_mut(3)) {
+        base64_decode_group(quad, group)?;
+    }
+    base64_decode_group(last_quad, last_group)?;
+
+    let mut crc = [0u8; 3];
+    if checksum.len() != 4 {
+        return Err(HpkeError::ValidationError);
+    }
+    base64_decode_group(checksum.as_bytes(), &mut crc)?;
+    if crc24(&body).to_be_bytes()[1..] != crc {
+        return Err(HpkeError::ValidationError);
+    }
+    Ok(body)
+}
+
+/// Computes the CRC-24 of RFC 4880 §6.1
+fn crc24(bytes: &[u8]) -> u32 {
+    const INIT: u32 = 0x00b7_04ce;
+    const POLY: u32 = 0x0186_4cfb;
+
+    let mut crc = INIT;
+    for byte in bytes {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x0100_0000 != 0 {
+                crc ^= POLY;
+            }
+        }
+    }
+    crc & 0x00ff_ffff
+}
+
+#[cfg(all(test, feature = "std", feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::format;
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// The armor of key ID 9, an encapped key of 0x11s, and a ciphertext of twenty 0x22s, which
+    /// is also the example in the module docs
+    const EXAMPLE: &str = "-----BEGIN HPKE MESSAGE-----
+CQAgAAEAAxERERERERERERERERERERERERERERERERERERERERERIiIiIiIiIiIi
+IiIiIiIiIiIiIiI=
+=pX53
+-----END HPKE MESSAGE-----
+";
+
+    /// Tests the armor against a known answer, that it parses back, with CRLFs and surrounding
+    /// whitespace too, and that mangled, reflowed, and other-suite text is rejected
+    #[test]
+    fn test_armor_envelope() {
+        // The check value of CRC-24/OPENPGP
+        assert_eq!(crc24(b"123456789"), 0x21cf02);
+
+        let enc = <Kem as KemTrait>::EncappedKey::from_bytes(&[0x11; 32]).unwrap();
+        assert_eq!(armor_envelope::<A, Kdf, Kem>(9, &enc, &[0x22; 20]), EXAMPLE);
+
+        let crlf = EXAMPLE.replace('\n', "\r\n");
+        for s in [EXAMPLE, &crlf, &format!("\n  {}\n\n", EXAMPLE)] {
+            let (key_id, decoded_enc, ct) = dearmor_envelope::<A, Kdf, Kem>(s).unwrap();
+            assert_eq!((key_id, &ct[..]), (9, &[0x22; 20][..]));
+            assert_eq!(decoded_enc.to_bytes(), enc.to_bytes());
+        }
+
+        let mangled = [
+            // A flipped character, which the checksum catches
+            EXAMPLE.replacen("CQAg", "CQAh", 1),
+            // A wrong checksum
+            EXAMPLE.replace("=pX53", "=pX54"),
+            // The body reflowed into shorter lines
+            EXAMPLE.replacen("IiIiIiIi\nIiIi", "IiIi\nIiIiIiIi", 1),
+            EXAMPLE.replacen("IiIi\nIiIi", "IiIiIiIi\n", 1),
+            // A header and a blank line
+            EXAMPLE.replacen("-----\n", "-----\nVersion: 1\n\n", 1),
+            // A missing checksum
+            EXAMPLE.replace("=pX53\n", ""),
+            // A different label
+            EXAMPLE.replace("HPKE MESSAGE", "PGP MESSAGE"),
+        ];
+        for s in mangled.iter() {
+            assert_eq!(
+                dearmor_envelope::<A, Kdf, Kem>(s).err(),
+                Some(HpkeError::ValidationError),
+                "{}",
+                s
+            );
+        }
+        assert!(dearmor_envelope::<crate::aead::AesGcm128, Kdf, Kem>(EXAMPLE).is_err());
+    }
+
+    /// Tests that a sealed envelope opens, that re-armoring it with another key ID, and a fixed-up
+    /// checksum, makes it fail to open when the header is bound but not when it isn't, and that
+    /// both ends must agree on binding
+    #[test]
+    fn test_armored_header_binding() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let open = |s: &str, bind_header| {
+            open_armored::<A, Kdf, Kem>(&sk, b"info", b"aad", bind_header, s)
+        };
+
+        for bind_header in [true, false] {
+            let armored = seal_armored::<A, Kdf, Kem, _>(
+                9,
+                &pk,
+                b"info",
+                b"msg",
+                b"aad",
+                bind_header,
+                &mut csprng,
+            )
+            .unwrap();
+            assert_eq!(open(&armored, bind_header).unwrap(), b"msg");
+            assert_eq!(open(&armored, !bind_header), Err(HpkeError::OpenError));
+
+            let (key_id, enc, ct) = dearmor_envelope::<A, Kdf, Kem>(&armored).unwrap();
+            let tampered = armor_envelope::<A, Kdf, Kem>(key_id ^ 0x01, &enc, &ct);
+            if bind_header {
+                assert_eq!(open(&tampered, true), Err(HpkeError::OpenError));
+            } else {
+                assert_eq!(open(&tampered, false).unwrap(), b"msg");
+            }
+        }
+    }
+}
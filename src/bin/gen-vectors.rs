@@ -0,0 +1,163 @@
+//! Generates HPKE test vectors in the RFC 9180 JSON format. Run with `--help` for usage.
+
+use hpke::{
+    rand_core::{OsRng, RngCore},
+    testing::vectors::{gen_test_vectors_from_seed, SuiteFilter},
+};
+
+use std::{fs::File, io::Write, process::exit};
+
+const USAGE: &str = "\
+Usage: gen-vectors [OPTIONS]
+
+Generates HPKE test vectors for every compiled ciphersuite and mode, and writes them as a JSON
+array in the format of the RFC 9180 test vectors.
+
+Options:
+  --kem <LIST>     Only generate vectors for these KEMs
+  --kdf <LIST>     Only generate vectors for these KDFs
+  --aead <LIST>    Only generate vectors for these AEADs
+  --mode <LIST>    Only generate vectors for these modes
+  --seed <HEX>     Seed the RNG with this 32-byte hex string. The same seed and filters always
+                   produce the same vectors. If omitted, a random seed is used and printed.
+  --rfc-seqs       Only keep the encryptions at the sequence numbers the RFC lists, i.e., 0, 1, 2,
+                   4, 255, and 256. Each gets a non-standard \"seq\" field.
+  --negative       Also generate tampered inputs that the receiver must reject. These go in a
+                   non-standard \"negative\" field of each vector.
+  --out <PATH>     Write to PATH instead of stdout
+  --help           Print this message
+
+A LIST is a comma-separated list of algorithm identifiers, in decimal or 0x-prefixed hex, or
+names. The names are:
+  KEMs:   x25519, p256, p384, p521
+  KDFs:   sha256, sha384, sha512
+  AEADs:  aes128gcm, aes256gcm, chacha20poly1305, exportonly
+  Modes:  base, psk, auth, authpsk";
+
+/// Parses an algorithm identifier, either numerically or by looking it up in `names`
+fn parse_id(s: &str, names: &[(&str, u16)]) -> Result<u16, String> {
+    if let Some(&(_, id)) = names.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+        return Ok(id);
+    }
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("unrecognized identifier \"{}\"", s))
+}
+
+/// Parses a comma-separated list of algorithm identifiers
+fn parse_list(s: &str, names: &[(&str, u16)]) -> Result<Vec<u16>, String> {
+    s.split(',').map(|id| parse_id(id.trim(), names)).collect()
+}
+
+const KEM_NAMES: &[(&str, u16)] = &[
+    ("x25519", 0x0020),
+    ("p256", 0x0010),
+    ("p384", 0x0011),
+    ("p521", 0x0012),
+];
+const KDF_NAMES: &[(&str, u16)] = &[("sha256", 0x0001), ("sha384", 0x0002), ("sha512", 0x0003)];
+const AEAD_NAMES: &[(&str, u16)] = &[
+    ("aes128gcm", 0x0001),
+    ("aes256gcm", 0x0002),
+    ("chacha20poly1305", 0x0003),
+    ("exportonly", 0xffff),
+];
+const MODE_NAMES: &[(&str, u16)] = &[("base", 0), ("psk", 1), ("auth", 2), ("authpsk", 3)];
+
+struct Args {
+    filter: SuiteFilter,
+    seed: Option<[u8; 32]>,
+    out: Option<String>,
+}
+
+/// Parses a 32-byte seed from a hex string
+fn parse_seed(s: &str) -> Result<[u8; 32], String> {
+    let mut seed = [0u8; 32];
+    hex::decode_to_slice(s, &mut seed)
+        .map_err(|_| format!("seed must be 64 hex characters, got \"{}\"", s))?;
+    Ok(seed)
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut filter = SuiteFilter::default();
+    let mut seed = None;
+    let mut out = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        if flag == "--help" || flag == "-h" {
+            println!("{}", USAGE);
+            exit(0);
+        }
+        if flag == "--rfc-seqs" {
+            filter.rfc_seqs_only = true;
+            continue;
+        }
+        if flag == "--negative" {
+            filter.negative_cases = true;
+            continue;
+        }
+
+        let val = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--kem" => filter.kem_ids = parse_list(&val, KEM_NAMES)?,
+            "--kdf" => filter.kdf_ids = parse_list(&val, KDF_NAMES)?,
+            "--aead" => filter.aead_ids = parse_list(&val, AEAD_NAMES)?,
+            "--mode" => {
+                let modes = parse_list(&val, MODE_NAMES)?;
+                filter.modes = modes
+                    .into_iter()
+                    .map(|m| match m {
+                        0..=3 => Ok(m as u8),
+                        _ => Err(format!("invalid mode {}", m)),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "--seed" => seed = Some(parse_seed(&val)?),
+            "--out" => out = Some(val),
+            _ => return Err(format!("unrecognized option {}", flag)),
+        }
+    }
+
+    Ok(Args { filter, seed, out })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {}\n\n{}", e, USAGE);
+        exit(2);
+    });
+
+    // Always generate from a seed, so that every run is reproducible
+    let seed = args.seed.unwrap_or_else(|| {
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut seed);
+        seed
+    });
+    eprintln!("Using seed {}", hex::encode(seed));
+
+    let tvs = gen_test_vectors_from_seed(&args.filter, seed);
+    if tvs.is_empty() {
+        eprintln!("error: no compiled ciphersuite matches the given filters");
+        exit(1);
+    }
+    let json = serde_json::to_string_pretty(&tvs).expect("couldn't serialize test vectors");
+
+    match args.out {
+        Some(path) => {
+            let mut file = File::create(&path).unwrap_or_else(|e| {
+                eprintln!("error: couldn't create {}: {}", path, e);
+                exit(1);
+            });
+            file.write_all(json.as_bytes())
+                .and_then(|_| file.write_all(b"\n"))
+                .expect("couldn't write test vectors");
+            eprintln!("Wrote {} test vectors to {}", tvs.len(), path);
+        }
+        None => println!("{}", json),
+    }
+}
@@ -0,0 +1,465 @@
+//! Deterministic CBOR encodings of envelopes, key configs, and fanout entries, for IoT and COSE
+//! deployments.
+//!
+//! Everything is encoded as a CBOR array, as COSE does, in the core deterministic encoding of RFC
+//! 8949 §4.2.1: integers and lengths in their shortest form, and no indefinite lengths. Decoding
+//! is strict. It accepts only that encoding, with nothing after it, so every value has exactly one
+//! encoding and can be compared or hashed as bytes.
+//!
+//! Formats
+//! =======
+//! In CDDL (RFC 8610), with algorithm IDs being the RFC 9180 code points:
+//!
+//! ```text
+//! envelope = [kem_id: uint, kdf_id: uint, aead_id: uint, key_id: uint .size 1,
+//!             enc: bstr, ciphertext: bstr]
+//! key_config = [key_id: uint .size 1, kem_id: uint, kdf_id: uint, aead_id: uint,
+//!               public_key: bstr, not_after: uint]
+//! fanout = [* [recipient_id: bstr, enc: bstr, ciphertext: bstr]]
+//! ```
+//!
+//! [`seal_envelope`] binds an envelope's header, i.e., its encoding up to `enc`, to the
+//! ciphertext, by prepending it to the AAD. Changing the suite or key ID then makes the envelope
+//! fail to open, rather than be misread.
+
+use crate::{
+    aead::Aead, fanout::FanoutEntry, kdf::Kdf as KdfTrait, kem::Kem as KemTrait,
+    keyring::KeyConfig, single_shot_open, single_shot_seal, Deserializable, HpkeError, OpModeR,
+    OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+
+/// Major type 0: an unsigned integer
+const MAJOR_UINT: u8 = 0;
+/// Major type 2: a byte string
+const MAJOR_BYTES: u8 = 2;
+/// Major type 4: an array
+const MAJOR_ARRAY: u8 = 4;
+
+/// Does a `single_shot_seal` of `plaintext` to `pk_recip`, in the Base mode, and encodes the
+/// result as an envelope to the key with ID `key_id`. If `bind_header` is true, the envelope's
+/// header is prepended to `aad`. It should be true unless the recipient doesn't expect that.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(envelope)` on success. The errors are those of `single_shot_seal`.
+pub fn seal_envelope<A, Kdf, Kem, R>(
+    key_id: u8,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    bind_header: bool,
+    csprng: &mut R,
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    let aad = envelope_aad::<A, Kdf, Kem>(key_id, aad, bind_header);
+    let (encapped_key, ciphertext) = single_shot_seal::<A, Kdf, Kem, R>(
+        &OpModeS::Base,
+        pk_recip,
+        info,
+        plaintext,
+        &aad,
+        csprng,
+    )?;
+    Ok(encode_envelope::<A, Kdf, Kem>(
+        key_id,
+        &encapped_key,
+        &ciphertext,
+    ))
+}
+
+/// Decodes an envelope sealed by [`seal_envelope`], and opens it with `sk_recip`, in the Base
+/// mode. `bind_header` must be what it was when the envelope was sealed. To find which key to
+/// open it with, call [`decode_envelope`] first.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)` on success. The errors are those of [`decode_envelope`] and
+/// `single_shot_open`.
+pub fn open_envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    sk_recip: &Kem::PrivateKey,
+    info: &[u8],
+    aad: &[u8],
+    bind_header: bool,
+    envelope: &[u8],
+) -> Result<crate::Vec<u8>, HpkeError> {
+    let (key_id, encapped_key, ciphertext) = decode_envelope::<A, Kdf, Kem>(envelope)?;
+    let aad = envelope_aad::<A, Kdf, Kem>(key_id, aad, bind_header);
+    single_shot_open::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_recip,
+        &encapped_key,
+        info,
+        &ciphertext,
+        &aad,
+    )
+}
+
+/// Encodes the output of a `single_shot_seal` with the given suite, to the key with ID `key_id`.
+/// This doesn't bind the header; see [`seal_envelope`].
+pub fn encode_envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    key_id: u8,
+    encapped_key: &Kem::EncappedKey,
+    ciphertext: &[u8],
+) -> crate::Vec<u8> {
+    let mut buf = envelope_header::<A, Kdf, Kem>(key_id);
+    put_bytes(&mut buf, &encapped_key.to_bytes());
+    put_bytes(&mut buf, ciphertext);
+    buf
+}
+
+/// Decodes an envelope encoded by [`encode_envelope`]
+///
+/// Return Value
+/// ============
+/// Returns `Ok((key_id, encapped_key, ciphertext))` on success. If `bytes` isn't a deterministic
+/// encoding of an envelope, or the envelope is for a different suite, returns
+/// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `Kem::EncappedKey::from_bytes`.
+pub fn decode_envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    bytes: &[u8],
+) -> Result<(u8, Kem::EncappedKey, crate::Vec<u8>), HpkeError> {
+    let mut r = Reader(bytes);
+    r.array_of(6)?;
+    for expected_id in [Kem::KEM_ID, Kdf::KDF_ID, A::AEAD_ID] {
+        if r.uint()? != u64::from(expected_id) {
+            return Err(HpkeError::ValidationError);
+        }
+    }
+    let key_id = r.uint_as::<u8>()?;
+    let encapped_key = Kem::EncappedKey::from_bytes(r.bytes()?)?;
+    let ciphertext = r.bytes()?.to_vec();
+    r.finish()?;
+    Ok((key_id, encapped_key, ciphertext))
+}
+
+/// Returns the encoding of an envelope to the key with ID `key_id`, up to its `enc`
+fn envelope_header<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(key_id: u8) -> crate::Vec<u8> {
+    let mut buf = crate::Vec::new();
+    put_head(&mut buf, MAJOR_ARRAY, 6);
+    put_head(&mut buf, MAJOR_UINT, Kem::KEM_ID.into());
+    put_head(&mut buf, MAJOR_UINT, Kdf::KDF_ID.into());
+    put_head(&mut buf, MAJOR_UINT, A::AEAD_ID.into());
+    put_head(&mut buf, MAJOR_UINT, key_id.into());
+    buf
+}
+
+/// Returns the AAD that an envelope to the key with ID `key_id` is sealed with: `aad`, after the
+/// envelope's header if `bind_header` is true
+fn envelope_aad<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    key_id: u8,
+    aad: &[u8],
+    bind_header: bool,
+) -> crate::Vec<u8> {
+    if !bind_header {
+        return aad.to_vec();
+    }
+    let mut buf = envelope_header::<A, Kdf, Kem>(key_id);
+    buf.extend_from_slice(aad);
+    buf
+}
+
+/// Encodes a recipient config
+pub fn encode_key_config<Kem: KemTrait>(config: &KeyConfig<Kem>) -> crate::Vec<u8> {
+    let mut buf = crate::Vec::new();
+    put_head(&mut buf, MAJOR_ARRAY, 6);
+    put_head(&mut buf, MAJOR_UINT, config.key_id.into());
+    put_head(&mut buf, MAJOR_UINT, Kem::KEM_ID.into());
+    put_head(&mut buf, MAJOR_UINT, config.kdf_id.into());
+    put_head(&mut buf, MAJOR_UINT, config.aead_id.into());
+    put_bytes(&mut buf, &config.pk.to_bytes());
+    put_head(&mut buf, MAJOR_UINT, config.not_after);
+    buf
+}
+
+/// Decodes a recipient config encoded by [`encode_key_config`]. The config may be for any suite
+/// of `Kem`.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(config)` on success. If `bytes` isn't a deterministic encoding of a config, or the
+/// config is for a different KEM, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors
+/// are those of `Kem::PublicKey::from_bytes`.
+pub fn decode_key_config<Kem: KemTrait>(bytes: &[u8]) -> Result<KeyConfig<Kem>, HpkeError> {
+    let mut r = Reader(bytes);
+    r.array_of(6)?;
+    let key_id = r.uint_as::<u8>()?;
+    if r.uint()? != u64::from(Kem::KEM_ID) {
+        return Err(HpkeError::ValidationError);
+    }
+    let kdf_id = r.uint_as::<u16>()?;
+    let aead_id = r.uint_as::<u16>()?;
+    let pk = Kem::PublicKey::from_bytes(r.bytes()?)?;
+    let not_after = r.uint()?;
+    r.finish()?;
+    Ok(KeyConfig {
+        key_id,
+        kdf_id,
+        aead_id,
+        pk,
+        not_after,
+    })
+}
+
+/// Encodes the entries `fanout_seal` returns
+pub fn encode_fanout<Kem: KemTrait>(entries: &[FanoutEntry<Kem>]) -> crate::Vec<u8> {
+    let mut buf = crate::Vec::new();
+    put_head(&mut buf, MAJOR_ARRAY, entries.len() as u64);
+    for entry in entries {
+        put_head(&mut buf, MAJOR_ARRAY, 3);
+        put_bytes(&mut buf, &entry.recipient_id);
+        put_bytes(&mut buf, &entry.encapped_key.to_bytes());
+        put_bytes(&mut buf, &entry.ciphertext);
+    }
+    buf
+}
+
+/// Decodes entries encoded by [`encode_fanout`]
+///
+/// Return Value
+/// ============
+/// Returns `Ok(entries)` on success. If `bytes` isn't a deterministic encoding of a list of
+/// entries, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `Kem::EncappedKey::from_bytes`.
+pub fn decode_fanout<Kem: KemTrait>(
+    bytes: &[u8],
+) -> Result<crate::Vec<FanoutEntry<Kem>>, HpkeError> {
+    let mut r = Reader(bytes);
+    let num_entries = r.head(MAJOR_ARRAY)?;
+    let mut entries = crate::Vec::new();
+    for _ in 0..num_entries {
+        r.array_of(3)?;
+        let recipient_id = r.bytes()?.to_vec();
+        let encapped_key = Kem::EncappedKey::from_bytes(r.bytes()?)?;
+        let ciphertext = r.bytes()?.to_vec();
+        entries.push(FanoutEntry {
+            recipient_id,
+            encapped_key,
+            ciphertext,
+        });
+    }
+    r.finish()?;
+    Ok(entries)
+}
+
+/// Appends the head of an item of the given major type, with `value` in its shortest form
+fn put_head(buf: &mut crate::Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => buf.push(major | value as u8),
+        24..=0xff => buf.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            buf.push(major | 25);
+            buf.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(major | 26);
+            buf.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(major | 27);
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Appends a byte string
+fn put_bytes(buf: &mut crate::Vec<u8>, bytes: &[u8]) {
+    put_head(buf, MAJOR_BYTES, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Reads items off the front of a buffer, accepting only the deterministic encoding
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Splits `len` bytes off the front
+    fn take(&mut self, len: usize) -> Result<&'a [u8], HpkeError> {
+        if self.0.len() < len {
+            return Err(HpkeError::ValidationError);
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    /// Reads the head of an item of the given major type, and returns its argument
+    fn head(&mut self, major: u8) -> Result<u64, HpkeError> {
+        let initial = self.take(1)?[0];
+        if initial >> 5 != major {
+            return Err(HpkeError::ValidationError);
+        }
+        let (arg_len, min) = match initial & 0x1f {
+            info @ 0..=23 => return Ok(info.into()),
+            24 => (1, 24),
+            25 => (2, 0x100),
+            26 => (4, 0x1_0000),
+            27 => (8, 0x1_0000_0000),
+            // Reserved values and indefinite lengths
+            _ => return Err(HpkeError::ValidationError),
+        };
+        let value = self
+            .take(arg_len)?
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+        // Anything that fits in a shorter form must use it
+        if value < min {
+            return Err(HpkeError::ValidationError);
+        }
+        Ok(value)
+    }
+
+    /// Reads the head of an array, which must have `len` items
+    fn array_of(&mut self, len: u64) -> Result<(), HpkeError> {
+        if self.head(MAJOR_ARRAY)? != len {
+            return Err(HpkeError::ValidationError);
+        }
+        Ok(())
+    }
+
+    fn uint(&mut self) -> Result<u64, HpkeError> {
+        self.head(MAJOR_UINT)
+    }
+
+    /// Reads an unsigned integer, which must fit in a `T`
+    fn uint_as<T: TryFrom<u64>>(&mut self) -> Result<T, HpkeError> {
+        T::try_from(self.uint()?).map_err(|_| HpkeError::ValidationError)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], HpkeError> {
+        let len = self.head(MAJOR_BYTES)?;
+        self.take(usize::try_from(len).map_err(|_| HpkeError::ValidationError)?)
+    }
+
+    /// Checks that nothing is left
+    fn finish(self) -> Result<(), HpkeError> {
+        if !self.0.is_empty() {
+            return Err(HpkeError::ValidationError);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305, fanout::fanout_seal, kdf::HkdfSha256, kem::X25519HkdfSha256,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests the encoding of heads against RFC 8949 Appendix A, and that non-shortest and
+    /// indefinite-length forms are refused
+    #[test]
+    fn test_heads() {
+        for (value, encoding) in [
+            (0, &[0x00][..]),
+            (23, &[0x17]),
+            (24, &[0x18, 0x18]),
+            (1000, &[0x19, 0x03, 0xe8]),
+            (1000000, &[0x1a, 0x00, 0x0f, 0x42, 0x40]),
+            (
+                1000000000000,
+                &[0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00],
+            ),
+        ] {
+            let mut buf = crate::Vec::new();
+            put_head(&mut buf, MAJOR_UINT, value);
+            assert_eq!(buf, encoding);
+            assert_eq!(Reader(encoding).uint(), Ok(value));
+        }
+
+        for bad in [
+            &[0x18, 0x17][..],
+            &[0x19, 0x00, 0xff],
+            &[0x9f],
+            &[0x1c],
+            &[0x18],
+        ] {
+            assert!(Reader(bad).head(bad[0] >> 5).is_err());
+        }
+    }
+
+    /// Tests round trips of envelopes, key configs, and fanout entries, and that trailing bytes and
+    /// other suites are refused
+    #[test]
+    fn test_cbor_round_trips() {
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk) = Kem::gen_keypair(&mut csprng);
+        let (_, enc) = Kem::encap(&pk, None, &mut csprng).unwrap();
+
+        let encoded = encode_envelope::<A, Kdf, Kem>(7, &enc, b"ct");
+        // [32, 1, 3, 7, h'<32 bytes>', h'6374']
+        assert_eq!(encoded[..6], [0x86, 0x18, 0x20, 0x01, 0x03, 0x07]);
+        let (key_id, decoded_enc, ct) = decode_envelope::<A, Kdf, Kem>(&encoded).unwrap();
+        assert_eq!((key_id, ct.as_slice()), (7, &b"ct"[..]));
+        assert_eq!(decoded_enc.to_bytes(), enc.to_bytes());
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(decode_envelope::<A, Kdf, Kem>(&trailing).is_err());
+        assert!(decode_envelope::<crate::aead::AesGcm128, Kdf, Kem>(&encoded).is_err());
+
+        let config = KeyConfig::<Kem>::new::<A, Kdf>(3, pk.clone(), 1 << 40);
+        let decoded = decode_key_config::<Kem>(&encode_key_config(&config)).unwrap();
+        assert!(decoded.is_suite::<A, Kdf>());
+        assert_eq!((decoded.key_id, decoded.not_after), (3, 1 << 40));
+        assert_eq!(decoded.pk, pk);
+
+        let entries =
+            fanout_seal::<A, Kdf, Kem, _>(&[(b"a", &pk), (b"b", &pk)], b"g", b"s", &mut csprng)
+                .unwrap();
+        let encoded = encode_fanout(&entries);
+        let decoded = decode_fanout::<Kem>(&encoded).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].recipient_id, b"b");
+        assert_eq!(decoded[1].ciphertext, entries[1].ciphertext);
+        assert!(decode_fanout::<Kem>(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    /// Tests that a sealed envelope opens, that changing its key ID makes it fail to open when the
+    /// header is bound but not when it isn't, and that both ends must agree on binding
+    #[test]
+    fn test_envelope_header_binding() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let open = |envelope: &[u8], bind_header| {
+            open_envelope::<A, Kdf, Kem>(&sk, b"info", b"aad", bind_header, envelope)
+        };
+
+        for bind_header in [true, false] {
+            let mut envelope = seal_envelope::<A, Kdf, Kem, _>(
+                7,
+                &pk,
+                b"info",
+                b"msg",
+                b"aad",
+                bind_header,
+                &mut csprng,
+            )
+            .unwrap();
+            assert_eq!(open(&envelope, bind_header).unwrap(), b"msg");
+            assert_eq!(open(&envelope, !bind_header), Err(HpkeError::OpenError));
+
+            // Key ID 7 becomes 6
+            envelope[5] ^= 0x01;
+            assert_eq!(decode_envelope::<A, Kdf, Kem>(&envelope).unwrap().0, 6);
+            if bind_header {
+                assert_eq!(open(&envelope, true), Err(HpkeError::OpenError));
+            } else {
+                assert_eq!(open(&envelope, false).unwrap(), b"msg");
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{kdf::Kdf as KdfTrait, util::KemSuiteId, Deserializable, Serializable};
 
 use core::fmt::Debug;
+use subtle::ConstantTimeEq;
 
 // This is the maximum value of all of Npk, Ndh, and Nenc. It's achieved by P-521 in RFC 9180 §7.1
 // Table 2.
@@ -21,7 +22,7 @@ pub trait DhKeyExchange {
 
     /// The key exchange's private key type. If you want to generate a keypair, see
     /// `Kem::gen_keypair` or `Kem::derive_keypair`
-    type PrivateKey: Clone + Serializable + Deserializable;
+    type PrivateKey: Clone + PartialEq + Eq + ConstantTimeEq + Serializable + Deserializable;
 
     /// The result of a DH operation
     #[doc(hidden)]
@@ -35,12 +36,20 @@ pub trait DhKeyExchange {
     #[doc(hidden)]
     fn dh(sk: &Self::PrivateKey, pk: &Self::PublicKey) -> Result<Self::KexResult, DhError>;
 
+    /// Returns whether `pk` generates a small subgroup. DH with such a key gives a result that
+    /// doesn't depend on the secret key, so it's never a valid encapsulated key.
+    #[doc(hidden)]
+    fn is_small_order(pk: &Self::PublicKey) -> bool;
+
     /// Computes a keypair given key material `ikm` of sufficient entropy. See
-    /// [`crate::kem::Kem::derive_keypair`] for discussion of entropy.
+    /// [`crate::kem::Kem::derive_keypair`] for discussion of entropy. A nonempty `domain` is
+    /// appended to the `info` of every expansion, as in `Kem::derive_keypair_with_domain`. An
+    /// empty one gives RFC 9180's `DeriveKeyPair`.
     #[doc(hidden)]
     fn derive_keypair<Kdf: KdfTrait>(
         suite_id: &KemSuiteId,
         ikm: &[u8],
+        domain: &[u8],
     ) -> (Self::PrivateKey, Self::PublicKey);
 }
 
@@ -86,6 +86,9 @@ macro_rules! nistp_dhkex {
                 }
             }
 
+            impl_hex_display_fromstr!(PublicKey);
+            impl_try_from_bytes!(PublicKey);
+
             impl Serializable for PrivateKey {
                 type OutputSize = $privkey_size;
 
@@ -104,10 +107,13 @@ macro_rules! nistp_dhkex {
                     enforce_equal_len(Self::OutputSize::to_usize(), encoded.len())?;
 
                     // * Invariant: PrivateKey is in [1,p). This is preserved here.
-                    // * SecretKey::from_be_bytes() directly checks that the value isn't zero. And
+                    // * SecretKey::from_bytes() directly checks that the value isn't zero. And
                     //   its submethod,
-                    // * ScalarCore::from_be_bytes() checks that the value doesn't exceed the
+                    // * ScalarPrimitive::from_bytes() checks that the value doesn't exceed the
                     //   modulus.
+                    // Both checks are constant time (ct_lt and ct_eq on the integer), and the
+                    // scalar is never reduced. The only branch is on whether the key is valid,
+                    // which the caller learns anyway. The timing_tests module checks this.
                     let sk = curve_crate::SecretKey::from_bytes(encoded.into())
                         .map_err(|_| HpkeError::ValidationError)?;
 
@@ -115,6 +121,8 @@ macro_rules! nistp_dhkex {
                 }
             }
 
+            impl_try_from_bytes!(PrivateKey);
+
             // DH results are serialized in the same way as public keys
             impl Serializable for KexResult {
                 // RFC 9180 §4.1
@@ -173,6 +181,13 @@ macro_rules! nistp_dhkex {
                     Ok(KexResult(dh_res))
                 }
 
+                /// Always false. The NIST curves have prime order, so the only small subgroup is
+                /// the identity, which we never deserialize.
+                #[doc(hidden)]
+                fn is_small_order(_: &PublicKey) -> bool {
+                    false
+                }
+
                 // RFC 9180 §7.1.3:
                 // def DeriveKeyPair(ikm):
                 //   dkp_prk = LabeledExtract("", "dkp_prk", ikm)
@@ -189,13 +204,14 @@ macro_rules! nistp_dhkex {
                 //   return (sk, pk(sk))
                 // where `bitmask` is defined to be 0xFF for P-256 and P-384, and 0x01 for P-521
 
-                /// Deterministically derives a keypair from the given input keying material and
-                /// ciphersuite ID. The keying material SHOULD have as many bits of entropy as the
-                /// bit length of a secret key
+                /// Deterministically derives a keypair from the given input keying material,
+                /// ciphersuite ID, and domain. The keying material SHOULD have as many bits of
+                /// entropy as the bit length of a secret key
                 #[doc(hidden)]
                 fn derive_keypair<Kdf: KdfTrait>(
                     suite_id: &KemSuiteId,
                     ikm: &[u8],
+                    domain: &[u8],
                 ) -> (PrivateKey, PublicKey) {
                     // Write the label into a byte buffer and extract from the IKM
                     let (_, hkdf_ctx) = labeled_extract::<Kdf>(&[], suite_id, b"dkp_prk", ikm);
@@ -209,9 +225,15 @@ macro_rules! nistp_dhkex {
                     // early on the first iteration.
                     for counter in 0u8..=255 {
                         // This unwrap is fine. It only triggers if buf is way too big. It's only
-                        // 32 bytes.
+                        // 32 bytes. The counter is always 1 byte, so the domain after it can't
+                        // be confused with another counter.
                         hkdf_ctx
-                            .labeled_expand(suite_id, b"candidate", &[counter], &mut buf)
+                            .labeled_expand_multi(
+                                suite_id,
+                                b"candidate",
+                                &[&[counter], domain],
+                                &mut buf,
+                            )
                             .unwrap();
 
                         // Apply the bitmask
@@ -410,8 +432,8 @@ mod tests {
         dh_res_xcoord_bytes: &[u8],
     ) {
         // Deserialize the pubkey and privkey and do a DH operation
-        let sk_recip = Kex::PrivateKey::from_bytes(&sk_recip_bytes).unwrap();
-        let pk_sender = Kex::PublicKey::from_bytes(&pk_sender_bytes).unwrap();
+        let sk_recip = Kex::PrivateKey::from_bytes(sk_recip_bytes).unwrap();
+        let pk_sender = Kex::PublicKey::from_bytes(pk_sender_bytes).unwrap();
         let derived_dh = Kex::dh(&sk_recip, &pk_sender).unwrap();
 
         // Assert that the derived DH result matches the test vector. Recall that the HPKE DH
@@ -477,19 +499,19 @@ mod tests {
     #[cfg(feature = "p256")]
     #[test]
     fn test_vector_ecdh_p256() {
-        test_vector_ecdh::<DhP256>(&P256_PRIVKEYS[0], &P256_PUBKEYS[1], &P256_DH_RES_XCOORD);
+        test_vector_ecdh::<DhP256>(P256_PRIVKEYS[0], P256_PUBKEYS[1], P256_DH_RES_XCOORD);
     }
 
     #[cfg(feature = "p384")]
     #[test]
     fn test_vector_ecdh_p384() {
-        test_vector_ecdh::<DhP384>(&P384_PRIVKEYS[0], &P384_PUBKEYS[1], &P384_DH_RES_XCOORD);
+        test_vector_ecdh::<DhP384>(P384_PRIVKEYS[0], P384_PUBKEYS[1], P384_DH_RES_XCOORD);
     }
 
     #[cfg(feature = "p521")]
     #[test]
     fn test_vector_ecdh_p521() {
-        test_vector_ecdh::<DhP521>(&P521_PRIVKEYS[0], &P521_PUBKEYS[1], &P521_DH_RES_XCOORD);
+        test_vector_ecdh::<DhP521>(P521_PRIVKEYS[0], P521_PUBKEYS[1], P521_DH_RES_XCOORD);
     }
 
     #[cfg(feature = "p256")]
@@ -65,6 +65,16 @@ impl Deserializable for PublicKey {
     }
 }
 
+impl_hex_display_fromstr!(PublicKey);
+impl_try_from_bytes!(PublicKey);
+
+// Pubkeys are stored as their encoding
+impl AsRef<[u8]> for PublicKey {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
 impl Serializable for PrivateKey {
     // RFC 9180 §7.1 Table 2: Nsk of DHKEM(X25519, HKDF-SHA256) is 32
     type OutputSize = typenum::U32;
@@ -100,6 +110,8 @@ impl Deserializable for PrivateKey {
     }
 }
 
+impl_try_from_bytes!(PrivateKey);
+
 impl Serializable for KexResult {
     // RFC 9180 §4.1: For X25519 and X448, the size Ndh is equal to 32 and 56, respectively
     type OutputSize = typenum::U32;
@@ -114,6 +126,49 @@ impl Serializable for KexResult {
     }
 }
 
+// The u-coordinates of the points of small order on Curve25519 and its twist, along with their
+// non-canonical encodings, all with the top bit cleared. This is the list libsodium uses.
+const SMALL_ORDER_POINTS: [[u8; 32]; 7] = [
+    // 0 (order 4)
+    [0; 32],
+    // 1 (order 1)
+    [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    // Order 8
+    [
+        0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4,
+        0x6a, 0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49,
+        0xb8, 0x00,
+    ],
+    // Order 8
+    [
+        0x5f, 0x9c, 0x95, 0xbc, 0xa3, 0x50, 0x8c, 0x24, 0xb1, 0xd0, 0xb1, 0x55, 0x9c, 0x83, 0xef,
+        0x5b, 0x04, 0x44, 0x5c, 0xc4, 0x58, 0x1c, 0x8e, 0x86, 0xd8, 0x22, 0x4e, 0xdd, 0xd0, 0x9f,
+        0x11, 0x57,
+    ],
+    // p - 1 (order 2)
+    [
+        0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    // p, i.e., 0 non-canonically
+    [
+        0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    // p + 1, i.e., 1 non-canonically
+    [
+        0xee, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+];
+
 /// Represents ECDH functionality over the X25519 group
 pub struct X25519 {}
 
@@ -146,23 +201,41 @@ impl DhKeyExchange for X25519 {
         }
     }
 
+    /// Checks `pk` against the encodings of the points of order 1, 2, 4, and 8, i.e., the points
+    /// that DH with any clamped scalar sends to zero. The top bit is ignored, as in DH itself.
+    #[doc(hidden)]
+    fn is_small_order(pk: &PublicKey) -> bool {
+        let mut bytes = *pk.0.as_bytes();
+        bytes[31] &= 0x7f;
+
+        SMALL_ORDER_POINTS
+            .iter()
+            .fold(Choice::from(0), |acc, p| acc | bytes.ct_eq(p))
+            .into()
+    }
+
     // RFC 9180 §7.1.3
     // def DeriveKeyPair(ikm):
     //   dkp_prk = LabeledExtract("", "dkp_prk", ikm)
     //   sk = LabeledExpand(dkp_prk, "sk", "", Nsk)
     //   return (sk, pk(sk))
 
-    /// Deterministically derives a keypair from the given input keying material and ciphersuite
-    /// ID. The keying material SHOULD have as many bits of entropy as the bit length of a secret
-    /// key, i.e., 256.
+    /// Deterministically derives a keypair from the given input keying material, ciphersuite ID,
+    /// and domain. The keying material SHOULD have as many bits of entropy as the bit length of a
+    /// secret key, i.e., 256.
     #[doc(hidden)]
-    fn derive_keypair<Kdf: KdfTrait>(suite_id: &KemSuiteId, ikm: &[u8]) -> (PrivateKey, PublicKey) {
+    fn derive_keypair<Kdf: KdfTrait>(
+        suite_id: &KemSuiteId,
+        ikm: &[u8],
+        domain: &[u8],
+    ) -> (PrivateKey, PublicKey) {
         // Write the label into a byte buffer and extract from the IKM
         let (_, hkdf_ctx) = labeled_extract::<Kdf>(&[], suite_id, b"dkp_prk", ikm);
-        // The buffer we hold the candidate scalar bytes in. This is the size of a private key.
+        // The buffer we hold the candidate scalar bytes in. This is the size of a private key. The
+        // RFC's info is empty, so the domain is all of it.
         let mut buf = [0u8; 32];
         hkdf_ctx
-            .labeled_expand(suite_id, b"sk", &[], &mut buf)
+            .labeled_expand(suite_id, b"sk", domain, &mut buf)
             .unwrap();
 
         let sk = x25519_dalek::StaticSecret::from(buf);
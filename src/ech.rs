@@ -0,0 +1,141 @@
+//! GREASE for TLS Encrypted Client Hello.
+//!
+//! A TLS client that supports ECH but has no ECH configuration for a server still sends an ECH
+//! extension, filled with random values that look like a real one (draft-ietf-tls-esni §6.2). This
+//! keeps middleboxes from ossifying around ClientHellos without ECH, and keeps the clients that do
+//! use ECH from standing out. [`grease_ech_extension`] builds such an extension body for a given
+//! ciphersuite, with the sizes that a real one for that ciphersuite would have.
+
+use crate::{aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, HpkeError, Serializable};
+
+use rand_core::{CryptoRng, RngCore};
+
+/// The TLS extension type of `encrypted_client_hello`
+pub const ECH_EXTENSION_TYPE: u16 = 0xfe0d;
+
+/// The `ECHClientHelloType` of the ECH extension sent in the outer ClientHello
+const ECH_CLIENT_HELLO_OUTER: u8 = 0;
+
+/// Returns the body of a GREASE ECH extension, i.e., the `extension_data` of an extension of type
+/// [`ECH_EXTENSION_TYPE`], as a client would send with no ECH configuration for the server.
+///
+/// The body is an outer `ECHClientHello` with the cipher suite `(Kdf, A)`, a random config ID, an
+/// `enc` that is a valid encapsulated key for `Kem` to a throwaway key, and a random `payload` as
+/// long as the ciphertext of a `payload_len`-byte plaintext. `payload_len` should be the length
+/// the client's padded `EncodedClientHelloInner` would have if it did offer ECH
+/// (draft-ietf-tls-esni §6.1.3), so that GREASE and real ECH are the same size.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(body)` on success. If `A` is the export-only AEAD, which ECH can't use, returns
+/// `Err(HpkeError::ValidationError)`. If the payload would be longer than 2^16 - 1 bytes, returns
+/// `Err(HpkeError::InputTooLong)`. If `csprng` failed, returns `Err(HpkeError::RngError)`.
+pub fn grease_ech_extension<A, Kdf, Kem, R>(
+    payload_len: usize,
+    csprng: &mut R,
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    if A::MAX_PLAINTEXT_LEN == 0 {
+        return Err(HpkeError::ValidationError);
+    }
+    let payload_len = u16::try_from(payload_len.saturating_add(A::TAG_SIZE))
+        .map_err(|_| HpkeError::InputTooLong)?;
+
+    // Encapsulate to a fresh key, so enc is as valid as a real one. The shared secret is dropped.
+    let (_, pk) = Kem::gen_keypair(csprng);
+    let (_, encapped_key) = Kem::encap(&pk, None, csprng)?;
+    let enc = encapped_key.to_bytes();
+    // Every encapsulated key size in RFC 9180 fits in a u16
+    let enc_len = enc.len() as u16;
+
+    let mut config_id = [0u8; 1];
+    csprng
+        .try_fill_bytes(&mut config_id)
+        .map_err(|_| HpkeError::RngError)?;
+
+    let mut body = crate::Vec::with_capacity(10 + enc.len() + payload_len as usize);
+    body.push(ECH_CLIENT_HELLO_OUTER);
+    body.extend_from_slice(&Kdf::KDF_ID.to_be_bytes());
+    body.extend_from_slice(&A::AEAD_ID.to_be_bytes());
+    body.extend_from_slice(&config_id);
+    body.extend_from_slice(&enc_len.to_be_bytes());
+    body.extend_from_slice(&enc);
+    body.extend_from_slice(&payload_len.to_be_bytes());
+    let payload_start = body.len();
+    body.resize(payload_start + payload_len as usize, 0);
+    csprng
+        .try_fill_bytes(&mut body[payload_start..])
+        .map_err(|_| HpkeError::RngError)?;
+
+    Ok(body)
+}
+
+#[cfg(all(test, feature = "x25519", feature = "p256"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::{AesGcm128, ChaCha20Poly1305, ExportOnlyAead},
+        kdf::HkdfSha256,
+        kem::{DhP256HkdfSha256, X25519HkdfSha256},
+        Deserializable,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that the extension body parses as an outer ECHClientHello with the suite's sizes, and
+    /// that unusable parameters are refused
+    #[test]
+    fn test_grease_ech_extension() {
+        let mut csprng = StdRng::from_entropy();
+
+        let body =
+            grease_ech_extension::<AesGcm128, HkdfSha256, X25519HkdfSha256, _>(200, &mut csprng)
+                .unwrap();
+        assert_eq!(body[0], ECH_CLIENT_HELLO_OUTER);
+        assert_eq!(body[1..5], [0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(body[6..8], [0, 32]);
+        let enc = &body[8..40];
+        assert!(<X25519HkdfSha256 as KemTrait>::EncappedKey::from_bytes(enc).is_ok());
+        assert_eq!(body[40..42], 216u16.to_be_bytes());
+        assert_eq!(body.len(), 42 + 216);
+
+        // P-256 encapsulated keys are uncompressed points
+        let body = grease_ech_extension::<ChaCha20Poly1305, HkdfSha256, DhP256HkdfSha256, _>(
+            0,
+            &mut csprng,
+        )
+        .unwrap();
+        assert_eq!(body[3..5], [0x00, 0x03]);
+        assert_eq!(body[6..8], [0, 65]);
+        assert_eq!(body.len(), 8 + 65 + 2 + 16);
+
+        // Two extensions don't share their random parts
+        let body1 =
+            grease_ech_extension::<AesGcm128, HkdfSha256, X25519HkdfSha256, _>(64, &mut csprng)
+                .unwrap();
+        let body2 =
+            grease_ech_extension::<AesGcm128, HkdfSha256, X25519HkdfSha256, _>(64, &mut csprng)
+                .unwrap();
+        assert_ne!(body1[8..], body2[8..]);
+
+        assert_eq!(
+            grease_ech_extension::<ExportOnlyAead, HkdfSha256, X25519HkdfSha256, _>(
+                64,
+                &mut csprng
+            ),
+            Err(HpkeError::ValidationError)
+        );
+        assert_eq!(
+            grease_ech_extension::<AesGcm128, HkdfSha256, X25519HkdfSha256, _>(
+                65535 - 15,
+                &mut csprng
+            ),
+            Err(HpkeError::InputTooLong)
+        );
+    }
+}
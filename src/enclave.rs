@@ -0,0 +1,232 @@
+//! Decapsulation inside a trusted execution environment, e.g., an SGX enclave, with recipient keys
+//! sealed to it.
+//!
+//! This is the split that the `kms` module makes, moved one step further in: the enclave does the
+//! whole KEM, and only the KEM shared secret comes back out. The key schedule and the AEAD, which
+//! handle every message, run outside, where they don't pay for a transition into the enclave.
+//! The recipient's private key only exists outside as a blob sealed to the enclave, e.g., with
+//! `sgx_seal_data`, which only that enclave can unseal.
+//!
+//! Each side has its half:
+//!
+//! * Outside, implement [`Enclave`] on a handle to the enclave, whose `decap` is an ECALL, and
+//!   call [`setup_receiver_with_enclave`] to get a decryption context.
+//! * Inside, the ECALL unseals the private key and hands it, with the rest of the ECALL's inputs,
+//!   to [`decap_in_enclave`], which writes the shared secret to the ECALL's output buffer.
+//!
+//! Everything that crosses the boundary is a byte string, as the `Serializable` impls encode it,
+//! so the ECALL only takes `[in]` and `[out]` buffers. Neither half needs `alloc`.
+
+use crate::{
+    aead::{Aead, AeadCtxR},
+    kdf::Kdf as KdfTrait,
+    kem::{Kem as KemTrait, SharedSecret},
+    setup::setup_receiver_from_shared_secret,
+    Deserializable, HpkeError, OpModeR, Serializable,
+};
+
+use generic_array::typenum::marker_traits::Unsigned;
+use zeroize::Zeroize;
+
+/// The untrusted side's handle to an enclave that can decapsulate with private keys of the KEM
+/// `Kem` that are sealed to it
+pub trait Enclave<Kem: KemTrait> {
+    /// A private key sealed to this enclave, e.g., `[u8]` for an SGX sealed data blob
+    type SealedKey: ?Sized;
+
+    /// Has the enclave unseal `sealed_key` and decapsulate `encapped_key` with it, in the Auth
+    /// modes with the sender's public key `pk_sender_id`, and write the shared secret to
+    /// `shared_secret`. The keys are serialized, and `shared_secret` is `Kem::NSecret` bytes
+    /// long. This is what [`decap_in_enclave`] does on the other side.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(())` on success. If the enclave fails, or the key doesn't unseal, e.g., because
+    /// it was sealed to another enclave, implementations should return
+    /// `Err(HpkeError::DecapError)`. Otherwise, they should return the error that
+    /// `decap_in_enclave` returned.
+    fn decap(
+        &self,
+        sealed_key: &Self::SealedKey,
+        pk_sender_id: Option<&[u8]>,
+        encapped_key: &[u8],
+        shared_secret: &mut [u8],
+    ) -> Result<(), HpkeError>;
+}
+
+/// Does what [`setup_receiver`](crate::setup_receiver) does, with the recipient's private key
+/// sealed to `enclave`. The KEM is done by the enclave, in one call, and the key schedule here.
+///
+/// Return Value
+/// ============
+/// On success, returns a decryption context. If `enclave.decap` fails, returns its error.
+/// Otherwise, the errors are those of `setup_receiver`.
+pub fn setup_receiver_with_enclave<A, Kdf, Kem, E>(
+    mode: &OpModeR<Kem>,
+    enclave: &E,
+    sealed_key: &E::SealedKey,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    E: Enclave<Kem> + ?Sized,
+{
+    let pk_sender_id = mode.get_pk_sender_id().map(|pk| pk.to_bytes());
+    let mut shared_secret = SharedSecret::<Kem>::default();
+    enclave.decap(
+        sealed_key,
+        pk_sender_id.as_deref(),
+        &encapped_key.to_bytes(),
+        &mut shared_secret.0,
+    )?;
+    setup_receiver_from_shared_secret(mode, shared_secret, info)
+}
+
+/// The enclave's half of [`Enclave::decap`]. Decapsulates `encapped_key` with the unsealed private
+/// key `sk_recip`, and, in the Auth modes, the sender's public key `pk_sender_id`, and writes the
+/// shared secret to `shared_secret`. The keys are serialized. The caller should zeroize
+/// `sk_recip` once this returns.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(())` on success. If `shared_secret` isn't `Kem::NSecret` bytes long, returns
+/// `Err(HpkeError::IncorrectInputLength(expected, got))`. If a key doesn't deserialize, returns
+/// its deserialization error. Otherwise, the errors are those of decapsulation, i.e.,
+/// `Err(HpkeError::InvalidEncappedKey)` or `Err(HpkeError::DecapError)`.
+pub fn decap_in_enclave<Kem: KemTrait>(
+    sk_recip: &[u8],
+    pk_sender_id: Option<&[u8]>,
+    encapped_key: &[u8],
+    shared_secret: &mut [u8],
+) -> Result<(), HpkeError> {
+    let expected_len = Kem::NSecret::to_usize();
+    if shared_secret.len() != expected_len {
+        return Err(HpkeError::IncorrectInputLength(
+            expected_len,
+            shared_secret.len(),
+        ));
+    }
+
+    let sk_recip = Kem::PrivateKey::from_bytes(sk_recip)?;
+    let pk_sender_id = pk_sender_id.map(Kem::PublicKey::from_bytes).transpose()?;
+    let encapped_key = Kem::EncappedKey::from_bytes(encapped_key)?;
+    let mut decapped = Kem::decap(&sk_recip, pk_sender_id.as_ref(), &encapped_key)?;
+    shared_secret.copy_from_slice(&decapped.0);
+    decapped.0.zeroize();
+    Ok(())
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256, setup_sender, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Stands in for an enclave. Its sealing is a one-time pad that never leaves it.
+    struct FakeEnclave {
+        pad: [u8; 32],
+    }
+
+    impl FakeEnclave {
+        fn seal(&self, sk: &<Kem as KemTrait>::PrivateKey) -> [u8; 32] {
+            let mut sealed = [0u8; 32];
+            sealed.copy_from_slice(&sk.to_bytes());
+            sealed
+                .iter_mut()
+                .zip(self.pad.iter())
+                .for_each(|(b, p)| *b ^= p);
+            sealed
+        }
+    }
+
+    impl Enclave<Kem> for FakeEnclave {
+        type SealedKey = [u8];
+
+        fn decap(
+            &self,
+            sealed_key: &[u8],
+            pk_sender_id: Option<&[u8]>,
+            encapped_key: &[u8],
+            shared_secret: &mut [u8],
+        ) -> Result<(), HpkeError> {
+            // This part runs inside the enclave
+            let mut sk = [0u8; 32];
+            sk.iter_mut()
+                .zip(sealed_key.iter().zip(self.pad.iter()))
+                .for_each(|(b, (s, p))| *b = s ^ p);
+            let res = decap_in_enclave::<Kem>(&sk, pk_sender_id, encapped_key, shared_secret);
+            sk.zeroize();
+            res
+        }
+    }
+
+    /// Tests that enclave setup agrees with the sender in the Base and Auth modes, and that a key
+    /// sealed to another enclave doesn't open anything
+    #[test]
+    fn test_setup_receiver_with_enclave() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (sk_sender, pk_sender) = Kem::gen_keypair(&mut csprng);
+        let mut pad = [0u8; 32];
+        csprng.fill_bytes(&mut pad);
+        let enclave = FakeEnclave { pad };
+        let sealed_key = enclave.seal(&sk_recip);
+
+        let modes = [
+            (OpModeS::Base, OpModeR::Base),
+            (
+                OpModeS::Auth((sk_sender, pk_sender.clone())),
+                OpModeR::Auth(pk_sender),
+            ),
+        ];
+        for (mode_s, mode_r) in modes.iter() {
+            let (enc, mut sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(mode_s, &pk_recip, b"info", &mut csprng).unwrap();
+            let mut receiver_ctx = setup_receiver_with_enclave::<A, Kdf, Kem, _>(
+                mode_r,
+                &enclave,
+                &sealed_key[..],
+                &enc,
+                b"info",
+            )
+            .unwrap();
+            let mut ct = [0u8; 19];
+            ct.copy_from_slice(b"from the enclave...");
+            let tag = sender_ctx.seal_in_place_detached(&mut ct, b"").unwrap();
+            receiver_ctx
+                .open_in_place_detached(&mut ct, b"", &tag)
+                .unwrap();
+            assert_eq!(&ct, b"from the enclave...");
+        }
+
+        // The same blob, in an enclave with a different sealing key, unseals to the wrong key
+        let (enc, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"info", &mut csprng)
+                .unwrap();
+        let other_enclave = FakeEnclave { pad: [0u8; 32] };
+        let mut receiver_ctx = setup_receiver_with_enclave::<A, Kdf, Kem, _>(
+            &OpModeR::Base,
+            &other_enclave,
+            &sealed_key[..],
+            &enc,
+            b"info",
+        )
+        .unwrap();
+        let mut ct = *b"sealed elsewhere";
+        let tag = sender_ctx.seal_in_place_detached(&mut ct, b"").unwrap();
+        assert_eq!(
+            receiver_ctx.open_in_place_detached(&mut ct, b"", &tag),
+            Err(HpkeError::OpenError)
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Human-readable encodings of serialized values, for config files, CLI flags, and JSON APIs.
+//!
+//! Public keys, encapsulated keys, and tags implement `Display` and `FromStr` as lowercase hex.
+//! For everything else, and for base64, wrap a value in [`Hex`] or [`Base64`] to print it, and use
+//! [`from_hex`] or [`from_base64`] to parse it.
+//!
+//! Parsing is strict. The input must be exactly as long as the encoding of a value of the type,
+//! with no whitespace, prefix, or separators. Hex may be upper or lower case. Base64 uses the
+//! standard alphabet with `=` padding (RFC 4648 §4), and rejects encodings whose unused trailing
+//! bits aren't zero, so every value has exactly one base64 encoding. The decoded bytes then go
+//! through `Deserializable::from_bytes`, so, e.g., an invalid point is rejected.
+//!
+//! None of this is constant-time. Don't use it on private keys.
+
+use crate::{Deserializable, HpkeError, Serializable};
+
+use core::fmt;
+
+use generic_array::{typenum::Unsigned, GenericArray};
+
+const HEX_ALPHABET: &[u8; 16] = b"0123456789abcdef";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Displays the serialization of the wrapped value as lowercase hex
+pub struct Hex<'a, T: Serializable>(pub &'a T);
+
+/// Displays the serialization of the wrapped value as padded base64, in the standard alphabet
+pub struct Base64<'a, T: Serializable>(pub &'a T);
+
+impl<T: Serializable> fmt::Display for Hex<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.to_bytes() {
+            let chars = [
+                HEX_ALPHABET[(byte >> 4) as usize],
+                HEX_ALPHABET[(byte & 0x0f) as usize],
+            ];
+            // The alphabet is ASCII
+            f.write_str(core::str::from_utf8(&chars).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Serializable> fmt::Display for Base64<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for group in self.0.to_bytes().chunks(3) {
+            let chars = base64_encode_group(group);
+            // The alphabet and the padding are ASCII
+            f.write_str(core::str::from_utf8(&chars).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a value from the hex encoding of its serialization
+///
+/// Return Value
+/// ============
+/// Returns `Ok(value)` on success. If `s` is not twice as long as the serialization, returns
+/// `Err(HpkeError::IncorrectInputLength(expected, s.len()))`. If `s` contains a non-hex character,
+/// returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `T::from_bytes`.
+pub fn from_hex<T: Serializable + Deserializable>(s: &str) -> Result<T, HpkeError> {
+    let mut buf = GenericArray::<u8, T::OutputSize>::default();
+    let expected_len = 2 * T::OutputSize::to_usize();
+    if s.len() != expected_len {
+        return Err(HpkeError::IncorrectInputLength(expected_len, s.len()));
+    }
+
+    for (byte, pair) in buf.iter_mut().zip(s.as_bytes().chunks(2)) {
+        *byte = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+    }
+    T::from_bytes(&buf)
+}
+
+/// Parses a value from the base64 encoding of its serialization
+///
+/// Return Value
+/// ============
+/// Returns `Ok(value)` on success. If `s` is not as long as the padded encoding of the
+/// serialization, returns `Err(HpkeError::IncorrectInputLength(expected, s.len()))`. If `s`
+/// contains a character outside the alphabet, has padding anywhere but the end, or has nonzero
+/// unused bits, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `T::from_bytes`.
+pub fn from_base64<T: Serializable + Deserializable>(s: &str) -> Result<T, HpkeError> {
+    let mut buf = GenericArray::<u8, T::OutputSize>::default();
+    let expected_len = 4 * buf.chunks(3).len();
+    if s.len() != expected_len {
+        return Err(HpkeError::IncorrectInputLength(expected_len, s.len()));
+    }
+
+    for (group, quad) in buf.chunks_mut(3).zip(s.as_bytes().chunks(4)) {
+        base64_decode_group(quad, group)?;
+    }
+    T::from_bytes(&buf)
+}
+
+/// Encodes a group of 1 to 3 bytes as 4 base64 characters, padded with `=`
+pub(crate) fn base64_encode_group(group: &[u8]) -> [u8; 4] {
+    // Pack up to 3 bytes into 24 bits, and write one character per 6 bits of input
+    let mut bits = 0u32;
+    for (i, byte) in group.iter().enumerate() {
+        bits |= (*byte as u32) << (16 - 8 * i);
+    }
+    let mut chars = [b'='; 4];
+    for (i, c) in chars.iter_mut().take(group.len() + 1).enumerate() {
+        *c = BASE64_ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize];
+    }
+    chars
+}
+
+/// Decodes 4 base64 characters into `group`, which is 1 to 3 bytes long. Fails if the padding
+/// doesn't match the length of `group`, or any unused bits are nonzero.
+pub(crate) fn base64_decode_group(quad: &[u8], group: &mut [u8]) -> Result<(), HpkeError> {
+    // A group of n bytes is encoded in n+1 characters, and padded to 4 with '='
+    let (chars, padding) = quad.split_at(group.len() + 1);
+    if padding.iter().any(|c| *c != b'=') {
+        return Err(HpkeError::ValidationError);
+    }
+    let mut bits = 0u32;
+    for (i, c) in chars.iter().enumerate() {
+        bits |= (base64_value(*c)? as u32) << (18 - 6 * i);
+    }
+    for (i, byte) in group.iter_mut().enumerate() {
+        *byte = (bits >> (16 - 8 * i)) as u8;
+    }
+    // The bits below the last byte must be zero, or two strings would decode the same
+    if bits & (0xff_ffff >> (8 * group.len())) != 0 {
+        return Err(HpkeError::ValidationError);
+    }
+    Ok(())
+}
+
+/// Returns the value of a hex digit
+fn hex_value(c: u8) -> Result<u8, HpkeError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(HpkeError::ValidationError),
+    }
+}
+
+/// Returns the value of a base64 character. Padding is not a character.
+fn base64_value(c: u8) -> Result<u8, HpkeError> {
+    match c {
+        b'A'..=b'Z' => Ok(c - b'A'),
+        b'a'..=b'z' => Ok(c - b'a' + 26),
+        b'0'..=b'9' => Ok(c - b'0' + 52),
+        b'+' => Ok(62),
+        b'/' => Ok(63),
+        _ => Err(HpkeError::ValidationError),
+    }
+}
+
+#[cfg(all(test, feature = "std", feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::{AeadTag, ChaCha20Poly1305},
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+    };
+
+    use std::string::ToString;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Kem = X25519HkdfSha256;
+
+    /// Tests round trips through both encodings, against a known encoding, and that malformed
+    /// strings are rejected
+    #[test]
+    fn test_text_encodings() {
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk) = Kem::gen_keypair(&mut csprng);
+
+        // Display and FromStr are hex
+        let pk_hex = pk.to_string();
+        assert_eq!(pk_hex, Hex(&pk).to_string());
+        assert_eq!(pk_hex.parse::<<Kem as KemTrait>::PublicKey>().unwrap(), pk);
+        assert_eq!(
+            from_hex::<<Kem as KemTrait>::PublicKey>(&pk_hex.to_uppercase()).unwrap(),
+            pk
+        );
+        let pk_b64 = Base64(&pk).to_string();
+        assert_eq!(pk_b64.len(), 44);
+        assert_eq!(
+            from_base64::<<Kem as KemTrait>::PublicKey>(&pk_b64).unwrap(),
+            pk
+        );
+
+        // A 16-byte tag is 5 groups of 3 bytes and 1 leftover byte, so it ends in "=="
+        let mut tag_bytes = [0u8; 16];
+        tag_bytes[0] = 0xfb;
+        tag_bytes[15] = 0xff;
+        let tag = AeadTag::<ChaCha20Poly1305>::from_bytes(&tag_bytes).unwrap();
+        assert_eq!(tag.to_string(), "fb0000000000000000000000000000ff");
+        assert_eq!(Base64(&tag).to_string(), "+wAAAAAAAAAAAAAAAAAA/w==");
+        let parse_tag = from_base64::<AeadTag<ChaCha20Poly1305>>;
+        assert!(parse_tag("+wAAAAAAAAAAAAAAAAAA/w==").unwrap() == tag);
+
+        // Wrong lengths, bad characters, misplaced padding, and nonzero unused bits are rejected
+        assert_eq!(
+            from_hex::<AeadTag<ChaCha20Poly1305>>("fb00").err(),
+            Some(HpkeError::IncorrectInputLength(32, 4))
+        );
+        assert_eq!(
+            from_hex::<AeadTag<ChaCha20Poly1305>>("fb000000000000000000000000000 ff").err(),
+            Some(HpkeError::ValidationError)
+        );
+        assert_eq!(
+            parse_tag("+wAAAAAAAAAAAAAAAAAA/w").err(),
+            Some(HpkeError::IncorrectInputLength(24, 22))
+        );
+        for bad in [
+            "+wAAAAAAAAAAAAAAAAAA/x==",
+            "+wAAAAAAAAAAAAAAAAAA/w=A",
+            "+wAA=AAAAAAAAAAAAAAA/w==",
+            "-wAAAAAAAAAAAAAAAAAA/w==",
+        ] {
+            assert_eq!(
+                parse_tag(bad).err(),
+                Some(HpkeError::ValidationError),
+                "{bad}"
+            );
+        }
+    }
+}
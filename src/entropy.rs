@@ -0,0 +1,272 @@
+//! Entropy sources for microcontrollers, which rarely have an RNG type of their own.
+//!
+//! Everything in this crate that needs randomness takes a `CryptoRng + RngCore`. The types here
+//! make one out of what an MCU usually has:
+//!
+//! * [`FnRng`] wraps a closure that fills a buffer, e.g., from a TRNG peripheral's data register.
+//! * [`DynRng`] wraps a `&mut dyn RngCore`. Passing it to `setup_sender` and friends means they're
+//!   compiled once, rather than once per RNG type, which matters on devices with little flash.
+//! * [`MixedRng`] conditions the output of a TRNG that isn't uniform enough to use directly, by
+//!   mixing it with a seed stored on the device. Its output is uniform as long as either the TRNG
+//!   output or the seed is.
+//!
+//! ```ignore
+//! let trng = FnRng::new(|buf| peripherals.rng.fill(buf).map_err(|_| trng_error()));
+//! let mut csprng = MixedRng::new(trng, &stored_seed);
+//! let (sk, pk) = Kem::try_gen_keypair(&mut csprng)?;
+//! flash.write(SEED_ADDR, &csprng.next_stored_seed()?);
+//! ```
+//!
+//! Wrapping a source in `FnRng` or `DynRng` asserts that it's cryptographically secure. Nothing
+//! here can check that. If in doubt, wrap it in a `MixedRng` too.
+
+use hkdf::Hkdf;
+use rand_core::{CryptoRng, Error, RngCore};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+/// The size of a [`MixedRng`] seed, in bytes
+pub const SEED_SIZE: usize = 32;
+
+/// The most bytes that one HKDF-SHA256 expansion can output
+const MAX_EXPAND_LEN: usize = 255 * 32;
+
+// The labels of the things a MixedRng derives from its state
+const OUTPUT_LABEL: &[u8] = b"hpke entropy output";
+const STATE_LABEL: &[u8] = b"hpke entropy state";
+const STORED_SEED_LABEL: &[u8] = b"hpke entropy stored seed";
+
+/// A cryptographically secure RNG made of a closure that fills a buffer with random bytes, or
+/// fails. See the [module docs](self).
+pub struct FnRng<F: FnMut(&mut [u8]) -> Result<(), Error>>(F);
+
+impl<F: FnMut(&mut [u8]) -> Result<(), Error>> FnRng<F> {
+    /// Makes an RNG out of `fill`. The caller vouches that `fill` is cryptographically secure.
+    pub fn new(fill: F) -> Self {
+        FnRng(fill)
+    }
+}
+
+impl<F: FnMut(&mut [u8]) -> Result<(), Error>> RngCore for FnRng<F> {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    /// Panics if the closure fails. `Kem::try_gen_keypair` and setup call `try_fill_bytes` instead.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("entropy source failed")
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        (self.0)(dest)
+    }
+}
+
+// The caller vouched for this in FnRng::new
+impl<F: FnMut(&mut [u8]) -> Result<(), Error>> CryptoRng for FnRng<F> {}
+
+/// A cryptographically secure RNG behind a trait object. See the [module docs](self).
+pub struct DynRng<'a>(&'a mut dyn RngCore);
+
+impl<'a> DynRng<'a> {
+    /// Makes an RNG out of `rng`. The caller vouches that `rng` is cryptographically secure.
+    pub fn new(rng: &'a mut dyn RngCore) -> Self {
+        DynRng(rng)
+    }
+}
+
+impl RngCore for DynRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+// The caller vouched for this in DynRng::new
+impl CryptoRng for DynRng<'_> {}
+
+/// An RNG that mixes the output of a TRNG with a seed stored on the device. See the [module
+/// docs](self).
+///
+/// Every request reads [`SEED_SIZE`] fresh bytes from the TRNG and computes
+/// `prk = HKDF-Extract(salt = state, ikm = trng_output)`. The output is then
+/// `HKDF-Expand(prk, "hpke entropy output", len)`, and the state is replaced with
+/// `HKDF-Expand(prk, "hpke entropy state", 32)`, so that a later compromise of the state doesn't
+/// reveal earlier outputs.
+pub struct MixedRng<T: RngCore> {
+    trng: T,
+    state: [u8; SEED_SIZE],
+}
+
+impl<T: RngCore> MixedRng<T> {
+    /// Makes an RNG that mixes the output of `trng` with `seed`. The seed should come from a
+    /// good source when the device is provisioned, and be replaced with
+    /// [`next_stored_seed`](Self::next_stored_seed) after every boot, so that no two boots start
+    /// from the same state.
+    pub fn new(trng: T, seed: &[u8; SEED_SIZE]) -> Self {
+        MixedRng { trng, state: *seed }
+    }
+
+    /// Returns a seed to store in place of the one this RNG was made with. It's derived from the
+    /// current state, after mixing in fresh TRNG output, and reveals nothing about this RNG's
+    /// past or future outputs.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns the new seed on success. If the TRNG fails, returns its error.
+    pub fn next_stored_seed(&mut self) -> Result<[u8; SEED_SIZE], Error> {
+        let mut seed = [0u8; SEED_SIZE];
+        self.mix(STORED_SEED_LABEL, &mut seed)?;
+        Ok(seed)
+    }
+
+    /// Mixes fresh TRNG output into the state, fills `dest` with output under `label`, and
+    /// ratchets the state
+    fn mix(&mut self, label: &[u8], dest: &mut [u8]) -> Result<(), Error> {
+        let mut sample = [0u8; SEED_SIZE];
+        let res = self.trng.try_fill_bytes(&mut sample);
+        if res.is_ok() {
+            let hkdf = Hkdf::<Sha256>::new(Some(&self.state), &sample);
+            // Each chunk is expanded under its own index, so requests of any length work
+            for (i, chunk) in dest.chunks_mut(MAX_EXPAND_LEN).enumerate() {
+                hkdf.expand_multi_info(&[label, &(i as u64).to_be_bytes()], chunk)
+                    .expect("chunk is at most the HKDF output limit");
+            }
+            hkdf.expand(STATE_LABEL, &mut self.state)
+                .expect("state is shorter than the HKDF output limit");
+        }
+        sample.zeroize();
+        res
+    }
+}
+
+impl<T: RngCore> RngCore for MixedRng<T> {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    /// Panics if the TRNG fails. `Kem::try_gen_keypair` and setup call `try_fill_bytes` instead.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("TRNG failed")
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.mix(OUTPUT_LABEL, dest)
+    }
+}
+
+// The output is uniform as long as either the TRNG output or the seed is. See the module docs.
+impl<T: RngCore> CryptoRng for MixedRng<T> {}
+
+// Zero out the state on drop
+impl<T: RngCore> Drop for MixedRng<T> {
+    fn drop(&mut self) {
+        self.state.zeroize();
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305,
+        kdf::HkdfSha256,
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+        setup_receiver, setup_sender, OpModeR, OpModeS,
+    };
+
+    use core::num::NonZeroU32;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests that keypairs and senders can be made from every kind of entropy source, that a
+    /// failing source makes setup fail instead of panicking, and that the mixing RNG depends on
+    /// both its inputs
+    #[test]
+    fn test_entropy_sources() {
+        let mut std_rng = StdRng::from_entropy();
+        let mut counter = 0u8;
+        let mut fn_rng = FnRng::new(|buf: &mut [u8]| {
+            buf.iter_mut().for_each(|b| {
+                counter = counter.wrapping_add(1);
+                *b = counter;
+            });
+            Ok(())
+        });
+        let (sk_recip, pk_recip) = Kem::try_gen_keypair(&mut fn_rng).unwrap();
+
+        // A sender from a trait object, and one from a mixing RNG over a closure, both work
+        let mut dyn_rng = DynRng::new(&mut std_rng);
+        let mut mixed = MixedRng::new(fn_rng, &[7u8; SEED_SIZE]);
+        for csprng in [&mut dyn_rng as &mut dyn RngCore, &mut mixed] {
+            let (enc, mut sender_ctx) = setup_sender::<A, Kdf, Kem, _>(
+                &OpModeS::Base,
+                &pk_recip,
+                b"info",
+                &mut DynRng::new(csprng),
+            )
+            .unwrap();
+            let mut receiver_ctx =
+                setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk_recip, &enc, b"info").unwrap();
+            let mut msg = *b"entropy";
+            let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+            receiver_ctx
+                .open_in_place_detached(&mut msg, b"", &tag)
+                .unwrap();
+            assert_eq!(&msg, b"entropy");
+        }
+
+        // A failing TRNG makes setup fail, with or without mixing
+        let fail = |_: &mut [u8]| Err(Error::from(NonZeroU32::new(Error::CUSTOM_START).unwrap()));
+        assert!(Kem::try_gen_keypair(&mut FnRng::new(fail)).is_err());
+        let mut mixed_fail = MixedRng::new(FnRng::new(fail), &[7u8; SEED_SIZE]);
+        assert!(setup_sender::<A, Kdf, Kem, _>(
+            &OpModeS::Base,
+            &pk_recip,
+            b"info",
+            &mut mixed_fail
+        )
+        .is_err());
+
+        // A constant TRNG still gives different outputs for different seeds, and successive
+        // outputs and stored seeds differ
+        let constant = || {
+            FnRng::new(|buf: &mut [u8]| {
+                buf.fill(0);
+                Ok(())
+            })
+        };
+        let mut a = MixedRng::new(constant(), &[1u8; SEED_SIZE]);
+        let mut b = MixedRng::new(constant(), &[2u8; SEED_SIZE]);
+        let (mut out_a1, mut out_a2, mut out_b) = ([0u8; 32], [0u8; 32], [0u8; 32]);
+        a.fill_bytes(&mut out_a1);
+        a.fill_bytes(&mut out_a2);
+        b.fill_bytes(&mut out_b);
+        assert_ne!(out_a1, out_a2);
+        assert_ne!(out_a1, out_b);
+        assert_ne!(a.next_stored_seed().unwrap(), a.next_stored_seed().unwrap());
+    }
+}
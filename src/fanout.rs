@@ -0,0 +1,245 @@
+//! Sealing one secret to every member of a group, as in an MLS Welcome message or an invite.
+//!
+//! [`fanout_seal`] seals a separate copy of the secret to each recipient's public key, and
+//! [`fanout_open`] finds and opens the recipient's own copy. Each copy's `info` binds the group
+//! context and the recipient's ID, so a copy can't be passed off as another recipient's, or as one
+//! from another group. [`encode_entries`] and [`decode_entries`] put the copies on the wire.
+//!
+//! Wire format
+//! ===========
+//! The entries are concatenated. Each is `I2OSP(len(id), 4) || id || enc ||
+//! I2OSP(len(ciphertext), 4) || ciphertext`, where `enc` is the KEM's fixed-size encapsulated key.
+
+use crate::{
+    aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, single_shot_open, single_shot_seal,
+    Deserializable, HpkeError, InfoBuilder, OpModeR, OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+
+/// One recipient's copy of the secret
+pub struct FanoutEntry<Kem: KemTrait> {
+    /// The recipient's ID, e.g., a hash of its key package
+    pub recipient_id: crate::Vec<u8>,
+    /// The encapsulated key
+    pub encapped_key: Kem::EncappedKey,
+    /// The sealed secret
+    pub ciphertext: crate::Vec<u8>,
+}
+
+/// Seals `secret` to each of `recipients`, which are pairs of a recipient ID and a public key.
+/// `context` identifies the group and epoch, e.g., the MLS `GroupContext`, and must be the same
+/// on opening.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(entries)` on success, one per recipient, in the same order. If two recipients have
+/// the same ID, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `single_shot_seal`.
+pub fn fanout_seal<A, Kdf, Kem, R>(
+    recipients: &[(&[u8], &Kem::PublicKey)],
+    context: &[u8],
+    secret: &[u8],
+    csprng: &mut R,
+) -> Result<crate::Vec<FanoutEntry<Kem>>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    for (i, (id, _)) in recipients.iter().enumerate() {
+        if recipients[..i].iter().any(|(other_id, _)| other_id == id) {
+            return Err(HpkeError::ValidationError);
+        }
+    }
+
+    recipients
+        .iter()
+        .map(|(id, pk_recip)| {
+            let (encapped_key, ciphertext) = single_shot_seal::<A, Kdf, Kem, R>(
+                &OpModeS::Base,
+                pk_recip,
+                &entry_info(context, id),
+                secret,
+                &[],
+                csprng,
+            )?;
+            Ok(FanoutEntry {
+                recipient_id: id.to_vec(),
+                encapped_key,
+                ciphertext,
+            })
+        })
+        .collect()
+}
+
+/// Finds the entry for `recipient_id`, and opens it with `sk_recip`
+///
+/// Return Value
+/// ============
+/// Returns `Ok(secret)` on success. If no entry is for `recipient_id`, returns
+/// `Err(HpkeError::UnknownKeyId)`. Otherwise, the errors are those of `single_shot_open`.
+pub fn fanout_open<A, Kdf, Kem>(
+    entries: &[FanoutEntry<Kem>],
+    recipient_id: &[u8],
+    sk_recip: &Kem::PrivateKey,
+    context: &[u8],
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let entry = entries
+        .iter()
+        .find(|entry| entry.recipient_id == recipient_id)
+        .ok_or(HpkeError::UnknownKeyId)?;
+    single_shot_open::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_recip,
+        &entry.encapped_key,
+        &entry_info(context, recipient_id),
+        &entry.ciphertext,
+        &[],
+    )
+}
+
+/// Serializes the entries in the wire format above
+///
+/// Panics
+/// ======
+/// Panics if a recipient ID or ciphertext is longer than `u32::MAX` bytes.
+pub fn encode_entries<Kem: KemTrait>(entries: &[FanoutEntry<Kem>]) -> crate::Vec<u8> {
+    let mut buf = crate::Vec::new();
+    for entry in entries {
+        put_u32_prefixed(&mut buf, &entry.recipient_id);
+        buf.extend_from_slice(&entry.encapped_key.to_bytes());
+        put_u32_prefixed(&mut buf, &entry.ciphertext);
+    }
+    buf
+}
+
+/// Parses entries serialized by [`encode_entries`]
+///
+/// Return Value
+/// ============
+/// Returns `Ok(entries)` on success. If `bytes` ends in the middle of an entry, returns
+/// `Err(HpkeError::ValidationError)`. If an encapsulated key is malformed, returns the error of
+/// `Kem::EncappedKey::from_bytes`.
+pub fn decode_entries<Kem: KemTrait>(
+    mut bytes: &[u8],
+) -> Result<crate::Vec<FanoutEntry<Kem>>, HpkeError> {
+    let mut entries = crate::Vec::new();
+    while !bytes.is_empty() {
+        let recipient_id = take_u32_prefixed(&mut bytes)?.to_vec();
+        let encapped_key = Kem::EncappedKey::from_bytes(take(&mut bytes, Kem::ENCAPPED_KEY_SIZE)?)?;
+        let ciphertext = take_u32_prefixed(&mut bytes)?.to_vec();
+        entries.push(FanoutEntry {
+            recipient_id,
+            encapped_key,
+            ciphertext,
+        });
+    }
+    Ok(entries)
+}
+
+/// Returns the `info` that the entry for `recipient_id` is sealed under
+fn entry_info(context: &[u8], recipient_id: &[u8]) -> crate::Vec<u8> {
+    InfoBuilder::new(b"hpke-fanout")
+        .field(b"context", context)
+        .recipient_id(recipient_id)
+        .build()
+}
+
+fn put_u32_prefixed(buf: &mut crate::Vec<u8>, bytes: &[u8]) {
+    let len = u32::try_from(bytes.len()).expect("fanout entry field is too long");
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+/// Splits `len` bytes off the front of `bytes`
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], HpkeError> {
+    if bytes.len() < len {
+        return Err(HpkeError::ValidationError);
+    }
+    let (head, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(head)
+}
+
+/// Splits a 4-byte big-endian length, and that many bytes, off the front of `bytes`, and returns
+/// the latter
+fn take_u32_prefixed<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], HpkeError> {
+    let len = take(bytes, 4)?;
+    let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]);
+    take(bytes, len as usize)
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests that every member opens its own copy after a round trip through the wire format, and
+    /// that copies don't open for other members or groups
+    #[test]
+    fn test_fanout() {
+        let mut csprng = StdRng::from_entropy();
+        let members: crate::Vec<_> = [&b"alice"[..], b"bob", b"carol"]
+            .into_iter()
+            .map(|id| (id, Kem::gen_keypair(&mut csprng)))
+            .collect();
+        let recipients: crate::Vec<_> = members.iter().map(|(id, (_, pk))| (*id, pk)).collect();
+        let (context, secret) = (b"group 1, epoch 5", b"joiner secret");
+
+        let entries =
+            fanout_seal::<A, Kdf, Kem, _>(&recipients, context, secret, &mut csprng).unwrap();
+        let entries = decode_entries::<Kem>(&encode_entries(&entries)).unwrap();
+        assert_eq!(entries.len(), 3);
+        for (id, (sk, _)) in &members {
+            let opened = fanout_open::<A, Kdf, Kem>(&entries, id, sk, context).unwrap();
+            assert_eq!(opened, secret);
+        }
+
+        let (alice_sk, bob_sk) = (&members[0].1 .0, &members[1].1 .0);
+        assert_eq!(
+            fanout_open::<A, Kdf, Kem>(&entries, b"alice", bob_sk, context),
+            Err(HpkeError::OpenError)
+        );
+        assert_eq!(
+            fanout_open::<A, Kdf, Kem>(&entries, b"alice", alice_sk, b"group 2"),
+            Err(HpkeError::OpenError)
+        );
+        assert_eq!(
+            fanout_open::<A, Kdf, Kem>(&entries, b"dave", alice_sk, context),
+            Err(HpkeError::UnknownKeyId)
+        );
+
+        // Relabeling bob's copy as alice's doesn't fool her, since his ID is bound in
+        let mut relabeled = decode_entries::<Kem>(&encode_entries(&entries[1..2])).unwrap();
+        relabeled[0].recipient_id = b"alice".to_vec();
+        assert_eq!(
+            fanout_open::<A, Kdf, Kem>(&relabeled, b"alice", alice_sk, context),
+            Err(HpkeError::OpenError)
+        );
+
+        // Duplicate IDs and truncated encodings are refused
+        let dup = [recipients[0], recipients[0]];
+        assert_eq!(
+            fanout_seal::<A, Kdf, Kem, _>(&dup, context, secret, &mut csprng).err(),
+            Some(HpkeError::ValidationError)
+        );
+        let encoded = encode_entries(&entries);
+        assert_eq!(
+            decode_entries::<Kem>(&encoded[..encoded.len() - 1]).err(),
+            Some(HpkeError::ValidationError)
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Fingerprints of recipient public keys, for key pinning, logging, and out-of-band verification.
+//!
+//! `Kem::fingerprint(&pk)` returns a [`Fingerprint`]: the SHA-256 hash of the KEM's suite ID and
+//! the serialized key, under a fixed domain:
+//!
+//! ```text
+//! fingerprint = SHA-256(concat("hpke-fingerprint-v1", "KEM", I2OSP(kem_id, 2), SerializePublicKey(pk)))
+//! ```
+//!
+//! Binding the KEM ID means the same bytes used as a key in two KEMs get different fingerprints.
+//! Pin keys by comparing the full 32 bytes from [`Fingerprint::as_bytes`]. The `Display` form is a
+//! short, 80-bit summary for people to read aloud or compare by eye, e.g., `4kx7-q2mz-a6cb-tw3d`.
+//! It is lowercase base32 (RFC 4648 §6, without padding) of the first 10 bytes, in groups of 4.
+
+use core::fmt;
+
+use sha2::{Digest, Sha256};
+
+/// The domain that fingerprints are computed under
+const FINGERPRINT_DOMAIN: &[u8] = b"hpke-fingerprint-v1";
+
+/// The number of bytes of the fingerprint that the short form shows
+const SHORT_LEN: usize = 10;
+/// The number of characters in the short form, without separators
+const SHORT_CHARS: usize = SHORT_LEN * 8 / 5;
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// The fingerprint of a public key. See the [module docs](crate::fingerprint).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    /// Computes the fingerprint of a serialized public key of the KEM with the given suite ID
+    pub(crate) fn new(kem_suite_id: &[u8], pk_bytes: &[u8]) -> Fingerprint {
+        let hash = Sha256::new()
+            .chain_update(FINGERPRINT_DOMAIN)
+            .chain_update(kem_suite_id)
+            .chain_update(pk_bytes)
+            .finalize();
+        Fingerprint(hash.into())
+    }
+
+    /// Returns the full fingerprint. Compare this when pinning keys.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns whether `short` is this fingerprint's short form, ignoring case, dashes, and spaces,
+    /// as it might be typed back in during out-of-band verification
+    pub fn matches_short(&self, short: &str) -> bool {
+        let expected = self.short_chars();
+        let mut given = short.bytes().filter(|c| *c != b'-' && *c != b' ');
+        expected
+            .iter()
+            .all(|c| given.next().map(|g| g.to_ascii_lowercase()) == Some(*c))
+            && given.next().is_none()
+    }
+
+    /// Returns the base32 characters of the short form
+    fn short_chars(&self) -> [u8; SHORT_CHARS] {
+        let mut chars = [0u8; SHORT_CHARS];
+        // Every 5 bytes are 40 bits, which is 8 characters
+        for (group, out) in self.0[..SHORT_LEN].chunks(5).zip(chars.chunks_mut(8)) {
+            let bits = group.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            for (i, c) in out.iter_mut().enumerate() {
+                *c = BASE32_ALPHABET[((bits >> (35 - 5 * i)) & 0x1f) as usize];
+            }
+        }
+        chars
+    }
+}
+
+/// Displays the short form, e.g., `4kx7-q2mz-a6cb-tw3d`
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, group) in self.short_chars().chunks(4).enumerate() {
+            if i > 0 {
+                f.write_str("-")?;
+            }
+            // The alphabet is ASCII
+            f.write_str(core::str::from_utf8(group).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs the short form, like `Display`
+#[cfg(feature = "defmt")]
+impl defmt::Format for Fingerprint {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        let chars = self.short_chars();
+        // The alphabet is ASCII
+        let group = |i: usize| core::str::from_utf8(&chars[4 * i..4 * i + 4]).unwrap();
+        defmt::write!(
+            f,
+            "{=str}-{=str}-{=str}-{=str}",
+            group(0),
+            group(1),
+            group(2),
+            group(3)
+        );
+    }
+}
+
+#[cfg(all(test, feature = "std", feature = "x25519", feature = "p256"))]
+mod tests {
+    use super::*;
+    use crate::{
+        kem::{DhP256HkdfSha256, Kem as KemTrait, X25519HkdfSha256},
+        Serializable,
+    };
+
+    use std::string::ToString;
+
+    /// Tests the fingerprint against a hash computed by hand, that the short form is formatted
+    /// and matched as documented, and that the KEM is bound in
+    #[test]
+    fn test_fingerprint() {
+        let (_, pk) = X25519HkdfSha256::derive_keypair(b"fingerprint test ikm");
+        let fp = X25519HkdfSha256::fingerprint(&pk);
+
+        let mut hash = Sha256::new();
+        hash.update(b"hpke-fingerprint-v1KEM\x00\x20");
+        hash.update(pk.to_bytes());
+        assert_eq!(fp.as_bytes()[..], hash.finalize()[..]);
+
+        let short = fp.to_string();
+        assert_eq!(short.len(), 19);
+        assert!(short
+            .split('-')
+            .all(|g| g.len() == 4 && g.bytes().all(|c| BASE32_ALPHABET.contains(&c))));
+        assert!(fp.matches_short(&short));
+        assert!(fp.matches_short(&short.to_uppercase().replace('-', " ")));
+        assert!(!fp.matches_short(&short[..18]));
+        assert!(!fp.matches_short(&(short.clone() + "a")));
+
+        // The same key bytes under another KEM's suite ID fingerprint differently
+        let (_, p256_pk) = DhP256HkdfSha256::derive_keypair(b"fingerprint test ikm");
+        assert_ne!(DhP256HkdfSha256::fingerprint(&p256_pk), fp);
+        assert_ne!(
+            Fingerprint::new(&DhP256HkdfSha256::SUITE_ID, &pk.to_bytes()),
+            fp
+        );
+    }
+}
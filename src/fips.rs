@@ -0,0 +1,173 @@
+//! A restricted API for deployments that may only use NIST-approved algorithms, e.g., ones
+//! seeking FIPS 140 validation.
+//!
+//! The functions here only accept the approved KEMs (DHKEM over P-256, P-384, and P-521), KDFs
+//! (HKDF-SHA256, HKDF-SHA384, and HKDF-SHA512), and AEADs (AES-128-GCM and AES-256-GCM). Passing
+//! anything else, e.g., `X25519HkdfSha256` or `ChaCha20Poly1305`, is a compile-time error. Before
+//! the first context is set up, they run [`self_test`](crate::self_test) and refuse to go on if
+//! it fails.
+//!
+//! This module does not remove the non-approved algorithms from the rest of the crate. To keep
+//! X25519 out of the binary entirely, also disable the `x25519` feature.
+
+use crate::{
+    aead::{Aead, AeadCtxR, AeadCtxS},
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    HpkeError, OpModeR, OpModeS,
+};
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use rand_core::{CryptoRng, RngCore};
+
+// Nobody outside this crate can mark an algorithm as approved
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A KEM approved for use in FIPS mode. This is implemented for the NIST curve DHKEMs.
+pub trait ApprovedKem: KemTrait + sealed::Sealed {}
+
+/// A KDF approved for use in FIPS mode. This is implemented for the HKDF-SHA2 family.
+pub trait ApprovedKdf: KdfTrait + sealed::Sealed {}
+
+/// An AEAD approved for use in FIPS mode. This is implemented for AES-GCM.
+pub trait ApprovedAead: Aead + sealed::Sealed {}
+
+// Marks each of the given types as approved under the given trait
+macro_rules! approve {
+    ($trait:ident, $($ty:ty),+) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            impl $trait for $ty {}
+        )+
+    };
+}
+
+#[cfg(feature = "p256")]
+approve!(ApprovedKem, crate::kem::DhP256HkdfSha256);
+#[cfg(feature = "p384")]
+approve!(ApprovedKem, crate::kem::DhP384HkdfSha384);
+#[cfg(feature = "p521")]
+approve!(ApprovedKem, crate::kem::DhP521HkdfSha512);
+approve!(
+    ApprovedKdf,
+    crate::kdf::HkdfSha256,
+    crate::kdf::HkdfSha384,
+    crate::kdf::HkdfSha512
+);
+approve!(ApprovedAead, crate::aead::AesGcm128, crate::aead::AesGcm256);
+
+// The outcome of the power-on self-test
+const SELF_TEST_NOT_RUN: u8 = 0;
+const SELF_TEST_PASSED: u8 = 1;
+const SELF_TEST_FAILED: u8 = 2;
+
+static SELF_TEST_STATE: AtomicU8 = AtomicU8::new(SELF_TEST_NOT_RUN);
+
+/// Runs [`self_test`](crate::self_test) if it hasn't been run yet, and remembers the outcome. The
+/// setup functions in this module call this, so there is no need to call it directly, except to
+/// pay its cost at a time of your choosing.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(())` if the self-test passed. Otherwise returns
+/// `Err(HpkeError::SelfTestFailed)`, now and on every later call.
+pub fn power_on_self_test() -> Result<(), HpkeError> {
+    // Two threads may race to run the test the first time. That's harmless, since it's
+    // deterministic.
+    let mut state = SELF_TEST_STATE.load(Ordering::Acquire);
+    if state == SELF_TEST_NOT_RUN {
+        state = if crate::self_test().passed() {
+            SELF_TEST_PASSED
+        } else {
+            SELF_TEST_FAILED
+        };
+        SELF_TEST_STATE.store(state, Ordering::Release);
+    }
+
+    if state == SELF_TEST_PASSED {
+        Ok(())
+    } else {
+        Err(HpkeError::SelfTestFailed)
+    }
+}
+
+/// Does [`setup_sender`](crate::setup_sender) with approved algorithms only, after making sure
+/// the power-on self-test has passed
+///
+/// Return Value
+/// ============
+/// If the self-test failed, returns `Err(HpkeError::SelfTestFailed)`. Otherwise returns what
+/// `setup_sender` returns.
+pub fn setup_sender<A, Kdf, Kem, R>(
+    mode: &OpModeS<Kem>,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    csprng: &mut R,
+) -> Result<(Kem::EncappedKey, AeadCtxS<A, Kdf, Kem>), HpkeError>
+where
+    A: ApprovedAead,
+    Kdf: ApprovedKdf,
+    Kem: ApprovedKem,
+    R: CryptoRng + RngCore,
+{
+    power_on_self_test()?;
+    crate::setup_sender(mode, pk_recip, info, csprng)
+}
+
+/// Does [`setup_receiver`](crate::setup_receiver) with approved algorithms only, after making
+/// sure the power-on self-test has passed
+///
+/// Return Value
+/// ============
+/// If the self-test failed, returns `Err(HpkeError::SelfTestFailed)`. Otherwise returns what
+/// `setup_receiver` returns.
+pub fn setup_receiver<A, Kdf, Kem>(
+    mode: &OpModeR<Kem>,
+    sk_recip: &Kem::PrivateKey,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: ApprovedAead,
+    Kdf: ApprovedKdf,
+    Kem: ApprovedKem,
+{
+    power_on_self_test()?;
+    crate::setup_receiver(mode, sk_recip, encapped_key, info)
+}
+
+#[cfg(all(test, feature = "p256"))]
+mod tests {
+    use super::*;
+    use crate::{aead::AesGcm128, kdf::HkdfSha256, kem::DhP256HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that the FIPS setup functions run the self-test and make working contexts
+    #[test]
+    fn test_fips_setup() {
+        type A = AesGcm128;
+        type Kdf = HkdfSha256;
+        type Kem = DhP256HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let info = b"fips";
+
+        let (encapped_key, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, info, &mut csprng).unwrap();
+        assert_eq!(SELF_TEST_STATE.load(Ordering::Acquire), SELF_TEST_PASSED);
+        let mut receiver_ctx =
+            setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk_recip, &encapped_key, info).unwrap();
+
+        let mut msg = *b"approved";
+        let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+        receiver_ctx
+            .open_in_place_detached(&mut msg, b"", &tag)
+            .unwrap();
+        assert_eq!(&msg, b"approved");
+    }
+}
@@ -0,0 +1,623 @@
+//! Fixed-capacity envelopes and fanout entries, for the no-`alloc` profile.
+//!
+//! These are the [`fanout`](crate::fanout) module's entries, and an owned envelope, with their
+//! variable-length fields in a [`heapless::Vec`](Vec) whose capacity is a const generic. They
+//! have the same wire formats as their allocating counterparts, so a sender with `alloc` and a
+//! receiver without it interoperate. Anything that doesn't fit its capacity is refused with
+//! `HpkeError::CapacityExceeded(needed, capacity)`. The [`Vec`] type is re-exported here.
+//!
+//! Formats
+//! =======
+//! An envelope is `key_id || I2OSP(kem_id, 2) || I2OSP(kdf_id, 2) || I2OSP(aead_id, 2) || enc ||
+//! ciphertext`, the same bytes that the `armor` module base64-encodes. Envelopes sealed with their
+//! header bound have its first 7 bytes prepended to the AAD, as the `armor` module's do. Fanout
+//! entries are in the `fanout` module's format.
+
+use crate::{
+    aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, single_shot_open_in_place,
+    single_shot_seal_in_place, Deserializable, HpkeError, OpModeR, OpModeS, Serializable,
+};
+
+use core::marker::PhantomData;
+
+use rand_core::{CryptoRng, RngCore};
+
+pub use ::heapless::Vec;
+
+/// The length of the key ID and the three algorithm IDs at the start of an envelope
+const HEADER_SIZE: usize = 7;
+
+/// The longest AAD, including the header, that an envelope with its header bound is sealed with
+const MAX_AAD_LEN: usize = 1024;
+
+/// The longest `aad` that an envelope with its header bound can be sealed or opened with
+pub const MAX_BOUND_AAD_LEN: usize = MAX_AAD_LEN - HEADER_SIZE;
+
+/// The longest `info` that fanout entries are sealed under
+const MAX_INFO_LEN: usize = 1024;
+/// The length of a fanout entry's `info`, other than the context and the recipient ID. That is,
+/// six 4-byte length prefixes and the names and protocol.
+const INFO_OVERHEAD: usize =
+    6 * 4 + b"protocol".len() + b"hpke-fanout".len() + b"context".len() + b"recipient".len();
+
+/// The most bytes that a fanout context and recipient ID can have together
+pub const MAX_CONTEXT_AND_ID_LEN: usize = MAX_INFO_LEN - INFO_OVERHEAD;
+
+/// A message sealed with `single_shot_seal` in the suite `(A, Kdf, Kem)`, to the key with ID
+/// `key_id`, whose ciphertext (including the tag) is at most `N` bytes
+pub struct Envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait, const N: usize> {
+    /// The ID of the recipient's key
+    pub key_id: u8,
+    /// The encapsulated key
+    pub encapped_key: Kem::EncappedKey,
+    /// The ciphertext, with the tag appended
+    pub ciphertext: Vec<u8, N>,
+    _suite: PhantomData<(A, Kdf)>,
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait, const N: usize> Envelope<A, Kdf, Kem, N> {
+    /// The length of the longest encoding of an envelope of this type
+    pub const MAX_ENCODED_LEN: usize = HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE + N;
+
+    /// Makes an envelope out of the output of a `single_shot_seal`
+    pub fn new(key_id: u8, encapped_key: Kem::EncappedKey, ciphertext: Vec<u8, N>) -> Self {
+        Envelope {
+            key_id,
+            encapped_key,
+            ciphertext,
+            _suite: PhantomData,
+        }
+    }
+
+    /// Does a `single_shot_seal` of `plaintext` to `pk_recip`, in the Base mode. If `bind_header`
+    /// is true, the envelope's header is prepended to `aad`. It should be true unless the
+    /// recipient doesn't expect that.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(envelope)` on success. If the ciphertext would be longer than `N` bytes, returns
+    /// `Err(HpkeError::CapacityExceeded(needed, N))`, and if `bind_header` is true and `aad` is
+    /// longer than [`MAX_BOUND_AAD_LEN`], returns `Err(HpkeError::CapacityExceeded(needed,
+    /// capacity))`. Otherwise, the errors are those of `single_shot_seal_in_place`.
+    pub fn seal<R: CryptoRng + RngCore>(
+        key_id: u8,
+        pk_recip: &Kem::PublicKey,
+        info: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+        bind_header: bool,
+        csprng: &mut R,
+    ) -> Result<Self, HpkeError> {
+        let bound_aad = bound_aad::<A, Kdf, Kem>(key_id, aad, bind_header)?;
+        let needed = plaintext.len() + A::TAG_SIZE;
+        let mut ciphertext = Vec::new();
+        ciphertext
+            .resize(needed, 0)
+            .map_err(|_| HpkeError::CapacityExceeded(needed, N))?;
+        ciphertext[..plaintext.len()].copy_from_slice(plaintext);
+
+        let (encapped_key, _) = single_shot_seal_in_place::<A, Kdf, Kem, R>(
+            &OpModeS::Base,
+            pk_recip,
+            info,
+            &mut ciphertext,
+            plaintext.len(),
+            bound_aad.as_deref().unwrap_or(aad),
+            csprng,
+        )?;
+        Ok(Envelope::new(key_id, encapped_key, ciphertext))
+    }
+
+    /// Opens the envelope with `sk_recip`, in the Base mode. `bind_header` must be what it was
+    /// when the envelope was sealed.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If `bind_header` is true and `aad` is longer than
+    /// [`MAX_BOUND_AAD_LEN`], returns `Err(HpkeError::CapacityExceeded(needed, capacity))`.
+    /// Otherwise, the errors are those of `single_shot_open_in_place`.
+    pub fn open(
+        &self,
+        sk_recip: &Kem::PrivateKey,
+        info: &[u8],
+        aad: &[u8],
+        bind_header: bool,
+    ) -> Result<Vec<u8, N>, HpkeError> {
+        let bound_aad = bound_aad::<A, Kdf, Kem>(self.key_id, aad, bind_header)?;
+        let mut buf = self.ciphertext.clone();
+        let plaintext_len = single_shot_open_in_place::<A, Kdf, Kem>(
+            &OpModeR::Base,
+            sk_recip,
+            &self.encapped_key,
+            info,
+            &mut buf,
+            bound_aad.as_deref().unwrap_or(aad),
+        )?
+        .len();
+        buf.truncate(plaintext_len);
+        Ok(buf)
+    }
+
+    /// Encodes the envelope into `out`, in the format in the [module docs](self)
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(encoding)`, the prefix of `out` that was written, on success. If `out` is too
+    /// short, returns `Err(HpkeError::IncorrectInputLength(needed, out.len()))`.
+    pub fn encode<'o>(&self, out: &'o mut [u8]) -> Result<&'o mut [u8], HpkeError> {
+        let needed = HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE + self.ciphertext.len();
+        if out.len() < needed {
+            return Err(HpkeError::IncorrectInputLength(needed, out.len()));
+        }
+
+        let out = &mut out[..needed];
+        out[..HEADER_SIZE].copy_from_slice(&header::<A, Kdf, Kem>(self.key_id));
+        let (enc, ciphertext) = out[HEADER_SIZE..].split_at_mut(Kem::ENCAPPED_KEY_SIZE);
+        enc.copy_from_slice(&self.encapped_key.to_bytes());
+        ciphertext.copy_from_slice(&self.ciphertext);
+        Ok(out)
+    }
+
+    /// Decodes an envelope encoded by [`Envelope::encode`]
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(envelope)` on success. If `bytes` is too short to be an envelope, or the
+    /// envelope is for a different suite, returns `Err(HpkeError::ValidationError)`. If the
+    /// ciphertext is longer than `N` bytes, returns `Err(HpkeError::CapacityExceeded(len, N))`.
+    /// Otherwise, the errors are those of `Kem::EncappedKey::from_bytes`.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, HpkeError> {
+        let header_bytes = take(&mut bytes, HEADER_SIZE)?;
+        if *header_bytes != header::<A, Kdf, Kem>(header_bytes[0]) {
+            return Err(HpkeError::ValidationError);
+        }
+
+        let encapped_key = Kem::EncappedKey::from_bytes(take(&mut bytes, Kem::ENCAPPED_KEY_SIZE)?)?;
+        let ciphertext = to_vec(bytes)?;
+        Ok(Envelope::new(header_bytes[0], encapped_key, ciphertext))
+    }
+}
+
+/// One recipient's copy of a fanout secret, with a recipient ID of at most `ID_CAP` bytes and a
+/// ciphertext of at most `CT_CAP` bytes
+pub struct FanoutEntry<Kem: KemTrait, const ID_CAP: usize, const CT_CAP: usize> {
+    /// The recipient's ID, e.g., a hash of its key package
+    pub recipient_id: Vec<u8, ID_CAP>,
+    /// The encapsulated key
+    pub encapped_key: Kem::EncappedKey,
+    /// The sealed secret
+    pub ciphertext: Vec<u8, CT_CAP>,
+}
+
+/// Does what [`fanout::fanout_seal`](crate::fanout::fanout_seal) does, into at most `N` entries
+///
+/// Return Value
+/// ============
+/// Returns `Ok(entries)` on success, one per recipient, in the same order. If there are more than
+/// `N` recipients, or a recipient ID or the ciphertext doesn't fit its capacity, returns
+/// `Err(HpkeError::CapacityExceeded(needed, capacity))`. This is also the error if `context` and a
+/// recipient ID are more than [`MAX_CONTEXT_AND_ID_LEN`] bytes together. If two recipients have
+/// the same ID, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `single_shot_seal_in_place`.
+pub fn fanout_seal<A, Kdf, Kem, R, const ID_CAP: usize, const CT_CAP: usize, const N: usize>(
+    recipients: &[(&[u8], &Kem::PublicKey)],
+    context: &[u8],
+    secret: &[u8],
+    csprng: &mut R,
+) -> Result<Vec<FanoutEntry<Kem, ID_CAP, CT_CAP>, N>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    if recipients.len() > N {
+        return Err(HpkeError::CapacityExceeded(recipients.len(), N));
+    }
+    for (i, (id, _)) in recipients.iter().enumerate() {
+        if recipients[..i].iter().any(|(other_id, _)| other_id == id) {
+            return Err(HpkeError::ValidationError);
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (id, pk_recip) in recipients {
+        let envelope = Envelope::<A, Kdf, Kem, CT_CAP>::seal(
+            0,
+            pk_recip,
+            &entry_info(context, id)?,
+            secret,
+            &[],
+            false,
+            csprng,
+        )?;
+        let entry = FanoutEntry {
+            recipient_id: to_vec(id)?,
+            encapped_key: envelope.encapped_key,
+            ciphertext: envelope.ciphertext,
+        };
+        // There are at most N recipients
+        if entries.push(entry).is_err() {
+            unreachable!("fanout entries outgrew their capacity");
+        }
+    }
+    Ok(entries)
+}
+
+/// Finds the entry for `recipient_id`, and opens it with `sk_recip`
+///
+/// Return Value
+/// ============
+/// Returns `Ok(secret)` on success. If no entry is for `recipient_id`, returns
+/// `Err(HpkeError::UnknownKeyId)`. If `context` and `recipient_id` are more than
+/// [`MAX_CONTEXT_AND_ID_LEN`] bytes together, returns `Err(HpkeError::CapacityExceeded(needed,
+/// capacity))`. Otherwise, the errors are those of `single_shot_open_in_place`.
+pub fn fanout_open<A, Kdf, Kem, const ID_CAP: usize, const CT_CAP: usize>(
+    entries: &[FanoutEntry<Kem, ID_CAP, CT_CAP>],
+    recipient_id: &[u8],
+    sk_recip: &Kem::PrivateKey,
+    context: &[u8],
+) -> Result<Vec<u8, CT_CAP>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let entry = entries
+        .iter()
+        .find(|entry| entry.recipient_id == recipient_id)
+        .ok_or(HpkeError::UnknownKeyId)?;
+    let mut buf = entry.ciphertext.clone();
+    let secret_len = single_shot_open_in_place::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_recip,
+        &entry.encapped_key,
+        &entry_info(context, recipient_id)?,
+        &mut buf,
+        &[],
+    )?
+    .len();
+    buf.truncate(secret_len);
+    Ok(buf)
+}
+
+/// Serializes the entries into `out`, in the `fanout` module's wire format
+///
+/// Return Value
+/// ============
+/// Returns `Ok(encoding)`, the prefix of `out` that was written, on success. If `out` is too
+/// short, returns `Err(HpkeError::IncorrectInputLength(needed, out.len()))`.
+///
+/// Panics
+/// ======
+/// Panics if a recipient ID or ciphertext is longer than `u32::MAX` bytes.
+pub fn encode_entries<'o, Kem: KemTrait, const ID_CAP: usize, const CT_CAP: usize>(
+    entries: &[FanoutEntry<Kem, ID_CAP, CT_CAP>],
+    out: &'o mut [u8],
+) -> Result<&'o mut [u8], HpkeError> {
+    let needed = entries
+        .iter()
+        .map(|entry| {
+            4 + entry.recipient_id.len() + Kem::ENCAPPED_KEY_SIZE + 4 + entry.ciphertext.len()
+        })
+        .sum();
+    if out.len() < needed {
+        return Err(HpkeError::IncorrectInputLength(needed, out.len()));
+    }
+
+    let mut rest = &mut out[..needed];
+    for entry in entries {
+        rest = put_u32_prefixed(rest, &entry.recipient_id);
+        let (enc, tail) = rest.split_at_mut(Kem::ENCAPPED_KEY_SIZE);
+        enc.copy_from_slice(&entry.encapped_key.to_bytes());
+        rest = put_u32_prefixed(tail, &entry.ciphertext);
+    }
+    Ok(&mut out[..needed])
+}
+
+/// Parses at most `N` entries serialized by [`encode_entries`] or by the `fanout` module
+///
+/// Return Value
+/// ============
+/// Returns `Ok(entries)` on success. If `bytes` ends in the middle of an entry, returns
+/// `Err(HpkeError::ValidationError)`. If a recipient ID or ciphertext doesn't fit its capacity,
+/// returns `Err(HpkeError::CapacityExceeded(len, capacity))`, and if there are more than `N`
+/// entries, returns `Err(HpkeError::CapacityExceeded(N + 1, N))`. If an encapsulated key is
+/// malformed, returns the error of `Kem::EncappedKey::from_bytes`.
+pub fn decode_entries<Kem: KemTrait, const ID_CAP: usize, const CT_CAP: usize, const N: usize>(
+    mut bytes: &[u8],
+) -> Result<Vec<FanoutEntry<Kem, ID_CAP, CT_CAP>, N>, HpkeError> {
+    let mut entries = Vec::new();
+    while !bytes.is_empty() {
+        let recipient_id = to_vec(take_u32_prefixed(&mut bytes)?)?;
+        let encapped_key = Kem::EncappedKey::from_bytes(take(&mut bytes, Kem::ENCAPPED_KEY_SIZE)?)?;
+        let ciphertext = to_vec(take_u32_prefixed(&mut bytes)?)?;
+        let entry = FanoutEntry {
+            recipient_id,
+            encapped_key,
+            ciphertext,
+        };
+        if entries.push(entry).is_err() {
+            return Err(HpkeError::CapacityExceeded(N + 1, N));
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the header of an envelope to the key with ID `key_id`
+fn header<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(key_id: u8) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[0] = key_id;
+    header[1..3].copy_from_slice(&Kem::KEM_ID.to_be_bytes());
+    header[3..5].copy_from_slice(&Kdf::KDF_ID.to_be_bytes());
+    header[5..7].copy_from_slice(&A::AEAD_ID.to_be_bytes());
+    header
+}
+
+/// Returns the header of an envelope to the key with ID `key_id`, followed by `aad`, if
+/// `bind_header` is true, and `None` otherwise
+fn bound_aad<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    key_id: u8,
+    aad: &[u8],
+    bind_header: bool,
+) -> Result<Option<Vec<u8, MAX_AAD_LEN>>, HpkeError> {
+    if !bind_header {
+        return Ok(None);
+    }
+    let needed = HEADER_SIZE + aad.len();
+    if needed > MAX_AAD_LEN {
+        return Err(HpkeError::CapacityExceeded(needed, MAX_AAD_LEN));
+    }
+
+    let mut bound = Vec::new();
+    // The length was checked above, so this fits
+    let _ = bound.extend_from_slice(&header::<A, Kdf, Kem>(key_id));
+    let _ = bound.extend_from_slice(aad);
+    Ok(Some(bound))
+}
+
+/// Returns the `info` that the entry for `recipient_id` is sealed under. This is what the
+/// `fanout` module's `InfoBuilder` makes.
+fn entry_info(context: &[u8], recipient_id: &[u8]) -> Result<Vec<u8, MAX_INFO_LEN>, HpkeError> {
+    let needed = INFO_OVERHEAD + context.len() + recipient_id.len();
+    if needed > MAX_INFO_LEN {
+        return Err(HpkeError::CapacityExceeded(needed, MAX_INFO_LEN));
+    }
+
+    let mut info = Vec::new();
+    let fields = [
+        (&b"protocol"[..], &b"hpke-fanout"[..]),
+        (&b"context"[..], context),
+        (&b"recipient"[..], recipient_id),
+    ];
+    for (name, value) in fields {
+        for part in [name, value] {
+            // The length was checked above, so this all fits, and each part is shorter than 2^32
+            let _ = info.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            let _ = info.extend_from_slice(part);
+        }
+    }
+    Ok(info)
+}
+
+/// Copies `bytes` into a `Vec` of capacity `CAP`
+fn to_vec<const CAP: usize>(bytes: &[u8]) -> Result<Vec<u8, CAP>, HpkeError> {
+    Vec::from_slice(bytes).map_err(|_| HpkeError::CapacityExceeded(bytes.len(), CAP))
+}
+
+/// Writes `bytes`, prefixed with its 4-byte big-endian length, to the front of `out`, and returns
+/// the rest of `out`
+fn put_u32_prefixed<'o>(out: &'o mut [u8], bytes: &[u8]) -> &'o mut [u8] {
+    let len = u32::try_from(bytes.len()).expect("fanout entry field is too long");
+    let (len_out, rest) = out.split_at_mut(4);
+    len_out.copy_from_slice(&len.to_be_bytes());
+    let (bytes_out, rest) = rest.split_at_mut(bytes.len());
+    bytes_out.copy_from_slice(bytes);
+    rest
+}
+
+/// Splits `len` bytes off the front of `bytes`
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], HpkeError> {
+    if bytes.len() < len {
+        return Err(HpkeError::ValidationError);
+    }
+    let (head, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(head)
+}
+
+/// Splits a 4-byte big-endian length, and that many bytes, off the front of `bytes`, and returns
+/// the latter
+fn take_u32_prefixed<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], HpkeError> {
+    let len = take(bytes, 4)?;
+    let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]);
+    take(bytes, len as usize)
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests an envelope round trip through the wire format, and that values that don't fit
+    /// their capacity are refused
+    #[test]
+    fn test_envelope() {
+        type Env = Envelope<A, Kdf, Kem, 32>;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let env = Env::seal(
+            9,
+            &pk_recip,
+            b"info",
+            b"sixteen byte msg",
+            b"aad",
+            true,
+            &mut csprng,
+        )
+        .unwrap();
+
+        let mut buf = [0u8; Env::MAX_ENCODED_LEN];
+        let encoded = env.encode(&mut buf).unwrap();
+        assert_eq!(encoded.len(), Env::MAX_ENCODED_LEN);
+        assert_eq!(encoded[..7], [9, 0x00, 0x20, 0x00, 0x01, 0x00, 0x03]);
+        let decoded = Env::decode(encoded).unwrap();
+        assert_eq!(decoded.key_id, 9);
+        assert_eq!(
+            decoded.open(&sk_recip, b"info", b"aad", true).unwrap(),
+            &b"sixteen byte msg"[..]
+        );
+
+        assert_eq!(
+            Env::seal(9, &pk_recip, b"info", &[0u8; 17], b"", true, &mut csprng).err(),
+            Some(HpkeError::CapacityExceeded(33, 32))
+        );
+        assert_eq!(
+            env.open(&sk_recip, b"info", &[0u8; MAX_BOUND_AAD_LEN + 1], true)
+                .err(),
+            Some(HpkeError::CapacityExceeded(MAX_AAD_LEN + 1, MAX_AAD_LEN))
+        );
+        assert_eq!(
+            Envelope::<A, Kdf, Kem, 31>::decode(encoded).err(),
+            Some(HpkeError::CapacityExceeded(32, 31))
+        );
+        assert_eq!(
+            env.encode(&mut buf[..Env::MAX_ENCODED_LEN - 1]).err(),
+            Some(HpkeError::IncorrectInputLength(
+                Env::MAX_ENCODED_LEN,
+                Env::MAX_ENCODED_LEN - 1
+            ))
+        );
+    }
+
+    /// Tests that changing an envelope's key ID makes it fail to open when the header is bound but
+    /// not when it isn't, that both ends must agree on binding, and that a bound envelope opens
+    /// with the `armor` module when it's available
+    #[test]
+    fn test_envelope_header_binding() {
+        type Env = Envelope<A, Kdf, Kem, 32>;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        for bind_header in [true, false] {
+            let env = Env::seal(
+                9,
+                &pk_recip,
+                b"info",
+                b"msg",
+                b"aad",
+                bind_header,
+                &mut csprng,
+            )
+            .unwrap();
+            assert_eq!(
+                env.open(&sk_recip, b"info", b"aad", !bind_header).err(),
+                Some(HpkeError::OpenError)
+            );
+
+            let mut buf = [0u8; Env::MAX_ENCODED_LEN];
+            let encoded = env.encode(&mut buf).unwrap();
+            #[cfg(feature = "armor")]
+            {
+                let armored = crate::armor::armor_envelope::<A, Kdf, Kem>(
+                    9,
+                    &env.encapped_key,
+                    &env.ciphertext,
+                );
+                assert_eq!(
+                    crate::armor::open_armored::<A, Kdf, Kem>(
+                        &sk_recip,
+                        b"info",
+                        b"aad",
+                        bind_header,
+                        &armored
+                    )
+                    .unwrap(),
+                    b"msg"
+                );
+            }
+
+            // Key ID 9 becomes 8
+            encoded[0] ^= 0x01;
+            let tampered = Env::decode(encoded).unwrap();
+            let opened = tampered.open(&sk_recip, b"info", b"aad", bind_header);
+            if bind_header {
+                assert_eq!(opened.err(), Some(HpkeError::OpenError));
+            } else {
+                assert_eq!(opened.unwrap(), &b"msg"[..]);
+            }
+        }
+    }
+
+    /// Tests that fanout entries round trip, interoperate with the `fanout` module's when it's
+    /// available, and that too many recipients or too long an ID are refused
+    #[test]
+    fn test_fanout() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk_alice, pk_alice) = Kem::gen_keypair(&mut csprng);
+        let (sk_bob, pk_bob) = Kem::gen_keypair(&mut csprng);
+        let recipients = [(&b"alice"[..], &pk_alice), (&b"bob"[..], &pk_bob)];
+        let (context, secret) = (b"group 1, epoch 5", b"joiner secret");
+
+        let entries: Vec<FanoutEntry<Kem, 8, 32>, 2> =
+            fanout_seal::<A, Kdf, Kem, _, 8, 32, 2>(&recipients, context, secret, &mut csprng)
+                .unwrap();
+        let mut buf = [0u8; 256];
+        let encoded = encode_entries(&entries, &mut buf).unwrap();
+        let decoded = decode_entries::<Kem, 8, 32, 2>(encoded).unwrap();
+        assert_eq!(
+            fanout_open::<A, Kdf, Kem, 8, 32>(&decoded, b"bob", &sk_bob, context).unwrap(),
+            &secret[..]
+        );
+        assert_eq!(
+            fanout_open::<A, Kdf, Kem, 8, 32>(&decoded, b"alice", &sk_bob, context).err(),
+            Some(HpkeError::OpenError)
+        );
+
+        #[cfg(feature = "fanout")]
+        {
+            let allocated = crate::fanout::decode_entries::<Kem>(encoded).unwrap();
+            assert_eq!(
+                crate::fanout::fanout_open::<A, Kdf, Kem>(&allocated, b"alice", &sk_alice, context)
+                    .unwrap(),
+                secret
+            );
+            let reencoded = crate::fanout::encode_entries(&allocated);
+            assert_eq!(reencoded, encoded);
+        }
+        #[cfg(not(feature = "fanout"))]
+        let _ = sk_alice;
+
+        assert_eq!(
+            decode_entries::<Kem, 8, 32, 1>(encoded).err(),
+            Some(HpkeError::CapacityExceeded(2, 1))
+        );
+        assert_eq!(
+            decode_entries::<Kem, 4, 32, 2>(encoded).err(),
+            Some(HpkeError::CapacityExceeded(5, 4))
+        );
+        assert_eq!(
+            fanout_seal::<A, Kdf, Kem, _, 8, 32, 1>(&recipients, context, secret, &mut csprng)
+                .err(),
+            Some(HpkeError::CapacityExceeded(2, 1))
+        );
+        let long_context = [0u8; MAX_CONTEXT_AND_ID_LEN];
+        assert_eq!(
+            fanout_seal::<A, Kdf, Kem, _, 8, 32, 2>(
+                &recipients,
+                &long_context,
+                secret,
+                &mut csprng
+            )
+            .err(),
+            Some(HpkeError::CapacityExceeded(MAX_INFO_LEN + 5, MAX_INFO_LEN))
+        );
+    }
+}
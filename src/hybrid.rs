@@ -0,0 +1,385 @@
+//! Sealing to recipients that publish two public keys under different KEMs, e.g., a classical one
+//! and a post-quantum one, for hybrid protection without a combined KEM.
+//!
+//! [`hybrid_seal`] picks a fresh payload secret, wraps a share of it to each of the recipient's
+//! keys with a single-shot base-mode seal, and encrypts the message under a context derived from
+//! the payload secret. What it takes to open depends on the [`HybridPolicy`]:
+//!
+//! * `Both`: the two shares are independent, and the payload secret is derived from both. An
+//!   attacker has to break both KEMs to read the message. This is what hybrid usually means.
+//! * `Either`: both shares are the payload secret itself. The recipient can open with either
+//!   private key, e.g., while one of them is still being rolled out. An attacker only has to
+//!   break one of the KEMs.
+//!
+//! The policy, `info`, and both encapsulated keys are bound into the payload context, so the
+//! pieces of one ciphertext can't be mixed with another's, and a ciphertext only opens under the
+//! policy it was sealed with. How the pieces of a [`HybridCiphertext`] are laid out on the wire is
+//! up to the application.
+
+use crate::{
+    aead::{Aead, AeadCtx, AeadCtxR, AeadCtxS},
+    kdf::{labeled_extract, Kdf as KdfTrait},
+    kem::Kem as KemTrait,
+    single_shot_open, single_shot_seal, HpkeError, InfoBuilder, OpModeR, OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+/// The size of each share of the payload secret, i.e., the plaintext of each wrapped key
+pub const PAYLOAD_SHARE_SIZE: usize = 32;
+
+/// Which of the recipient's private keys it takes to open a hybrid ciphertext. The sender and
+/// recipient must agree on this ahead of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HybridPolicy {
+    /// Either private key opens the ciphertext
+    Either,
+    /// Both private keys are needed to open the ciphertext
+    Both,
+}
+
+impl HybridPolicy {
+    fn id(&self) -> u8 {
+        match self {
+            HybridPolicy::Either => 0x00,
+            HybridPolicy::Both => 0x01,
+        }
+    }
+}
+
+/// The output of [`hybrid_seal`]. Send all of it to the recipient.
+pub struct HybridCiphertext<Kem1: KemTrait, Kem2: KemTrait> {
+    /// The encapsulated key for the first public key
+    pub encapped_key1: Kem1::EncappedKey,
+    /// The payload secret share wrapped to the first public key
+    pub wrapped_share1: crate::Vec<u8>,
+    /// The encapsulated key for the second public key
+    pub encapped_key2: Kem2::EncappedKey,
+    /// The payload secret share wrapped to the second public key
+    pub wrapped_share2: crate::Vec<u8>,
+    /// The encrypted message
+    pub ciphertext: crate::Vec<u8>,
+}
+
+/// Encrypts `plaintext` to a recipient with the public keys `pk_recip1` and `pk_recip2`, such that
+/// opening it takes what `policy` says. The shares of the payload secret are wrapped with the
+/// ciphersuites `(Kem1, Kdf, A)` and `(Kem2, Kdf, A)`, and the message is encrypted with `A`.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(ciphertext)` on success. If `csprng` failed, returns `Err(HpkeError::RngError)`.
+/// Otherwise, the errors are those of `single_shot_seal`.
+pub fn hybrid_seal<A, Kdf, Kem1, Kem2, R>(
+    policy: HybridPolicy,
+    pk_recip1: &Kem1::PublicKey,
+    pk_recip2: &Kem2::PublicKey,
+    info: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    csprng: &mut R,
+) -> Result<HybridCiphertext<Kem1, Kem2>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem1: KemTrait,
+    Kem2: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    let mut shares = [[0u8; PAYLOAD_SHARE_SIZE]; 2];
+    csprng
+        .try_fill_bytes(&mut shares[0])
+        .map_err(|_| HpkeError::RngError)?;
+    shares[1] = match policy {
+        HybridPolicy::Either => shares[0],
+        HybridPolicy::Both => {
+            let mut share = [0u8; PAYLOAD_SHARE_SIZE];
+            csprng
+                .try_fill_bytes(&mut share)
+                .map_err(|_| HpkeError::RngError)?;
+            share
+        }
+    };
+
+    let res = (|| {
+        let (encapped_key1, wrapped_share1) = single_shot_seal::<A, Kdf, Kem1, R>(
+            &OpModeS::Base,
+            pk_recip1,
+            &wrap_info(policy, 1, info),
+            &shares[0],
+            &[],
+            csprng,
+        )?;
+        let (encapped_key2, wrapped_share2) = single_shot_seal::<A, Kdf, Kem2, R>(
+            &OpModeS::Base,
+            pk_recip2,
+            &wrap_info(policy, 2, info),
+            &shares[1],
+            &[],
+            csprng,
+        )?;
+
+        let ctx = payload_ctx::<A, Kdf, Kem1, Kem2>(
+            policy,
+            &shares,
+            &encapped_key1,
+            &encapped_key2,
+            info,
+        );
+        let ciphertext = AeadCtxS::from(ctx).seal(plaintext, aad)?;
+
+        Ok(HybridCiphertext {
+            encapped_key1,
+            wrapped_share1,
+            encapped_key2,
+            wrapped_share2,
+            ciphertext,
+        })
+    })();
+    shares.zeroize();
+    res
+}
+
+/// Decrypts a ciphertext made by [`hybrid_seal`] with the same `policy`, `info`, and `aad`. Pass
+/// the private keys you have. Under `HybridPolicy::Either`, one is enough. If both are given,
+/// the first is tried first.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)` on success. If `policy` needs a private key that isn't given, returns
+/// `Err(HpkeError::ValidationError)`. If a wrapped share or the ciphertext fails to open, or the
+/// ciphertext was sealed under a different policy, returns `Err(HpkeError::OpenError)`. Otherwise,
+/// the errors are those of `single_shot_open`.
+pub fn hybrid_open<A, Kdf, Kem1, Kem2>(
+    policy: HybridPolicy,
+    sk_recip1: Option<&Kem1::PrivateKey>,
+    sk_recip2: Option<&Kem2::PrivateKey>,
+    info: &[u8],
+    ciphertext: &HybridCiphertext<Kem1, Kem2>,
+    aad: &[u8],
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem1: KemTrait,
+    Kem2: KemTrait,
+{
+    let unwrap1 = |sk: &Kem1::PrivateKey| {
+        unwrap_share(single_shot_open::<A, Kdf, Kem1>(
+            &OpModeR::Base,
+            sk,
+            &ciphertext.encapped_key1,
+            &wrap_info(policy, 1, info),
+            &ciphertext.wrapped_share1,
+            &[],
+        ))
+    };
+    let unwrap2 = |sk: &Kem2::PrivateKey| {
+        unwrap_share(single_shot_open::<A, Kdf, Kem2>(
+            &OpModeR::Base,
+            sk,
+            &ciphertext.encapped_key2,
+            &wrap_info(policy, 2, info),
+            &ciphertext.wrapped_share2,
+            &[],
+        ))
+    };
+
+    let mut shares = match (policy, sk_recip1, sk_recip2) {
+        (HybridPolicy::Both, Some(sk1), Some(sk2)) => [unwrap1(sk1)?, unwrap2(sk2)?],
+        (HybridPolicy::Either, Some(sk1), sk2) => {
+            // Fall back to the second key if the first one's share doesn't open
+            let share = match (unwrap1(sk1), sk2) {
+                (Err(_), Some(sk2)) => unwrap2(sk2)?,
+                (res, _) => res?,
+            };
+            [share, share]
+        }
+        (HybridPolicy::Either, None, Some(sk2)) => {
+            let share = unwrap2(sk2)?;
+            [share, share]
+        }
+        _ => return Err(HpkeError::ValidationError),
+    };
+
+    let ctx = payload_ctx::<A, Kdf, Kem1, Kem2>(
+        policy,
+        &shares,
+        &ciphertext.encapped_key1,
+        &ciphertext.encapped_key2,
+        info,
+    );
+    shares.zeroize();
+    AeadCtxR::from(ctx).open(&ciphertext.ciphertext, aad)
+}
+
+/// Returns the `info` that the share for key number `index` is wrapped under
+fn wrap_info(policy: HybridPolicy, index: u8, info: &[u8]) -> crate::Vec<u8> {
+    InfoBuilder::new(b"hpke-hybrid-share")
+        .field(b"policy", &[policy.id()])
+        .field(b"index", &[index])
+        .field(b"info", info)
+        .build()
+}
+
+/// Checks that an unwrapped share is the right size, and copies it out
+fn unwrap_share(
+    res: Result<crate::Vec<u8>, HpkeError>,
+) -> Result<[u8; PAYLOAD_SHARE_SIZE], HpkeError> {
+    let mut share_vec = res?;
+    let share = share_vec
+        .as_slice()
+        .try_into()
+        .map_err(|_| HpkeError::OpenError);
+    share_vec.zeroize();
+    share
+}
+
+/// Derives the context that the message is encrypted under from the shares of the payload secret,
+/// and binds in everything else that makes up the ciphertext
+fn payload_ctx<A, Kdf, Kem1, Kem2>(
+    policy: HybridPolicy,
+    shares: &[[u8; PAYLOAD_SHARE_SIZE]; 2],
+    encapped_key1: &Kem1::EncappedKey,
+    encapped_key2: &Kem2::EncappedKey,
+    info: &[u8],
+) -> AeadCtx<A, Kdf, Kem1>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem1: KemTrait,
+    Kem2: KemTrait,
+{
+    let suite_id = AeadCtx::<A, Kdf, Kem1>::SUITE_ID;
+    let mut ikm = [0u8; 2 * PAYLOAD_SHARE_SIZE];
+    ikm[..PAYLOAD_SHARE_SIZE].copy_from_slice(&shares[0]);
+    ikm[PAYLOAD_SHARE_SIZE..].copy_from_slice(&shares[1]);
+    let (_, prk) = labeled_extract::<Kdf>(&[], &suite_id, b"hybrid_secret", &ikm);
+    ikm.zeroize();
+    let context = InfoBuilder::new(b"hpke-hybrid-payload")
+        .field(b"policy", &[policy.id()])
+        .field(b"kem_id2", &Kem2::KEM_ID.to_be_bytes())
+        .field(b"enc1", &encapped_key1.to_bytes())
+        .field(b"enc2", &encapped_key2.to_bytes())
+        .field(b"info", info)
+        .build();
+
+    AeadCtx::from_prk(
+        &prk,
+        [b"hybrid_key", b"hybrid_base_nonce", b"hybrid_exp"],
+        &[&context],
+    )
+}
+
+#[cfg(all(test, feature = "x25519", feature = "p256"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305,
+        kdf::HkdfSha256,
+        kem::{DhP256HkdfSha256, X25519HkdfSha256},
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that each policy opens with the keys it should and only those, and that tampering
+    /// with any piece, or opening under the wrong policy, fails
+    #[test]
+    fn test_hybrid_seal_open() {
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem1 = X25519HkdfSha256;
+        type Kem2 = DhP256HkdfSha256;
+        let open = hybrid_open::<A, Kdf, Kem1, Kem2>;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk1, pk1) = Kem1::gen_keypair(&mut csprng);
+        let (sk2, pk2) = Kem2::gen_keypair(&mut csprng);
+        let (info, msg, aad) = (b"info", b"hybrid message", b"aad");
+
+        let seal = |policy, csprng: &mut StdRng| {
+            hybrid_seal::<A, Kdf, Kem1, Kem2, _>(policy, &pk1, &pk2, info, msg, aad, csprng)
+                .unwrap()
+        };
+
+        // Both keys are needed under Both
+        let both = seal(HybridPolicy::Both, &mut csprng);
+        let pt = open(HybridPolicy::Both, Some(&sk1), Some(&sk2), info, &both, aad);
+        assert_eq!(pt.unwrap(), msg);
+        for (sk1, sk2) in [(Some(&sk1), None), (None, Some(&sk2))] {
+            let res = open(HybridPolicy::Both, sk1, sk2, info, &both, aad);
+            assert_eq!(res, Err(HpkeError::ValidationError));
+        }
+        // The shares aren't the payload secret, so opening as Either fails
+        let res = open(HybridPolicy::Either, Some(&sk1), None, info, &both, aad);
+        assert_eq!(res, Err(HpkeError::OpenError));
+
+        // Either key is enough under Either
+        let either = seal(HybridPolicy::Either, &mut csprng);
+        for (sk1, sk2) in [
+            (Some(&sk1), None),
+            (None, Some(&sk2)),
+            (Some(&sk1), Some(&sk2)),
+        ] {
+            let pt = open(HybridPolicy::Either, sk1, sk2, info, &either, aad);
+            assert_eq!(pt.unwrap(), msg);
+        }
+        let res = open(
+            HybridPolicy::Both,
+            Some(&sk1),
+            Some(&sk2),
+            info,
+            &either,
+            aad,
+        );
+        assert_eq!(res, Err(HpkeError::OpenError));
+
+        // With both keys, a corrupted first share falls back to the second
+        let mut tampered = seal(HybridPolicy::Either, &mut csprng);
+        tampered.wrapped_share1[0] ^= 1;
+        let pt = open(
+            HybridPolicy::Either,
+            Some(&sk1),
+            Some(&sk2),
+            info,
+            &tampered,
+            aad,
+        );
+        assert_eq!(pt.unwrap(), msg);
+        let res = open(HybridPolicy::Either, Some(&sk1), None, info, &tampered, aad);
+        assert_eq!(res, Err(HpkeError::OpenError));
+
+        // Pieces of another ciphertext don't fit
+        let mut mixed = seal(HybridPolicy::Both, &mut csprng);
+        mixed.ciphertext = both.ciphertext.clone();
+        let res = open(
+            HybridPolicy::Both,
+            Some(&sk1),
+            Some(&sk2),
+            info,
+            &mixed,
+            aad,
+        );
+        assert_eq!(res, Err(HpkeError::OpenError));
+
+        // The wrong info or AAD fails
+        let res = open(
+            HybridPolicy::Both,
+            Some(&sk1),
+            Some(&sk2),
+            b"other",
+            &both,
+            aad,
+        );
+        assert_eq!(res, Err(HpkeError::OpenError));
+        let res = open(
+            HybridPolicy::Both,
+            Some(&sk1),
+            Some(&sk2),
+            info,
+            &both,
+            b"other",
+        );
+        assert_eq!(res, Err(HpkeError::OpenError));
+    }
+}
@@ -1,92 +1,30 @@
 use crate::{
-    aead::{Aead, AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead},
+    aead::{Aead, AeadCtx, AeadCtxS, AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead},
     kdf::{HkdfSha256, HkdfSha384, HkdfSha512, Kdf as KdfTrait},
     kem::{
-        self, DhP256HkdfSha256, DhP384HkdfSha384, DhP521HkdfSha512, Kem as KemTrait, SharedSecret,
-        X25519HkdfSha256,
+        DhP256HkdfSha256, DhP384HkdfSha384, DhP521HkdfSha512, Kem as KemTrait, X25519HkdfSha256,
+    },
+    setup::{key_schedule, key_schedule_context, setup_receiver, KeySchedule},
+    testing::vectors::{
+        for_each_test_vector, gen_negative_cases, gen_test_case, keep_encryptions, make_op_mode_r,
+        run_negative_case, EncryptionTestVector, MainTestVector, RFC_SEQS,
     },
-    op_mode::{OpModeR, PskBundle},
-    setup::setup_receiver,
     Deserializable, HpkeError, Serializable,
 };
 
 extern crate std;
-use std::{fs::File, string::String, vec::Vec};
-
-use hex;
-use serde::{de::Error as SError, Deserialize, Deserializer};
-use serde_json;
-
-// For known-answer tests we need to be able to encap with fixed randomness. This allows that.
-trait TestableKem: KemTrait {
-    /// The ephemeral key used in encapsulation. This is the same thing as a private key in the
-    /// case of DHKEM, but this is not always true
-    type EphemeralKey: Deserializable;
-
-    // Encap with fixed randomness
-    #[doc(hidden)]
-    fn encap_with_eph(
-        pk_recip: &Self::PublicKey,
-        sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
-        sk_eph: Self::EphemeralKey,
-    ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError>;
-}
-
-// Now implement TestableKem for all the KEMs in the KAT
-impl TestableKem for X25519HkdfSha256 {
-    // In DHKEM, ephemeral keys and private keys are both scalars
-    type EphemeralKey = <X25519HkdfSha256 as KemTrait>::PrivateKey;
-
-    // Call the x25519 deterministic encap function we defined in dhkem.rs
-    fn encap_with_eph(
-        pk_recip: &Self::PublicKey,
-        sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
-        sk_eph: Self::EphemeralKey,
-    ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
-        kem::x25519_hkdfsha256::encap_with_eph(pk_recip, sender_id_keypair, sk_eph)
-    }
-}
-impl TestableKem for DhP256HkdfSha256 {
-    // In DHKEM, ephemeral keys and private keys are both scalars
-    type EphemeralKey = <DhP256HkdfSha256 as KemTrait>::PrivateKey;
-
-    // Call the p256 deterministic encap function we defined in dhkem.rs
-    fn encap_with_eph(
-        pk_recip: &Self::PublicKey,
-        sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
-        sk_eph: Self::EphemeralKey,
-    ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
-        kem::dhp256_hkdfsha256::encap_with_eph(pk_recip, sender_id_keypair, sk_eph)
-    }
-}
-
-impl TestableKem for DhP384HkdfSha384 {
-    // In DHKEM, ephemeral keys and private keys are both scalars
-    type EphemeralKey = <DhP384HkdfSha384 as KemTrait>::PrivateKey;
-
-    // Call the p384 deterministic encap function we defined in dhkem.rs
-    fn encap_with_eph(
-        pk_recip: &Self::PublicKey,
-        sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
-        sk_eph: Self::EphemeralKey,
-    ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
-        kem::dhp384_hkdfsha384::encap_with_eph(pk_recip, sender_id_keypair, sk_eph)
-    }
-}
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    fs::File,
+    io::BufReader,
+    string::String,
+    vec::Vec,
+};
 
-impl TestableKem for DhP521HkdfSha512 {
-    // In DHKEM, ephemeral keys and private keys are both scalars
-    type EphemeralKey = <DhP521HkdfSha512 as KemTrait>::PrivateKey;
-
-    // Call the p521 deterministic encap function we defined in dhkem.rs
-    fn encap_with_eph(
-        pk_recip: &Self::PublicKey,
-        sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
-        sk_eph: Self::EphemeralKey,
-    ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
-        kem::dhp521_hkdfsha512::encap_with_eph(pk_recip, sender_id_keypair, sk_eph)
-    }
-}
+use digest::Digest;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::de::IgnoredAny;
 
 /// Asserts that the given serializable values are equal
 macro_rules! assert_serializable_eq {
@@ -95,110 +33,6 @@ macro_rules! assert_serializable_eq {
     };
 }
 
-// Tells serde how to deserialize bytes from the hex representation
-fn bytes_from_hex<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let mut hex_str = String::deserialize(deserializer)?;
-    // Prepend a 0 if it's not even length
-    if hex_str.len() % 2 == 1 {
-        hex_str.insert(0, '0');
-    }
-    hex::decode(hex_str).map_err(|e| SError::custom(format!("{:?}", e)))
-}
-
-// Tells serde how to deserialize bytes from an optional field with hex encoding
-fn bytes_from_hex_opt<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    bytes_from_hex(deserializer).map(|v| Some(v))
-}
-
-// Each individual test case looks like this
-#[derive(Clone, serde::Deserialize, Debug)]
-struct MainTestVector {
-    // Parameters
-    mode: u8,
-    kem_id: u16,
-    kdf_id: u16,
-    aead_id: u16,
-    #[serde(deserialize_with = "bytes_from_hex")]
-    info: Vec<u8>,
-
-    // Keying material
-    #[serde(rename = "ikmR", deserialize_with = "bytes_from_hex")]
-    ikm_recip: Vec<u8>,
-    #[serde(default, rename = "ikmS", deserialize_with = "bytes_from_hex_opt")]
-    ikm_sender: Option<Vec<u8>>,
-    #[serde(rename = "ikmE", deserialize_with = "bytes_from_hex")]
-    _ikm_eph: Vec<u8>,
-
-    // Private keys
-    #[serde(rename = "skRm", deserialize_with = "bytes_from_hex")]
-    sk_recip: Vec<u8>,
-    #[serde(default, rename = "skSm", deserialize_with = "bytes_from_hex_opt")]
-    sk_sender: Option<Vec<u8>>,
-    #[serde(rename = "skEm", deserialize_with = "bytes_from_hex")]
-    sk_eph: Vec<u8>,
-
-    // Preshared Key Bundle
-    #[serde(default, deserialize_with = "bytes_from_hex_opt")]
-    psk: Option<Vec<u8>>,
-    #[serde(default, rename = "psk_id", deserialize_with = "bytes_from_hex_opt")]
-    psk_id: Option<Vec<u8>>,
-
-    // Public Keys
-    #[serde(rename = "pkRm", deserialize_with = "bytes_from_hex")]
-    pk_recip: Vec<u8>,
-    #[serde(default, rename = "pkSm", deserialize_with = "bytes_from_hex_opt")]
-    pk_sender: Option<Vec<u8>>,
-    #[serde(rename = "pkEm", deserialize_with = "bytes_from_hex")]
-    _pk_eph: Vec<u8>,
-
-    // Key schedule inputs and computations
-    #[serde(rename = "enc", deserialize_with = "bytes_from_hex")]
-    encapped_key: Vec<u8>,
-    #[serde(deserialize_with = "bytes_from_hex")]
-    shared_secret: Vec<u8>,
-    #[serde(rename = "key_schedule_context", deserialize_with = "bytes_from_hex")]
-    _hpke_context: Vec<u8>,
-    #[serde(rename = "secret", deserialize_with = "bytes_from_hex")]
-    _key_schedule_secret: Vec<u8>,
-    #[serde(rename = "key", deserialize_with = "bytes_from_hex")]
-    _aead_key: Vec<u8>,
-    #[serde(rename = "base_nonce", deserialize_with = "bytes_from_hex")]
-    _aead_base_nonce: Vec<u8>,
-    #[serde(rename = "exporter_secret", deserialize_with = "bytes_from_hex")]
-    _exporter_secret: Vec<u8>,
-
-    encryptions: Vec<EncryptionTestVector>,
-    exports: Vec<ExporterTestVector>,
-}
-
-#[derive(Clone, serde::Deserialize, Debug)]
-struct EncryptionTestVector {
-    #[serde(rename = "pt", deserialize_with = "bytes_from_hex")]
-    plaintext: Vec<u8>,
-    #[serde(deserialize_with = "bytes_from_hex")]
-    aad: Vec<u8>,
-    #[serde(rename = "nonce", deserialize_with = "bytes_from_hex")]
-    _nonce: Vec<u8>,
-    #[serde(rename = "ct", deserialize_with = "bytes_from_hex")]
-    ciphertext: Vec<u8>,
-}
-
-#[derive(Clone, serde::Deserialize, Debug)]
-struct ExporterTestVector {
-    #[serde(rename = "exporter_context", deserialize_with = "bytes_from_hex")]
-    export_ctx: Vec<u8>,
-    #[serde(rename = "L")]
-    export_len: usize,
-    #[serde(rename = "exported_value", deserialize_with = "bytes_from_hex")]
-    export_val: Vec<u8>,
-}
-
 /// Returns a keypair given the secret bytes and pubkey bytes
 fn deser_keypair<Kem: KemTrait>(
     sk_bytes: &[u8],
@@ -212,36 +46,11 @@ fn deser_keypair<Kem: KemTrait>(
     (sk, pk)
 }
 
-/// Constructs an `OpModeR` from the given components. The variant constructed is determined solely
-/// by `mode_id`. This will panic if there is insufficient data to construct the variants specified
-/// by `mode_id`.
-fn make_op_mode_r<'a, Kem: KemTrait>(
-    mode_id: u8,
-    pk: Option<Kem::PublicKey>,
-    psk: Option<&'a [u8]>,
-    psk_id: Option<&'a [u8]>,
-) -> OpModeR<'a, Kem> {
-    // Deserialize the optional bundle
-    let bundle = psk.map(|bytes| PskBundle {
-        psk: bytes,
-        psk_id: psk_id.unwrap(),
-    });
-
-    // These better be set if the mode ID calls for them
-    match mode_id {
-        0 => OpModeR::Base,
-        1 => OpModeR::Psk(bundle.unwrap()),
-        2 => OpModeR::Auth(pk.unwrap()),
-        3 => OpModeR::AuthPsk(pk.unwrap(), bundle.unwrap()),
-        _ => panic!("Invalid mode ID: {}", mode_id),
-    }
-}
-
 // This does all the legwork
-fn test_case<A: Aead, Kdf: KdfTrait, Kem: TestableKem>(tv: MainTestVector) {
+fn test_case<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(tv: MainTestVector) {
     // First, deserialize all the relevant keys so we can reconstruct the encapped key
     let recip_keypair = deser_keypair::<Kem>(&tv.sk_recip, &tv.pk_recip);
-    let sk_eph = <Kem as TestableKem>::EphemeralKey::from_bytes(&tv.sk_eph).unwrap();
+    let sk_eph = Kem::PrivateKey::from_bytes(&tv.sk_eph).unwrap();
     let sender_keypair = {
         let pk_sender = &tv.pk_sender.as_ref();
         tv.sk_sender
@@ -256,7 +65,7 @@ fn test_case<A: Aead, Kdf: KdfTrait, Kem: TestableKem>(tv: MainTestVector) {
         assert_serializable_eq!(recip_keypair.1, derived_kp.1, "pk recip doesn't match");
     }
     if let Some(sks) = sender_keypair.as_ref() {
-        let derived_kp = Kem::derive_keypair(&tv.ikm_sender.unwrap());
+        let derived_kp = Kem::derive_keypair(tv.ikm_sender.as_ref().unwrap());
         assert_serializable_eq!(sks.0, derived_kp.0, "sk sender doesn't match");
         assert_serializable_eq!(sks.1, derived_kp.1, "pk sender doesn't match");
     }
@@ -266,8 +75,8 @@ fn test_case<A: Aead, Kdf: KdfTrait, Kem: TestableKem>(tv: MainTestVector) {
     // Now derive the encapped key with the deterministic encap function, using all the inputs
     // above
     let (shared_secret, encapped_key) = {
-        let sender_keypair_ref = sender_keypair.as_ref().map(|&(ref sk, ref pk)| (sk, pk));
-        Kem::encap_with_eph(&pk_recip, sender_keypair_ref, sk_eph).expect("encap failed")
+        let sender_keypair_ref = sender_keypair.as_ref().map(|(sk, pk)| (sk, pk));
+        Kem::danger_encap_with_eph(&pk_recip, sender_keypair_ref, sk_eph).expect("encap failed")
     };
 
     // Assert that the derived shared secret key is identical to the one provided
@@ -292,23 +101,119 @@ fn test_case<A: Aead, Kdf: KdfTrait, Kem: TestableKem>(tv: MainTestVector) {
     let mode = make_op_mode_r(
         tv.mode,
         sender_keypair.map(|(_, pk)| pk),
-        tv.psk.as_ref().map(Vec::as_slice),
-        tv.psk_id.as_ref().map(Vec::as_slice),
+        tv.psk.as_deref(),
+        tv.psk_id.as_deref(),
     );
+
+    // Run the key schedule by hand and check every intermediate value. A bug here could
+    // otherwise hide behind a symmetric mistake on the sender and receiver side. Then use the
+    // result to make the sender's context. setup_sender would pick a random ephemeral key.
+    let mut sender_ctx: AeadCtxS<A, Kdf, Kem> = {
+        let ks = key_schedule::<A, Kdf, Kem, _>(&mode, &shared_secret, &tv.info);
+        assert_eq!(
+            ks.context(),
+            tv.hpke_context.as_slice(),
+            "key_schedule_context doesn't match"
+        );
+        assert_eq!(
+            key_schedule_context::<A, Kdf, Kem>(
+                tv.mode,
+                tv.psk_id.as_deref().unwrap_or_default(),
+                &tv.info
+            )
+            .as_slice(),
+            tv.hpke_context.as_slice(),
+            "public key_schedule_context doesn't match"
+        );
+        let KeySchedule {
+            secret,
+            key,
+            base_nonce,
+            exporter_secret,
+            ..
+        } = ks;
+        assert_eq!(
+            secret.0.as_slice(),
+            tv.key_schedule_secret.as_slice(),
+            "secret doesn't match"
+        );
+        // The export-only AEAD has no key or nonce, and its vectors leave them empty
+        if A::AEAD_ID != ExportOnlyAead::AEAD_ID {
+            assert_eq!(
+                key.0.as_slice(),
+                tv.aead_key.as_slice(),
+                "key doesn't match"
+            );
+            assert_eq!(
+                base_nonce.0.as_slice(),
+                tv.aead_base_nonce.as_slice(),
+                "base_nonce doesn't match"
+            );
+        } else {
+            assert!(tv.aead_key.is_empty() && tv.aead_base_nonce.is_empty());
+        }
+        assert_eq!(
+            exporter_secret.0.as_slice(),
+            tv.exporter_secret.as_slice(),
+            "exporter_secret doesn't match"
+        );
+
+        AeadCtx::new(&key, base_nonce, exporter_secret).into()
+    };
+
+    // Check that every tampered input fails, and fails the right way
+    for case in tv.negative_cases.iter() {
+        let err = run_negative_case::<A, Kdf, Kem>(&tv, case)
+            .expect_err("receiver accepted a tampered input");
+        assert_eq!(
+            format!("{:?}", err),
+            case.expected_error,
+            "wrong error for {:?}",
+            case.tampering
+        );
+    }
+
     let mut aead_ctx = setup_receiver::<A, Kdf, Kem>(&mode, &sk_recip, &encapped_key, &tv.info)
         .expect("setup_receiver failed");
 
-    // Go through all the plaintext-ciphertext pairs of this test vector and assert the
-    // ciphertext decrypts to the corresponding plaintext
+    // Go through all the plaintext-ciphertext pairs of this test vector and assert that the
+    // plaintext encrypts to the ciphertext, and that the ciphertext decrypts to the plaintext
+    let mut next_seq = 0u64;
     for enc_packet in tv.encryptions {
         // Descructure the vector
         let EncryptionTestVector {
+            seq,
             aad,
             ciphertext,
             plaintext,
-            ..
+            nonce,
         } = enc_packet;
 
+        // If the vector skipped some messages, catch up by sealing and opening dummy ones
+        let seq = seq.unwrap_or(next_seq);
+        assert!(seq >= next_seq, "encryptions are out of order");
+        for _ in next_seq..seq {
+            let ct = sender_ctx.seal(b"", b"").expect("seal failed");
+            aead_ctx.open(&ct, b"").expect("open failed");
+        }
+        next_seq = seq + 1;
+
+        // Both sides must be at the nonce the vector says this message used
+        assert_eq!(
+            sender_ctx.current_nonce().0.as_slice(),
+            nonce.as_slice(),
+            "sender nonces don't match"
+        );
+        assert_eq!(
+            aead_ctx.current_nonce().0.as_slice(),
+            nonce.as_slice(),
+            "receiver nonces don't match"
+        );
+
+        // Seal the plaintext and assert we get the expected ciphertext
+        let sealed = sender_ctx.seal(&plaintext, &aad).expect("seal failed");
+        assert_eq!(sealed, ciphertext, "ciphertexts don't match");
+
         // Open the ciphertext and assert that it succeeds
         let decrypted = aead_ctx.open(&ciphertext, &aad).expect("open failed");
 
@@ -323,7 +228,26 @@ fn test_case<A: Aead, Kdf: KdfTrait, Kem: TestableKem>(tv: MainTestVector) {
             .export(&export.export_ctx, &mut exported_val)
             .unwrap();
         assert_eq!(exported_val, export.export_val, "export values don't match");
+
+        sender_ctx
+            .export(&export.export_ctx, &mut exported_val)
+            .unwrap();
+        assert_eq!(
+            exported_val, export.export_val,
+            "sender export values don't match"
+        );
     }
+
+    // One byte more than HKDF can output must fail on both sides
+    let mut too_long = vec![0u8; 255 * <Kdf::HashImpl as Digest>::output_size() + 1];
+    assert!(matches!(
+        aead_ctx.export(b"", &mut too_long),
+        Err(HpkeError::KdfOutputTooLong)
+    ));
+    assert!(matches!(
+        sender_ctx.export(b"", &mut too_long),
+        Err(HpkeError::KdfOutputTooLong)
+    ));
 }
 
 // This macro takes in all the supported AEADs, KDFs, and KEMs, and dispatches the given test
@@ -364,50 +288,141 @@ macro_rules! dispatch_testcase {
                 stringify!($kem_ty)
             );
 
-            let tv = $tv.clone();
-            test_case::<$aead_ty, $kdf_ty, $kem_ty>(tv);
+            test_case::<$aead_ty, $kdf_ty, $kem_ty>($tv);
 
             // This is so that code that comes after a dispatch_testcase! invocation will know that
             // the test vector matched no known ciphersuites
-            continue;
+            return;
         }
     };
 }
 
+/// Runs the known-answer test on a single vector, dispatching it to the appropriate ciphersuite
+fn dispatch_kat(tv: MainTestVector) {
+    // Ignore everything that doesn't use X25519, P256, P384 or P521, since that's all we support
+    // right now
+    if tv.kem_id != X25519HkdfSha256::KEM_ID
+        && tv.kem_id != DhP256HkdfSha256::KEM_ID
+        && tv.kem_id != DhP384HkdfSha384::KEM_ID
+        && tv.kem_id != DhP521HkdfSha512::KEM_ID
+    {
+        return;
+    }
+
+    // This unrolls into 36 `if let` statements
+    dispatch_testcase!(
+        tv,
+        (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+        (HkdfSha256, HkdfSha384, HkdfSha512),
+        (
+            X25519HkdfSha256,
+            DhP256HkdfSha256,
+            DhP384HkdfSha384,
+            DhP521HkdfSha512
+        )
+    );
+
+    // The above macro has a `return` in every branch. We only get to this line if it failed to
+    // match every combination of the above primitives.
+    panic!(
+        "Unrecognized (AEAD ID, KDF ID, KEM ID) combo: ({}, {}, {})",
+        tv.aead_id, tv.kdf_id, tv.kem_id
+    );
+}
+
 #[test]
 fn kat_test() {
+    // Stream the vectors rather than reading them all at once, to keep memory use bounded
     let file = File::open("test-vectors-5f503c5.json").unwrap();
-    let tvs: Vec<MainTestVector> = serde_json::from_reader(file).unwrap();
-
-    for tv in tvs.into_iter() {
-        // Ignore everything that doesn't use X25519, P256, P384 or P521, since that's all we support
-        // right now
-        if tv.kem_id != X25519HkdfSha256::KEM_ID
-            && tv.kem_id != DhP256HkdfSha256::KEM_ID
-            && tv.kem_id != DhP384HkdfSha384::KEM_ID
-            && tv.kem_id != DhP521HkdfSha512::KEM_ID
-        {
-            continue;
-        }
-
-        // This unrolls into 36 `if let` statements
-        dispatch_testcase!(
-            tv,
-            (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
-            (HkdfSha256, HkdfSha384, HkdfSha512),
-            (
-                X25519HkdfSha256,
-                DhP256HkdfSha256,
-                DhP384HkdfSha384,
-                DhP521HkdfSha512
-            )
-        );
+    for_each_test_vector(file, dispatch_kat).unwrap();
+}
 
-        // The above macro has a `continue` in every branch. We only get to this line if it failed
-        // to match every combination of the above primitives.
-        panic!(
-            "Unrecognized (AEAD ID, KDF ID, KEM ID) combo: ({}, {}, {})",
-            tv.aead_id, tv.kdf_id, tv.kem_id
-        );
+/// Returns, for each mode, the set of fields that the RFC 9180 test vectors of that mode carry
+fn rfc_fields_by_mode() -> [BTreeSet<String>; 4] {
+    // We don't care about the values, so don't bother parsing them
+    let file = File::open("test-vectors-5f503c5.json").unwrap();
+    let tvs: Vec<BTreeMap<String, IgnoredAny>> =
+        serde_json::from_reader(BufReader::new(file)).unwrap();
+
+    // Every vector of a given mode has the same fields. The first 4 vectors have modes
+    // 0 through 3.
+    let mut fields: [BTreeSet<String>; 4] = Default::default();
+    for (mode_fields, tv) in fields.iter_mut().zip(tvs) {
+        *mode_fields = tv.into_keys().collect();
     }
+    fields
 }
+
+macro_rules! test_gen_roundtrip {
+    ($test_name:ident, $aead_ty:ty, $kdf_ty:ty, $kem_ty:ty) => {
+        /// Tests that a generated vector passes the known-answer test, both before and after a
+        /// JSON round trip, with and without negative cases
+        #[test]
+        fn $test_name() {
+            let mut csprng = StdRng::from_entropy();
+            let rfc_fields = rfc_fields_by_mode();
+            for mode_id in 0..4 {
+                let mut tv = gen_test_case::<$aead_ty, $kdf_ty, $kem_ty, _>(mode_id, &mut csprng);
+                assert_eq!(tv.mode, mode_id);
+
+                // Apart from the schema version, the vector must have exactly the fields the RFC
+                // vectors of the same mode carry
+                let json = serde_json::to_value(&tv).unwrap();
+                let our_fields: BTreeSet<String> = json
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .filter(|k| *k != "schema_version")
+                    .cloned()
+                    .collect();
+                assert_eq!(
+                    our_fields, rfc_fields[mode_id as usize],
+                    "fields don't match for mode {}",
+                    mode_id
+                );
+
+                let reparsed: MainTestVector = serde_json::from_value(json).unwrap();
+                test_case::<$aead_ty, $kdf_ty, $kem_ty>(reparsed);
+
+                // Skipping messages mustn't change anything
+                let mut skipped = tv.clone();
+                keep_encryptions(&mut skipped, RFC_SEQS);
+                test_case::<$aead_ty, $kdf_ty, $kem_ty>(skipped);
+
+                // Now add the negative cases and check they survive a round trip too
+                tv.negative_cases = gen_negative_cases::<$aead_ty, $kdf_ty, $kem_ty>(&tv);
+                assert!(!tv.negative_cases.is_empty());
+                let json = serde_json::to_string(&tv).unwrap();
+                let reparsed: MainTestVector = serde_json::from_str(&json).unwrap();
+                assert_eq!(reparsed.negative_cases.len(), tv.negative_cases.len());
+                test_case::<$aead_ty, $kdf_ty, $kem_ty>(tv);
+                test_case::<$aead_ty, $kdf_ty, $kem_ty>(reparsed);
+            }
+        }
+    };
+}
+
+test_gen_roundtrip!(
+    test_gen_x25519_chacha,
+    ChaCha20Poly1305,
+    HkdfSha256,
+    X25519HkdfSha256
+);
+test_gen_roundtrip!(
+    test_gen_p256_aes128,
+    AesGcm128,
+    HkdfSha256,
+    DhP256HkdfSha256
+);
+test_gen_roundtrip!(
+    test_gen_p384_aes256,
+    AesGcm256,
+    HkdfSha384,
+    DhP384HkdfSha384
+);
+test_gen_roundtrip!(
+    test_gen_p521_exportonly,
+    ExportOnlyAead,
+    HkdfSha512,
+    DhP521HkdfSha512
+);
@@ -2,7 +2,7 @@
 
 use byteorder::{BigEndian, ByteOrder};
 use digest::{core_api::BlockSizeUser, Digest, OutputSizeUser};
-use generic_array::GenericArray;
+use generic_array::{typenum::Unsigned, GenericArray};
 use hmac::SimpleHmac;
 use sha2::{Sha256, Sha384, Sha512};
 
@@ -21,6 +21,18 @@ pub trait Kdf {
 
     /// The algorithm identifier for a KDF implementation
     const KDF_ID: u16;
+
+    /// The output size of the underlying hash function, i.e., Nh
+    const DIGEST_SIZE: usize = <<Self::HashImpl as OutputSizeUser>::OutputSize as Unsigned>::USIZE;
+
+    /// The longest PSK, in bytes, that this KDF accepts, per RFC 9180 §7.2.1
+    const MAX_PSK_LEN: u64;
+    /// The longest PSK ID, in bytes, that this KDF accepts, per RFC 9180 §7.2.1
+    const MAX_PSK_ID_LEN: u64;
+    /// The longest `info` string, in bytes, that this KDF accepts, per RFC 9180 §7.2.1
+    const MAX_INFO_LEN: u64;
+    /// The longest exporter context, in bytes, that this KDF accepts, per RFC 9180 §7.2.1
+    const MAX_EXPORTER_CTX_LEN: u64;
 }
 
 // We use Kdf as a type parameter, so this is to avoid ambiguity.
@@ -43,6 +55,13 @@ impl KdfTrait for HkdfSha256 {
 
     // RFC 9180 §7.2: HKDF-SHA256
     const KDF_ID: u16 = 0x0001;
+
+    // RFC 9180 §7.2.1: SHA-256 takes at most 2^61 - 1 bytes of input. The limits are what's left
+    // once the label and the rest of the hash input are accounted for.
+    const MAX_PSK_LEN: u64 = (1 << 61) - 88;
+    const MAX_PSK_ID_LEN: u64 = (1 << 61) - 93;
+    const MAX_INFO_LEN: u64 = (1 << 61) - 91;
+    const MAX_EXPORTER_CTX_LEN: u64 = (1 << 61) - 120;
 }
 
 /// The implementation of HKDF-SHA384
@@ -54,6 +73,12 @@ impl KdfTrait for HkdfSha384 {
 
     // RFC 9180 §7.2: HKDF-SHA384
     const KDF_ID: u16 = 0x0002;
+
+    // RFC 9180 §7.2.1: The limits are about 2^125 bytes, which no u64 length can exceed
+    const MAX_PSK_LEN: u64 = u64::MAX;
+    const MAX_PSK_ID_LEN: u64 = u64::MAX;
+    const MAX_INFO_LEN: u64 = u64::MAX;
+    const MAX_EXPORTER_CTX_LEN: u64 = u64::MAX;
 }
 
 /// The implementation of HKDF-SHA512
@@ -65,6 +90,12 @@ impl KdfTrait for HkdfSha512 {
 
     // RFC 9180 §7.2: HKDF-SHA512
     const KDF_ID: u16 = 0x0003;
+
+    // RFC 9180 §7.2.1: The limits are about 2^125 bytes, which no u64 length can exceed
+    const MAX_PSK_LEN: u64 = u64::MAX;
+    const MAX_PSK_ID_LEN: u64 = u64::MAX;
+    const MAX_INFO_LEN: u64 = u64::MAX;
+    const MAX_EXPORTER_CTX_LEN: u64 = u64::MAX;
 }
 
 // RFC 9180 §4.1
@@ -124,6 +155,26 @@ pub trait LabeledExpand {
         info: &[u8],
         out: &mut [u8],
     ) -> Result<(), hkdf::InvalidLength>;
+
+    /// Does what `labeled_expand` does, with `info` being the concatenation of the given pieces.
+    /// There can be at most `MAX_INFO_PIECES` pieces, which is checked at compile time.
+    fn labeled_expand_multi<const N: usize>(
+        &self,
+        suite_id: &[u8],
+        label: &[u8],
+        info: &[&[u8]; N],
+        out: &mut [u8],
+    ) -> Result<(), hkdf::InvalidLength>;
+}
+
+/// The most pieces that `LabeledExpand::labeled_expand_multi` can take
+const MAX_INFO_PIECES: usize = 8;
+
+/// Fails to compile when it's used with more than `MAX_INFO_PIECES` pieces
+struct InfoPieces<const N: usize>;
+
+impl<const N: usize> InfoPieces<N> {
+    const FIT: () = assert!(N <= MAX_INFO_PIECES, "too many info pieces");
 }
 
 impl<D> LabeledExpand for hkdf::Hkdf<D, SimpleHmac<D>>
@@ -146,6 +197,18 @@ where
         info: &[u8],
         out: &mut [u8],
     ) -> Result<(), hkdf::InvalidLength> {
+        self.labeled_expand_multi(suite_id, label, &[info], out)
+    }
+
+    fn labeled_expand_multi<const N: usize>(
+        &self,
+        suite_id: &[u8],
+        label: &[u8],
+        info: &[&[u8]; N],
+        out: &mut [u8],
+    ) -> Result<(), hkdf::InvalidLength> {
+        let () = InfoPieces::<N>::FIT;
+
         // We need to write the length as a u16, so that's the de-facto upper bound on length
         if out.len() > u16::MAX as usize {
             // The error condition is met, since 2^16 is way bigger than 255 * digest_bytelen
@@ -157,7 +220,9 @@ where
         BigEndian::write_u16(&mut len_buf, out.len() as u16);
 
         // Call HKDF-Expand() with the info string set to the concatenation of all of the above
-        let labeled_info = [&len_buf, VERSION_LABEL, suite_id, label, info];
-        self.expand_multi_info(&labeled_info, out)
+        let mut labeled_info: [&[u8]; 4 + MAX_INFO_PIECES] = [&[]; 4 + MAX_INFO_PIECES];
+        labeled_info[..4].copy_from_slice(&[&len_buf, VERSION_LABEL, suite_id, label]);
+        labeled_info[4..4 + N].copy_from_slice(info);
+        self.expand_multi_info(&labeled_info[..4 + N], out)
     }
 }
@@ -1,11 +1,17 @@
 //! Traits and structs for key encapsulation mechanisms
 
-use crate::{Deserializable, HpkeError, Serializable};
+use crate::{
+    fingerprint::Fingerprint,
+    kdf::{labeled_extract, HkdfSha256},
+    util::kem_suite_id,
+    Deserializable, HpkeError, Serializable,
+};
 
 use core::fmt::Debug;
 
-use generic_array::{ArrayLength, GenericArray};
+use generic_array::{typenum::Unsigned, ArrayLength, GenericArray};
 use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConstantTimeEq};
 use zeroize::Zeroize;
 
 mod dhkem;
@@ -15,11 +21,15 @@ pub use dhkem::*;
 pub trait Kem: Sized {
     /// The key exchange's public key type. If you want to generate a keypair, see
     /// `Kem::gen_keypair` or `Kem::derive_keypair`
+    ///
+    /// A public key is fully validated when it's deserialized, and is stored in the form the key
+    /// exchange uses directly. So when encapsulating to the same recipient many times, deserialize
+    /// their key once and reuse it. Nothing is re-validated per encapsulation.
     type PublicKey: Clone + Debug + PartialEq + Eq + Serializable + Deserializable;
 
     /// The key exchange's private key type. If you want to generate a keypair, see
     /// `Kem::gen_keypair` or `Kem::derive_keypair`
-    type PrivateKey: Clone + PartialEq + Eq + Serializable + Deserializable;
+    type PrivateKey: Clone + PartialEq + Eq + ConstantTimeEq + Serializable + Deserializable;
 
     /// Computes the public key of a given private key
     fn sk_to_pk(sk: &Self::PrivateKey) -> Self::PublicKey;
@@ -34,6 +44,20 @@ pub trait Kem: Sized {
     /// The algorithm identifier for a KEM implementation
     const KEM_ID: u16;
 
+    /// The `suite_id` this KEM uses for domain separation, i.e., `"KEM" || I2OSP(KEM_ID, 2)`. This
+    /// is computed at compile time.
+    const SUITE_ID: [u8; 5] = kem_suite_id::<Self>();
+
+    /// The size of an encapsulated key, i.e., Nenc
+    const ENCAPPED_KEY_SIZE: usize =
+        <<Self::EncappedKey as Serializable>::OutputSize as Unsigned>::USIZE;
+
+    /// Returns the fingerprint of a public key of this KEM, for key pinning, logging, and
+    /// out-of-band verification. See the [`fingerprint`](crate::fingerprint) module.
+    fn fingerprint(pk: &Self::PublicKey) -> Fingerprint {
+        Fingerprint::new(&Self::SUITE_ID, &pk.to_bytes())
+    }
+
     /// Deterministically derives a keypair from the given input keying material
     ///
     /// Requirements
@@ -43,7 +67,38 @@ pub trait Kem: Sized {
     /// entropy.
     fn derive_keypair(ikm: &[u8]) -> (Self::PrivateKey, Self::PublicKey);
 
-    /// Generates a random keypair using the given RNG
+    /// Does what `derive_keypair` does, but mixes in an application-specific `domain`, e.g.,
+    /// `b"example.com/signing-subsystem"`. Subsystems that derive keys from the same keying
+    /// material under different domains get unrelated keypairs.
+    ///
+    /// The empty domain gives the same keypair as `derive_keypair`, i.e., RFC 9180's
+    /// `DeriveKeyPair`. A keypair derived under a nonempty domain can only be reproduced by this
+    /// crate, or by code that follows the same construction.
+    ///
+    /// The built-in KEMs put the domain into the `info` of the expansions that make the private
+    /// key. The default, for other KEMs, extracts new keying material from `ikm` with HKDF-SHA256,
+    /// salted with the domain, and passes that to `derive_keypair`.
+    ///
+    /// Requirements
+    /// ============
+    /// The keying material has the same requirements as in `derive_keypair`. The domain need not
+    /// be secret.
+    fn derive_keypair_with_domain(
+        ikm: &[u8],
+        domain: &[u8],
+    ) -> (Self::PrivateKey, Self::PublicKey) {
+        if domain.is_empty() {
+            return Self::derive_keypair(ikm);
+        }
+        let (mut domain_ikm, _) =
+            labeled_extract::<HkdfSha256>(domain, &Self::SUITE_ID, b"dkp_domain", ikm);
+        let keypair = Self::derive_keypair(&domain_ikm);
+        domain_ikm.zeroize();
+        keypair
+    }
+
+    /// Generates a random keypair using the given RNG. Panics if the RNG fails. See
+    /// [`Kem::try_gen_keypair`] for a version that doesn't.
     fn gen_keypair<R: CryptoRng + RngCore>(csprng: &mut R) -> (Self::PrivateKey, Self::PublicKey) {
         // Make some keying material that's the size of a private key
         let mut ikm: GenericArray<u8, <Self::PrivateKey as Serializable>::OutputSize> =
@@ -54,13 +109,30 @@ pub trait Kem: Sized {
         Self::derive_keypair(&ikm)
     }
 
+    /// Generates a random keypair using the given RNG, without panicking if the RNG fails
+    ///
+    /// Return Value
+    /// ============
+    /// Returns a keypair on success. If the RNG failed, returns `Err(HpkeError::RngError)`.
+    fn try_gen_keypair<R: CryptoRng + RngCore>(
+        csprng: &mut R,
+    ) -> Result<(Self::PrivateKey, Self::PublicKey), HpkeError> {
+        let mut ikm: GenericArray<u8, <Self::PrivateKey as Serializable>::OutputSize> =
+            GenericArray::default();
+        csprng
+            .try_fill_bytes(&mut ikm)
+            .map_err(|_| HpkeError::RngError)?;
+        Ok(Self::derive_keypair(&ikm))
+    }
+
     /// Derives a shared secret given the encapsulated key and the recipients secret key. If
     /// `pk_sender_id` is given, the sender's identity will be tied to the shared secret.
     ///
     /// Return Value
     /// ============
-    /// Returns a shared secret on success. If an error happened during key exchange, returns
-    /// `Err(HpkeError::DecapError)`.
+    /// Returns a shared secret on success. If the encapped key is invalid, returns
+    /// `Err(HpkeError::InvalidEncappedKey)`. If any other error happened during key exchange,
+    /// returns `Err(HpkeError::DecapError)`.
     #[doc(hidden)]
     fn decap(
         sk_recip: &Self::PrivateKey,
@@ -76,13 +148,88 @@ pub trait Kem: Sized {
     /// Return Value
     /// ============
     /// Returns a shared secret and encapped key on success. If an error happened during key
-    /// exchange, returns `Err(HpkeError::EncapError)`.
+    /// exchange, returns `Err(HpkeError::EncapError)`. If the RNG failed, returns
+    /// `Err(HpkeError::RngError)`.
     #[doc(hidden)]
     fn encap<R: CryptoRng + RngCore>(
         pk_recip: &Self::PublicKey,
         sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
         csprng: &mut R,
     ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError>;
+
+    /// Does what `encap` does, but with the given ephemeral private key instead of a freshly
+    /// generated one. This exists to reproduce test vectors.
+    ///
+    /// DANGER: The security of HPKE rests on every encapsulation using a fresh, uniformly random
+    /// ephemeral key. Calling this twice with the same `sk_eph` and recipient gives the same
+    /// shared secret both times, and an `sk_eph` that anyone else knows or can guess lets them
+    /// compute the shared secret. Never call this outside of tests.
+    ///
+    /// The built-in KEMs implement this. KEMs that don't keep the default, which always fails, so
+    /// that turning on this feature never breaks a downstream `Kem` impl.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns a shared secret and encapped key on success. If an error happened during key
+    /// exchange, or the KEM doesn't support deterministic encapsulation, returns
+    /// `Err(HpkeError::EncapError)`.
+    #[cfg(feature = "hazmat-deterministic")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hazmat-deterministic")))]
+    fn danger_encap_with_eph(
+        _pk_recip: &Self::PublicKey,
+        _sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
+        _sk_eph: Self::PrivateKey,
+    ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
+        Err(HpkeError::EncapError)
+    }
+}
+
+// Nobody outside this crate can mark a KEM as a DHKEM
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A KEM built on Diffie-Hellman, i.e., a DHKEM. This is what the recipient setups that do their
+/// DH operations somewhere else, like those in the `kms` and `keystore` modules, work with. It is
+/// implemented for every KEM in this crate.
+pub trait DhKem: Kem + sealed::Sealed {
+    /// Does what `decap` does, given the Diffie-Hellman outputs instead of the private key, for
+    /// keys held somewhere else. `dh` is `DH(skR, pkE)`, followed by `DH(skR, pkS)` if
+    /// `pk_sender_id` is given.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns a shared secret on success. If `dh` is the wrong length, returns
+    /// `Err(HpkeError::IncorrectInputLength(expected, dh.len()))`. If the DH with the encapped key
+    /// is all zeros, returns `Err(HpkeError::InvalidEncappedKey)`. If the DH with the sender's
+    /// identity key is, returns `Err(HpkeError::DecapError)`.
+    #[doc(hidden)]
+    fn decap_with_dh(
+        dh: &[u8],
+        pk_recip: &Self::PublicKey,
+        pk_sender_id: Option<&Self::PublicKey>,
+        encapped_key: &Self::EncappedKey,
+    ) -> Result<SharedSecret<Self>, HpkeError>;
+
+    /// Returns the ephemeral public key that an encapsulated key carries, which is what the
+    /// recipient does DH with
+    #[doc(hidden)]
+    fn encapped_key_to_pk(encapped_key: &Self::EncappedKey) -> Self::PublicKey;
+
+    /// Computes the Diffie-Hellman output of `sk` and `pk`, and writes it to the start of `out`.
+    /// This is for callers that do the DH operations of an encap or decap one at a time.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns the length of the DH output on success. If `out` is too short for it, returns
+    /// `Err(HpkeError::IncorrectInputLength(needed, out.len()))`. If the output is all zeros,
+    /// returns `Err(HpkeError::DecapError)`.
+    #[doc(hidden)]
+    fn dh_into(
+        sk: &Self::PrivateKey,
+        pk: &Self::PublicKey,
+        out: &mut [u8],
+    ) -> Result<usize, HpkeError>;
 }
 
 // Kem is used as a type parameter everywhere. To avoid confusion, alias it
@@ -110,10 +257,26 @@ impl<Kem: KemTrait> Drop for SharedSecret<Kem> {
     }
 }
 
+impl<Kem: KemTrait> ConstantTimeEq for SharedSecret<Kem> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+// Comparing secrets with == is constant-time too
+impl<Kem: KemTrait> PartialEq for SharedSecret<Kem> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+impl<Kem: KemTrait> Eq for SharedSecret<Kem> {}
+
 #[cfg(test)]
 mod tests {
     use crate::{kem::Kem as KemTrait, Deserializable, Serializable};
 
+    use subtle::ConstantTimeEq;
+
     use rand::{rngs::StdRng, SeedableRng};
 
     macro_rules! test_encap_correctness {
@@ -135,7 +298,9 @@ mod tests {
                     Kem::decap(&sk_recip, None, &encapped_key).unwrap();
 
                 // Ensure that the encapsulated secret is what decap() derives
-                assert_eq!(auth_shared_secret.0, decapped_auth_shared_secret.0);
+                assert!(bool::from(
+                    auth_shared_secret.ct_eq(&decapped_auth_shared_secret)
+                ));
 
                 //
                 // Now do it with the auth, i.e., using the sender's identity keys
@@ -162,6 +327,32 @@ mod tests {
         };
     }
 
+    /// Tests that the empty domain gives the RFC derivation, and that different domains give
+    /// different keypairs
+    macro_rules! test_derive_keypair_with_domain {
+        ($test_name:ident, $kem_ty:ty) => {
+            #[test]
+            fn $test_name() {
+                type Kem = $kem_ty;
+
+                let ikm = [7u8; 64];
+                let (sk, pk) = Kem::derive_keypair(&ikm);
+                let (sk_empty, pk_empty) = Kem::derive_keypair_with_domain(&ikm, b"");
+                assert!(sk == sk_empty && pk == pk_empty);
+
+                let (sk_a, pk_a) = Kem::derive_keypair_with_domain(&ikm, b"subsystem a");
+                let (sk_b, pk_b) = Kem::derive_keypair_with_domain(&ikm, b"subsystem b");
+                assert!(sk_a != sk && sk_a != sk_b && sk_b != sk);
+                assert!(pk_a != pk && pk_a != pk_b && pk_b != pk);
+                assert!(Kem::sk_to_pk(&sk_a) == pk_a);
+
+                // Deterministic
+                let (sk_a2, _) = Kem::derive_keypair_with_domain(&ikm, b"subsystem a");
+                assert!(sk_a == sk_a2);
+            }
+        };
+    }
+
     /// Tests that an deserialize-serialize round trip on an encapped key ends up at the same value
     macro_rules! test_encapped_serialize {
         ($test_name:ident, $kem_ty:ty) => {
@@ -188,6 +379,99 @@ mod tests {
                     new_encapped_key.0, encapped_key.0,
                     "encapped key doesn't serialize correctly"
                 );
+
+                // TryFrom is the same as from_bytes
+                let new_encapped_key =
+                    <Kem as KemTrait>::EncappedKey::try_from(&encapped_key_bytes[..]).unwrap();
+                assert_eq!(new_encapped_key.0, encapped_key.0);
+                assert!(
+                    <Kem as KemTrait>::EncappedKey::try_from(&encapped_key_bytes[1..]).is_err()
+                );
+            }
+        };
+    }
+
+    /// Tests that NIST curve public keys and encapped keys that are the point at infinity, have
+    /// non-canonical coordinates, aren't on the curve, or are in the wrong format, are rejected.
+    /// `$modulus` is the big-endian field modulus. The curve equation doesn't depend on the sign
+    /// of `y`, so an off-curve point is made by nudging the generator's `y`.
+    #[cfg(any(feature = "p256", feature = "p384", feature = "p521"))]
+    macro_rules! test_reject_invalid_nistp_pubkeys {
+        ($test_name:ident, $kem_ty:ty, $modulus:expr) => {
+            #[test]
+            fn $test_name() {
+                type Kem = $kem_ty;
+
+                // Get the generator by computing 1·G
+                let mut one = generic_array::GenericArray::<
+                    u8,
+                    <<Kem as KemTrait>::PrivateKey as Serializable>::OutputSize,
+                >::default();
+                *one.last_mut().unwrap() = 1;
+                let sk_one = <Kem as KemTrait>::PrivateKey::from_bytes(&one).unwrap();
+                let gen = Kem::sk_to_pk(&sk_one).to_bytes();
+                let coord_len = (gen.len() - 1) / 2;
+
+                // The point at infinity, in the only length we accept
+                let mut infinity = gen.clone();
+                infinity.fill(0);
+                // x = p is the non-canonical encoding of x = 0
+                let mut x_is_p = gen.clone();
+                x_is_p[1..1 + coord_len].copy_from_slice(&$modulus);
+                // The generator with y + 1 is not on the curve
+                let mut off_curve = gen.clone();
+                let carry = off_curve[1 + coord_len..]
+                    .iter_mut()
+                    .rev()
+                    .try_for_each(|b| {
+                        let (sum, overflow) = b.overflowing_add(1);
+                        *b = sum;
+                        if overflow {
+                            Ok(())
+                        } else {
+                            Err(())
+                        }
+                    });
+                assert!(carry.is_err());
+                // The right length, but a bogus SEC1 tag
+                let mut bad_tag = gen.clone();
+                bad_tag[0] = 0x05;
+                // The compressed encoding of the generator
+                let mut compressed = gen.clone();
+                compressed[0] = 0x02 | (gen[gen.len() - 1] & 1);
+                let compressed = &compressed[..1 + coord_len];
+
+                for (desc, bad_pk) in [
+                    ("point at infinity", &infinity[..]),
+                    ("x = p", &x_is_p[..]),
+                    ("off-curve point", &off_curve[..]),
+                    ("bad SEC1 tag", &bad_tag[..]),
+                    ("compressed point", compressed),
+                ] {
+                    assert!(
+                        <Kem as KemTrait>::PublicKey::from_bytes(bad_pk).is_err(),
+                        "accepted {} as a public key",
+                        desc
+                    );
+                    // A wrong-length input is a length error. Anything else is an invalid point.
+                    let expected_err = if bad_pk.len() == gen.len() {
+                        crate::HpkeError::InvalidEncappedKey
+                    } else {
+                        crate::HpkeError::IncorrectInputLength(gen.len(), bad_pk.len())
+                    };
+                    assert!(
+                        matches!(
+                            <Kem as KemTrait>::EncappedKey::from_bytes(bad_pk),
+                            Err(e) if e == expected_err
+                        ),
+                        "didn't reject {} as an encapped key with {:?}",
+                        desc,
+                        expected_err
+                    );
+                }
+
+                // Make sure the test is valid by checking the untampered generator is fine
+                assert!(<Kem as KemTrait>::PublicKey::from_bytes(&gen).is_ok());
             }
         };
     }
@@ -195,9 +479,146 @@ mod tests {
     #[cfg(feature = "x25519")]
     mod x25519_tests {
         use super::*;
+        use crate::{kem::DhKem, test_util::gen_rand_buf, HpkeError};
+
+        use hex_literal::hex;
 
         test_encap_correctness!(test_encap_correctness_x25519, crate::kem::X25519HkdfSha256);
+        test_derive_keypair_with_domain!(
+            test_derive_keypair_with_domain_x25519,
+            crate::kem::X25519HkdfSha256
+        );
         test_encapped_serialize!(test_encapped_serialize_x25519, crate::kem::X25519HkdfSha256);
+
+        // Curve25519 points of small order, and their non-canonical encodings. DH with any of
+        // these gives the all-zero output for every clamped scalar. The list is the same one
+        // libsodium and Wycheproof use.
+        const LOW_ORDER_POINTS: &[[u8; 32]] = &[
+            // 0 (order 4)
+            hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+            // 1 (order 1)
+            hex!("0100000000000000000000000000000000000000000000000000000000000000"),
+            // Order 8
+            hex!("e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800"),
+            // Order 8
+            hex!("5f9c95bca3508c24b1d0b1559c83ef5b04445cc4581c8e86d8224eddd09f1157"),
+            // p - 1 (order 2)
+            hex!("ecffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f"),
+            // p, i.e., 0 non-canonically
+            hex!("edffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f"),
+            // p + 1, i.e., 1 non-canonically
+            hex!("eeffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f"),
+            // The two order 8 points above, with the ignored top bit set
+            hex!("e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b880"),
+            hex!("5f9c95bca3508c24b1d0b1559c83ef5b04445cc4581c8e86d8224eddd09f11d7"),
+            // p - 1, p, and p + 1, with the ignored top bit set
+            hex!("ecffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+            hex!("edffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+            hex!("eeffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+        ];
+
+        /// Tests that encap and decap refuse every low-order point, whether it's the recipient's
+        /// public key, the encapped key, or the sender's identity key
+        #[test]
+        fn test_reject_low_order_points_x25519() {
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let mut csprng = StdRng::from_entropy();
+            let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+            let sender_keypair = Kem::gen_keypair(&mut csprng);
+            let sender_keypair_ref = Some((&sender_keypair.0, &sender_keypair.1));
+            let (_, encapped_key) = Kem::encap(&pk_recip, None, &mut csprng).unwrap();
+
+            for point in LOW_ORDER_POINTS {
+                // Low-order points are well-formed public keys, they just can't be used. As
+                // encapped keys, they're rejected up front.
+                let bad_pk = <Kem as KemTrait>::PublicKey::from_bytes(point).unwrap();
+                assert!(matches!(
+                    <Kem as KemTrait>::EncappedKey::from_bytes(point),
+                    Err(HpkeError::InvalidEncappedKey)
+                ));
+                // Sneak one past deserialization to check that decap catches it too
+                let bad_encapped_key =
+                    super::super::dhkem::x25519_hkdfsha256::EncappedKey(bad_pk.clone());
+
+                assert!(matches!(
+                    Kem::encap(&bad_pk, None, &mut csprng),
+                    Err(HpkeError::EncapError)
+                ));
+                assert!(matches!(
+                    Kem::encap(&bad_pk, sender_keypair_ref, &mut csprng),
+                    Err(HpkeError::EncapError)
+                ));
+                assert!(matches!(
+                    Kem::decap(&sk_recip, None, &bad_encapped_key),
+                    Err(HpkeError::InvalidEncappedKey)
+                ));
+                assert!(matches!(
+                    Kem::decap(&sk_recip, Some(&pk_recip), &bad_encapped_key),
+                    Err(HpkeError::InvalidEncappedKey)
+                ));
+                assert!(matches!(
+                    Kem::decap(&sk_recip, Some(&bad_pk), &encapped_key),
+                    Err(HpkeError::DecapError)
+                ));
+            }
+
+            // A low-order point's DH output is all zeros. A decap whose DH is done elsewhere
+            // catches that too.
+            let zeros = [0u8; 64];
+            let mut dh = [0u8; 64];
+            dh[..32].copy_from_slice(&gen_rand_buf());
+            assert!(matches!(
+                Kem::decap_with_dh(&zeros[..32], &pk_recip, None, &encapped_key),
+                Err(HpkeError::InvalidEncappedKey)
+            ));
+            assert!(matches!(
+                Kem::decap_with_dh(&dh, &pk_recip, Some(&pk_recip), &encapped_key),
+                Err(HpkeError::DecapError)
+            ));
+            dh[32..].copy_from_slice(&gen_rand_buf());
+            assert!(Kem::decap_with_dh(&dh, &pk_recip, Some(&pk_recip), &encapped_key).is_ok());
+        }
+
+        /// An RNG that always fails
+        struct FailingRng;
+
+        impl rand_core::RngCore for FailingRng {
+            fn next_u32(&mut self) -> u32 {
+                rand_core::impls::next_u32_via_fill(self)
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                rand_core::impls::next_u64_via_fill(self)
+            }
+
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                self.try_fill_bytes(dest).unwrap()
+            }
+
+            fn try_fill_bytes(&mut self, _: &mut [u8]) -> Result<(), rand_core::Error> {
+                let code = core::num::NonZeroU32::new(rand_core::Error::CUSTOM_START).unwrap();
+                Err(code.into())
+            }
+        }
+
+        impl rand_core::CryptoRng for FailingRng {}
+
+        /// Tests that a failing RNG makes encap return an RNG error rather than panic
+        #[test]
+        fn test_encap_rng_failure() {
+            type Kem = crate::kem::X25519HkdfSha256;
+
+            let mut csprng = StdRng::from_entropy();
+            let (_, pk_recip) = Kem::gen_keypair(&mut csprng);
+
+            let err = match Kem::encap(&pk_recip, None, &mut FailingRng) {
+                Err(e) => e,
+                Ok(_) => panic!("encap succeeded with a failing RNG"),
+            };
+            assert_eq!(err, HpkeError::RngError);
+            assert_eq!(err.primitive(), Some(crate::Primitive::Rng));
+        }
     }
 
     #[cfg(feature = "p256")]
@@ -205,7 +626,18 @@ mod tests {
         use super::*;
 
         test_encap_correctness!(test_encap_correctness_p256, crate::kem::DhP256HkdfSha256);
+        test_derive_keypair_with_domain!(
+            test_derive_keypair_with_domain_p256,
+            crate::kem::DhP256HkdfSha256
+        );
         test_encapped_serialize!(test_encapped_serialize_p256, crate::kem::DhP256HkdfSha256);
+        test_reject_invalid_nistp_pubkeys!(
+            test_reject_invalid_pubkeys_p256,
+            crate::kem::DhP256HkdfSha256,
+            hex_literal::hex!(
+                "ffffffff 00000001 00000000 00000000 00000000 ffffffff ffffffff ffffffff"
+            )
+        );
     }
 
     #[cfg(feature = "p384")]
@@ -213,7 +645,19 @@ mod tests {
         use super::*;
 
         test_encap_correctness!(test_encap_correctness_p384, crate::kem::DhP384HkdfSha384);
+        test_derive_keypair_with_domain!(
+            test_derive_keypair_with_domain_p384,
+            crate::kem::DhP384HkdfSha384
+        );
         test_encapped_serialize!(test_encapped_serialize_p384, crate::kem::DhP384HkdfSha384);
+        test_reject_invalid_nistp_pubkeys!(
+            test_reject_invalid_pubkeys_p384,
+            crate::kem::DhP384HkdfSha384,
+            hex_literal::hex!(
+                "ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff fffffffe"
+                "ffffffff 00000000 00000000 ffffffff"
+            )
+        );
     }
 
     #[cfg(feature = "p521")]
@@ -221,6 +665,19 @@ mod tests {
         use super::*;
 
         test_encap_correctness!(test_encap_correctness_p521, crate::kem::DhP521HkdfSha512);
+        test_derive_keypair_with_domain!(
+            test_derive_keypair_with_domain_p521,
+            crate::kem::DhP521HkdfSha512
+        );
         test_encapped_serialize!(test_encapped_serialize_p521, crate::kem::DhP521HkdfSha512);
+        test_reject_invalid_nistp_pubkeys!(
+            test_reject_invalid_pubkeys_p521,
+            crate::kem::DhP521HkdfSha512,
+            hex_literal::hex!(
+                "01ff ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff"
+                "ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff ffffffff"
+                "ffffffff"
+            )
+        );
     }
 }
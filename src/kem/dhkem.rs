@@ -1,3 +1,26 @@
+#[cfg(any(
+    feature = "x25519",
+    feature = "p256",
+    feature = "p384",
+    feature = "p521"
+))]
+use subtle::{Choice, ConstantTimeEq};
+
+/// Returns whether `bytes` is all zeros, in time that depends only on its length. A DH output that
+/// was computed elsewhere is checked with this, as `DhKeyExchange::dh` checks its own.
+#[cfg(any(
+    feature = "x25519",
+    feature = "p256",
+    feature = "p384",
+    feature = "p521"
+))]
+fn is_all_zero(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .fold(Choice::from(1), |acc, b| acc & b.ct_eq(&0))
+        .into()
+}
+
 /// Defines DHKEM(G, K) given a Diffie-Hellman group G and KDF K
 macro_rules! impl_dhkem {
     (
@@ -14,11 +37,13 @@ macro_rules! impl_dhkem {
             use crate::{
                 dhkex::{DhKeyExchange, MAX_PUBKEY_SIZE},
                 kdf::{extract_and_expand, Kdf as KdfTrait},
-                kem::{Kem as KemTrait, SharedSecret},
-                util::{enforce_outbuf_len, kem_suite_id},
+                kem::{DhKem, Kem as KemTrait, SharedSecret},
+                util::enforce_outbuf_len,
                 Deserializable, HpkeError, Serializable,
             };
 
+            use super::is_all_zero;
+
             use digest::OutputSizeUser;
             use rand_core::{CryptoRng, RngCore};
 
@@ -54,14 +79,26 @@ macro_rules! impl_dhkem {
             }
 
             impl Deserializable for EncappedKey {
-                // Pass to underlying from_bytes() impl
+                // Pass to underlying from_bytes() impl, then reject the points that can't come
+                // from an honest encap: the identity, off-curve points, and small-order points.
+                // Length errors are passed through as-is.
                 fn from_bytes(encoded: &[u8]) -> Result<Self, HpkeError> {
                     let pubkey =
-                        <<$dhkex as DhKeyExchange>::PublicKey as Deserializable>::from_bytes(encoded)?;
+                        <<$dhkex as DhKeyExchange>::PublicKey as Deserializable>::from_bytes(encoded)
+                            .map_err(|e| match e {
+                                HpkeError::ValidationError => HpkeError::InvalidEncappedKey,
+                                e => e,
+                            })?;
+                    if <$dhkex as DhKeyExchange>::is_small_order(&pubkey) {
+                        return Err(HpkeError::InvalidEncappedKey);
+                    }
                     Ok(EncappedKey(pubkey))
                 }
             }
 
+            impl_hex_display_fromstr!(EncappedKey);
+            impl_try_from_bytes!(EncappedKey);
+
             // Define the KEM struct
             #[doc = $doc_str]
             pub struct $kem_name;
@@ -88,8 +125,9 @@ macro_rules! impl_dhkem {
             //   return shared_secret, enc
 
             // The reason we define encap_with_eph() rather than just encap() is because we need to
-            // use deterministic ephemeral keys in the known-answer tests. So we define a function
-            // here, then use it to impl kem::Kem and kat_tests::TestableKem.
+            // use deterministic ephemeral keys in the known-answer tests and the test vector
+            // generator. So we define a function here, then use it to impl both Kem::encap and,
+            // under the hazmat-deterministic feature, Kem::danger_encap_with_eph.
 
             /// Derives a shared secret that the owner of the recipient's pubkey can use to derive
             /// the same shared secret. If `sk_sender_id` is given, the sender's identity will be
@@ -99,14 +137,13 @@ macro_rules! impl_dhkem {
             /// ============
             /// Returns a shared secret and encapped key on success. If an error happened during
             /// key exchange, returns `Err(HpkeError::EncapError)`.
-            #[doc(hidden)]
-            pub(crate) fn encap_with_eph(
+            fn encap_with_eph(
                 pk_recip: &PublicKey,
                 sender_id_keypair: Option<(&PrivateKey, &PublicKey)>,
                 sk_eph: PrivateKey,
             ) -> Result<(SharedSecret<$kem_name>, EncappedKey), HpkeError> {
                 // Put together the binding context used for all KDF operations
-                let suite_id = kem_suite_id::<$kem_name>();
+                let suite_id = <$kem_name as KemTrait>::SUITE_ID;
 
                 // Compute the shared secret from the ephemeral inputs
                 let kex_res_eph = <$dhkex as DhKeyExchange>::dh(&sk_eph, pk_recip)
@@ -208,8 +245,16 @@ macro_rules! impl_dhkem {
                 /// secret key, i.e., `8 * Self::PrivateKey::size()`. For X25519 and P-256, this is
                 /// 256 bits of entropy.
                 fn derive_keypair(ikm: &[u8]) -> (Self::PrivateKey, Self::PublicKey) {
-                    let suite_id = kem_suite_id::<Self>();
-                    <$dhkex as DhKeyExchange>::derive_keypair::<$kdf>(&suite_id, ikm)
+                    let suite_id = Self::SUITE_ID;
+                    <$dhkex as DhKeyExchange>::derive_keypair::<$kdf>(&suite_id, ikm, &[])
+                }
+
+                fn derive_keypair_with_domain(
+                    ikm: &[u8],
+                    domain: &[u8],
+                ) -> (Self::PrivateKey, Self::PublicKey) {
+                    let suite_id = Self::SUITE_ID;
+                    <$dhkex as DhKeyExchange>::derive_keypair::<$kdf>(&suite_id, ikm, domain)
                 }
 
                 /// Computes the public key of a given private key
@@ -223,12 +268,21 @@ macro_rules! impl_dhkem {
                     sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
                     csprng: &mut R,
                 ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
-                    // Generate a new ephemeral key
-                    let (sk_eph, _) = Self::gen_keypair(csprng);
+                    // Generate a new ephemeral key. Don't panic if the RNG fails.
+                    let (sk_eph, _) = Self::try_gen_keypair(csprng)?;
                     // Now pass to encap_with_eph()
                     encap_with_eph(pk_recip, sender_id_keypair, sk_eph)
                 }
 
+                #[cfg(feature = "hazmat-deterministic")]
+                fn danger_encap_with_eph(
+                    pk_recip: &Self::PublicKey,
+                    sender_id_keypair: Option<(&Self::PrivateKey, &Self::PublicKey)>,
+                    sk_eph: Self::PrivateKey,
+                ) -> Result<(SharedSecret<Self>, Self::EncappedKey), HpkeError> {
+                    encap_with_eph(pk_recip, sender_id_keypair, sk_eph)
+                }
+
                 // RFC 9180 §4.1
                 // def Decap(enc, skR):
                 //   pkE = DeserializePublicKey(enc)
@@ -257,8 +311,9 @@ macro_rules! impl_dhkem {
                 ///
                 /// Return Value
                 /// ============
-                /// Returns a shared secret on success. If an error happened during key exchange,
-                /// returns `Err(HpkeError::DecapError)`.
+                /// Returns a shared secret on success. If the DH with the encapped key gave the
+                /// all-zero output, returns `Err(HpkeError::InvalidEncappedKey)`. If the DH with
+                /// the sender's identity key did, returns `Err(HpkeError::DecapError)`.
                 #[doc(hidden)]
                 fn decap(
                     sk_recip: &Self::PrivateKey,
@@ -266,11 +321,11 @@ macro_rules! impl_dhkem {
                     encapped_key: &Self::EncappedKey,
                 ) -> Result<SharedSecret<Self>, HpkeError> {
                     // Put together the binding context used for all KDF operations
-                    let suite_id = kem_suite_id::<Self>();
+                    let suite_id = Self::SUITE_ID;
 
                     // Compute the shared secret from the ephemeral inputs
                     let kex_res_eph = <$dhkex as DhKeyExchange>::dh(sk_recip, &encapped_key.0)
-                        .map_err(|_| HpkeError::DecapError)?;
+                        .map_err(|_| HpkeError::InvalidEncappedKey)?;
 
                     // Compute the sender's pubkey from their privkey
                     let pk_recip = <$dhkex as DhKeyExchange>::sk_to_pk(sk_recip);
@@ -344,6 +399,83 @@ macro_rules! impl_dhkem {
                     }
                 }
             }
+
+            impl crate::kem::sealed::Sealed for $kem_name {}
+
+            impl DhKem for $kem_name {
+                /// Does what `decap` does, with the DH outputs computed elsewhere, e.g., by a KMS
+                /// that holds the private key. `dh` is `DH(skR, pkE)`, followed by `DH(skR, pkS)`
+                /// if `pk_sender_id` is given.
+                ///
+                /// Return Value
+                /// ============
+                /// Returns a shared secret on success. If `dh` is the wrong length, returns
+                /// `Err(HpkeError::IncorrectInputLength(expected, dh.len()))`. If the DH with the
+                /// encapped key is all zeros, returns `Err(HpkeError::InvalidEncappedKey)`. If
+                /// the DH with the sender's identity key is, returns `Err(HpkeError::DecapError)`.
+                #[doc(hidden)]
+                fn decap_with_dh(
+                    dh: &[u8],
+                    pk_recip: &Self::PublicKey,
+                    pk_sender_id: Option<&Self::PublicKey>,
+                    encapped_key: &Self::EncappedKey,
+                ) -> Result<SharedSecret<Self>, HpkeError> {
+                    let suite_id = Self::SUITE_ID;
+                    let kex_res_size =
+                        <<$dhkex as DhKeyExchange>::KexResult as Serializable>::size();
+                    let expected_len = match pk_sender_id {
+                        Some(_) => 2 * kex_res_size,
+                        None => kex_res_size,
+                    };
+                    if dh.len() != expected_len {
+                        return Err(HpkeError::IncorrectInputLength(expected_len, dh.len()));
+                    }
+
+                    // Do the all-zero checks that DhKeyExchange::dh does locally
+                    let (kex_res_eph, kex_res_identity) = dh.split_at(kex_res_size);
+                    if is_all_zero(kex_res_eph) {
+                        return Err(HpkeError::InvalidEncappedKey);
+                    }
+                    if pk_sender_id.is_some() && is_all_zero(kex_res_identity) {
+                        return Err(HpkeError::DecapError);
+                    }
+
+                    // kem_context = encapped_key || pk_recip [|| pk_sender_id]
+                    let pk_sender_id = pk_sender_id.map(|pk| pk.to_bytes());
+                    let (kem_context_buf, kem_context_size) = concat_with_known_maxlen!(
+                        MAX_PUBKEY_SIZE,
+                        &encapped_key.to_bytes(),
+                        &pk_recip.to_bytes(),
+                        pk_sender_id.as_deref().unwrap_or(&[])
+                    );
+                    let kem_context = &kem_context_buf[..kem_context_size];
+
+                    let mut shared_secret = <SharedSecret<Self> as Default>::default();
+                    extract_and_expand::<$kdf>(dh, &suite_id, kem_context, &mut shared_secret.0)
+                        .expect("shared secret is way too big");
+                    Ok(shared_secret)
+                }
+
+                #[doc(hidden)]
+                fn encapped_key_to_pk(encapped_key: &Self::EncappedKey) -> Self::PublicKey {
+                    encapped_key.0.clone()
+                }
+
+                fn dh_into(
+                    sk: &Self::PrivateKey,
+                    pk: &Self::PublicKey,
+                    out: &mut [u8],
+                ) -> Result<usize, HpkeError> {
+                    let kex_res = <$dhkex as DhKeyExchange>::dh(sk, pk)
+                        .map_err(|_| HpkeError::DecapError)?
+                        .to_bytes();
+                    if out.len() < kex_res.len() {
+                        return Err(HpkeError::IncorrectInputLength(kex_res.len(), out.len()));
+                    }
+                    out[..kex_res.len()].copy_from_slice(&kex_res);
+                    Ok(kex_res.len())
+                }
+            }
         }
     };
 }
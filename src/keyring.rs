@@ -0,0 +1,414 @@
+//! Holding several recipient keys at once, for key rotation.
+//!
+//! A recipient that rotates its key usually has to keep accepting messages to its old key for a
+//! while, since senders pick up the new one at their own pace. A [`RecipientKeyRing`] holds the
+//! current and old private keys, each under a one-byte key ID, as in Oblivious HTTP's key
+//! configurations. Senders put the ID of the key they encapsulated to next to the encapsulated
+//! key, and the recipient uses it to pick the private key. If the ID isn't known, e.g., because
+//! the protocol doesn't send one, the ring can instead try every key in turn.
+//!
+//! A [`KeyConfigSet`] also tracks what's published about each key: its suite, its public key, and
+//! when it expires. Senders use it to pick the freshest config they can use, and recipients to
+//! find the private key for a config's ID.
+
+use crate::{
+    aead::{Aead, AeadCtxR},
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    setup::setup_receiver,
+    single_shot::single_shot_open,
+    HpkeError, OpModeR, Primitive,
+};
+
+/// A set of private keys, each with a distinct key ID
+pub struct RecipientKeyRing<Kem: KemTrait> {
+    keys: crate::Vec<(u8, Kem::PrivateKey)>,
+}
+
+impl<Kem: KemTrait> Default for RecipientKeyRing<Kem> {
+    fn default() -> RecipientKeyRing<Kem> {
+        RecipientKeyRing {
+            keys: crate::Vec::new(),
+        }
+    }
+}
+
+impl<Kem: KemTrait> RecipientKeyRing<Kem> {
+    /// Makes an empty key ring
+    pub fn new() -> RecipientKeyRing<Kem> {
+        RecipientKeyRing::default()
+    }
+
+    /// Adds a key under the given ID. If the ring already has a key with this ID, replaces it and
+    /// returns the old one.
+    pub fn insert(&mut self, key_id: u8, sk: Kem::PrivateKey) -> Option<Kem::PrivateKey> {
+        match self.keys.iter_mut().find(|(id, _)| *id == key_id) {
+            Some((_, old_sk)) => Some(core::mem::replace(old_sk, sk)),
+            None => {
+                self.keys.push((key_id, sk));
+                None
+            }
+        }
+    }
+
+    /// Removes the key with the given ID, e.g., once its grace period is over, and returns it
+    pub fn remove(&mut self, key_id: u8) -> Option<Kem::PrivateKey> {
+        let idx = self.keys.iter().position(|(id, _)| *id == key_id)?;
+        Some(self.keys.remove(idx).1)
+    }
+
+    /// Returns the key with the given ID
+    pub fn get(&self, key_id: u8) -> Option<&Kem::PrivateKey> {
+        self.keys
+            .iter()
+            .find(|(id, _)| *id == key_id)
+            .map(|(_, sk)| sk)
+    }
+
+    /// Returns the IDs of the keys in the ring, in the order they were added
+    pub fn key_ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.keys.iter().map(|(id, _)| *id)
+    }
+
+    /// Does a [`setup_receiver`] with the key with the given ID
+    ///
+    /// Return Value
+    /// ============
+    /// If no key has ID `key_id`, returns `Err(HpkeError::UnknownKeyId)`. Otherwise returns what
+    /// `setup_receiver` returns.
+    pub fn setup_receiver<A: Aead, Kdf: KdfTrait>(
+        &self,
+        key_id: u8,
+        mode: &OpModeR<Kem>,
+        encapped_key: &Kem::EncappedKey,
+        info: &[u8],
+    ) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError> {
+        let sk_recip = self.get(key_id).ok_or(HpkeError::UnknownKeyId)?;
+        setup_receiver(mode, sk_recip, encapped_key, info)
+    }
+
+    /// Does a [`single_shot_open`] with the key with ID `key_id`. If `key_id` is `None`, tries
+    /// every key in the ring, most recently added first, until one opens the ciphertext. This
+    /// costs a decapsulation per key tried.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok((key_id, plaintext))` on success, where `key_id` is the ID of the key that
+    /// opened the ciphertext. If `key_id` is given and no key has it, returns
+    /// `Err(HpkeError::UnknownKeyId)`. If `key_id` is given, the other errors are those of
+    /// `single_shot_open`. If it isn't, and no key opens the ciphertext, returns
+    /// `Err(HpkeError::OpenError)`.
+    pub fn open<A: Aead, Kdf: KdfTrait>(
+        &self,
+        key_id: Option<u8>,
+        mode: &OpModeR<Kem>,
+        encapped_key: &Kem::EncappedKey,
+        info: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<(u8, crate::Vec<u8>), HpkeError> {
+        let open_with = |sk_recip| {
+            single_shot_open::<A, Kdf, Kem>(mode, sk_recip, encapped_key, info, ciphertext, aad)
+        };
+
+        match key_id {
+            Some(key_id) => {
+                let sk_recip = self.get(key_id).ok_or(HpkeError::UnknownKeyId)?;
+                open_with(sk_recip).map(|plaintext| (key_id, plaintext))
+            }
+            None => self
+                .keys
+                .iter()
+                .rev()
+                .find_map(|(id, sk_recip)| open_with(sk_recip).ok().map(|pt| (*id, pt)))
+                .ok_or(HpkeError::OpenError),
+        }
+    }
+}
+
+/// A published recipient configuration: a key ID, the suite to use with the key, the public key,
+/// and when the config expires
+pub struct KeyConfig<Kem: KemTrait> {
+    /// The ID senders send along with the encapsulated key
+    pub key_id: u8,
+    /// The KDF to use with this key
+    pub kdf_id: u16,
+    /// The AEAD to use with this key
+    pub aead_id: u16,
+    /// The recipient's public key
+    pub pk: Kem::PublicKey,
+    /// The time after which senders must stop using this config, in whatever units the
+    /// application measures time in, e.g., seconds since the Unix epoch
+    pub not_after: u64,
+}
+
+impl<Kem: KemTrait> KeyConfig<Kem> {
+    /// Makes a config for the suite of `A` and `Kdf`
+    pub fn new<A: Aead, Kdf: KdfTrait>(
+        key_id: u8,
+        pk: Kem::PublicKey,
+        not_after: u64,
+    ) -> KeyConfig<Kem> {
+        KeyConfig {
+            key_id,
+            kdf_id: Kdf::KDF_ID,
+            aead_id: A::AEAD_ID,
+            pk,
+            not_after,
+        }
+    }
+
+    /// Returns whether this config is for the suite of `A` and `Kdf`
+    pub fn is_suite<A: Aead, Kdf: KdfTrait>(&self) -> bool {
+        self.kdf_id == Kdf::KDF_ID && self.aead_id == A::AEAD_ID
+    }
+
+    /// Returns whether senders may still use this config at time `now`
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        now <= self.not_after
+    }
+}
+
+/// A set of published recipient configs, each with a distinct key ID, and the private keys of
+/// those the holder is the recipient of
+///
+/// Senders fill the set with [`KeyConfigSet::insert`] from whatever the recipient publishes, and
+/// call [`KeyConfigSet::select`]. Recipients add their configs with
+/// [`KeyConfigSet::insert_with_key`], publish [`KeyConfigSet::configs`], and open with
+/// [`KeyConfigSet::setup_receiver`]. Expiry only stops senders. A recipient keeps accepting a
+/// config until it's removed, so it can give slow senders a grace period.
+pub struct KeyConfigSet<Kem: KemTrait> {
+    configs: crate::Vec<KeyConfig<Kem>>,
+    keys: RecipientKeyRing<Kem>,
+}
+
+impl<Kem: KemTrait> Default for KeyConfigSet<Kem> {
+    fn default() -> KeyConfigSet<Kem> {
+        KeyConfigSet {
+            configs: crate::Vec::new(),
+            keys: RecipientKeyRing::new(),
+        }
+    }
+}
+
+impl<Kem: KemTrait> KeyConfigSet<Kem> {
+    /// Makes an empty set
+    pub fn new() -> KeyConfigSet<Kem> {
+        KeyConfigSet::default()
+    }
+
+    /// Adds a config without a private key, as a sender does. If the set already has a config
+    /// with this key ID, replaces it, drops its private key, and returns the old config.
+    pub fn insert(&mut self, config: KeyConfig<Kem>) -> Option<KeyConfig<Kem>> {
+        self.keys.remove(config.key_id);
+        match self.configs.iter_mut().find(|c| c.key_id == config.key_id) {
+            Some(old_config) => Some(core::mem::replace(old_config, config)),
+            None => {
+                self.configs.push(config);
+                None
+            }
+        }
+    }
+
+    /// Adds a config along with its private key, as a recipient does. If the set already has a
+    /// config with this key ID, replaces it and returns the old config.
+    pub fn insert_with_key(
+        &mut self,
+        config: KeyConfig<Kem>,
+        sk: Kem::PrivateKey,
+    ) -> Option<KeyConfig<Kem>> {
+        let key_id = config.key_id;
+        let old_config = self.insert(config);
+        self.keys.insert(key_id, sk);
+        old_config
+    }
+
+    /// Removes the config with the given key ID, and its private key, and returns the config
+    pub fn remove(&mut self, key_id: u8) -> Option<KeyConfig<Kem>> {
+        self.keys.remove(key_id);
+        let idx = self.configs.iter().position(|c| c.key_id == key_id)?;
+        Some(self.configs.remove(idx))
+    }
+
+    /// Removes every config that expired before `now`, along with its private key
+    pub fn remove_expired(&mut self, now: u64) {
+        let keys = &mut self.keys;
+        self.configs.retain(|c| {
+            let keep = c.is_valid_at(now);
+            if !keep {
+                keys.remove(c.key_id);
+            }
+            keep
+        });
+    }
+
+    /// Returns the config with the given key ID
+    pub fn get(&self, key_id: u8) -> Option<&KeyConfig<Kem>> {
+        self.configs.iter().find(|c| c.key_id == key_id)
+    }
+
+    /// Returns the configs in the set, in the order they were added
+    pub fn configs(&self) -> impl Iterator<Item = &KeyConfig<Kem>> + '_ {
+        self.configs.iter()
+    }
+
+    /// Returns the private key of the config with the given key ID
+    pub fn private_key(&self, key_id: u8) -> Option<&Kem::PrivateKey> {
+        self.keys.get(key_id)
+    }
+
+    /// Picks the config a sender should use at time `now`: of the unexpired configs for the suite
+    /// of `A` and `Kdf`, the one that expires last. Ties go to the one added last.
+    pub fn select<A: Aead, Kdf: KdfTrait>(&self, now: u64) -> Option<&KeyConfig<Kem>> {
+        self.configs
+            .iter()
+            .filter(|c| c.is_suite::<A, Kdf>() && c.is_valid_at(now))
+            .max_by_key(|c| c.not_after)
+    }
+
+    /// Does a [`setup_receiver`] with the private key of the config with the given key ID
+    ///
+    /// Return Value
+    /// ============
+    /// If no config with a private key has ID `key_id`, returns `Err(HpkeError::UnknownKeyId)`.
+    /// If the config is for a different suite than `A` and `Kdf`, returns
+    /// `Err(HpkeError::SuiteNotAllowed(primitive))`, where `primitive` is the one that differs.
+    /// Otherwise returns what `setup_receiver` returns.
+    pub fn setup_receiver<A: Aead, Kdf: KdfTrait>(
+        &self,
+        key_id: u8,
+        mode: &OpModeR<Kem>,
+        encapped_key: &Kem::EncappedKey,
+        info: &[u8],
+    ) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError> {
+        let config = self.get(key_id).ok_or(HpkeError::UnknownKeyId)?;
+        if config.kdf_id != Kdf::KDF_ID {
+            return Err(HpkeError::SuiteNotAllowed(Primitive::Kdf));
+        }
+        if config.aead_id != A::AEAD_ID {
+            return Err(HpkeError::SuiteNotAllowed(Primitive::Aead));
+        }
+        self.keys.setup_receiver(key_id, mode, encapped_key, info)
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::{AesGcm128, ChaCha20Poly1305},
+        kdf::HkdfSha256,
+        kem::X25519HkdfSha256,
+        setup_sender, single_shot_seal, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that the ring opens messages to old and new keys, by ID and by trial, and refuses
+    /// unknown IDs and removed keys
+    #[test]
+    fn test_recipient_key_ring() {
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (old_sk, old_pk) = Kem::gen_keypair(&mut csprng);
+        let (new_sk, new_pk) = Kem::gen_keypair(&mut csprng);
+        let mut ring = RecipientKeyRing::<Kem>::new();
+        assert!(ring.insert(1, old_sk).is_none());
+        assert!(ring.insert(2, new_sk).is_none());
+        assert_eq!(ring.key_ids().collect::<crate::Vec<_>>(), [1, 2]);
+
+        let seal = |pk, csprng: &mut StdRng| {
+            single_shot_seal::<A, Kdf, Kem, _>(&OpModeS::Base, pk, b"info", b"msg", b"", csprng)
+                .unwrap()
+        };
+        let open = |ring: &RecipientKeyRing<Kem>, key_id, (enc, ct): &(_, crate::Vec<u8>)| {
+            ring.open::<A, Kdf>(key_id, &OpModeR::Base, enc, b"info", ct, b"")
+        };
+        let to_old = seal(&old_pk, &mut csprng);
+        let to_new = seal(&new_pk, &mut csprng);
+
+        assert_eq!(open(&ring, Some(1), &to_old).unwrap(), (1, b"msg".to_vec()));
+        assert_eq!(open(&ring, Some(2), &to_new).unwrap(), (2, b"msg".to_vec()));
+        assert_eq!(open(&ring, None, &to_old).unwrap(), (1, b"msg".to_vec()));
+        assert_eq!(open(&ring, None, &to_new).unwrap(), (2, b"msg".to_vec()));
+        assert_eq!(open(&ring, Some(2), &to_old), Err(HpkeError::OpenError));
+        assert_eq!(open(&ring, Some(3), &to_old), Err(HpkeError::UnknownKeyId));
+
+        // Once the old key is retired, its messages no longer open
+        assert!(ring.remove(1).is_some());
+        assert_eq!(open(&ring, Some(1), &to_old), Err(HpkeError::UnknownKeyId));
+        assert_eq!(open(&ring, None, &to_old), Err(HpkeError::OpenError));
+        assert!(ring
+            .setup_receiver::<A, Kdf>(2, &OpModeR::Base, &to_new.0, b"info")
+            .is_ok());
+    }
+
+    /// Tests that senders pick the freshest unexpired config for their suite, and that recipients
+    /// open with the private key of the config senders picked
+    #[test]
+    fn test_key_config_set() {
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let mut recipient = KeyConfigSet::<Kem>::new();
+        let mut sender = KeyConfigSet::<Kem>::new();
+        for (key_id, not_after) in [(1, 100), (2, 200), (3, 150)] {
+            let (sk, pk) = Kem::gen_keypair(&mut csprng);
+            let config = KeyConfig::new::<A, Kdf>(key_id, pk.clone(), not_after);
+            recipient.insert_with_key(config, sk);
+            sender.insert(KeyConfig::new::<A, Kdf>(key_id, pk, not_after));
+        }
+        let (_, pk) = Kem::gen_keypair(&mut csprng);
+        sender.insert(KeyConfig::new::<AesGcm128, Kdf>(4, pk, 300));
+
+        let selected = |set: &KeyConfigSet<Kem>, now| set.select::<A, Kdf>(now).map(|c| c.key_id);
+        assert_eq!(selected(&sender, 50), Some(2));
+        assert_eq!(selected(&sender, 200), Some(2));
+        assert_eq!(selected(&sender, 201), None);
+        assert_eq!(
+            sender.select::<AesGcm128, Kdf>(250).map(|c| c.key_id),
+            Some(4)
+        );
+        assert!(sender.private_key(2).is_none());
+
+        let config = sender.select::<A, Kdf>(50).unwrap();
+        let (enc, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &config.pk, b"info", &mut csprng)
+                .unwrap();
+        let mut receiver_ctx = recipient
+            .setup_receiver::<A, Kdf>(config.key_id, &OpModeR::Base, &enc, b"info")
+            .unwrap();
+        let ct = sender_ctx.seal(b"msg", b"").unwrap();
+        assert_eq!(receiver_ctx.open(&ct, b"").unwrap(), b"msg");
+
+        // Recipients refuse unknown IDs and other suites, and stop accepting expired configs once
+        // they're removed
+        assert_eq!(
+            recipient
+                .setup_receiver::<AesGcm128, Kdf>(2, &OpModeR::Base, &enc, b"info")
+                .err(),
+            Some(HpkeError::SuiteNotAllowed(Primitive::Aead))
+        );
+        assert_eq!(
+            recipient
+                .setup_receiver::<A, Kdf>(4, &OpModeR::Base, &enc, b"info")
+                .err(),
+            Some(HpkeError::UnknownKeyId)
+        );
+        recipient.remove_expired(160);
+        assert_eq!(
+            recipient
+                .configs()
+                .map(|c| c.key_id)
+                .collect::<crate::Vec<_>>(),
+            [2]
+        );
+        assert!(recipient.private_key(1).is_none());
+        assert!(recipient.private_key(2).is_some());
+    }
+}
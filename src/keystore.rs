@@ -0,0 +1,314 @@
+//! Decapsulation with recipient keys held by a mobile OS keystore, i.e., the Android Keystore or
+//! the iOS Secure Enclave.
+//!
+//! Keys in these keystores never leave them, but both platforms can do raw ECDH with them, and
+//! that is all a DHKEM recipient needs. [`setup_receiver_with_keystore`] does the DH operations
+//! through a [`KeystorePrivateKey`], and the rest of the KEM and key schedule in this crate. This
+//! is the blocking counterpart of the `kms` module, since the platform calls are blocking.
+//!
+//! The platform call itself is made by the app, through its own bindings, by implementing
+//! [`PlatformEcdh`]. The reference adapters here take care of the rest:
+//!
+//! * [`SecureEnclaveKey`] is for P-256 keys in the Secure Enclave, the only curve it has. The
+//!   peer key is passed as the X9.63 uncompressed point that `SecKeyCreateWithData` takes, and
+//!   `ecdh` is `SecKeyCopyKeyExchangeResult` with `.ecdhKeyExchangeStandard`.
+//! * [`AndroidKeystoreKey`] is for keys in the Android Keystore. The peer key is passed as the
+//!   DER `SubjectPublicKeyInfo` that `X509EncodedKeySpec` takes, and `ecdh` is
+//!   `KeyAgreement.getInstance("ECDH" or "XDH", "AndroidKeyStore")`, `doPhase`, and
+//!   `generateSecret`. Android 12 (API level 31) added ECDH to the keystore, and Android 13 (API
+//!   level 33) added X25519. Below those, constructing the adapter fails with
+//!   `HpkeError::KeystoreUnsupported`, so the app can fall back, e.g., to a software key.
+//!
+//! Both platforms return the x-coordinate of the shared point, or the X25519 output, which is
+//! exactly the DH output that DHKEM uses.
+
+use crate::{
+    aead::{Aead, AeadCtxR},
+    kdf::Kdf as KdfTrait,
+    kem::{DhKem, Kem as KemTrait},
+    setup::setup_receiver_from_shared_secret,
+    spki::{to_spki_der, SpkiKem},
+    HpkeError, OpModeR,
+};
+
+#[cfg(feature = "p256")]
+use crate::Serializable;
+
+use zeroize::Zeroize;
+
+/// The lowest Android API level whose keystore does ECDH on the NIST curves
+pub const ANDROID_ECDH_MIN_API_LEVEL: u32 = 31;
+/// The lowest Android API level whose keystore does X25519
+pub const ANDROID_X25519_MIN_API_LEVEL: u32 = 33;
+
+/// The ECDH call of a platform keystore, made through the app's bindings. This is the only
+/// platform-specific part.
+pub trait PlatformEcdh {
+    /// Does ECDH of the keystore's private key with the peer public key `peer`, encoded as the
+    /// adapter using this documents, and returns the platform's output
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(dh)` on success. If the platform fails or refuses, e.g., because the user
+    /// didn't authenticate, implementations should return `Err(HpkeError::DecapError)`.
+    fn ecdh(&self, peer: &[u8]) -> Result<crate::Vec<u8>, HpkeError>;
+}
+
+/// A recipient private key of the KEM `Kem` that is held by a platform keystore
+pub trait KeystorePrivateKey<Kem: KemTrait> {
+    /// Returns the public key of this private key
+    fn public_key(&self) -> &Kem::PublicKey;
+
+    /// Computes the Diffie-Hellman output of this private key and the public key `pk`
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(dh)` on success. If the platform fails or refuses, returns
+    /// `Err(HpkeError::DecapError)`.
+    fn dh(&self, pk: &Kem::PublicKey) -> Result<crate::Vec<u8>, HpkeError>;
+}
+
+/// Does what [`setup_receiver`](crate::setup_receiver) does, with the recipient's private key held
+/// by `sk_recip`. In the Auth modes, this does two DH operations, one after the other.
+///
+/// Return Value
+/// ============
+/// On success, returns a decryption context. If `sk_recip.dh` fails, returns its error. If it
+/// returns the wrong number of bytes, returns `Err(HpkeError::IncorrectInputLength(expected,
+/// got))`. Otherwise, the errors are those of `setup_receiver`.
+pub fn setup_receiver_with_keystore<A, Kdf, Kem, K>(
+    mode: &OpModeR<Kem>,
+    sk_recip: &K,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: DhKem,
+    K: KeystorePrivateKey<Kem> + ?Sized,
+{
+    // dh = DH(skR, pkE) [|| DH(skR, pkS)]. Each output is zeroized once it's copied, or if the
+    // second DH fails.
+    let mut dh_eph = sk_recip.dh(&Kem::encapped_key_to_pk(encapped_key))?;
+    let dh_identity = match mode
+        .get_pk_sender_id()
+        .map(|pk_sender_id| sk_recip.dh(pk_sender_id))
+        .transpose()
+    {
+        Ok(dh_identity) => dh_identity,
+        Err(e) => {
+            dh_eph.zeroize();
+            return Err(e);
+        }
+    };
+    // Sized up front, so that no copy is left behind in a freed allocation
+    let identity_len = dh_identity
+        .as_ref()
+        .map_or(0, |dh_identity| dh_identity.len());
+    let mut dh = crate::Vec::with_capacity(dh_eph.len() + identity_len);
+    dh.extend_from_slice(&dh_eph);
+    dh_eph.zeroize();
+    if let Some(mut dh_identity) = dh_identity {
+        dh.extend_from_slice(&dh_identity);
+        dh_identity.zeroize();
+    }
+
+    let shared_secret = Kem::decap_with_dh(
+        &dh,
+        sk_recip.public_key(),
+        mode.get_pk_sender_id(),
+        encapped_key,
+    );
+    dh.zeroize();
+    setup_receiver_from_shared_secret(mode, shared_secret?, info)
+}
+
+/// A P-256 key in the iOS Secure Enclave. See the [module docs](self).
+#[cfg(feature = "p256")]
+#[cfg_attr(docsrs, doc(cfg(feature = "p256")))]
+pub struct SecureEnclaveKey<E: PlatformEcdh> {
+    pk: <crate::kem::DhP256HkdfSha256 as KemTrait>::PublicKey,
+    ecdh: E,
+}
+
+#[cfg(feature = "p256")]
+impl<E: PlatformEcdh> SecureEnclaveKey<E> {
+    /// Makes a key out of the Secure Enclave key that `ecdh` uses, and its public key `pk`, e.g.,
+    /// from `SecKeyCopyPublicKey` and `SecKeyCopyExternalRepresentation`
+    pub fn new(pk: <crate::kem::DhP256HkdfSha256 as KemTrait>::PublicKey, ecdh: E) -> Self {
+        SecureEnclaveKey { pk, ecdh }
+    }
+}
+
+#[cfg(feature = "p256")]
+impl<E: PlatformEcdh> KeystorePrivateKey<crate::kem::DhP256HkdfSha256> for SecureEnclaveKey<E> {
+    fn public_key(&self) -> &<crate::kem::DhP256HkdfSha256 as KemTrait>::PublicKey {
+        &self.pk
+    }
+
+    fn dh(
+        &self,
+        pk: &<crate::kem::DhP256HkdfSha256 as KemTrait>::PublicKey,
+    ) -> Result<crate::Vec<u8>, HpkeError> {
+        // P-256 public keys serialize as uncompressed points, which is what the Secure Enclave
+        // takes
+        self.ecdh.ecdh(&pk.to_bytes())
+    }
+}
+
+/// A key in the Android Keystore. See the [module docs](self).
+pub struct AndroidKeystoreKey<Kem: SpkiKem, E: PlatformEcdh> {
+    pk: Kem::PublicKey,
+    ecdh: E,
+}
+
+impl<Kem: SpkiKem, E: PlatformEcdh> AndroidKeystoreKey<Kem, E> {
+    /// Makes a key out of the keystore key that `ecdh` uses, and its public key `pk`, e.g., from
+    /// `KeyStore.getCertificate(alias).getPublicKey()`, on a device with the given API level
+    /// (`Build.VERSION.SDK_INT`)
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(key)` on success. If the keystore at `api_level` can't do ECDH with `Kem`'s
+    /// curve, returns `Err(HpkeError::KeystoreUnsupported)`.
+    pub fn new(pk: Kem::PublicKey, ecdh: E, api_level: u32) -> Result<Self, HpkeError> {
+        if api_level < Self::min_api_level() {
+            return Err(HpkeError::KeystoreUnsupported);
+        }
+        Ok(AndroidKeystoreKey { pk, ecdh })
+    }
+
+    /// Returns the lowest API level whose keystore does ECDH with `Kem`'s curve
+    pub fn min_api_level() -> u32 {
+        // RFC 9180 §7.1: DHKEM(X25519, HKDF-SHA256)
+        if Kem::KEM_ID == 0x0020 {
+            ANDROID_X25519_MIN_API_LEVEL
+        } else {
+            ANDROID_ECDH_MIN_API_LEVEL
+        }
+    }
+}
+
+impl<Kem: SpkiKem, E: PlatformEcdh> KeystorePrivateKey<Kem> for AndroidKeystoreKey<Kem, E> {
+    fn public_key(&self) -> &Kem::PublicKey {
+        &self.pk
+    }
+
+    fn dh(&self, pk: &Kem::PublicKey) -> Result<crate::Vec<u8>, HpkeError> {
+        self.ecdh.ecdh(&to_spki_der::<Kem>(pk))
+    }
+}
+
+#[cfg(all(test, feature = "p256", feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305,
+        dhkex::{ecdh_nistp::p256::DhP256, x25519::X25519, DhKeyExchange},
+        kdf::HkdfSha256,
+        kem::{DhP256HkdfSha256, X25519HkdfSha256},
+        setup_sender,
+        spki::from_spki_der,
+        Deserializable, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+
+    /// Stands in for the Secure Enclave. It only takes uncompressed P-256 points.
+    struct FakeSecureEnclave(<DhP256HkdfSha256 as KemTrait>::PrivateKey);
+
+    impl PlatformEcdh for FakeSecureEnclave {
+        fn ecdh(&self, peer: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+            if peer.len() != 65 || peer[0] != 0x04 {
+                return Err(HpkeError::DecapError);
+            }
+            let pk = <DhP256 as DhKeyExchange>::PublicKey::from_bytes(peer)?;
+            let dh = DhP256::dh(&self.0, &pk).map_err(|_| HpkeError::DecapError)?;
+            Ok(dh.to_bytes().to_vec())
+        }
+    }
+
+    /// Stands in for the Android Keystore. It only takes SPKIs of X25519 keys.
+    struct FakeAndroidKeystore(<X25519HkdfSha256 as KemTrait>::PrivateKey);
+
+    impl PlatformEcdh for FakeAndroidKeystore {
+        fn ecdh(&self, peer: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+            let pk = from_spki_der::<X25519HkdfSha256>(peer)?;
+            let dh = X25519::dh(&self.0, &pk).map_err(|_| HpkeError::DecapError)?;
+            Ok(dh.to_bytes().to_vec())
+        }
+    }
+
+    /// Tests that both adapters agree with the sender, in the Base and Auth modes, and that old
+    /// Android versions are refused
+    #[test]
+    fn test_keystore_adapters() {
+        let mut csprng = StdRng::from_entropy();
+
+        let (sk_recip, pk_recip) = DhP256HkdfSha256::gen_keypair(&mut csprng);
+        let (sk_sender, pk_sender) = DhP256HkdfSha256::gen_keypair(&mut csprng);
+        let enclave_key = SecureEnclaveKey::new(pk_recip.clone(), FakeSecureEnclave(sk_recip));
+        let modes = [
+            (OpModeS::Base, OpModeR::Base),
+            (
+                OpModeS::Auth((sk_sender, pk_sender.clone())),
+                OpModeR::Auth(pk_sender),
+            ),
+        ];
+        for (mode_s, mode_r) in modes.iter() {
+            let (enc, mut sender_ctx) = setup_sender::<A, Kdf, DhP256HkdfSha256, _>(
+                mode_s,
+                &pk_recip,
+                b"info",
+                &mut csprng,
+            )
+            .unwrap();
+            let mut receiver_ctx =
+                setup_receiver_with_keystore::<A, Kdf, _, _>(mode_r, &enclave_key, &enc, b"info")
+                    .unwrap();
+            let ct = sender_ctx.seal(b"from the enclave", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&ct, b"").unwrap(), b"from the enclave");
+        }
+
+        let (sk_recip, pk_recip) = X25519HkdfSha256::gen_keypair(&mut csprng);
+        assert_eq!(
+            AndroidKeystoreKey::<X25519HkdfSha256, _>::new(
+                pk_recip.clone(),
+                FakeAndroidKeystore(sk_recip.clone()),
+                32
+            )
+            .err(),
+            Some(HpkeError::KeystoreUnsupported)
+        );
+        assert_eq!(
+            AndroidKeystoreKey::<DhP256HkdfSha256, FakeSecureEnclave>::min_api_level(),
+            31
+        );
+        let android_key = AndroidKeystoreKey::<X25519HkdfSha256, _>::new(
+            pk_recip.clone(),
+            FakeAndroidKeystore(sk_recip),
+            33,
+        )
+        .unwrap();
+        let (enc, mut sender_ctx) = setup_sender::<A, Kdf, X25519HkdfSha256, _>(
+            &OpModeS::Base,
+            &pk_recip,
+            b"info",
+            &mut csprng,
+        )
+        .unwrap();
+        let mut receiver_ctx = setup_receiver_with_keystore::<A, Kdf, _, _>(
+            &OpModeR::Base,
+            &android_key,
+            &enc,
+            b"info",
+        )
+        .unwrap();
+        let ct = sender_ctx.seal(b"from the keystore", b"").unwrap();
+        assert_eq!(receiver_ctx.open(&ct, b"").unwrap(), b"from the keystore");
+    }
+}
@@ -0,0 +1,123 @@
+//! Decapsulation with recipient keys held by a cloud KMS or HSM that is only reachable over an
+//! async API.
+//!
+//! Such services don't hand out private keys, but they do offer raw ECDH, e.g., AWS KMS's
+//! `DeriveSharedSecret` or an HSM's `C_DeriveKey` with `CKM_ECDH1_DERIVE`. That is all a DHKEM
+//! recipient needs: implement [`AsyncDh`] on a handle to the key, and [`setup_receiver_async`]
+//! does the DH operations through it, awaiting each, and the rest of the KEM and key schedule
+//! locally. Nothing blocks the executor while the service answers.
+//!
+//! `AsyncDh` is the same trait that the [`nonblocking`](crate::nonblocking) module uses for keys
+//! held by crypto peripherals. A KMS client's futures are usually boxed, so [`BoxedDhFuture`] is
+//! provided to name them.
+
+use crate::HpkeError;
+
+use core::{future::Future, pin::Pin};
+
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use std::boxed::Box;
+
+/// Sets up a receiver context with the recipient's private key held by a KMS or HSM. This is
+/// [`nonblocking::setup_receiver_nonblocking`](crate::nonblocking::setup_receiver_nonblocking).
+pub use crate::nonblocking::setup_receiver_nonblocking as setup_receiver_async;
+pub use crate::nonblocking::AsyncDh;
+
+/// A boxed, `Send` future of a DH operation, for use as an [`AsyncDh::DhFuture`]
+pub type BoxedDhFuture<'a> = Pin<Box<dyn Future<Output = Result<usize, HpkeError>> + Send + 'a>>;
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305,
+        dhkex::{x25519::X25519, DhKeyExchange},
+        kdf::HkdfSha256,
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+        setup_sender, OpModeR, OpModeS, Serializable,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Stands in for a KMS. It can be told to answer with too few bytes.
+    struct FakeKms {
+        sk: <Kem as KemTrait>::PrivateKey,
+        pk: <Kem as KemTrait>::PublicKey,
+        truncate: bool,
+    }
+
+    impl AsyncDh<Kem> for FakeKms {
+        type DhFuture<'a> = BoxedDhFuture<'a>;
+
+        fn public_key(&self) -> &<Kem as KemTrait>::PublicKey {
+            &self.pk
+        }
+
+        fn dh<'a>(
+            &'a mut self,
+            pk: &'a <Kem as KemTrait>::PublicKey,
+            out: &'a mut [u8],
+        ) -> BoxedDhFuture<'a> {
+            Box::pin(async move {
+                let dh = <X25519 as DhKeyExchange>::dh(&self.sk, pk)
+                    .map_err(|_| HpkeError::DecapError)?;
+                let len = if self.truncate { 31 } else { 32 };
+                out[..len].copy_from_slice(&dh.to_bytes()[..len]);
+                Ok(len)
+            })
+        }
+    }
+
+    /// Tests that async setup agrees with the sender in the Base and Auth modes, and that a DH
+    /// output of the wrong length is refused
+    #[test]
+    fn test_setup_receiver_async() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (sk_sender, pk_sender) = Kem::gen_keypair(&mut csprng);
+        let mut kms = FakeKms {
+            sk: sk_recip,
+            pk: pk_recip.clone(),
+            truncate: false,
+        };
+
+        let modes = [
+            (OpModeS::Base, OpModeR::Base),
+            (
+                OpModeS::Auth((sk_sender, pk_sender.clone())),
+                OpModeR::Auth(pk_sender),
+            ),
+        ];
+        for (mode_s, mode_r) in modes.iter() {
+            let (enc, mut sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(mode_s, &pk_recip, b"info", &mut csprng).unwrap();
+            let mut receiver_ctx = pollster::block_on(setup_receiver_async::<A, Kdf, Kem, _>(
+                mode_r, &mut kms, &enc, b"info",
+            ))
+            .unwrap();
+            let ct = sender_ctx.seal(b"from the kms", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&ct, b"").unwrap(), b"from the kms");
+        }
+
+        let (enc, _) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"info", &mut csprng)
+                .unwrap();
+        kms.truncate = true;
+        assert_eq!(
+            pollster::block_on(setup_receiver_async::<A, Kdf, Kem, _>(
+                &OpModeR::Base,
+                &mut kms,
+                &enc,
+                b"info"
+            ))
+            .err(),
+            Some(HpkeError::IncorrectInputLength(32, 31))
+        );
+    }
+}
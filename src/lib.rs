@@ -92,13 +92,17 @@ extern crate alloc;
 #[cfg(all(feature = "alloc", not(feature = "std")))]
 pub(crate) use alloc::vec::Vec;
 
+// Lets the code that `#[derive(HpkeSealed)]` generates, which names `::hpke`, work in this crate
+#[cfg(feature = "derive")]
+extern crate self as hpke;
+
 //-------- Testing stuff --------//
 
-// kat_tests tests all the implemented ciphersuites, and thus needs all the dependencies. It also
-// needs std for file IO.
+// kat_tests tests all the implemented ciphersuites, and thus needs all the dependencies. Its file
+// IO and JSON parsing are behind the kat feature, so nothing else here can come to depend on std.
 #[cfg(all(
     test,
-    feature = "std",
+    feature = "kat",
     feature = "x25519",
     feature = "p256",
     feature = "p384",
@@ -109,43 +113,174 @@ mod kat_tests;
 #[cfg(test)]
 mod test_util;
 
+// The timing tests are opt-in, since they're slow and noisy
+#[cfg(all(test, feature = "timing-tests"))]
+mod timing_tests;
+
+// The test vector loader and generator, which the known-answer tests and the gen-vectors binary
+// use. This is the only part of the crate that needs serde_json and std::io.
+#[cfg(feature = "kat")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kat")))]
+pub mod testing;
+
 //-------- Modules and exports--------//
 
-// Re-export our versions of generic_array and rand_core, since their traits and types are exposed
-// in this crate
+// Re-export our versions of generic_array, rand_core, and subtle, since their traits and types are
+// exposed in this crate
 pub use generic_array;
 pub use rand_core;
+pub use subtle;
 
 #[macro_use]
 mod util;
 
 pub mod aead;
+#[cfg(feature = "agent")]
+#[cfg_attr(docsrs, doc(cfg(feature = "agent")))]
+pub mod agent;
+#[cfg(feature = "armor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "armor")))]
+pub mod armor;
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+pub mod cbor;
 mod dhkex;
+#[cfg(feature = "ech")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ech")))]
+pub mod ech;
+#[cfg(feature = "enclave")]
+#[cfg_attr(docsrs, doc(cfg(feature = "enclave")))]
+pub mod enclave;
+#[cfg(feature = "encoding")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+pub mod encoding;
+pub mod entropy;
+#[cfg(feature = "fanout")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fanout")))]
+pub mod fanout;
+pub mod fingerprint;
+#[cfg(feature = "fips")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fips")))]
+pub mod fips;
+#[cfg(feature = "heapless")]
+#[cfg_attr(docsrs, doc(cfg(feature = "heapless")))]
+pub mod heapless;
+#[cfg(feature = "hybrid")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hybrid")))]
+pub mod hybrid;
+#[cfg(any(feature = "alloc", feature = "std"))]
+mod info;
 pub mod kdf;
 pub mod kem;
+#[cfg(feature = "keyring")]
+#[cfg_attr(docsrs, doc(cfg(feature = "keyring")))]
+pub mod keyring;
+#[cfg(feature = "keystore")]
+#[cfg_attr(docsrs, doc(cfg(feature = "keystore")))]
+pub mod keystore;
+#[cfg(feature = "kms")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kms")))]
+pub mod kms;
+#[cfg(all(feature = "mlock", unix))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "mlock", unix))))]
+pub mod locked;
+pub mod media;
+pub mod nonblocking;
+#[cfg(feature = "offload")]
+#[cfg_attr(docsrs, doc(cfg(feature = "offload")))]
+pub mod offload;
+#[cfg(feature = "ohttp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ohttp")))]
+pub mod ohttp;
+#[cfg(feature = "onion")]
+#[cfg_attr(docsrs, doc(cfg(feature = "onion")))]
+pub mod onion;
 mod op_mode;
+#[cfg(feature = "padding")]
+#[cfg_attr(docsrs, doc(cfg(feature = "padding")))]
+pub mod padding;
+pub mod policy;
+#[cfg(feature = "postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postcard")))]
+pub mod postcard;
+#[cfg(feature = "prost")]
+#[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+pub mod proto;
+pub mod psk;
+#[cfg(feature = "reduced-security")]
+#[cfg_attr(docsrs, doc(cfg(feature = "reduced-security")))]
+pub mod reduced_security;
+#[cfg(feature = "derive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+pub mod sealed;
+mod self_test;
+#[cfg(feature = "session")]
+#[cfg_attr(docsrs, doc(cfg(feature = "session")))]
+pub mod session;
 mod setup;
+mod simple;
 mod single_shot;
+#[cfg(feature = "spki")]
+#[cfg_attr(docsrs, doc(cfg(feature = "spki")))]
+pub mod spki;
+mod suite_params;
+pub mod suites;
+mod trace;
+#[cfg(feature = "wasm")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm")))]
+pub mod wasm;
 
+#[doc(inline)]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+#[cfg(any(feature = "alloc", feature = "std"))]
+pub use info::InfoBuilder;
 #[doc(inline)]
 pub use kem::Kem;
 #[doc(inline)]
-pub use op_mode::{OpModeR, OpModeS, PskBundle};
+pub use op_mode::{OpModeR, OpModeS, PskBundle, PskStore};
+#[doc(inline)]
+pub use self_test::{self_test, SelfTestEntry, SelfTestFailure, SelfTestKind, SelfTestReport};
+#[doc(inline)]
+pub use setup::{
+    key_schedule_context, setup_receiver, setup_receiver_with_psk_store, setup_sender,
+    MAX_SCHED_CONTEXT_SIZE,
+};
 #[doc(inline)]
-pub use setup::{setup_receiver, setup_sender};
+pub use simple::Hpke;
 #[doc(inline)]
-pub use single_shot::{single_shot_open_in_place_detached, single_shot_seal_in_place_detached};
+#[cfg(feature = "p256")]
+pub use simple::HpkeP256;
+#[doc(inline)]
+#[cfg(feature = "x25519")]
+pub use simple::HpkeX25519;
+#[doc(inline)]
+pub use suite_params::SuiteParams;
+
+#[doc(inline)]
+#[cfg(feature = "parallel")]
+pub use setup::setup_sender_batch;
+
+#[doc(inline)]
+pub use single_shot::{
+    single_shot_open_in_place, single_shot_open_in_place_detached, single_shot_seal_in_place,
+    single_shot_seal_in_place_detached,
+};
 
 #[doc(inline)]
 #[cfg(any(feature = "alloc", feature = "std"))]
 pub use single_shot::{single_shot_open, single_shot_seal};
 
+#[doc(inline)]
+#[cfg(feature = "parallel")]
+pub use single_shot::{single_shot_open_batch, single_shot_seal_batch, OpenJob, SealJob};
+
 //-------- Top-level types --------//
 
 use generic_array::{typenum::marker_traits::Unsigned, ArrayLength, GenericArray};
 
 /// Describes things that can go wrong in the HPKE protocol
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum HpkeError {
     /// The allowed number of message encryptions has been reached
     MessageLimitReached,
@@ -164,6 +299,93 @@ pub enum HpkeError {
     /// An input isn't the right length. First value is the expected length, second is the given
     /// length.
     IncorrectInputLength(usize, usize),
+    /// The RNG failed to produce randomness
+    RngError,
+    /// The encapsulated key is the identity, is not on the curve, or lies in a small subgroup
+    InvalidEncappedKey,
+    /// The preshared key or its ID is empty or, with the `strict` feature, too weak
+    InvalidPsk,
+    /// The power-on self-test failed, so the `fips` module refuses to set up contexts
+    SelfTestFailed,
+    /// A [`policy::Policy`] refused the ciphersuite. The value is the primitive it refused.
+    SuiteNotAllowed(Primitive),
+    /// An export label is one that HPKE uses internally. See [`aead::RESERVED_LABELS`].
+    ReservedLabel,
+    /// A plaintext is longer than the AEAD can seal. See [`aead::Aead::MAX_PLAINTEXT_LEN`].
+    PlaintextTooLong,
+    /// An AAD, info string, PSK, PSK ID, or exporter context is longer than the AEAD or KDF
+    /// allows
+    InputTooLong,
+    /// Memory for a private key couldn't be locked
+    MemoryLockError,
+    /// No key has the given key ID, or no PSK the given PSK ID. See [`keyring::RecipientKeyRing`]
+    /// and [`PskStore`].
+    UnknownKeyId,
+    /// A fixed-capacity buffer or list is too small. First value is the capacity needed, second is
+    /// the capacity. See the [`heapless`](crate::heapless) module.
+    CapacityExceeded(usize, usize),
+    /// The platform keystore can't do ECDH with this kind of key. See the
+    /// [`keystore`](crate::keystore) module.
+    KeystoreUnsupported,
+}
+
+/// The kind of primitive that produced an [`HpkeError`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Primitive {
+    /// The key encapsulation mechanism
+    Kem,
+    /// The key derivation function
+    Kdf,
+    /// The authenticated encryption scheme
+    Aead,
+    /// The caller-provided random number generator
+    Rng,
+}
+
+impl core::fmt::Display for Primitive {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Primitive::Kem => write!(f, "KEM"),
+            Primitive::Kdf => write!(f, "KDF"),
+            Primitive::Aead => write!(f, "AEAD"),
+            Primitive::Rng => write!(f, "RNG"),
+        }
+    }
+}
+
+impl HpkeError {
+    /// Returns the primitive that produced this error. Returns `None` if the error is due to a
+    /// malformed input, i.e., a `ValidationError`, `IncorrectInputLength`, `InvalidPsk`,
+    /// `ReservedLabel`, `PlaintextTooLong`, `InputTooLong`, `UnknownKeyId`, or
+    /// `CapacityExceeded`, rather than to a primitive failing.
+    /// Also returns `None` for `SelfTestFailed`, which says which primitive failed in
+    /// [`self_test`]'s report, and for `MemoryLockError` and `KeystoreUnsupported`, which are the
+    /// operating system's doing.
+    pub fn primitive(&self) -> Option<Primitive> {
+        match self {
+            HpkeError::EncapError | HpkeError::DecapError | HpkeError::InvalidEncappedKey => {
+                Some(Primitive::Kem)
+            }
+            HpkeError::KdfOutputTooLong => Some(Primitive::Kdf),
+            HpkeError::MessageLimitReached | HpkeError::OpenError | HpkeError::SealError => {
+                Some(Primitive::Aead)
+            }
+            HpkeError::RngError => Some(Primitive::Rng),
+            HpkeError::SuiteNotAllowed(p) => Some(*p),
+            HpkeError::ValidationError
+            | HpkeError::IncorrectInputLength(..)
+            | HpkeError::InvalidPsk
+            | HpkeError::ReservedLabel
+            | HpkeError::PlaintextTooLong
+            | HpkeError::InputTooLong
+            | HpkeError::MemoryLockError
+            | HpkeError::UnknownKeyId
+            | HpkeError::CapacityExceeded(..)
+            | HpkeError::KeystoreUnsupported
+            | HpkeError::SelfTestFailed => None,
+        }
+    }
 }
 
 impl core::fmt::Display for HpkeError {
@@ -181,6 +403,24 @@ impl core::fmt::Display for HpkeError {
                 "Incorrect input length. Expected {} bytes. Got {}.",
                 expected, given
             ),
+            HpkeError::RngError => write!(f, "RNG failed to produce randomness"),
+            HpkeError::InvalidEncappedKey => write!(f, "Encapsulated key is invalid"),
+            HpkeError::InvalidPsk => write!(f, "Preshared key is invalid"),
+            HpkeError::SelfTestFailed => write!(f, "Power-on self-test failed"),
+            HpkeError::SuiteNotAllowed(p) => write!(f, "{} is not allowed by policy", p),
+            HpkeError::ReservedLabel => write!(f, "Label is reserved for HPKE's internal use"),
+            HpkeError::PlaintextTooLong => write!(f, "Plaintext is too long for the AEAD"),
+            HpkeError::InputTooLong => write!(f, "Input is longer than the ciphersuite allows"),
+            HpkeError::MemoryLockError => write!(f, "Failed to lock memory"),
+            HpkeError::UnknownKeyId => write!(f, "No key has the given key ID"),
+            HpkeError::CapacityExceeded(needed, capacity) => write!(
+                f,
+                "Capacity exceeded. Needed {}. Capacity is {}.",
+                needed, capacity
+            ),
+            HpkeError::KeystoreUnsupported => {
+                write!(f, "Platform keystore can't do ECDH with this key")
+            }
         }
     }
 }
@@ -0,0 +1,136 @@
+//! Private keys stored in page-locked memory, for long-term keys whose threat model includes swap
+//! and core dumps.
+//!
+//! A [`LockedPrivateKey`] lives in its own pages of memory, which are locked with `mlock` so they
+//! are never written to swap. On Linux, the pages are also excluded from core dumps with
+//! `madvise(MADV_DONTDUMP)`. When the key is dropped, it is zeroized and the pages are unlocked.
+//!
+//! This only protects the key once it's locked away. Whatever it was made from, e.g., the bytes
+//! passed to [`LockedPrivateKey::from_bytes`], is not protected, and should be zeroized by the
+//! caller. Locking can fail if the process's locked memory limit (`RLIMIT_MEMLOCK`) is used up.
+
+use crate::{kem::Kem as KemTrait, Deserializable, HpkeError};
+
+use core::{ptr::NonNull, slice};
+use std::alloc::{self, Layout};
+
+use zeroize::Zeroize;
+
+/// A private key stored in page-locked memory. Use [`LockedPrivateKey::expose`] to get at the key,
+/// e.g., to pass it to `setup_receiver`.
+pub struct LockedPrivateKey<Kem: KemTrait> {
+    /// The key. This is the start of an allocation of `layout`, which is locked.
+    ptr: NonNull<Kem::PrivateKey>,
+    /// The page-aligned, whole-page layout of the allocation
+    layout: Layout,
+}
+
+// The key is owned, like in a Box
+unsafe impl<Kem: KemTrait> Send for LockedPrivateKey<Kem> where Kem::PrivateKey: Send {}
+unsafe impl<Kem: KemTrait> Sync for LockedPrivateKey<Kem> where Kem::PrivateKey: Sync {}
+
+impl<Kem: KemTrait> LockedPrivateKey<Kem> {
+    /// Moves the given private key into locked memory. The key gets pages to itself, so unlocking
+    /// them on drop never unlocks anything else.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(locked_key)` on success. If the memory couldn't be locked, returns
+    /// `Err(HpkeError::MemoryLockError)`.
+    pub fn new(sk: Kem::PrivateKey) -> Result<LockedPrivateKey<Kem>, HpkeError> {
+        // Round the key's size up to a whole number of pages, and align to a page. Page sizes are
+        // powers of two.
+        let page_size = page_size();
+        let size = core::mem::size_of::<Kem::PrivateKey>().max(1);
+        let layout = Layout::from_size_align(
+            (size + page_size - 1) & !(page_size - 1),
+            page_size.max(core::mem::align_of::<Kem::PrivateKey>()),
+        )
+        .map_err(|_| HpkeError::MemoryLockError)?;
+
+        // Allocate and lock before the key is written, so it never touches swappable memory there
+        let ptr = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        if unsafe { libc::mlock(ptr.as_ptr().cast(), layout.size()) } != 0 {
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) };
+            return Err(HpkeError::MemoryLockError);
+        }
+        // This is best effort. Old kernels don't know MADV_DONTDUMP, and the key is still locked.
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::madvise(ptr.as_ptr().cast(), layout.size(), libc::MADV_DONTDUMP);
+        }
+
+        let ptr = ptr.cast::<Kem::PrivateKey>();
+        unsafe { ptr.as_ptr().write(sk) };
+        Ok(LockedPrivateKey { ptr, layout })
+    }
+
+    /// Deserializes a private key into locked memory. The key is briefly on the stack while it's
+    /// deserialized. `encoded` is left as is, so zeroize it when you're done with it.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(locked_key)` on success. Returns the errors of `Kem::PrivateKey::from_bytes`
+    /// and `LockedPrivateKey::new`.
+    pub fn from_bytes(encoded: &[u8]) -> Result<LockedPrivateKey<Kem>, HpkeError> {
+        Self::new(Kem::PrivateKey::from_bytes(encoded)?)
+    }
+
+    /// Returns a reference to the key. Keep the reference short-lived, and don't clone the key
+    /// out, or the copy won't be locked.
+    pub fn expose(&self) -> &Kem::PrivateKey {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<Kem: KemTrait> Drop for LockedPrivateKey<Kem> {
+    fn drop(&mut self) {
+        unsafe {
+            // Run the key's own destructor, then zero the whole allocation in case the key left
+            // anything behind
+            core::ptr::drop_in_place(self.ptr.as_ptr());
+            let bytes = self.ptr.as_ptr().cast::<u8>();
+            slice::from_raw_parts_mut(bytes, self.layout.size()).zeroize();
+
+            libc::munlock(bytes.cast(), self.layout.size());
+            alloc::dealloc(bytes, self.layout);
+        }
+    }
+}
+
+/// Returns the size of a page of memory
+fn page_size() -> usize {
+    // This can't fail on any Unix this crate runs on, but fall back to the most common size
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        n if n > 0 => n as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{kem::X25519HkdfSha256, Serializable};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that a locked key is the same key, whether moved in or deserialized
+    #[test]
+    fn test_locked_private_key() {
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk, _) = Kem::gen_keypair(&mut csprng);
+        let sk_bytes = sk.to_bytes();
+
+        let locked = LockedPrivateKey::<Kem>::new(sk.clone()).unwrap();
+        assert!(locked.expose() == &sk);
+        // The key got a page to itself
+        assert_eq!(locked.ptr.as_ptr() as usize % page_size(), 0);
+
+        let locked = LockedPrivateKey::<Kem>::from_bytes(&sk_bytes).unwrap();
+        assert!(locked.expose() == &sk);
+        assert!(LockedPrivateKey::<Kem>::from_bytes(&sk_bytes[..31]).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! An export profile for realtime media keys, e.g., SRTP master keys and salts for a call set up
+//! over HPKE.
+//!
+//! `AeadCtxS::export_media_keys` and `AeadCtxR::export_media_keys` derive a key and a salt for
+//! each direction, sized for an SRTP protection profile. The HPKE sender is the *initiator*, and
+//! the receiver is the *responder*. Each side gets [`MediaKeys`] from its own point of view, so
+//! one side's `send` keys are the other's `recv` keys.
+//!
+//! Derivation
+//! ==========
+//! With `id = I2OSP(profile_id, 2)`, where `profile_id` is the profile's DTLS-SRTP ID (RFC 5764
+//! §4.1.2), and `context` the caller's context, e.g., a call ID, the four secrets are
+//!
+//! ```text
+//! initiator_key  = export_labeled("hpke-media", concat(id, "initiator key"),  context, Nkey)
+//! initiator_salt = export_labeled("hpke-media", concat(id, "initiator salt"), context, Nsalt)
+//! responder_key  = export_labeled("hpke-media", concat(id, "responder key"),  context, Nkey)
+//! responder_salt = export_labeled("hpke-media", concat(id, "responder salt"), context, Nsalt)
+//! ```
+//!
+//! where `Nkey` and `Nsalt` are the profile's master key and salt lengths. Binding the profile ID
+//! means two ends that disagree on the profile get unrelated keys, rather than prefixes of the
+//! same ones.
+
+use crate::HpkeError;
+
+use zeroize::Zeroize;
+
+/// The `domain` that media keys are exported under
+pub const MEDIA_EXPORT_DOMAIN: &[u8] = b"hpke-media";
+
+/// The longest master key of any profile
+const MAX_KEY_LEN: usize = 32;
+/// The longest master salt of any profile
+const MAX_SALT_LEN: usize = 14;
+/// The longest label name, "initiator salt"
+const MAX_LABEL_LEN: usize = 14;
+
+/// An SRTP protection profile, which fixes the lengths of the master key and salt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SrtpProfile {
+    /// `SRTP_AES128_CM_HMAC_SHA1_80` (RFC 3711): a 16-byte key and a 14-byte salt
+    Aes128CmHmacSha1_80,
+    /// `SRTP_AEAD_AES_128_GCM` (RFC 7714): a 16-byte key and a 12-byte salt
+    AeadAes128Gcm,
+    /// `SRTP_AEAD_AES_256_GCM` (RFC 7714): a 32-byte key and a 12-byte salt
+    AeadAes256Gcm,
+}
+
+impl SrtpProfile {
+    /// The profile's DTLS-SRTP protection profile ID
+    pub fn id(&self) -> u16 {
+        match self {
+            SrtpProfile::Aes128CmHmacSha1_80 => 0x0001,
+            SrtpProfile::AeadAes128Gcm => 0x0007,
+            SrtpProfile::AeadAes256Gcm => 0x0008,
+        }
+    }
+
+    /// The length of the master key, in bytes
+    pub fn key_len(&self) -> usize {
+        match self {
+            SrtpProfile::Aes128CmHmacSha1_80 | SrtpProfile::AeadAes128Gcm => 16,
+            SrtpProfile::AeadAes256Gcm => 32,
+        }
+    }
+
+    /// The length of the master salt, in bytes
+    pub fn salt_len(&self) -> usize {
+        match self {
+            SrtpProfile::Aes128CmHmacSha1_80 => 14,
+            SrtpProfile::AeadAes128Gcm | SrtpProfile::AeadAes256Gcm => 12,
+        }
+    }
+}
+
+/// The master key and salt for one direction of a media session
+pub struct DirectionKeys {
+    key: [u8; MAX_KEY_LEN],
+    salt: [u8; MAX_SALT_LEN],
+    profile: SrtpProfile,
+}
+
+// The keys are secret
+impl Drop for DirectionKeys {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.salt.zeroize();
+    }
+}
+
+impl DirectionKeys {
+    /// Returns the master key, which is `profile.key_len()` bytes long
+    pub fn key(&self) -> &[u8] {
+        &self.key[..self.profile.key_len()]
+    }
+
+    /// Returns the master salt, which is `profile.salt_len()` bytes long
+    pub fn salt(&self) -> &[u8] {
+        &self.salt[..self.profile.salt_len()]
+    }
+}
+
+/// The media keys for both directions, from one end's point of view
+pub struct MediaKeys {
+    /// The keys for media this end sends
+    pub send: DirectionKeys,
+    /// The keys for media this end receives
+    pub recv: DirectionKeys,
+}
+
+/// Derives the media keys with `export_labeled`, from the initiator's point of view if
+/// `is_initiator`, and the responder's otherwise
+pub(crate) fn export_media_keys(
+    profile: SrtpProfile,
+    context: &[u8],
+    is_initiator: bool,
+    export_labeled: impl Fn(&[u8], &[u8], &mut [u8]) -> Result<(), HpkeError>,
+) -> Result<MediaKeys, HpkeError> {
+    let direction = |key_label: &[u8], salt_label: &[u8]| -> Result<DirectionKeys, HpkeError> {
+        let mut keys = DirectionKeys {
+            key: [0u8; MAX_KEY_LEN],
+            salt: [0u8; MAX_SALT_LEN],
+            profile,
+        };
+        let mut label = [0u8; 2 + MAX_LABEL_LEN];
+        label[..2].copy_from_slice(&profile.id().to_be_bytes());
+        for (name, out) in [
+            (key_label, &mut keys.key[..profile.key_len()]),
+            (salt_label, &mut keys.salt[..profile.salt_len()]),
+        ] {
+            label[2..2 + name.len()].copy_from_slice(name);
+            export_labeled(&label[..2 + name.len()], context, out)?;
+        }
+        Ok(keys)
+    };
+    let initiator = direction(b"initiator key", b"initiator salt")?;
+    let responder = direction(b"responder key", b"responder salt")?;
+    Ok(if is_initiator {
+        MediaKeys {
+            send: initiator,
+            recv: responder,
+        }
+    } else {
+        MediaKeys {
+            send: responder,
+            recv: initiator,
+        }
+    })
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305,
+        kdf::HkdfSha256,
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+        setup_receiver, setup_sender, OpModeR, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests that each side's send keys are the other's receive keys, that the lengths follow the
+    /// profile, and that the direction, profile, and context all separate the keys
+    #[test]
+    fn test_export_media_keys() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let (enc, sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk, b"call", &mut csprng).unwrap();
+        let receiver_ctx =
+            setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk, &enc, b"call").unwrap();
+
+        for profile in [
+            SrtpProfile::Aes128CmHmacSha1_80,
+            SrtpProfile::AeadAes128Gcm,
+            SrtpProfile::AeadAes256Gcm,
+        ] {
+            let initiator = sender_ctx.export_media_keys(profile, b"call-1").unwrap();
+            let responder = receiver_ctx.export_media_keys(profile, b"call-1").unwrap();
+            assert_eq!(initiator.send.key(), responder.recv.key());
+            assert_eq!(initiator.send.salt(), responder.recv.salt());
+            assert_eq!(initiator.recv.key(), responder.send.key());
+            assert_eq!(initiator.recv.salt(), responder.send.salt());
+            assert_ne!(initiator.send.key(), initiator.recv.key());
+            assert_eq!(initiator.send.key().len(), profile.key_len());
+            assert_eq!(initiator.send.salt().len(), profile.salt_len());
+
+            let other_call = sender_ctx.export_media_keys(profile, b"call-2").unwrap();
+            assert_ne!(other_call.send.key(), initiator.send.key());
+        }
+
+        // Profiles with the same key length still get unrelated keys
+        let cm = sender_ctx
+            .export_media_keys(SrtpProfile::Aes128CmHmacSha1_80, b"")
+            .unwrap();
+        let gcm = sender_ctx
+            .export_media_keys(SrtpProfile::AeadAes128Gcm, b"")
+            .unwrap();
+        assert_ne!(cm.send.key(), gcm.send.key());
+    }
+}
@@ -0,0 +1,381 @@
+//! Setup that yields to the executor, for async firmware, e.g., on Embassy, where every task
+//! shares one thread and a blocking P-384 decap stalls all of them.
+//!
+//! [`setup_sender_nonblocking`] and [`setup_receiver_nonblocking`] do what `setup_sender` and
+//! `setup_receiver` do, one step at a time, and yield between the steps. Every DH operation of
+//! the receiver goes through [`AsyncDh`], so a key held by a crypto peripheral, a secure element,
+//! or an external key agent is awaited instead of blocked on. [`SoftwareKey`] is the `AsyncDh`
+//! for a key in memory, which yields once before each DH.
+//!
+//! ```ignore
+//! let mut sk_recip = SoftwareKey::<Kem>::new(sk_recip);
+//! let mut ctx =
+//!     setup_receiver_nonblocking::<A, Kdf, Kem, _>(&OpModeR::Base, &mut sk_recip, &enc, info)
+//!         .await?;
+//! ```
+//!
+//! Nothing here allocates. The futures are as big as the DH outputs they hold, under 200 bytes.
+
+use crate::{
+    aead::{Aead, AeadCtxR, AeadCtxS},
+    kdf::Kdf as KdfTrait,
+    kem::{DhKem, Kem as KemTrait},
+    setup::{setup_receiver_from_shared_secret, setup_sender_from_shared_secret},
+    Deserializable, HpkeError, OpModeR, OpModeS, Serializable,
+};
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+/// The largest DH output of any KEM here, which is P-521's x-coordinate
+const MAX_DH_SIZE: usize = 66;
+
+/// A future that returns `Pending` once, after asking to be polled again, and then completes
+#[derive(Default)]
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// A private key of the KEM `Kem` whose Diffie-Hellman operation completes asynchronously
+pub trait AsyncDh<Kem: KemTrait> {
+    /// The future that [`dh`](Self::dh) returns
+    type DhFuture<'a>: Future<Output = Result<usize, HpkeError>> + 'a
+    where
+        Self: 'a;
+
+    /// Returns the public key of this private key
+    fn public_key(&self) -> &Kem::PublicKey;
+
+    /// Computes the Diffie-Hellman output of this private key and `pk`, as `Kem`'s key exchange
+    /// serializes it, i.e., the X25519 output, or the x-coordinate of the shared point for the
+    /// NIST curves, and writes it to the start of `out`
+    ///
+    /// Return Value
+    /// ============
+    /// Returns the length of the output on success. If the operation fails, implementations
+    /// should return `Err(HpkeError::DecapError)`.
+    fn dh<'a>(&'a mut self, pk: &'a Kem::PublicKey, out: &'a mut [u8]) -> Self::DhFuture<'a>;
+}
+
+/// A private key in memory, as an [`AsyncDh`]. Each DH yields once, then runs in software.
+pub struct SoftwareKey<Kem: DhKem> {
+    sk: Kem::PrivateKey,
+    pk: Kem::PublicKey,
+}
+
+impl<Kem: DhKem> SoftwareKey<Kem> {
+    /// Makes an `AsyncDh` out of `sk`
+    pub fn new(sk: Kem::PrivateKey) -> Self {
+        let pk = Kem::sk_to_pk(&sk);
+        SoftwareKey { sk, pk }
+    }
+}
+
+/// The future of [`SoftwareKey`]'s DH
+pub struct SoftwareDhFuture<'a, Kem: DhKem> {
+    sk: &'a Kem::PrivateKey,
+    pk: &'a Kem::PublicKey,
+    out: &'a mut [u8],
+    yielded: YieldNow,
+}
+
+impl<Kem: DhKem> Future for SoftwareDhFuture<'_, Kem> {
+    type Output = Result<usize, HpkeError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.yielded).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(()) => Poll::Ready(Kem::dh_into(this.sk, this.pk, this.out)),
+        }
+    }
+}
+
+impl<Kem: DhKem> AsyncDh<Kem> for SoftwareKey<Kem> {
+    type DhFuture<'a>
+        = SoftwareDhFuture<'a, Kem>
+    where
+        Self: 'a;
+
+    fn public_key(&self) -> &Kem::PublicKey {
+        &self.pk
+    }
+
+    fn dh<'a>(&'a mut self, pk: &'a Kem::PublicKey, out: &'a mut [u8]) -> Self::DhFuture<'a> {
+        SoftwareDhFuture {
+            sk: &self.sk,
+            pk,
+            out,
+            yielded: YieldNow::default(),
+        }
+    }
+}
+
+/// Does what [`setup_sender`](crate::setup_sender) does, yielding after generating the ephemeral
+/// key and after each DH operation
+///
+/// Return Value
+/// ============
+/// The errors are those of `setup_sender`.
+pub async fn setup_sender_nonblocking<A, Kdf, Kem, R>(
+    mode: &OpModeS<'_, Kem>,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    csprng: &mut R,
+) -> Result<(Kem::EncappedKey, AeadCtxS<A, Kdf, Kem>), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: DhKem,
+    R: CryptoRng + RngCore,
+{
+    let (sk_eph, pk_eph) = Kem::try_gen_keypair(csprng)?;
+    YieldNow::default().await;
+
+    // dh = DH(skE, pkR) [|| DH(skS, pkR)]
+    let mut dh = [0u8; 2 * MAX_DH_SIZE];
+    let mut dh_len = Kem::dh_into(&sk_eph, pk_recip, &mut dh).map_err(|_| HpkeError::EncapError)?;
+    drop(sk_eph);
+    YieldNow::default().await;
+    if let Some((sk_sender_id, _)) = mode.get_sender_id_keypair() {
+        dh_len += Kem::dh_into(sk_sender_id, pk_recip, &mut dh[dh_len..])
+            .map_err(|_| HpkeError::EncapError)?;
+        YieldNow::default().await;
+    }
+
+    // The encapped key is the ephemeral public key, and the shared secret is computed from the
+    // DH outputs exactly as the recipient computes it
+    let encapped_key = Kem::EncappedKey::from_bytes(&pk_eph.to_bytes())?;
+    let pk_sender_id = mode.get_sender_id_keypair().map(|(_, pk)| pk);
+    let shared_secret = Kem::decap_with_dh(&dh[..dh_len], pk_recip, pk_sender_id, &encapped_key)
+        .map_err(|_| HpkeError::EncapError);
+    dh.zeroize();
+
+    let ctx = setup_sender_from_shared_secret(mode, shared_secret?, info)?;
+    Ok((encapped_key, ctx))
+}
+
+/// Does what [`setup_receiver`](crate::setup_receiver) does, with the recipient's private key
+/// behind `sk_recip`, awaiting each DH operation, and yielding before the key schedule
+///
+/// Return Value
+/// ============
+/// On success, returns a decryption context. If `sk_recip.dh` fails, returns its error. If it
+/// returns the wrong number of bytes, returns `Err(HpkeError::IncorrectInputLength(expected,
+/// got))`, where `expected` is the most a DH output can be if `got` is more than that.
+/// Otherwise, the errors are those of `setup_receiver`.
+pub async fn setup_receiver_nonblocking<A, Kdf, Kem, K>(
+    mode: &OpModeR<'_, Kem>,
+    sk_recip: &mut K,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: DhKem,
+    K: AsyncDh<Kem> + ?Sized,
+{
+    // dh = DH(skR, pkE) [|| DH(skR, pkS)]
+    let mut dh = [0u8; 2 * MAX_DH_SIZE];
+    let pk_eph = Kem::encapped_key_to_pk(encapped_key);
+    // Each DH gets its own half of the buffer, and can't claim to have written more than that.
+    // decap_with_dh checks the total. This is awaited here, so that dh is zeroized even if a DH
+    // fails.
+    let dh_len = async {
+        let mut dh_len = check_dh_len(sk_recip.dh(&pk_eph, &mut dh[..MAX_DH_SIZE]).await?)?;
+        if let Some(pk_sender_id) = mode.get_pk_sender_id() {
+            let out = &mut dh[dh_len..dh_len + MAX_DH_SIZE];
+            dh_len += check_dh_len(sk_recip.dh(pk_sender_id, out).await?)?;
+        }
+        Ok(dh_len)
+    }
+    .await;
+
+    let shared_secret = dh_len.and_then(|dh_len| {
+        Kem::decap_with_dh(
+            &dh[..dh_len],
+            sk_recip.public_key(),
+            mode.get_pk_sender_id(),
+            encapped_key,
+        )
+    });
+    dh.zeroize();
+    YieldNow::default().await;
+
+    setup_receiver_from_shared_secret(mode, shared_secret?, info)
+}
+
+/// Returns `len`, the length an `AsyncDh` said it wrote, if it fits in the space it was given
+fn check_dh_len(len: usize) -> Result<usize, HpkeError> {
+    if len > MAX_DH_SIZE {
+        Err(HpkeError::IncorrectInputLength(MAX_DH_SIZE, len))
+    } else {
+        Ok(len)
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256, setup_receiver,
+        setup_sender,
+    };
+
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Polls `fut` to completion with a waker that does nothing, and returns its output and the
+    /// number of times it yielded
+    fn run<F: Future>(fut: F) -> (F::Output, usize) {
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = fut;
+        // The future is never moved again, since it's shadowed
+        let mut fut = unsafe { Pin::new_unchecked(&mut fut) };
+        let mut yields = 0;
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(out) => return (out, yields),
+                Poll::Pending => yields += 1,
+            }
+        }
+    }
+
+    /// Tests that nonblocking setup agrees with blocking setup in the Base and Auth modes, in
+    /// both directions, and that it yields between steps
+    #[test]
+    fn test_nonblocking_setup() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (sk_sender, pk_sender) = Kem::gen_keypair(&mut csprng);
+        let mut async_sk_recip = SoftwareKey::<Kem>::new(sk_recip.clone());
+
+        let modes = [
+            (OpModeS::Base, OpModeR::Base),
+            (
+                OpModeS::Auth((sk_sender, pk_sender.clone())),
+                OpModeR::Auth(pk_sender),
+            ),
+        ];
+        for (mode_s, mode_r) in modes.iter() {
+            // Nonblocking sender, blocking receiver
+            let (res, sender_yields) = run(setup_sender_nonblocking::<A, Kdf, Kem, _>(
+                mode_s,
+                &pk_recip,
+                b"info",
+                &mut csprng,
+            ));
+            let (enc, mut sender_ctx) = res.unwrap();
+            let mut receiver_ctx =
+                setup_receiver::<A, Kdf, Kem>(mode_r, &sk_recip, &enc, b"info").unwrap();
+            let mut msg = *b"to a blocking receiver";
+            let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+            receiver_ctx
+                .open_in_place_detached(&mut msg, b"", &tag)
+                .unwrap();
+            assert_eq!(&msg, b"to a blocking receiver");
+
+            // Blocking sender, nonblocking receiver
+            let (enc, mut sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(mode_s, &pk_recip, b"info", &mut csprng).unwrap();
+            let (res, receiver_yields) = run(setup_receiver_nonblocking::<A, Kdf, Kem, _>(
+                mode_r,
+                &mut async_sk_recip,
+                &enc,
+                b"info",
+            ));
+            let mut receiver_ctx = res.unwrap();
+            let mut msg = *b"from a blocking sender";
+            let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+            receiver_ctx
+                .open_in_place_detached(&mut msg, b"", &tag)
+                .unwrap();
+            assert_eq!(&msg, b"from a blocking sender");
+
+            // There's a yield per DH, plus one before the key schedule. The sender also yields
+            // after generating its ephemeral key.
+            let num_dh = if mode_r.get_pk_sender_id().is_some() {
+                2
+            } else {
+                1
+            };
+            assert_eq!(sender_yields, num_dh + 1);
+            assert_eq!(receiver_yields, num_dh + 1);
+        }
+    }
+
+    /// Tests that a DH that claims to have written more than it was given room for is refused
+    #[test]
+    fn test_overlong_dh() {
+        struct Overclaiming(<Kem as KemTrait>::PublicKey);
+
+        impl AsyncDh<Kem> for Overclaiming {
+            type DhFuture<'a> = core::future::Ready<Result<usize, HpkeError>>;
+
+            fn public_key(&self) -> &<Kem as KemTrait>::PublicKey {
+                &self.0
+            }
+
+            fn dh<'a>(
+                &'a mut self,
+                _: &'a <Kem as KemTrait>::PublicKey,
+                out: &'a mut [u8],
+            ) -> Self::DhFuture<'a> {
+                core::future::ready(Ok(out.len() + 1))
+            }
+        }
+
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (enc, _) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"info", &mut csprng)
+                .unwrap();
+        let (res, _) = run(setup_receiver_nonblocking::<A, Kdf, Kem, _>(
+            &OpModeR::Base,
+            &mut Overclaiming(pk_recip),
+            &enc,
+            b"info",
+        ));
+        assert_eq!(
+            res.err(),
+            Some(HpkeError::IncorrectInputLength(
+                MAX_DH_SIZE,
+                MAX_DH_SIZE + 1
+            ))
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! Offloading AES-GCM and SHA-256 to crypto peripherals, e.g., the AES and HASH blocks of an
+//! STM32 or the crypto accelerators of an nRF or ESP32, with software fallback.
+//!
+//! The key schedule, nonce handling, and API stay this crate's. Only the bulk work is handed off:
+//!
+//! * [`AesGcmAccelerator`] does AES-GCM for [`OffloadedAesGcm128`] and [`OffloadedAesGcm256`],
+//!   which are drop-in replacements for `AesGcm128` and `AesGcm256` with the same algorithm IDs.
+//!   Every context method, single-shot function, and setup function works with them unchanged.
+//! * [`Sha256Accelerator`] does the SHA-256 compression function for [`OffloadedHkdfSha256`], a
+//!   drop-in replacement for `HkdfSha256`. This speeds up setup and export, and everything else
+//!   that runs HKDF.
+//! * [`AsyncAesGcmAccelerator`] is for peripherals that are driven by DMA and an interrupt, and is
+//!   used through `AeadCtxS::seal_in_place_detached_offloaded` and
+//!   `AeadCtxR::open_in_place_detached_offloaded`, which await the peripheral instead of blocking.
+//!
+//! The sync traits have no receiver, since the AEAD and hash types are made from just a key. A
+//! peripheral is a singleton on a microcontroller anyway, so an implementation reaches it through
+//! a `static`, e.g., in a critical section. Any accelerator method can return [`Unsupported`],
+//! e.g., if the peripheral is busy or doesn't do 256-bit keys, and the operation is then done in
+//! software. An accelerator must return `Unsupported` before it touches its buffers.
+
+use crate::{aead::Aead, kdf::Kdf as KdfTrait};
+
+use core::{future::Future, marker::PhantomData};
+
+use aead::{
+    consts::{U0, U12, U16, U32, U64},
+    AeadCore as BaseAeadCore, AeadInPlace as BaseAeadInPlace, KeyInit as BaseKeyInit,
+    KeySizeUser as BaseKeySizeUser,
+};
+use digest::{
+    block_buffer::Eager,
+    core_api::{
+        Block, BlockSizeUser, Buffer, BufferKindUser, CoreWrapper, FixedOutputCore, OutputSizeUser,
+        UpdateCore,
+    },
+    HashMarker, Output, Reset,
+};
+use generic_array::GenericArray;
+use zeroize::Zeroize;
+
+/// Returned by an accelerator that can't do an operation, so that it's done in software instead
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Unsupported;
+
+/// A peripheral that does AES-GCM with 96-bit nonces and 128-bit tags, for [`OffloadedAesGcm128`]
+/// and [`OffloadedAesGcm256`]
+pub trait AesGcmAccelerator {
+    /// Encrypts `buf` in place under `key`, which is 16 or 32 bytes, and returns the tag
+    fn encrypt_in_place_detached(
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<[u8; 16], Unsupported>;
+
+    /// Decrypts `buf` in place under `key`, which is 16 or 32 bytes
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(true)` if `tag` is valid, in which case `buf` must hold the plaintext. Returns
+    /// `Ok(false)` otherwise, in which case `buf` may hold anything.
+    fn decrypt_in_place_detached(
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<bool, Unsupported>;
+}
+
+/// A peripheral that does AES-GCM like an [`AesGcmAccelerator`], asynchronously
+pub trait AsyncAesGcmAccelerator {
+    /// The future that `encrypt_in_place_detached` returns
+    type EncryptFuture<'a>: Future<Output = Result<[u8; 16], Unsupported>> + 'a
+    where
+        Self: 'a;
+    /// The future that `decrypt_in_place_detached` returns
+    type DecryptFuture<'a>: Future<Output = Result<bool, Unsupported>> + 'a
+    where
+        Self: 'a;
+
+    /// Does what [`AesGcmAccelerator::encrypt_in_place_detached`] does
+    fn encrypt_in_place_detached<'a>(
+        &'a mut self,
+        key: &'a [u8],
+        nonce: &'a [u8; 12],
+        aad: &'a [u8],
+        buf: &'a mut [u8],
+    ) -> Self::EncryptFuture<'a>;
+
+    /// Does what [`AesGcmAccelerator::decrypt_in_place_detached`] does
+    fn decrypt_in_place_detached<'a>(
+        &'a mut self,
+        key: &'a [u8],
+        nonce: &'a [u8; 12],
+        aad: &'a [u8],
+        buf: &'a mut [u8],
+        tag: &'a [u8; 16],
+    ) -> Self::DecryptFuture<'a>;
+}
+
+/// A peripheral that does the SHA-256 compression function, for [`OffloadedHkdfSha256`]
+pub trait Sha256Accelerator {
+    /// Updates the hash state `state` with one 64-byte block, as in FIPS 180-4 §6.2.2
+    fn compress(state: &mut [u32; 8], block: &[u8; 64]) -> Result<(), Unsupported>;
+}
+
+/// An accelerator that can't do anything, so that everything is done in software
+pub struct NoAccelerator;
+
+impl AesGcmAccelerator for NoAccelerator {
+    fn encrypt_in_place_detached(
+        _: &[u8],
+        _: &[u8; 12],
+        _: &[u8],
+        _: &mut [u8],
+    ) -> Result<[u8; 16], Unsupported> {
+        Err(Unsupported)
+    }
+
+    fn decrypt_in_place_detached(
+        _: &[u8],
+        _: &[u8; 12],
+        _: &[u8],
+        _: &mut [u8],
+        _: &[u8; 16],
+    ) -> Result<bool, Unsupported> {
+        Err(Unsupported)
+    }
+}
+
+impl AsyncAesGcmAccelerator for NoAccelerator {
+    type EncryptFuture<'a> = core::future::Ready<Result<[u8; 16], Unsupported>>;
+    type DecryptFuture<'a> = core::future::Ready<Result<bool, Unsupported>>;
+
+    fn encrypt_in_place_detached<'a>(
+        &'a mut self,
+        _: &'a [u8],
+        _: &'a [u8; 12],
+        _: &'a [u8],
+        _: &'a mut [u8],
+    ) -> Self::EncryptFuture<'a> {
+        core::future::ready(Err(Unsupported))
+    }
+
+    fn decrypt_in_place_detached<'a>(
+        &'a mut self,
+        _: &'a [u8],
+        _: &'a [u8; 12],
+        _: &'a [u8],
+        _: &'a mut [u8],
+        _: &'a [u8; 16],
+    ) -> Self::DecryptFuture<'a> {
+        core::future::ready(Err(Unsupported))
+    }
+}
+
+impl Sha256Accelerator for NoAccelerator {
+    fn compress(_: &mut [u32; 8], _: &[u8; 64]) -> Result<(), Unsupported> {
+        Err(Unsupported)
+    }
+}
+
+// Nobody outside this crate can mark an AEAD as AES-GCM
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An AES-GCM AEAD, i.e., one that an [`AsyncAesGcmAccelerator`] can do. This is implemented for
+/// `AesGcm128`, `AesGcm256`, and their offloaded versions.
+pub trait AesGcm: Aead + sealed::Sealed {}
+
+impl sealed::Sealed for crate::aead::AesGcm128 {}
+impl AesGcm for crate::aead::AesGcm128 {}
+impl sealed::Sealed for crate::aead::AesGcm256 {}
+impl AesGcm for crate::aead::AesGcm256 {}
+impl<H: AesGcmAccelerator> sealed::Sealed for OffloadedAesGcm128<H> {}
+impl<H: AesGcmAccelerator> AesGcm for OffloadedAesGcm128<H> {}
+impl<H: AesGcmAccelerator> sealed::Sealed for OffloadedAesGcm256<H> {}
+impl<H: AesGcmAccelerator> AesGcm for OffloadedAesGcm256<H> {}
+
+/// AES-128-GCM, done by the accelerator `H` when it can
+pub struct OffloadedAesGcm128<H: AesGcmAccelerator>(PhantomData<fn() -> H>);
+
+impl<H: AesGcmAccelerator> Aead for OffloadedAesGcm128<H> {
+    #[doc(hidden)]
+    type AeadImpl = OffloadedAesGcmImpl<H, aes_gcm::Aes128Gcm>;
+
+    // RFC 9180 §7.3: AES-128-GCM
+    const AEAD_ID: u16 = 0x0001;
+
+    // Same as AesGcm128, which this falls back to
+    const MAX_PLAINTEXT_LEN: u64 = aes_gcm::P_MAX;
+    const MAX_AAD_LEN: u64 = aes_gcm::A_MAX;
+}
+
+/// AES-256-GCM, done by the accelerator `H` when it can
+pub struct OffloadedAesGcm256<H: AesGcmAccelerator>(PhantomData<fn() -> H>);
+
+impl<H: AesGcmAccelerator> Aead for OffloadedAesGcm256<H> {
+    #[doc(hidden)]
+    type AeadImpl = OffloadedAesGcmImpl<H, aes_gcm::Aes256Gcm>;
+
+    // RFC 9180 §7.3: AES-256-GCM
+    const AEAD_ID: u16 = 0x0002;
+
+    // Same as AesGcm256, which this falls back to
+    const MAX_PLAINTEXT_LEN: u64 = aes_gcm::P_MAX;
+    const MAX_AAD_LEN: u64 = aes_gcm::A_MAX;
+}
+
+/// The underlying AEAD of the offloaded AES-GCMs. This keeps the raw key for the accelerator, and
+/// the software implementation `S` for fallback.
+#[doc(hidden)]
+pub struct OffloadedAesGcmImpl<H, S: BaseKeySizeUser> {
+    key: GenericArray<u8, S::KeySize>,
+    software: S,
+    accel: PhantomData<fn() -> H>,
+}
+
+impl<H, S: BaseKeySizeUser + Clone> Clone for OffloadedAesGcmImpl<H, S> {
+    fn clone(&self) -> Self {
+        OffloadedAesGcmImpl {
+            key: self.key.clone(),
+            software: self.software.clone(),
+            accel: PhantomData,
+        }
+    }
+}
+
+// Zero out the raw key on drop. Like the other AEADs, the software key schedule is not zeroized.
+impl<H, S: BaseKeySizeUser> Drop for OffloadedAesGcmImpl<H, S> {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl<H, S: BaseKeySizeUser> BaseKeySizeUser for OffloadedAesGcmImpl<H, S> {
+    type KeySize = S::KeySize;
+}
+
+impl<H, S: BaseKeyInit> BaseKeyInit for OffloadedAesGcmImpl<H, S> {
+    fn new(key: &aead::Key<Self>) -> Self {
+        OffloadedAesGcmImpl {
+            key: key.clone(),
+            software: S::new(key),
+            accel: PhantomData,
+        }
+    }
+}
+
+impl<H, S: BaseKeySizeUser> BaseAeadCore for OffloadedAesGcmImpl<H, S> {
+    type NonceSize = U12;
+    type TagSize = U16;
+    type CiphertextOverhead = U0;
+}
+
+impl<H, S> BaseAeadInPlace for OffloadedAesGcmImpl<H, S>
+where
+    H: AesGcmAccelerator,
+    S: BaseKeySizeUser + BaseAeadInPlace + BaseAeadCore<NonceSize = U12, TagSize = U16>,
+{
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<aead::Tag<Self>, aead::Error> {
+        match H::encrypt_in_place_detached(&self.key, &(*nonce).into(), aad, buf) {
+            Ok(tag) => Ok(tag.into()),
+            Err(Unsupported) => self.software.encrypt_in_place_detached(nonce, aad, buf),
+        }
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &aead::Tag<Self>,
+    ) -> Result<(), aead::Error> {
+        match H::decrypt_in_place_detached(&self.key, &(*nonce).into(), aad, buf, &(*tag).into()) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(aead::Error),
+            Err(Unsupported) => self
+                .software
+                .decrypt_in_place_detached(nonce, aad, buf, tag),
+        }
+    }
+}
+
+/// HKDF-SHA256, with the SHA-256 compression function done by the accelerator `H` when it can
+pub struct OffloadedHkdfSha256<H: Sha256Accelerator>(PhantomData<fn() -> H>);
+
+impl<H: Sha256Accelerator> KdfTrait for OffloadedHkdfSha256<H> {
+    #[doc(hidden)]
+    type HashImpl = OffloadedSha256<H>;
+
+    // RFC 9180 §7.2: HKDF-SHA256
+    const KDF_ID: u16 = 0x0001;
+
+    // Same as HkdfSha256
+    const MAX_PSK_LEN: u64 = crate::kdf::HkdfSha256::MAX_PSK_LEN;
+    const MAX_PSK_ID_LEN: u64 = crate::kdf::HkdfSha256::MAX_PSK_ID_LEN;
+    const MAX_INFO_LEN: u64 = crate::kdf::HkdfSha256::MAX_INFO_LEN;
+    const MAX_EXPORTER_CTX_LEN: u64 = crate::kdf::HkdfSha256::MAX_EXPORTER_CTX_LEN;
+}
+
+/// SHA-256, with the compression function done by the accelerator `H` when it can
+#[doc(hidden)]
+pub type OffloadedSha256<H> = CoreWrapper<OffloadedSha256Core<H>>;
+
+// FIPS 180-4 §5.3.3
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// The core of [`OffloadedSha256`], i.e., everything but the block buffer
+#[doc(hidden)]
+pub struct OffloadedSha256Core<H> {
+    state: [u32; 8],
+    block_count: u64,
+    accel: PhantomData<fn() -> H>,
+}
+
+impl<H: Sha256Accelerator> OffloadedSha256Core<H> {
+    fn compress(state: &mut [u32; 8], block: &Block<Self>) {
+        let block_arr: &[u8; 64] = block
+            .as_slice()
+            .try_into()
+            .expect("SHA-256 blocks are 64 bytes");
+        if H::compress(state, block_arr).is_err() {
+            sha2::compress256(state, core::slice::from_ref(block));
+        }
+    }
+}
+
+impl<H> Clone for OffloadedSha256Core<H> {
+    fn clone(&self) -> Self {
+        OffloadedSha256Core {
+            state: self.state,
+            block_count: self.block_count,
+            accel: PhantomData,
+        }
+    }
+}
+
+impl<H> Default for OffloadedSha256Core<H> {
+    fn default() -> Self {
+        OffloadedSha256Core {
+            state: SHA256_IV,
+            block_count: 0,
+            accel: PhantomData,
+        }
+    }
+}
+
+impl<H> Reset for OffloadedSha256Core<H> {
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl<H> HashMarker for OffloadedSha256Core<H> {}
+
+impl<H> BlockSizeUser for OffloadedSha256Core<H> {
+    type BlockSize = U64;
+}
+
+impl<H> BufferKindUser for OffloadedSha256Core<H> {
+    type BufferKind = Eager;
+}
+
+impl<H> OutputSizeUser for OffloadedSha256Core<H> {
+    type OutputSize = U32;
+}
+
+impl<H: Sha256Accelerator> UpdateCore for OffloadedSha256Core<H> {
+    fn update_blocks(&mut self, blocks: &[Block<Self>]) {
+        self.block_count += blocks.len() as u64;
+        for block in blocks {
+            Self::compress(&mut self.state, block);
+        }
+    }
+}
+
+impl<H: Sha256Accelerator> FixedOutputCore for OffloadedSha256Core<H> {
+    fn finalize_fixed_core(&mut self, buffer: &mut Buffer<Self>, out: &mut Output<Self>) {
+        let bit_len = 8 * (self.block_count * 64 + buffer.get_pos() as u64);
+        let state = &mut self.state;
+        buffer.len64_padding_be(bit_len, |block| Self::compress(state, block));
+        for (chunk, word) in out.chunks_exact_mut(4).zip(state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::{AeadCtxR, AeadCtxS, AeadTag, AesGcm128},
+        kdf::HkdfSha256,
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+        setup_receiver, setup_sender, Deserializable, OpModeR, OpModeS, Serializable,
+    };
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use ::aes_gcm::Aes128Gcm;
+    use digest::Digest;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Kem = X25519HkdfSha256;
+
+    static AES_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static SHA_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Stands in for a peripheral that only does AES-128, by doing it in software and counting
+    struct FakePeripheral;
+
+    impl AesGcmAccelerator for FakePeripheral {
+        fn encrypt_in_place_detached(
+            key: &[u8],
+            nonce: &[u8; 12],
+            aad: &[u8],
+            buf: &mut [u8],
+        ) -> Result<[u8; 16], Unsupported> {
+            let cipher = Aes128Gcm::new_from_slice(key).map_err(|_| Unsupported)?;
+            AES_CALLS.fetch_add(1, Ordering::SeqCst);
+            let tag = cipher
+                .encrypt_in_place_detached(nonce.into(), aad, buf)
+                .unwrap();
+            Ok(tag.into())
+        }
+
+        fn decrypt_in_place_detached(
+            key: &[u8],
+            nonce: &[u8; 12],
+            aad: &[u8],
+            buf: &mut [u8],
+            tag: &[u8; 16],
+        ) -> Result<bool, Unsupported> {
+            let cipher = Aes128Gcm::new_from_slice(key).map_err(|_| Unsupported)?;
+            AES_CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(cipher
+                .decrypt_in_place_detached(nonce.into(), aad, buf, tag.into())
+                .is_ok())
+        }
+    }
+
+    impl Sha256Accelerator for FakePeripheral {
+        fn compress(state: &mut [u32; 8], block: &[u8; 64]) -> Result<(), Unsupported> {
+            SHA_CALLS.fetch_add(1, Ordering::SeqCst);
+            sha2::compress256(state, &[(*block).into()]);
+            Ok(())
+        }
+    }
+
+    impl AsyncAesGcmAccelerator for FakePeripheral {
+        type EncryptFuture<'a> = core::future::Ready<Result<[u8; 16], Unsupported>>;
+        type DecryptFuture<'a> = core::future::Ready<Result<bool, Unsupported>>;
+
+        fn encrypt_in_place_detached<'a>(
+            &'a mut self,
+            key: &'a [u8],
+            nonce: &'a [u8; 12],
+            aad: &'a [u8],
+            buf: &'a mut [u8],
+        ) -> Self::EncryptFuture<'a> {
+            core::future::ready(<Self as AesGcmAccelerator>::encrypt_in_place_detached(
+                key, nonce, aad, buf,
+            ))
+        }
+
+        fn decrypt_in_place_detached<'a>(
+            &'a mut self,
+            key: &'a [u8],
+            nonce: &'a [u8; 12],
+            aad: &'a [u8],
+            buf: &'a mut [u8],
+            tag: &'a [u8; 16],
+        ) -> Self::DecryptFuture<'a> {
+            core::future::ready(<Self as AesGcmAccelerator>::decrypt_in_place_detached(
+                key, nonce, aad, buf, tag,
+            ))
+        }
+    }
+
+    /// Converts a tag to the same tag of another AEAD with the same tag size
+    fn convert_tag<A1: Aead, A2: Aead>(tag: &AeadTag<A1>) -> AeadTag<A2> {
+        AeadTag::from_bytes(&tag.to_bytes()).unwrap()
+    }
+
+    /// Tests that offloaded suites interoperate with the software ones, that the accelerator is
+    /// used, and that anything it doesn't do falls back to software
+    #[test]
+    fn test_offload() {
+        type OffloadedA = OffloadedAesGcm128<FakePeripheral>;
+        type OffloadedKdf = OffloadedHkdfSha256<FakePeripheral>;
+
+        // The hash agrees with SHA-256 around block boundaries
+        let data = [0xa5u8; 200];
+        for len in 0..data.len() {
+            assert_eq!(
+                OffloadedSha256::<FakePeripheral>::digest(&data[..len]),
+                sha2::Sha256::digest(&data[..len])
+            );
+        }
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+
+        let sha_calls = SHA_CALLS.load(Ordering::SeqCst);
+        let (enc, mut sender_ctx) = setup_sender::<OffloadedA, OffloadedKdf, Kem, _>(
+            &OpModeS::Base,
+            &pk_recip,
+            b"info",
+            &mut csprng,
+        )
+        .unwrap();
+        assert!(SHA_CALLS.load(Ordering::SeqCst) > sha_calls);
+        let mut receiver_ctx =
+            setup_receiver::<AesGcm128, HkdfSha256, Kem>(&OpModeR::Base, &sk_recip, &enc, b"info")
+                .unwrap();
+
+        let aes_calls = AES_CALLS.load(Ordering::SeqCst);
+        let mut msg = *b"offloaded";
+        let tag = sender_ctx.seal_in_place_detached(&mut msg, b"aad").unwrap();
+        assert_eq!(AES_CALLS.load(Ordering::SeqCst), aes_calls + 1);
+        let tag = convert_tag(&tag);
+        receiver_ctx
+            .open_in_place_detached(&mut msg, b"aad", &tag)
+            .unwrap();
+        assert_eq!(&msg, b"offloaded");
+
+        let mut exported = [[0u8; 32]; 2];
+        sender_ctx.export(b"ctx", &mut exported[0]).unwrap();
+        receiver_ctx.export(b"ctx", &mut exported[1]).unwrap();
+        assert_eq!(exported[0], exported[1]);
+
+        // The fake peripheral refuses 256-bit keys, and NoAccelerator refuses everything
+        let aes_calls = AES_CALLS.load(Ordering::SeqCst);
+        let (enc, mut sender_ctx) = setup_sender::<
+            OffloadedAesGcm256<FakePeripheral>,
+            HkdfSha256,
+            Kem,
+            _,
+        >(&OpModeS::Base, &pk_recip, b"info", &mut csprng)
+        .unwrap();
+        let mut receiver_ctx = setup_receiver::<
+            OffloadedAesGcm256<NoAccelerator>,
+            OffloadedHkdfSha256<NoAccelerator>,
+            Kem,
+        >(&OpModeR::Base, &sk_recip, &enc, b"info")
+        .unwrap();
+        let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+        receiver_ctx
+            .open_in_place_detached(&mut msg, b"", &convert_tag(&tag))
+            .unwrap();
+        assert_eq!(&msg, b"offloaded");
+        assert_eq!(AES_CALLS.load(Ordering::SeqCst), aes_calls);
+    }
+
+    /// Tests that the async methods agree with the sync ones, with and without an accelerator
+    #[test]
+    fn test_offload_async() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (enc, mut sender_ctx): (_, AeadCtxS<AesGcm128, HkdfSha256, Kem>) =
+            setup_sender(&OpModeS::Base, &pk_recip, b"info", &mut csprng).unwrap();
+        let mut receiver_ctx: AeadCtxR<AesGcm128, HkdfSha256, Kem> =
+            setup_receiver(&OpModeR::Base, &sk_recip, &enc, b"info").unwrap();
+
+        let mut msg = *b"over dma";
+        let tag = pollster::block_on(sender_ctx.seal_in_place_detached_offloaded(
+            &mut FakePeripheral,
+            &mut msg,
+            b"aad",
+        ))
+        .unwrap();
+        pollster::block_on(receiver_ctx.open_in_place_detached_offloaded(
+            &mut NoAccelerator,
+            &mut msg,
+            b"aad",
+            &tag,
+        ))
+        .unwrap();
+        assert_eq!(&msg, b"over dma");
+
+        // Both contexts moved on to the next sequence number
+        let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+        assert_eq!(
+            pollster::block_on(receiver_ctx.open_in_place_detached_offloaded(
+                &mut FakePeripheral,
+                &mut msg,
+                b"wrong aad",
+                &tag,
+            )),
+            Err(crate::HpkeError::OpenError)
+        );
+    }
+}
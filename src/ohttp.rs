@@ -0,0 +1,452 @@
+//! Encapsulation of Oblivious HTTP requests and responses (RFC 9458 §4).
+//!
+//! A client encapsulates a request to one of the gateway's keys with [`encapsulate_request`], and
+//! keeps the [`ClientResponseCtx`] it gets back to open the response. The gateway picks the private
+//! key by the key ID in the request header, decapsulates the request with
+//! [`decapsulate_request`], and answers with the [`GatewayResponseCtx`] it gets back. The response
+//! is bound to the request, so it can only be opened by the client that sent it.
+//!
+//! For bodies that are too large to hold in memory, see [`chunked`].
+//!
+//! Only the message framing is done here. Binary HTTP, the key configuration format, and the
+//! transport are up to the application.
+
+use crate::{
+    aead::{Aead, AeadCtx, AeadCtxR, AeadCtxS, AeadKey, AeadNonce},
+    kdf::{Kdf as KdfTrait, SimpleHkdf},
+    kem::Kem as KemTrait,
+    keyring::RecipientKeyRing,
+    setup::{setup_sender, ExporterSecret},
+    Deserializable, HpkeError, OpModeR, OpModeS, Serializable,
+};
+
+use core::marker::PhantomData;
+
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+pub mod chunked;
+
+/// The media type of encapsulated requests, which starts their `info`
+pub const REQUEST_LABEL: &[u8] = b"message/bhttp request";
+
+/// The media type of encapsulated responses, which is the exporter context of the response secret
+pub const RESPONSE_LABEL: &[u8] = b"message/bhttp response";
+
+/// The size of the header that starts every encapsulated request
+pub const REQUEST_HEADER_SIZE: usize = 7;
+
+/// The header of an encapsulated request: the ID of the gateway key it's encapsulated to, and
+/// the algorithm identifiers of the ciphersuite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestHeader {
+    /// The ID of the key in the gateway's key configuration
+    pub key_id: u8,
+    /// The KEM's algorithm identifier
+    pub kem_id: u16,
+    /// The KDF's algorithm identifier
+    pub kdf_id: u16,
+    /// The AEAD's algorithm identifier
+    pub aead_id: u16,
+}
+
+impl RequestHeader {
+    /// Returns the header of a request to the key with the given ID, in the given ciphersuite
+    pub fn new<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(key_id: u8) -> RequestHeader {
+        RequestHeader {
+            key_id,
+            kem_id: Kem::KEM_ID,
+            kdf_id: Kdf::KDF_ID,
+            aead_id: A::AEAD_ID,
+        }
+    }
+
+    /// Parses the header at the start of an encapsulated request. This lets a gateway that serves
+    /// several ciphersuites pick one before decapsulating.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(header)` on success. If `enc_request` is shorter than a header, returns
+    /// `Err(HpkeError::IncorrectInputLength(REQUEST_HEADER_SIZE, enc_request.len()))`.
+    pub fn parse(enc_request: &[u8]) -> Result<RequestHeader, HpkeError> {
+        let hdr: &[u8; REQUEST_HEADER_SIZE] = enc_request
+            .get(..REQUEST_HEADER_SIZE)
+            .and_then(|h| h.try_into().ok())
+            .ok_or(HpkeError::IncorrectInputLength(
+                REQUEST_HEADER_SIZE,
+                enc_request.len(),
+            ))?;
+        Ok(RequestHeader {
+            key_id: hdr[0],
+            kem_id: u16::from_be_bytes([hdr[1], hdr[2]]),
+            kdf_id: u16::from_be_bytes([hdr[3], hdr[4]]),
+            aead_id: u16::from_be_bytes([hdr[5], hdr[6]]),
+        })
+    }
+
+    /// Serializes the header
+    pub fn to_bytes(&self) -> [u8; REQUEST_HEADER_SIZE] {
+        let mut hdr = [0u8; REQUEST_HEADER_SIZE];
+        hdr[0] = self.key_id;
+        hdr[1..3].copy_from_slice(&self.kem_id.to_be_bytes());
+        hdr[3..5].copy_from_slice(&self.kdf_id.to_be_bytes());
+        hdr[5..7].copy_from_slice(&self.aead_id.to_be_bytes());
+        hdr
+    }
+
+    /// Returns the `info` that a request with this header is encapsulated under, i.e.,
+    /// `label || 0x00 || hdr`, where `label` is the request's media type
+    fn info(&self, label: &[u8]) -> crate::Vec<u8> {
+        let mut info = label.to_vec();
+        info.push(0);
+        info.extend_from_slice(&self.to_bytes());
+        info
+    }
+}
+
+/// What a client keeps after encapsulating a request, to open the response
+pub struct ClientResponseCtx<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    secret: ResponseSecret,
+    encapped_key: Kem::EncappedKey,
+    marker: PhantomData<(A, Kdf)>,
+}
+
+/// What a gateway keeps after decapsulating a request, to encapsulate the response
+pub struct GatewayResponseCtx<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    secret: ResponseSecret,
+    encapped_key: Kem::EncappedKey,
+    marker: PhantomData<(A, Kdf)>,
+}
+
+/// The secret exported from the request context. It is `max(Nn, Nk)` bytes long.
+struct ResponseSecret(crate::Vec<u8>);
+
+impl Drop for ResponseSecret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Encapsulates `request` to the gateway's public key `pk_gateway`, which has ID `key_id` in the
+/// gateway's key configuration
+///
+/// Return Value
+/// ============
+/// Returns `Ok((enc_request, response_ctx))` on success. Send `enc_request` to the gateway, and
+/// open its response with `response_ctx`. If `A` is the export-only AEAD, returns
+/// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of `setup_sender` and
+/// `AeadCtxS::seal`.
+pub fn encapsulate_request<A, Kdf, Kem, R>(
+    key_id: u8,
+    pk_gateway: &Kem::PublicKey,
+    request: &[u8],
+    csprng: &mut R,
+) -> Result<(crate::Vec<u8>, ClientResponseCtx<A, Kdf, Kem>), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    if A::MAX_PLAINTEXT_LEN == 0 {
+        return Err(HpkeError::ValidationError);
+    }
+    let hdr = RequestHeader::new::<A, Kdf, Kem>(key_id);
+
+    let (encapped_key, mut ctx) = setup_sender::<A, Kdf, Kem, R>(
+        &OpModeS::Base,
+        pk_gateway,
+        &hdr.info(REQUEST_LABEL),
+        csprng,
+    )?;
+    let ciphertext = ctx.seal(request, &[])?;
+    let secret = export_response_secret::<A>(|out| ctx.export(RESPONSE_LABEL, out))?;
+
+    let mut enc_request = hdr.to_bytes().to_vec();
+    enc_request.extend_from_slice(&encapped_key.to_bytes());
+    enc_request.extend_from_slice(&ciphertext);
+
+    let response_ctx = ClientResponseCtx {
+        secret,
+        encapped_key,
+        marker: PhantomData,
+    };
+    Ok((enc_request, response_ctx))
+}
+
+/// Decapsulates a request with the key in `keys` that its header names
+///
+/// Return Value
+/// ============
+/// Returns `Ok((request, response_ctx))` on success. Encapsulate the response with
+/// `response_ctx`. If the request is too short to hold a header and an encapsulated key, returns
+/// `Err(HpkeError::IncorrectInputLength(REQUEST_HEADER_SIZE + Nenc, enc_request.len()))`. If the
+/// header names a different ciphersuite, or `A` is the export-only AEAD, returns
+/// `Err(HpkeError::ValidationError)`. If `keys` has no key with the header's key ID, returns
+/// `Err(HpkeError::UnknownKeyId)`. Otherwise, the errors are those of `setup_receiver` and
+/// `AeadCtxR::open`.
+pub fn decapsulate_request<A, Kdf, Kem>(
+    keys: &RecipientKeyRing<Kem>,
+    enc_request: &[u8],
+) -> Result<(crate::Vec<u8>, GatewayResponseCtx<A, Kdf, Kem>), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let prefix_len = REQUEST_HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE;
+    if enc_request.len() < prefix_len {
+        return Err(HpkeError::IncorrectInputLength(
+            prefix_len,
+            enc_request.len(),
+        ));
+    }
+    let (prefix, ciphertext) = enc_request.split_at(prefix_len);
+    let (mut ctx, encapped_key) = setup_gateway::<A, Kdf, Kem>(keys, prefix, REQUEST_LABEL)?;
+    let request = ctx.open(ciphertext, &[])?;
+    let secret = export_response_secret::<A>(|out| ctx.export(RESPONSE_LABEL, out))?;
+
+    let response_ctx = GatewayResponseCtx {
+        secret,
+        encapped_key,
+        marker: PhantomData,
+    };
+    Ok((request, response_ctx))
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> GatewayResponseCtx<A, Kdf, Kem> {
+    /// Encapsulates the response to the request this context came from. A request gets one
+    /// response, so this consumes the context.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(enc_response)` on success. If `csprng` failed, returns
+    /// `Err(HpkeError::RngError)`. Otherwise, the errors are those of `AeadCtxS::seal`.
+    pub fn encapsulate_response<R: CryptoRng + RngCore>(
+        self,
+        response: &[u8],
+        csprng: &mut R,
+    ) -> Result<crate::Vec<u8>, HpkeError> {
+        let mut enc_response = vec![0u8; self.secret.0.len()];
+        csprng
+            .try_fill_bytes(&mut enc_response)
+            .map_err(|_| HpkeError::RngError)?;
+
+        let ctx = response_ctx::<A, Kdf, Kem>(&self.secret, &self.encapped_key, &enc_response)?;
+        let ciphertext = AeadCtxS::from(ctx).seal(response, &[])?;
+        enc_response.extend_from_slice(&ciphertext);
+        Ok(enc_response)
+    }
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> ClientResponseCtx<A, Kdf, Kem> {
+    /// Opens the gateway's response to the request this context came from
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(response)` on success. If `enc_response` is too short or fails to open, returns
+    /// `Err(HpkeError::OpenError)`.
+    pub fn decapsulate_response(self, enc_response: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        if enc_response.len() < self.secret.0.len() {
+            return Err(HpkeError::OpenError);
+        }
+        let (response_nonce, ciphertext) = enc_response.split_at(self.secret.0.len());
+
+        let ctx = response_ctx::<A, Kdf, Kem>(&self.secret, &self.encapped_key, response_nonce)?;
+        AeadCtxR::from(ctx).open(ciphertext, &[])
+    }
+}
+
+/// Parses the header and encapsulated key that start a request, and sets up the receiver context
+/// with the key the header names. `prefix` is exactly `REQUEST_HEADER_SIZE + Nenc` bytes long.
+fn setup_gateway<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    keys: &RecipientKeyRing<Kem>,
+    prefix: &[u8],
+    label: &[u8],
+) -> Result<(AeadCtxR<A, Kdf, Kem>, Kem::EncappedKey), HpkeError> {
+    let hdr = RequestHeader::parse(prefix)?;
+    if A::MAX_PLAINTEXT_LEN == 0 || hdr != RequestHeader::new::<A, Kdf, Kem>(hdr.key_id) {
+        return Err(HpkeError::ValidationError);
+    }
+    let encapped_key = Kem::EncappedKey::from_bytes(&prefix[REQUEST_HEADER_SIZE..])?;
+    let ctx =
+        keys.setup_receiver::<A, Kdf>(hdr.key_id, &OpModeR::Base, &encapped_key, &hdr.info(label))?;
+    Ok((ctx, encapped_key))
+}
+
+/// Exports the `max(Nn, Nk)`-byte response secret with the given exporter
+fn export_response_secret<A: Aead>(
+    export: impl FnOnce(&mut [u8]) -> Result<(), HpkeError>,
+) -> Result<ResponseSecret, HpkeError> {
+    let len = core::cmp::max(
+        AeadKey::<A>::default().0.len(),
+        AeadNonce::<A>::default().0.len(),
+    );
+    let mut secret = ResponseSecret(vec![0u8; len]);
+    export(&mut secret.0)?;
+    Ok(secret)
+}
+
+// RFC 9458 §4.4
+//   salt = concat(enc, response_nonce)
+//   prk = Extract(salt, secret)
+//   aead_key = Expand(prk, "key", Nk)
+//   aead_nonce = Expand(prk, "nonce", Nn)
+
+/// Derives the context that the response is sealed under. Its first message is sealed with the
+/// derived nonce as is.
+fn response_ctx<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    secret: &ResponseSecret,
+    encapped_key: &Kem::EncappedKey,
+    response_nonce: &[u8],
+) -> Result<AeadCtx<A, Kdf, Kem>, HpkeError> {
+    let mut salt = encapped_key.to_bytes().to_vec();
+    salt.extend_from_slice(response_nonce);
+    let (_, hkdf) = SimpleHkdf::<Kdf>::extract(Some(&salt), &secret.0);
+
+    let mut key = AeadKey::<A>::default();
+    let mut nonce = AeadNonce::<A>::default();
+    hkdf.expand(b"key", &mut key.0)
+        .map_err(|_| HpkeError::KdfOutputTooLong)?;
+    hkdf.expand(b"nonce", &mut nonce.0)
+        .map_err(|_| HpkeError::KdfOutputTooLong)?;
+
+    // The response context never exports, so it has no exporter secret
+    Ok(AeadCtx::new(&key, nonce, ExporterSecret::default()))
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::{AesGcm128, ChaCha20Poly1305},
+        kdf::HkdfSha256,
+        kem::X25519HkdfSha256,
+        util::ReplayRng,
+    };
+
+    use hex_literal::hex;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = AesGcm128;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests a round trip through a gateway with two keys, and that requests to unknown keys, in
+    /// other suites, or tampered with are refused, as are responses to other requests
+    #[test]
+    fn test_ohttp_round_trip() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk1, pk1) = Kem::gen_keypair(&mut csprng);
+        let (sk2, pk2) = Kem::gen_keypair(&mut csprng);
+        let mut keys = RecipientKeyRing::<Kem>::new();
+        keys.insert(1, sk1);
+        keys.insert(2, sk2);
+
+        for (key_id, pk) in [(1, &pk1), (2, &pk2)] {
+            let (enc_request, client_ctx) =
+                encapsulate_request::<A, Kdf, Kem, _>(key_id, pk, b"GET /", &mut csprng).unwrap();
+            assert_eq!(
+                RequestHeader::parse(&enc_request).unwrap(),
+                RequestHeader {
+                    key_id,
+                    kem_id: 0x0020,
+                    kdf_id: 0x0001,
+                    aead_id: 0x0001,
+                }
+            );
+            assert_eq!(enc_request.len(), REQUEST_HEADER_SIZE + 32 + 5 + 16);
+
+            let (request, gateway_ctx) =
+                decapsulate_request::<A, Kdf, Kem>(&keys, &enc_request).unwrap();
+            assert_eq!(request, b"GET /");
+            let enc_response = gateway_ctx
+                .encapsulate_response(b"200 OK", &mut csprng)
+                .unwrap();
+            // The response nonce is max(Nn, Nk) = 16 bytes
+            assert_eq!(enc_response.len(), 16 + 6 + 16);
+            assert_eq!(
+                client_ctx.decapsulate_response(&enc_response).unwrap(),
+                b"200 OK"
+            );
+        }
+
+        let (enc_request, client_ctx) =
+            encapsulate_request::<A, Kdf, Kem, _>(3, &pk1, b"GET /", &mut csprng).unwrap();
+        assert_eq!(
+            decapsulate_request::<A, Kdf, Kem>(&keys, &enc_request).err(),
+            Some(HpkeError::UnknownKeyId)
+        );
+        assert_eq!(
+            decapsulate_request::<ChaCha20Poly1305, Kdf, Kem>(&keys, &enc_request).err(),
+            Some(HpkeError::ValidationError)
+        );
+        assert_eq!(
+            decapsulate_request::<A, Kdf, Kem>(&keys, &enc_request[..10]).err(),
+            Some(HpkeError::IncorrectInputLength(
+                REQUEST_HEADER_SIZE + 32,
+                10
+            ))
+        );
+
+        // A response to one request doesn't open as the response to another
+        let (enc_request, _) =
+            encapsulate_request::<A, Kdf, Kem, _>(1, &pk1, b"GET /", &mut csprng).unwrap();
+        let (_, gateway_ctx) = decapsulate_request::<A, Kdf, Kem>(&keys, &enc_request).unwrap();
+        let enc_response = gateway_ctx
+            .encapsulate_response(b"200 OK", &mut csprng)
+            .unwrap();
+        assert_eq!(
+            client_ctx.decapsulate_response(&enc_response).err(),
+            Some(HpkeError::OpenError)
+        );
+
+        // Changing the key ID changes the info, so the request doesn't open
+        let mut tampered = enc_request.clone();
+        tampered[0] = 2;
+        assert_eq!(
+            decapsulate_request::<A, Kdf, Kem>(&keys, &tampered).err(),
+            Some(HpkeError::OpenError)
+        );
+    }
+
+    /// Tests the gateway against the example in RFC 9458 Appendix A: it decapsulates the client's
+    /// request, and with the same response nonce, encapsulates the same response
+    #[test]
+    fn test_rfc9458_vector() {
+        let sk_gateway = <Kem as KemTrait>::PrivateKey::from_bytes(&hex!(
+            "3c168975674b2fa8e465970b79c8dcf09f1c741626480bd4c6162fc5b6a98e1a"
+        ))
+        .unwrap();
+        assert_eq!(
+            Kem::sk_to_pk(&sk_gateway).to_bytes().as_slice(),
+            hex!("31e1f05a740102115220e9af918f738674aec95f54db6e04eb705aae8e798155")
+        );
+        let mut keys = RecipientKeyRing::<Kem>::new();
+        keys.insert(1, sk_gateway);
+
+        let enc_request = hex!(
+            "010020000100014b28f881333e7c164ffc499ad9796f877f4e1051ee6d31bad1"
+            "9dec96c208b4726374e469135906992e1268c594d2a10c695d858c40a026e796"
+            "5e7d86b83dd440b2c0185204b4d63525"
+        );
+        let (request, gateway_ctx) =
+            decapsulate_request::<A, Kdf, Kem>(&keys, &enc_request).unwrap();
+        assert_eq!(
+            request,
+            hex!("00034745540568747470730b6578616d706c652e636f6d012f")
+        );
+
+        let response_nonce = hex!("c789e7151fcba46158ca84b04464910d");
+        let enc_response = gateway_ctx
+            .encapsulate_response(&hex!("0140c8"), &mut ReplayRng(&response_nonce))
+            .unwrap();
+        assert_eq!(
+            enc_response,
+            hex!(
+                "c789e7151fcba46158ca84b04464910d86f9013e404feea014e7be4a441f234f"
+                "857fbd"
+            )
+        );
+    }
+}
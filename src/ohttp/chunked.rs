@@ -0,0 +1,575 @@
+//! Chunked Oblivious HTTP (draft-ietf-ohai-chunked-ohttp), for bodies too large to hold in memory,
+//! or that are produced a piece at a time.
+//!
+//! A chunked request is its header and encapsulated key, followed by chunks sealed one after the
+//! other under the same context. A chunked response is the response nonce, followed by chunks
+//! sealed the same way under the response context. Each chunk is its length as a QUIC
+//! variable-length integer, then its ciphertext. The final chunk has length 0, runs to the end of
+//! the stream, and is sealed with the AAD `"final"`, so a stream that's cut short doesn't open.
+//!
+//! Sealers return the bytes to write to the stream next. Openers take bytes in whatever pieces
+//! they arrive in, and return the plaintext of every chunk that's complete. After an opener
+//! returns an error, the stream is unusable.
+//!
+//! An opener buffers a chunk until all of it has arrived, so it refuses chunks whose ciphertext is
+//! longer than its maximum, [`DEFAULT_MAX_CHUNK_LEN`] unless set with `with_max_chunk_len`. A
+//! chunk's declared length is checked before any of the chunk is buffered.
+
+use crate::{
+    aead::{Aead, AeadCtxR, AeadCtxS},
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    keyring::RecipientKeyRing,
+    ohttp::{
+        export_response_secret, response_ctx, setup_gateway, RequestHeader, ResponseSecret,
+        REQUEST_HEADER_SIZE,
+    },
+    setup::setup_sender,
+    HpkeError, OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+
+/// The media type of chunked encapsulated requests, which starts their `info`
+pub const CHUNKED_REQUEST_LABEL: &[u8] = b"message/bhttp chunked request";
+
+/// The media type of chunked encapsulated responses, which is the exporter context of the response
+/// secret
+pub const CHUNKED_RESPONSE_LABEL: &[u8] = b"message/bhttp chunked response";
+
+/// The AAD of the final chunk
+const FINAL_AAD: &[u8] = b"final";
+
+/// The longest chunk ciphertext that an opener accepts by default, in bytes
+pub const DEFAULT_MAX_CHUNK_LEN: usize = 1 << 16;
+
+/// The client's end of a chunked request
+pub struct RequestSealer<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    writer: ChunkWriter<A, Kdf, Kem>,
+    secret: ResponseSecret,
+    encapped_key: Kem::EncappedKey,
+}
+
+/// The gateway's end of a chunked request
+pub struct RequestOpener<'a, A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    keys: &'a RecipientKeyRing<Kem>,
+    /// The header and encapsulated key, until they've all arrived
+    prefix: crate::Vec<u8>,
+    /// The chunks, the response secret, and the encapsulated key, once the prefix has arrived
+    state: Option<(ChunkReader<A, Kdf, Kem>, ResponseSecret, Kem::EncappedKey)>,
+    max_chunk_len: usize,
+}
+
+/// The gateway's end of a chunked response
+pub struct ResponseSealer<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    writer: ChunkWriter<A, Kdf, Kem>,
+}
+
+/// The client's end of a chunked response
+pub struct ResponseOpener<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    secret: ResponseSecret,
+    encapped_key: Kem::EncappedKey,
+    /// The response nonce, until it's all arrived
+    prefix: crate::Vec<u8>,
+    /// The chunks, once the response nonce has arrived
+    reader: Option<ChunkReader<A, Kdf, Kem>>,
+    max_chunk_len: usize,
+}
+
+/// Starts a chunked request to the gateway's public key `pk_gateway`, which has ID `key_id` in the
+/// gateway's key configuration
+///
+/// Return Value
+/// ============
+/// Returns `Ok(sealer)` on success. If `A` is the export-only AEAD, returns
+/// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of `setup_sender`.
+pub fn encapsulate_request<A, Kdf, Kem, R>(
+    key_id: u8,
+    pk_gateway: &Kem::PublicKey,
+    csprng: &mut R,
+) -> Result<RequestSealer<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    if A::MAX_PLAINTEXT_LEN == 0 {
+        return Err(HpkeError::ValidationError);
+    }
+    let hdr = RequestHeader::new::<A, Kdf, Kem>(key_id);
+    let info = hdr.info(CHUNKED_REQUEST_LABEL);
+
+    let (encapped_key, ctx) =
+        setup_sender::<A, Kdf, Kem, R>(&OpModeS::Base, pk_gateway, &info, csprng)?;
+    let secret = export_response_secret::<A>(|out| ctx.export(CHUNKED_RESPONSE_LABEL, out))?;
+
+    let mut prefix = hdr.to_bytes().to_vec();
+    prefix.extend_from_slice(&encapped_key.to_bytes());
+    Ok(RequestSealer {
+        writer: ChunkWriter { ctx, prefix },
+        secret,
+        encapped_key,
+    })
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> RequestSealer<A, Kdf, Kem> {
+    /// Seals the next chunk of the request. The first call's output also carries the header and
+    /// encapsulated key.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(bytes)` on success. Write them to the stream. The errors are those of
+    /// `AeadCtxS::seal`.
+    pub fn seal_chunk(&mut self, chunk: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        self.writer.seal_chunk(chunk)
+    }
+
+    /// Seals the last chunk of the request, which may be empty
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok((bytes, response_opener))` on success. Write the bytes to the stream, then end
+    /// it, and open the response with `response_opener`. The errors are those of `AeadCtxS::seal`.
+    pub fn seal_final_chunk(
+        self,
+        chunk: &[u8],
+    ) -> Result<(crate::Vec<u8>, ResponseOpener<A, Kdf, Kem>), HpkeError> {
+        let bytes = self.writer.seal_final_chunk(chunk)?;
+        let response_opener = ResponseOpener {
+            secret: self.secret,
+            encapped_key: self.encapped_key,
+            prefix: crate::Vec::new(),
+            reader: None,
+            max_chunk_len: DEFAULT_MAX_CHUNK_LEN,
+        };
+        Ok((bytes, response_opener))
+    }
+}
+
+impl<'a, A: Aead, Kdf: KdfTrait, Kem: KemTrait> RequestOpener<'a, A, Kdf, Kem> {
+    /// Starts reading a chunked request that's encapsulated to one of the keys in `keys`
+    pub fn new(keys: &'a RecipientKeyRing<Kem>) -> RequestOpener<'a, A, Kdf, Kem> {
+        RequestOpener {
+            keys,
+            prefix: crate::Vec::new(),
+            state: None,
+            max_chunk_len: DEFAULT_MAX_CHUNK_LEN,
+        }
+    }
+
+    /// Sets the longest chunk ciphertext that this accepts, in bytes. This must be called before
+    /// the first `push`.
+    pub fn with_max_chunk_len(mut self, max_chunk_len: usize) -> RequestOpener<'a, A, Kdf, Kem> {
+        self.max_chunk_len = max_chunk_len;
+        self
+    }
+
+    /// Takes the next bytes of the request stream, and opens every chunk they complete
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success, which is empty if no chunk was completed. If the header
+    /// names a different ciphersuite, or `A` is the export-only AEAD, returns
+    /// `Err(HpkeError::ValidationError)`. If `keys` has no key with the header's key ID, returns
+    /// `Err(HpkeError::UnknownKeyId)`. If a chunk is longer than the maximum chunk length, returns
+    /// `Err(HpkeError::IncorrectInputLength(max_chunk_len, len))`, and if it fails to open, returns
+    /// `Err(HpkeError::OpenError)`. Otherwise, the errors are those of `setup_receiver`.
+    pub fn push(&mut self, data: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        let data = match self.state {
+            Some(_) => data,
+            None => {
+                let prefix_len = REQUEST_HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE;
+                let rest = match fill_prefix(&mut self.prefix, prefix_len, data) {
+                    Some(rest) => rest,
+                    None => return Ok(crate::Vec::new()),
+                };
+                let (ctx, encapped_key) =
+                    setup_gateway::<A, Kdf, Kem>(self.keys, &self.prefix, CHUNKED_REQUEST_LABEL)?;
+                let secret =
+                    export_response_secret::<A>(|out| ctx.export(CHUNKED_RESPONSE_LABEL, out))?;
+                let reader = ChunkReader::new(ctx, self.max_chunk_len);
+                self.state = Some((reader, secret, encapped_key));
+                rest
+            }
+        };
+        // The state was just filled in, if it wasn't already
+        self.state.as_mut().unwrap().0.push(data)
+    }
+
+    /// Opens the final chunk, once the stream has ended, and starts the response
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok((plaintext, response_sealer))` on success. Seal the response with
+    /// `response_sealer`. If the stream ended before the final chunk started, or the final chunk
+    /// fails to open, returns `Err(HpkeError::OpenError)`. If `csprng` failed, returns
+    /// `Err(HpkeError::RngError)`.
+    pub fn finish<R: CryptoRng + RngCore>(
+        self,
+        csprng: &mut R,
+    ) -> Result<(crate::Vec<u8>, ResponseSealer<A, Kdf, Kem>), HpkeError> {
+        let (reader, secret, encapped_key) = self.state.ok_or(HpkeError::OpenError)?;
+        let plaintext = reader.finish()?;
+
+        let mut response_nonce = vec![0u8; secret.0.len()];
+        csprng
+            .try_fill_bytes(&mut response_nonce)
+            .map_err(|_| HpkeError::RngError)?;
+        let ctx = response_ctx::<A, Kdf, Kem>(&secret, &encapped_key, &response_nonce)?;
+        let response_sealer = ResponseSealer {
+            writer: ChunkWriter {
+                ctx: AeadCtxS::from(ctx),
+                prefix: response_nonce,
+            },
+        };
+        Ok((plaintext, response_sealer))
+    }
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> ResponseSealer<A, Kdf, Kem> {
+    /// Seals the next chunk of the response. The first call's output also carries the response
+    /// nonce.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(bytes)` on success. Write them to the stream. The errors are those of
+    /// `AeadCtxS::seal`.
+    pub fn seal_chunk(&mut self, chunk: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        self.writer.seal_chunk(chunk)
+    }
+
+    /// Seals the last chunk of the response, which may be empty
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(bytes)` on success. Write them to the stream, then end it. The errors are those
+    /// of `AeadCtxS::seal`.
+    pub fn seal_final_chunk(self, chunk: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        self.writer.seal_final_chunk(chunk)
+    }
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> ResponseOpener<A, Kdf, Kem> {
+    /// Sets the longest chunk ciphertext that this accepts, in bytes. This must be called before
+    /// the first `push`.
+    pub fn with_max_chunk_len(mut self, max_chunk_len: usize) -> ResponseOpener<A, Kdf, Kem> {
+        self.max_chunk_len = max_chunk_len;
+        self
+    }
+
+    /// Takes the next bytes of the response stream, and opens every chunk they complete
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success, which is empty if no chunk was completed. If a chunk is
+    /// longer than the maximum chunk length, returns
+    /// `Err(HpkeError::IncorrectInputLength(max_chunk_len, len))`, and if it fails to open,
+    /// returns `Err(HpkeError::OpenError)`.
+    pub fn push(&mut self, data: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        let data = match self.reader {
+            Some(_) => data,
+            None => {
+                let rest = match fill_prefix(&mut self.prefix, self.secret.0.len(), data) {
+                    Some(rest) => rest,
+                    None => return Ok(crate::Vec::new()),
+                };
+                let ctx =
+                    response_ctx::<A, Kdf, Kem>(&self.secret, &self.encapped_key, &self.prefix)?;
+                self.reader = Some(ChunkReader::new(AeadCtxR::from(ctx), self.max_chunk_len));
+                rest
+            }
+        };
+        // The reader was just filled in, if it wasn't already
+        self.reader.as_mut().unwrap().push(data)
+    }
+
+    /// Opens the final chunk, once the stream has ended
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If the stream ended before the final chunk started, or
+    /// the final chunk fails to open, returns `Err(HpkeError::OpenError)`.
+    pub fn finish(self) -> Result<crate::Vec<u8>, HpkeError> {
+        self.reader.ok_or(HpkeError::OpenError)?.finish()
+    }
+}
+
+/// Seals chunks under a context, and frames them
+struct ChunkWriter<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    ctx: AeadCtxS<A, Kdf, Kem>,
+    /// What goes before the first chunk. This is empty once it's been written.
+    prefix: crate::Vec<u8>,
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> ChunkWriter<A, Kdf, Kem> {
+    fn seal_chunk(&mut self, chunk: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        let ciphertext = self.ctx.seal(chunk, &[])?;
+        let mut bytes = core::mem::take(&mut self.prefix);
+        put_varint(&mut bytes, ciphertext.len() as u64);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    fn seal_final_chunk(mut self, chunk: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        let ciphertext = self.ctx.seal(chunk, FINAL_AAD)?;
+        let mut bytes = core::mem::take(&mut self.prefix);
+        put_varint(&mut bytes, 0);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+}
+
+/// Buffers and opens framed chunks under a context
+struct ChunkReader<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    ctx: AeadCtxR<A, Kdf, Kem>,
+    /// The bytes of the stream that aren't part of an opened chunk yet
+    buf: crate::Vec<u8>,
+    /// Whether the final chunk has started, i.e., everything from here on is its ciphertext
+    in_final: bool,
+    /// The longest chunk ciphertext that's buffered
+    max_chunk_len: usize,
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> ChunkReader<A, Kdf, Kem> {
+    fn new(ctx: AeadCtxR<A, Kdf, Kem>, max_chunk_len: usize) -> ChunkReader<A, Kdf, Kem> {
+        ChunkReader {
+            ctx,
+            buf: crate::Vec::new(),
+            in_final: false,
+            max_chunk_len,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        if self.in_final {
+            self.check_chunk_len(self.buf.len().saturating_add(data.len()))?;
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut plaintext = crate::Vec::new();
+        let mut pos = 0;
+        while !self.in_final {
+            let (len, len_size) = match get_varint(&self.buf[pos..]) {
+                Some(varint) => varint,
+                None => break,
+            };
+            if len == 0 {
+                self.in_final = true;
+                pos += len_size;
+                break;
+            }
+            let len = usize::try_from(len).unwrap_or(usize::MAX);
+            self.check_chunk_len(len)?;
+            // A chunk longer than memory can't be buffered, so it can't be opened either
+            let end = (pos + len_size)
+                .checked_add(len)
+                .ok_or(HpkeError::OpenError)?;
+            if self.buf.len() < end {
+                break;
+            }
+            plaintext.extend(self.ctx.open(&self.buf[pos + len_size..end], &[])?);
+            pos = end;
+        }
+        self.buf.drain(..pos);
+        if self.in_final {
+            self.check_chunk_len(self.buf.len())?;
+        }
+
+        Ok(plaintext)
+    }
+
+    /// Refuses a chunk of `len` bytes if it's longer than `max_chunk_len`
+    fn check_chunk_len(&self, len: usize) -> Result<(), HpkeError> {
+        if len > self.max_chunk_len {
+            Err(HpkeError::IncorrectInputLength(self.max_chunk_len, len))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn finish(mut self) -> Result<crate::Vec<u8>, HpkeError> {
+        if !self.in_final {
+            return Err(HpkeError::OpenError);
+        }
+        self.ctx.open(&self.buf, FINAL_AAD)
+    }
+}
+
+/// Buffers `data` until `prefix` is `len` bytes long. Once it is, returns the rest of `data`.
+fn fill_prefix<'d>(prefix: &mut crate::Vec<u8>, len: usize, data: &'d [u8]) -> Option<&'d [u8]> {
+    let (head, rest) = data.split_at(core::cmp::min(len - prefix.len(), data.len()));
+    prefix.extend_from_slice(head);
+    if prefix.len() == len {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+// RFC 9000 §16: the top two bits of the first byte are the base-2 log of the length in bytes, and
+// the rest is the value, big-endian
+
+/// Appends the shortest variable-length encoding of `n`, which must be less than 2^62
+fn put_varint(buf: &mut crate::Vec<u8>, n: u64) {
+    match n {
+        0..=0x3f => buf.push(n as u8),
+        0x40..=0x3fff => buf.extend_from_slice(&(n as u16 | 0x4000).to_be_bytes()),
+        0x4000..=0x3fff_ffff => buf.extend_from_slice(&(n as u32 | 0x8000_0000).to_be_bytes()),
+        _ => buf.extend_from_slice(&(n | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+/// Parses the variable-length integer at the start of `buf`. Returns the value and the size of its
+/// encoding, or `None` if `buf` doesn't hold all of it.
+fn get_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let size = 1 << (buf.first()? >> 6);
+    let bytes = buf.get(..size)?;
+    let mut n = (bytes[0] & 0x3f) as u64;
+    for byte in &bytes[1..] {
+        n = (n << 8) | *byte as u64;
+    }
+    Some((n, size))
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{aead::AesGcm128, kdf::HkdfSha256, kem::X25519HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = AesGcm128;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests the varint encoding against RFC 9000 §A.1
+    #[test]
+    fn test_varint() {
+        for (n, encoded) in [
+            (37, &[0x25][..]),
+            (15293, &[0x7b, 0xbd]),
+            (494878333, &[0x9d, 0x7f, 0x3e, 0x7d]),
+            (
+                151288809941952652,
+                &[0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c],
+            ),
+        ] {
+            let mut buf = crate::Vec::new();
+            put_varint(&mut buf, n);
+            assert_eq!(buf, encoded);
+            assert_eq!(get_varint(encoded), Some((n, encoded.len())));
+            assert_eq!(get_varint(&encoded[..encoded.len() - 1]), None);
+        }
+    }
+
+    /// Tests a chunked request and response fed to the other end a byte at a time and all at
+    /// once, and that a truncated or reordered stream doesn't open
+    #[test]
+    fn test_chunked_round_trip() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let mut keys = RecipientKeyRing::<Kem>::new();
+        keys.insert(7, sk);
+
+        let chunks: [&[u8]; 3] = [b"POST /upload", &[0xab; 100], b""];
+        let mut sealer = encapsulate_request::<A, Kdf, Kem, _>(7, &pk, &mut csprng).unwrap();
+        let mut stream = crate::Vec::new();
+        let mut chunk_bytes = crate::Vec::new();
+        for chunk in chunks {
+            let bytes = sealer.seal_chunk(chunk).unwrap();
+            chunk_bytes.push(bytes.clone());
+            stream.extend(bytes);
+        }
+        let (bytes, response_opener) = sealer.seal_final_chunk(b"end").unwrap();
+        let final_len = bytes.len();
+        stream.extend(bytes);
+        let expected = [&chunks.concat()[..], &b"end"[..]].concat();
+
+        // Byte by byte
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys);
+        let mut request = crate::Vec::new();
+        for byte in &stream {
+            request.extend(opener.push(&[*byte]).unwrap());
+        }
+        let (last, mut response_sealer) = opener.finish(&mut csprng).unwrap();
+        request.extend(last);
+        assert_eq!(request, expected);
+
+        // All at once
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys);
+        let mut request = opener.push(&stream).unwrap();
+        request.extend(opener.finish(&mut csprng).unwrap().0);
+        assert_eq!(request, expected);
+
+        // The response, in two pieces that don't line up with chunks
+        let mut response = response_sealer.seal_chunk(b"200 OK").unwrap();
+        response.extend(response_sealer.seal_final_chunk(b"done").unwrap());
+        let mut response_opener = response_opener;
+        let mut body = response_opener.push(&response[..5]).unwrap();
+        body.extend(response_opener.push(&response[5..]).unwrap());
+        body.extend(response_opener.finish().unwrap());
+        assert_eq!(body, b"200 OKdone");
+
+        // A stream cut off before or inside the final chunk doesn't open
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys);
+        opener.push(&stream[..stream.len() - final_len]).unwrap();
+        assert_eq!(opener.finish(&mut csprng).err(), Some(HpkeError::OpenError));
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys);
+        opener.push(&stream[..stream.len() - 1]).unwrap();
+        assert_eq!(opener.finish(&mut csprng).err(), Some(HpkeError::OpenError));
+
+        // Swapping two chunks breaks the sequence
+        let prefix_len = REQUEST_HEADER_SIZE + 32;
+        let mut reordered = stream[..prefix_len].to_vec();
+        reordered.extend_from_slice(&chunk_bytes[1]);
+        reordered.extend_from_slice(&chunk_bytes[0][prefix_len..]);
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys);
+        assert_eq!(opener.push(&reordered).err(), Some(HpkeError::OpenError));
+    }
+
+    /// Tests that a chunk, or final chunk, longer than the maximum is refused from its length
+    /// prefix, or as soon as it outgrows the maximum, without waiting for the rest of it
+    #[test]
+    fn test_max_chunk_len() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let mut keys = RecipientKeyRing::<Kem>::new();
+        keys.insert(7, sk);
+        let sealer = encapsulate_request::<A, Kdf, Kem, _>(7, &pk, &mut csprng).unwrap();
+        let (prefix, _) = sealer.seal_final_chunk(b"").unwrap();
+        let prefix = &prefix[..REQUEST_HEADER_SIZE + 32];
+
+        // A length prefix of 2^62 - 1, with none of the chunk after it
+        let mut stream = prefix.to_vec();
+        put_varint(&mut stream, (1 << 62) - 1);
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys);
+        assert_eq!(
+            opener.push(&stream).err(),
+            Some(HpkeError::IncorrectInputLength(
+                DEFAULT_MAX_CHUNK_LEN,
+                usize::try_from((1u64 << 62) - 1).unwrap_or(usize::MAX)
+            ))
+        );
+
+        // A lower maximum, and a chunk just over it
+        let mut stream = prefix.to_vec();
+        put_varint(&mut stream, 101);
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys).with_max_chunk_len(100);
+        assert_eq!(
+            opener.push(&stream).err(),
+            Some(HpkeError::IncorrectInputLength(100, 101))
+        );
+
+        // A final chunk that keeps going
+        let mut stream = prefix.to_vec();
+        put_varint(&mut stream, 0);
+        let mut opener = RequestOpener::<A, Kdf, Kem>::new(&keys).with_max_chunk_len(100);
+        opener.push(&stream).unwrap();
+        opener.push(&[0; 100]).unwrap();
+        assert_eq!(
+            opener.push(&[0]).err(),
+            Some(HpkeError::IncorrectInputLength(100, 101))
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Wrapping a payload in one HPKE layer per relay, for mixnet-style routing.
+//!
+//! [`seal_onion`] seals the payload to the last relay's key, then seals that to the one before,
+//! and so on out to the first. Each relay [`peel`]s off its own layer, and learns either the onion
+//! to pass on or, if it's the last, the payload. Each layer is `enc || ciphertext`, under a fresh
+//! encapsulation.
+//!
+//! The payload is padded before the innermost layer is sealed, and every layer adds the same
+//! overhead, so the size of an onion at each hop depends only on the padded size and on how many
+//! layers are left. Pick a [`Padding`] that all payloads share a size under, e.g.,
+//! `Padding::Fixed`. A relay does learn how many hops are after it from the size of its onion.
+//! Where the onion goes next is up to the application, e.g., as part of each relay's payload
+//! format or its transport.
+
+use crate::{
+    aead::Aead,
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    padding::{pad, unpad, Padding},
+    single_shot_open, single_shot_seal, Deserializable, HpkeError, OpModeR, OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+/// Starts the plaintext of a layer whose inside is another onion
+const LAYER_RELAY: u8 = 0x00;
+/// Starts the plaintext of a layer whose inside is the padded payload
+const LAYER_FINAL: u8 = 0x01;
+
+/// What's inside a peeled layer
+#[derive(Debug, PartialEq, Eq)]
+pub enum Peeled {
+    /// The onion to pass on to the next relay
+    Relay(crate::Vec<u8>),
+    /// The payload. This relay is the last.
+    Final(crate::Vec<u8>),
+}
+
+/// Wraps `plaintext` in one layer for each of `pks_relay`, in the order the onion visits them.
+/// Every layer is sealed under `info`.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(onion)` on success. Send it to the first relay. If `pks_relay` is empty, returns
+/// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of `Padding::padded_len` and
+/// `single_shot_seal`.
+pub fn seal_onion<A, Kdf, Kem, R>(
+    pks_relay: &[&Kem::PublicKey],
+    info: &[u8],
+    plaintext: &[u8],
+    padding: Padding,
+    csprng: &mut R,
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    if pks_relay.is_empty() {
+        return Err(HpkeError::ValidationError);
+    }
+
+    let mut inner = [LAYER_FINAL].to_vec();
+    inner.extend(pad(plaintext, padding)?);
+    for (i, pk_relay) in pks_relay.iter().enumerate().rev() {
+        let res =
+            single_shot_seal::<A, Kdf, Kem, R>(&OpModeS::Base, pk_relay, info, &inner, &[], csprng);
+        inner.zeroize();
+        let (encapped_key, ciphertext) = res?;
+
+        // Every layer but the outermost is wrapped again
+        inner = if i == 0 {
+            crate::Vec::new()
+        } else {
+            [LAYER_RELAY].to_vec()
+        };
+        inner.extend_from_slice(&encapped_key.to_bytes());
+        inner.extend_from_slice(&ciphertext);
+    }
+    Ok(inner)
+}
+
+/// Removes the layer sealed to `sk_relay` from `onion`
+///
+/// Return Value
+/// ============
+/// Returns `Ok(peeled)` on success. If `onion` is too short to hold an encapsulated key, returns
+/// `Err(HpkeError::IncorrectInputLength(Nenc, onion.len()))`. If the layer fails to open or is
+/// malformed, returns `Err(HpkeError::OpenError)`. Otherwise, the errors are those of
+/// `single_shot_open`.
+pub fn peel<A, Kdf, Kem>(
+    sk_relay: &Kem::PrivateKey,
+    info: &[u8],
+    onion: &[u8],
+) -> Result<Peeled, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    if onion.len() < Kem::ENCAPPED_KEY_SIZE {
+        return Err(HpkeError::IncorrectInputLength(
+            Kem::ENCAPPED_KEY_SIZE,
+            onion.len(),
+        ));
+    }
+    let (encapped_key, ciphertext) = onion.split_at(Kem::ENCAPPED_KEY_SIZE);
+    let encapped_key = Kem::EncappedKey::from_bytes(encapped_key)?;
+
+    let mut layer = single_shot_open::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_relay,
+        &encapped_key,
+        info,
+        ciphertext,
+        &[],
+    )?;
+    let peeled = match layer.split_first() {
+        Some((&LAYER_RELAY, inner)) => Ok(Peeled::Relay(inner.to_vec())),
+        Some((&LAYER_FINAL, padded)) => unpad(padded).map(Peeled::Final),
+        _ => Err(HpkeError::OpenError),
+    };
+    layer.zeroize();
+    peeled
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests that each relay peels its own layer in order, that onions of different payloads are
+    /// the same size at every hop, and that relays can't peel out of order
+    #[test]
+    fn test_onion() {
+        let mut csprng = StdRng::from_entropy();
+        let relays: crate::Vec<_> = (0..3).map(|_| Kem::gen_keypair(&mut csprng)).collect();
+        let pks: crate::Vec<_> = relays.iter().map(|(_, pk)| pk).collect();
+        let padding = Padding::Fixed(64);
+
+        let mut sizes = crate::Vec::new();
+        for payload in [&b"hi"[..], &[0xaa; 50]] {
+            let mut onion =
+                seal_onion::<A, Kdf, Kem, _>(&pks, b"mix", payload, padding, &mut csprng).unwrap();
+            let mut hop_sizes = crate::Vec::new();
+            for (i, (sk, _)) in relays.iter().enumerate() {
+                hop_sizes.push(onion.len());
+                match peel::<A, Kdf, Kem>(sk, b"mix", &onion).unwrap() {
+                    Peeled::Relay(inner) if i < 2 => onion = inner,
+                    Peeled::Final(pt) if i == 2 => assert_eq!(pt, payload),
+                    other => panic!("unexpected layer {other:?} at hop {i}"),
+                }
+            }
+            sizes.push(hop_sizes);
+        }
+        // The innermost layer is a 32-byte encapped key, the type byte and padded payload, and a
+        // 16-byte tag. Each layer around it adds another type byte, encapped key, and tag.
+        assert_eq!(sizes[0], [211, 162, 113]);
+        assert_eq!(sizes[0], sizes[1]);
+
+        let onion =
+            seal_onion::<A, Kdf, Kem, _>(&pks, b"mix", b"hi", padding, &mut csprng).unwrap();
+        assert_eq!(
+            peel::<A, Kdf, Kem>(&relays[1].0, b"mix", &onion),
+            Err(HpkeError::OpenError)
+        );
+        assert_eq!(
+            peel::<A, Kdf, Kem>(&relays[0].0, b"other", &onion),
+            Err(HpkeError::OpenError)
+        );
+        assert_eq!(
+            seal_onion::<A, Kdf, Kem, _>(&[], b"mix", b"hi", padding, &mut csprng),
+            Err(HpkeError::ValidationError)
+        );
+    }
+}
@@ -1,4 +1,11 @@
-use crate::kem::Kem as KemTrait;
+use crate::{kem::Kem as KemTrait, HpkeError};
+
+use subtle::{Choice, ConstantTimeEq};
+
+// The minimum PSK length, in bytes, that the `strict` feature enforces. A PSK can't have more
+// entropy than its length, and RFC 9180 §5.1.2 requires 32 bytes of it.
+#[cfg(feature = "strict")]
+const MIN_STRICT_PSK_LEN: usize = 32;
 
 /// Contains preshared key bytes and an identifier. This is intended to go inside an `OpModeR` or
 /// `OpModeS` struct.
@@ -16,6 +23,92 @@ pub struct PskBundle<'a> {
     pub psk_id: &'a [u8],
 }
 
+// Only the lengths of the PSKs and PSK IDs leak, which the KDF reveals anyway
+impl ConstantTimeEq for PskBundle<'_> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.psk.ct_eq(other.psk) & self.psk_id.ct_eq(other.psk_id)
+    }
+}
+
+impl<'a> PskBundle<'a> {
+    /// Makes a PSK bundle, checking that neither `psk` nor `psk_id` is empty, as RFC 9180 §5.1
+    /// requires of the PSK modes. With the `strict` feature, this also checks that `psk` is at
+    /// least 32 bytes long and isn't a single repeated byte, e.g., all zeros.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns the bundle on success. If either check fails, returns `Err(HpkeError::InvalidPsk)`.
+    pub fn new(psk: &'a [u8], psk_id: &'a [u8]) -> Result<PskBundle<'a>, HpkeError> {
+        let bundle = PskBundle { psk, psk_id };
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    // Does the checks described in new()
+    pub(crate) fn validate(&self) -> Result<(), HpkeError> {
+        // RFC 9180 §5.1
+        // def VerifyPSKInputs(mode, psk, psk_id):
+        //   got_psk = (psk != default_psk)
+        //   got_psk_id = (psk_id != default_psk_id)
+        //   if got_psk != got_psk_id:
+        //     raise Exception("Inconsistent PSK inputs")
+        //
+        //   if got_psk and (mode in [mode_base, mode_auth]):
+        //     raise Exception("PSK input provided when not needed")
+        //   if (not got_psk) and (mode in [mode_psk, mode_auth_psk]):
+        //     raise Exception("Missing required PSK input")
+        //
+        // A bundle is only ever used in a PSK mode, so both values must be nonempty
+        if self.psk.is_empty() || self.psk_id.is_empty() {
+            return Err(HpkeError::InvalidPsk);
+        }
+
+        // RFC 9180 §5.1.2: The PSK MUST have at least 32 bytes of entropy. We can't measure
+        // entropy, but we can catch short PSKs and the most common placeholder values.
+        #[cfg(feature = "strict")]
+        {
+            // Don't short-circuit, so that the running time doesn't say where the first
+            // differing byte is
+            let is_repeated_byte: bool = self
+                .psk
+                .iter()
+                .fold(Choice::from(1), |acc, b| acc & b.ct_eq(&self.psk[0]))
+                .into();
+            if self.psk.len() < MIN_STRICT_PSK_LEN || is_repeated_byte {
+                return Err(HpkeError::InvalidPsk);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Looks up preshared keys by their IDs. This lets a receiver that shares PSKs with many senders
+/// wait until it knows a sender's PSK ID before finding the PSK. See
+/// [`setup_receiver_with_psk_store`](crate::setup_receiver_with_psk_store).
+///
+/// This is implemented for closures `Fn(&[u8]) -> Option<P>`, where `P: AsRef<[u8]>`. A store
+/// should take care not to leak, e.g., through timing, which PSK IDs it knows.
+pub trait PskStore {
+    /// The PSK type the store hands out, e.g., `&[u8]`, or an owned buffer that zeroizes itself
+    type Psk: AsRef<[u8]>;
+
+    /// Returns the PSK with the given ID, or `None` if there isn't one
+    fn lookup(&self, psk_id: &[u8]) -> Option<Self::Psk>;
+}
+
+impl<F, P> PskStore for F
+where
+    F: Fn(&[u8]) -> Option<P>,
+    P: AsRef<[u8]>,
+{
+    type Psk = P;
+
+    fn lookup(&self, psk_id: &[u8]) -> Option<P> {
+        self(psk_id)
+    }
+}
+
 /// The operation mode of the HPKE session (receiver's view). This is how the sender authenticates
 /// their identity to the receiver. This authentication information can include a preshared key,
 /// the identity key of the sender, both, or neither. `Base` is the only mode that does not provide
@@ -31,8 +124,42 @@ pub enum OpModeR<'a, Kem: KemTrait> {
     AuthPsk(Kem::PublicKey, PskBundle<'a>),
 }
 
-// Helper function for setup_receiver
 impl<'a, Kem: KemTrait> OpModeR<'a, Kem> {
+    /// Makes the mode with the given RFC 9180 mode ID (0 for base, 1 for PSK, 2 for auth, 3 for
+    /// auth-PSK) out of whichever of the sender's identity key and PSK bundle it calls for. This is
+    /// for code that picks the mode at runtime, e.g., from a header.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(mode)` on success. If `mode_id` is unknown, or `pk_sender_id` or `psk` is
+    /// given to a mode that doesn't use it or missing from one that does, returns
+    /// `Err(HpkeError::ValidationError)`.
+    pub fn from_parts(
+        mode_id: u8,
+        pk_sender_id: Option<Kem::PublicKey>,
+        psk: Option<PskBundle<'a>>,
+    ) -> Result<OpModeR<'a, Kem>, HpkeError> {
+        match (mode_id, pk_sender_id, psk) {
+            (0x00, None, None) => Ok(OpModeR::Base),
+            (0x01, None, Some(bundle)) => Ok(OpModeR::Psk(bundle)),
+            (0x02, Some(pk), None) => Ok(OpModeR::Auth(pk)),
+            (0x03, Some(pk), Some(bundle)) => Ok(OpModeR::AuthPsk(pk, bundle)),
+            _ => Err(HpkeError::ValidationError),
+        }
+    }
+
+    /// Returns the RFC 9180 identifier of this mode: 0 for base, 1 for PSK, 2 for auth, and 3 for
+    /// auth-PSK
+    pub fn mode_id(&self) -> u8 {
+        // Defined in RFC 9180 §5 Table 1
+        match self {
+            OpModeR::Base => 0x00,
+            OpModeR::Psk(..) => 0x01,
+            OpModeR::Auth(..) => 0x02,
+            OpModeR::AuthPsk(..) => 0x03,
+        }
+    }
+
     /// Returns the sender's identity pubkey if it's specified
     pub(crate) fn get_pk_sender_id(&self) -> Option<&Kem::PublicKey> {
         match self {
@@ -58,8 +185,41 @@ pub enum OpModeS<'a, Kem: KemTrait> {
     AuthPsk((Kem::PrivateKey, Kem::PublicKey), PskBundle<'a>),
 }
 
-// Helpers functions for setup_sender and testing
 impl<'a, Kem: KemTrait> OpModeS<'a, Kem> {
+    /// Makes the mode with the given RFC 9180 mode ID out of whichever of the sender's identity
+    /// keypair and PSK bundle it calls for. See `OpModeR::from_parts`.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(mode)` on success. If `mode_id` is unknown, or `sender_id_keypair` or `psk`
+    /// is given to a mode that doesn't use it or missing from one that does, returns
+    /// `Err(HpkeError::ValidationError)`.
+    pub fn from_parts(
+        mode_id: u8,
+        sender_id_keypair: Option<(Kem::PrivateKey, Kem::PublicKey)>,
+        psk: Option<PskBundle<'a>>,
+    ) -> Result<OpModeS<'a, Kem>, HpkeError> {
+        match (mode_id, sender_id_keypair, psk) {
+            (0x00, None, None) => Ok(OpModeS::Base),
+            (0x01, None, Some(bundle)) => Ok(OpModeS::Psk(bundle)),
+            (0x02, Some(keypair), None) => Ok(OpModeS::Auth(keypair)),
+            (0x03, Some(keypair), Some(bundle)) => Ok(OpModeS::AuthPsk(keypair, bundle)),
+            _ => Err(HpkeError::ValidationError),
+        }
+    }
+
+    /// Returns the RFC 9180 identifier of this mode: 0 for base, 1 for PSK, 2 for auth, and 3 for
+    /// auth-PSK
+    pub fn mode_id(&self) -> u8 {
+        // Defined in RFC 9180 §5 Table 1
+        match self {
+            OpModeS::Base => 0x00,
+            OpModeS::Psk(..) => 0x01,
+            OpModeS::Auth(..) => 0x02,
+            OpModeS::AuthPsk(..) => 0x03,
+        }
+    }
+
     /// Returns the sender's identity pubkey if it's specified
     pub(crate) fn get_sender_id_keypair(&self) -> Option<(&Kem::PrivateKey, &Kem::PublicKey)> {
         match self {
@@ -79,17 +239,24 @@ pub(crate) trait OpMode<Kem: KemTrait> {
     fn get_psk_bytes(&self) -> &[u8];
     /// If this is a PSK mode, returns the PSK ID. Otherwise returns the empty string.
     fn get_psk_id(&self) -> &[u8];
+
+    /// If this is a PSK mode, checks the PSK bundle as [`PskBundle::new`] does
+    #[cfg(feature = "strict")]
+    fn verify_psk_inputs(&self) -> Result<(), HpkeError> {
+        match self.mode_id() {
+            0x01 | 0x03 => PskBundle {
+                psk: self.get_psk_bytes(),
+                psk_id: self.get_psk_id(),
+            }
+            .validate(),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<'a, Kem: KemTrait> OpMode<Kem> for OpModeR<'a, Kem> {
-    // Defined in RFC 9180 §5 Table 1
     fn mode_id(&self) -> u8 {
-        match self {
-            OpModeR::Base => 0x00,
-            OpModeR::Psk(..) => 0x01,
-            OpModeR::Auth(..) => 0x02,
-            OpModeR::AuthPsk(..) => 0x03,
-        }
+        OpModeR::mode_id(self)
     }
 
     // Returns the preshared key bytes if it's set in the mode, otherwise returns
@@ -117,14 +284,8 @@ impl<'a, Kem: KemTrait> OpMode<Kem> for OpModeR<'a, Kem> {
 // I know there's a bunch of code reuse here, but it's not so much that I feel the need to abstract
 // something away
 impl<'a, Kem: KemTrait> OpMode<Kem> for OpModeS<'a, Kem> {
-    // Defined in RFC 9180 §5 Table 1
     fn mode_id(&self) -> u8 {
-        match self {
-            OpModeS::Base => 0x00,
-            OpModeS::Psk(..) => 0x01,
-            OpModeS::Auth(..) => 0x02,
-            OpModeS::AuthPsk(..) => 0x03,
-        }
+        OpModeS::mode_id(self)
     }
 
     // Returns the preshared key bytes if it's set in the mode, otherwise returns
@@ -148,3 +309,43 @@ impl<'a, Kem: KemTrait> OpMode<Kem> for OpModeS<'a, Kem> {
         }
     }
 }
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::kem::X25519HkdfSha256;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that `from_parts` round-trips with `mode_id`, and refuses missing and extra parts
+    #[test]
+    fn test_from_parts() {
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let psk: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let bundle = PskBundle::new(&psk, b"psk id").unwrap();
+
+        for mode_id in 0..4u8 {
+            let has_pk = mode_id & 0x02 != 0;
+            let has_psk = mode_id & 0x01 != 0;
+            let pk_part = || Some(pk.clone()).filter(|_| has_pk);
+            let keypair_part = || Some((sk.clone(), pk.clone())).filter(|_| has_pk);
+            let psk_part = Some(bundle).filter(|_| has_psk);
+
+            let mode_r = OpModeR::<Kem>::from_parts(mode_id, pk_part(), psk_part).unwrap();
+            let mode_s = OpModeS::<Kem>::from_parts(mode_id, keypair_part(), psk_part).unwrap();
+            assert_eq!(mode_r.mode_id(), mode_id);
+            assert_eq!(mode_s.mode_id(), mode_id);
+
+            // Flipping either part makes the parts wrong for the mode
+            let flipped_pk = Some(pk.clone()).filter(|_| !has_pk);
+            let flipped_psk = Some(bundle).filter(|_| !has_psk);
+            assert!(OpModeR::<Kem>::from_parts(mode_id, flipped_pk, psk_part).is_err());
+            assert!(OpModeR::<Kem>::from_parts(mode_id, pk_part(), flipped_psk).is_err());
+        }
+        assert!(OpModeR::<Kem>::from_parts(4, None, None).is_err());
+        assert!(OpModeS::<Kem>::from_parts(4, None, None).is_err());
+    }
+}
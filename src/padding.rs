@@ -0,0 +1,275 @@
+//! Padding plaintexts before sealing, so ciphertext lengths don't reveal what's inside.
+//!
+//! A protocol whose messages are distinguishable by length, e.g., a Payjoin v2 directory, where an
+//! original PSBT and a proposal differ in size, can pad every plaintext to one of a few sizes
+//! before sealing it. [`pad`] appends a `0x80` marker and then zeros up to the size the
+//! [`Padding`] picks, and [`unpad`] strips them in constant time. [`single_shot_seal_padded`] and
+//! [`single_shot_open_padded`] do this around a single-shot seal and open.
+
+use crate::{
+    aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, single_shot_open, single_shot_seal,
+    HpkeError, OpModeR, OpModeS,
+};
+
+use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+use zeroize::Zeroize;
+
+/// The byte that ends the plaintext and starts the padding
+const PADDING_MARKER: u8 = 0x80;
+
+/// How long a padded plaintext is. Every scheme adds at least one byte, for the marker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// Pad every plaintext to exactly this many bytes. Only the fact that the plaintext fits is
+    /// revealed. This is what Payjoin v2 does.
+    Fixed(usize),
+    /// Pad to the next multiple of this many bytes, which must be nonzero
+    Bucket(usize),
+    /// Padmé (Nikitin et al., PETS 2019): round up so that the padded length leaks
+    /// `O(log log L)` bits about the length `L`, for at most about 12% overhead
+    Padme,
+}
+
+impl Padding {
+    /// Returns the length that a plaintext of length `len` is padded to
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(padded_len)` on success. If the plaintext doesn't fit under `Padding::Fixed`,
+    /// or the padded length would overflow, returns `Err(HpkeError::PlaintextTooLong)`. If the
+    /// bucket size is 0, returns `Err(HpkeError::ValidationError)`.
+    pub fn padded_len(&self, len: usize) -> Result<usize, HpkeError> {
+        let min_len = len.checked_add(1).ok_or(HpkeError::PlaintextTooLong)?;
+        match *self {
+            Padding::Fixed(size) if min_len <= size => Ok(size),
+            Padding::Fixed(_) => Err(HpkeError::PlaintextTooLong),
+            Padding::Bucket(0) => Err(HpkeError::ValidationError),
+            Padding::Bucket(size) => {
+                let buckets = min_len / size + (min_len % size != 0) as usize;
+                buckets.checked_mul(size).ok_or(HpkeError::PlaintextTooLong)
+            }
+            Padding::Padme => padme(min_len),
+        }
+    }
+}
+
+/// Padmé's rounding: keep the top `floor(log2(E)) + 1` bits of `len`, where
+/// `E = floor(log2(len))`, and round the rest up
+fn padme(len: usize) -> Result<usize, HpkeError> {
+    if len < 2 {
+        return Ok(len);
+    }
+    let e = usize::BITS - 1 - len.leading_zeros();
+    let s = u32::BITS - e.leading_zeros();
+    let mask = (1usize << (e - s)) - 1;
+    len.checked_add(mask)
+        .map(|n| n & !mask)
+        .ok_or(HpkeError::PlaintextTooLong)
+}
+
+/// Returns `plaintext` followed by `0x80` and as many zeros as `padding` calls for
+///
+/// Return Value
+/// ============
+/// Returns `Ok(padded)` on success. The errors are those of `Padding::padded_len`.
+pub fn pad(plaintext: &[u8], padding: Padding) -> Result<crate::Vec<u8>, HpkeError> {
+    let padded_len = padding.padded_len(plaintext.len())?;
+    let mut padded = crate::Vec::with_capacity(padded_len);
+    padded.extend_from_slice(plaintext);
+    padded.push(PADDING_MARKER);
+    padded.resize(padded_len, 0);
+    Ok(padded)
+}
+
+/// Strips the padding that [`pad`] added. Which byte is the marker isn't revealed by timing,
+/// though the length of the result of course is.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)` on success. If `padded` isn't of the form `plaintext || 0x80 || 0x00*`,
+/// returns `Err(HpkeError::OpenError)`.
+pub fn unpad(padded: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+    let (marker_pos, valid) = find_marker(padded);
+    if bool::from(valid) {
+        Ok(padded[..marker_pos as usize].to_vec())
+    } else {
+        Err(HpkeError::OpenError)
+    }
+}
+
+/// Scans all of `padded` from the end for the last nonzero byte. Returns its position, and whether
+/// there is one and it's the marker.
+fn find_marker(padded: &[u8]) -> (u64, Choice) {
+    let mut marker_pos = 0u64;
+    let mut found = Choice::from(0);
+    let mut valid = Choice::from(0);
+    for (i, byte) in padded.iter().enumerate().rev() {
+        let is_first_nonzero = !byte.ct_eq(&0) & !found;
+        marker_pos.conditional_assign(&(i as u64), is_first_nonzero);
+        valid.conditional_assign(&byte.ct_eq(&PADDING_MARKER), is_first_nonzero);
+        found |= is_first_nonzero;
+    }
+    (marker_pos, valid)
+}
+
+/// Pads `plaintext` and does a [`single_shot_seal`] of the result
+///
+/// Return Value
+/// ============
+/// Returns `Ok((encapped_key, ciphertext))` on success. The errors are those of
+/// `Padding::padded_len` and `single_shot_seal`.
+pub fn single_shot_seal_padded<A, Kdf, Kem, R>(
+    padding: Padding,
+    mode: &OpModeS<Kem>,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    csprng: &mut R,
+) -> Result<(Kem::EncappedKey, crate::Vec<u8>), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    let mut padded = pad(plaintext, padding)?;
+    let res = single_shot_seal::<A, Kdf, Kem, R>(mode, pk_recip, info, &padded, aad, csprng);
+    padded.zeroize();
+    res
+}
+
+/// Does a [`single_shot_open`] of a ciphertext made by [`single_shot_seal_padded`], and strips the
+/// padding
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)` on success. If the ciphertext fails to open, or opens to something
+/// that isn't padded, returns `Err(HpkeError::OpenError)`. Otherwise, the errors are those of
+/// `single_shot_open`.
+pub fn single_shot_open_padded<A, Kdf, Kem>(
+    mode: &OpModeR<Kem>,
+    sk_recip: &Kem::PrivateKey,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let mut padded =
+        single_shot_open::<A, Kdf, Kem>(mode, sk_recip, encapped_key, info, ciphertext, aad)?;
+    let res = unpad(&padded);
+    padded.zeroize();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the padded lengths of each scheme, including Padmé's against the paper's examples
+    #[test]
+    fn test_padded_len() {
+        assert_eq!(Padding::Fixed(100).padded_len(0), Ok(100));
+        assert_eq!(Padding::Fixed(100).padded_len(99), Ok(100));
+        assert_eq!(
+            Padding::Fixed(100).padded_len(100),
+            Err(HpkeError::PlaintextTooLong)
+        );
+
+        assert_eq!(Padding::Bucket(16).padded_len(0), Ok(16));
+        assert_eq!(Padding::Bucket(16).padded_len(15), Ok(16));
+        assert_eq!(Padding::Bucket(16).padded_len(16), Ok(32));
+        assert_eq!(
+            Padding::Bucket(0).padded_len(1),
+            Err(HpkeError::ValidationError)
+        );
+        assert_eq!(
+            Padding::Bucket(16).padded_len(usize::MAX),
+            Err(HpkeError::PlaintextTooLong)
+        );
+
+        // The padded length of L, for the lengths L that the marker makes
+        for (len, padded_len) in [
+            (1, 1),
+            (2, 2),
+            (9, 10),
+            (100, 104),
+            (1000, 1024),
+            (9999, 10240),
+        ] {
+            assert_eq!(Padding::Padme.padded_len(len - 1), Ok(padded_len));
+        }
+    }
+
+    /// Tests that unpadding inverts padding, and refuses anything not of the padded form
+    #[test]
+    fn test_pad_unpad() {
+        for padding in [Padding::Fixed(64), Padding::Bucket(7), Padding::Padme] {
+            for len in [0, 1, 6, 7, 40] {
+                let plaintext: crate::Vec<u8> = (1..=len as u8).collect();
+                let padded = pad(&plaintext, padding).unwrap();
+                assert_eq!(padded.len(), padding.padded_len(len).unwrap());
+                assert_eq!(unpad(&padded).unwrap(), plaintext);
+            }
+        }
+
+        // A plaintext that ends in zeros or in the marker keeps them
+        let plaintext = [0x80, 0x00, 0x80, 0x00];
+        assert_eq!(
+            unpad(&pad(&plaintext, Padding::Bucket(8)).unwrap()).unwrap(),
+            plaintext
+        );
+
+        for bad in [&[][..], &[0, 0, 0], &[1, 0x81, 0], &[0x80, 1]] {
+            assert_eq!(unpad(bad), Err(HpkeError::OpenError));
+        }
+    }
+
+    /// Tests that padded ciphertexts of different plaintexts are the same length, and open
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_seal_open_padded() {
+        use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let padding = Padding::Fixed(256);
+
+        let mut lens = crate::Vec::new();
+        for msg in [&b"original PSBT"[..], &[0xab; 200]] {
+            let (enc, ct) = single_shot_seal_padded::<A, Kdf, Kem, _>(
+                padding,
+                &OpModeS::Base,
+                &pk,
+                b"info",
+                msg,
+                b"aad",
+                &mut csprng,
+            )
+            .unwrap();
+            lens.push(ct.len());
+            let pt = single_shot_open_padded::<A, Kdf, Kem>(
+                &OpModeR::Base,
+                &sk,
+                &enc,
+                b"info",
+                &ct,
+                b"aad",
+            )
+            .unwrap();
+            assert_eq!(pt, msg);
+        }
+        assert_eq!(lens, [256 + 16, 256 + 16]);
+    }
+}
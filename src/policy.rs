@@ -0,0 +1,212 @@
+//! Runtime restrictions on which ciphersuites may be used. This is for code that picks a
+//! ciphersuite from algorithm identifiers at runtime, e.g., from a peer's message, and wants the
+//! set of acceptable suites to come from configuration rather than code.
+
+use crate::{aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, HpkeError, Primitive};
+
+/// The algorithm identifiers of a single kind that a [`Policy`] admits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdList<'a> {
+    /// Every identifier is admitted
+    Any,
+    /// Only these identifiers are admitted
+    Allow(&'a [u16]),
+    /// Every identifier except these is admitted
+    Deny(&'a [u16]),
+}
+
+impl IdList<'_> {
+    /// Returns whether `id` is admitted by this list
+    pub fn admits(&self, id: u16) -> bool {
+        match self {
+            IdList::Any => true,
+            IdList::Allow(ids) => ids.contains(&id),
+            IdList::Deny(ids) => !ids.contains(&id),
+        }
+    }
+}
+
+/// A policy on which ciphersuites may be used. A suite is admitted if each of its algorithms is
+/// admitted by the corresponding list, and each has a security level of at least
+/// `min_security_bits`.
+///
+/// Example
+/// =======
+/// A policy that refuses the export-only AEAD and anything weaker than 192 bits:
+/// ```
+/// # use hpke::{aead::{Aead, ExportOnlyAead}, policy::{IdList, Policy}};
+/// let policy = Policy {
+///     aeads: IdList::Deny(&[ExportOnlyAead::AEAD_ID]),
+///     min_security_bits: 192,
+///     ..Policy::PERMISSIVE
+/// };
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Policy<'a> {
+    /// The KEM identifiers this policy admits
+    pub kems: IdList<'a>,
+    /// The KDF identifiers this policy admits
+    pub kdfs: IdList<'a>,
+    /// The AEAD identifiers this policy admits
+    pub aeads: IdList<'a>,
+    /// The minimum security level, in bits, of every algorithm in a suite. The levels are those
+    /// of NIST SP 800-57, e.g., 128 for P-256 and AES-128-GCM. Algorithms this crate doesn't know
+    /// the level of are refused unless this is 0.
+    pub min_security_bits: u16,
+}
+
+impl Policy<'static> {
+    /// The policy that admits every suite
+    pub const PERMISSIVE: Policy<'static> = Policy {
+        kems: IdList::Any,
+        kdfs: IdList::Any,
+        aeads: IdList::Any,
+        min_security_bits: 0,
+    };
+}
+
+impl Policy<'_> {
+    /// Checks the ciphersuite with the given algorithm identifiers against this policy
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(())` if the suite is admitted. Otherwise returns
+    /// `Err(HpkeError::SuiteNotAllowed(p))`, where `p` is the first of the KEM, KDF, and AEAD
+    /// that isn't admitted.
+    pub fn check_ids(&self, kem_id: u16, kdf_id: u16, aead_id: u16) -> Result<(), HpkeError> {
+        let check = |primitive, list: &IdList, id, bits: Option<u16>| {
+            let strong_enough = self.min_security_bits == 0
+                || matches!(bits, Some(bits) if bits >= self.min_security_bits);
+            if list.admits(id) && strong_enough {
+                Ok(())
+            } else {
+                Err(HpkeError::SuiteNotAllowed(primitive))
+            }
+        };
+
+        check(
+            Primitive::Kem,
+            &self.kems,
+            kem_id,
+            kem_security_bits(kem_id),
+        )?;
+        check(
+            Primitive::Kdf,
+            &self.kdfs,
+            kdf_id,
+            kdf_security_bits(kdf_id),
+        )?;
+        check(
+            Primitive::Aead,
+            &self.aeads,
+            aead_id,
+            aead_security_bits(aead_id),
+        )
+    }
+
+    /// Checks the given ciphersuite against this policy. This is
+    /// `check_ids(Kem::KEM_ID, Kdf::KDF_ID, A::AEAD_ID)`.
+    pub fn check<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(&self) -> Result<(), HpkeError> {
+        self.check_ids(Kem::KEM_ID, Kdf::KDF_ID, A::AEAD_ID)
+    }
+}
+
+// NIST SP 800-57 Part 1 §5.6.1: ECC with an n-bit group order has n/2 bits of security, as does
+// X25519
+fn kem_security_bits(kem_id: u16) -> Option<u16> {
+    match kem_id {
+        0x0010 | 0x0020 => Some(128),
+        0x0011 => Some(192),
+        0x0012 => Some(256),
+        _ => None,
+    }
+}
+
+// Used as a KDF, a hash function has security equal to half its output size
+fn kdf_security_bits(kdf_id: u16) -> Option<u16> {
+    match kdf_id {
+        0x0001 => Some(128),
+        0x0002 => Some(192),
+        0x0003 => Some(256),
+        _ => None,
+    }
+}
+
+// The security of an AEAD is its key size
+fn aead_security_bits(aead_id: u16) -> Option<u16> {
+    match aead_id {
+        0x0001 => Some(128),
+        0x0002 | 0x0003 => Some(256),
+        // The truncated-tag AEADs in the `reduced_security` module are limited by their 64-bit tags
+        0xFF01..=0xFF03 => Some(64),
+        // The export-only AEAD encrypts nothing, so it can't weaken a suite. Refusing it is up to
+        // the AEAD list.
+        0xFFFF => Some(u16::MAX),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aead::{AesGcm128, AesGcm256, ExportOnlyAead};
+    use crate::kdf::HkdfSha256;
+
+    /// Tests that allow lists, deny lists, and security levels are each enforced, and that the
+    /// error says which primitive was refused
+    #[test]
+    fn test_policy() {
+        let policy = Policy::PERMISSIVE;
+        assert_eq!(policy.check_ids(0x0020, 0x0001, 0x0003), Ok(()));
+        // Even unknown algorithms are admitted
+        assert_eq!(policy.check_ids(0x9999, 0x9999, 0x9999), Ok(()));
+
+        let policy = Policy {
+            kems: IdList::Allow(&[0x0011, 0x0012]),
+            aeads: IdList::Deny(&[ExportOnlyAead::AEAD_ID]),
+            min_security_bits: 192,
+            ..Policy::PERMISSIVE
+        };
+        assert_eq!(policy.check_ids(0x0011, 0x0002, AesGcm256::AEAD_ID), Ok(()));
+        assert_eq!(
+            policy.check_ids(0x0010, 0x0002, AesGcm256::AEAD_ID),
+            Err(HpkeError::SuiteNotAllowed(Primitive::Kem))
+        );
+        assert_eq!(
+            policy.check_ids(0x0011, HkdfSha256::KDF_ID, AesGcm256::AEAD_ID),
+            Err(HpkeError::SuiteNotAllowed(Primitive::Kdf))
+        );
+        assert_eq!(
+            policy.check_ids(0x0011, 0x0002, AesGcm128::AEAD_ID),
+            Err(HpkeError::SuiteNotAllowed(Primitive::Aead))
+        );
+        assert_eq!(
+            policy.check_ids(0x0011, 0x0002, ExportOnlyAead::AEAD_ID),
+            Err(HpkeError::SuiteNotAllowed(Primitive::Aead))
+        );
+        // Unknown algorithms have no security level, so they're refused
+        assert_eq!(
+            policy.check_ids(0x0011, 0x0002, 0x9999),
+            Err(HpkeError::SuiteNotAllowed(Primitive::Aead))
+        );
+
+        // The typed check agrees with the untyped one
+        #[cfg(feature = "x25519")]
+        {
+            use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha384, kem::X25519HkdfSha256};
+
+            assert_eq!(
+                policy.check::<ChaCha20Poly1305, HkdfSha384, X25519HkdfSha256>(),
+                Err(HpkeError::SuiteNotAllowed(Primitive::Kem))
+            );
+            let policy = Policy {
+                min_security_bits: 128,
+                ..Policy::PERMISSIVE
+            };
+            assert_eq!(
+                policy.check::<ChaCha20Poly1305, HkdfSha256, X25519HkdfSha256>(),
+                Ok(())
+            );
+        }
+    }
+}
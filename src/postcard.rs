@@ -0,0 +1,260 @@
+//! [postcard](https://docs.rs/postcard) encodings of envelopes and suspended contexts, for
+//! embedded links. None of this allocates.
+//!
+//! Encoding writes into a caller-provided buffer, and decoding borrows from its input. The largest
+//! encoding for a given suite and plaintext length is known at compile time, from
+//! [`max_envelope_size`] and [`MAX_SUSPENDED_CTX_ENCODING_SIZE`], so buffers can be arrays:
+//!
+//! ```
+//! # #[cfg(feature = "x25519")] {
+//! use hpke::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+//!
+//! let buf = [0u8; hpke::postcard::max_envelope_size::<
+//!     ChaCha20Poly1305,
+//!     HkdfSha256,
+//!     X25519HkdfSha256,
+//! >(64)];
+//! assert_eq!(buf.len(), 118);
+//! # }
+//! ```
+//!
+//! Formats
+//! =======
+//! An envelope is the postcard encoding of the struct `{ kem_id: u16, kdf_id: u16, aead_id: u16,
+//! key_id: u8, enc: [u8], ciphertext: [u8] }`. That is, the IDs as varints, the key ID as a byte,
+//! and then each byte string as a varint length followed by the bytes. A suspended context is the
+//! postcard encoding of the bytes `suspend` returns, which already name the suite.
+
+use crate::{
+    aead::{Aead, MAX_SUSPENDED_CTX_SIZE},
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    Deserializable, HpkeError, Serializable,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The longest encoding of a suspended context
+pub const MAX_SUSPENDED_CTX_ENCODING_SIZE: usize =
+    varint_size(MAX_SUSPENDED_CTX_SIZE) + MAX_SUSPENDED_CTX_SIZE;
+
+/// The longest encoding of an envelope of a `plaintext_len`-byte message in the given suite
+pub const fn max_envelope_size<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    plaintext_len: usize,
+) -> usize {
+    let ciphertext_len = plaintext_len + A::TAG_SIZE;
+    varint_size(Kem::KEM_ID as usize)
+        + varint_size(Kdf::KDF_ID as usize)
+        + varint_size(A::AEAD_ID as usize)
+        + 1
+        + varint_size(Kem::ENCAPPED_KEY_SIZE)
+        + Kem::ENCAPPED_KEY_SIZE
+        + varint_size(ciphertext_len)
+        + ciphertext_len
+}
+
+/// The number of bytes postcard's varint encoding of `n` takes
+const fn varint_size(mut n: usize) -> usize {
+    let mut size = 1;
+    while n >= 0x80 {
+        n >>= 7;
+        size += 1;
+    }
+    size
+}
+
+/// The wire form of an envelope
+#[derive(Serialize, Deserialize)]
+struct Envelope<'a> {
+    kem_id: u16,
+    kdf_id: u16,
+    aead_id: u16,
+    key_id: u8,
+    enc: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+/// Encodes the output of a `single_shot_seal` with the given suite, to the key with ID `key_id`,
+/// into `out`
+///
+/// Return Value
+/// ============
+/// Returns `Ok(encoding)`, the prefix of `out` that was written, on success. If `out` is too
+/// short, returns `Err(HpkeError::IncorrectInputLength(needed, out.len()))`.
+pub fn encode_envelope<'o, A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    key_id: u8,
+    encapped_key: &Kem::EncappedKey,
+    ciphertext: &[u8],
+    out: &'o mut [u8],
+) -> Result<&'o mut [u8], HpkeError> {
+    let enc = encapped_key.to_bytes();
+    let envelope = Envelope {
+        kem_id: Kem::KEM_ID,
+        kdf_id: Kdf::KDF_ID,
+        aead_id: A::AEAD_ID,
+        key_id,
+        enc: &enc,
+        ciphertext,
+    };
+    let needed = varint_size(Kem::KEM_ID.into())
+        + varint_size(Kdf::KDF_ID.into())
+        + varint_size(A::AEAD_ID.into())
+        + 1
+        + varint_size(enc.len())
+        + enc.len()
+        + varint_size(ciphertext.len())
+        + ciphertext.len();
+    encode(&envelope, needed, out)
+}
+
+/// Decodes an envelope encoded by [`encode_envelope`]
+///
+/// Return Value
+/// ============
+/// Returns `Ok((key_id, encapped_key, ciphertext))` on success. If `bytes` isn't an encoding of an
+/// envelope, has anything after it, or the envelope is for a different suite, returns
+/// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+/// `Kem::EncappedKey::from_bytes`.
+pub fn decode_envelope<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    bytes: &[u8],
+) -> Result<(u8, Kem::EncappedKey, &[u8]), HpkeError> {
+    let envelope: Envelope = decode(bytes)?;
+    if (envelope.kem_id, envelope.kdf_id, envelope.aead_id)
+        != (Kem::KEM_ID, Kdf::KDF_ID, A::AEAD_ID)
+    {
+        return Err(HpkeError::ValidationError);
+    }
+    let encapped_key = Kem::EncappedKey::from_bytes(envelope.enc)?;
+    Ok((envelope.key_id, encapped_key, envelope.ciphertext))
+}
+
+/// Encodes a context that `AeadCtxS::suspend` or `AeadCtxR::suspend` returned into `out`. This
+/// is as secret as the context itself.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(encoding)`, the prefix of `out` that was written, on success. If `out` is too
+/// short, returns `Err(HpkeError::IncorrectInputLength(needed, out.len()))`.
+pub fn encode_suspended_ctx<'o>(
+    suspended: &[u8],
+    out: &'o mut [u8],
+) -> Result<&'o mut [u8], HpkeError> {
+    let needed = varint_size(suspended.len()) + suspended.len();
+    encode(&suspended, needed, out)
+}
+
+/// Decodes a suspended context encoded by [`encode_suspended_ctx`], to pass to `resume`
+///
+/// Return Value
+/// ============
+/// Returns `Ok(suspended)` on success. If `bytes` isn't an encoding of at most
+/// `MAX_SUSPENDED_CTX_SIZE` bytes, or has anything after it, returns
+/// `Err(HpkeError::ValidationError)`.
+pub fn decode_suspended_ctx(bytes: &[u8]) -> Result<&[u8], HpkeError> {
+    let suspended: &[u8] = decode(bytes)?;
+    if suspended.len() > MAX_SUSPENDED_CTX_SIZE {
+        return Err(HpkeError::ValidationError);
+    }
+    Ok(suspended)
+}
+
+/// Encodes `value`, whose encoding is `needed` bytes long, into `out`
+fn encode<'o, T: Serialize>(
+    value: &T,
+    needed: usize,
+    out: &'o mut [u8],
+) -> Result<&'o mut [u8], HpkeError> {
+    if out.len() < needed {
+        return Err(HpkeError::IncorrectInputLength(needed, out.len()));
+    }
+    // The buffer is long enough, and serializing to a slice can't fail otherwise
+    Ok(::postcard::to_slice(value, out).expect("postcard encoding was longer than computed"))
+}
+
+/// Decodes a `T` that takes up all of `bytes`
+fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, HpkeError> {
+    match ::postcard::take_from_bytes(bytes) {
+        Ok((value, [])) => Ok(value),
+        _ => Err(HpkeError::ValidationError),
+    }
+}
+
+#[cfg(all(test, feature = "x25519", any(feature = "alloc", feature = "std")))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::{AeadCtxR, ChaCha20Poly1305},
+        kdf::HkdfSha256,
+        kem::X25519HkdfSha256,
+        setup_sender, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests envelope round trips into buffers of exactly the maximum size, and that short buffers,
+    /// trailing bytes, and other suites are refused
+    #[test]
+    fn test_postcard_envelope() {
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk) = Kem::gen_keypair(&mut csprng);
+        let (_, enc) = Kem::encap(&pk, None, &mut csprng).unwrap();
+
+        for pt_len in [0, 100, 200] {
+            let ct = vec![0xc7; pt_len + 16];
+            let mut buf = vec![0u8; max_envelope_size::<A, Kdf, Kem>(pt_len)];
+            let encoded_len = encode_envelope::<A, Kdf, Kem>(9, &enc, &ct, &mut buf)
+                .unwrap()
+                .len();
+            assert_eq!(encoded_len, buf.len());
+            let (key_id, decoded_enc, decoded_ct) = decode_envelope::<A, Kdf, Kem>(&buf).unwrap();
+            assert_eq!((key_id, decoded_ct), (9, &ct[..]));
+            assert_eq!(decoded_enc.to_bytes(), enc.to_bytes());
+
+            let needed = buf.len();
+            assert_eq!(
+                encode_envelope::<A, Kdf, Kem>(9, &enc, &ct, &mut buf[..needed - 1]).err(),
+                Some(HpkeError::IncorrectInputLength(needed, needed - 1))
+            );
+        }
+
+        let mut buf = [0u8; max_envelope_size::<A, Kdf, Kem>(4)];
+        encode_envelope::<A, Kdf, Kem>(9, &enc, &[0; 20], &mut buf).unwrap();
+        // [0x20, 0x01, 0x03, 0x09, 0x20, <32 bytes>, 0x14, <20 bytes>]
+        assert_eq!(buf[..5], [0x20, 0x01, 0x03, 0x09, 0x20]);
+        assert!(decode_envelope::<crate::aead::AesGcm128, Kdf, Kem>(&buf).is_err());
+        let mut trailing = buf.to_vec();
+        trailing.push(0);
+        assert!(decode_envelope::<A, Kdf, Kem>(&trailing).is_err());
+    }
+
+    /// Tests that a suspended context survives a round trip and resumes
+    #[test]
+    fn test_postcard_suspended_ctx() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let (enc, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk, b"info", &mut csprng).unwrap();
+        let receiver_ctx =
+            crate::setup_receiver::<A, Kdf, Kem>(&crate::OpModeR::Base, &sk, &enc, b"info")
+                .unwrap();
+
+        let mut buf = [0u8; MAX_SUSPENDED_CTX_ENCODING_SIZE];
+        let encoded = encode_suspended_ctx(&receiver_ctx.suspend(), &mut buf).unwrap();
+        let suspended = decode_suspended_ctx(encoded).unwrap();
+        let mut resumed = AeadCtxR::<A, Kdf, Kem>::resume(suspended).unwrap();
+        let ct = sender_ctx.seal(b"after sleep", b"").unwrap();
+        assert_eq!(resumed.open(&ct, b"").unwrap(), b"after sleep");
+
+        // Too long to be a suspended context
+        let mut buf = [0u8; 130];
+        let encoded = encode_suspended_ctx(&[0; 128], &mut buf).unwrap();
+        assert_eq!(
+            decode_suspended_ctx(encoded),
+            Err(HpkeError::ValidationError)
+        );
+    }
+}
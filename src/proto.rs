@@ -0,0 +1,285 @@
+//! Protocol Buffers messages for envelopes, key configs, and fanout entries, so services that
+//! exchange HPKE messages, e.g., over gRPC, share one schema.
+//!
+//! The messages are those of `proto/hpke.proto`, in package `hpke.v1`, written out as [`prost`]
+//! types so that building this crate doesn't need `protoc`. Other languages can generate code from
+//! the `.proto` file. Each message converts to and from the native type it carries. Conversions
+//! check the algorithm IDs against the expected suite, and return `HpkeError::ValidationError` if
+//! they differ or a key ID doesn't fit in a byte.
+//!
+//! Encode and decode with [`prost::Message`], e.g., `envelope.encode_to_vec()` and
+//! `Envelope::decode(bytes)`.
+
+use crate::{
+    aead::Aead, fanout, kdf::Kdf as KdfTrait, kem::Kem as KemTrait,
+    keyring::KeyConfig as NativeKeyConfig, Deserializable, HpkeError, Serializable,
+};
+
+/// A message sealed with a single-shot seal, and the suite and key it was sealed to
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Envelope {
+    /// The KEM's RFC 9180 ID
+    #[prost(uint32, tag = "1")]
+    pub kem_id: u32,
+    /// The KDF's RFC 9180 ID
+    #[prost(uint32, tag = "2")]
+    pub kdf_id: u32,
+    /// The AEAD's RFC 9180 ID
+    #[prost(uint32, tag = "3")]
+    pub aead_id: u32,
+    /// The ID of the recipient key the message was sealed to
+    #[prost(uint32, tag = "4")]
+    pub key_id: u32,
+    /// The encapsulated key
+    #[prost(bytes = "vec", tag = "5")]
+    pub enc: crate::Vec<u8>,
+    /// The ciphertext
+    #[prost(bytes = "vec", tag = "6")]
+    pub ciphertext: crate::Vec<u8>,
+}
+
+impl Envelope {
+    /// Makes an envelope for the output of a `single_shot_seal` with the given suite, to the key
+    /// with ID `key_id`
+    pub fn new<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+        key_id: u8,
+        encapped_key: &Kem::EncappedKey,
+        ciphertext: crate::Vec<u8>,
+    ) -> Envelope {
+        Envelope {
+            kem_id: Kem::KEM_ID.into(),
+            kdf_id: Kdf::KDF_ID.into(),
+            aead_id: A::AEAD_ID.into(),
+            key_id: key_id.into(),
+            enc: encapped_key.to_bytes().to_vec(),
+            ciphertext,
+        }
+    }
+
+    /// Returns the key ID, encapsulated key, and ciphertext, to pass to, e.g.,
+    /// `RecipientKeyRing::open`
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok((key_id, encapped_key, ciphertext))` on success. If the envelope is for a
+    /// different suite, or its key ID doesn't fit in a byte, returns
+    /// `Err(HpkeError::ValidationError)`. Otherwise, the errors are those of
+    /// `Kem::EncappedKey::from_bytes`.
+    pub fn parts<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+        &self,
+    ) -> Result<(u8, Kem::EncappedKey, &[u8]), HpkeError> {
+        check_kem_id::<Kem>(self.kem_id)?;
+        if self.kdf_id != u32::from(Kdf::KDF_ID) || self.aead_id != u32::from(A::AEAD_ID) {
+            return Err(HpkeError::ValidationError);
+        }
+        let key_id = key_id_from_proto(self.key_id)?;
+        let encapped_key = Kem::EncappedKey::from_bytes(&self.enc)?;
+        Ok((key_id, encapped_key, &self.ciphertext))
+    }
+}
+
+/// A published recipient config. This is the wire form of
+/// [`keyring::KeyConfig`](crate::keyring::KeyConfig).
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyConfig {
+    /// The ID senders send along with the encapsulated key
+    #[prost(uint32, tag = "1")]
+    pub key_id: u32,
+    /// The KEM's RFC 9180 ID
+    #[prost(uint32, tag = "2")]
+    pub kem_id: u32,
+    /// The KDF's RFC 9180 ID
+    #[prost(uint32, tag = "3")]
+    pub kdf_id: u32,
+    /// The AEAD's RFC 9180 ID
+    #[prost(uint32, tag = "4")]
+    pub aead_id: u32,
+    /// The recipient's public key
+    #[prost(bytes = "vec", tag = "5")]
+    pub public_key: crate::Vec<u8>,
+    /// The time after which senders must stop using this config
+    #[prost(uint64, tag = "6")]
+    pub not_after: u64,
+}
+
+impl<Kem: KemTrait> From<&NativeKeyConfig<Kem>> for KeyConfig {
+    fn from(config: &NativeKeyConfig<Kem>) -> KeyConfig {
+        KeyConfig {
+            key_id: config.key_id.into(),
+            kem_id: Kem::KEM_ID.into(),
+            kdf_id: config.kdf_id.into(),
+            aead_id: config.aead_id.into(),
+            public_key: config.pk.to_bytes().to_vec(),
+            not_after: config.not_after,
+        }
+    }
+}
+
+/// Converts a config of any suite of `Kem`. The KDF and AEAD IDs are carried over as is.
+impl<Kem: KemTrait> TryFrom<&KeyConfig> for NativeKeyConfig<Kem> {
+    type Error = HpkeError;
+
+    fn try_from(config: &KeyConfig) -> Result<NativeKeyConfig<Kem>, HpkeError> {
+        check_kem_id::<Kem>(config.kem_id)?;
+        Ok(NativeKeyConfig {
+            key_id: key_id_from_proto(config.key_id)?,
+            kdf_id: u16::try_from(config.kdf_id).map_err(|_| HpkeError::ValidationError)?,
+            aead_id: u16::try_from(config.aead_id).map_err(|_| HpkeError::ValidationError)?,
+            pk: Kem::PublicKey::from_bytes(&config.public_key)?,
+            not_after: config.not_after,
+        })
+    }
+}
+
+/// One recipient's copy of a secret sealed to a group. This is the wire form of
+/// [`fanout::FanoutEntry`].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FanoutEntry {
+    /// The recipient's ID
+    #[prost(bytes = "vec", tag = "1")]
+    pub recipient_id: crate::Vec<u8>,
+    /// The encapsulated key
+    #[prost(bytes = "vec", tag = "2")]
+    pub enc: crate::Vec<u8>,
+    /// The sealed secret
+    #[prost(bytes = "vec", tag = "3")]
+    pub ciphertext: crate::Vec<u8>,
+}
+
+impl<Kem: KemTrait> From<&fanout::FanoutEntry<Kem>> for FanoutEntry {
+    fn from(entry: &fanout::FanoutEntry<Kem>) -> FanoutEntry {
+        FanoutEntry {
+            recipient_id: entry.recipient_id.clone(),
+            enc: entry.encapped_key.to_bytes().to_vec(),
+            ciphertext: entry.ciphertext.clone(),
+        }
+    }
+}
+
+impl<Kem: KemTrait> TryFrom<&FanoutEntry> for fanout::FanoutEntry<Kem> {
+    type Error = HpkeError;
+
+    fn try_from(entry: &FanoutEntry) -> Result<fanout::FanoutEntry<Kem>, HpkeError> {
+        Ok(fanout::FanoutEntry {
+            recipient_id: entry.recipient_id.clone(),
+            encapped_key: Kem::EncappedKey::from_bytes(&entry.enc)?,
+            ciphertext: entry.ciphertext.clone(),
+        })
+    }
+}
+
+/// Every recipient's copy of a secret sealed to a group, as `fanout_seal` returns them
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Fanout {
+    /// The copies
+    #[prost(message, repeated, tag = "1")]
+    pub entries: crate::Vec<FanoutEntry>,
+}
+
+impl Fanout {
+    /// Makes a message out of the entries `fanout_seal` returns
+    pub fn new<Kem: KemTrait>(entries: &[fanout::FanoutEntry<Kem>]) -> Fanout {
+        Fanout {
+            entries: entries.iter().map(FanoutEntry::from).collect(),
+        }
+    }
+
+    /// Returns the entries, to pass to `fanout_open`
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(entries)` on success. If an encapsulated key is malformed, returns the error of
+    /// `Kem::EncappedKey::from_bytes`.
+    pub fn to_entries<Kem: KemTrait>(
+        &self,
+    ) -> Result<crate::Vec<fanout::FanoutEntry<Kem>>, HpkeError> {
+        self.entries.iter().map(TryFrom::try_from).collect()
+    }
+}
+
+/// Checks that `kem_id` is `Kem`'s
+fn check_kem_id<Kem: KemTrait>(kem_id: u32) -> Result<(), HpkeError> {
+    if kem_id != u32::from(Kem::KEM_ID) {
+        return Err(HpkeError::ValidationError);
+    }
+    Ok(())
+}
+
+/// Narrows a key ID to the byte it must fit in
+fn key_id_from_proto(key_id: u32) -> Result<u8, HpkeError> {
+    u8::try_from(key_id).map_err(|_| HpkeError::ValidationError)
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305, fanout::fanout_seal, kdf::HkdfSha256, kem::X25519HkdfSha256,
+        single_shot_open, single_shot_seal, OpModeR, OpModeS,
+    };
+
+    use prost::Message;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests round trips of every message through its wire encoding and back to the native type,
+    /// and that envelopes and configs of other suites are refused
+    #[test]
+    fn test_proto_round_trips() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+
+        let (enc, ct) = single_shot_seal::<A, Kdf, Kem, _>(
+            &OpModeS::Base,
+            &pk,
+            b"info",
+            b"msg",
+            b"",
+            &mut csprng,
+        )
+        .unwrap();
+        let envelope = Envelope::new::<A, Kdf, Kem>(7, &enc, ct);
+        let decoded = Envelope::decode(envelope.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, envelope);
+        let (key_id, enc, ct) = decoded.parts::<A, Kdf, Kem>().unwrap();
+        assert_eq!(key_id, 7);
+        let pt = single_shot_open::<A, Kdf, Kem>(&OpModeR::Base, &sk, &enc, b"info", ct, b"");
+        assert_eq!(pt.unwrap(), b"msg");
+        let mut other_suite = decoded.clone();
+        other_suite.aead_id = 1;
+        assert_eq!(
+            other_suite.parts::<A, Kdf, Kem>().err(),
+            Some(HpkeError::ValidationError)
+        );
+
+        let config = NativeKeyConfig::<Kem>::new::<A, Kdf>(3, pk.clone(), 1000);
+        let msg = KeyConfig::decode(KeyConfig::from(&config).encode_to_vec().as_slice()).unwrap();
+        let native = NativeKeyConfig::<Kem>::try_from(&msg).unwrap();
+        assert!(native.is_suite::<A, Kdf>());
+        assert_eq!((native.key_id, native.not_after), (3, 1000));
+        assert_eq!(native.pk, pk);
+        for bad in [
+            KeyConfig {
+                kem_id: 0x10,
+                ..msg.clone()
+            },
+            KeyConfig { key_id: 256, ..msg },
+        ] {
+            assert_eq!(
+                NativeKeyConfig::<Kem>::try_from(&bad).err(),
+                Some(HpkeError::ValidationError)
+            );
+        }
+
+        let entries =
+            fanout_seal::<A, Kdf, Kem, _>(&[(b"alice", &pk)], b"group", b"secret", &mut csprng)
+                .unwrap();
+        let msg = Fanout::decode(Fanout::new(&entries).encode_to_vec().as_slice()).unwrap();
+        let decoded = msg.to_entries::<Kem>().unwrap();
+        let opened = fanout::fanout_open::<A, Kdf, Kem>(&decoded, b"alice", &sk, b"group");
+        assert_eq!(opened.unwrap(), b"secret");
+    }
+}
@@ -0,0 +1,187 @@
+//! Moving secrets between HPKE and other protocols as PSKs, for channel binding.
+//!
+//! [`ImportedPsk::import`] turns a secret exported from another protocol, e.g., a TLS exporter
+//! value, into a PSK and PSK ID for HPKE's PSK modes, so an HPKE context is bound to the session
+//! the secret came from. Going the other way, `AeadCtxS::export_psk` and `AeadCtxR::export_psk`
+//! export a secret for another protocol to use as its PSK.
+//!
+//! Both directions label the secret with the protocol on the other side, so a secret imported
+//! from, or exported for, one protocol can't be passed off as one for another.
+//!
+//! Derivation
+//! ==========
+//! With `suite_id = concat("PSK", I2OSP(kdf_id, 2))` and `source_info = concat(I2OSP(len(source),
+//! 2), source, context)`, an imported PSK is
+//!
+//! ```text
+//! prk = LabeledExtract("", "imported_psk", external_secret)
+//! psk = LabeledExpand(prk, "psk", source_info, 32)
+//! psk_id = LabeledExpand(prk, "psk_id", source_info, 32)
+//! ```
+//!
+//! An exported PSK is `export_labeled(PSK_EXPORT_DOMAIN, target, context, 32)`.
+
+use crate::{
+    kdf::{labeled_extract, Kdf as KdfTrait, LabeledExpand},
+    HpkeError, PskBundle,
+};
+
+use zeroize::Zeroize;
+
+/// The length of imported and exported PSKs, and of imported PSK IDs
+pub const PSK_LEN: usize = 32;
+
+/// The `domain` that `export_psk` passes to `export_labeled`
+pub const PSK_EXPORT_DOMAIN: &[u8] = b"hpke-psk-export";
+
+/// A PSK and PSK ID derived from another protocol's secret. Use [`ImportedPsk::bundle`] to set up
+/// a context with it.
+pub struct ImportedPsk {
+    psk: [u8; PSK_LEN],
+    psk_id: [u8; PSK_LEN],
+}
+
+// The PSK is secret
+impl Drop for ImportedPsk {
+    fn drop(&mut self) {
+        self.psk.zeroize();
+    }
+}
+
+impl ImportedPsk {
+    /// Derives a PSK and PSK ID from `external_secret`, which came from the protocol named
+    /// `source`, e.g., `b"tls13-exporter"`. `context` is anything else both sides agree on, e.g.,
+    /// the exporter label the secret was made with.
+    ///
+    /// The PSK ID depends on the secret, so both sides get the same one without sending it, and
+    /// it reveals nothing about the secret.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(imported)` on success. If `external_secret` is shorter than `PSK_LEN` bytes,
+    /// returns `Err(HpkeError::InvalidPsk)`. If `source` is longer than 65535 bytes, returns
+    /// `Err(HpkeError::ValidationError)`.
+    pub fn import<Kdf: KdfTrait>(
+        external_secret: &[u8],
+        source: &[u8],
+        context: &[u8],
+    ) -> Result<ImportedPsk, HpkeError> {
+        if external_secret.len() < PSK_LEN {
+            return Err(HpkeError::InvalidPsk);
+        }
+        let source_len = u16::try_from(source.len()).map_err(|_| HpkeError::ValidationError)?;
+
+        let mut suite_id = *b"PSK\0\0";
+        suite_id[3..].copy_from_slice(&Kdf::KDF_ID.to_be_bytes());
+        let (_, prk) = labeled_extract::<Kdf>(&[], &suite_id, b"imported_psk", external_secret);
+
+        let source_info = [&source_len.to_be_bytes()[..], source, context];
+        let mut imported = ImportedPsk {
+            psk: [0u8; PSK_LEN],
+            psk_id: [0u8; PSK_LEN],
+        };
+        // PSK_LEN is less than 255 times any digest size, so these can't fail
+        prk.labeled_expand_multi(&suite_id, b"psk", &source_info, &mut imported.psk)
+            .expect("PSK_LEN is a valid HKDF output length");
+        prk.labeled_expand_multi(&suite_id, b"psk_id", &source_info, &mut imported.psk_id)
+            .expect("PSK_LEN is a valid HKDF output length");
+        Ok(imported)
+    }
+
+    /// Returns the PSK bundle to pass to `OpModeS::Psk` or `OpModeR::Psk`
+    pub fn bundle(&self) -> PskBundle<'_> {
+        PskBundle {
+            psk: &self.psk,
+            psk_id: &self.psk_id,
+        }
+    }
+}
+
+/// A secret exported from an HPKE context for use as another protocol's PSK
+pub struct ExportedPsk(pub(crate) [u8; PSK_LEN]);
+
+// The PSK is secret
+impl Drop for ExportedPsk {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl ExportedPsk {
+    /// Returns the PSK
+    pub fn as_bytes(&self) -> &[u8; PSK_LEN] {
+        &self.0
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305,
+        kdf::{HkdfSha256, HkdfSha384},
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+        setup_receiver, setup_sender, OpModeR, OpModeS,
+    };
+
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests that both sides of a channel binding import the same PSK and can use it, that the
+    /// source and KDF separate imports, and that exports match on both sides and differ by target
+    #[test]
+    fn test_import_export_psk() {
+        let mut csprng = StdRng::from_entropy();
+        let mut tls_exporter = [0u8; 32];
+        csprng.fill_bytes(&mut tls_exporter);
+
+        let sender_psk = ImportedPsk::import::<Kdf>(&tls_exporter, b"tls13", b"ctx").unwrap();
+        let recip_psk = ImportedPsk::import::<Kdf>(&tls_exporter, b"tls13", b"ctx").unwrap();
+        for other in [
+            ImportedPsk::import::<Kdf>(&tls_exporter, b"tls1", b"3ctx").unwrap(),
+            ImportedPsk::import::<HkdfSha384>(&tls_exporter, b"tls13", b"ctx").unwrap(),
+        ] {
+            assert_ne!(other.psk, sender_psk.psk);
+            assert_ne!(other.psk_id, sender_psk.psk_id);
+        }
+        assert_eq!(
+            ImportedPsk::import::<Kdf>(&tls_exporter[..31], b"tls13", b"ctx").err(),
+            Some(HpkeError::InvalidPsk)
+        );
+
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (enc, mut sender_ctx) = setup_sender::<A, Kdf, Kem, _>(
+            &OpModeS::Psk(sender_psk.bundle()),
+            &pk_recip,
+            b"info",
+            &mut csprng,
+        )
+        .unwrap();
+        let mut receiver_ctx = setup_receiver::<A, Kdf, Kem>(
+            &OpModeR::Psk(recip_psk.bundle()),
+            &sk_recip,
+            &enc,
+            b"info",
+        )
+        .unwrap();
+        let mut msg = *b"bound";
+        let tag = sender_ctx.seal_in_place_detached(&mut msg, b"").unwrap();
+        receiver_ctx
+            .open_in_place_detached(&mut msg, b"", &tag)
+            .unwrap();
+        assert_eq!(&msg, b"bound");
+
+        let exported = sender_ctx.export_psk(b"noise", b"").unwrap();
+        assert_eq!(
+            exported.as_bytes(),
+            receiver_ctx.export_psk(b"noise", b"").unwrap().as_bytes()
+        );
+        assert_ne!(
+            exported.as_bytes(),
+            sender_ctx.export_psk(b"wireguard", b"").unwrap().as_bytes()
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! AEADs with 64-bit tags, and a one-byte envelope header, for LPWAN-class links where the usual
+//! 16 bytes of tag per packet don't fit.
+//!
+//! **These are weaker than the suites in RFC 9180.** Each forged packet that a recipient tries to
+//! open succeeds with probability about 2^-64, rather than 2^-128, so an attacker who can make
+//! 2^32 attempts succeeds with probability about 2^-32. Keys and confidentiality are unaffected.
+//! A recipient should stop using a context, or slow down, after a number of failed opens.
+//!
+//! The AEAD identifiers aren't registered with IANA, and nothing but this crate speaks them. Each
+//! is the identifier of its full-tag counterpart with the high byte set, so the key schedule of a
+//! suite with a truncated tag never derives the keys of the suite without.
+//!
+//! Formats
+//! =======
+//! A compact envelope is `header || enc || ciphertext`, where `header` is one byte:
+//! `key_id << 2 | (AEAD_ID & 0x03)`. The key ID is at most [`MAX_KEY_ID`]. The two low bits say
+//! which of the three AEADs here sealed the envelope, so that a misconfigured recipient fails
+//! cleanly instead of failing to open every packet. The KEM and KDF aren't in the header, since
+//! both ends of a link like this are configured with a single suite. Unless it's sealed with
+//! `bind_header` false, the header is prepended to the AAD, so a changed key ID fails to open.
+
+use crate::{
+    aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, single_shot_open_in_place,
+    single_shot_seal_in_place, Deserializable, HpkeError, OpModeR, OpModeS, Serializable,
+};
+
+use aead::{
+    consts::{U0, U8},
+    AeadCore as BaseAeadCore, AeadInPlace as BaseAeadInPlace, KeyInit as BaseKeyInit,
+    KeySizeUser as BaseKeySizeUser,
+};
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, RngCore};
+use subtle::ConstantTimeEq;
+
+/// The size of a truncated tag, in bytes
+const TRUNCATED_TAG_SIZE: usize = 8;
+
+/// The size of a compact envelope's header, in bytes
+pub const HEADER_SIZE: usize = 1;
+
+/// The largest key ID that fits in a compact envelope's header
+pub const MAX_KEY_ID: u8 = 0x3F;
+
+/// The longest `aad` that a compact envelope with its header bound can be sealed or opened with
+pub const MAX_BOUND_AAD_LEN: usize = 255;
+
+// Nobody outside this crate can mark an AEAD as having a truncated tag
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An AEAD whose tags are truncated to 64 bits. See the [module docs](self) for what that costs.
+pub trait TruncatedTagAead: Aead + sealed::Sealed {
+    /// The length of a tag, in bits. A forged packet is opened with probability about
+    /// `2^-TAG_BITS`.
+    const TAG_BITS: u32 = (Self::TAG_SIZE * 8) as u32;
+}
+
+/// AES-128-GCM with 64-bit tags. Forgery resistance is 64 bits per attempt, not 128.
+pub struct AesGcm128Tag64;
+
+impl Aead for AesGcm128Tag64 {
+    #[doc(hidden)]
+    type AeadImpl = TruncatedTagImpl<aes_gcm::Aes128Gcm>;
+
+    // Unregistered: AES-128-GCM's 0x0001 with the high byte set
+    const AEAD_ID: u16 = 0xFF01;
+
+    // NIST SP 800-38D Appendix C: with 64-bit tags, a packet's ciphertext and AAD together should
+    // be at most 2^15 bytes
+    const MAX_PLAINTEXT_LEN: u64 = 1 << 14;
+    const MAX_AAD_LEN: u64 = 1 << 14;
+}
+
+/// AES-256-GCM with 64-bit tags. Forgery resistance is 64 bits per attempt, not 128.
+pub struct AesGcm256Tag64;
+
+impl Aead for AesGcm256Tag64 {
+    #[doc(hidden)]
+    type AeadImpl = TruncatedTagImpl<aes_gcm::Aes256Gcm>;
+
+    // Unregistered: AES-256-GCM's 0x0002 with the high byte set
+    const AEAD_ID: u16 = 0xFF02;
+
+    // Same as AesGcm128Tag64
+    const MAX_PLAINTEXT_LEN: u64 = 1 << 14;
+    const MAX_AAD_LEN: u64 = 1 << 14;
+}
+
+/// ChaCha20-Poly1305 with 64-bit tags. Forgery resistance is 64 bits per attempt, not 128.
+pub struct ChaCha20Poly1305Tag64;
+
+impl Aead for ChaCha20Poly1305Tag64 {
+    #[doc(hidden)]
+    type AeadImpl = TruncatedTagImpl<chacha20poly1305::ChaCha20Poly1305>;
+
+    // Unregistered: ChaCha20Poly1305's 0x0003 with the high byte set
+    const AEAD_ID: u16 = 0xFF03;
+
+    // Truncating the tag doesn't change how much Poly1305 can authenticate
+    const MAX_PLAINTEXT_LEN: u64 = crate::aead::ChaCha20Poly1305::MAX_PLAINTEXT_LEN;
+    const MAX_AAD_LEN: u64 = crate::aead::ChaCha20Poly1305::MAX_AAD_LEN;
+}
+
+impl sealed::Sealed for AesGcm128Tag64 {}
+impl TruncatedTagAead for AesGcm128Tag64 {}
+impl sealed::Sealed for AesGcm256Tag64 {}
+impl TruncatedTagAead for AesGcm256Tag64 {}
+impl sealed::Sealed for ChaCha20Poly1305Tag64 {}
+impl TruncatedTagAead for ChaCha20Poly1305Tag64 {}
+
+/// The underlying AEAD of the truncated-tag AEADs. This is the full-tag AEAD `S`, whose tags are
+/// cut to their first 8 bytes.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct TruncatedTagImpl<S>(S);
+
+impl<S: BaseKeySizeUser> BaseKeySizeUser for TruncatedTagImpl<S> {
+    type KeySize = S::KeySize;
+}
+
+impl<S: BaseKeyInit> BaseKeyInit for TruncatedTagImpl<S> {
+    fn new(key: &aead::Key<Self>) -> Self {
+        TruncatedTagImpl(S::new(key))
+    }
+}
+
+impl<S: BaseAeadCore> BaseAeadCore for TruncatedTagImpl<S> {
+    type NonceSize = S::NonceSize;
+    type TagSize = U8;
+    type CiphertextOverhead = U0;
+}
+
+impl<S: BaseAeadInPlace> BaseAeadInPlace for TruncatedTagImpl<S> {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<aead::Tag<Self>, aead::Error> {
+        let tag = self.0.encrypt_in_place_detached(nonce, aad, buf)?;
+        Ok(GenericArray::clone_from_slice(&tag[..TRUNCATED_TAG_SIZE]))
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &aead::Tag<Self>,
+    ) -> Result<(), aead::Error> {
+        // The underlying AEADs can only check a full tag. But both encrypt by XORing a keystream,
+        // and tag the ciphertext, so encrypting decrypts, and encrypting what that gives back
+        // restores the ciphertext and recomputes its full tag. If the tag is wrong, the buffer is
+        // left holding the ciphertext, as it would be for a full-tag AEAD.
+        self.0.encrypt_in_place_detached(nonce, aad, buf)?;
+        let full_tag = self.0.encrypt_in_place_detached(nonce, aad, buf)?;
+        if bool::from(full_tag[..TRUNCATED_TAG_SIZE].ct_eq(tag)) {
+            self.0.encrypt_in_place_detached(nonce, aad, buf)?;
+            Ok(())
+        } else {
+            Err(aead::Error)
+        }
+    }
+}
+
+/// Seals `plaintext` to `pk_recip`, in the Base mode, into a compact envelope in `out`, in the
+/// format in the [module docs](self). The envelope is `HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE +
+/// plaintext.len() + A::TAG_SIZE` bytes long. If `bind_header` is true, the header is prepended
+/// to `aad`. It should be true unless the recipient doesn't expect that.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(len)`, the length of the envelope, on success. If `key_id` is greater than
+/// `MAX_KEY_ID`, returns `Err(HpkeError::ValidationError)`. If `out` is too short, returns
+/// `Err(HpkeError::IncorrectInputLength(needed, out.len()))`. If `bind_header` is true and `aad`
+/// is longer than `MAX_BOUND_AAD_LEN`, returns `Err(HpkeError::InputTooLong)`. Otherwise, the
+/// errors are those of `single_shot_seal_in_place`.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_compact<A, Kdf, Kem, R>(
+    key_id: u8,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    bind_header: bool,
+    out: &mut [u8],
+    csprng: &mut R,
+) -> Result<usize, HpkeError>
+where
+    A: TruncatedTagAead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    if key_id > MAX_KEY_ID {
+        return Err(HpkeError::ValidationError);
+    }
+    let ct_start = HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE;
+    let needed = ct_start + plaintext.len() + A::TAG_SIZE;
+    if out.len() < needed {
+        return Err(HpkeError::IncorrectInputLength(needed, out.len()));
+    }
+
+    let header = header_byte::<A>(key_id);
+    let mut bound_aad = [0u8; HEADER_SIZE + MAX_BOUND_AAD_LEN];
+    let aad = with_header(header, aad, bind_header, &mut bound_aad)?;
+
+    out[ct_start..ct_start + plaintext.len()].copy_from_slice(plaintext);
+    let (encapped_key, _) = single_shot_seal_in_place::<A, Kdf, Kem, R>(
+        &OpModeS::Base,
+        pk_recip,
+        info,
+        &mut out[ct_start..needed],
+        plaintext.len(),
+        aad,
+        csprng,
+    )?;
+    out[0] = header;
+    encapped_key.write_exact(&mut out[HEADER_SIZE..ct_start]);
+    Ok(needed)
+}
+
+/// Returns the key ID in the header of the compact envelope `envelope`, so the recipient can pick
+/// the private key to open it with
+///
+/// Return Value
+/// ============
+/// Returns `Ok(key_id)` on success. If `envelope` is empty, returns
+/// `Err(HpkeError::IncorrectInputLength(HEADER_SIZE, 0))`.
+pub fn compact_key_id(envelope: &[u8]) -> Result<u8, HpkeError> {
+    envelope
+        .first()
+        .map(|header| header >> 2)
+        .ok_or(HpkeError::IncorrectInputLength(HEADER_SIZE, 0))
+}
+
+/// Opens the compact envelope `envelope` with `sk_recip`, in the Base mode, in place.
+/// `bind_header` must be what it was when the envelope was sealed.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)`, a subslice of `envelope`, on success. If `envelope` is too short to
+/// hold a header, an encapsulated key, and a tag, returns
+/// `Err(HpkeError::IncorrectInputLength(min_len, envelope.len()))`. If the header says the
+/// envelope was sealed with another AEAD, returns `Err(HpkeError::ValidationError)`. If
+/// `bind_header` is true and `aad` is longer than `MAX_BOUND_AAD_LEN`, returns
+/// `Err(HpkeError::InputTooLong)`. Otherwise, the errors are those of
+/// `single_shot_open_in_place`.
+pub fn open_compact<'a, A, Kdf, Kem>(
+    sk_recip: &Kem::PrivateKey,
+    info: &[u8],
+    aad: &[u8],
+    bind_header: bool,
+    envelope: &'a mut [u8],
+) -> Result<&'a mut [u8], HpkeError>
+where
+    A: TruncatedTagAead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let ct_start = HEADER_SIZE + Kem::ENCAPPED_KEY_SIZE;
+    let min_len = ct_start + A::TAG_SIZE;
+    if envelope.len() < min_len {
+        return Err(HpkeError::IncorrectInputLength(min_len, envelope.len()));
+    }
+    if envelope[0] != header_byte::<A>(envelope[0] >> 2) {
+        return Err(HpkeError::ValidationError);
+    }
+
+    let mut bound_aad = [0u8; HEADER_SIZE + MAX_BOUND_AAD_LEN];
+    let aad = with_header(envelope[0], aad, bind_header, &mut bound_aad)?;
+
+    let encapped_key = Kem::EncappedKey::from_bytes(&envelope[HEADER_SIZE..ct_start])?;
+    single_shot_open_in_place::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_recip,
+        &encapped_key,
+        info,
+        &mut envelope[ct_start..],
+        aad,
+    )
+}
+
+/// The header of a compact envelope with the given key ID, which is at most `MAX_KEY_ID`
+fn header_byte<A: TruncatedTagAead>(key_id: u8) -> u8 {
+    (key_id << 2) | (A::AEAD_ID & 0x03) as u8
+}
+
+/// Returns `aad`, or, if `bind_header` is true, `header` followed by `aad`, written to the front
+/// of `buf`
+fn with_header<'a>(
+    header: u8,
+    aad: &'a [u8],
+    bind_header: bool,
+    buf: &'a mut [u8; HEADER_SIZE + MAX_BOUND_AAD_LEN],
+) -> Result<&'a [u8], HpkeError> {
+    if !bind_header {
+        return Ok(aad);
+    }
+    if aad.len() > MAX_BOUND_AAD_LEN {
+        return Err(HpkeError::InputTooLong);
+    }
+    buf[0] = header;
+    buf[HEADER_SIZE..HEADER_SIZE + aad.len()].copy_from_slice(aad);
+    Ok(&buf[..HEADER_SIZE + aad.len()])
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{kdf::HkdfSha256, kem::X25519HkdfSha256, setup_receiver, setup_sender};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    /// Tests that each truncated-tag AEAD opens what it seals, refuses a tampered tag while
+    /// leaving the ciphertext intact, and has 8-byte tags
+    #[test]
+    fn test_truncated_tags() {
+        fn check<A: TruncatedTagAead>() {
+            let mut csprng = StdRng::from_entropy();
+            let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+            let (enc, mut sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"lpwan", &mut csprng)
+                    .unwrap();
+            let mut receiver_ctx =
+                setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk_recip, &enc, b"lpwan").unwrap();
+            assert_eq!(A::TAG_BITS, 64);
+
+            let mut buf = *b"20 bytes of reading";
+            let tag = sender_ctx.seal_in_place_detached(&mut buf, b"aad").unwrap();
+            assert_eq!(tag.to_bytes().len(), 8);
+
+            let ciphertext = buf;
+            let mut bad_tag = tag.to_bytes();
+            bad_tag[7] ^= 1;
+            let bad_tag = crate::aead::AeadTag::<A>::from_bytes(&bad_tag).unwrap();
+            assert_eq!(
+                receiver_ctx.open_in_place_detached(&mut buf, b"aad", &bad_tag),
+                Err(HpkeError::OpenError)
+            );
+            assert_eq!(buf, ciphertext);
+
+            receiver_ctx
+                .open_in_place_detached(&mut buf, b"aad", &tag)
+                .unwrap();
+            assert_eq!(&buf, b"20 bytes of reading");
+        }
+
+        check::<AesGcm128Tag64>();
+        check::<AesGcm256Tag64>();
+        check::<ChaCha20Poly1305Tag64>();
+    }
+
+    /// Tests that a compact envelope opens, carries its key ID, is as short as promised, and is
+    /// refused by a recipient configured with another AEAD, and that its header is bound unless
+    /// that's opted out of
+    #[test]
+    fn test_compact_envelope() {
+        type A = ChaCha20Poly1305Tag64;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let plaintext = b"temp=21.5C hum=40%";
+
+        let mut out = [0u8; 64];
+        assert_eq!(
+            seal_compact::<A, Kdf, Kem, _>(
+                64,
+                &pk_recip,
+                b"",
+                plaintext,
+                b"",
+                true,
+                &mut out,
+                &mut csprng
+            ),
+            Err(HpkeError::ValidationError)
+        );
+        let len = seal_compact::<A, Kdf, Kem, _>(
+            5,
+            &pk_recip,
+            b"",
+            plaintext,
+            b"",
+            true,
+            &mut out,
+            &mut csprng,
+        )
+        .unwrap();
+        assert_eq!(len, 1 + 32 + plaintext.len() + 8);
+        assert_eq!(compact_key_id(&out[..len]), Ok(5));
+
+        let mut wrong_aead = out;
+        assert_eq!(
+            open_compact::<AesGcm128Tag64, Kdf, Kem>(
+                &sk_recip,
+                b"",
+                b"",
+                true,
+                &mut wrong_aead[..len]
+            ),
+            Err(HpkeError::ValidationError)
+        );
+        assert_eq!(
+            open_compact::<A, Kdf, Kem>(&sk_recip, b"", &[0u8; 256], true, &mut out[..len]),
+            Err(HpkeError::InputTooLong)
+        );
+        let mut unbound = out;
+        assert_eq!(
+            open_compact::<A, Kdf, Kem>(&sk_recip, b"", b"", false, &mut unbound[..len]),
+            Err(HpkeError::OpenError)
+        );
+        // Key ID 5 becomes 4
+        let mut tampered = out;
+        tampered[0] ^= 0x04;
+        assert_eq!(compact_key_id(&tampered[..len]), Ok(4));
+        assert_eq!(
+            open_compact::<A, Kdf, Kem>(&sk_recip, b"", b"", true, &mut tampered[..len]),
+            Err(HpkeError::OpenError)
+        );
+        let opened =
+            open_compact::<A, Kdf, Kem>(&sk_recip, b"", b"", true, &mut out[..len]).unwrap();
+        assert_eq!(opened, plaintext);
+
+        let len = seal_compact::<A, Kdf, Kem, _>(
+            5,
+            &pk_recip,
+            b"",
+            plaintext,
+            b"",
+            false,
+            &mut out,
+            &mut csprng,
+        )
+        .unwrap();
+        out[0] ^= 0x04;
+        let opened =
+            open_compact::<A, Kdf, Kem>(&sk_recip, b"", b"", false, &mut out[..len]).unwrap();
+        assert_eq!(opened, plaintext);
+    }
+}
@@ -0,0 +1,219 @@
+//! Sealing individual struct fields, for application-layer encryption of, e.g., database columns.
+//!
+//! `#[derive(HpkeSealed)]` on a struct with named fields generates a companion struct, named after
+//! the original with `Sealed` appended, in which every field marked `#[hpke(sealed)]` is replaced
+//! by its sealed bytes, as a `Vec<u8>`. The other fields are copied, so they must be `Clone`. It
+//! also generates:
+//!
+//! * `seal::<A, Kdf, Kem, R>(&self, pk_recip, aad, csprng)` on the original, which seals each
+//!   marked field to `pk_recip` under its own encapsulation, and returns the companion
+//! * `open::<A, Kdf, Kem>(&self, sk_recip, aad)` on the companion, which opens them again
+//!
+//! Store or serialize the companion instead of the original. Each sealed field's `info` binds the
+//! struct and field names, so a sealed field can't be moved to another column, and `aad` binds
+//! whatever else the caller passes, e.g., the row's primary key, so it can't be moved to another
+//! row. Sealed field types must implement [`SealedField`].
+//!
+//! The struct-level attribute `#[hpke(name = "...")]` renames the companion, and
+//! `#[hpke(derive(...))]` puts derives on it, e.g., `serde::Serialize` and `serde::Deserialize`.
+//! Generic structs aren't supported.
+//!
+//! Example
+//! =======
+//! ```
+//! # #[cfg(feature = "x25519")] {
+//! use hpke::{
+//!     aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256, sealed::HpkeSealed, Kem,
+//! };
+//! # use rand::{rngs::StdRng, SeedableRng};
+//!
+//! #[derive(HpkeSealed)]
+//! #[hpke(derive(Clone, Debug))]
+//! struct Customer {
+//!     id: u64,
+//!     #[hpke(sealed)]
+//!     email: String,
+//! }
+//!
+//! let mut csprng = StdRng::from_entropy();
+//! let (sk, pk) = X25519HkdfSha256::gen_keypair(&mut csprng);
+//! let customer = Customer { id: 7, email: "a@example.com".into() };
+//!
+//! let row: CustomerSealed = customer
+//!     .seal::<ChaCha20Poly1305, HkdfSha256, X25519HkdfSha256, _>(&pk, b"row 7", &mut csprng)
+//!     .unwrap();
+//! let opened = row
+//!     .open::<ChaCha20Poly1305, HkdfSha256, X25519HkdfSha256>(&sk, b"row 7")
+//!     .unwrap();
+//! assert_eq!(opened.email, "a@example.com");
+//! # }
+//! ```
+
+use crate::{
+    aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, single_shot_open, single_shot_seal,
+    Deserializable, HpkeError, InfoBuilder, OpModeR, OpModeS, Serializable,
+};
+
+use rand_core::{CryptoRng, RngCore};
+
+pub use hpke_derive::HpkeSealed;
+
+// The generated code names Vec through here, since the user's crate might be no_std. String is
+// for the impl below.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use std::{string::String, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+pub use alloc::{string::String, vec::Vec};
+
+/// A type that `#[hpke(sealed)]` fields can have
+pub trait SealedField: Sized {
+    /// Returns the bytes to seal
+    fn to_field_bytes(&self) -> crate::Vec<u8>;
+
+    /// Parses the bytes that `to_field_bytes` returned
+    fn from_field_bytes(bytes: crate::Vec<u8>) -> Result<Self, HpkeError>;
+}
+
+impl SealedField for crate::Vec<u8> {
+    fn to_field_bytes(&self) -> crate::Vec<u8> {
+        self.clone()
+    }
+
+    fn from_field_bytes(bytes: crate::Vec<u8>) -> Result<Self, HpkeError> {
+        Ok(bytes)
+    }
+}
+
+/// Strings are sealed as their UTF-8 bytes. Opening bytes that aren't UTF-8 returns
+/// `HpkeError::ValidationError`.
+impl SealedField for String {
+    fn to_field_bytes(&self) -> crate::Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_field_bytes(bytes: crate::Vec<u8>) -> Result<Self, HpkeError> {
+        String::from_utf8(bytes).map_err(|_| HpkeError::ValidationError)
+    }
+}
+
+/// Seals one field's bytes, for the code `#[derive(HpkeSealed)]` generates. Returns `enc ||
+/// ciphertext`.
+#[doc(hidden)]
+pub fn seal_field<A, Kdf, Kem, R>(
+    pk_recip: &Kem::PublicKey,
+    struct_name: &str,
+    field_name: &str,
+    value: &[u8],
+    aad: &[u8],
+    csprng: &mut R,
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    let info = field_info(struct_name, field_name);
+    let (encapped_key, ciphertext) =
+        single_shot_seal::<A, Kdf, Kem, R>(&OpModeS::Base, pk_recip, &info, value, aad, csprng)?;
+    let mut sealed = encapped_key.to_bytes().to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Opens what `seal_field` returned, for the code `#[derive(HpkeSealed)]` generates
+#[doc(hidden)]
+pub fn open_field<A, Kdf, Kem>(
+    sk_recip: &Kem::PrivateKey,
+    struct_name: &str,
+    field_name: &str,
+    sealed: &[u8],
+    aad: &[u8],
+) -> Result<crate::Vec<u8>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    if sealed.len() < Kem::ENCAPPED_KEY_SIZE {
+        return Err(HpkeError::IncorrectInputLength(
+            Kem::ENCAPPED_KEY_SIZE,
+            sealed.len(),
+        ));
+    }
+    let (encapped_key, ciphertext) = sealed.split_at(Kem::ENCAPPED_KEY_SIZE);
+    let encapped_key = Kem::EncappedKey::from_bytes(encapped_key)?;
+    let info = field_info(struct_name, field_name);
+    single_shot_open::<A, Kdf, Kem>(
+        &OpModeR::Base,
+        sk_recip,
+        &encapped_key,
+        &info,
+        ciphertext,
+        aad,
+    )
+}
+
+/// Returns the `info` that the given field is sealed under
+fn field_info(struct_name: &str, field_name: &str) -> crate::Vec<u8> {
+    InfoBuilder::new(b"hpke-sealed-field")
+        .field(b"struct", struct_name.as_bytes())
+        .field(b"field", field_name.as_bytes())
+        .build()
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type A = ChaCha20Poly1305;
+    type Kdf = HkdfSha256;
+    type Kem = X25519HkdfSha256;
+
+    #[derive(HpkeSealed, Debug, PartialEq)]
+    #[hpke(name = "SealedRow", derive(Clone))]
+    struct Row {
+        id: u64,
+        #[hpke(sealed)]
+        ssn: String,
+        #[hpke(sealed)]
+        notes: crate::Vec<u8>,
+    }
+
+    /// Tests that sealed rows open to the original, and that sealed fields can't be moved between
+    /// columns or rows
+    #[test]
+    fn test_derive_hpke_sealed() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Kem::gen_keypair(&mut csprng);
+        let row = Row {
+            id: 42,
+            ssn: "078-05-1120".into(),
+            notes: b"\x00\xffbinary".to_vec(),
+        };
+
+        let sealed = row
+            .seal::<A, Kdf, Kem, _>(&pk, b"row 42", &mut csprng)
+            .unwrap();
+        assert_eq!(sealed.id, 42);
+        assert_eq!(sealed.ssn.len(), 32 + 11 + 16);
+        assert_eq!(sealed.open::<A, Kdf, Kem>(&sk, b"row 42").unwrap(), row);
+
+        assert_eq!(
+            sealed.open::<A, Kdf, Kem>(&sk, b"row 43"),
+            Err(HpkeError::OpenError)
+        );
+        let mut swapped = sealed.clone();
+        swapped.notes = sealed.ssn.clone();
+        assert_eq!(
+            swapped.open::<A, Kdf, Kem>(&sk, b"row 42"),
+            Err(HpkeError::OpenError)
+        );
+    }
+}
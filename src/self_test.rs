@@ -0,0 +1,872 @@
+//! A runtime self-test of every compiled ciphersuite. This is meant to be run at startup by
+//! deployments that need a power-on self-test, e.g., FIPS 140-style ones.
+
+// Without any KEMs there's nothing to test, and most of this module goes unused
+#![cfg_attr(
+    not(any(
+        feature = "x25519",
+        feature = "p256",
+        feature = "p384",
+        feature = "p521"
+    )),
+    allow(unused_imports, dead_code)
+)]
+
+use crate::{
+    aead::Aead,
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    setup::{setup_receiver, setup_sender},
+    util::ReplayRng,
+    OpModeR, OpModeS,
+};
+
+// The maximum number of suites we test. There is one per compiled KEM.
+const MAX_SELF_TESTS: usize = 4;
+
+// The plaintext every self-test seals and opens. This is the first plaintext of every RFC 9180
+// test vector.
+const PLAINTEXT: &[u8] = b"Beauty is truth, truth beauty";
+// The largest ciphertext we handle is a PLAINTEXT-sized ciphertext with a 16-byte tag
+const MAX_CIPHERTEXT_LEN: usize = 29 + 16;
+
+/// The kind of test run on a ciphersuite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SelfTestKind {
+    /// A known-answer test using a test vector from RFC 9180. This checks key derivation,
+    /// encapsulation, decapsulation, the key schedule, seal, open, and export against fixed
+    /// expected values.
+    KnownAnswer,
+    /// A pairwise consistency test. This is used for ciphersuites for which RFC 9180 has no test
+    /// vectors. It checks that a sender and receiver context agree on seal, open, and export.
+    PairwiseConsistency,
+}
+
+/// Describes which step of a self-test failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SelfTestFailure {
+    /// A keypair derived from fixed keying material was not the expected one
+    KeyDerivation,
+    /// Encapsulation errored or produced an unexpected encapsulated key
+    Encapsulation,
+    /// Decapsulation errored
+    Decapsulation,
+    /// Sealing errored or produced an unexpected ciphertext
+    Seal,
+    /// Opening errored or produced an unexpected plaintext
+    Open,
+    /// Exporting errored or produced an unexpected secret
+    Export,
+    /// There is no locked answer for the ciphersuite. See [`assert_kat!`](crate::assert_kat).
+    NoLockedAnswer,
+}
+
+impl core::fmt::Display for SelfTestFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SelfTestFailure::KeyDerivation => write!(f, "Key derivation self-test failed"),
+            SelfTestFailure::Encapsulation => write!(f, "Encapsulation self-test failed"),
+            SelfTestFailure::Decapsulation => write!(f, "Decapsulation self-test failed"),
+            SelfTestFailure::Seal => write!(f, "Seal self-test failed"),
+            SelfTestFailure::Open => write!(f, "Open self-test failed"),
+            SelfTestFailure::Export => write!(f, "Export self-test failed"),
+            SelfTestFailure::NoLockedAnswer => write!(f, "No locked known answer for this suite"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SelfTestFailure {}
+
+/// The outcome of the self-test of a single ciphersuite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestEntry {
+    /// The algorithm identifier of the KEM under test
+    pub kem_id: u16,
+    /// The algorithm identifier of the KDF under test
+    pub kdf_id: u16,
+    /// The algorithm identifier of the AEAD under test
+    pub aead_id: u16,
+    /// The kind of test that was run
+    pub kind: SelfTestKind,
+    /// `Ok(())` if the test passed, otherwise the step that failed
+    pub result: Result<(), SelfTestFailure>,
+}
+
+/// The outcome of [`self_test`], with one entry per compiled KEM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestReport {
+    entries: [Option<SelfTestEntry>; MAX_SELF_TESTS],
+}
+
+impl SelfTestReport {
+    /// Returns the outcome of every ciphersuite that was tested
+    pub fn entries(&self) -> impl Iterator<Item = &SelfTestEntry> {
+        self.entries.iter().flatten()
+    }
+
+    /// Returns `true` iff every ciphersuite passed its self-test
+    pub fn passed(&self) -> bool {
+        self.entries().all(|e| e.result.is_ok())
+    }
+
+    /// Returns the first ciphersuite that failed its self-test, if any
+    pub fn first_failure(&self) -> Option<&SelfTestEntry> {
+        self.entries().find(|e| e.result.is_err())
+    }
+
+    // Appends an entry to the report. Panics if the report is full, which can only happen if
+    // MAX_SELF_TESTS is wrong.
+    fn push(&mut self, entry: SelfTestEntry) {
+        let slot = self
+            .entries
+            .iter_mut()
+            .find(|e| e.is_none())
+            .expect("too many self-tests");
+        *slot = Some(entry);
+    }
+}
+
+/// Runs a small known-answer test for every compiled ciphersuite and returns a report of the
+/// outcomes. This does not use any randomness, and does not allocate.
+///
+/// Every compiled KEM is tested once, paired with one KDF and one AEAD such that every KDF and
+/// AEAD is covered by at least one test whenever the default features are enabled. The KEMs for
+/// which RFC 9180 has test vectors (X25519, P-256, and P-521) get a known-answer test in
+/// AuthPsk mode. P-384 has no such vectors, so it gets a pairwise consistency test instead.
+///
+/// Return Value
+/// ============
+/// Returns a [`SelfTestReport`]. Callers that need a hard failure should check
+/// [`SelfTestReport::passed`] and refuse to proceed if it is `false`.
+pub fn self_test() -> SelfTestReport {
+    #[allow(unused_mut)]
+    let mut report = SelfTestReport {
+        entries: [None; MAX_SELF_TESTS],
+    };
+
+    #[cfg(feature = "x25519")]
+    report.push(known_answer::<
+        crate::aead::ChaCha20Poly1305,
+        crate::kdf::HkdfSha256,
+        crate::kem::X25519HkdfSha256,
+    >(&kats::X25519_HKDFSHA256_CHACHA20POLY1305));
+
+    #[cfg(feature = "p256")]
+    report.push(known_answer::<
+        crate::aead::AesGcm128,
+        crate::kdf::HkdfSha256,
+        crate::kem::DhP256HkdfSha256,
+    >(&kats::P256_HKDFSHA256_AESGCM128));
+
+    #[cfg(feature = "p384")]
+    report.push(pairwise_consistency::<
+        crate::aead::AesGcm256,
+        crate::kdf::HkdfSha384,
+        crate::kem::DhP384HkdfSha384,
+    >());
+
+    #[cfg(feature = "p521")]
+    report.push(known_answer::<
+        crate::aead::AesGcm256,
+        crate::kdf::HkdfSha512,
+        crate::kem::DhP521HkdfSha512,
+    >(&kats::P521_HKDFSHA512_AESGCM256));
+
+    report
+}
+
+/// An AuthPsk-mode test vector, trimmed down to the first encryption and the first export
+#[cfg(any(feature = "x25519", feature = "p256", feature = "p521"))]
+struct KnownAnswer {
+    info: &'static [u8],
+    ikm_r: &'static [u8],
+    ikm_s: &'static [u8],
+    ikm_e: &'static [u8],
+    pk_rm: &'static [u8],
+    pk_sm: &'static [u8],
+    psk: &'static [u8],
+    psk_id: &'static [u8],
+    enc: &'static [u8],
+    aad: &'static [u8],
+    ct: &'static [u8],
+    exporter_context: &'static [u8],
+    exported_value: &'static [u8],
+}
+
+// Helper for the known-answer and pairwise tests. Fails with the given failure if the condition
+// is false.
+fn check(cond: bool, failure: SelfTestFailure) -> Result<(), SelfTestFailure> {
+    if cond {
+        Ok(())
+    } else {
+        Err(failure)
+    }
+}
+
+/// Runs the given known-answer test for the given ciphersuite
+#[cfg(any(feature = "x25519", feature = "p256", feature = "p521"))]
+fn known_answer<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(kat: &KnownAnswer) -> SelfTestEntry {
+    SelfTestEntry {
+        kem_id: Kem::KEM_ID,
+        kdf_id: Kdf::KDF_ID,
+        aead_id: A::AEAD_ID,
+        kind: SelfTestKind::KnownAnswer,
+        result: known_answer_inner::<A, Kdf, Kem>(kat),
+    }
+}
+
+#[cfg(any(feature = "x25519", feature = "p256", feature = "p521"))]
+fn known_answer_inner<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    kat: &KnownAnswer,
+) -> Result<(), SelfTestFailure> {
+    use crate::{aead::AeadTag, Deserializable, PskBundle, Serializable};
+    use SelfTestFailure::*;
+
+    // Derive the recipient's and sender's keypairs and check the pubkeys
+    let (sk_recip, pk_recip) = Kem::derive_keypair(kat.ikm_r);
+    let (sk_sender, pk_sender) = Kem::derive_keypair(kat.ikm_s);
+    check(pk_recip.to_bytes().as_slice() == kat.pk_rm, KeyDerivation)?;
+    check(pk_sender.to_bytes().as_slice() == kat.pk_sm, KeyDerivation)?;
+
+    let psk_bundle = PskBundle {
+        psk: kat.psk,
+        psk_id: kat.psk_id,
+    };
+
+    // Set up the sender. The ephemeral key is derived from the RNG output, so replaying ikmE
+    // makes the encapsulation deterministic.
+    let mode_s = OpModeS::AuthPsk((sk_sender, pk_sender.clone()), psk_bundle);
+    let (encapped_key, mut sender_ctx) =
+        setup_sender::<A, Kdf, Kem, _>(&mode_s, &pk_recip, kat.info, &mut ReplayRng(kat.ikm_e))
+            .map_err(|_| Encapsulation)?;
+    check(encapped_key.to_bytes().as_slice() == kat.enc, Encapsulation)?;
+
+    // Seal the plaintext and check the ciphertext and tag
+    let mut buf = [0u8; MAX_CIPHERTEXT_LEN];
+    let msg = &mut buf[..PLAINTEXT.len()];
+    msg.copy_from_slice(PLAINTEXT);
+    let tag = sender_ctx
+        .seal_in_place_detached(msg, kat.aad)
+        .map_err(|_| Seal)?;
+    let (expected_ct, expected_tag) = kat.ct.split_at(PLAINTEXT.len());
+    check(&*msg == expected_ct, Seal)?;
+    check(tag.to_bytes().as_slice() == expected_tag, Seal)?;
+
+    // Set up the receiver from the expected encapsulated key, then open the expected ciphertext
+    let encapped_key = Kem::EncappedKey::from_bytes(kat.enc).map_err(|_| Decapsulation)?;
+    let mode_r = OpModeR::AuthPsk(pk_sender, psk_bundle);
+    let mut receiver_ctx =
+        setup_receiver::<A, Kdf, Kem>(&mode_r, &sk_recip, &encapped_key, kat.info)
+            .map_err(|_| Decapsulation)?;
+    let tag = AeadTag::<A>::from_bytes(expected_tag).map_err(|_| Open)?;
+    let msg = &mut buf[..PLAINTEXT.len()];
+    msg.copy_from_slice(expected_ct);
+    receiver_ctx
+        .open_in_place_detached(msg, kat.aad, &tag)
+        .map_err(|_| Open)?;
+    check(&*msg == PLAINTEXT, Open)?;
+
+    // Export from both sides and check the outputs
+    let mut exported = [0u8; 32];
+    sender_ctx
+        .export(kat.exporter_context, &mut exported)
+        .map_err(|_| Export)?;
+    check(exported.as_slice() == kat.exported_value, Export)?;
+    receiver_ctx
+        .export(kat.exporter_context, &mut exported)
+        .map_err(|_| Export)?;
+    check(exported.as_slice() == kat.exported_value, Export)?;
+
+    Ok(())
+}
+
+/// Runs a pairwise consistency test for the given ciphersuite
+#[cfg(feature = "p384")]
+fn pairwise_consistency<A: Aead, Kdf: KdfTrait, Kem: KemTrait>() -> SelfTestEntry {
+    SelfTestEntry {
+        kem_id: Kem::KEM_ID,
+        kdf_id: Kdf::KDF_ID,
+        aead_id: A::AEAD_ID,
+        kind: SelfTestKind::PairwiseConsistency,
+        result: pairwise_consistency_inner::<A, Kdf, Kem>(),
+    }
+}
+
+#[cfg(feature = "p384")]
+fn pairwise_consistency_inner<A: Aead, Kdf: KdfTrait, Kem: KemTrait>() -> Result<(), SelfTestFailure>
+{
+    use SelfTestFailure::*;
+
+    // Fixed keying material. The recipient's key and the ephemeral key must differ.
+    let ikm_r = [0x52; 66];
+    let ikm_e = [0x45; 66];
+    let info = b"hpke self-test";
+    let aad = b"hpke self-test aad";
+
+    // Set up a sender and receiver in Base mode
+    let (sk_recip, pk_recip) = Kem::derive_keypair(&ikm_r);
+    check(Kem::sk_to_pk(&sk_recip) == pk_recip, KeyDerivation)?;
+    let (encapped_key, mut sender_ctx) =
+        setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, info, &mut ReplayRng(&ikm_e))
+            .map_err(|_| Encapsulation)?;
+    let mut receiver_ctx =
+        setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk_recip, &encapped_key, info)
+            .map_err(|_| Decapsulation)?;
+
+    // Seal, make sure the ciphertext isn't the plaintext, and open
+    let mut buf = [0u8; MAX_CIPHERTEXT_LEN];
+    let msg = &mut buf[..PLAINTEXT.len()];
+    msg.copy_from_slice(PLAINTEXT);
+    let tag = sender_ctx
+        .seal_in_place_detached(msg, aad)
+        .map_err(|_| Seal)?;
+    check(&*msg != PLAINTEXT, Seal)?;
+    receiver_ctx
+        .open_in_place_detached(msg, aad, &tag)
+        .map_err(|_| Open)?;
+    check(&*msg == PLAINTEXT, Open)?;
+
+    // Make sure both sides export the same secret
+    let mut exported_s = [0u8; 32];
+    let mut exported_r = [0u8; 32];
+    sender_ctx
+        .export(b"", &mut exported_s)
+        .map_err(|_| Export)?;
+    receiver_ctx
+        .export(b"", &mut exported_r)
+        .map_err(|_| Export)?;
+    check(exported_s == exported_r, Export)?;
+
+    Ok(())
+}
+
+/// The locked answer of a ciphersuite: the first 8 bytes of the SHA-256 digests of the
+/// encapsulated key, the ciphertext and tag, and the exported secret of [`locked_answer`]'s run.
+/// The ciphertext of an export-only suite is empty.
+struct LockedAnswer {
+    kem_id: u16,
+    kdf_id: u16,
+    aead_id: u16,
+    enc: [u8; 8],
+    ct: [u8; 8],
+    exported: [u8; 8],
+}
+
+// The locked answers of every ciphersuite, by KEM, KDF, and AEAD ID. These were recorded with
+// this crate at the time `assert_kat!` was added. Never change an entry unless the suite is meant
+// to behave differently.
+#[rustfmt::skip]
+const LOCKED_ANSWERS: &[LockedAnswer] = &[
+    la(0x0010, 0x0001, 0x0001, 0x0e6305aa565a88a4, 0xb8bd3b0d6a1ceaf8, 0xe06d7c7d437c1036),
+    la(0x0010, 0x0001, 0x0002, 0x0e6305aa565a88a4, 0x6a5f95f02094f8cf, 0x0a1ba6ac08c1c212),
+    la(0x0010, 0x0001, 0x0003, 0x0e6305aa565a88a4, 0x0d83d8039be79b18, 0x7b7f156eaeafa6ad),
+    la(0x0010, 0x0001, 0xFFFF, 0x0e6305aa565a88a4, 0xe3b0c44298fc1c14, 0x724b20303c0fe236),
+    la(0x0010, 0x0002, 0x0001, 0x0e6305aa565a88a4, 0xb0154279d120918e, 0x70aaaa559ed8dd40),
+    la(0x0010, 0x0002, 0x0002, 0x0e6305aa565a88a4, 0x7e713d24b909e384, 0xd77adf38006dd127),
+    la(0x0010, 0x0002, 0x0003, 0x0e6305aa565a88a4, 0x625c9bbe9b94b40d, 0xccda226fb4f2a84b),
+    la(0x0010, 0x0002, 0xFFFF, 0x0e6305aa565a88a4, 0xe3b0c44298fc1c14, 0x96b975c41dc1ac82),
+    la(0x0010, 0x0003, 0x0001, 0x0e6305aa565a88a4, 0x14698ad4ee32a42f, 0xbf47ea931dd7e5ba),
+    la(0x0010, 0x0003, 0x0002, 0x0e6305aa565a88a4, 0x825fb8d8525f3106, 0xd725f8d18b24f4ba),
+    la(0x0010, 0x0003, 0x0003, 0x0e6305aa565a88a4, 0xfb434db069dbaedc, 0x059ed5d3d02fe6c6),
+    la(0x0010, 0x0003, 0xFFFF, 0x0e6305aa565a88a4, 0xe3b0c44298fc1c14, 0xcfb1101781c68770),
+    la(0x0011, 0x0001, 0x0001, 0x8fc92f0d66109260, 0xa2856be5eaa59077, 0xb0ccbe5a2d4f61c7),
+    la(0x0011, 0x0001, 0x0002, 0x8fc92f0d66109260, 0x06b9a016331ce1ec, 0xd640c29546262f75),
+    la(0x0011, 0x0001, 0x0003, 0x8fc92f0d66109260, 0xb72516b0134ffcb5, 0xd4b79e52bad487f9),
+    la(0x0011, 0x0001, 0xFFFF, 0x8fc92f0d66109260, 0xe3b0c44298fc1c14, 0x7f8b07fe75fc9774),
+    la(0x0011, 0x0002, 0x0001, 0x8fc92f0d66109260, 0x527dd9f031ead1d9, 0xd301b4da1fbba843),
+    la(0x0011, 0x0002, 0x0002, 0x8fc92f0d66109260, 0xf0c2107a222d664a, 0xbb1ec3f39b006284),
+    la(0x0011, 0x0002, 0x0003, 0x8fc92f0d66109260, 0xaa3e284c2dd4ab28, 0x61bccd4ae2996b5c),
+    la(0x0011, 0x0002, 0xFFFF, 0x8fc92f0d66109260, 0xe3b0c44298fc1c14, 0x584dfc0ad0fb33a0),
+    la(0x0011, 0x0003, 0x0001, 0x8fc92f0d66109260, 0x70c118e42e16e5b6, 0x17016eb524d1a05f),
+    la(0x0011, 0x0003, 0x0002, 0x8fc92f0d66109260, 0x5c10d3d5d1ce553f, 0x26242227cf7c1aaa),
+    la(0x0011, 0x0003, 0x0003, 0x8fc92f0d66109260, 0x155ce91ad98e0212, 0xa0818e029932b680),
+    la(0x0011, 0x0003, 0xFFFF, 0x8fc92f0d66109260, 0xe3b0c44298fc1c14, 0xfed5ae8dc162a50e),
+    la(0x0012, 0x0001, 0x0001, 0x75609b403e735fc6, 0x688516f30f85e83f, 0x211f7826d4a3f00c),
+    la(0x0012, 0x0001, 0x0002, 0x75609b403e735fc6, 0x7754199449addc1c, 0x8f083de37f71ab2c),
+    la(0x0012, 0x0001, 0x0003, 0x75609b403e735fc6, 0x34333b7f8d190b5e, 0xaa3dd43f8c456c9a),
+    la(0x0012, 0x0001, 0xFFFF, 0x75609b403e735fc6, 0xe3b0c44298fc1c14, 0xee3484f4feec23e2),
+    la(0x0012, 0x0002, 0x0001, 0x75609b403e735fc6, 0x99931ad1996acd4f, 0x42242ab5d0522846),
+    la(0x0012, 0x0002, 0x0002, 0x75609b403e735fc6, 0x5e8f795e730dbf15, 0xf224a0328574e09a),
+    la(0x0012, 0x0002, 0x0003, 0x75609b403e735fc6, 0x6bbb5d56b5499e58, 0xb7c9f2feef83a8e2),
+    la(0x0012, 0x0002, 0xFFFF, 0x75609b403e735fc6, 0xe3b0c44298fc1c14, 0x10a9bfc9f7ba4df1),
+    la(0x0012, 0x0003, 0x0001, 0x75609b403e735fc6, 0xb4d3e9b1f2c5e519, 0x99867ad28a1e04e2),
+    la(0x0012, 0x0003, 0x0002, 0x75609b403e735fc6, 0x849c0e971df34dc9, 0x7666fdc1d36503f9),
+    la(0x0012, 0x0003, 0x0003, 0x75609b403e735fc6, 0x4b6098c0dbd23cf4, 0x3258173501448a33),
+    la(0x0012, 0x0003, 0xFFFF, 0x75609b403e735fc6, 0xe3b0c44298fc1c14, 0x775dc321231a1522),
+    la(0x0020, 0x0001, 0x0001, 0x900389d338764652, 0x7431826c04f115ce, 0xac9a50869c15f794),
+    la(0x0020, 0x0001, 0x0002, 0x900389d338764652, 0x7ede756595f4aff3, 0xff0de16a4512a759),
+    la(0x0020, 0x0001, 0x0003, 0x900389d338764652, 0x4d12d3f6546ad5e8, 0xb2d905db0b45684e),
+    la(0x0020, 0x0001, 0xFFFF, 0x900389d338764652, 0xe3b0c44298fc1c14, 0xafc50bdbcd0d685e),
+    la(0x0020, 0x0002, 0x0001, 0x900389d338764652, 0xba57aba163089b87, 0x6fb3a2b7b01ca00b),
+    la(0x0020, 0x0002, 0x0002, 0x900389d338764652, 0x5ddef25fcd74d754, 0x9205b2ae7a29a9a4),
+    la(0x0020, 0x0002, 0x0003, 0x900389d338764652, 0xeef3c27d4f023d31, 0xffd3417817ac805e),
+    la(0x0020, 0x0002, 0xFFFF, 0x900389d338764652, 0xe3b0c44298fc1c14, 0x4443fe015bf73cd0),
+    la(0x0020, 0x0003, 0x0001, 0x900389d338764652, 0xd279f37b2ebd2539, 0xc88b0b88ef89c41a),
+    la(0x0020, 0x0003, 0x0002, 0x900389d338764652, 0xb01f8f8e04ae864a, 0xbff3f43e513358df),
+    la(0x0020, 0x0003, 0x0003, 0x900389d338764652, 0xf3832744a9d1e96e, 0x4a0e918b883e567e),
+    la(0x0020, 0x0003, 0xFFFF, 0x900389d338764652, 0xe3b0c44298fc1c14, 0x420d31c624b4fa94),
+];
+
+// Makes a locked answer out of its three digests, each written as a big-endian integer. This keeps
+// the table one line per suite.
+const fn la(
+    kem_id: u16,
+    kdf_id: u16,
+    aead_id: u16,
+    enc: u64,
+    ct: u64,
+    exported: u64,
+) -> LockedAnswer {
+    LockedAnswer {
+        kem_id,
+        kdf_id,
+        aead_id,
+        enc: enc.to_be_bytes(),
+        ct: ct.to_be_bytes(),
+        exported: exported.to_be_bytes(),
+    }
+}
+
+/// Returns whether there is a locked answer for the given ciphersuite. Every combination of the
+/// KEMs, KDFs, and AEADs in this crate has one.
+#[doc(hidden)]
+pub const fn has_locked_answer(kem_id: u16, kdf_id: u16, aead_id: u16) -> bool {
+    let mut i = 0;
+    while i < LOCKED_ANSWERS.len() {
+        let la = &LOCKED_ANSWERS[i];
+        if la.kem_id == kem_id && la.kdf_id == kdf_id && la.aead_id == aead_id {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Runs the given ciphersuite in AuthPsk mode with fixed inputs, and checks the outputs against
+/// the suite's locked answer. Unlike the RFC 9180 vectors, this covers every ciphersuite. The
+/// locked answers were recorded with this crate, so they don't show that a suite is right, only
+/// that it does exactly what it did when they were recorded.
+pub(crate) fn locked_answer<A: Aead, Kdf: KdfTrait, Kem: KemTrait>() -> SelfTestEntry {
+    SelfTestEntry {
+        kem_id: Kem::KEM_ID,
+        kdf_id: Kdf::KDF_ID,
+        aead_id: A::AEAD_ID,
+        kind: SelfTestKind::KnownAnswer,
+        result: locked_answer_inner::<A, Kdf, Kem>(),
+    }
+}
+
+fn locked_answer_inner<A: Aead, Kdf: KdfTrait, Kem: KemTrait>() -> Result<(), SelfTestFailure> {
+    use crate::{PskBundle, Serializable};
+    use sha2::{Digest, Sha256};
+    use SelfTestFailure::*;
+
+    // Returns the truncated digest that a locked answer stores
+    fn digest8(bytes: &[u8]) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        out.copy_from_slice(&Sha256::digest(bytes)[..8]);
+        out
+    }
+
+    let locked = LOCKED_ANSWERS
+        .iter()
+        .find(|la| la.kem_id == Kem::KEM_ID && la.kdf_id == Kdf::KDF_ID && la.aead_id == A::AEAD_ID)
+        .ok_or(NoLockedAnswer)?;
+
+    // Fixed keying material, as in the pairwise consistency test
+    let ikm_r = [0x52; 66];
+    let ikm_s = [0x53; 66];
+    let ikm_e = [0x45; 66];
+    let psk_bundle = PskBundle {
+        psk: b"hpke locked answer psk, 32 bytes",
+        psk_id: b"hpke locked answer",
+    };
+    let info = b"hpke self-test";
+    let aad = b"hpke self-test aad";
+
+    let (sk_recip, pk_recip) = Kem::derive_keypair(&ikm_r);
+    let (sk_sender, pk_sender) = Kem::derive_keypair(&ikm_s);
+    check(Kem::sk_to_pk(&sk_recip) == pk_recip, KeyDerivation)?;
+
+    // Set up both sides, and check the encapsulated key
+    let mode_s = OpModeS::AuthPsk((sk_sender, pk_sender.clone()), psk_bundle);
+    let (encapped_key, mut sender_ctx) =
+        setup_sender::<A, Kdf, Kem, _>(&mode_s, &pk_recip, info, &mut ReplayRng(&ikm_e))
+            .map_err(|_| Encapsulation)?;
+    check(
+        digest8(&encapped_key.to_bytes()) == locked.enc,
+        Encapsulation,
+    )?;
+    let mode_r = OpModeR::AuthPsk(pk_sender, psk_bundle);
+    let mut receiver_ctx = setup_receiver::<A, Kdf, Kem>(&mode_r, &sk_recip, &encapped_key, info)
+        .map_err(|_| Decapsulation)?;
+
+    // Seal, check the ciphertext and tag, and open. Export-only suites can't seal.
+    let mut buf = [0u8; MAX_CIPHERTEXT_LEN];
+    if A::AEAD_ID != crate::aead::ExportOnlyAead::AEAD_ID {
+        let (msg, tag_buf) = buf.split_at_mut(PLAINTEXT.len());
+        msg.copy_from_slice(PLAINTEXT);
+        let tag = sender_ctx
+            .seal_in_place_detached(msg, aad)
+            .map_err(|_| Seal)?;
+        let tag_bytes = tag.to_bytes();
+        tag_buf[..tag_bytes.len()].copy_from_slice(&tag_bytes);
+        let ct_len = PLAINTEXT.len() + tag_bytes.len();
+        check(digest8(&buf[..ct_len]) == locked.ct, Seal)?;
+
+        let msg = &mut buf[..PLAINTEXT.len()];
+        receiver_ctx
+            .open_in_place_detached(msg, aad, &tag)
+            .map_err(|_| Open)?;
+        check(&*msg == PLAINTEXT, Open)?;
+    } else {
+        check(digest8(&[]) == locked.ct, Seal)?;
+    }
+
+    // Export from both sides and check the outputs
+    let mut exported_s = [0u8; 32];
+    let mut exported_r = [0u8; 32];
+    sender_ctx
+        .export(b"locked", &mut exported_s)
+        .map_err(|_| Export)?;
+    receiver_ctx
+        .export(b"locked", &mut exported_r)
+        .map_err(|_| Export)?;
+    check(exported_s == exported_r, Export)?;
+    check(digest8(&exported_s) == locked.exported, Export)?;
+
+    Ok(())
+}
+
+/// Checks, in a `#[test]` named `hpke_assert_kat`, that each of the given [`Hpke`](crate::Hpke)
+/// suites still produces its locked known answer. Invoke this once, at module level, with the
+/// suites an application uses, and an upgrade of this crate or of its dependencies that changes
+/// what any of them does fails that application's tests. A suite this crate has no locked answer
+/// for fails to compile.
+///
+/// To check outside of tests, e.g., at startup or in a build script, call
+/// [`Hpke::known_answer_test`](crate::Hpke::known_answer_test) instead.
+///
+/// Example
+/// =======
+/// ```
+/// # #[cfg(all(feature = "x25519", feature = "p256"))]
+/// hpke::assert_kat!(hpke::HpkeX25519, hpke::HpkeP256);
+/// ```
+#[macro_export]
+macro_rules! assert_kat {
+    ($($suite:ty),+ $(,)?) => {
+        $(
+            const _: () = assert!(
+                <$suite>::HAS_LOCKED_ANSWER,
+                "hpke has no locked known answer for this suite"
+            );
+        )+
+
+        #[test]
+        fn hpke_assert_kat() {
+            $(
+                let entry = <$suite>::known_answer_test();
+                if let Err(failure) = entry.result {
+                    panic!(
+                        "{} for suite ({:#06x}, {:#06x}, {:#06x})",
+                        failure, entry.kem_id, entry.kdf_id, entry.aead_id
+                    );
+                }
+            )+
+        }
+    };
+}
+
+// Test vectors taken from test-vectors-5f503c5.json. Each is the mode 3 (AuthPsk) vector for the
+// given ciphersuite, with its first encryption and first export.
+#[cfg(any(feature = "x25519", feature = "p256", feature = "p521"))]
+mod kats {
+    use super::KnownAnswer;
+
+    // The values that are shared among all the RFC 9180 AuthPsk test vectors
+    const INFO: &[u8] = b"Ode on a Grecian Urn";
+    const PSK: &[u8] = &[
+        0x02, 0x47, 0xfd, 0x33, 0xb9, 0x13, 0x76, 0x0f, 0xa1, 0xfa, 0x51, 0xe1, 0x89, 0x2d, 0x9f,
+        0x30, 0x7f, 0xbe, 0x65, 0xeb, 0x17, 0x1e, 0x81, 0x32, 0xc2, 0xaf, 0x18, 0x55, 0x5a, 0x73,
+        0x8b, 0x82,
+    ];
+    const PSK_ID: &[u8] = b"Ennyn Durin aran Moria";
+    const AAD: &[u8] = b"Count-0";
+
+    #[cfg(feature = "x25519")]
+    pub(super) const X25519_HKDFSHA256_CHACHA20POLY1305: KnownAnswer = KnownAnswer {
+        info: INFO,
+        ikm_r: &[
+            0xf3, 0x30, 0x4d, 0xdc, 0xf1, 0x58, 0x48, 0x48, 0x82, 0x71, 0xf1, 0x2b, 0x75, 0xec,
+            0xaf, 0x72, 0x30, 0x1f, 0xaa, 0xbf, 0x6a, 0xd2, 0x83, 0x65, 0x4a, 0x14, 0xc3, 0x98,
+            0x83, 0x2e, 0xb1, 0x84,
+        ],
+        ikm_s: &[
+            0x20, 0xad, 0xe1, 0xd5, 0x20, 0x3d, 0xe1, 0xaa, 0xdf, 0xb2, 0x61, 0xc4, 0x70, 0x0b,
+            0x64, 0x32, 0xe2, 0x60, 0xd0, 0xd3, 0x17, 0xbe, 0x6e, 0xbb, 0xb8, 0xd7, 0xff, 0xfb,
+            0x1f, 0x86, 0xad, 0x9d,
+        ],
+        ikm_e: &[
+            0x49, 0xd6, 0xea, 0xc8, 0xc6, 0xc5, 0x58, 0xc9, 0x53, 0xa0, 0xa2, 0x52, 0x92, 0x9a,
+            0x81, 0x87, 0x45, 0xbb, 0x08, 0xcd, 0x3d, 0x29, 0xe1, 0x5f, 0x9f, 0x5d, 0xb5, 0xeb,
+            0x2e, 0x7d, 0x4b, 0x84,
+        ],
+        pk_rm: &[
+            0xa5, 0x09, 0x94, 0x31, 0xc3, 0x5c, 0x49, 0x1e, 0xc6, 0x2c, 0xa9, 0x1d, 0xf1, 0x52,
+            0x5d, 0x63, 0x49, 0xcb, 0x8a, 0xa1, 0x70, 0xc5, 0x1f, 0x95, 0x81, 0xf8, 0x62, 0x7b,
+            0xe6, 0x33, 0x48, 0x51,
+        ],
+        pk_sm: &[
+            0x3a, 0xc5, 0xbd, 0x4d, 0xd6, 0x6f, 0xf9, 0xf2, 0x74, 0x0b, 0xef, 0x0d, 0x6c, 0xcb,
+            0x66, 0xda, 0xa7, 0x7b, 0xff, 0x78, 0x49, 0xd7, 0x89, 0x51, 0x82, 0xb0, 0x7f, 0xb7,
+            0x4d, 0x08, 0x7c, 0x45,
+        ],
+        psk: PSK,
+        psk_id: PSK_ID,
+        enc: &[
+            0x65, 0x6a, 0x2e, 0x00, 0xdc, 0x99, 0x90, 0xfd, 0x18, 0x9e, 0x6e, 0x47, 0x34, 0x59,
+            0x39, 0x2d, 0xf5, 0x56, 0xe9, 0xa2, 0x75, 0x87, 0x54, 0xa0, 0x9d, 0xb3, 0xf5, 0x11,
+            0x79, 0xa3, 0xfc, 0x02,
+        ],
+        aad: AAD,
+        ct: &[
+            0x9a, 0xa5, 0x2e, 0x29, 0x27, 0x4f, 0xc6, 0x17, 0x2e, 0x38, 0xa4, 0x46, 0x13, 0x61,
+            0xd2, 0x34, 0x25, 0x85, 0xd3, 0xae, 0xec, 0x67, 0xfb, 0x3b, 0x72, 0x1e, 0xcd, 0x63,
+            0xf0, 0x59, 0x57, 0x7c, 0x7f, 0xe8, 0x86, 0xbe, 0x0e, 0xde, 0x01, 0x45, 0x6e, 0xbc,
+            0x67, 0xd5, 0x97,
+        ],
+        exporter_context: b"",
+        exported_value: &[
+            0xc2, 0x3e, 0xbd, 0x4e, 0x7a, 0x0a, 0xd0, 0x6a, 0x5d, 0xdd, 0xf7, 0x79, 0xf6, 0x50,
+            0x04, 0xce, 0x94, 0x81, 0x06, 0x9c, 0xe0, 0xf0, 0xe6, 0xdd, 0x51, 0xa0, 0x45, 0x39,
+            0xdd, 0xcb, 0xd5, 0xcd,
+        ],
+    };
+
+    #[cfg(feature = "p256")]
+    pub(super) const P256_HKDFSHA256_AESGCM128: KnownAnswer = KnownAnswer {
+        info: INFO,
+        ikm_r: &[
+            0xab, 0xcc, 0x2d, 0xa5, 0xb3, 0xfa, 0x81, 0xd8, 0xaa, 0xbd, 0x91, 0xf7, 0xf8, 0x00,
+            0xa8, 0xcc, 0xf6, 0x0e, 0xc3, 0x7b, 0x1b, 0x58, 0x5a, 0x5d, 0x1d, 0x1a, 0xc7, 0x7f,
+            0x25, 0x8b, 0x6c, 0xca,
+        ],
+        ikm_s: &[
+            0x62, 0x62, 0x03, 0x1f, 0x04, 0x0a, 0x9d, 0xb8, 0x53, 0xed, 0xd6, 0xf9, 0x1d, 0x22,
+            0x72, 0x59, 0x6e, 0xab, 0xbc, 0x78, 0xa2, 0xed, 0x2b, 0xd6, 0x43, 0xf7, 0x70, 0xec,
+            0xd0, 0xf1, 0x9b, 0x82,
+        ],
+        ikm_e: &[
+            0x3c, 0x1f, 0xce, 0xb4, 0x77, 0xec, 0x95, 0x4c, 0x8d, 0x58, 0xef, 0x32, 0x49, 0xe4,
+            0xbb, 0x4c, 0x38, 0x24, 0x1b, 0x59, 0x25, 0xb9, 0x5f, 0x74, 0x86, 0xe4, 0xd9, 0xf1,
+            0xd0, 0xd3, 0x5f, 0xbb,
+        ],
+        pk_rm: &[
+            0x04, 0xd8, 0x24, 0xd7, 0xe8, 0x97, 0x89, 0x7c, 0x17, 0x2a, 0xc8, 0xa9, 0xe8, 0x62,
+            0xe4, 0xbd, 0x82, 0x01, 0x33, 0xb8, 0xd0, 0x90, 0xa9, 0xb1, 0x88, 0xb8, 0x23, 0x3a,
+            0x64, 0xdf, 0xbc, 0x5f, 0x72, 0x5a, 0xa0, 0xaa, 0x52, 0xc8, 0x46, 0x2a, 0xb7, 0xc9,
+            0x18, 0x8f, 0x1c, 0x48, 0x72, 0xf0, 0xc9, 0x90, 0x87, 0xa8, 0x67, 0xe8, 0xa7, 0x73,
+            0xa1, 0x3d, 0xf4, 0x8a, 0x62, 0x70, 0x58, 0xe1, 0xb3,
+        ],
+        pk_sm: &[
+            0x04, 0x9f, 0x15, 0x8c, 0x75, 0x0e, 0x55, 0xd8, 0xd5, 0xad, 0x13, 0xed, 0xe6, 0x6c,
+            0xf6, 0xe7, 0x98, 0x01, 0x63, 0x4b, 0x7a, 0xca, 0xdc, 0xad, 0x72, 0x04, 0x4e, 0xac,
+            0x2a, 0xe1, 0xd0, 0x48, 0x00, 0x69, 0x13, 0x3d, 0x64, 0x88, 0xbf, 0x73, 0x86, 0x3f,
+            0xa9, 0x88, 0xc4, 0xba, 0x8b, 0xde, 0x1c, 0x2e, 0x94, 0x8b, 0x76, 0x12, 0x74, 0x80,
+            0x2b, 0x4d, 0x80, 0x12, 0xaf, 0x4f, 0x13, 0xaf, 0x9e,
+        ],
+        psk: PSK,
+        psk_id: PSK_ID,
+        enc: &[
+            0x04, 0x6a, 0x1d, 0xe3, 0xfc, 0x26, 0xa3, 0xd4, 0x3f, 0x4e, 0x4b, 0xa9, 0x7d, 0xbe,
+            0x24, 0xf7, 0xe9, 0x91, 0x81, 0x13, 0x61, 0x29, 0xc4, 0x8f, 0xbe, 0x87, 0x2d, 0x47,
+            0x43, 0xe2, 0xb1, 0x31, 0x35, 0x7e, 0xd4, 0xf2, 0x9a, 0x7b, 0x31, 0x7d, 0xc2, 0x25,
+            0x09, 0xc7, 0xb0, 0x09, 0x91, 0xae, 0x99, 0x0b, 0xf6, 0x5f, 0x8b, 0x23, 0x67, 0x00,
+            0xc8, 0x2a, 0xb7, 0xc1, 0x1a, 0x84, 0x51, 0x14, 0x01,
+        ],
+        aad: AAD,
+        ct: &[
+            0xb9, 0xf3, 0x6d, 0x58, 0xd9, 0xeb, 0x10, 0x16, 0x29, 0xa3, 0xe5, 0xa7, 0xb6, 0x3d,
+            0x2e, 0xe4, 0xaf, 0x42, 0xb3, 0x64, 0x42, 0x09, 0xab, 0x37, 0xe0, 0xa2, 0x72, 0xd4,
+            0x43, 0x65, 0x40, 0x7d, 0xb8, 0xe6, 0x55, 0xc7, 0x2e, 0x4f, 0xa4, 0x6f, 0x4f, 0xf8,
+            0x1b, 0x92, 0x46,
+        ],
+        exporter_context: b"",
+        exported_value: &[
+            0x59, 0x5c, 0xe0, 0xef, 0xf4, 0x05, 0xd4, 0xb3, 0xbb, 0x1d, 0x08, 0x30, 0x8d, 0x70,
+            0xa4, 0xe7, 0x72, 0x26, 0xce, 0x11, 0x76, 0x6e, 0x0a, 0x94, 0xc4, 0xfd, 0xb5, 0xd9,
+            0x00, 0x25, 0xc9, 0x78,
+        ],
+    };
+
+    #[cfg(feature = "p521")]
+    pub(super) const P521_HKDFSHA512_AESGCM256: KnownAnswer = KnownAnswer {
+        info: INFO,
+        ikm_r: &[
+            0x3d, 0xb4, 0x34, 0xa8, 0xbc, 0x25, 0xb2, 0x7e, 0xb0, 0xc5, 0x90, 0xdc, 0x64, 0x99,
+            0x7a, 0xb1, 0x37, 0x8a, 0x99, 0xf5, 0x2b, 0x2c, 0xb5, 0xa5, 0xa5, 0xb2, 0xfa, 0x54,
+            0x08, 0x88, 0xf6, 0xc0, 0xf0, 0x97, 0x94, 0xc6, 0x54, 0xf4, 0x46, 0x85, 0x24, 0xe0,
+            0x40, 0xe6, 0xb4, 0xec, 0xa2, 0xc9, 0xdc, 0xf2, 0x29, 0xf9, 0x08, 0xb9, 0xd3, 0x18,
+            0xf9, 0x60, 0xcc, 0x9e, 0x9b, 0xaa, 0x92, 0xc5, 0xee, 0xe6,
+        ],
+        ikm_s: &[
+            0x65, 0xd5, 0x23, 0xd9, 0xb3, 0x7e, 0x12, 0x73, 0xeb, 0x25, 0xad, 0x05, 0x27, 0xd3,
+            0xa7, 0xbd, 0x33, 0xf6, 0x72, 0x08, 0xdd, 0x16, 0x66, 0xd9, 0x90, 0x4c, 0x6b, 0xc0,
+            0x49, 0x69, 0xae, 0x58, 0x31, 0xa8, 0xb8, 0x49, 0xe7, 0xff, 0x64, 0x25, 0x81, 0xf2,
+            0xc3, 0xe5, 0x6b, 0xe8, 0x46, 0x09, 0x60, 0x0d, 0x3c, 0x6b, 0xbd, 0xad, 0xed, 0x3f,
+            0x69, 0x89, 0xc3, 0x7d, 0x28, 0x92, 0xb1, 0xe9, 0x78, 0xd5,
+        ],
+        ikm_e: &[
+            0x54, 0x27, 0x27, 0x97, 0xb1, 0xfb, 0xc1, 0x28, 0xa6, 0x96, 0x7f, 0xf1, 0xfd, 0x60,
+            0x6e, 0x0c, 0x67, 0x86, 0x8f, 0x77, 0x62, 0xce, 0x14, 0x21, 0x43, 0x9c, 0xbc, 0x9e,
+            0x90, 0xce, 0x1b, 0x28, 0xd5, 0x66, 0xe6, 0xc2, 0xac, 0xbc, 0xe7, 0x12, 0xe4, 0x8e,
+            0xeb, 0xf2, 0x36, 0x69, 0x6e, 0xb6, 0x80, 0x84, 0x9d, 0x68, 0x73, 0xe9, 0x95, 0x93,
+            0x95, 0xb2, 0x93, 0x19, 0x75, 0xd6, 0x1d, 0x38, 0xbd, 0x6c,
+        ],
+        pk_rm: &[
+            0x04, 0x01, 0x65, 0x5b, 0x5d, 0x3b, 0x7c, 0xfa, 0xfa, 0xba, 0x30, 0x85, 0x1d, 0x25,
+            0xed, 0xc4, 0x4c, 0x6d, 0xd1, 0x7d, 0x99, 0x41, 0x0e, 0xfb, 0xed, 0x85, 0x91, 0x30,
+            0x3b, 0x4d, 0xbe, 0xea, 0x8c, 0xb1, 0x04, 0x5d, 0x52, 0x55, 0xf9, 0xa6, 0x03, 0x84,
+            0xc3, 0xbb, 0xd4, 0xa3, 0x38, 0x6a, 0xe6, 0xe6, 0xfa, 0xb3, 0x41, 0xdc, 0x1f, 0x8d,
+            0xb0, 0xee, 0xd5, 0xf0, 0xab, 0x1a, 0xaa, 0xc6, 0xd7, 0x83, 0x8e, 0x00, 0xda, 0xdf,
+            0x8a, 0x1c, 0x2c, 0x64, 0xb4, 0x8f, 0x89, 0xc6, 0x33, 0x72, 0x1e, 0x88, 0x36, 0x9e,
+            0x54, 0x10, 0x4b, 0x31, 0x36, 0x8f, 0x26, 0xe3, 0x5d, 0x04, 0xa4, 0x42, 0xb0, 0xb4,
+            0x28, 0x51, 0x0f, 0xb2, 0x3c, 0xaa, 0xda, 0x68, 0x6a, 0xdd, 0x16, 0x49, 0x2f, 0x33,
+            0x3b, 0x0f, 0x7b, 0xa7, 0x4c, 0x39, 0x1d, 0x77, 0x9b, 0x78, 0x8d, 0xf2, 0xc3, 0x8d,
+            0x7a, 0x7f, 0x47, 0x78, 0x00, 0x9d, 0x91,
+        ],
+        pk_sm: &[
+            0x04, 0x00, 0x13, 0x76, 0x1e, 0x97, 0x00, 0x72, 0x93, 0xd5, 0x7d, 0xe7, 0x09, 0x62,
+            0x87, 0x6b, 0x49, 0x26, 0xf6, 0x9a, 0x52, 0x68, 0x0b, 0x47, 0x14, 0xbe, 0xe1, 0xd4,
+            0x23, 0x6a, 0xa9, 0x6c, 0x19, 0xb8, 0x40, 0xc5, 0x7e, 0x80, 0xb1, 0x4e, 0x91, 0x25,
+            0x8f, 0x0a, 0x35, 0x0e, 0x3f, 0x7b, 0xa5, 0x9f, 0x3f, 0x09, 0x16, 0x33, 0xae, 0xde,
+            0x4c, 0x7e, 0xc4, 0xfa, 0x89, 0x18, 0x32, 0x3a, 0xa4, 0x5d, 0x59, 0x01, 0x07, 0x6d,
+            0xec, 0x8e, 0xeb, 0x22, 0x89, 0x9f, 0xda, 0x9a, 0xb9, 0xe1, 0x96, 0x00, 0x03, 0xff,
+            0x05, 0x35, 0xf5, 0x3c, 0x02, 0xc4, 0x0f, 0x2a, 0xe4, 0xcd, 0xc6, 0x07, 0x0a, 0x38,
+            0x70, 0xb8, 0x5b, 0x4b, 0xdd, 0x0b, 0xb7, 0x7f, 0x1f, 0x88, 0x9e, 0x7e, 0xe5, 0x1f,
+            0x46, 0x5a, 0x30, 0x8f, 0x08, 0xc6, 0x66, 0xad, 0x34, 0x07, 0xf7, 0x5d, 0xc0, 0x46,
+            0xb2, 0xff, 0x5a, 0x24, 0xdb, 0xe2, 0xed,
+        ],
+        psk: PSK,
+        psk_id: PSK_ID,
+        enc: &[
+            0x04, 0x00, 0x0a, 0x50, 0x96, 0xa6, 0xe6, 0xe0, 0x02, 0xc8, 0x35, 0x17, 0xb4, 0x94,
+            0xbf, 0xc2, 0xe3, 0x6b, 0xfb, 0x86, 0x32, 0xfa, 0xe8, 0x06, 0x83, 0x62, 0x85, 0x2b,
+            0x70, 0xd0, 0xff, 0x71, 0xe5, 0x60, 0xb1, 0x5a, 0xff, 0x96, 0x74, 0x1e, 0xcf, 0xfb,
+            0x63, 0xd8, 0xac, 0x30, 0x90, 0xc3, 0x76, 0x96, 0x79, 0x00, 0x9a, 0xc5, 0x9a, 0x99,
+            0xa1, 0xfe, 0xb4, 0x71, 0x3c, 0x5f, 0x09, 0x0f, 0xc0, 0xdb, 0xed, 0x01, 0xad, 0x73,
+            0xc4, 0x5d, 0x29, 0xd3, 0x69, 0xe3, 0x67, 0x44, 0xe9, 0xed, 0x37, 0xd1, 0x2f, 0x80,
+            0x70, 0x0c, 0x16, 0xd8, 0x16, 0x48, 0x56, 0x55, 0x16, 0x9a, 0x5d, 0xd6, 0x6e, 0x4d,
+            0xdf, 0x27, 0xf2, 0xac, 0xff, 0xe0, 0xf5, 0x6f, 0x7f, 0x77, 0xea, 0x2b, 0x47, 0x3b,
+            0x4b, 0xf0, 0x51, 0x8b, 0x97, 0x5d, 0x95, 0x27, 0x00, 0x9a, 0x3d, 0x14, 0xe5, 0xa4,
+            0x95, 0x7e, 0x3e, 0x8a, 0x90, 0x74, 0xf8,
+        ],
+        aad: AAD,
+        ct: &[
+            0x94, 0x2a, 0x2a, 0x92, 0xe0, 0x81, 0x7c, 0xf0, 0x32, 0xce, 0x61, 0xab, 0xcc, 0xf4,
+            0xf3, 0xa7, 0xc5, 0xd2, 0x1b, 0x79, 0x4e, 0xd9, 0x43, 0x22, 0x7e, 0x07, 0xb7, 0xdf,
+            0x2d, 0x6d, 0xd9, 0x2c, 0x9b, 0x8a, 0x93, 0x71, 0x94, 0x9e, 0x65, 0xcc, 0xa2, 0x62,
+            0x44, 0x8a, 0xb7,
+        ],
+        exporter_context: b"",
+        exported_value: &[
+            0xa3, 0x95, 0x02, 0xef, 0x5c, 0xa1, 0x16, 0xaa, 0x13, 0x17, 0xbd, 0x95, 0x83, 0xdd,
+            0x52, 0xf1, 0x5b, 0x05, 0x02, 0xb7, 0x1d, 0x90, 0x0f, 0xc8, 0xa6, 0x22, 0xd1, 0x96,
+            0x23, 0xd0, 0xcb, 0x5d,
+        ],
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "x25519", feature = "p256"))]
+    crate::assert_kat!(crate::HpkeX25519, crate::HpkeP256);
+
+    /// Tests that every ciphersuite with a compiled KEM matches its locked answer
+    #[test]
+    fn test_locked_answers() {
+        macro_rules! check_kem {
+            ($kem:ty) => {
+                check_kem!(@kdf $kem, crate::kdf::HkdfSha256);
+                check_kem!(@kdf $kem, crate::kdf::HkdfSha384);
+                check_kem!(@kdf $kem, crate::kdf::HkdfSha512);
+            };
+            (@kdf $kem:ty, $kdf:ty) => {
+                check_kem!(@aead $kem, $kdf, crate::aead::AesGcm128);
+                check_kem!(@aead $kem, $kdf, crate::aead::AesGcm256);
+                check_kem!(@aead $kem, $kdf, crate::aead::ChaCha20Poly1305);
+                check_kem!(@aead $kem, $kdf, crate::aead::ExportOnlyAead);
+            };
+            (@aead $kem:ty, $kdf:ty, $aead:ty) => {
+                let entry = locked_answer::<$aead, $kdf, $kem>();
+                assert_eq!(entry.result, Ok(()), "{:?}", entry);
+            };
+        }
+
+        #[cfg(feature = "x25519")]
+        check_kem!(crate::kem::X25519HkdfSha256);
+        #[cfg(feature = "p256")]
+        check_kem!(crate::kem::DhP256HkdfSha256);
+        #[cfg(feature = "p384")]
+        check_kem!(crate::kem::DhP384HkdfSha384);
+        #[cfg(feature = "p521")]
+        check_kem!(crate::kem::DhP521HkdfSha512);
+    }
+
+    /// Tests that every compiled ciphersuite passes its self-test
+    #[test]
+    fn test_self_test_passes() {
+        let report = self_test();
+        assert!(report.passed(), "{:?}", report.first_failure());
+
+        // There's one entry per compiled KEM
+        let num_kems = cfg!(feature = "x25519") as usize
+            + cfg!(feature = "p256") as usize
+            + cfg!(feature = "p384") as usize
+            + cfg!(feature = "p521") as usize;
+        assert_eq!(report.entries().count(), num_kems);
+    }
+
+    /// Tests that a corrupted known answer makes the self-test fail at the right step
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_self_test_detects_bad_kat() {
+        type A = crate::aead::ChaCha20Poly1305;
+        type Kdf = crate::kdf::HkdfSha256;
+        type Kem = crate::kem::X25519HkdfSha256;
+
+        let good = kats::X25519_HKDFSHA256_CHACHA20POLY1305;
+
+        // Wrong ephemeral keying material means the encapsulated key won't match
+        let bad_ikm_e = KnownAnswer {
+            ikm_e: &[0u8; 32],
+            ..good
+        };
+        let entry = known_answer::<A, Kdf, Kem>(&bad_ikm_e);
+        assert_eq!(entry.result, Err(SelfTestFailure::Encapsulation));
+
+        // Wrong AAD means the ciphertext won't match
+        let bad_aad = KnownAnswer {
+            aad: b"Count-1",
+            ..good
+        };
+        let entry = known_answer::<A, Kdf, Kem>(&bad_aad);
+        assert_eq!(entry.result, Err(SelfTestFailure::Seal));
+
+        // Wrong exporter context means the exported value won't match
+        let bad_export = KnownAnswer {
+            exporter_context: b"00",
+            ..good
+        };
+        let entry = known_answer::<A, Kdf, Kem>(&bad_export);
+        assert_eq!(entry.result, Err(SelfTestFailure::Export));
+    }
+}
@@ -0,0 +1,201 @@
+//! Two-way messaging over an ordered transport, e.g., a TCP stream, on top of an HPKE context.
+//!
+//! HPKE contexts only go one way: the sender seals and the receiver opens. A [`Session`] turns a
+//! context into a duplex channel. Each end derives two new contexts from the context's exporter
+//! secret, one per direction, so the two directions never share a key. Messages travel in frames,
+//! which carry their own length, and are rekeyed on a fixed schedule.
+//!
+//! Frame format
+//! ============
+//! A frame is a 4-byte big-endian length, followed by that many bytes of ciphertext and tag. The
+//! length is authenticated as the AAD.
+
+use crate::{
+    aead::{Aead, AeadCtxR, AeadCtxS},
+    kdf::Kdf as KdfTrait,
+    kem::Kem as KemTrait,
+    util::enforce_equal_len,
+    HpkeError, Serializable,
+};
+
+/// The size of the length prefix that starts every frame
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+/// A reasonable number of messages to send in each direction between rekeys
+pub const DEFAULT_REKEY_INTERVAL: u64 = 1 << 20;
+
+/// One end of a duplex session. Make the initiator's end from the `AeadCtxS` that `setup_sender`
+/// returns, and the responder's end from the matching `AeadCtxR`, with the same rekey interval.
+///
+/// Frames MUST be received in the order they were sent, and none may be dropped. A frame that
+/// fails to open leaves the session as it was, so a corrupted frame can't desynchronize it, but a
+/// lost one does.
+///
+/// Rekeying
+/// ========
+/// After every `rekey_interval` messages in a direction, both ends replace that direction's
+/// context with one derived from its exporter secret, and forget the old one. A key that leaks
+/// then only exposes the messages of its own interval, and of later ones.
+pub struct Session<A: Aead, Kdf: KdfTrait, Kem: KemTrait> {
+    sender: AeadCtxS<A, Kdf, Kem>,
+    receiver: AeadCtxR<A, Kdf, Kem>,
+    rekey_interval: u64,
+    /// The number of messages sent under the current sending context
+    sent: u64,
+    /// The number of messages received under the current receiving context
+    received: u64,
+}
+
+impl<A: Aead, Kdf: KdfTrait, Kem: KemTrait> Session<A, Kdf, Kem> {
+    /// Makes the initiator's end of a session from its sender context. `rekey_interval` is the
+    /// number of messages in each direction between rekeys, e.g., `DEFAULT_REKEY_INTERVAL`. 0
+    /// means never rekey.
+    pub fn initiator(ctx: AeadCtxS<A, Kdf, Kem>, rekey_interval: u64) -> Session<A, Kdf, Kem> {
+        let (sender, receiver) = ctx.into_duplex();
+        Session::new(sender, receiver, rekey_interval)
+    }
+
+    /// Makes the responder's end of a session from its receiver context. `rekey_interval` MUST be
+    /// the same as the initiator's.
+    pub fn responder(ctx: AeadCtxR<A, Kdf, Kem>, rekey_interval: u64) -> Session<A, Kdf, Kem> {
+        let (sender, receiver) = ctx.into_duplex();
+        Session::new(sender, receiver, rekey_interval)
+    }
+
+    fn new(
+        sender: AeadCtxS<A, Kdf, Kem>,
+        receiver: AeadCtxR<A, Kdf, Kem>,
+        rekey_interval: u64,
+    ) -> Session<A, Kdf, Kem> {
+        Session {
+            sender,
+            receiver,
+            rekey_interval,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    /// Returns the total length of the frame that starts with the given header, i.e.,
+    /// `FRAME_HEADER_SIZE` plus the length it encodes. Use this to know how much to read from the
+    /// transport before calling `recv`.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(frame_len)` on success. If `header` is shorter than `FRAME_HEADER_SIZE`, returns
+    /// `Err(HpkeError::IncorrectInputLength(FRAME_HEADER_SIZE, header.len()))`.
+    pub fn frame_len(header: &[u8]) -> Result<usize, HpkeError> {
+        let header: [u8; FRAME_HEADER_SIZE] = header
+            .get(..FRAME_HEADER_SIZE)
+            .and_then(|h| h.try_into().ok())
+            .ok_or(HpkeError::IncorrectInputLength(
+                FRAME_HEADER_SIZE,
+                header.len(),
+            ))?;
+        Ok(FRAME_HEADER_SIZE + u32::from_be_bytes(header) as usize)
+    }
+
+    /// Seals `plaintext` into a frame for the other end
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(frame)` on success. If the ciphertext wouldn't fit in a frame, returns
+    /// `Err(HpkeError::PlaintextTooLong)`. Otherwise, the errors are those of `AeadCtxS::seal`.
+    pub fn send(&mut self, plaintext: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        let ciphertext_len = u32::try_from(plaintext.len().saturating_add(A::TAG_SIZE))
+            .map_err(|_| HpkeError::PlaintextTooLong)?;
+        let header = ciphertext_len.to_be_bytes();
+
+        if self.rekey_interval != 0 && self.sent == self.rekey_interval {
+            self.sender = self.sender.fork_for_broadcast(b"rekey")?;
+            self.sent = 0;
+        }
+        let mut frame = header.to_vec();
+        frame.extend_from_slice(plaintext);
+        let tag = self
+            .sender
+            .seal_in_place_detached(&mut frame[FRAME_HEADER_SIZE..], &header)?;
+        frame.extend_from_slice(&tag.to_bytes());
+        self.sent += 1;
+
+        Ok(frame)
+    }
+
+    /// Opens a frame from the other end. `frame` must be exactly one whole frame.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. If `frame` is not as long as its header says, returns
+    /// `Err(HpkeError::IncorrectInputLength(expected, frame.len()))`. Otherwise, the errors are
+    /// those of `AeadCtxR::open`.
+    pub fn recv(&mut self, frame: &[u8]) -> Result<crate::Vec<u8>, HpkeError> {
+        enforce_equal_len(Self::frame_len(frame)?, frame.len())?;
+        let (header, ciphertext) = frame.split_at(FRAME_HEADER_SIZE);
+
+        // Open under the next interval's context if this frame starts it. Only move to it if the
+        // frame opens.
+        if self.rekey_interval != 0 && self.received == self.rekey_interval {
+            let mut next = self.receiver.peek_fork(b"rekey");
+            let plaintext = next.open(ciphertext, header)?;
+            self.receiver = next;
+            self.received = 1;
+            Ok(plaintext)
+        } else {
+            let plaintext = self.receiver.open(ciphertext, header)?;
+            self.received += 1;
+            Ok(plaintext)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256,
+        test_util::gen_ctx_simple_pair,
+    };
+
+    /// Tests that both ends can talk to each other across rekeys, that the two directions use
+    /// different keys, and that a bad frame is refused without breaking the session
+    #[test]
+    fn test_session() {
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let (sender_ctx, receiver_ctx) = gen_ctx_simple_pair::<A, Kdf, Kem>();
+        let mut initiator = Session::initiator(sender_ctx, 3);
+        let mut responder = Session::responder(receiver_ctx, 3);
+
+        for i in 0..10u8 {
+            let frame = initiator.send(&[i; 5]).unwrap();
+            assert_eq!(frame.len(), FRAME_HEADER_SIZE + 5 + 16);
+            assert_eq!(
+                Session::<A, Kdf, Kem>::frame_len(&frame).unwrap(),
+                frame.len()
+            );
+            assert_eq!(responder.recv(&frame).unwrap(), [i; 5]);
+
+            let frame = responder.send(&[i; 7]).unwrap();
+            assert_eq!(initiator.recv(&frame).unwrap(), [i; 7]);
+        }
+
+        // The directions don't share keys, so a frame can't be reflected back to its sender
+        let frame = initiator.send(b"ping").unwrap();
+        assert_eq!(initiator.recv(&frame), Err(HpkeError::OpenError));
+
+        // A tampered or truncated frame is refused, and the real one still opens after it
+        let mut bad_frame = frame.clone();
+        bad_frame[FRAME_HEADER_SIZE] ^= 1;
+        assert_eq!(responder.recv(&bad_frame), Err(HpkeError::OpenError));
+        assert_eq!(
+            responder.recv(&frame[..frame.len() - 1]),
+            Err(HpkeError::IncorrectInputLength(
+                frame.len(),
+                frame.len() - 1
+            ))
+        );
+        assert_eq!(responder.recv(&frame).unwrap(), b"ping");
+    }
+}
@@ -1,9 +1,9 @@
 use crate::{
-    aead::{Aead, AeadCtx, AeadCtxR, AeadCtxS},
+    aead::{Aead, AeadCtx, AeadCtxR, AeadCtxS, AeadKey, AeadNonce, ArrayBuf},
     kdf::{labeled_extract, DigestArray, Kdf as KdfTrait, LabeledExpand, MAX_DIGEST_SIZE},
     kem::{Kem as KemTrait, SharedSecret},
-    op_mode::{OpMode, OpModeR, OpModeS},
-    util::full_suite_id,
+    op_mode::{OpMode, OpModeR, OpModeS, PskBundle, PskStore},
+    util::{exceeds, FullSuiteId},
     HpkeError,
 };
 
@@ -51,43 +51,106 @@ impl<K: KdfTrait> Drop for ExporterSecret<K> {
 //
 //   return Context<ROLE>(key, base_nonce, 0, exporter_secret)
 
+/// The secret derived in the middle of the key schedule. We only ever hold onto it when we need
+/// to record it, e.g., in test vectors
+pub(crate) struct KeyScheduleSecret<K: KdfTrait>(pub(crate) DigestArray<K>);
+
+// Zero key schedule secrets on drop
+impl<K: KdfTrait> Drop for KeyScheduleSecret<K> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// The capacity of the buffer `key_schedule_context` returns. The key schedule context is a mode
+/// byte followed by two digests, so it never fills the buffer.
+pub const MAX_SCHED_CONTEXT_SIZE: usize = 3 * MAX_DIGEST_SIZE;
+
+/// Everything computed by the key schedule. `derive_enc_ctx` only needs the key, base nonce, and
+/// exporter secret. The rest is kept so that test vectors can record every intermediate value.
+pub(crate) struct KeySchedule<A: Aead, Kdf: KdfTrait> {
+    sched_context_buf: [u8; MAX_SCHED_CONTEXT_SIZE],
+    sched_context_size: usize,
+    // Only read by the test vector generator and the known-answer tests
+    #[allow(dead_code)]
+    pub(crate) secret: KeyScheduleSecret<Kdf>,
+    pub(crate) key: AeadKey<A>,
+    pub(crate) base_nonce: AeadNonce<A>,
+    pub(crate) exporter_secret: ExporterSecret<Kdf>,
+}
+
+impl<A: Aead, Kdf: KdfTrait> KeySchedule<A, Kdf> {
+    /// Returns the `key_schedule_context` value, i.e., `mode || psk_id_hash || info_hash`
+    #[allow(dead_code)]
+    pub(crate) fn context(&self) -> &[u8] {
+        &self.sched_context_buf[..self.sched_context_size]
+    }
+}
+
 // This is the KeySchedule function. It runs a KDF over all the parameters, inputs, and secrets,
-// and spits out a key-nonce pair to be used for symmetric encryption.
-fn derive_enc_ctx<A, Kdf, Kem, O>(
+// and spits out a key-nonce pair to be used for symmetric encryption, along with the values it
+// computed along the way.
+pub(crate) fn key_schedule<A, Kdf, Kem, O>(
     mode: &O,
-    shared_secret: SharedSecret<Kem>,
+    shared_secret: &SharedSecret<Kem>,
     info: &[u8],
-) -> AeadCtx<A, Kdf, Kem>
+) -> KeySchedule<A, Kdf>
 where
     A: Aead,
     Kdf: KdfTrait,
     Kem: KemTrait,
     O: OpMode<Kem>,
 {
-    // Put together the binding context used for all KDF operations
-    let suite_id = full_suite_id::<A, Kdf, Kem>();
+    // Empty fixed-size buffers
+    let mut key = AeadKey::<A>::default();
+    let mut base_nonce = AeadNonce::<A>::default();
+    let mut exporter_secret = <ExporterSecret<Kdf> as Default>::default();
 
+    // Do all the work in a function that's only generic over the KDF. This keeps the amount of
+    // code from growing with the number of ciphersuites.
+    let (sched_context_buf, sched_context_size, secret) = key_schedule_inner::<Kdf>(
+        &AeadCtx::<A, Kdf, Kem>::SUITE_ID,
+        mode.mode_id(),
+        mode.get_psk_bytes(),
+        mode.get_psk_id(),
+        &shared_secret.0,
+        info,
+        key.0.as_mut_slice(),
+        base_nonce.0.as_mut_slice(),
+        exporter_secret.0.as_mut_slice(),
+    );
+
+    KeySchedule {
+        sched_context_buf,
+        sched_context_size,
+        secret: KeyScheduleSecret(secret),
+        key,
+        base_nonce,
+        exporter_secret,
+    }
+}
+
+/// The body of `key_schedule`. This fills `key`, `base_nonce`, and `exporter_secret`, and returns
+/// the key schedule context and the secret.
+#[allow(clippy::too_many_arguments)]
+fn key_schedule_inner<Kdf: KdfTrait>(
+    suite_id: &FullSuiteId,
+    mode_id: u8,
+    psk: &[u8],
+    psk_id: &[u8],
+    shared_secret: &[u8],
+    info: &[u8],
+    key: &mut [u8],
+    base_nonce: &mut [u8],
+    exporter_secret: &mut [u8],
+) -> ([u8; MAX_SCHED_CONTEXT_SIZE], usize, DigestArray<Kdf>) {
     // In KeySchedule(),
     //   psk_id_hash = LabeledExtract("", "psk_id_hash", psk_id)
     //   info_hash = LabeledExtract("", "info_hash", info)
     //   key_schedule_context = concat(mode, psk_id_hash, info_hash)
 
-    // We concat without allocation by making a buffer of the maximum possible size, then
-    // taking the appropriately sized slice.
-    let (sched_context_buf, sched_context_size) = {
-        let (psk_id_hash, _) =
-            labeled_extract::<Kdf>(&[], &suite_id, b"psk_id_hash", mode.get_psk_id());
-        let (info_hash, _) = labeled_extract::<Kdf>(&[], &suite_id, b"info_hash", info);
-
-        // Yes it's overkill to bound the first input by MAX_DIGEST_SIZE, since it's only 1 byte.
-        // But whatever, this is pretty clean.
-        concat_with_known_maxlen!(
-            MAX_DIGEST_SIZE,
-            &[mode.mode_id()],
-            psk_id_hash.as_slice(),
-            info_hash.as_slice()
-        )
-    };
+    let (sched_context_buf, sched_context_size) =
+        sched_context::<Kdf>(suite_id, mode_id, psk_id, info);
     let sched_context = &sched_context_buf[..sched_context_size];
 
     // In KeySchedule(),
@@ -95,39 +158,106 @@ where
     //   key = LabeledExpand(secret, "key", key_schedule_context, Nk)
     //   base_nonce = LabeledExpand(secret, "base_nonce", key_schedule_context, Nn)
     //   exporter_secret = LabeledExpand(secret, "exp", key_schedule_context, Nh)
-    // Instead of `secret` we derive an HKDF context which we run .expand() on to derive the
+    // Along with `secret` we get an HKDF context which we run .expand() on to derive the
     // key-nonce pair.
-    let (_, secret_ctx) =
-        labeled_extract::<Kdf>(&shared_secret.0, &suite_id, b"secret", mode.get_psk_bytes());
-
-    // Empty fixed-size buffers
-    let mut key = crate::aead::AeadKey::<A>::default();
-    let mut base_nonce = crate::aead::AeadNonce::<A>::default();
-    let mut exporter_secret = <ExporterSecret<Kdf> as Default>::default();
+    let (secret, secret_ctx) = labeled_extract::<Kdf>(shared_secret, suite_id, b"secret", psk);
 
     // Fill the key, base nonce, and exporter secret. This only errors if the output values are
     // 255x the digest size of the hash function. Since these values are fixed at compile time, we
     // don't worry about it.
     secret_ctx
-        .labeled_expand(&suite_id, b"key", sched_context, key.0.as_mut_slice())
+        .labeled_expand(suite_id, b"key", sched_context, key)
         .expect("aead key len is way too big");
     secret_ctx
-        .labeled_expand(
-            &suite_id,
-            b"base_nonce",
-            sched_context,
-            base_nonce.0.as_mut_slice(),
-        )
+        .labeled_expand(suite_id, b"base_nonce", sched_context, base_nonce)
         .expect("nonce len is way too big");
     secret_ctx
-        .labeled_expand(
-            &suite_id,
-            b"exp",
-            sched_context,
-            exporter_secret.0.as_mut_slice(),
-        )
+        .labeled_expand(suite_id, b"exp", sched_context, exporter_secret)
         .expect("exporter secret len is way too big");
 
+    (sched_context_buf, sched_context_size, secret)
+}
+
+/// Computes `key_schedule_context = concat(mode, psk_id_hash, info_hash)`. We concat without
+/// allocation by making a buffer of the maximum possible size, and returning it with the length
+/// written.
+fn sched_context<Kdf: KdfTrait>(
+    suite_id: &FullSuiteId,
+    mode_id: u8,
+    psk_id: &[u8],
+    info: &[u8],
+) -> ([u8; MAX_SCHED_CONTEXT_SIZE], usize) {
+    let (psk_id_hash, _) = labeled_extract::<Kdf>(&[], suite_id, b"psk_id_hash", psk_id);
+    let (info_hash, _) = labeled_extract::<Kdf>(&[], suite_id, b"info_hash", info);
+
+    // Yes it's overkill to bound the first input by MAX_DIGEST_SIZE, since it's only 1 byte.
+    // But whatever, this is pretty clean.
+    concat_with_known_maxlen!(
+        MAX_DIGEST_SIZE,
+        &[mode_id],
+        psk_id_hash.as_slice(),
+        info_hash.as_slice()
+    )
+}
+
+/// Computes the key schedule context of RFC 9180 §5.1, i.e., `mode || psk_id_hash || info_hash`,
+/// for the ciphersuite. `mode_id` is the mode's RFC 9180 identifier (0 for base, 1 for PSK, 2 for
+/// auth, 3 for auth-PSK), and `psk_id` is empty in the modes without a PSK.
+///
+/// This depends only on public values, so both sides, and anyone who knows `info` and the PSK
+/// ID, compute the same bytes. It identifies the parameters a context was set up with, not the
+/// context itself. To bind to a particular context, use `AeadCtxS::context_id`.
+pub fn key_schedule_context<A, Kdf, Kem>(
+    mode_id: u8,
+    psk_id: &[u8],
+    info: &[u8],
+) -> ArrayBuf<MAX_SCHED_CONTEXT_SIZE>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let (buf, len) = sched_context::<Kdf>(&AeadCtx::<A, Kdf, Kem>::SUITE_ID, mode_id, psk_id, info);
+    ArrayBuf::new(buf, len)
+}
+
+/// Checks `info` and `mode`'s PSK and PSK ID against the KDF's length limits. This is done up
+/// front, rather than left to the hash function, so that oversized inputs get a clear error.
+fn check_input_lens<Kdf, Kem, O>(mode: &O, info: &[u8]) -> Result<(), HpkeError>
+where
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    O: OpMode<Kem>,
+{
+    if exceeds(info.len(), Kdf::MAX_INFO_LEN)
+        || exceeds(mode.get_psk_bytes().len(), Kdf::MAX_PSK_LEN)
+        || exceeds(mode.get_psk_id().len(), Kdf::MAX_PSK_ID_LEN)
+    {
+        Err(HpkeError::InputTooLong)
+    } else {
+        Ok(())
+    }
+}
+
+// Runs the key schedule and makes an encryption context out of the result
+fn derive_enc_ctx<A, Kdf, Kem, O>(
+    mode: &O,
+    shared_secret: SharedSecret<Kem>,
+    info: &[u8],
+) -> AeadCtx<A, Kdf, Kem>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    O: OpMode<Kem>,
+{
+    let KeySchedule {
+        key,
+        base_nonce,
+        exporter_secret,
+        ..
+    } = key_schedule::<A, Kdf, Kem, O>(mode, &shared_secret, info);
+
     AeadCtx::new(&key, base_nonce, exporter_secret)
 }
 
@@ -139,11 +269,18 @@ where
 
 /// Initiates an encryption context to the given recipient public key
 ///
+/// `pk_recip` was validated when it was deserialized, so it can be reused across calls at no extra
+/// cost.
+///
 /// Return Value
 /// ============
 /// On success, returns an encapsulated public key (intended to be sent to the recipient), and an
 /// encryption context. If an error happened during key encapsulation, returns
-/// `Err(HpkeError::EncapError)`. This is the only possible error.
+/// `Err(HpkeError::EncapError)`. If `csprng` failed, returns `Err(HpkeError::RngError)`. If `info`,
+/// or the PSK or PSK ID in `mode`, is longer than `Kdf` allows, returns
+/// `Err(HpkeError::InputTooLong)`. With the
+/// `strict` feature, if `mode` has a PSK that [`PskBundle::new`](crate::PskBundle::new) would
+/// reject, returns `Err(HpkeError::InvalidPsk)`.
 pub fn setup_sender<A, Kdf, Kem, R>(
     mode: &OpModeS<Kem>,
     pk_recip: &Kem::PublicKey,
@@ -156,14 +293,89 @@ where
     Kem: KemTrait,
     R: CryptoRng + RngCore,
 {
+    let res = setup_sender_untraced(mode, pk_recip, info, csprng);
+    crate::trace::setup::<A, Kdf, Kem>(
+        "setup_sender",
+        mode.mode_id(),
+        info.len(),
+        res.as_ref().map(|_| ()),
+    );
+    res
+}
+
+/// The body of `setup_sender`
+fn setup_sender_untraced<A, Kdf, Kem, R>(
+    mode: &OpModeS<Kem>,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    csprng: &mut R,
+) -> Result<(Kem::EncappedKey, AeadCtxS<A, Kdf, Kem>), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    check_input_lens::<Kdf, Kem, _>(mode, info)?;
+    // Refuse weak PSKs in strict mode
+    #[cfg(feature = "strict")]
+    mode.verify_psk_inputs()?;
+
     // If the identity key is set, use it
     let sender_id_keypair = mode.get_sender_id_keypair();
     // Do the encapsulation
     let (shared_secret, encapped_key) = Kem::encap(pk_recip, sender_id_keypair, csprng)?;
     // Use everything to derive an encryption context
     let enc_ctx = derive_enc_ctx::<_, _, Kem, _>(mode, shared_secret, info);
+    let enc_ctx = AeadCtxS::from(enc_ctx).with_encapped_key(encapped_key.clone());
 
-    Ok((encapped_key, enc_ctx.into()))
+    Ok((encapped_key, enc_ctx))
+}
+
+/// Does a `setup_sender` to each of the given recipients, in parallel. This is for sending to many
+/// recipients at once, where the encapsulations dominate the cost. Each recipient gets its own
+/// encapsulation, so the results are independent of each other.
+///
+/// Randomness is drawn from `csprng` up front, one seed per recipient, so `csprng` itself is never
+/// shared across threads.
+///
+/// Return Value
+/// ============
+/// Returns one result per recipient, in the same order as `pk_recips`. Each result is what
+/// `setup_sender` would have returned for that recipient.
+#[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+#[cfg(feature = "parallel")]
+#[allow(clippy::type_complexity)]
+pub fn setup_sender_batch<A, Kdf, Kem, R>(
+    mode: &OpModeS<Kem>,
+    pk_recips: &[Kem::PublicKey],
+    info: &[u8],
+    csprng: &mut R,
+) -> crate::Vec<Result<(Kem::EncappedKey, AeadCtxS<A, Kdf, Kem>), HpkeError>>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    Kem::PublicKey: Sync,
+    Kem::PrivateKey: Sync,
+    Kem::EncappedKey: Send,
+    AeadCtxS<A, Kdf, Kem>: Send,
+    R: CryptoRng + RngCore,
+{
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+    use rayon::prelude::*;
+
+    // Seed one RNG per recipient
+    let seeds = crate::util::draw_seeds(pk_recips.len(), csprng);
+
+    pk_recips
+        .par_iter()
+        .zip(seeds)
+        .map(|(pk_recip, seed)| {
+            setup_sender::<A, Kdf, Kem, _>(mode, pk_recip, info, &mut ChaCha20Rng::from_seed(seed))
+        })
+        .collect()
 }
 
 // RFC 9180 §5.1.4
@@ -178,7 +390,10 @@ where
 /// Return Value
 /// ============
 /// On success, returns a decryption context. If an error happened during key decapsulation,
-/// returns `Err(HpkeError::DecapError)`. This is the only possible error.
+/// returns `Err(HpkeError::DecapError)`, or `Err(HpkeError::InvalidEncappedKey)` if the fault is
+/// with `encapped_key`. If `info`, or the PSK or PSK ID in `mode`, is longer than `Kdf` allows,
+/// returns `Err(HpkeError::InputTooLong)`. With the `strict` feature, if `mode` has a PSK that
+/// [`PskBundle::new`](crate::PskBundle::new) would reject, returns `Err(HpkeError::InvalidPsk)`.
 pub fn setup_receiver<A, Kdf, Kem>(
     mode: &OpModeR<Kem>,
     sk_recip: &Kem::PrivateKey,
@@ -190,6 +405,33 @@ where
     Kdf: KdfTrait,
     Kem: KemTrait,
 {
+    let res = setup_receiver_untraced(mode, sk_recip, encapped_key, info);
+    crate::trace::setup::<A, Kdf, Kem>(
+        "setup_receiver",
+        mode.mode_id(),
+        info.len(),
+        res.as_ref().map(|_| ()),
+    );
+    res
+}
+
+/// The body of `setup_receiver`
+fn setup_receiver_untraced<A, Kdf, Kem>(
+    mode: &OpModeR<Kem>,
+    sk_recip: &Kem::PrivateKey,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    check_input_lens::<Kdf, Kem, _>(mode, info)?;
+    // Refuse weak PSKs in strict mode
+    #[cfg(feature = "strict")]
+    mode.verify_psk_inputs()?;
+
     // If the identity key is set, use it
     let pk_sender_id: Option<&Kem::PublicKey> = mode.get_pk_sender_id();
     // Do the decapsulation
@@ -200,11 +442,86 @@ where
     Ok(enc_ctx.into())
 }
 
+/// Does the rest of a `setup_sender` with a shared secret that was encapsulated elsewhere, e.g.,
+/// one DH operation at a time
+pub(crate) fn setup_sender_from_shared_secret<A, Kdf, Kem>(
+    mode: &OpModeS<Kem>,
+    shared_secret: SharedSecret<Kem>,
+    info: &[u8],
+) -> Result<AeadCtxS<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    check_input_lens::<Kdf, Kem, _>(mode, info)?;
+    #[cfg(feature = "strict")]
+    mode.verify_psk_inputs()?;
+
+    let enc_ctx = derive_enc_ctx::<_, _, Kem, _>(mode, shared_secret, info);
+    Ok(enc_ctx.into())
+}
+
+/// Does the rest of a `setup_receiver` with a shared secret that was decapsulated elsewhere, e.g.,
+/// by an agent or an enclave that holds the private key
+pub(crate) fn setup_receiver_from_shared_secret<A, Kdf, Kem>(
+    mode: &OpModeR<Kem>,
+    shared_secret: SharedSecret<Kem>,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    check_input_lens::<Kdf, Kem, _>(mode, info)?;
+    #[cfg(feature = "strict")]
+    mode.verify_psk_inputs()?;
+
+    let enc_ctx = derive_enc_ctx::<_, _, Kem, _>(mode, shared_secret, info);
+    Ok(enc_ctx.into())
+}
+
+/// Does a [`setup_receiver`] in PSK or AuthPSK mode, getting the PSK with ID `psk_id` from
+/// `psk_store`. This is for receivers that only learn which PSK a sender uses from the message
+/// itself. If `pk_sender_id` is given, the mode is AuthPSK, and otherwise PSK.
+///
+/// Return Value
+/// ============
+/// On success, returns a decryption context. If `psk_store` has no PSK with ID `psk_id`, returns
+/// `Err(HpkeError::UnknownKeyId)`. If the PSK or `psk_id` is empty, returns
+/// `Err(HpkeError::InvalidPsk)`. Otherwise, the errors are those of `setup_receiver`.
+pub fn setup_receiver_with_psk_store<A, Kdf, Kem, S>(
+    psk_store: &S,
+    psk_id: &[u8],
+    pk_sender_id: Option<&Kem::PublicKey>,
+    sk_recip: &Kem::PrivateKey,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+) -> Result<AeadCtxR<A, Kdf, Kem>, HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    S: PskStore + ?Sized,
+{
+    let psk = psk_store.lookup(psk_id).ok_or(HpkeError::UnknownKeyId)?;
+    let bundle = PskBundle::new(psk.as_ref(), psk_id)?;
+    let mode = match pk_sender_id {
+        Some(pk) => OpModeR::AuthPsk(pk.clone(), bundle),
+        None => OpModeR::Psk(bundle),
+    };
+
+    setup_receiver(&mode, sk_recip, encapped_key, info)
+}
+
 #[cfg(test)]
 mod test {
     use super::{setup_receiver, setup_sender};
+    #[cfg(any(feature = "x25519", feature = "p256"))]
+    use crate::kdf::HkdfSha256;
     use crate::test_util::{aead_ctx_eq, gen_rand_buf, new_op_mode_pair, OpModeKind};
-    use crate::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::Kem as KemTrait};
+    use crate::{aead::ChaCha20Poly1305, kem::Kem as KemTrait};
 
     use rand::{rngs::StdRng, SeedableRng};
 
@@ -235,7 +552,7 @@ mod test {
                     // Generate a mutually agreeing op mode pair
                     let (psk, psk_id) = (gen_rand_buf(), gen_rand_buf());
                     let (sender_mode, receiver_mode) =
-                        new_op_mode_pair::<Kdf, Kem>(*op_mode_kind, &psk, &psk_id);
+                        new_op_mode_pair::<Kem>(*op_mode_kind, &psk, &psk_id);
 
                     // Construct the sender's encryption context, and get an encapped key
                     let (encapped_key, mut aead_ctx1) = setup_sender::<A, Kdf, Kem, _>(
@@ -281,7 +598,7 @@ mod test {
                 // Generate a mutually agreeing op mode pair
                 let (psk, psk_id) = (gen_rand_buf(), gen_rand_buf());
                 let (sender_mode, receiver_mode) =
-                    new_op_mode_pair::<Kdf, Kem>(OpModeKind::Base, &psk, &psk_id);
+                    new_op_mode_pair::<Kem>(OpModeKind::Base, &psk, &psk_id);
 
                 // Construct the sender's encryption context normally
                 let (encapped_key, sender_ctx) =
@@ -337,6 +654,96 @@ mod test {
         };
     }
 
+    /// Tests that `setup_sender_batch` makes a working context for every recipient, and reports
+    /// failures per recipient
+    #[cfg(all(feature = "parallel", feature = "x25519"))]
+    #[test]
+    fn test_setup_sender_batch() {
+        use super::setup_sender_batch;
+        use crate::{kem::X25519HkdfSha256, Deserializable, HpkeError, OpModeR, OpModeS};
+
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let info = b"group message";
+        let mut keypairs: crate::Vec<_> = (0..8).map(|_| Kem::gen_keypair(&mut csprng)).collect();
+
+        // Make recipient 5's pubkey the all-zero point, which fails encapsulation
+        keypairs[5].1 = <Kem as KemTrait>::PublicKey::from_bytes(&[0u8; 32]).unwrap();
+        let pk_recips: crate::Vec<_> = keypairs.iter().map(|(_, pk)| pk.clone()).collect();
+
+        let results =
+            setup_sender_batch::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recips, info, &mut csprng);
+        assert_eq!(results.len(), keypairs.len());
+
+        for (i, (res, (sk_recip, _))) in results.into_iter().zip(keypairs.iter()).enumerate() {
+            if i == 5 {
+                assert_eq!(res.err(), Some(HpkeError::EncapError));
+                continue;
+            }
+
+            // Every other recipient can open what's sealed to them
+            let (encapped_key, mut sender_ctx) = res.unwrap();
+            let mut receiver_ctx =
+                setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, sk_recip, &encapped_key, info)
+                    .unwrap();
+            let ciphertext = sender_ctx.seal(b"hello all", b"").unwrap();
+            assert_eq!(receiver_ctx.open(&ciphertext, b"").unwrap(), b"hello all");
+        }
+    }
+
+    /// Tests that `PskBundle::new` rejects empty inputs, and that the `strict` feature makes it and
+    /// setup reject weak PSKs
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_psk_validation() {
+        use crate::{kem::X25519HkdfSha256, HpkeError, OpModeS, PskBundle};
+        use subtle::ConstantTimeEq;
+
+        type Kem = X25519HkdfSha256;
+
+        let psk = gen_rand_buf();
+        let psk_id = b"psk id";
+        let bundle = PskBundle::new(&psk, psk_id).unwrap();
+        assert!(bool::from(bundle.ct_eq(&bundle)));
+        assert!(!bool::from(bundle.ct_eq(&PskBundle {
+            psk: &psk,
+            psk_id: b"psk ie"
+        })));
+        assert_eq!(
+            PskBundle::new(&[], psk_id).err(),
+            Some(HpkeError::InvalidPsk)
+        );
+        assert_eq!(PskBundle::new(&psk, &[]).err(), Some(HpkeError::InvalidPsk));
+
+        // Short and all-zero PSKs are only rejected in strict mode
+        let zero_psk = [0u8; 32];
+        let strict = cfg!(feature = "strict");
+        assert_eq!(PskBundle::new(&psk[..16], psk_id).is_err(), strict);
+        assert_eq!(PskBundle::new(&zero_psk, psk_id).is_err(), strict);
+
+        // Strict mode also catches bundles that were built directly
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let mode = OpModeS::<Kem>::Psk(PskBundle {
+            psk: &zero_psk,
+            psk_id,
+        });
+        let res = setup_sender::<ChaCha20Poly1305, HkdfSha256, Kem, _>(
+            &mode,
+            &pk_recip,
+            b"",
+            &mut csprng,
+        );
+        if strict {
+            assert_eq!(res.err(), Some(HpkeError::InvalidPsk));
+        } else {
+            assert!(res.is_ok());
+        }
+    }
+
     #[cfg(feature = "x25519")]
     mod x25519_tests {
         use super::*;
@@ -392,6 +799,138 @@ mod test {
         );
     }
 
+    /// Tests that a receiver using a PSK store gets the same context as one given the PSK up
+    /// front, and refuses unknown PSK IDs
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_setup_receiver_with_psk_store() {
+        use super::setup_receiver_with_psk_store;
+        use crate::{kem::X25519HkdfSha256, HpkeError, OpModeS, PskBundle};
+
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        // Not a repeated byte, so that the strict feature takes them
+        let psk = |start: u8| core::array::from_fn::<u8, 32, _>(|i| start + i as u8);
+        let psks: [(&[u8], [u8; 32]); 2] = [(b"alice", psk(0)), (b"bob", psk(100))];
+        let store = |psk_id: &[u8]| {
+            psks.iter()
+                .find(|(id, _)| *id == psk_id)
+                .map(|(_, psk)| &psk[..])
+        };
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (sk_sender_id, pk_sender_id) = Kem::gen_keypair(&mut csprng);
+        let bundle = PskBundle::new(&psks[1].1, b"bob").unwrap();
+
+        // PSK mode
+        let (encapped_key, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Psk(bundle), &pk_recip, b"", &mut csprng)
+                .unwrap();
+        let mut receiver_ctx = setup_receiver_with_psk_store::<A, Kdf, Kem, _>(
+            &store,
+            b"bob",
+            None,
+            &sk_recip,
+            &encapped_key,
+            b"",
+        )
+        .unwrap();
+        assert!(aead_ctx_eq(&mut sender_ctx, &mut receiver_ctx));
+
+        // AuthPSK mode
+        let mode = OpModeS::AuthPsk((sk_sender_id, pk_sender_id.clone()), bundle);
+        let (encapped_key, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&mode, &pk_recip, b"", &mut csprng).unwrap();
+        let mut receiver_ctx = setup_receiver_with_psk_store::<A, Kdf, Kem, _>(
+            &store,
+            b"bob",
+            Some(&pk_sender_id),
+            &sk_recip,
+            &encapped_key,
+            b"",
+        )
+        .unwrap();
+        assert!(aead_ctx_eq(&mut sender_ctx, &mut receiver_ctx));
+
+        let res = setup_receiver_with_psk_store::<A, Kdf, Kem, _>(
+            &store,
+            b"carol",
+            None,
+            &sk_recip,
+            &encapped_key,
+            b"",
+        );
+        assert_eq!(res.err(), Some(HpkeError::UnknownKeyId));
+    }
+
+    /// Tests that both sides get the same context ID, that it differs between contexts with the
+    /// same parameters, and that the key schedule context only depends on the parameters
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_context_id() {
+        use super::key_schedule_context;
+        use crate::{kem::X25519HkdfSha256, OpModeR, OpModeS};
+
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+
+        let mut ids = [[0u8; 32]; 2];
+        for id in ids.iter_mut() {
+            let (encapped_key, sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"info", &mut csprng)
+                    .unwrap();
+            let receiver_ctx =
+                setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk_recip, &encapped_key, b"info")
+                    .unwrap();
+            assert_eq!(sender_ctx.context_id(), receiver_ctx.context_id());
+            id.copy_from_slice(&sender_ctx.context_id());
+
+            // The ID is not an export
+            let mut exported = [0u8; 32];
+            sender_ctx.export(b"", &mut exported).unwrap();
+            assert_ne!(*id, exported);
+        }
+        assert_ne!(ids[0], ids[1]);
+
+        let ctx = key_schedule_context::<A, Kdf, Kem>(0, b"", b"info");
+        assert_eq!(ctx.len(), 1 + 2 * 32);
+        assert_eq!(ctx[0], 0);
+        assert_eq!(ctx, key_schedule_context::<A, Kdf, Kem>(0, b"", b"info"));
+        assert_ne!(ctx, key_schedule_context::<A, Kdf, Kem>(0, b"", b"other"));
+    }
+
+    /// Tests that a sender context keeps the encapsulated key it was set up with, and that
+    /// contexts derived from it don't claim one
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_sender_ctx_encapped_key() {
+        use crate::{kem::X25519HkdfSha256, OpModeS, Serializable};
+
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let (encapped_key, mut sender_ctx) =
+            setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, b"", &mut csprng).unwrap();
+
+        let stored = sender_ctx.encapped_key().unwrap();
+        assert_eq!(stored.to_bytes(), encapped_key.to_bytes());
+        assert!(sender_ctx
+            .fork_for_broadcast(b"fork")
+            .unwrap()
+            .encapped_key()
+            .is_none());
+    }
+
     #[cfg(feature = "p521")]
     mod p521_tests {
         use super::*;
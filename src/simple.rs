@@ -0,0 +1,298 @@
+//! A one-type front end for the common case: single-shot encryption in base mode
+
+use crate::{
+    aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, single_shot_open_in_place,
+    single_shot_seal_in_place, HpkeError, OpModeR, OpModeS, SuiteParams,
+};
+#[cfg(any(feature = "alloc", feature = "std"))]
+use crate::{single_shot_open, single_shot_seal};
+
+use core::marker::PhantomData;
+
+use rand_core::{CryptoRng, RngCore};
+
+/// An HPKE ciphersuite, with the KEM, KDF, and AEAD in the order of RFC 9180's suite IDs. Its
+/// functions do single-shot encryption to a public key in base mode, i.e., with no sender
+/// authentication and no PSK. For anything else, use [`setup_sender`](crate::setup_sender) and
+/// friends.
+///
+/// Example
+/// =======
+/// ```
+/// # #[cfg(all(feature = "x25519", any(feature = "alloc", feature = "std")))] {
+/// # use rand::{rngs::StdRng, SeedableRng};
+/// use hpke::HpkeX25519;
+///
+/// let mut csprng = StdRng::from_entropy();
+/// let (sk, pk) = HpkeX25519::gen_keypair(&mut csprng);
+/// let (enc, ct) = HpkeX25519::seal(&pk, b"info", b"aad", b"hello", &mut csprng).unwrap();
+/// let pt = HpkeX25519::open(&sk, &enc, b"info", b"aad", &ct).unwrap();
+/// assert_eq!(pt, b"hello");
+/// # }
+/// ```
+pub struct Hpke<Kem: KemTrait, Kdf: KdfTrait, A: Aead>(PhantomData<(Kem, Kdf, A)>);
+
+/// X25519 with HKDF-SHA256 and ChaCha20Poly1305
+#[cfg(feature = "x25519")]
+#[cfg_attr(docsrs, doc(cfg(feature = "x25519")))]
+pub type HpkeX25519 =
+    Hpke<crate::kem::X25519HkdfSha256, crate::kdf::HkdfSha256, crate::aead::ChaCha20Poly1305>;
+
+/// P-256 with HKDF-SHA256 and AES-128-GCM
+#[cfg(feature = "p256")]
+#[cfg_attr(docsrs, doc(cfg(feature = "p256")))]
+pub type HpkeP256 =
+    Hpke<crate::kem::DhP256HkdfSha256, crate::kdf::HkdfSha256, crate::aead::AesGcm128>;
+
+impl<Kem: KemTrait, Kdf: KdfTrait, A: Aead> Hpke<Kem, Kdf, A> {
+    /// The KEM's algorithm identifier
+    pub const KEM_ID: u16 = Kem::KEM_ID;
+    /// The KDF's algorithm identifier
+    pub const KDF_ID: u16 = Kdf::KDF_ID;
+    /// The AEAD's algorithm identifier
+    pub const AEAD_ID: u16 = A::AEAD_ID;
+    /// The size of an encapsulated key
+    pub const ENCAPPED_KEY_SIZE: usize = Kem::ENCAPPED_KEY_SIZE;
+    /// The size of an AEAD tag
+    pub const TAG_SIZE: usize = A::TAG_SIZE;
+    /// The number of bytes that [`seal`](Self::seal) adds to a plaintext, i.e., the encapsulated
+    /// key and the tag
+    pub const OVERHEAD: usize = Kem::ENCAPPED_KEY_SIZE + A::TAG_SIZE;
+    /// The rest of this suite's sizes and limits
+    pub const PARAMS: SuiteParams = SuiteParams::new::<A, Kdf, Kem>();
+
+    /// Whether [`known_answer_test`](Self::known_answer_test) has a locked answer for this suite.
+    /// [`assert_kat!`](crate::assert_kat) checks this at compile time.
+    #[doc(hidden)]
+    pub const HAS_LOCKED_ANSWER: bool =
+        crate::self_test::has_locked_answer(Kem::KEM_ID, Kdf::KDF_ID, A::AEAD_ID);
+
+    /// Runs this suite with fixed inputs and checks that the encapsulated key, ciphertext, and
+    /// exported secret are the ones this crate recorded for it. This catches any change in what
+    /// the suite does, e.g., after upgrading this crate or a dependency. It doesn't use any
+    /// randomness, and doesn't allocate.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns a [`SelfTestEntry`](crate::SelfTestEntry) whose `result` is `Ok(())` if everything
+    /// matched, and otherwise the step that didn't.
+    pub fn known_answer_test() -> crate::SelfTestEntry {
+        crate::self_test::locked_answer::<A, Kdf, Kem>()
+    }
+
+    /// Generates a random keypair for the KEM. This is [`Kem::gen_keypair`](KemTrait::gen_keypair).
+    pub fn gen_keypair<R: CryptoRng + RngCore>(
+        csprng: &mut R,
+    ) -> (Kem::PrivateKey, Kem::PublicKey) {
+        Kem::gen_keypair(csprng)
+    }
+
+    /// Encrypts `plaintext` to `pk_recip`, authenticating `aad` along with it. This is
+    /// [`single_shot_seal`] in base mode.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok((encapped_key, ciphertext))` on success. Send both to the recipient. The errors
+    /// are those of `single_shot_seal`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn seal<R: CryptoRng + RngCore>(
+        pk_recip: &Kem::PublicKey,
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+        csprng: &mut R,
+    ) -> Result<(Kem::EncappedKey, crate::Vec<u8>), HpkeError> {
+        single_shot_seal::<A, Kdf, Kem, R>(&OpModeS::Base, pk_recip, info, plaintext, aad, csprng)
+    }
+
+    /// Decrypts a ciphertext made by [`Hpke::seal`] with the same `info` and `aad`. This is
+    /// [`single_shot_open`] in base mode.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success. The errors are those of `single_shot_open`.
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "alloc", feature = "std"))))]
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    pub fn open(
+        sk_recip: &Kem::PrivateKey,
+        encapped_key: &Kem::EncappedKey,
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<crate::Vec<u8>, HpkeError> {
+        single_shot_open::<A, Kdf, Kem>(
+            &OpModeR::Base,
+            sk_recip,
+            encapped_key,
+            info,
+            ciphertext,
+            aad,
+        )
+    }
+
+    /// Does what [`Hpke::seal`] does, in a caller-provided buffer, without allocating. This is
+    /// [`single_shot_seal_in_place`](crate::single_shot_seal_in_place) in base mode. `buf`
+    /// starts with the `plaintext_len`-byte plaintext, and must have room for the tag after it.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok((encapped_key, ciphertext_len))` on success, where `buf[..ciphertext_len]` is
+    /// the ciphertext. The errors are those of `single_shot_seal_in_place`.
+    pub fn seal_in_place<R: CryptoRng + RngCore>(
+        pk_recip: &Kem::PublicKey,
+        info: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        plaintext_len: usize,
+        csprng: &mut R,
+    ) -> Result<(Kem::EncappedKey, usize), HpkeError> {
+        single_shot_seal_in_place::<A, Kdf, Kem, R>(
+            &OpModeS::Base,
+            pk_recip,
+            info,
+            buf,
+            plaintext_len,
+            aad,
+            csprng,
+        )
+    }
+
+    /// Does what [`Hpke::open`] does, in place, without allocating. This is
+    /// [`single_shot_open_in_place`](crate::single_shot_open_in_place) in base mode.
+    ///
+    /// Return Value
+    /// ============
+    /// Returns `Ok(plaintext)` on success, where `plaintext` is the start of `ciphertext`. The
+    /// errors are those of `single_shot_open_in_place`.
+    pub fn open_in_place<'a>(
+        sk_recip: &Kem::PrivateKey,
+        encapped_key: &Kem::EncappedKey,
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &'a mut [u8],
+    ) -> Result<&'a mut [u8], HpkeError> {
+        single_shot_open_in_place::<A, Kdf, Kem>(
+            &OpModeR::Base,
+            sk_recip,
+            encapped_key,
+            info,
+            ciphertext,
+            aad,
+        )
+    }
+}
+
+/// Defines a type alias for an [`Hpke`] ciphersuite, for code that names the same suite in many
+/// places. The alias has all of `Hpke`'s functions and constants, e.g., `KEM_ID` and `OVERHEAD`.
+/// Attributes and a visibility go before the name. If the KEM, KDF, or AEAD isn't one, the error
+/// is reported here, rather than at the alias's first use.
+///
+/// Example
+/// =======
+/// ```
+/// # #[cfg(feature = "x25519")] {
+/// use hpke::{aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256};
+///
+/// hpke::hpke_suite!(
+///     /// The suite of our wire protocol
+///     pub Wire: kem = X25519HkdfSha256, kdf = HkdfSha256, aead = ChaCha20Poly1305
+/// );
+///
+/// assert_eq!(Wire::AEAD_ID, 0x0003);
+/// let packet_buf = [0u8; 32 + Wire::OVERHEAD];
+/// # }
+/// ```
+#[macro_export]
+macro_rules! hpke_suite {
+    (
+        $(#[$attr:meta])*
+        $vis:vis $name:ident: kem = $kem:ty, kdf = $kdf:ty, aead = $aead:ty $(,)?
+    ) => {
+        $(#[$attr])*
+        $vis type $name = $crate::Hpke<$kem, $kdf, $aead>;
+
+        // Type aliases don't check bounds, so make sure this names a real suite
+        $(#[$attr])*
+        const _: usize = <$name>::OVERHEAD;
+    };
+}
+
+#[cfg(all(test, any(feature = "alloc", feature = "std")))]
+mod tests {
+    use super::*;
+    use crate::{aead::AesGcm256, kdf::HkdfSha384, HpkeError};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Tests that `Hpke::open` opens what `Hpke::seal` seals, and refuses the wrong `info` or
+    /// `aad`
+    fn test_hpke_roundtrip<Kem: KemTrait, Kdf: KdfTrait, A: Aead>() {
+        let mut csprng = StdRng::from_entropy();
+        let (sk, pk) = Hpke::<Kem, Kdf, A>::gen_keypair(&mut csprng);
+        let (enc, ct) =
+            Hpke::<Kem, Kdf, A>::seal(&pk, b"info", b"aad", b"plaintext", &mut csprng).unwrap();
+
+        let pt = Hpke::<Kem, Kdf, A>::open(&sk, &enc, b"info", b"aad", &ct).unwrap();
+        assert_eq!(pt, b"plaintext");
+        assert_eq!(
+            Hpke::<Kem, Kdf, A>::open(&sk, &enc, b"other", b"aad", &ct),
+            Err(HpkeError::OpenError)
+        );
+        assert_eq!(
+            Hpke::<Kem, Kdf, A>::open(&sk, &enc, b"info", b"other", &ct),
+            Err(HpkeError::OpenError)
+        );
+    }
+
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_hpke_x25519() {
+        test_hpke_roundtrip::<crate::kem::X25519HkdfSha256, HkdfSha384, AesGcm256>();
+    }
+
+    #[cfg(feature = "p256")]
+    #[test]
+    fn test_hpke_p256() {
+        test_hpke_roundtrip::<crate::kem::DhP256HkdfSha256, HkdfSha384, AesGcm256>();
+    }
+
+    #[cfg(feature = "p384")]
+    #[test]
+    fn test_hpke_p384() {
+        test_hpke_roundtrip::<crate::kem::DhP384HkdfSha384, HkdfSha384, AesGcm256>();
+    }
+
+    #[cfg(feature = "p521")]
+    #[test]
+    fn test_hpke_p521() {
+        test_hpke_roundtrip::<crate::kem::DhP521HkdfSha512, HkdfSha384, AesGcm256>();
+    }
+
+    crate::hpke_suite!(
+        #[cfg(feature = "p256")]
+        P256Sha384: kem = crate::kem::DhP256HkdfSha256, kdf = HkdfSha384, aead = AesGcm256,
+    );
+
+    /// Tests that a suite defined with `hpke_suite!` is the `Hpke` it names, and that its
+    /// overhead is what sealing adds
+    #[cfg(feature = "p256")]
+    #[test]
+    fn test_hpke_suite() {
+        use crate::Serializable;
+
+        assert_eq!(
+            (P256Sha384::KEM_ID, P256Sha384::KDF_ID, P256Sha384::AEAD_ID),
+            (0x0010, 0x0002, 0x0002)
+        );
+        assert_eq!(P256Sha384::PARAMS.digest_size, 48);
+
+        let mut csprng = StdRng::from_entropy();
+        let (_, pk) = P256Sha384::gen_keypair(&mut csprng);
+        let (enc, ct) = P256Sha384::seal(&pk, b"info", b"aad", b"plaintext", &mut csprng).unwrap();
+        assert_eq!(
+            enc.to_bytes().len() + ct.len(),
+            b"plaintext".len() + P256Sha384::OVERHEAD
+        );
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
     kem::Kem as KemTrait,
     op_mode::{OpModeR, OpModeS},
     setup::{setup_receiver, setup_sender},
-    HpkeError,
+    HpkeError, Serializable,
 };
 
 use rand_core::{CryptoRng, RngCore};
@@ -47,6 +47,46 @@ where
     Ok((encapped_key, tag))
 }
 
+/// Does a `setup_sender` and a seal in one shot, in a caller-provided buffer. `buf` starts with
+/// the `plaintext_len`-byte plaintext, and must have room for the tag after it. On success, the
+/// plaintext is overwritten by the ciphertext, followed by the tag. Nothing is allocated.
+///
+/// Return Value
+/// ============
+/// Returns `Ok((encapped_key, ciphertext_len))` on success, where `buf[..ciphertext_len]` is the
+/// ciphertext and tag. If `buf` is shorter than `plaintext_len` plus the tag size, returns
+/// `Err(HpkeError::IncorrectInputLength(needed, buf.len()))` without encapsulating. Otherwise, the
+/// errors are those of `single_shot_seal_in_place_detached`.
+pub fn single_shot_seal_in_place<A, Kdf, Kem, R>(
+    mode: &OpModeS<Kem>,
+    pk_recip: &Kem::PublicKey,
+    info: &[u8],
+    buf: &mut [u8],
+    plaintext_len: usize,
+    aad: &[u8],
+    csprng: &mut R,
+) -> Result<(Kem::EncappedKey, usize), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    // Check that the tag fits before doing any work
+    let ciphertext_len = plaintext_len.saturating_add(AeadTag::<A>::size());
+    if buf.len() < ciphertext_len {
+        return Err(HpkeError::IncorrectInputLength(ciphertext_len, buf.len()));
+    }
+
+    let (plaintext, rest) = buf.split_at_mut(plaintext_len);
+    let (encapped_key, tag) = single_shot_seal_in_place_detached::<A, Kdf, Kem, R>(
+        mode, pk_recip, info, plaintext, aad, csprng,
+    )?;
+    tag.write_exact(&mut rest[..AeadTag::<A>::size()]);
+
+    Ok((encapped_key, ciphertext_len))
+}
+
 /// Does a `setup_sender` and `AeadCtxS::seal` in one shot. That is, it does a key encapsulation to
 /// the specified recipient and encrypts the provided plaintext. See `setup::setup_sender` and
 /// `AeadCtxS::seal` for more detail.
@@ -115,6 +155,36 @@ where
     aead_ctx.open_in_place_detached(ciphertext, aad, tag)
 }
 
+/// Does a `setup_receiver` and `AeadCtxR::open_in_place_combined` in one shot. This opens the
+/// output of `single_shot_seal_in_place`, i.e., a ciphertext with the tag appended, in place.
+/// Nothing is allocated.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(plaintext)` on success, where `plaintext` is the start of `ciphertext_and_tag`. If
+/// an error happened during key decapsulation, returns `Err(HpkeError::DecapError)`. If
+/// `ciphertext_and_tag` is too short to contain a tag, or an error happened during decryption,
+/// returns `Err(HpkeError::OpenError)`. In this case, the contents of `ciphertext_and_tag` is
+/// undefined.
+pub fn single_shot_open_in_place<'a, A, Kdf, Kem>(
+    mode: &OpModeR<Kem>,
+    sk_recip: &Kem::PrivateKey,
+    encapped_key: &Kem::EncappedKey,
+    info: &[u8],
+    ciphertext_and_tag: &'a mut [u8],
+    aad: &[u8],
+) -> Result<&'a mut [u8], HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    // Decap the key
+    let mut aead_ctx = setup_receiver::<A, Kdf, Kem>(mode, sk_recip, encapped_key, info)?;
+    // Decrypt
+    aead_ctx.open_in_place_combined(ciphertext_and_tag, aad)
+}
+
 /// Does a `setup_receiver` and `AeadCtxR::open` in one shot. That is, it does a key decapsulation
 /// for the specified recipient and decrypts the provided ciphertext. See `setup::setup_reciever`
 /// and `AeadCtxR::open` for more detail.
@@ -145,10 +215,125 @@ where
     aead_ctx.open(ciphertext, aad)
 }
 
+/// A message for `single_shot_seal_batch` to seal
+#[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+#[cfg(feature = "parallel")]
+pub struct SealJob<'a, Kem: KemTrait> {
+    /// The public key of the recipient
+    pub pk_recip: &'a Kem::PublicKey,
+    /// The message to encrypt
+    pub plaintext: &'a [u8],
+    /// The associated data to authenticate
+    pub aad: &'a [u8],
+}
+
+/// A ciphertext for `single_shot_open_batch` to open
+#[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+#[cfg(feature = "parallel")]
+pub struct OpenJob<'a, Kem: KemTrait> {
+    /// The encapsulated key that came with the ciphertext
+    pub encapped_key: &'a Kem::EncappedKey,
+    /// The ciphertext to decrypt
+    pub ciphertext: &'a [u8],
+    /// The associated data to authenticate
+    pub aad: &'a [u8],
+}
+
+/// Does a `single_shot_seal` on every job in `jobs`, in parallel. Every job gets its own
+/// encapsulation, so the jobs are completely independent of each other. See `single_shot_seal`
+/// for more detail.
+///
+/// Randomness is drawn from `csprng` up front, one seed per job, so `csprng` itself is never
+/// shared across threads.
+///
+/// Return Value
+/// ============
+/// Returns one result per job, in the same order as `jobs`. Each result is what
+/// `single_shot_seal` would have returned for that job.
+#[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+#[cfg(feature = "parallel")]
+pub fn single_shot_seal_batch<A, Kdf, Kem, R>(
+    mode: &OpModeS<Kem>,
+    info: &[u8],
+    jobs: &[SealJob<Kem>],
+    csprng: &mut R,
+) -> crate::Vec<Result<(Kem::EncappedKey, crate::Vec<u8>), HpkeError>>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    Kem::PublicKey: Sync,
+    Kem::PrivateKey: Sync,
+    Kem::EncappedKey: Send,
+    R: CryptoRng + RngCore,
+{
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+    use rayon::prelude::*;
+
+    // Seed one RNG per job
+    let seeds = crate::util::draw_seeds(jobs.len(), csprng);
+
+    jobs.par_iter()
+        .zip(seeds)
+        .map(|(job, seed)| {
+            single_shot_seal::<A, Kdf, Kem, _>(
+                mode,
+                job.pk_recip,
+                info,
+                job.plaintext,
+                job.aad,
+                &mut ChaCha20Rng::from_seed(seed),
+            )
+        })
+        .collect()
+}
+
+/// Does a `single_shot_open` on every job in `jobs`, in parallel. See `single_shot_open` for more
+/// detail.
+///
+/// Return Value
+/// ============
+/// Returns one result per job, in the same order as `jobs`. Each result is what
+/// `single_shot_open` would have returned for that job.
+#[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+#[cfg(feature = "parallel")]
+pub fn single_shot_open_batch<A, Kdf, Kem>(
+    mode: &OpModeR<Kem>,
+    sk_recip: &Kem::PrivateKey,
+    info: &[u8],
+    jobs: &[OpenJob<Kem>],
+) -> crate::Vec<Result<crate::Vec<u8>, HpkeError>>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    Kem::PublicKey: Sync,
+    Kem::PrivateKey: Sync,
+    Kem::EncappedKey: Sync,
+{
+    use rayon::prelude::*;
+
+    jobs.par_iter()
+        .map(|job| {
+            single_shot_open::<A, Kdf, Kem>(
+                mode,
+                sk_recip,
+                job.encapped_key,
+                info,
+                job.ciphertext,
+                job.aad,
+            )
+        })
+        .collect()
+}
+
 #[cfg(any(feature = "alloc", feature = "std"))]
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(feature = "parallel")]
+    use crate::Vec;
     use crate::{
         aead::ChaCha20Poly1305,
         kem::Kem as KemTrait,
@@ -224,6 +409,161 @@ mod test {
         };
     }
 
+    /// Tests that `single_shot_open_in_place` opens what `single_shot_seal_in_place` seals, and
+    /// that a buffer with no room for the tag is refused
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_single_shot_in_place() {
+        type A = ChaCha20Poly1305;
+        type Kdf = crate::kdf::HkdfSha256;
+        type Kem = crate::kem::X25519HkdfSha256;
+
+        let msg = b"sealed where it lies";
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+
+        let mut buf = [0u8; 64];
+        buf[..msg.len()].copy_from_slice(msg);
+        let (encapped_key, ciphertext_len) = single_shot_seal_in_place::<A, Kdf, Kem, _>(
+            &OpModeS::Base,
+            &pk_recip,
+            b"info",
+            &mut buf,
+            msg.len(),
+            b"aad",
+            &mut csprng,
+        )
+        .unwrap();
+        assert_eq!(ciphertext_len, msg.len() + 16);
+        assert!(&buf[..msg.len()] != msg);
+
+        let plaintext = single_shot_open_in_place::<A, Kdf, Kem>(
+            &OpModeR::Base,
+            &sk_recip,
+            &encapped_key,
+            b"info",
+            &mut buf[..ciphertext_len],
+            b"aad",
+        )
+        .unwrap();
+        assert_eq!(plaintext, msg);
+
+        // There's no room for the tag
+        let mut buf = *msg;
+        assert_eq!(
+            single_shot_seal_in_place::<A, Kdf, Kem, _>(
+                &OpModeS::Base,
+                &pk_recip,
+                b"info",
+                &mut buf,
+                msg.len(),
+                b"aad",
+                &mut csprng,
+            )
+            .map(|(_, len)| len),
+            Err(HpkeError::IncorrectInputLength(msg.len() + 16, msg.len()))
+        );
+    }
+
+    /// Tests that `single_shot_open_batch` opens what `single_shot_seal_batch` seals, and that a
+    /// bad ciphertext only fails its own job
+    #[cfg(feature = "parallel")]
+    macro_rules! test_batch_correctness {
+        ($test_name:ident, $aead:ty, $kdf:ty, $kem:ty) => {
+            #[test]
+            fn $test_name() {
+                type A = $aead;
+                type Kdf = $kdf;
+                type Kem = $kem;
+
+                let mut csprng = StdRng::from_entropy();
+                let info = b"batch of messages";
+                let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+
+                let msgs: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; 100]).collect();
+                let seal_jobs: Vec<SealJob<Kem>> = msgs
+                    .iter()
+                    .map(|msg| SealJob {
+                        pk_recip: &pk_recip,
+                        plaintext: msg,
+                        aad: b"aad",
+                    })
+                    .collect();
+                let sealed: Vec<_> = single_shot_seal_batch::<A, Kdf, Kem, _>(
+                    &OpModeS::Base,
+                    info,
+                    &seal_jobs,
+                    &mut csprng,
+                )
+                .into_iter()
+                .map(|res| res.expect("seal failed"))
+                .collect();
+
+                // Corrupt the ciphertext of message 3
+                let mut ciphertexts: Vec<Vec<u8>> =
+                    sealed.iter().map(|(_, ct)| ct.clone()).collect();
+                ciphertexts[3][0] ^= 1;
+
+                let open_jobs: Vec<OpenJob<Kem>> = sealed
+                    .iter()
+                    .zip(ciphertexts.iter())
+                    .map(|((encapped_key, _), ct)| OpenJob {
+                        encapped_key,
+                        ciphertext: ct,
+                        aad: b"aad",
+                    })
+                    .collect();
+                let opened = single_shot_open_batch::<A, Kdf, Kem>(
+                    &OpModeR::Base,
+                    &sk_recip,
+                    info,
+                    &open_jobs,
+                );
+
+                assert_eq!(opened.len(), msgs.len());
+                for (i, (res, msg)) in opened.into_iter().zip(msgs.iter()).enumerate() {
+                    if i == 3 {
+                        assert_eq!(res, Err(HpkeError::OpenError));
+                    } else {
+                        assert_eq!(&res.unwrap(), msg);
+                    }
+                }
+            }
+        };
+    }
+
+    #[cfg(all(feature = "parallel", feature = "x25519"))]
+    test_batch_correctness!(
+        test_batch_correctness_x25519,
+        ChaCha20Poly1305,
+        crate::kdf::HkdfSha256,
+        crate::kem::x25519_hkdfsha256::X25519HkdfSha256
+    );
+
+    #[cfg(all(feature = "parallel", feature = "p256"))]
+    test_batch_correctness!(
+        test_batch_correctness_p256,
+        ChaCha20Poly1305,
+        crate::kdf::HkdfSha256,
+        crate::kem::dhp256_hkdfsha256::DhP256HkdfSha256
+    );
+
+    #[cfg(all(feature = "parallel", feature = "p384"))]
+    test_batch_correctness!(
+        test_batch_correctness_p384,
+        ChaCha20Poly1305,
+        crate::kdf::HkdfSha384,
+        crate::kem::dhp384_hkdfsha384::DhP384HkdfSha384
+    );
+
+    #[cfg(all(feature = "parallel", feature = "p521"))]
+    test_batch_correctness!(
+        test_batch_correctness_p521,
+        ChaCha20Poly1305,
+        crate::kdf::HkdfSha512,
+        crate::kem::dhp521_hkdfsha512::DhP521HkdfSha512
+    );
+
     #[cfg(feature = "x25519")]
     test_single_shot_correctness!(
         test_single_shot_correctness_x25519,
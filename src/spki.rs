@@ -0,0 +1,179 @@
+//! Encoding recipient public keys as X.509 `SubjectPublicKeyInfo`, for distributing them in
+//! certificates and other PKI formats.
+//!
+//! The algorithm identifiers are those of the KEM's Diffie-Hellman group: `id-X25519` from RFC
+//! 8410 for X25519, and `id-ecPublicKey` with the curve as a named-curve parameter from RFC 5480
+//! for the NIST curves. No OIDs are registered for HPKE itself, so an SPKI says which group a key
+//! is in, but not which KEM or ciphersuite it's for. Agree on those some other way, e.g., in the
+//! certificate's extensions or in the protocol.
+//!
+//! Only DER is accepted, and only the exact encoding [`to_spki_der`] produces, so every key has one
+//! encoding.
+
+use crate::{kem::Kem as KemTrait, Deserializable, HpkeError, Serializable};
+
+/// A KEM whose public keys have an X.509 `SubjectPublicKeyInfo` encoding
+pub trait SpkiKem: KemTrait {
+    /// The DER encoding of the `AlgorithmIdentifier` of the KEM's public keys
+    const SPKI_ALGORITHM: &'static [u8];
+}
+
+#[cfg(feature = "x25519")]
+impl SpkiKem for crate::kem::X25519HkdfSha256 {
+    // SEQUENCE { OID 1.3.101.110 }
+    const SPKI_ALGORITHM: &'static [u8] = &[0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x6e];
+}
+
+#[cfg(feature = "p256")]
+impl SpkiKem for crate::kem::DhP256HkdfSha256 {
+    // SEQUENCE { OID 1.2.840.10045.2.1, OID 1.2.840.10045.3.1.7 }
+    const SPKI_ALGORITHM: &'static [u8] = &[
+        0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86,
+        0x48, 0xce, 0x3d, 0x03, 0x01, 0x07,
+    ];
+}
+
+#[cfg(feature = "p384")]
+impl SpkiKem for crate::kem::DhP384HkdfSha384 {
+    // SEQUENCE { OID 1.2.840.10045.2.1, OID 1.3.132.0.34 }
+    const SPKI_ALGORITHM: &'static [u8] = &[
+        0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b, 0x81,
+        0x04, 0x00, 0x22,
+    ];
+}
+
+#[cfg(feature = "p521")]
+impl SpkiKem for crate::kem::DhP521HkdfSha512 {
+    // SEQUENCE { OID 1.2.840.10045.2.1, OID 1.3.132.0.35 }
+    const SPKI_ALGORITHM: &'static [u8] = &[
+        0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b, 0x81,
+        0x04, 0x00, 0x23,
+    ];
+}
+
+// RFC 5280 §4.1
+//   SubjectPublicKeyInfo  ::=  SEQUENCE  {
+//        algorithm            AlgorithmIdentifier,
+//        subjectPublicKey     BIT STRING  }
+
+/// Returns the DER encoding of `pk` as a `SubjectPublicKeyInfo`
+pub fn to_spki_der<Kem: SpkiKem>(pk: &Kem::PublicKey) -> crate::Vec<u8> {
+    let pk_bytes = pk.to_bytes();
+    let mut der = spki_header::<Kem>(pk_bytes.len());
+    der.extend_from_slice(&pk_bytes);
+    der
+}
+
+/// Parses a public key from the DER encoding of its `SubjectPublicKeyInfo`
+///
+/// Return Value
+/// ============
+/// Returns `Ok(pk)` on success. If `der` isn't as long as the encoding of a key of this KEM,
+/// returns `Err(HpkeError::IncorrectInputLength(expected, der.len()))`. If it has a different
+/// algorithm or isn't in DER, returns `Err(HpkeError::ValidationError)`. Otherwise, the errors
+/// are those of `Kem::PublicKey::from_bytes`.
+pub fn from_spki_der<Kem: SpkiKem>(der: &[u8]) -> Result<Kem::PublicKey, HpkeError> {
+    let pk_len = Kem::PublicKey::size();
+    let header = spki_header::<Kem>(pk_len);
+    let expected_len = header.len() + pk_len;
+    if der.len() != expected_len {
+        return Err(HpkeError::IncorrectInputLength(expected_len, der.len()));
+    }
+
+    let (der_header, pk_bytes) = der.split_at(header.len());
+    if der_header != header.as_slice() {
+        return Err(HpkeError::ValidationError);
+    }
+    Kem::PublicKey::from_bytes(pk_bytes)
+}
+
+/// Returns everything in the SPKI of a `pk_len`-byte public key that comes before the key
+fn spki_header<Kem: SpkiKem>(pk_len: usize) -> crate::Vec<u8> {
+    // The BIT STRING's contents start with the number of unused bits, which is 0
+    let bit_string_len = 1 + pk_len;
+    let mut bit_string_header = [0x03].to_vec();
+    push_der_len(&mut bit_string_header, bit_string_len);
+    bit_string_header.push(0x00);
+
+    let mut header = [0x30].to_vec();
+    push_der_len(
+        &mut header,
+        Kem::SPKI_ALGORITHM.len() + bit_string_header.len() + pk_len,
+    );
+    header.extend_from_slice(Kem::SPKI_ALGORITHM);
+    header.extend_from_slice(&bit_string_header);
+    header
+}
+
+/// Appends the DER encoding of a length, which must be less than 2^16
+fn push_der_len(buf: &mut crate::Vec<u8>, len: usize) {
+    debug_assert!(len < 1 << 16);
+    match len {
+        0..=0x7f => buf.push(len as u8),
+        0x80..=0xff => buf.extend_from_slice(&[0x81, len as u8]),
+        _ => {
+            buf.push(0x82);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    /// Tests against the example key in RFC 8410 §10.1, and that other encodings are refused
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn test_spki_x25519() {
+        use crate::kem::X25519HkdfSha256;
+
+        let der = hex::decode(
+            "302a300506032b656e03210019bf44096984cdfe8541bac167dc3b96c85086aa30b6b6cb0c5c38ad703166e1",
+        )
+        .unwrap();
+        let pk = from_spki_der::<X25519HkdfSha256>(&der).unwrap();
+        assert_eq!(pk.to_bytes().as_slice(), &der[12..]);
+        assert_eq!(to_spki_der::<X25519HkdfSha256>(&pk), der);
+
+        // Flipping any header byte is refused
+        for i in 0..12 {
+            let mut bad = der.clone();
+            bad[i] ^= 1;
+            assert_eq!(
+                from_spki_der::<X25519HkdfSha256>(&bad).err(),
+                Some(HpkeError::ValidationError)
+            );
+        }
+        assert_eq!(
+            from_spki_der::<X25519HkdfSha256>(&der[..43]).err(),
+            Some(HpkeError::IncorrectInputLength(44, 43))
+        );
+    }
+
+    /// Tests round trips for the NIST curves, including P-521, whose lengths need the long form
+    #[cfg(all(feature = "p256", feature = "p521"))]
+    #[test]
+    fn test_spki_nistp() {
+        use crate::kem::{DhP256HkdfSha256, DhP521HkdfSha512};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut csprng = StdRng::from_entropy();
+
+        let (_, pk) = DhP256HkdfSha256::gen_keypair(&mut csprng);
+        let der = to_spki_der::<DhP256HkdfSha256>(&pk);
+        assert_eq!(der[..2], [0x30, 0x59]);
+        assert_eq!(der.len(), 91);
+        assert_eq!(from_spki_der::<DhP256HkdfSha256>(&der).unwrap(), pk);
+
+        let (_, pk) = DhP521HkdfSha512::gen_keypair(&mut csprng);
+        let der = to_spki_der::<DhP521HkdfSha512>(&pk);
+        assert_eq!(der[..3], [0x30, 0x81, 0x9b]);
+        assert_eq!(der[21..24], [0x03, 0x81, 0x86]);
+        assert_eq!(from_spki_der::<DhP521HkdfSha512>(&der).unwrap(), pk);
+
+        // A key of one curve doesn't parse as another's
+        assert!(from_spki_der::<DhP256HkdfSha256>(&der).is_err());
+    }
+}
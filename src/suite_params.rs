@@ -0,0 +1,100 @@
+use crate::{aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait};
+
+use aead::{AeadCore as BaseAeadCore, KeySizeUser as BaseKeySizeUser};
+use generic_array::typenum::Unsigned;
+
+/// The sizes and limits of a ciphersuite. This lets a transport compute its overhead, e.g., to fit
+/// messages in an MTU, or to schedule rekeying, without hardcoding the numbers of each algorithm.
+///
+/// Get one with `SuiteParams::new::<A, Kdf, Kem>()`. Every field is also available as an
+/// associated constant of the algorithm it comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SuiteParams {
+    /// The KEM's algorithm identifier
+    pub kem_id: u16,
+    /// The KDF's algorithm identifier
+    pub kdf_id: u16,
+    /// The AEAD's algorithm identifier
+    pub aead_id: u16,
+    /// The size of an encapsulated key, i.e., Nenc. This is sent once per context.
+    pub encapped_key_size: usize,
+    /// The size of the AEAD key, i.e., Nk
+    pub key_size: usize,
+    /// The size of the AEAD nonce, i.e., Nn
+    pub nonce_size: usize,
+    /// The output size of the KDF's hash function, i.e., Nh
+    pub digest_size: usize,
+    /// The number of bytes sealing adds to each message, i.e., the size of the AEAD tag
+    pub tag_size: usize,
+    /// The largest plaintext, in bytes, that can be sealed in a single message
+    pub max_plaintext_len: u64,
+    /// The number of messages one context can seal or open before returning
+    /// `HpkeError::MessageLimitReached`. This is 2^64 for every AEAD, since sequence numbers are
+    /// 64 bits, and 0 for the export-only AEAD.
+    pub max_messages: u128,
+}
+
+impl SuiteParams {
+    /// Returns the parameters of the given ciphersuite
+    pub const fn new<A: Aead, Kdf: KdfTrait, Kem: KemTrait>() -> SuiteParams {
+        SuiteParams {
+            kem_id: Kem::KEM_ID,
+            kdf_id: Kdf::KDF_ID,
+            aead_id: A::AEAD_ID,
+            encapped_key_size: Kem::ENCAPPED_KEY_SIZE,
+            key_size: <<A::AeadImpl as BaseKeySizeUser>::KeySize as Unsigned>::USIZE,
+            nonce_size: <<A::AeadImpl as BaseAeadCore>::NonceSize as Unsigned>::USIZE,
+            digest_size: Kdf::DIGEST_SIZE,
+            tag_size: A::TAG_SIZE,
+            max_plaintext_len: A::MAX_PLAINTEXT_LEN,
+            max_messages: if A::MAX_PLAINTEXT_LEN == 0 {
+                0
+            } else {
+                1 << 64
+            },
+        }
+    }
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod test {
+    use super::SuiteParams;
+    use crate::{
+        aead::{AesGcm128, ChaCha20Poly1305, ExportOnlyAead},
+        kdf::{HkdfSha256, HkdfSha512},
+        kem::X25519HkdfSha256,
+    };
+
+    /// Checks the parameters against RFC 9180 §7
+    #[test]
+    fn test_suite_params() {
+        let params = SuiteParams::new::<AesGcm128, HkdfSha256, X25519HkdfSha256>();
+        assert_eq!(
+            params,
+            SuiteParams {
+                kem_id: 0x0020,
+                kdf_id: 0x0001,
+                aead_id: 0x0001,
+                encapped_key_size: 32,
+                key_size: 16,
+                nonce_size: 12,
+                digest_size: 32,
+                tag_size: 16,
+                max_plaintext_len: 1 << 36,
+                max_messages: 1 << 64,
+            }
+        );
+
+        let params = SuiteParams::new::<ChaCha20Poly1305, HkdfSha512, X25519HkdfSha256>();
+        assert_eq!(params.key_size, 32);
+        assert_eq!(params.digest_size, 64);
+        assert_eq!(params.tag_size, 16);
+
+        let params = SuiteParams::new::<ExportOnlyAead, HkdfSha256, X25519HkdfSha256>();
+        assert_eq!(params.aead_id, 0xFFFF);
+        assert_eq!(params.tag_size, 0);
+        assert_eq!(params.max_plaintext_len, 0);
+        assert_eq!(params.max_messages, 0);
+    }
+}
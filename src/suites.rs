@@ -0,0 +1,59 @@
+//! Named ciphersuites, for when you don't want to assemble a KEM, KDF, and AEAD yourself. Each is
+//! an [`Hpke`], so it comes with `gen_keypair`, `seal`, and `open`.
+//!
+//! If you don't know which one to pick, use [`Recommended`].
+//!
+//! Example
+//! =======
+//! ```
+//! # #[cfg(all(feature = "x25519", any(feature = "alloc", feature = "std")))] {
+//! # use rand::{rngs::StdRng, SeedableRng};
+//! use hpke::suites::Recommended;
+//!
+//! let mut csprng = StdRng::from_entropy();
+//! let (sk, pk) = Recommended::gen_keypair(&mut csprng);
+//! let (enc, ct) = Recommended::seal(&pk, b"info", b"", b"hello", &mut csprng).unwrap();
+//! assert_eq!(Recommended::open(&sk, &enc, b"info", b"", &ct).unwrap(), b"hello");
+//! # }
+//! ```
+//!
+//! There is no secp256k1 suite, e.g., the one Payjoin uses, since this crate has no secp256k1 KEM.
+
+#[allow(unused_imports)]
+use crate::{
+    aead::{AesGcm128, AesGcm256, ChaCha20Poly1305},
+    kdf::{HkdfSha256, HkdfSha384, HkdfSha512},
+    Hpke,
+};
+
+/// X25519 with HKDF-SHA256 and ChaCha20Poly1305. This is fast in software on any platform.
+#[cfg(feature = "x25519")]
+#[cfg_attr(docsrs, doc(cfg(feature = "x25519")))]
+pub type X25519ChaCha = Hpke<crate::kem::X25519HkdfSha256, HkdfSha256, ChaCha20Poly1305>;
+
+/// X25519 with HKDF-SHA256 and AES-128-GCM. This is faster than [`X25519ChaCha`] where there's
+/// hardware AES.
+#[cfg(feature = "x25519")]
+#[cfg_attr(docsrs, doc(cfg(feature = "x25519")))]
+pub type X25519AesGcm128 = Hpke<crate::kem::X25519HkdfSha256, HkdfSha256, AesGcm128>;
+
+/// P-256 with HKDF-SHA256 and AES-128-GCM. All three are NIST-approved.
+#[cfg(feature = "p256")]
+#[cfg_attr(docsrs, doc(cfg(feature = "p256")))]
+pub type P256AesGcm128 = Hpke<crate::kem::DhP256HkdfSha256, HkdfSha256, AesGcm128>;
+
+/// P-384 with HKDF-SHA384 and AES-256-GCM. All three are NIST-approved, at the 192-bit level or
+/// above.
+#[cfg(feature = "p384")]
+#[cfg_attr(docsrs, doc(cfg(feature = "p384")))]
+pub type P384AesGcm256 = Hpke<crate::kem::DhP384HkdfSha384, HkdfSha384, AesGcm256>;
+
+/// P-521 with HKDF-SHA512 and AES-256-GCM. All three are NIST-approved, at the 256-bit level.
+#[cfg(feature = "p521")]
+#[cfg_attr(docsrs, doc(cfg(feature = "p521")))]
+pub type P521AesGcm256 = Hpke<crate::kem::DhP521HkdfSha512, HkdfSha512, AesGcm256>;
+
+/// The suite to use when nothing else decides it: [`X25519ChaCha`]
+#[cfg(feature = "x25519")]
+#[cfg_attr(docsrs, doc(cfg(feature = "x25519")))]
+pub type Recommended = X25519ChaCha;
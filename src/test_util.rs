@@ -1,10 +1,9 @@
 use crate::{
-    aead::{Aead, AeadCtx, AeadCtxR, AeadCtxS, AeadKey, AeadNonce},
+    aead::{Aead, AeadCtxR, AeadCtxS},
     dhkex::DhKeyExchange,
     kdf::Kdf as KdfTrait,
     kem::Kem as KemTrait,
     op_mode::{OpModeR, OpModeS, PskBundle},
-    setup::ExporterSecret,
     Serializable,
 };
 
@@ -29,16 +28,22 @@ pub(crate) fn dhkex_gen_keypair<Kex: DhKeyExchange, R: CryptoRng + RngCore>(
     // Fill it with randomness
     csprng.fill_bytes(&mut ikm);
     // Run derive_keypair with a nonsense ciphersuite. We use SHA-512 to satisfy any security level
-    Kex::derive_keypair::<crate::kdf::HkdfSha512>(b"31337", &ikm)
+    Kex::derive_keypair::<crate::kdf::HkdfSha512>(b"31337", &ikm, &[])
 }
 
 /// Creates a pair of `AeadCtx`s without doing a key exchange
+#[cfg(any(feature = "alloc", feature = "std"))]
 pub(crate) fn gen_ctx_simple_pair<A, Kdf, Kem>() -> (AeadCtxS<A, Kdf, Kem>, AeadCtxR<A, Kdf, Kem>)
 where
     A: Aead,
     Kdf: KdfTrait,
     Kem: KemTrait,
 {
+    use crate::{
+        aead::{AeadCtx, AeadKey, AeadNonce},
+        setup::ExporterSecret,
+    };
+
     let mut csprng = StdRng::from_entropy();
 
     // Initialize the key and nonce
@@ -73,7 +78,7 @@ pub(crate) enum OpModeKind {
 }
 
 /// Makes an agreeing pair of `OpMode`s of the specified variant
-pub(crate) fn new_op_mode_pair<'a, Kdf: KdfTrait, Kem: KemTrait>(
+pub(crate) fn new_op_mode_pair<'a, Kem: KemTrait>(
     kind: OpModeKind,
     psk: &'a [u8],
     psk_id: &'a [u8],
@@ -132,17 +137,18 @@ pub(crate) fn aead_ctx_eq<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
     // Do 1000 iterations of encryption-decryption. The underlying sequence number increments
     // each time.
     for i in 0..1000 {
-        let plaintext = &mut msg_buf.clone()[..msg_len];
+        let mut plaintext_buf = msg_buf;
+        let plaintext = &mut plaintext_buf[..msg_len];
         // Encrypt the plaintext
         let tag = sender
-            .seal_in_place_detached(&mut plaintext[..], &aad)
+            .seal_in_place_detached(&mut plaintext[..], aad)
             .unwrap_or_else(|_| panic!("seal() #{} failed", i));
         // Rename for clarity
         let ciphertext = plaintext;
 
         // Now to decrypt on the other side
         if receiver
-            .open_in_place_detached(&mut ciphertext[..], &aad, &tag)
+            .open_in_place_detached(&mut ciphertext[..], aad, &tag)
             .is_err()
         {
             // An error occurred in decryption. These encryption contexts are not identical.
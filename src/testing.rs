@@ -0,0 +1,3 @@
+//! Tools for testing this and other HPKE implementations
+
+pub mod vectors;
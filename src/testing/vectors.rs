@@ -0,0 +1,1116 @@
+//! Generates test vectors in the format of the RFC 9180 test vectors. Every field is computed by
+//! this crate's own key schedule, so the output is only as trustworthy as the crate. The point is
+//! to make vectors for ciphersuites the RFC doesn't cover, and to check them against other
+//! implementations.
+//!
+//! The `gen-vectors` binary is a command-line frontend to [`gen_test_vectors`].
+
+// Without any KEMs there's nothing to generate, and most of this module goes unused
+#![cfg_attr(
+    not(any(
+        feature = "x25519",
+        feature = "p256",
+        feature = "p384",
+        feature = "p521"
+    )),
+    allow(unused_imports, unused_macros, unused_variables, dead_code)
+)]
+
+use crate::{
+    aead::{Aead, AeadCtx, AeadCtxS, AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead},
+    kdf::{HkdfSha256, HkdfSha384, HkdfSha512, Kdf as KdfTrait},
+    kem::Kem as KemTrait,
+    op_mode::{OpModeR, OpModeS, PskBundle},
+    setup::{key_schedule, setup_receiver, KeySchedule},
+    Deserializable, HpkeError, Serializable,
+};
+
+use std::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use digest::Digest;
+use rand_chacha::ChaCha20Rng;
+use rand_core::{CryptoRng, RngCore, SeedableRng};
+use serde::{
+    de::{Error as SError, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serializer,
+};
+
+// Tells serde how to deserialize bytes from the hex representation
+fn bytes_from_hex<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut hex_str = String::deserialize(deserializer)?;
+    // Prepend a 0 if it's not even length
+    if hex_str.len() % 2 == 1 {
+        hex_str.insert(0, '0');
+    }
+    hex::decode(hex_str).map_err(|e| SError::custom(format!("{:?}", e)))
+}
+
+// Tells serde how to serialize bytes to the hex representation
+fn bytes_to_hex<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+// Tells serde how to serialize bytes to an optional field with hex encoding. Only called on
+// `Some`, since `None` fields are skipped.
+fn bytes_to_hex_opt<S>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    bytes_to_hex(bytes.as_deref().unwrap_or_default(), serializer)
+}
+
+// Tells serde how to deserialize bytes from an optional field with hex encoding
+fn bytes_from_hex_opt<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    bytes_from_hex(deserializer).map(Some)
+}
+
+/// The version of the test vector format that this crate writes. Version 1 is the format of the
+/// RFC 9180 test vectors, which have no `schema_version` field. Version 2 adds the
+/// `schema_version` field itself, and the optional `negative` and `seq` fields.
+pub const SCHEMA_VERSION: u32 = 2;
+
+// Vectors without a `schema_version` field are RFC 9180 vectors
+fn rfc_schema_version() -> u32 {
+    1
+}
+
+/// A test vector in the format of the RFC 9180 test vectors
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct MainTestVector {
+    /// The version of the format this vector is in. See [`SCHEMA_VERSION`].
+    #[serde(default = "rfc_schema_version")]
+    pub schema_version: u32,
+
+    // Parameters
+    /// The RFC 9180 mode identifier: 0 for Base, 1 for Psk, 2 for Auth, 3 for AuthPsk
+    pub mode: u8,
+    /// The KEM identifier
+    pub kem_id: u16,
+    /// The KDF identifier
+    pub kdf_id: u16,
+    /// The AEAD identifier
+    pub aead_id: u16,
+    /// The `info` string given to the key schedule
+    #[serde(serialize_with = "bytes_to_hex", deserialize_with = "bytes_from_hex")]
+    pub info: Vec<u8>,
+
+    // Keying material
+    /// Keying material for the recipient's keypair
+    #[serde(
+        rename = "ikmR",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub ikm_recip: Vec<u8>,
+    /// Keying material for the sender's identity keypair, if the mode is authenticated
+    #[serde(
+        default,
+        rename = "ikmS",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub ikm_sender: Option<Vec<u8>>,
+    /// Keying material for the ephemeral keypair
+    #[serde(
+        rename = "ikmE",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub ikm_eph: Vec<u8>,
+
+    // Private keys
+    /// The recipient's private key
+    #[serde(
+        rename = "skRm",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub sk_recip: Vec<u8>,
+    /// The sender's identity private key, if the mode is authenticated
+    #[serde(
+        default,
+        rename = "skSm",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub sk_sender: Option<Vec<u8>>,
+    /// The ephemeral private key
+    #[serde(
+        rename = "skEm",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub sk_eph: Vec<u8>,
+
+    // Preshared Key Bundle
+    /// The preshared key, if the mode uses one
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub psk: Option<Vec<u8>>,
+    /// The preshared key ID, if the mode uses one
+    #[serde(
+        default,
+        rename = "psk_id",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub psk_id: Option<Vec<u8>>,
+
+    // Public Keys
+    /// The recipient's public key
+    #[serde(
+        rename = "pkRm",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub pk_recip: Vec<u8>,
+    /// The sender's identity public key, if the mode is authenticated
+    #[serde(
+        default,
+        rename = "pkSm",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub pk_sender: Option<Vec<u8>>,
+    /// The ephemeral public key
+    #[serde(
+        rename = "pkEm",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub pk_eph: Vec<u8>,
+
+    // Key schedule inputs and computations
+    /// The encapsulated key
+    #[serde(
+        rename = "enc",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub encapped_key: Vec<u8>,
+    /// The KEM shared secret
+    #[serde(serialize_with = "bytes_to_hex", deserialize_with = "bytes_from_hex")]
+    pub shared_secret: Vec<u8>,
+    /// The `key_schedule_context` value computed in the key schedule
+    #[serde(
+        rename = "key_schedule_context",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub hpke_context: Vec<u8>,
+    /// The `secret` value computed in the key schedule
+    #[serde(
+        rename = "secret",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub key_schedule_secret: Vec<u8>,
+    /// The AEAD key. This is empty for the export-only AEAD
+    #[serde(
+        rename = "key",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub aead_key: Vec<u8>,
+    /// The AEAD base nonce. This is empty for the export-only AEAD
+    #[serde(
+        rename = "base_nonce",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub aead_base_nonce: Vec<u8>,
+    /// The exporter secret
+    #[serde(
+        rename = "exporter_secret",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub exporter_secret: Vec<u8>,
+
+    /// A sequence of encryptions, in the order they were made
+    pub encryptions: Vec<EncryptionTestVector>,
+    /// A set of exported secrets
+    pub exports: Vec<ExporterTestVector>,
+
+    /// Tampered inputs that the receiver must reject. This isn't part of the RFC 9180 format, so
+    /// it's omitted when empty.
+    #[serde(default, rename = "negative", skip_serializing_if = "Vec::is_empty")]
+    pub negative_cases: Vec<NegativeTestVector>,
+}
+
+/// A single encryption of a [`MainTestVector`]
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct EncryptionTestVector {
+    /// The sequence number of this message. The RFC vectors list every message in order and
+    /// omit this. It's set when messages were skipped, see [`keep_encryptions`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// The plaintext
+    #[serde(
+        rename = "pt",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub plaintext: Vec<u8>,
+    /// The associated data
+    #[serde(serialize_with = "bytes_to_hex", deserialize_with = "bytes_from_hex")]
+    pub aad: Vec<u8>,
+    /// The nonce this message was encrypted under
+    #[serde(
+        rename = "nonce",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub nonce: Vec<u8>,
+    /// The ciphertext, including the tag
+    #[serde(
+        rename = "ct",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub ciphertext: Vec<u8>,
+}
+
+/// A single export of a [`MainTestVector`]
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct ExporterTestVector {
+    /// The exporter context
+    #[serde(
+        rename = "exporter_context",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub export_ctx: Vec<u8>,
+    /// The length of the exported secret
+    #[serde(rename = "L")]
+    pub export_len: usize,
+    /// The exported secret
+    #[serde(
+        rename = "exported_value",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub export_val: Vec<u8>,
+}
+
+/// The ways a [`NegativeTestVector`] can differ from the valid inputs of its [`MainTestVector`]
+#[derive(Clone, Copy, serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tampering {
+    /// The first ciphertext has a bit flipped
+    FlippedCiphertext,
+    /// The first ciphertext is opened with the wrong associated data
+    WrongAad,
+    /// The encapsulated key is missing its last byte
+    TruncatedEnc,
+    /// The receiver uses the wrong preshared key ID
+    WrongPskId,
+}
+
+/// A tampered version of the inputs of a [`MainTestVector`], which the receiver must reject
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct NegativeTestVector {
+    /// What was tampered with
+    pub tampering: Tampering,
+    /// The encapsulated key the receiver is given
+    #[serde(
+        rename = "enc",
+        serialize_with = "bytes_to_hex",
+        deserialize_with = "bytes_from_hex"
+    )]
+    pub encapped_key: Vec<u8>,
+    /// The preshared key ID the receiver uses, if different from the main vector's
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub psk_id: Option<Vec<u8>>,
+    /// The associated data the receiver opens the ciphertext with, if there's a ciphertext
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub aad: Option<Vec<u8>>,
+    /// The ciphertext the receiver opens as the first message. If this is absent, the failure
+    /// must happen during setup.
+    #[serde(
+        default,
+        rename = "ct",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "bytes_to_hex_opt",
+        deserialize_with = "bytes_from_hex_opt"
+    )]
+    pub ciphertext: Option<Vec<u8>>,
+    /// The `Debug` representation of the `HpkeError` the receiver must fail with
+    pub expected_error: String,
+}
+
+/// An error found while loading a test vector file. This says which field of which vector is the
+/// problem, since hand-edited vector files are common.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorError {
+    /// The position of the offending vector in the file. This is `None` if the file isn't a JSON
+    /// array at all.
+    pub index: Option<usize>,
+    /// The `(kem_id, kdf_id, aead_id)` of the offending vector, if they could be read
+    pub suite: Option<(u16, u16, u16)>,
+    /// The path to the offending field within the vector, e.g., `encryptions[3].ct`. This is
+    /// empty if the problem is with the vector as a whole.
+    pub path: String,
+    /// What's wrong
+    pub message: String,
+}
+
+impl core::fmt::Display for VectorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "test vector {}", index)?,
+            None => write!(f, "test vector file")?,
+        }
+        if let Some((kem_id, kdf_id, aead_id)) = self.suite {
+            write!(
+                f,
+                " (KEM {:#06x}, KDF {:#06x}, AEAD {:#06x})",
+                kem_id, kdf_id, aead_id
+            )?;
+        }
+        if !self.path.is_empty() {
+            write!(f, ", field {}", self.path)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for VectorError {}
+
+/// Checks and parses a single vector, which was found at position `index` of its file
+fn parse_test_vector(
+    index: usize,
+    value: serde_json::Value,
+) -> Result<MainTestVector, VectorError> {
+    // Read the suite IDs first, so that errors can say which vector they're about
+    let id = |name| value.get(name).and_then(|v| v.as_u64()).map(|id| id as u16);
+    let suite = match (id("kem_id"), id("kdf_id"), id("aead_id")) {
+        (Some(kem_id), Some(kdf_id), Some(aead_id)) => Some((kem_id, kdf_id, aead_id)),
+        _ => None,
+    };
+    let err = |path: &str, message: String| VectorError {
+        index: Some(index),
+        suite,
+        path: path.into(),
+        message,
+    };
+
+    // Refuse formats from the future. We might silently ignore fields we don't know about.
+    if let Some(version) = value.get("schema_version").and_then(|v| v.as_u64()) {
+        if version > SCHEMA_VERSION as u64 {
+            return Err(err(
+                "schema_version",
+                format!(
+                    "unsupported schema version {}, expected at most {}",
+                    version, SCHEMA_VERSION
+                ),
+            ));
+        }
+    }
+
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let path = e.path().to_string();
+        // The path of a problem with the vector as a whole is "."
+        let path = if path == "." { String::new() } else { path };
+        err(&path, e.into_inner().to_string())
+    })
+}
+
+/// Reads a JSON array of test vectors and calls `f` on each one as soon as it's parsed. Unlike
+/// deserializing a `Vec<MainTestVector>`, this only ever holds one vector in memory, which
+/// matters for vector files that are tens of megabytes.
+///
+/// Return Value
+/// ============
+/// Returns `Ok(())` if every vector parsed. Otherwise, returns a `VectorError` describing the
+/// first malformed vector. Vectors before it will already have been passed to `f`.
+pub fn for_each_test_vector<R, F>(reader: R, f: F) -> Result<(), VectorError>
+where
+    R: std::io::Read,
+    F: FnMut(MainTestVector),
+{
+    // Visits the elements of a JSON array one at a time. If an element is malformed, the error is
+    // stashed here, since serde_json's error type can't hold it.
+    struct ForEach<F> {
+        f: F,
+        err: Option<VectorError>,
+    }
+
+    impl<'de, F: FnMut(MainTestVector)> Visitor<'de> for &mut ForEach<F> {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+            formatter.write_str("an array of test vectors")
+        }
+
+        fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<(), S::Error> {
+            let mut index = 0;
+            while let Some(value) = seq.next_element()? {
+                match parse_test_vector(index, value) {
+                    Ok(tv) => (self.f)(tv),
+                    Err(e) => {
+                        self.err = Some(e);
+                        return Err(SError::custom("malformed test vector"));
+                    }
+                }
+                index += 1;
+            }
+            Ok(())
+        }
+    }
+
+    let mut visitor = ForEach { f, err: None };
+    let mut deserializer = serde_json::Deserializer::from_reader(std::io::BufReader::new(reader));
+    deserializer
+        .deserialize_seq(&mut visitor)
+        // Make sure there's nothing but whitespace after the array
+        .and_then(|_| deserializer.end())
+        .map_err(|e| {
+            visitor.err.take().unwrap_or_else(|| VectorError {
+                index: None,
+                suite: None,
+                path: String::new(),
+                message: e.to_string(),
+            })
+        })
+}
+
+/// Reads a JSON array of test vectors. See [`for_each_test_vector`] for a version that doesn't
+/// hold the whole file in memory.
+pub fn load_test_vectors<R: std::io::Read>(reader: R) -> Result<Vec<MainTestVector>, VectorError> {
+    let mut tvs = Vec::new();
+    for_each_test_vector(reader, |tv| tvs.push(tv))?;
+    Ok(tvs)
+}
+
+/// Constructs an `OpModeR` from the given components. The variant constructed is determined solely
+/// by `mode_id`. This will panic if the components are not exactly the ones the variant specified
+/// by `mode_id` calls for.
+pub(crate) fn make_op_mode_r<'a, Kem: KemTrait>(
+    mode_id: u8,
+    pk: Option<Kem::PublicKey>,
+    psk: Option<&'a [u8]>,
+    psk_id: Option<&'a [u8]>,
+) -> OpModeR<'a, Kem> {
+    // Deserialize the optional bundle
+    let bundle = psk.map(|bytes| PskBundle {
+        psk: bytes,
+        psk_id: psk_id.unwrap(),
+    });
+
+    // These better be set if the mode ID calls for them
+    OpModeR::from_parts(mode_id, pk, bundle).expect("invalid mode")
+}
+
+// These are the same values as the RFC 9180 test vectors use
+const INFO: &[u8] = b"Ode on a Grecian Urn";
+const PLAINTEXT: &[u8] = b"Beauty is truth, truth beauty";
+const NUM_ENCRYPTIONS: usize = 257;
+const EXPORTER_CONTEXTS: &[&[u8]] = &[b"", &[0x00], b"TestContext"];
+const EXPORT_LEN: usize = 32;
+// On top of the RFC's exports, we export under a context containing every byte value, at lengths
+// given in multiples of Nh. 255·Nh is the most that HKDF can output.
+const EXTRA_EXPORT_LENS_IN_NH: &[(usize, usize)] = &[(0, 0), (0, 1), (1, 0), (255, 0)];
+const PSK_LEN: usize = 32;
+const PSK_ID_LEN: usize = 22;
+
+/// Returns `Nsk` random bytes. This is what the RFC uses as keying material for `DeriveKeyPair`.
+fn gen_ikm<Kem: KemTrait, R: CryptoRng + RngCore>(csprng: &mut R) -> Vec<u8> {
+    let mut ikm = vec![0u8; <Kem::PrivateKey as Serializable>::size()];
+    csprng.fill_bytes(&mut ikm);
+    ikm
+}
+
+/// Generates a test vector for the given ciphersuite and mode. `mode_id` is the RFC 9180 mode
+/// identifier: 0 for Base, 1 for Psk, 2 for Auth, 3 for AuthPsk. Everything the mode calls for,
+/// i.e., the sender's identity keypair and the PSK bundle, is randomly generated.
+pub fn gen_test_case<A, Kdf, Kem, R>(mode_id: u8, csprng: &mut R) -> MainTestVector
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+    R: CryptoRng + RngCore,
+{
+    let uses_auth = mode_id == 2 || mode_id == 3;
+    let uses_psk = mode_id == 1 || mode_id == 3;
+
+    // Derive the recipient's and the ephemeral keypairs from fresh keying material
+    let ikm_recip = gen_ikm::<Kem, _>(csprng);
+    let ikm_eph = gen_ikm::<Kem, _>(csprng);
+    let (sk_recip, pk_recip) = Kem::derive_keypair(&ikm_recip);
+    let (sk_eph, pk_eph) = Kem::derive_keypair(&ikm_eph);
+
+    // Derive the sender's identity keypair if the mode is authenticated
+    let ikm_sender = uses_auth.then(|| gen_ikm::<Kem, _>(csprng));
+    let sender_keypair = ikm_sender.as_ref().map(|ikm| Kem::derive_keypair(ikm));
+
+    // Make a PSK bundle if the mode calls for it
+    let (psk, psk_id) = if uses_psk {
+        let mut psk = vec![0u8; PSK_LEN];
+        let mut psk_id = vec![0u8; PSK_ID_LEN];
+        csprng.fill_bytes(&mut psk);
+        csprng.fill_bytes(&mut psk_id);
+        (Some(psk), Some(psk_id))
+    } else {
+        (None, None)
+    };
+    let psk_bundle = psk.as_ref().map(|psk| PskBundle {
+        psk,
+        psk_id: psk_id.as_ref().unwrap(),
+    });
+
+    // Encapsulate deterministically with the ephemeral key
+    let (shared_secret, encapped_key) = {
+        let sender_keypair_ref = sender_keypair.as_ref().map(|(sk, pk)| (sk, pk));
+        Kem::danger_encap_with_eph(&pk_recip, sender_keypair_ref, sk_eph.clone())
+            .expect("encap failed")
+    };
+
+    // Construct the agreeing pair of modes
+    let mode_s =
+        OpModeS::from_parts(mode_id, sender_keypair.clone(), psk_bundle).expect("invalid mode ID");
+    let mode_r = make_op_mode_r::<Kem>(
+        mode_id,
+        sender_keypair.as_ref().map(|(_, pk)| pk.clone()),
+        psk.as_deref(),
+        psk_id.as_deref(),
+    );
+
+    // Run the key schedule and record everything it computes
+    let ks = key_schedule::<A, Kdf, Kem, _>(&mode_s, &shared_secret, INFO);
+    let hpke_context = ks.context().to_vec();
+    let KeySchedule {
+        secret,
+        key,
+        base_nonce,
+        exporter_secret,
+        ..
+    } = ks;
+    let key_schedule_secret = secret.0.to_vec();
+    // The export-only AEAD has no key or nonce. The RFC leaves these fields empty.
+    let (aead_key, aead_base_nonce) = if A::AEAD_ID == ExportOnlyAead::AEAD_ID {
+        (Vec::new(), Vec::new())
+    } else {
+        (key.0.to_vec(), base_nonce.0.to_vec())
+    };
+    let exporter_secret_bytes = exporter_secret.0.to_vec();
+
+    // Make the sender's context straight from the key schedule. setup_sender would use a random
+    // ephemeral key, which isn't what we want.
+    let mut sender_ctx: AeadCtxS<A, Kdf, Kem> =
+        AeadCtx::new(&key, base_nonce, exporter_secret).into();
+
+    // Make the receiver's context the normal way. Everything below checks that it agrees with
+    // the sender's. If it doesn't, this vector is garbage.
+    let mut receiver_ctx = setup_receiver::<A, Kdf, Kem>(&mode_r, &sk_recip, &encapped_key, INFO)
+        .expect("setup_receiver failed");
+
+    // Encrypt NUM_ENCRYPTIONS messages in sequence, recording the nonce of each. The export-only
+    // AEAD can't encrypt anything, so its vectors have no encryptions.
+    let mut encryptions = Vec::new();
+    if A::AEAD_ID != ExportOnlyAead::AEAD_ID {
+        for i in 0..NUM_ENCRYPTIONS {
+            let aad = format!("Count-{}", i).into_bytes();
+            let nonce = sender_ctx.current_nonce().0.to_vec();
+            let ciphertext = sender_ctx.seal(PLAINTEXT, &aad).expect("seal failed");
+            let decrypted = receiver_ctx.open(&ciphertext, &aad).expect("open failed");
+            assert_eq!(
+                decrypted, PLAINTEXT,
+                "sender and receiver disagree on seal/open"
+            );
+            encryptions.push(EncryptionTestVector {
+                seq: None,
+                plaintext: PLAINTEXT.to_vec(),
+                aad,
+                nonce,
+                ciphertext,
+            });
+        }
+    }
+
+    // Export EXPORT_LEN bytes under each of the RFC's exporter contexts, then the extra lengths
+    // under a binary context
+    let nh = <Kdf::HashImpl as Digest>::output_size();
+    let binary_ctx: Vec<u8> = (0..=255).collect();
+    let export_reqs = EXPORTER_CONTEXTS
+        .iter()
+        .map(|ctx| (ctx.to_vec(), EXPORT_LEN))
+        .chain(
+            EXTRA_EXPORT_LENS_IN_NH
+                .iter()
+                .map(|(mul, add)| (binary_ctx.clone(), mul * nh + add)),
+        );
+    let exports = export_reqs
+        .map(|(export_ctx, export_len)| {
+            let mut export_val = vec![0u8; export_len];
+            let mut receiver_export_val = vec![0u8; export_len];
+            sender_ctx
+                .export(&export_ctx, &mut export_val)
+                .expect("export failed");
+            receiver_ctx
+                .export(&export_ctx, &mut receiver_export_val)
+                .expect("export failed");
+            assert_eq!(
+                export_val, receiver_export_val,
+                "sender and receiver disagree on export"
+            );
+            ExporterTestVector {
+                export_ctx,
+                export_len,
+                export_val,
+            }
+        })
+        .collect();
+
+    MainTestVector {
+        schema_version: SCHEMA_VERSION,
+        mode: mode_id,
+        kem_id: Kem::KEM_ID,
+        kdf_id: Kdf::KDF_ID,
+        aead_id: A::AEAD_ID,
+        info: INFO.to_vec(),
+
+        ikm_recip,
+        ikm_sender,
+        ikm_eph,
+
+        sk_recip: sk_recip.to_bytes().to_vec(),
+        sk_sender: sender_keypair
+            .as_ref()
+            .map(|(sk, _)| sk.to_bytes().to_vec()),
+        sk_eph: sk_eph.to_bytes().to_vec(),
+
+        psk,
+        psk_id,
+
+        pk_recip: pk_recip.to_bytes().to_vec(),
+        pk_sender: sender_keypair
+            .as_ref()
+            .map(|(_, pk)| pk.to_bytes().to_vec()),
+        pk_eph: pk_eph.to_bytes().to_vec(),
+
+        encapped_key: encapped_key.to_bytes().to_vec(),
+        shared_secret: shared_secret.0.to_vec(),
+        hpke_context,
+        key_schedule_secret,
+        aead_key,
+        aead_base_nonce,
+        exporter_secret: exporter_secret_bytes,
+
+        encryptions,
+        exports,
+        negative_cases: Vec::new(),
+    }
+}
+
+/// The sequence numbers of the encryptions that the RFC 9180 text lists for every test vector.
+/// These exercise the first few increments and the carry into the second byte of the nonce.
+pub const RFC_SEQS: &[u64] = &[0, 1, 2, 4, 255, 256];
+
+/// Drops every encryption of `tv` whose sequence number isn't in `seqs`, and records the sequence
+/// number of the rest. The remaining ciphertexts are still the ones made by sealing every message
+/// in order, so a reader must skip ahead by sealing and opening the messages in between.
+///
+/// Panics
+/// ======
+/// Panics if the encryptions of `tv` have already been skipped.
+pub fn keep_encryptions(tv: &mut MainTestVector, seqs: &[u64]) {
+    assert!(
+        tv.encryptions.iter().all(|e| e.seq.is_none()),
+        "encryptions have already been skipped"
+    );
+
+    let encryptions = core::mem::take(&mut tv.encryptions);
+    tv.encryptions = encryptions
+        .into_iter()
+        .zip(0u64..)
+        .filter(|(_, seq)| seqs.contains(seq))
+        .map(|(e, seq)| EncryptionTestVector {
+            seq: Some(seq),
+            ..e
+        })
+        .collect();
+}
+
+/// Runs the receiver side of `tv` on the tampered inputs of `case`. Returns the first error the
+/// receiver hits, or `Ok(())` if it accepted everything.
+pub(crate) fn run_negative_case<A, Kdf, Kem>(
+    tv: &MainTestVector,
+    case: &NegativeTestVector,
+) -> Result<(), HpkeError>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    let sk_recip = Kem::PrivateKey::from_bytes(&tv.sk_recip)?;
+    let pk_sender = tv
+        .pk_sender
+        .as_deref()
+        .map(Kem::PublicKey::from_bytes)
+        .transpose()?;
+    let encapped_key = Kem::EncappedKey::from_bytes(&case.encapped_key)?;
+    let mode = make_op_mode_r::<Kem>(
+        tv.mode,
+        pk_sender,
+        tv.psk.as_deref(),
+        case.psk_id.as_deref().or(tv.psk_id.as_deref()),
+    );
+
+    let mut receiver_ctx =
+        setup_receiver::<A, Kdf, Kem>(&mode, &sk_recip, &encapped_key, &tv.info)?;
+    if let (Some(ciphertext), Some(aad)) = (case.ciphertext.as_ref(), case.aad.as_ref()) {
+        receiver_ctx.open(ciphertext, aad)?;
+    }
+
+    Ok(())
+}
+
+/// Makes tampered versions of the inputs of `tv` and records the error the receiver fails with on
+/// each. Tamperings that don't apply, e.g., a wrong PSK ID in a mode without a PSK, are skipped.
+///
+/// Panics
+/// ======
+/// Panics if `tv` isn't a vector for the given ciphersuite, or if the receiver accepts a tampered
+/// input.
+pub fn gen_negative_cases<A, Kdf, Kem>(tv: &MainTestVector) -> Vec<NegativeTestVector>
+where
+    A: Aead,
+    Kdf: KdfTrait,
+    Kem: KemTrait,
+{
+    assert_eq!(
+        (tv.kem_id, tv.kdf_id, tv.aead_id),
+        (Kem::KEM_ID, Kdf::KDF_ID, A::AEAD_ID),
+        "test vector is for a different ciphersuite"
+    );
+
+    // The valid first message, if there is one
+    let first = tv.encryptions.first().filter(|e| e.seq.unwrap_or(0) == 0);
+    let untampered = |tampering| NegativeTestVector {
+        tampering,
+        encapped_key: tv.encapped_key.clone(),
+        psk_id: None,
+        aad: first.map(|e| e.aad.clone()),
+        ciphertext: first.map(|e| e.ciphertext.clone()),
+        expected_error: String::new(),
+    };
+
+    let mut cases = Vec::new();
+    if let Some(first) = first {
+        let mut case = untampered(Tampering::FlippedCiphertext);
+        let mut ciphertext = first.ciphertext.clone();
+        *ciphertext.last_mut().unwrap() ^= 1;
+        case.ciphertext = Some(ciphertext);
+        cases.push(case);
+
+        let mut case = untampered(Tampering::WrongAad);
+        let mut aad = first.aad.clone();
+        aad.push(0);
+        case.aad = Some(aad);
+        cases.push(case);
+    }
+
+    let mut case = untampered(Tampering::TruncatedEnc);
+    case.encapped_key.pop();
+    cases.push(case);
+
+    // A wrong PSK ID only shows up as a failure to open, so it needs a message
+    if let (Some(psk_id), Some(_)) = (tv.psk_id.as_ref(), first) {
+        let mut case = untampered(Tampering::WrongPskId);
+        let mut psk_id = psk_id.clone();
+        match psk_id.first_mut() {
+            Some(b) => *b ^= 1,
+            None => psk_id.push(0),
+        }
+        case.psk_id = Some(psk_id);
+        cases.push(case);
+    }
+
+    // Record what the receiver fails with
+    for case in cases.iter_mut() {
+        let err = run_negative_case::<A, Kdf, Kem>(tv, case)
+            .expect_err("receiver accepted a tampered input");
+        case.expected_error = format!("{:?}", err);
+    }
+
+    cases
+}
+
+/// Selects which ciphersuites and modes to generate test vectors for. An empty list matches
+/// everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SuiteFilter {
+    /// The KEM identifiers to generate vectors for
+    pub kem_ids: Vec<u16>,
+    /// The KDF identifiers to generate vectors for
+    pub kdf_ids: Vec<u16>,
+    /// The AEAD identifiers to generate vectors for
+    pub aead_ids: Vec<u16>,
+    /// The mode identifiers to generate vectors for
+    pub modes: Vec<u8>,
+    /// Whether to only keep the encryptions at the sequence numbers in [`RFC_SEQS`], rather than
+    /// all 257. See [`keep_encryptions`].
+    pub rfc_seqs_only: bool,
+    /// Whether to also generate tampered inputs for every vector. See [`gen_negative_cases`].
+    pub negative_cases: bool,
+}
+
+impl SuiteFilter {
+    fn matches_suite(&self, kem_id: u16, kdf_id: u16, aead_id: u16) -> bool {
+        (self.kem_ids.is_empty() || self.kem_ids.contains(&kem_id))
+            && (self.kdf_ids.is_empty() || self.kdf_ids.contains(&kdf_id))
+            && (self.aead_ids.is_empty() || self.aead_ids.contains(&aead_id))
+    }
+
+    fn modes(&self) -> Vec<u8> {
+        if self.modes.is_empty() {
+            vec![0, 1, 2, 3]
+        } else {
+            self.modes.clone()
+        }
+    }
+}
+
+// Generates vectors for every combination of the given AEADs and KDFs with the given KEM, if the
+// combination matches the filter
+macro_rules! gen_for_kem {
+    ($out:ident, $filter:ident, $csprng:ident, ($( $aead_ty:ty ),*), $kdf_tup:tt, $kem_ty:ty) => {
+        $(
+            gen_for_kem!(@aead $out, $filter, $csprng, $aead_ty, $kdf_tup, $kem_ty);
+        )*
+    };
+    (@aead $out:ident, $filter:ident, $csprng:ident, $aead_ty:ty, ($( $kdf_ty:ty ),*), $kem_ty:ty) => {
+        $(
+            if $filter.matches_suite(
+                <$kem_ty as KemTrait>::KEM_ID,
+                <$kdf_ty as KdfTrait>::KDF_ID,
+                <$aead_ty as Aead>::AEAD_ID,
+            ) {
+                for mode_id in $filter.modes() {
+                    let mut tv = gen_test_case::<$aead_ty, $kdf_ty, $kem_ty, _>(mode_id, $csprng);
+                    if $filter.rfc_seqs_only {
+                        keep_encryptions(&mut tv, RFC_SEQS);
+                    }
+                    if $filter.negative_cases {
+                        tv.negative_cases = gen_negative_cases::<$aead_ty, $kdf_ty, $kem_ty>(&tv);
+                    }
+                    $out.push(tv);
+                }
+            }
+        )*
+    };
+}
+
+/// Generates test vectors for every compiled ciphersuite and mode that matches `filter`. Vectors
+/// are ordered by KEM, then AEAD, then KDF, then mode.
+///
+/// Panics
+/// ======
+/// Panics if `filter` contains a mode identifier other than 0, 1, 2, or 3.
+pub fn gen_test_vectors<R>(filter: &SuiteFilter, csprng: &mut R) -> Vec<MainTestVector>
+where
+    R: CryptoRng + RngCore,
+{
+    #[allow(unused_mut)]
+    let mut out = Vec::new();
+
+    #[cfg(feature = "x25519")]
+    gen_for_kem!(
+        out,
+        filter,
+        csprng,
+        (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+        (HkdfSha256, HkdfSha384, HkdfSha512),
+        crate::kem::X25519HkdfSha256
+    );
+    #[cfg(feature = "p256")]
+    gen_for_kem!(
+        out,
+        filter,
+        csprng,
+        (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+        (HkdfSha256, HkdfSha384, HkdfSha512),
+        crate::kem::DhP256HkdfSha256
+    );
+    #[cfg(feature = "p384")]
+    gen_for_kem!(
+        out,
+        filter,
+        csprng,
+        (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+        (HkdfSha256, HkdfSha384, HkdfSha512),
+        crate::kem::DhP384HkdfSha384
+    );
+    #[cfg(feature = "p521")]
+    gen_for_kem!(
+        out,
+        filter,
+        csprng,
+        (AesGcm128, AesGcm256, ChaCha20Poly1305, ExportOnlyAead),
+        (HkdfSha256, HkdfSha384, HkdfSha512),
+        crate::kem::DhP521HkdfSha512
+    );
+
+    out
+}
+
+/// Generates test vectors exactly like [`gen_test_vectors`], but with all randomness drawn from a
+/// ChaCha20 RNG seeded with `seed`. The same seed, filter, and crate version always produce the
+/// same vectors, so published vectors can be reproduced from a published seed.
+pub fn gen_test_vectors_from_seed(filter: &SuiteFilter, seed: [u8; 32]) -> Vec<MainTestVector> {
+    let mut csprng = ChaCha20Rng::from_seed(seed);
+    gen_test_vectors(filter, &mut csprng)
+}
+
+#[cfg(all(test, feature = "x25519"))]
+mod test {
+    use super::{
+        gen_test_case, gen_test_vectors, gen_test_vectors_from_seed, load_test_vectors,
+        SuiteFilter, RFC_SEQS, SCHEMA_VERSION,
+    };
+    use crate::{
+        aead::{Aead, ChaCha20Poly1305},
+        kdf::{HkdfSha256, Kdf as KdfTrait},
+        kem::{Kem as KemTrait, X25519HkdfSha256},
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use serde_json::Value;
+    use std::{string::ToString, vec::Vec};
+
+    /// Tests that the filter selects exactly the requested suites, modes, encryptions, and
+    /// negative cases
+    #[test]
+    fn test_filter() {
+        let mut csprng = StdRng::from_entropy();
+        let filter = SuiteFilter {
+            kem_ids: vec![X25519HkdfSha256::KEM_ID],
+            kdf_ids: vec![HkdfSha256::KDF_ID],
+            aead_ids: vec![ChaCha20Poly1305::AEAD_ID],
+            modes: vec![0, 3],
+            rfc_seqs_only: true,
+            negative_cases: true,
+        };
+        let tvs = gen_test_vectors(&filter, &mut csprng);
+
+        assert_eq!(tvs.len(), 2);
+        for (tv, mode) in tvs.iter().zip([0, 3]) {
+            assert_eq!(tv.kem_id, X25519HkdfSha256::KEM_ID);
+            assert_eq!(tv.kdf_id, HkdfSha256::KDF_ID);
+            assert_eq!(tv.aead_id, ChaCha20Poly1305::AEAD_ID);
+            assert_eq!(tv.mode, mode);
+        }
+
+        // Only the RFC's sequence numbers are kept
+        for tv in tvs.iter() {
+            let seqs: Vec<u64> = tv.encryptions.iter().map(|e| e.seq.unwrap()).collect();
+            assert_eq!(seqs, RFC_SEQS);
+        }
+
+        // Base mode has no PSK ID to tamper with, AuthPsk mode does
+        assert_eq!(tvs[0].negative_cases.len(), 3);
+        assert_eq!(tvs[1].negative_cases.len(), 4);
+    }
+
+    /// Tests that malformed vectors are reported with their position, suite, and field
+    #[test]
+    fn test_load_errors() {
+        let mut csprng = StdRng::from_entropy();
+        let tv = gen_test_case::<ChaCha20Poly1305, HkdfSha256, X25519HkdfSha256, _>(0, &mut csprng);
+        let good = serde_json::to_value(&tv).unwrap();
+        let suite = Some((
+            X25519HkdfSha256::KEM_ID,
+            HkdfSha256::KDF_ID,
+            ChaCha20Poly1305::AEAD_ID,
+        ));
+
+        // Loads a file whose second vector has been tampered with by `tamper`
+        let load_tampered = |tamper: &dyn Fn(&mut Value)| {
+            let mut bad = good.clone();
+            tamper(&mut bad);
+            let file = Value::Array(vec![good.clone(), bad]).to_string();
+            load_test_vectors(file.as_bytes())
+        };
+
+        // The untampered file loads fine
+        assert_eq!(load_tampered(&|_| {}).unwrap().len(), 2);
+
+        let err = load_tampered(&|v| v["encryptions"][3]["ct"] = "zz".into()).unwrap_err();
+        assert_eq!(err.index, Some(1));
+        assert_eq!(err.suite, suite);
+        assert_eq!(err.path, "encryptions[3].ct");
+
+        let err = load_tampered(&|v| {
+            v.as_object_mut().unwrap().remove("skRm");
+        })
+        .unwrap_err();
+        assert_eq!((err.index, err.suite), (Some(1), suite));
+        assert!(err.message.contains("skRm"));
+
+        let err =
+            load_tampered(&|v| v["schema_version"] = (SCHEMA_VERSION + 1).into()).unwrap_err();
+        assert_eq!(err.path, "schema_version");
+
+        // RFC vectors have no schema version
+        let tvs = load_tampered(&|v| {
+            v.as_object_mut().unwrap().remove("schema_version");
+        })
+        .unwrap();
+        assert_eq!(tvs[1].schema_version, 1);
+
+        // A file that isn't an array isn't about any vector in particular
+        let err = load_test_vectors(good.to_string().as_bytes()).unwrap_err();
+        assert_eq!(err.index, None);
+    }
+
+    /// Tests that seeded generation is deterministic, and depends on the seed
+    #[test]
+    fn test_seeded_determinism() {
+        let filter = SuiteFilter {
+            kem_ids: vec![X25519HkdfSha256::KEM_ID],
+            kdf_ids: vec![HkdfSha256::KDF_ID],
+            aead_ids: vec![ChaCha20Poly1305::AEAD_ID],
+            modes: vec![],
+            rfc_seqs_only: false,
+            negative_cases: false,
+        };
+
+        let to_json =
+            |seed| serde_json::to_string(&gen_test_vectors_from_seed(&filter, seed)).unwrap();
+        assert_eq!(to_json([7u8; 32]), to_json([7u8; 32]));
+        assert_ne!(to_json([7u8; 32]), to_json([8u8; 32]));
+    }
+}
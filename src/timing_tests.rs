@@ -0,0 +1,155 @@
+//! dudect-style timing tests. These time an operation on two classes of secret inputs, a single
+//! fixed input and fresh random ones, and use Welch's t-test to check that the classes can't be
+//! told apart. See Reparaz, Balasch, and Verbauwhede, "Dude, is my code constant time?" (DATE
+//! 2017).
+//!
+//! Timing is noisy, so these are `#[ignore]`d even when the `timing-tests` feature is on. Run them
+//! on an otherwise idle machine, in release mode:
+//!
+//! ```text
+//! cargo test --release --features timing-tests,p384,p521 timing_tests -- --ignored
+//! ```
+
+use crate::{kem::Kem as KemTrait, Deserializable, Serializable};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::Instant;
+
+/// The number of timing samples taken per test
+const NUM_SAMPLES: usize = 200_000;
+
+/// How many times the operation is run per sample. Batching keeps each sample well above the
+/// timer's resolution.
+const BATCH_SIZE: usize = 16;
+
+/// The number of distinct random inputs per test. Generating keys is slow, so the random class
+/// cycles through a pool of them.
+const POOL_SIZE: usize = 1024;
+
+/// The |t| above which the classes are considered distinguishable. dudect flags leaks at 4.5. This
+/// is higher, since it's meant to catch regressions without failing on the odd noisy run.
+const T_THRESHOLD: f64 = 10.0;
+
+/// Welch's t-test on two classes of samples, updated online
+#[derive(Default)]
+struct WelchT {
+    n: [f64; 2],
+    mean: [f64; 2],
+    m2: [f64; 2],
+}
+
+impl WelchT {
+    /// Adds a sample to the given class, using Welford's method
+    fn push(&mut self, class: usize, x: f64) {
+        self.n[class] += 1.0;
+        let delta = x - self.mean[class];
+        self.mean[class] += delta / self.n[class];
+        self.m2[class] += delta * (x - self.mean[class]);
+    }
+
+    /// Returns the t statistic of the samples so far
+    fn t(&self) -> f64 {
+        let var = |c: usize| self.m2[c] / (self.n[c] - 1.0);
+        let se = (var(0) / self.n[0] + var(1) / self.n[1]).sqrt();
+        (self.mean[0] - self.mean[1]) / se
+    }
+}
+
+/// Hides `x` from the optimizer, so that neither it nor the work that made it is optimized away.
+/// This stands in for `std::hint::black_box`, which needs Rust 1.66.
+#[inline(never)]
+fn black_box<T>(x: &T) -> &T {
+    // A volatile read of the reference can't be assumed to give back what was written
+    unsafe { core::ptr::read_volatile(&x) }
+}
+
+/// Times `op` on the fixed input (class 0) and the random inputs (class 1), picking the class of
+/// each sample at random, and returns the t statistic. As in dudect, samples above the 90th
+/// percentile are dropped, since those are mostly interrupts and other noise.
+fn measure<T: Clone>(fixed: &T, random: &[T], mut op: impl FnMut(&T)) -> f64 {
+    let mut rng = StdRng::from_entropy();
+
+    // Lay out every sample's input in advance, fixed ones included, so that both classes are read
+    // from memory the same way. Otherwise the fixed input is always in cache and the random ones
+    // often aren't.
+    let inputs: crate::Vec<(usize, T)> = (0..NUM_SAMPLES)
+        .map(|i| {
+            let class = rng.gen_range(0..2usize);
+            let input = if class == 0 {
+                fixed.clone()
+            } else {
+                random[i % random.len()].clone()
+            };
+            (class, input)
+        })
+        .collect();
+
+    let mut samples = crate::Vec::with_capacity(NUM_SAMPLES);
+    for (class, input) in &inputs {
+        let start = Instant::now();
+        for _ in 0..BATCH_SIZE {
+            op(black_box(input));
+        }
+        samples.push((*class, start.elapsed().as_nanos() as f64));
+    }
+
+    let mut times: crate::Vec<f64> = samples.iter().map(|&(_, t)| t).collect();
+    times.sort_by(f64::total_cmp);
+    let cutoff = times[times.len() * 9 / 10];
+
+    let mut welch = WelchT::default();
+    for (class, time) in samples {
+        if time <= cutoff {
+            welch.push(class, time);
+        }
+    }
+    welch.t()
+}
+
+/// Checks that deserializing a private key of the given KEM takes the same time for every valid
+/// key. This is where the scalar range checks happen for the NIST curves.
+fn test_privkey_from_bytes_ct<Kem: KemTrait>() {
+    let mut csprng = StdRng::from_entropy();
+    let encode = |sk: Kem::PrivateKey| sk.to_bytes();
+
+    let fixed = encode(Kem::gen_keypair(&mut csprng).0);
+    let random: crate::Vec<_> = (0..POOL_SIZE)
+        .map(|_| encode(Kem::gen_keypair(&mut csprng).0))
+        .collect();
+
+    let t = measure(&fixed, &random, |bytes| {
+        black_box(&Kem::PrivateKey::from_bytes(bytes).unwrap());
+    });
+    assert!(
+        t.abs() < T_THRESHOLD,
+        "private key deserialization is not constant time: t = {t:.2}"
+    );
+}
+
+#[cfg(feature = "x25519")]
+#[test]
+#[ignore]
+fn test_privkey_from_bytes_ct_x25519() {
+    test_privkey_from_bytes_ct::<crate::kem::X25519HkdfSha256>();
+}
+
+#[cfg(feature = "p256")]
+#[test]
+#[ignore]
+fn test_privkey_from_bytes_ct_p256() {
+    test_privkey_from_bytes_ct::<crate::kem::DhP256HkdfSha256>();
+}
+
+#[cfg(feature = "p384")]
+#[test]
+#[ignore]
+fn test_privkey_from_bytes_ct_p384() {
+    test_privkey_from_bytes_ct::<crate::kem::DhP384HkdfSha384>();
+}
+
+#[cfg(feature = "p521")]
+#[test]
+#[ignore]
+fn test_privkey_from_bytes_ct_p521() {
+    test_privkey_from_bytes_ct::<crate::kem::DhP521HkdfSha512>();
+}
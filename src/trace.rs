@@ -0,0 +1,219 @@
+//! Tracing events for debugging interop failures. With the `trace` feature, each setup, seal,
+//! open, and export emits a `tracing` event at the debug level, with target `hpke`. Without the
+//! feature, these functions do nothing and compile away.
+//!
+//! Every event carries the suite's algorithm IDs, and whatever of the mode, sequence number, input
+//! lengths, and error applies. These are all public, i.e., a network observer learns them anyway.
+//! Nothing here is passed a key, shared secret, plaintext, PSK, or `info` string, and it must stay
+//! that way.
+
+use crate::{aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, HpkeError};
+
+/// Records the outcome of a `setup_sender` or `setup_receiver`
+#[cfg_attr(not(feature = "trace"), allow(unused_variables))]
+#[inline(always)]
+pub(crate) fn setup<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    op: &'static str,
+    mode_id: u8,
+    info_len: usize,
+    res: Result<(), &HpkeError>,
+) {
+    #[cfg(feature = "trace")]
+    match res {
+        Ok(()) => tracing::debug!(
+            target: "hpke",
+            kem_id = Kem::KEM_ID,
+            kdf_id = Kdf::KDF_ID,
+            aead_id = A::AEAD_ID,
+            mode_id,
+            info_len,
+            "{op} succeeded",
+        ),
+        Err(err) => tracing::debug!(
+            target: "hpke",
+            kem_id = Kem::KEM_ID,
+            kdf_id = Kdf::KDF_ID,
+            aead_id = A::AEAD_ID,
+            mode_id,
+            info_len,
+            error = ?err,
+            "{op} failed",
+        ),
+    }
+}
+
+/// Records the outcome of sealing or opening a message under sequence number `seq`
+#[cfg_attr(not(feature = "trace"), allow(unused_variables))]
+#[inline(always)]
+pub(crate) fn message<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    op: &'static str,
+    seq: u64,
+    msg_len: usize,
+    aad_len: usize,
+    res: Result<(), &HpkeError>,
+) {
+    #[cfg(feature = "trace")]
+    match res {
+        Ok(()) => tracing::debug!(
+            target: "hpke",
+            kem_id = Kem::KEM_ID,
+            kdf_id = Kdf::KDF_ID,
+            aead_id = A::AEAD_ID,
+            seq,
+            msg_len,
+            aad_len,
+            "{op} succeeded",
+        ),
+        Err(err) => tracing::debug!(
+            target: "hpke",
+            kem_id = Kem::KEM_ID,
+            kdf_id = Kdf::KDF_ID,
+            aead_id = A::AEAD_ID,
+            seq,
+            msg_len,
+            aad_len,
+            error = ?err,
+            "{op} failed",
+        ),
+    }
+}
+
+/// Records the outcome of an export. `ctx_len` is the length of the exporter context, and
+/// `out_len` the number of bytes requested.
+#[cfg_attr(not(feature = "trace"), allow(unused_variables))]
+#[inline(always)]
+pub(crate) fn export<A: Aead, Kdf: KdfTrait, Kem: KemTrait>(
+    ctx_len: usize,
+    out_len: usize,
+    res: Result<(), &HpkeError>,
+) {
+    #[cfg(feature = "trace")]
+    match res {
+        Ok(()) => tracing::debug!(
+            target: "hpke",
+            kem_id = Kem::KEM_ID,
+            kdf_id = Kdf::KDF_ID,
+            aead_id = A::AEAD_ID,
+            ctx_len,
+            out_len,
+            "export succeeded",
+        ),
+        Err(err) => tracing::debug!(
+            target: "hpke",
+            kem_id = Kem::KEM_ID,
+            kdf_id = Kdf::KDF_ID,
+            aead_id = A::AEAD_ID,
+            ctx_len,
+            out_len,
+            error = ?err,
+            "export failed",
+        ),
+    }
+}
+
+#[cfg(all(test, feature = "trace", feature = "std", feature = "x25519"))]
+mod tests {
+    use crate::{
+        aead::ChaCha20Poly1305, kdf::HkdfSha256, kem::X25519HkdfSha256, setup_receiver,
+        setup_sender, Kem as KemTrait, OpModeR, OpModeS, Serializable,
+    };
+
+    use std::{
+        string::{String, ToString},
+        sync::{Arc, Mutex},
+        vec::Vec,
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    /// A subscriber that records every field of every event as text
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            let line = format!("{}={:?}", field.name(), value);
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut self.clone());
+        }
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    /// Tests that setup, seal, open, and export each emit an event with the public values, and
+    /// that no event contains a secret
+    #[test]
+    fn test_trace_events() {
+        type A = ChaCha20Poly1305;
+        type Kdf = HkdfSha256;
+        type Kem = X25519HkdfSha256;
+
+        let recorder = Recorder::default();
+        let lines = recorder.0.clone();
+        let mut csprng = StdRng::from_entropy();
+        let (sk_recip, pk_recip) = Kem::gen_keypair(&mut csprng);
+        let info = b"traced info";
+        let msg = b"traced plaintext";
+
+        let mut exported = [0u8; 32];
+        tracing::subscriber::with_default(recorder, || {
+            let (encapped_key, mut sender_ctx) =
+                setup_sender::<A, Kdf, Kem, _>(&OpModeS::Base, &pk_recip, info, &mut csprng)
+                    .unwrap();
+            let mut receiver_ctx =
+                setup_receiver::<A, Kdf, Kem>(&OpModeR::Base, &sk_recip, &encapped_key, info)
+                    .unwrap();
+            let ciphertext = sender_ctx.seal(msg, b"").unwrap();
+            receiver_ctx.open(&ciphertext, b"").unwrap();
+            assert!(receiver_ctx.open(&ciphertext, b"").is_err());
+            sender_ctx.export(b"ctx", &mut exported).unwrap();
+        });
+
+        let log = lines.lock().unwrap().join("\n");
+        for expected in [
+            "setup_sender succeeded",
+            "setup_receiver succeeded",
+            "seal succeeded",
+            "open succeeded",
+            "open failed",
+            "error=OpenError",
+            "export succeeded",
+            "kem_id=32",
+            "mode_id=0",
+            "info_len=11",
+            "seq=1",
+            "msg_len=16",
+            "out_len=32",
+        ] {
+            assert!(log.contains(expected), "missing {expected:?} in:\n{log}");
+        }
+
+        // No secret or plaintext shows up, in any form
+        let forbidden = [
+            format!("{:?}", sk_recip.to_bytes().as_slice()),
+            format!("{:?}", exported),
+            String::from_utf8_lossy(msg).to_string(),
+            String::from_utf8_lossy(info).to_string(),
+        ];
+        for secret in forbidden {
+            assert!(!log.contains(&secret));
+        }
+    }
+}
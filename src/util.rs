@@ -1,6 +1,6 @@
 use crate::{aead::Aead, kdf::Kdf as KdfTrait, kem::Kem as KemTrait, HpkeError, Serializable};
 
-use byteorder::{BigEndian, ByteOrder};
+use rand_core::{CryptoRng, RngCore};
 
 /// Represents a ciphersuite context. That's "KEMXX", where `XX` is the KEM ID
 pub(crate) type KemSuiteId = [u8; 5];
@@ -17,8 +17,9 @@ pub(crate) type FullSuiteId = [u8; 10];
 //   I2OSP(aead_id, 2)
 // )
 
-/// Constructs the `suite_id` used as binding context in all functions in `setup` and `aead`
-pub(crate) fn full_suite_id<A, Kdf, Kem>() -> FullSuiteId
+/// Constructs the `suite_id` used as binding context in all functions in `setup` and `aead`. This
+/// is a `const fn` so it can be evaluated at compile time. See `AeadCtxS::SUITE_ID`.
+pub(crate) const fn full_suite_id<A, Kdf, Kem>() -> FullSuiteId
 where
     A: Aead,
     Kdf: KdfTrait,
@@ -27,10 +28,17 @@ where
     // XX is the KEM ID, YY is the KDF ID, ZZ is the AEAD ID
     let mut suite_id = *b"HPKEXXYYZZ";
 
-    // Write the ciphersuite identifiers to the buffer. Forgive the explicit indexing.
-    BigEndian::write_u16(&mut suite_id[4..6], Kem::KEM_ID);
-    BigEndian::write_u16(&mut suite_id[6..8], Kdf::KDF_ID);
-    BigEndian::write_u16(&mut suite_id[8..10], A::AEAD_ID);
+    // Write the ciphersuite identifiers to the buffer. Forgive the explicit indexing. Slice
+    // methods aren't const, so we go byte by byte.
+    let [kem_hi, kem_lo] = Kem::KEM_ID.to_be_bytes();
+    let [kdf_hi, kdf_lo] = Kdf::KDF_ID.to_be_bytes();
+    let [aead_hi, aead_lo] = A::AEAD_ID.to_be_bytes();
+    suite_id[4] = kem_hi;
+    suite_id[5] = kem_lo;
+    suite_id[6] = kdf_hi;
+    suite_id[7] = kdf_lo;
+    suite_id[8] = aead_hi;
+    suite_id[9] = aead_lo;
 
     suite_id
 }
@@ -38,23 +46,82 @@ where
 // RFC 9180 §4.1
 // suite_id = concat("KEM", I2OSP(kem_id, 2))
 
-/// Constructs the `suite_id` used as binding context in all functions in `kem`
-pub(crate) fn kem_suite_id<Kem: KemTrait>() -> KemSuiteId {
+/// Constructs the `suite_id` used as binding context in all functions in `kem`. This is a
+/// `const fn` so it can be evaluated at compile time. See `Kem::SUITE_ID`.
+pub(crate) const fn kem_suite_id<Kem: KemTrait>() -> KemSuiteId {
     // XX is the KEM ID
     let mut suite_id = *b"KEMXX";
 
     // Write the KEM ID to the buffer. Forgive the explicit indexing.
-    BigEndian::write_u16(&mut suite_id[3..5], Kem::KEM_ID);
+    let [kem_hi, kem_lo] = Kem::KEM_ID.to_be_bytes();
+    suite_id[3] = kem_hi;
+    suite_id[4] = kem_lo;
 
     suite_id
 }
 
+/// Draws one 32-byte seed per job from `csprng`. The parallel batch functions seed a separate RNG
+/// for each job from these, so `csprng` itself is never shared across threads. Drawing the seeds
+/// is cheap, and doing it sequentially means we don't need a thread-safe RNG.
+#[cfg(feature = "parallel")]
+pub(crate) fn draw_seeds<R: CryptoRng + RngCore>(
+    num_jobs: usize,
+    csprng: &mut R,
+) -> crate::Vec<[u8; 32]> {
+    (0..num_jobs)
+        .map(|_| {
+            let mut seed = [0u8; 32];
+            csprng.fill_bytes(&mut seed);
+            seed
+        })
+        .collect()
+}
+
 /// Returns a const expression that evaluates to the number of arguments it received
 macro_rules! count {
     () => (0usize);
     ( $x:tt $($xs:tt)* ) => (1usize + count!($($xs)*));
 }
 
+/// Implements `TryFrom<&[u8]>` for a deserializable type as its `from_bytes`. A generic type takes
+/// its one type parameter after it, e.g., `impl_try_from_bytes!(AeadTag<A>, A: Aead)`.
+macro_rules! impl_try_from_bytes {
+    ($ty:ty $(, $param:ident: $bound:path)?) => {
+        impl<$($param: $bound)?> TryFrom<&[u8]> for $ty {
+            type Error = crate::HpkeError;
+
+            fn try_from(encoded: &[u8]) -> Result<Self, crate::HpkeError> {
+                <Self as crate::Deserializable>::from_bytes(encoded)
+            }
+        }
+    };
+}
+
+/// Implements `Display` and `FromStr` for a serializable type as the hex encoding of its bytes.
+/// These only exist under the `encoding` feature. A generic type takes its one type parameter after
+/// it, e.g., `impl_hex_display_fromstr!(AeadTag<A>, A: Aead)`.
+macro_rules! impl_hex_display_fromstr {
+    ($ty:ty $(, $param:ident: $bound:path)?) => {
+        #[cfg(feature = "encoding")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+        impl<$($param: $bound)?> core::fmt::Display for $ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Display::fmt(&crate::encoding::Hex(self), f)
+            }
+        }
+
+        #[cfg(feature = "encoding")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+        impl<$($param: $bound)?> core::str::FromStr for $ty {
+            type Err = crate::HpkeError;
+
+            fn from_str(s: &str) -> Result<Self, crate::HpkeError> {
+                crate::encoding::from_hex(s)
+            }
+        }
+    };
+}
+
 /// Given a length L and a sequence of n bytestrings with length at most L, this does a
 /// non-allocating concatentation of the bytestrings. It constructs a big buffer of n*L many bytes
 /// writes everything into there, and keeps track of how many bytes it wrote. The macro returns
@@ -85,6 +152,11 @@ pub(crate) fn write_to_buf<'a>(buf: &'a mut [u8], to_write: &[u8]) -> &'a mut [u
     &mut buf[to_write.len()..]
 }
 
+/// Returns whether `len` is more than `max`. Lengths that don't fit in a u64 are more than any max.
+pub(crate) fn exceeds(len: usize, max: u64) -> bool {
+    u64::try_from(len).map_or(true, |len| len > max)
+}
+
 /// Takes two lengths and returns an `Err(Error::IncorrectInputLength)` iff they don't match
 pub(crate) fn enforce_equal_len(expected_len: usize, given_len: usize) -> Result<(), HpkeError> {
     if given_len != expected_len {
@@ -106,3 +178,32 @@ pub(crate) fn enforce_outbuf_len<T: Serializable>(buf: &[u8]) {
         buf_len,
     );
 }
+
+/// An RNG that outputs a fixed string of bytes, then zeros. We only ever give this to
+/// encapsulation, in order to make it use a known ephemeral key. It is in no way a CSPRNG.
+pub(crate) struct ReplayRng<'a>(pub(crate) &'a [u8]);
+
+impl RngCore for ReplayRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let n = core::cmp::min(dest.len(), self.0.len());
+        dest[..n].copy_from_slice(&self.0[..n]);
+        dest[n..].fill(0);
+        self.0 = &self.0[n..];
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// This is a lie, but encapsulation requires it. See the comment on ReplayRng.
+impl CryptoRng for ReplayRng<'_> {}
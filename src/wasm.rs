@@ -0,0 +1,199 @@
+//! AES-GCM with WebCrypto (`crypto.subtle`), for wasm in browsers, workers, and Node, where it's
+//! several times faster than AES in wasm on large messages.
+//!
+//! [`WebCryptoAesGcm`] is an [`AsyncAesGcmAccelerator`], so only the AEAD is done by WebCrypto.
+//! The key schedule, nonces, and sequence numbers stay in Rust. Use it with
+//! `AeadCtxS::seal_in_place_detached_offloaded` and `AeadCtxR::open_in_place_detached_offloaded`:
+//!
+//! ```ignore
+//! let mut webcrypto = WebCryptoAesGcm::new();
+//! let tag = ctx
+//!     .seal_in_place_detached_offloaded(&mut webcrypto, &mut upload, b"")
+//!     .await?;
+//! ```
+//!
+//! If WebCrypto is missing, e.g., on a page that isn't a secure context, or refuses a key, the
+//! message is sealed or opened in software instead.
+//!
+//! WebCrypto copies the key and messages into the JavaScript heap. The copy of the raw key is
+//! overwritten once it's imported, but the imported key and the plaintexts that `decrypt` returns
+//! are up to the garbage collector, so nothing here can zeroize them.
+
+use crate::offload::{AsyncAesGcmAccelerator, Unsupported};
+
+use core::{future::Future, pin::Pin};
+
+use js_sys::{Array, Reflect, Uint8Array};
+use subtle::ConstantTimeEq;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AesGcmParams, Crypto, CryptoKey, SubtleCrypto};
+use zeroize::Zeroize;
+
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use std::boxed::Box;
+
+/// The largest AES key, in bytes
+const MAX_KEY_SIZE: usize = 32;
+/// The size of an AES-GCM tag, in bytes
+const TAG_SIZE: usize = 16;
+
+/// An [`AsyncAesGcmAccelerator`] that uses WebCrypto. It imports each key once, and keeps the last
+/// one, so that the messages of a context only pay for the import once.
+pub struct WebCryptoAesGcm {
+    /// `crypto.subtle`, if this environment has it
+    subtle: Option<SubtleCrypto>,
+    /// The last key that was imported
+    key: Option<CryptoKey>,
+    /// The raw bytes of `key`, to tell whether the next message uses the same one
+    key_bytes: [u8; MAX_KEY_SIZE],
+    /// The length of `key_bytes`
+    key_len: usize,
+}
+
+impl WebCryptoAesGcm {
+    /// Finds `crypto.subtle` in the global scope. This works in a window, a worker, and Node.
+    pub fn new() -> WebCryptoAesGcm {
+        let subtle = Reflect::get(&js_sys::global(), &JsValue::from_str("crypto"))
+            .ok()
+            .and_then(|crypto| crypto.dyn_into::<Crypto>().ok())
+            .map(|crypto| crypto.subtle());
+        WebCryptoAesGcm {
+            subtle,
+            key: None,
+            key_bytes: [0u8; MAX_KEY_SIZE],
+            key_len: 0,
+        }
+    }
+
+    /// Returns whether WebCrypto is available. If not, everything is done in software.
+    pub fn is_available(&self) -> bool {
+        self.subtle.is_some()
+    }
+
+    /// Returns WebCrypto and `key`, imported
+    async fn import(&mut self, key: &[u8]) -> Result<(SubtleCrypto, CryptoKey), Unsupported> {
+        let subtle = self.subtle.clone().ok_or(Unsupported)?;
+        if key.len() > MAX_KEY_SIZE {
+            return Err(Unsupported);
+        }
+        if let Some(cached) = &self.key {
+            if key.len() == self.key_len && bool::from(self.key_bytes[..key.len()].ct_eq(key)) {
+                return Ok((subtle, cached.clone()));
+            }
+        }
+
+        // Import a non-extractable key, and overwrite the JavaScript copy of the raw key
+        let key_data = Uint8Array::from(key);
+        let usages = Array::of2(&"encrypt".into(), &"decrypt".into());
+        let imported = subtle
+            .import_key_with_str("raw", &key_data, "AES-GCM", false, &usages)
+            .map(JsFuture::from);
+        let imported = match imported {
+            Ok(promise) => promise.await,
+            Err(err) => Err(err),
+        };
+        key_data.fill(0, 0, key_data.length());
+        let imported: CryptoKey = imported
+            .and_then(|k| k.dyn_into())
+            .map_err(|_| Unsupported)?;
+
+        self.key_bytes.zeroize();
+        self.key_bytes[..key.len()].copy_from_slice(key);
+        self.key_len = key.len();
+        self.key = Some(imported.clone());
+        Ok((subtle, imported))
+    }
+}
+
+impl Default for WebCryptoAesGcm {
+    fn default() -> Self {
+        WebCryptoAesGcm::new()
+    }
+}
+
+// Zero out the cached raw key on drop
+impl Drop for WebCryptoAesGcm {
+    fn drop(&mut self) {
+        self.key_bytes.zeroize();
+    }
+}
+
+/// Returns the WebCrypto parameters of an AES-GCM operation
+fn params(nonce: &[u8; 12], aad: &[u8]) -> AesGcmParams {
+    let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(&nonce[..]));
+    params.set_additional_data(&Uint8Array::from(aad));
+    params
+}
+
+impl AsyncAesGcmAccelerator for WebCryptoAesGcm {
+    type EncryptFuture<'a> = Pin<Box<dyn Future<Output = Result<[u8; 16], Unsupported>> + 'a>>;
+    type DecryptFuture<'a> = Pin<Box<dyn Future<Output = Result<bool, Unsupported>> + 'a>>;
+
+    fn encrypt_in_place_detached<'a>(
+        &'a mut self,
+        key: &'a [u8],
+        nonce: &'a [u8; 12],
+        aad: &'a [u8],
+        buf: &'a mut [u8],
+    ) -> Self::EncryptFuture<'a> {
+        Box::pin(async move {
+            let (subtle, key) = self.import(key).await?;
+            let promise = subtle
+                .encrypt_with_object_and_u8_array(&params(nonce, aad), &key, buf)
+                .map_err(|_| Unsupported)?;
+            let out = Uint8Array::new(&JsFuture::from(promise).await.map_err(|_| Unsupported)?);
+
+            // WebCrypto appends the tag
+            let ct_len = buf.len() as u32;
+            if out.length() as usize != buf.len() + TAG_SIZE {
+                return Err(Unsupported);
+            }
+            let mut tag = [0u8; TAG_SIZE];
+            out.subarray(0, ct_len).copy_to(buf);
+            out.subarray(ct_len, out.length()).copy_to(&mut tag);
+            Ok(tag)
+        })
+    }
+
+    fn decrypt_in_place_detached<'a>(
+        &'a mut self,
+        key: &'a [u8],
+        nonce: &'a [u8; 12],
+        aad: &'a [u8],
+        buf: &'a mut [u8],
+        tag: &'a [u8; 16],
+    ) -> Self::DecryptFuture<'a> {
+        Box::pin(async move {
+            let (subtle, key) = self.import(key).await?;
+            let mut ct_and_tag = crate::Vec::with_capacity(buf.len() + TAG_SIZE);
+            ct_and_tag.extend_from_slice(buf);
+            ct_and_tag.extend_from_slice(tag);
+            let promise = subtle
+                .decrypt_with_object_and_u8_array(&params(nonce, aad), &key, &ct_and_tag)
+                .map_err(|_| Unsupported)?;
+
+            match JsFuture::from(promise).await {
+                Ok(plaintext) => {
+                    let plaintext = Uint8Array::new(&plaintext);
+                    if plaintext.length() as usize != buf.len() {
+                        return Err(Unsupported);
+                    }
+                    plaintext.copy_to(buf);
+                    Ok(true)
+                }
+                // WebCrypto rejects a bad tag with an OperationError
+                Err(err) => {
+                    let name = Reflect::get(&err, &JsValue::from_str("name")).ok();
+                    if name.and_then(|n| n.as_string()).as_deref() == Some("OperationError") {
+                        Ok(false)
+                    } else {
+                        Err(Unsupported)
+                    }
+                }
+            }
+        })
+    }
+}
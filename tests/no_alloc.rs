@@ -0,0 +1,116 @@
+//! Runs every core operation through APIs that don't allocate. This crate is `#![no_std]` and
+//! never links `alloc` itself, and CI runs it against `hpke` built without the `alloc` feature,
+//! so it only compiles if all of these stay available on allocator-less targets. The libtest
+//! harness links `std` for its own use.
+
+#![no_std]
+
+use hpke::{
+    aead::{AeadCtxR, AeadCtxS, AeadTag, ChaCha20Poly1305, RESPONSE_NONCE_SIZE},
+    kdf::HkdfSha256,
+    kem::X25519HkdfSha256,
+    setup_receiver, setup_sender, single_shot_open_in_place, single_shot_seal_in_place, HpkeX25519,
+    Kem, OpModeR, OpModeS, Serializable,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+type A = ChaCha20Poly1305;
+type Kdf = HkdfSha256;
+type KemT = X25519HkdfSha256;
+
+const TAG_SIZE: usize = 16;
+
+/// Sets up both ends of a context, in the given modes
+fn setup(
+    mode_s: &OpModeS<KemT>,
+    mode_r: &OpModeR<KemT>,
+    csprng: &mut StdRng,
+) -> (AeadCtxS<A, Kdf, KemT>, AeadCtxR<A, Kdf, KemT>) {
+    let (sk_recip, pk_recip) = KemT::gen_keypair(csprng);
+    let (enc, sender_ctx) =
+        setup_sender::<A, Kdf, KemT, _>(mode_s, &pk_recip, b"info", csprng).unwrap();
+    let receiver_ctx = setup_receiver::<A, Kdf, KemT>(mode_r, &sk_recip, &enc, b"info").unwrap();
+    (sender_ctx, receiver_ctx)
+}
+
+#[test]
+fn no_alloc_context() {
+    let mut csprng = StdRng::from_entropy();
+    let (sk_sender, pk_sender) = KemT::gen_keypair(&mut csprng);
+    let (mut sender_ctx, mut receiver_ctx) = setup(
+        &OpModeS::Auth((sk_sender, pk_sender.clone())),
+        &OpModeR::Auth(pk_sender),
+        &mut csprng,
+    );
+
+    // Detached and combined in-place seal and open
+    let mut msg = *b"detached";
+    let tag = sender_ctx.seal_in_place_detached(&mut msg, b"aad").unwrap();
+    receiver_ctx
+        .open_in_place_detached(&mut msg, b"aad", &tag)
+        .unwrap();
+    assert_eq!(&msg, b"detached");
+
+    let mut buf = [0u8; 8 + TAG_SIZE];
+    buf[..8].copy_from_slice(b"combined");
+    let tag = sender_ctx
+        .seal_in_place_detached(&mut buf[..8], b"")
+        .unwrap();
+    tag.write_exact(&mut buf[8..]);
+    let pt = receiver_ctx.open_in_place_combined(&mut buf, b"").unwrap();
+    assert_eq!(pt, b"combined");
+    assert_eq!(AeadTag::<A>::size(), TAG_SIZE);
+
+    // Exports agree
+    let (mut sender_export, mut receiver_export) = ([0u8; 32], [0u8; 32]);
+    sender_ctx.export(b"ctx", &mut sender_export).unwrap();
+    receiver_ctx.export(b"ctx", &mut receiver_export).unwrap();
+    assert_eq!(sender_export, receiver_export);
+
+    // Responses
+    let mut buf = [0u8; RESPONSE_NONCE_SIZE + 5 + TAG_SIZE];
+    buf[..5].copy_from_slice(b"reply");
+    let response_len = receiver_ctx
+        .respond_in_place(&mut buf, 5, b"", &mut csprng)
+        .unwrap();
+    assert_eq!(response_len, buf.len());
+    let pt = sender_ctx.open_response_in_place(&mut buf, b"").unwrap();
+    assert_eq!(pt, b"reply");
+}
+
+#[test]
+fn no_alloc_single_shot() {
+    let mut csprng = StdRng::from_entropy();
+    let (sk_recip, pk_recip) = KemT::gen_keypair(&mut csprng);
+
+    let mut buf = [0u8; 5 + TAG_SIZE];
+    buf[..5].copy_from_slice(b"hello");
+    let (enc, ct_len) = single_shot_seal_in_place::<A, Kdf, KemT, _>(
+        &OpModeS::Base,
+        &pk_recip,
+        b"info",
+        &mut buf,
+        5,
+        b"",
+        &mut csprng,
+    )
+    .unwrap();
+    let pt = single_shot_open_in_place::<A, Kdf, KemT>(
+        &OpModeR::Base,
+        &sk_recip,
+        &enc,
+        b"info",
+        &mut buf[..ct_len],
+        b"",
+    )
+    .unwrap();
+    assert_eq!(pt, b"hello");
+
+    // The same through the one-type front end
+    let mut buf = [0u8; 5 + TAG_SIZE];
+    buf[..5].copy_from_slice(b"hello");
+    let (enc, ct_len) =
+        HpkeX25519::seal_in_place(&pk_recip, b"info", b"", &mut buf, 5, &mut csprng).unwrap();
+    let pt = HpkeX25519::open_in_place(&sk_recip, &enc, b"info", b"", &mut buf[..ct_len]).unwrap();
+    assert_eq!(pt, b"hello");
+}